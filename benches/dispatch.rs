@@ -0,0 +1,95 @@
+//! Benchmarks for fanning out gateway events to connected peers.
+//!
+//! Peers are simulated in memory, so only the cost of routing and queueing events is measured.
+//! Run with `cargo bench --features bench --bench dispatch`.
+
+use std::time::{Duration, Instant};
+
+use chat_backend::{
+    gateway::handler::{Gateway, SimulatedPeer},
+    models::{
+        gateway_event::{GatewayEvent, MessageBulkRemovePayload, PresenceUpdatePayload},
+        guild::Guild,
+        shard::ShardInfo,
+        snowflake::Snowflake,
+        user::{Presence, User},
+    },
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+/// The amount of guilds peers are spread across
+const GUILDS: i64 = 100;
+/// The amount of guilds each peer is a member of
+const GUILDS_PER_PEER: i64 = 3;
+
+/// Create a gateway with the given amount of simulated peers, each a member of [`GUILDS_PER_PEER`] guilds
+fn populate(peers: i64) -> (Gateway, Vec<SimulatedPeer>) {
+    let gateway = Gateway::new(ShardInfo::default());
+    let peers = (0..peers)
+        .map(|i| {
+            let guilds = (0..GUILDS_PER_PEER).map(|offset| Snowflake::<Guild>::new((i + offset * 37) % GUILDS));
+            gateway.add_simulated_peer(Snowflake::<User>::new(i), guilds)
+        })
+        .collect();
+    (gateway, peers)
+}
+
+/// Measure dispatching events to all peers, draining the peers between iterations so their buffers do not grow
+fn dispatch_and_drain(
+    gateway: &Gateway,
+    peers: &mut [SimulatedPeer],
+    iters: u64,
+    event: impl Fn() -> GatewayEvent,
+) -> Duration {
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..iters {
+        let event = event();
+        let start = Instant::now();
+        gateway.dispatch(event);
+        elapsed += start.elapsed();
+
+        for peer in peers.iter_mut() {
+            peer.drain();
+        }
+    }
+    elapsed
+}
+
+fn bench_dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dispatch");
+
+    for peers in [1_000, 5_000, 10_000] {
+        let (gateway, mut receivers) = populate(peers);
+
+        // Guild events are filtered by the guilds of each peer
+        group.bench_with_input(BenchmarkId::new("guild_event", peers), &peers, |b, _| {
+            b.iter_custom(|iters| {
+                dispatch_and_drain(&gateway, &mut receivers, iters, || {
+                    GatewayEvent::MessageBulkRemove(MessageBulkRemovePayload {
+                        ids: vec![Snowflake::new(1)],
+                        channel_id: Snowflake::new(1),
+                        guild_id: Some(Snowflake::new(1)),
+                    })
+                })
+            });
+        });
+
+        // User events are filtered by the guilds each peer shares with the user
+        group.bench_with_input(BenchmarkId::new("presence_update", peers), &peers, |b, _| {
+            b.iter_custom(|iters| {
+                dispatch_and_drain(&gateway, &mut receivers, iters, || {
+                    GatewayEvent::PresenceUpdate(PresenceUpdatePayload {
+                        user_id: Snowflake::new(0),
+                        presence: Presence::Online,
+                        activity: None,
+                    })
+                })
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_dispatch);
+criterion_main!(benches);
@@ -0,0 +1,181 @@
+//! Benchmarks for fetching the message history of large channels.
+//!
+//! Needs a Postgres server to connect to, set with `DATABASE_URL`. A scratch database is created
+//! and seeded with messages, and dropped once the benchmarks finish.
+//! Run with `DATABASE_URL=... cargo bench --features bench --bench history`.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use argon2::Params;
+use chat_backend::models::{
+    channel::{Channel, ChannelLike, TextChannel},
+    clock::SystemClock,
+    message::Message,
+    requests::{CreateGuild, CreateUser},
+    snowflake::{self, Snowflake},
+    state::{App, ApplicationState, Config},
+};
+use chrono::Utc;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use secrecy::Secret;
+use sqlx::{Connection, Executor, PgConnection};
+use url::Url;
+
+/// The amount of messages in each seeded channel
+const CHANNEL_SIZES: [i64; 2] = [1_000, 100_000];
+
+/// Create a scratch database and an application connected to it
+async fn setup(admin_url: &str, database: &str) -> App {
+    let mut conn = PgConnection::connect(admin_url)
+        .await
+        .expect("Failed to connect to the database server");
+    conn.execute(format!("CREATE DATABASE {database}").as_str())
+        .await
+        .expect("Failed to create benchmark database");
+    conn.close().await.ok();
+
+    let mut database_url = Url::parse(admin_url).expect("DATABASE_URL should be a valid URL");
+    database_url.set_path(database);
+
+    let config = Config::builder()
+        .database_url(database_url.to_string())
+        .minio_url("http://127.0.0.1:9000")
+        .minio_access_key(String::from("unused"))
+        .minio_secret_key(String::from("unused"))
+        .listen_addr(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .machine_id(0)
+        .process_id(0)
+        .app_secret(String::from("unused"))
+        .argon2_params(Params::default())
+        .build()
+        .expect("Benchmark configuration should be valid");
+
+    ApplicationState::new_test(config, Arc::new(SystemClock))
+        .await
+        .expect("Failed to initialize application state")
+}
+
+/// Create a channel with the given amount of messages, one in ten with an attachment
+///
+/// ## Arguments
+///
+/// * `size` - The amount of messages to create
+/// * `last_timestamp` - The timestamp of the last message, relative to the [`snowflake::epoch`]
+///
+/// ## Returns
+///
+/// The channel and the ID of the message in the middle of it
+async fn seed_channel(app: &App, size: i64, last_timestamp: i64) -> (Channel, Snowflake<Message>) {
+    let user = app
+        .ops()
+        .create_user(CreateUser {
+            username: format!("bench{size}"),
+            password: Secret::new(String::new()),
+            device_id: None,
+        })
+        .await
+        .expect("Failed to create user");
+    let (guild, ..) = app
+        .ops()
+        .create_guild(
+            CreateGuild {
+                name: format!("Bench {size}"),
+            },
+            &user,
+        )
+        .await
+        .expect("Failed to create guild");
+    let channel: Channel = TextChannel::new(Snowflake::gen_new(&app.config), &guild, format!("messages-{size}")).into();
+    app.ops()
+        .create_channel(&channel)
+        .await
+        .expect("Failed to create channel");
+
+    // One message per millisecond
+    let first_timestamp = last_timestamp - size;
+
+    sqlx::query(
+        "INSERT INTO messages (id, channel_id, user_id, content)
+        SELECT ($1 + n) << 22, $2, $3, 'Message number ' || n
+        FROM generate_series(1, $4) AS n",
+    )
+    .bind(first_timestamp)
+    .bind(channel.id())
+    .bind(user.id())
+    .bind(size)
+    .execute(app.db.executor())
+    .await
+    .expect("Failed to seed messages");
+
+    sqlx::query(
+        "INSERT INTO attachments (id, filename, message_id, channel_id, content_type)
+        SELECT 0, 'file.png', id, channel_id, 'image/png'
+        FROM messages WHERE channel_id = $1 AND (id >> 22) % 10 = 0",
+    )
+    .bind(channel.id())
+    .execute(app.db.executor())
+    .await
+    .expect("Failed to seed attachments");
+
+    sqlx::query("ANALYZE messages, attachments")
+        .execute(app.db.executor())
+        .await
+        .expect("Failed to analyze tables");
+
+    (channel, Snowflake::new((first_timestamp + size / 2) << 22))
+}
+
+fn bench_history(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let admin_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set to run the history benchmarks");
+    let database = format!("chat_bench_{:016x}", rand::random::<u64>());
+
+    let app = runtime.block_on(setup(&admin_url, &database));
+    // Channels are seeded back to back so their message IDs do not overlap
+    let mut last_timestamp = Utc::now().timestamp_millis() - snowflake::epoch();
+    let channels: Vec<_> = CHANNEL_SIZES
+        .iter()
+        .map(|size| {
+            let channel = runtime.block_on(seed_channel(&app, *size, last_timestamp));
+            last_timestamp -= size;
+            (size, channel)
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("history");
+
+    for (size, (channel, middle)) in &channels {
+        group.bench_with_input(BenchmarkId::new("latest", size), channel, |b, channel| {
+            b.to_async(&runtime).iter(|| async {
+                app.ops()
+                    .fetch_messages_from(channel.id(), Some(100), None, None)
+                    .await
+                    .expect("Failed to fetch messages")
+            });
+        });
+
+        group.bench_with_input(BenchmarkId::new("before_middle", size), channel, |b, channel| {
+            b.to_async(&runtime).iter(|| async {
+                app.ops()
+                    .fetch_messages_from(channel.id(), Some(100), Some(*middle), None)
+                    .await
+                    .expect("Failed to fetch messages")
+            });
+        });
+    }
+
+    group.finish();
+
+    runtime.block_on(async {
+        app.close().await;
+        let mut conn = PgConnection::connect(&admin_url)
+            .await
+            .expect("Failed to connect to the database server");
+        conn.execute(format!("DROP DATABASE IF EXISTS {database} WITH (FORCE)").as_str())
+            .await
+            .expect("Failed to drop benchmark database");
+    });
+}
+
+criterion_group!(benches, bench_history);
+criterion_main!(benches);
@@ -0,0 +1,111 @@
+//! Benchmarks for parsing message uploads from multipart form data.
+//!
+//! Run with `cargo bench --features bench --bench multipart`.
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::{FromRequest, Multipart},
+    http::{header, Request},
+};
+use bytes::Bytes;
+use chat_backend::models::{
+    member::UserLike, message::Message, snowflake::Snowflake, state::Config, upload_throttle::UploadThrottle,
+    user::User,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const BOUNDARY: &str = "chat-bench-boundary";
+
+/// Build a multipart body with a JSON part and the given amount of attachments of the given size
+fn form_body(attachments: usize, size: usize) -> Vec<u8> {
+    let mut body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"json\"\r\nContent-Type: application/json\r\n\r\n\
+        {{\"content\": \"Hello **world**, this is a benchmark\", \"nonce\": \"1\"}}\r\n"
+    )
+    .into_bytes();
+
+    for id in 0..attachments {
+        body.extend_from_slice(
+            format!(
+                "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"attachment-{id}\"; filename=\"file{id}.bin\"\r\n\
+                Content-Type: application/octet-stream\r\n\r\n"
+            )
+            .as_bytes(),
+        );
+        body.extend(std::iter::repeat_n(b'x', size));
+        body.extend_from_slice(b"\r\n");
+    }
+
+    body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+    body
+}
+
+fn bench_config() -> Config {
+    Config::builder()
+        .database_url(String::from("postgres://localhost/unused"))
+        .minio_url("http://127.0.0.1:9000")
+        .minio_access_key(String::from("unused"))
+        .minio_secret_key(String::from("unused"))
+        .listen_addr(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .machine_id(0)
+        .process_id(0)
+        .app_secret(String::from("unused"))
+        .build()
+        .expect("Benchmark configuration should be valid")
+}
+
+fn bench_multipart(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    let config = bench_config();
+    // Throttling would dominate the measurement
+    let throttle = UploadThrottle::new(0);
+    let author = User::builder()
+        .id(Snowflake::new(1))
+        .username("bench")
+        .build()
+        .expect("Benchmark user should be valid");
+
+    let mut group = c.benchmark_group("multipart");
+
+    for (name, attachments, size) in [
+        ("text_only", 0, 0),
+        ("one_1mb_attachment", 1, 1024 * 1024),
+        ("ten_64kb_attachments", 10, 64 * 1024),
+    ] {
+        let body = Bytes::from(form_body(attachments, size));
+        group.throughput(Throughput::Bytes(body.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("from_formdata", name), &body, |b, body| {
+            b.to_async(&runtime).iter(|| async {
+                let request = Request::builder()
+                    .header(
+                        header::CONTENT_TYPE,
+                        format!("multipart/form-data; boundary={BOUNDARY}"),
+                    )
+                    .body(Body::from(body.clone()))
+                    .expect("Request should be valid");
+                let form = Multipart::from_request(request, &())
+                    .await
+                    .expect("Request should be multipart");
+
+                Message::from_formdata(
+                    &config,
+                    config.limits(),
+                    &throttle,
+                    UserLike::User(author.clone()),
+                    Snowflake::new(1),
+                    form,
+                )
+                .await
+                .expect("Form should be valid")
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_multipart);
+criterion_main!(benches);
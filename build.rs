@@ -2,4 +2,48 @@
 fn main() {
     // trigger recompilation when a new migration is added
     println!("cargo:rerun-if-changed=migrations");
+
+    compile_internal_api();
+}
+
+/// Generate the server of the internal gRPC API.
+/// The messages are defined in `src/internal/proto.rs`, so no `.proto` files or `protoc` are needed.
+fn compile_internal_api() {
+    let method = |name: &str, route: &str, input: &str, output: &str| {
+        tonic_build::manual::Method::builder()
+            .name(name)
+            .route_name(route)
+            .input_type(format!("crate::internal::proto::{input}"))
+            .output_type(format!("crate::internal::proto::{output}"))
+            .codec_path("tonic::codec::ProstCodec")
+            .build()
+    };
+
+    let service = tonic_build::manual::Service::builder()
+        .name("Internal")
+        .package("chat.internal.v1")
+        .method(method("fetch_user", "FetchUser", "FetchUserRequest", "User"))
+        .method(method(
+            "create_message",
+            "CreateMessage",
+            "CreateMessageRequest",
+            "CreateMessageResponse",
+        ))
+        .method(method(
+            "fetch_presences",
+            "FetchPresences",
+            "FetchPresencesRequest",
+            "FetchPresencesResponse",
+        ))
+        .method(method(
+            "create_announcement",
+            "CreateAnnouncement",
+            "CreateAnnouncementRequest",
+            "CreateAnnouncementResponse",
+        ))
+        .build();
+
+    tonic_build::manual::Builder::new()
+        .build_client(false)
+        .compile(&[service]);
 }
@@ -0,0 +1,48 @@
+use chat_core::{close_code::GatewayCloseCode, errors::ErrorCode};
+use reqwest::StatusCode;
+use thiserror::Error;
+use tokio_tungstenite::tungstenite;
+
+/// An error returned by the client.
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The request could not be sent or its response could not be read.
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    /// The server responded with an error.
+    #[error("The server responded with {status}: {message}")]
+    Api {
+        /// The status of the response.
+        status: StatusCode,
+        /// The machine-readable code of the error, if the server sent one this client knows.
+        code: Option<ErrorCode>,
+        /// The human-readable description of the error.
+        message: String,
+    },
+    /// A response or gateway event did not have the expected shape.
+    #[error("Failed to decode payload: {0}")]
+    Decode(#[from] serde_json::Error),
+    /// The gateway connection failed.
+    #[error("Gateway connection failed: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+    /// The gateway closed the session and asked the client not to reconnect.
+    #[error("The gateway closed the session with {code:?}: {reason}")]
+    SessionClosed {
+        /// The close code sent by the server.
+        code: GatewayCloseCode,
+        /// The reason sent by the server.
+        reason: String,
+    },
+    /// The gateway did not follow the protocol.
+    #[error("Gateway protocol violation: {0}")]
+    Protocol(&'static str),
+    /// The request needs a token, but the client is not logged in.
+    #[error("The client is not logged in")]
+    MissingToken,
+}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(error: tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(error))
+    }
+}
@@ -0,0 +1,232 @@
+//! The events the gateway dispatches to clients.
+
+use chat_core::{close_code::GatewayCloseCode, snowflake::Snowflake};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::models::{Channel, Guild, Member, Message, User};
+
+/// An event as sent by the gateway, before its payload is decoded.
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct RawEvent {
+    pub(crate) event: String,
+    #[serde(default)]
+    pub(crate) data: Value,
+    /// The sequence number of the event in the session, `None` for events that are not numbered.
+    #[serde(default)]
+    pub(crate) seq: Option<u64>,
+}
+
+/// Sent by the gateway right after connecting.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub(crate) struct Hello {
+    /// The interval heartbeats must be sent at, in milliseconds.
+    pub(crate) heartbeat_interval: u64,
+}
+
+/// Sent after a successful `IDENTIFY`.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct Ready {
+    /// The user the session belongs to.
+    pub user: User,
+    /// The guilds the user is a member of. A `GUILD_CREATE` event follows for each of them.
+    pub guilds: Vec<Guild>,
+}
+
+/// Sent when the user joins a guild, and for every guild of the user after `READY`.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct GuildCreate {
+    /// The guild.
+    pub guild: Guild,
+    /// The members of the guild. Empty if the guild is large.
+    #[serde(default)]
+    pub members: Vec<Member>,
+    /// The channels of the guild.
+    #[serde(default)]
+    pub channels: Vec<Channel>,
+    /// The amount of members in the guild.
+    #[serde(default)]
+    pub member_count: usize,
+    /// Whether the guild has more members than the large guild threshold.
+    #[serde(default)]
+    pub large: bool,
+}
+
+/// The ID of an object that was removed.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(bound = "")]
+#[non_exhaustive]
+pub struct Removed<T> {
+    /// The ID of the removed object.
+    pub id: Snowflake<T>,
+}
+
+/// Sent right before the gateway closes the connection.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct InvalidSession {
+    /// The close code of the connection.
+    pub code: GatewayCloseCode,
+    /// Why the connection is being closed.
+    pub reason: String,
+    /// How long to wait before reconnecting, in milliseconds. `None` if reconnecting would fail the same way.
+    #[serde(default)]
+    pub reconnect_after_ms: Option<u64>,
+}
+
+/// An event dispatched by the gateway.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Event {
+    /// The session is ready.
+    Ready(Ready),
+    /// The session was resumed on a new connection. The events missed while reconnecting were dispatched before it.
+    Resumed,
+    /// A message was sent.
+    MessageCreate(Message),
+    /// A message was edited.
+    MessageUpdate(Message),
+    /// A user joined a guild.
+    MemberCreate(Member),
+    /// A member was updated.
+    MemberUpdate(Member),
+    /// A user left or was removed from a guild.
+    MemberRemove(Removed<User>),
+    /// A guild became available to the user.
+    GuildCreate(GuildCreate),
+    /// The user left a guild, or it was deleted.
+    GuildRemove(Guild),
+    /// A channel was created.
+    ChannelCreate(Channel),
+    /// A channel was updated.
+    ChannelUpdate(Channel),
+    /// A channel was deleted.
+    ChannelRemove(Channel),
+    /// The gateway is about to close the connection.
+    /// The client reconnects on its own if the server allows it.
+    InvalidSession(InvalidSession),
+    /// An event this client does not have a type for.
+    Unknown {
+        /// The name of the event.
+        name: String,
+        /// The payload of the event.
+        data: Value,
+    },
+}
+
+impl Event {
+    /// Decode the payload of a raw event.
+    ///
+    /// ## Errors
+    ///
+    /// * [`serde_json::Error`] - If the payload does not match the event.
+    pub(crate) fn from_raw(raw: RawEvent) -> Result<Self, serde_json::Error> {
+        let RawEvent { event, data, .. } = raw;
+        Ok(match event.as_str() {
+            "READY" => Self::Ready(serde_json::from_value(data)?),
+            "RESUMED" => Self::Resumed,
+            "MESSAGE_CREATE" => Self::MessageCreate(serde_json::from_value(data)?),
+            "MESSAGE_UPDATE" => Self::MessageUpdate(serde_json::from_value(data)?),
+            "MEMBER_CREATE" => Self::MemberCreate(serde_json::from_value(data)?),
+            "MEMBER_UPDATE" => Self::MemberUpdate(serde_json::from_value(data)?),
+            "MEMBER_REMOVE" => Self::MemberRemove(serde_json::from_value(data)?),
+            "GUILD_CREATE" => Self::GuildCreate(serde_json::from_value(data)?),
+            "GUILD_REMOVE" => Self::GuildRemove(serde_json::from_value(data)?),
+            "CHANNEL_CREATE" => Self::ChannelCreate(serde_json::from_value(data)?),
+            "CHANNEL_UPDATE" => Self::ChannelUpdate(serde_json::from_value(data)?),
+            "CHANNEL_REMOVE" => Self::ChannelRemove(serde_json::from_value(data)?),
+            "INVALID_SESSION" => Self::InvalidSession(serde_json::from_value(data)?),
+            _ => Self::Unknown { name: event, data },
+        })
+    }
+
+    /// The name of the event, as sent by the gateway.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Ready(_) => "READY",
+            Self::Resumed => "RESUMED",
+            Self::MessageCreate(_) => "MESSAGE_CREATE",
+            Self::MessageUpdate(_) => "MESSAGE_UPDATE",
+            Self::MemberCreate(_) => "MEMBER_CREATE",
+            Self::MemberUpdate(_) => "MEMBER_UPDATE",
+            Self::MemberRemove(_) => "MEMBER_REMOVE",
+            Self::GuildCreate(_) => "GUILD_CREATE",
+            Self::GuildRemove(_) => "GUILD_REMOVE",
+            Self::ChannelCreate(_) => "CHANNEL_CREATE",
+            Self::ChannelUpdate(_) => "CHANNEL_UPDATE",
+            Self::ChannelRemove(_) => "CHANNEL_REMOVE",
+            Self::InvalidSession(_) => "INVALID_SESSION",
+            Self::Unknown { name, .. } => name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chat_core::close_code::GatewayCloseCode;
+
+    use super::{Event, RawEvent};
+    use crate::models::Author;
+
+    fn parse(json: &str) -> Event {
+        let raw: RawEvent = serde_json::from_str(json).expect("Event should be valid JSON");
+        Event::from_raw(raw).expect("Event should decode")
+    }
+
+    #[test]
+    fn test_message_create() {
+        let event = parse(
+            r#"{"event": "MESSAGE_CREATE", "data": {
+                "id": "123456789123456789",
+                "channel_id": "223456789123456789",
+                "author": {
+                    "user": {"id": "323456789123456789", "username": "among_us", "display_name": null, "presence": "ONLINE"},
+                    "guild_id": "423456789123456789",
+                    "nickname": null,
+                    "joined_at": 1630000000,
+                    "timeout_until": null
+                },
+                "content": "sus",
+                "attachments": [{"id": 0, "filename": "a.png", "content_type": "image/png", "width": 10}],
+                "mentions": [],
+                "channel_mentions": [],
+                "embeds": [],
+                "some_future_field": true
+            }}"#,
+        );
+        let Event::MessageCreate(message) = event else {
+            panic!("Expected MESSAGE_CREATE, got {event:?}");
+        };
+        assert_eq!(message.id.to_string(), "123456789123456789");
+        assert_eq!(message.content.as_deref(), Some("sus"));
+        assert_eq!(message.attachments.len(), 1);
+        let Some(Author::Member(member)) = message.author else {
+            panic!("Author should be a member");
+        };
+        assert_eq!(member.user.username, "among_us");
+    }
+
+    #[test]
+    fn test_invalid_session() {
+        let event = parse(
+            r#"{"event": "INVALID_SESSION", "data": {"code": 4001, "reason": "Invalid token", "reconnect_after_ms": null}}"#,
+        );
+        let Event::InvalidSession(payload) = event else {
+            panic!("Expected INVALID_SESSION, got {event:?}");
+        };
+        assert_eq!(payload.code, GatewayCloseCode::AuthenticationFailed);
+        assert_eq!(payload.reconnect_after_ms, None);
+    }
+
+    #[test]
+    fn test_unknown_event() {
+        let event = parse(r#"{"event": "SOMETHING_NEW", "data": {"answer": 42}}"#);
+        assert_eq!(event.name(), "SOMETHING_NEW");
+        let Event::Unknown { data, .. } = event else {
+            panic!("Expected an unknown event, got {event:?}");
+        };
+        assert_eq!(data["answer"], 42);
+    }
+}
@@ -0,0 +1,417 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chat_core::close_code::GatewayCloseCode;
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::json;
+use tokio::{
+    net::TcpStream,
+    sync::watch,
+    time::{interval_at, sleep, timeout, Instant},
+};
+use tokio_tungstenite::{
+    tungstenite::{
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Message as WsMessage,
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::{
+    error::ClientError,
+    events::{Event, Hello, InvalidSession, RawEvent},
+    rest::RestClient,
+};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// How long to wait for `HELLO` after connecting.
+const HELLO_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait before the first reconnect attempt after a connection failed.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// The longest time to wait between reconnect attempts.
+const MAX_BACKOFF: Duration = Duration::from_mins(1);
+
+/// The kind of device a client runs on, reported to the server in `IDENTIFY`.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DeviceType {
+    /// A desktop application.
+    Desktop,
+    /// A browser.
+    Web,
+    /// A mobile application.
+    Mobile,
+    /// An automated client.
+    #[default]
+    Bot,
+}
+
+/// Information about the client, shown to the user in their list of sessions.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClientProperties {
+    /// The name of the client.
+    pub name: String,
+    /// The version of the client.
+    pub version: String,
+    /// The operating system the client runs on.
+    pub os: String,
+    /// The kind of device the client runs on.
+    pub device: DeviceType,
+}
+
+impl Default for ClientProperties {
+    /// Describes this crate as a bot on the current operating system.
+    fn default() -> Self {
+        Self {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            device: DeviceType::default(),
+        }
+    }
+}
+
+/// Handles the events dispatched by the gateway.
+///
+/// Every event is handled in its own task, so a slow handler does not hold up heartbeats
+/// or other events. Events may thus be handled concurrently and finish out of order.
+#[async_trait]
+pub trait EventHandler: Send + Sync {
+    /// Handle an event.
+    ///
+    /// ## Arguments
+    ///
+    /// * `ctx` - The context of the connection, to call the REST API or shut down.
+    /// * `event` - The event.
+    async fn on_event(&self, ctx: Context, event: Event);
+}
+
+/// Stops a running [`Gateway`] when triggered.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl ShutdownHandle {
+    fn new() -> Self {
+        Self {
+            sender: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Close the gateway connection and stop reconnecting.
+    pub fn shutdown(&self) {
+        self.sender.send_replace(true);
+    }
+
+    /// Wait until [`Self::shutdown`] is called.
+    async fn triggered(mut receiver: watch::Receiver<bool>) {
+        // The sender lives as long as the handle, so this cannot fail
+        let _ = receiver.wait_for(|triggered| *triggered).await;
+    }
+}
+
+/// The context events are handled in.
+#[derive(Debug, Clone)]
+pub struct Context {
+    rest: RestClient,
+    shutdown: ShutdownHandle,
+}
+
+impl Context {
+    /// A REST client authenticated as the user of the gateway connection.
+    pub const fn rest(&self) -> &RestClient {
+        &self.rest
+    }
+
+    /// Close the gateway connection and stop reconnecting.
+    pub fn shutdown(&self) {
+        self.shutdown.shutdown();
+    }
+}
+
+/// How a gateway session ended without a fatal error.
+enum SessionEnd {
+    /// The client was shut down.
+    Shutdown,
+    /// The connection was closed and may be reopened after the given delay.
+    Reconnect(Duration),
+}
+
+/// A session that can be resumed on a new connection.
+struct ResumeState {
+    /// The ID of the session, as sent in `READY`.
+    session_id: String,
+    /// The token to resume the session with, as sent in `READY` or the last `RESUMED`. Each token is only valid once.
+    resume_token: String,
+    /// The sequence number of the last event received.
+    seq: u64,
+}
+
+impl ResumeState {
+    /// Update the session to resume with a received event, starting to track it on `READY`.
+    fn track(state: &mut Option<Self>, raw: &RawEvent) {
+        match (
+            raw.event.as_str(),
+            raw.data["session_id"].as_str(),
+            raw.data["resume_token"].as_str(),
+        ) {
+            ("READY", Some(session_id), Some(resume_token)) => {
+                *state = Some(Self {
+                    session_id: session_id.to_string(),
+                    resume_token: resume_token.to_string(),
+                    seq: raw.seq.unwrap_or_default(),
+                });
+            }
+            // Each resume token is only valid once, the next one is sent in `RESUMED`
+            ("RESUMED", _, Some(resume_token)) => {
+                if let Some(state) = state.as_mut() {
+                    state.resume_token = resume_token.to_string();
+                }
+            }
+            _ => {
+                if let (Some(state), Some(seq)) = (state.as_mut(), raw.seq) {
+                    state.seq = seq;
+                }
+            }
+        }
+    }
+}
+
+/// A connection to the gateway that sends heartbeats and reconnects on its own.
+///
+/// Reconnecting resumes the session, so events dispatched while disconnected are delivered once the connection is back,
+/// followed by [`Event::Resumed`]. If the session can no longer be resumed, a new session is opened with `IDENTIFY`,
+/// and clients should refetch what they need on `READY`.
+#[derive(Debug)]
+pub struct Gateway {
+    rest: RestClient,
+    url: String,
+    properties: ClientProperties,
+    shutdown: ShutdownHandle,
+}
+
+impl Gateway {
+    /// Create a new gateway connection for the user the REST client is logged in as.
+    /// The connection is only opened once [`Self::run`] is called.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::MissingToken`] - If the REST client is not logged in.
+    pub fn new(rest: RestClient) -> Result<Self, ClientError> {
+        if rest.token().is_none() {
+            return Err(ClientError::MissingToken);
+        }
+        Ok(Self {
+            url: rest.gateway_url(),
+            rest,
+            properties: ClientProperties::default(),
+            shutdown: ShutdownHandle::new(),
+        })
+    }
+
+    /// Connect to the given gateway URL instead of the one derived from the REST client.
+    #[must_use]
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Describe the client with the given properties instead of the default ones.
+    #[must_use]
+    pub fn with_properties(mut self, properties: ClientProperties) -> Self {
+        self.properties = properties;
+        self
+    }
+
+    /// A handle to stop the connection from outside of event handlers.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Connect to the gateway and dispatch events to the handler until shut down.
+    ///
+    /// Dropped connections are reopened, honoring the delay the server asks for.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::SessionClosed`] - If the server closed the session and reconnecting would fail the same way,
+    ///   such as when the token is invalid or the session was replaced by another connection.
+    pub async fn run(self, handler: impl EventHandler + 'static) -> Result<(), ClientError> {
+        let handler: Arc<dyn EventHandler> = Arc::new(handler);
+        let mut backoff = INITIAL_BACKOFF;
+        let mut resume = None;
+
+        loop {
+            let delay = match self.run_session(&handler, &mut backoff, &mut resume).await {
+                Ok(SessionEnd::Shutdown) => return Ok(()),
+                Ok(SessionEnd::Reconnect(delay)) => delay,
+                Err(error @ ClientError::SessionClosed { .. }) => return Err(error),
+                Err(error) => {
+                    tracing::warn!(error = %error, "Gateway connection failed, reconnecting in {backoff:?}");
+                    let delay = backoff;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    delay
+                }
+            };
+
+            tokio::select! {
+                () = sleep(delay) => {}
+                () = ShutdownHandle::triggered(self.shutdown.sender.subscribe()) => return Ok(()),
+            }
+        }
+    }
+
+    /// Open a new session, or resume the previous one, and dispatch its events until the connection ends.
+    ///
+    /// ## Arguments
+    ///
+    /// * `handler` - The handler to dispatch events to.
+    /// * `backoff` - The delay before reconnecting after a failure, reset once the session is ready.
+    /// * `resume` - The session to resume, updated as events are received and cleared once the session ended.
+    async fn run_session(
+        &self,
+        handler: &Arc<dyn EventHandler>,
+        backoff: &mut Duration,
+        resume: &mut Option<ResumeState>,
+    ) -> Result<SessionEnd, ClientError> {
+        let (mut socket, _) = tokio_tungstenite::connect_async(self.url.as_str()).await?;
+        let hello: Hello = match timeout(HELLO_TIMEOUT, next_incoming(&mut socket)).await {
+            Ok(Ok(Incoming::Event(raw))) if raw.event == "HELLO" => serde_json::from_value(raw.data)?,
+            Ok(Err(error)) => return Err(error),
+            _ => return Err(ClientError::Protocol("Expected HELLO after connecting")),
+        };
+
+        let handshake = resume.as_ref().map_or_else(
+            || {
+                json!({
+                    "event": "IDENTIFY",
+                    "data": { "token": self.rest.token(), "properties": self.properties },
+                })
+            },
+            |state| {
+                json!({
+                    "event": "RESUME",
+                    "data": {
+                        "token": self.rest.token(),
+                        "session_id": state.session_id,
+                        "resume_token": state.resume_token,
+                        "seq": state.seq,
+                    },
+                })
+            },
+        );
+        socket.send(WsMessage::Text(handshake.to_string())).await?;
+
+        let ctx = Context {
+            rest: self.rest.clone(),
+            shutdown: self.shutdown.clone(),
+        };
+        let period = Duration::from_millis(hello.heartbeat_interval);
+        let mut heartbeat = interval_at(Instant::now() + period, period);
+        let mut awaiting_ack = false;
+        let mut invalid_session = None;
+        let shutdown = ShutdownHandle::triggered(self.shutdown.sender.subscribe());
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                () = &mut shutdown => {
+                    // A normal closure ends the session instead of keeping it around to be resumed
+                    let frame = CloseFrame { code: CloseCode::Normal, reason: "".into() };
+                    let _ = socket.close(Some(frame)).await;
+                    return Ok(SessionEnd::Shutdown);
+                }
+                _ = heartbeat.tick() => {
+                    // The connection is most likely dead if the last heartbeat was not acknowledged
+                    if awaiting_ack {
+                        let _ = socket.close(None).await;
+                        return Err(ClientError::Protocol("Heartbeat was not acknowledged"));
+                    }
+                    let seq = resume.as_ref().map_or(0, |state| state.seq);
+                    let heartbeat = json!({ "event": "HEARTBEAT", "data": { "seq": seq } });
+                    socket.send(WsMessage::Text(heartbeat.to_string())).await?;
+                    awaiting_ack = true;
+                }
+                incoming = next_incoming(&mut socket) => {
+                    let raw = match incoming? {
+                        Incoming::Event(raw) => raw,
+                        Incoming::Closed(code) => return closed(invalid_session.take(), code),
+                    };
+                    if raw.event == "HEARTBEAT_ACK" {
+                        awaiting_ack = false;
+                        continue;
+                    }
+                    ResumeState::track(resume, &raw);
+
+                    let event = match Event::from_raw(raw) {
+                        Ok(event) => event,
+                        Err(error) => {
+                            tracing::warn!(error = %error, "Failed to decode gateway event");
+                            continue;
+                        }
+                    };
+                    match &event {
+                        Event::Ready(_) | Event::Resumed => *backoff = INITIAL_BACKOFF,
+                        Event::InvalidSession(payload) => {
+                            // Only sessions that timed out are kept around to be resumed
+                            if payload.code != GatewayCloseCode::SessionTimeout {
+                                *resume = None;
+                            }
+                            invalid_session = Some(payload.clone());
+                        }
+                        _ => {}
+                    }
+
+                    let handler = handler.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move { handler.on_event(ctx, event).await });
+                }
+            }
+        }
+    }
+}
+
+/// Decide how to continue after the server closed the connection.
+///
+/// ## Arguments
+///
+/// * `invalid_session` - The `INVALID_SESSION` payload sent before closing, if any.
+/// * `close_code` - The code of the close frame, if any.
+fn closed(invalid_session: Option<InvalidSession>, close_code: Option<u16>) -> Result<SessionEnd, ClientError> {
+    let (code, reason, reconnect_after) = if let Some(payload) = invalid_session {
+        (
+            payload.code,
+            payload.reason,
+            payload.reconnect_after_ms.map(Duration::from_millis),
+        )
+    } else {
+        let code = close_code.map_or(GatewayCloseCode::Abnormal, GatewayCloseCode::from);
+        (code, String::new(), code.reconnect_after())
+    };
+    reconnect_after.map_or(Err(ClientError::SessionClosed { code, reason }), |delay| {
+        Ok(SessionEnd::Reconnect(delay))
+    })
+}
+
+/// A frame received from the gateway that matters to the client.
+enum Incoming {
+    /// An event.
+    Event(RawEvent),
+    /// The connection was closed, with the code of the close frame if the server sent one.
+    Closed(Option<u16>),
+}
+
+/// Read the next event from the socket, skipping frames that are not events.
+async fn next_incoming(socket: &mut Socket) -> Result<Incoming, ClientError> {
+    while let Some(message) = socket.next().await {
+        match message? {
+            WsMessage::Text(text) => return Ok(Incoming::Event(serde_json::from_str(&text)?)),
+            WsMessage::Close(frame) => return Ok(Incoming::Closed(frame.map(|frame| frame.code.into()))),
+            _ => {}
+        }
+    }
+    Ok(Incoming::Closed(None))
+}
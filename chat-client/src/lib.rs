@@ -0,0 +1,40 @@
+//! A client for the chat REST API and gateway.
+//!
+//! [`RestClient`] makes typed calls to the REST API, while [`Gateway`] keeps a connection to the gateway open,
+//! sending heartbeats and reconnecting as needed, and dispatches events to an [`EventHandler`].
+//!
+//! ```no_run
+//! use chat_client::{async_trait, Context, Event, EventHandler, Gateway, RestClient};
+//!
+//! struct PingBot;
+//!
+//! #[async_trait]
+//! impl EventHandler for PingBot {
+//!     async fn on_event(&self, ctx: Context, event: Event) {
+//!         if let Event::MessageCreate(message) = event {
+//!             if message.content.as_deref() == Some("!ping") {
+//!                 let _ = ctx.rest().send_message(message.channel_id, "pong").await;
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! # async fn run() -> Result<(), chat_client::ClientError> {
+//! let mut rest = RestClient::new("https://chat.example.com");
+//! rest.login("ping_bot", "hunter2").await?;
+//! Gateway::new(rest)?.run(PingBot).await
+//! # }
+//! ```
+
+pub mod error;
+pub mod events;
+pub mod gateway;
+pub mod models;
+pub mod rest;
+
+pub use async_trait::async_trait;
+pub use chat_core::{close_code::GatewayCloseCode, errors::ErrorCode, snowflake::Snowflake};
+pub use error::ClientError;
+pub use events::Event;
+pub use gateway::{ClientProperties, Context, DeviceType, EventHandler, Gateway, ShutdownHandle};
+pub use rest::{FetchMessages, RestClient};
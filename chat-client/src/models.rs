@@ -0,0 +1,176 @@
+//! The objects returned by the REST API and sent through the gateway, as seen by a client.
+//!
+//! Only the fields a client commonly needs are included. Unknown fields are ignored and
+//! fields added by newer servers are optional, so these types keep deserializing as the API grows.
+
+use chat_core::snowflake::Snowflake;
+use serde::{Deserialize, Serialize};
+
+/// The presence of a user.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Presence {
+    /// The user is currently active.
+    Online,
+    /// The user is idle or away from the keyboard.
+    Away,
+    /// The user is busy.
+    Busy,
+    /// The user is offline or invisible.
+    Offline,
+    /// A presence this client does not know yet.
+    #[serde(other)]
+    Unknown,
+}
+
+/// A user of the chat.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct User {
+    /// The ID of the user.
+    pub id: Snowflake<Self>,
+    /// The unique name of the user.
+    pub username: String,
+    /// The name shown instead of the username, if set.
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// The hash of the user's avatar, if set.
+    #[serde(default)]
+    pub avatar_hash: Option<String>,
+    /// The presence of the user, only sent in contexts where it is known.
+    #[serde(default)]
+    pub presence: Option<Presence>,
+}
+
+/// A user in the context of a guild.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct Member {
+    /// The user this member represents.
+    pub user: User,
+    /// The guild the member is in.
+    pub guild_id: Snowflake<Guild>,
+    /// The nickname of the member in the guild, if set.
+    #[serde(default)]
+    pub nickname: Option<String>,
+    /// UNIX timestamp of when the member joined the guild.
+    pub joined_at: i64,
+    /// UNIX timestamp of when the member's timeout expires, if they are timed out.
+    #[serde(default)]
+    pub timeout_until: Option<i64>,
+}
+
+/// The author of a message, a member if the message was sent in a guild and the author is still in it.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Author {
+    Member(Member),
+    User(User),
+}
+
+impl Author {
+    /// The user behind the author.
+    pub const fn user(&self) -> &User {
+        match self {
+            Self::Member(member) => &member.user,
+            Self::User(user) => user,
+        }
+    }
+}
+
+/// A guild, a group of users and channels.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct Guild {
+    /// The ID of the guild.
+    pub id: Snowflake<Self>,
+    /// The name of the guild.
+    pub name: String,
+    /// The user owning the guild.
+    pub owner_id: Snowflake<User>,
+    /// The hash of the guild's avatar, if set.
+    #[serde(default)]
+    pub avatar_hash: Option<String>,
+}
+
+/// A channel messages can be sent in.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct Channel {
+    /// The ID of the channel.
+    pub id: Snowflake<Self>,
+    /// The kind of the channel, such as `GUILD_TEXT`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// The name of the channel.
+    pub name: String,
+    /// The guild the channel belongs to, if it is a guild channel.
+    #[serde(default)]
+    pub guild_id: Option<Snowflake<Guild>>,
+    /// The ID of the last message sent in the channel, if any.
+    #[serde(default)]
+    pub last_message_id: Option<Snowflake<Message>>,
+    /// Whether the channel is archived. Messages cannot be sent in archived channels.
+    #[serde(default)]
+    pub archived: bool,
+    /// The ID of the message kept visible at the top of the channel, if any.
+    #[serde(default)]
+    pub sticky_message_id: Option<Snowflake<Message>>,
+}
+
+/// A file sent with a message.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct Attachment {
+    /// The position of the attachment within the message, starting from 0.
+    pub id: u8,
+    /// The name of the file, including the file extension.
+    pub filename: String,
+    /// The MIME type of the file.
+    pub content_type: String,
+}
+
+/// A message sent in a channel.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct Message {
+    /// The ID of the message, this also encodes when it was sent.
+    pub id: Snowflake<Self>,
+    /// The channel the message was sent in.
+    pub channel_id: Snowflake<Channel>,
+    /// The author of the message, if it still exists and the message was not posted by the server.
+    #[serde(default)]
+    pub author: Option<Author>,
+    /// The nonce the message was sent with, only present in gateway events.
+    #[serde(default)]
+    pub nonce: Option<String>,
+    /// The content of the message.
+    #[serde(default)]
+    pub content: Option<String>,
+    /// The files sent with the message.
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// The users mentioned in the content of the message.
+    #[serde(default)]
+    pub mentions: Vec<Snowflake<User>>,
+}
+
+/// The response of a successful login.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct Session {
+    /// The user that logged in.
+    pub user_id: Snowflake<User>,
+    /// The token to authenticate requests and gateway connections with.
+    pub token: String,
+}
+
+/// Information about a server that clients need to interpret its responses.
+#[derive(Deserialize, Debug, Clone)]
+#[non_exhaustive]
+pub struct ServerMeta {
+    /// The version of the server.
+    pub version: String,
+    /// The epoch of the server's snowflakes, as a UNIX timestamp in milliseconds.
+    pub snowflake_epoch: i64,
+}
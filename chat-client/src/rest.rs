@@ -0,0 +1,303 @@
+use chat_core::{errors::ErrorCode, snowflake::Snowflake};
+use reqwest::{Method, RequestBuilder, Response};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    error::ClientError,
+    models::{Channel, Guild, Member, Message, ServerMeta, Session, User},
+};
+
+/// The boundary of multipart bodies sent by the client.
+const MULTIPART_BOUNDARY: &str = "chat-client-boundary";
+
+/// The error body returned by the API for unsuccessful requests.
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+    code: Option<serde_json::Value>,
+}
+
+/// The parameters of fetching the message history of a channel, see [`RestClient::fetch_messages`].
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub struct FetchMessages {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    before: Option<Snowflake<Message>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    after: Option<Snowflake<Message>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<u32>,
+}
+
+impl FetchMessages {
+    /// Only fetch messages sent before the given message.
+    #[must_use]
+    pub const fn before(mut self, message: Snowflake<Message>) -> Self {
+        self.before = Some(message);
+        self
+    }
+
+    /// Only fetch messages sent after the given message.
+    #[must_use]
+    pub const fn after(mut self, message: Snowflake<Message>) -> Self {
+        self.after = Some(message);
+        self
+    }
+
+    /// The maximum amount of messages to fetch, the server caps this at its own limit.
+    #[must_use]
+    pub const fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+/// A client for the REST API.
+///
+/// Cloning the client is cheap, the clones share their connection pool.
+#[derive(Debug, Clone)]
+pub struct RestClient {
+    http: reqwest::Client,
+    /// The URL of the server, without a trailing slash, such as `https://chat.example.com`.
+    base_url: String,
+    /// The token requests are authenticated with, if logged in.
+    token: Option<String>,
+}
+
+impl RestClient {
+    /// Create a new client that is not logged in.
+    ///
+    /// ## Arguments
+    ///
+    /// * `base_url` - The URL of the server, such as `https://chat.example.com`.
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url: String = base_url.into();
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: None,
+        }
+    }
+
+    /// Authenticate requests with the given token.
+    #[must_use]
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// The token requests are authenticated with, if logged in.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// The URL of the gateway of the server.
+    pub fn gateway_url(&self) -> String {
+        let url = self
+            .base_url
+            .strip_prefix("http")
+            .map_or_else(|| self.base_url.clone(), |rest| format!("ws{rest}"));
+        format!("{url}/gateway/v1")
+    }
+
+    /// Fetch information about the server, and decode snowflakes with its epoch from now on.
+    ///
+    /// Snowflake creation times are only correct after calling this if the server uses a custom epoch.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::Http`] - If the server is unreachable.
+    pub async fn fetch_meta(&self) -> Result<ServerMeta, ClientError> {
+        let meta: ServerMeta = self.json(self.build(Method::GET, "/meta")).await?;
+        chat_core::snowflake::set_epoch(meta.snowflake_epoch);
+        Ok(meta)
+    }
+
+    /// Register a new user. This does not log in as the user.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::Api`] - If the username is taken or the credentials are invalid.
+    pub async fn register(&self, username: &str, password: &str) -> Result<User, ClientError> {
+        let credentials = json!({ "username": username, "password": password });
+        self.json(self.build(Method::POST, "/users").json(&credentials)).await
+    }
+
+    /// Log in as a user, authenticating all further requests of this client as them.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::Api`] - If the credentials are wrong.
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<Session, ClientError> {
+        let credentials = json!({ "username": username, "password": password });
+        let session: Session = self
+            .json(self.build(Method::POST, "/users/auth").json(&credentials))
+            .await?;
+        self.token = Some(session.token.clone());
+        Ok(session)
+    }
+
+    /// Fetch the user the client is logged in as.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::MissingToken`] - If the client is not logged in.
+    pub async fn fetch_self(&self) -> Result<User, ClientError> {
+        self.json(self.authed(Method::GET, "/users/@me")?).await
+    }
+
+    /// Create a new guild owned by the current user.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::Api`] - If the user is in too many guilds or the name is invalid.
+    pub async fn create_guild(&self, name: &str) -> Result<Guild, ClientError> {
+        self.json(self.authed(Method::POST, "/guilds")?.json(&json!({ "name": name })))
+            .await
+    }
+
+    /// Fetch a guild the current user is a member of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::Api`] - If the guild does not exist or the user is not a member.
+    pub async fn fetch_guild(&self, guild: Snowflake<Guild>) -> Result<Guild, ClientError> {
+        self.json(self.authed(Method::GET, &format!("/guilds/{guild}"))?).await
+    }
+
+    /// Join a guild as the current user, returning the new member.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::Api`] - If the guild does not exist or the user may not join it.
+    pub async fn join_guild(&self, guild: Snowflake<Guild>) -> Result<Member, ClientError> {
+        self.json(self.authed(Method::POST, &format!("/guilds/{guild}/members"))?)
+            .await
+    }
+
+    /// Leave a guild as the current user.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::Api`] - If the guild does not exist or the user owns it.
+    pub async fn leave_guild(&self, guild: Snowflake<Guild>) -> Result<(), ClientError> {
+        self.send(self.authed(Method::DELETE, &format!("/guilds/{guild}/members/@me"))?)
+            .await
+            .map(drop)
+    }
+
+    /// Create a new text channel in a guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::Api`] - If the user may not manage the guild or it has too many channels.
+    pub async fn create_channel(&self, guild: Snowflake<Guild>, name: &str) -> Result<Channel, ClientError> {
+        let payload = json!({ "type": "GUILD_TEXT", "name": name });
+        self.json(
+            self.authed(Method::POST, &format!("/guilds/{guild}/channels"))?
+                .json(&payload),
+        )
+        .await
+    }
+
+    /// Fetch the message history of a channel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::Api`] - If the channel does not exist or the user may not view it.
+    pub async fn fetch_messages(
+        &self,
+        channel: Snowflake<Channel>,
+        query: FetchMessages,
+    ) -> Result<Vec<Message>, ClientError> {
+        self.json(
+            self.authed(Method::GET, &format!("/channels/{channel}/messages"))?
+                .query(&query),
+        )
+        .await
+    }
+
+    /// Send a message without attachments to a channel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ClientError::Api`] - If the channel does not exist, the user may not send messages in it,
+    ///   or the content is invalid.
+    pub async fn send_message(&self, channel: Snowflake<Channel>, content: &str) -> Result<Message, ClientError> {
+        let payload = json!({ "content": content });
+        let body = format!(
+            "--{MULTIPART_BOUNDARY}\r\nContent-Disposition: form-data; name=\"json\"\r\n\
+            Content-Type: application/json\r\n\r\n{payload}\r\n--{MULTIPART_BOUNDARY}--\r\n"
+        );
+        self.json(
+            self.authed(Method::POST, &format!("/channels/{channel}/messages"))?
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    format!("multipart/form-data; boundary={MULTIPART_BOUNDARY}"),
+                )
+                .body(body),
+        )
+        .await
+    }
+
+    /// Start building a request to the API.
+    ///
+    /// ## Arguments
+    ///
+    /// * `method` - The HTTP method of the request.
+    /// * `path` - The path of the endpoint, relative to `/api/v1`.
+    fn build(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http.request(method, format!("{}/api/v1{path}", self.base_url))
+    }
+
+    /// Start building a request to the API, authenticated with the client's token.
+    fn authed(&self, method: Method, path: &str) -> Result<RequestBuilder, ClientError> {
+        let token = self.token.as_deref().ok_or(ClientError::MissingToken)?;
+        Ok(self.build(method, path).bearer_auth(token))
+    }
+
+    /// Send a request, turning unsuccessful responses into errors.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, ClientError> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.bytes().await?;
+        let (code, message) = match serde_json::from_slice::<ErrorBody>(&body) {
+            // Servers newer than this client may send codes it does not know yet
+            Ok(error) => (
+                error
+                    .code
+                    .and_then(|code| serde_json::from_value::<ErrorCode>(code).ok()),
+                error.error,
+            ),
+            Err(_) => (None, String::from_utf8_lossy(&body).into_owned()),
+        };
+        Err(ClientError::Api { status, code, message })
+    }
+
+    /// Send a request and decode its JSON response.
+    async fn json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, ClientError> {
+        let body = self.send(request).await?.bytes().await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RestClient;
+
+    #[test]
+    fn test_gateway_url() {
+        assert_eq!(
+            RestClient::new("https://chat.example.com/").gateway_url(),
+            "wss://chat.example.com/gateway/v1"
+        );
+        assert_eq!(
+            RestClient::new("http://127.0.0.1:8080").gateway_url(),
+            "ws://127.0.0.1:8080/gateway/v1"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// How long clients should wait before reconnecting after a close that is likely to happen again right away.
+const RECONNECT_BACKOFF: Duration = Duration::from_secs(5);
+/// The least amount of time clients should wait before reconnecting after a server restart.
+pub const RESTART_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The code a gateway connection is closed with, sent in the close frame and in `INVALID_SESSION`.
+///
+/// Codes below 4000 are defined by RFC 6455 and are only used for problems on the websocket level.
+/// Codes from 4000 are specific to this gateway and tell clients why their session ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum GatewayCloseCode {
+    /// Successful operation / regular socket shutdown
+    Normal = 1000,
+    /// Client/Server is leaving (browser tab closing, server shutting down, etc.)
+    GoingAway = 1001,
+    /// Endpoint received a malformed frame
+    ProtocolError = 1002,
+    /// Endpoint received an unsupported frame (e.g. binary-only endpoint received text frame)
+    Unsupported = 1003,
+    /// Expected close status, received none
+    NoStatus = 1005,
+    /// No close code frame has been receieved
+    Abnormal = 1006,
+    /// Endpoint received inconsistent message (e.g. malformed UTF-8)
+    InvalidFramePayload = 1007,
+    ///Generic code used for situations other than 1003 and 1009
+    PolicyViolation = 1008,
+    /// Endpoint won't process large frame
+    TooLarge = 1009,
+    /// Client wanted an extension which server did not negotiate
+    ExtensionRequired = 1010,
+    /// Internal server error while operating
+    ServerError = 1011,
+    /// Server/service is restarting
+    ServiceRestart = 1012,
+    /// Temporary server condition forced blocking client's request
+    TryAgainLater = 1013,
+    /// Server acting as gateway received an invalid response
+    BadGateway = 1014,
+    /// Transport Layer Security handshake failure
+    TLSHandshakeFail = 1015,
+    /// The token sent in `IDENTIFY` was missing or invalid
+    AuthenticationFailed = 4001,
+    /// The client sent a payload that could not be parsed, was invalid, or was not expected at the time
+    InvalidPayload = 4002,
+    /// The client sent a request too often
+    RateLimited = 4003,
+    /// The client did not `IDENTIFY` or `HEARTBEAT` in time, or stopped answering pings
+    SessionTimeout = 4004,
+    /// The server is shutting down or restarting
+    ServerRestart = 4005,
+    /// The user identified on a new connection, which replaced this one
+    SessionReplaced = 4006,
+    /// The client tried to connect to a shard not served by this process
+    InvalidShard = 4007,
+    /// The session sent in `RESUME` does not exist anymore or missed too many events, the client must `IDENTIFY`
+    ResumeFailed = 4008,
+}
+
+impl GatewayCloseCode {
+    /// How long clients should wait before reconnecting after a connection was closed with this code,
+    /// or `None` if reconnecting would fail again the same way without the client changing something first.
+    pub const fn reconnect_after(self) -> Option<Duration> {
+        match self {
+            Self::AuthenticationFailed | Self::SessionReplaced | Self::InvalidShard => None,
+            Self::Normal | Self::GoingAway | Self::SessionTimeout | Self::ResumeFailed => Some(Duration::ZERO),
+            Self::ServerRestart | Self::ServiceRestart => Some(RESTART_RECONNECT_DELAY),
+            _ => Some(RECONNECT_BACKOFF),
+        }
+    }
+}
+
+impl From<GatewayCloseCode> for u16 {
+    fn from(value: GatewayCloseCode) -> Self {
+        value as Self
+    }
+}
+
+impl From<u16> for GatewayCloseCode {
+    fn from(value: u16) -> Self {
+        match value {
+            1000 => Self::Normal,
+            1001 => Self::GoingAway,
+            1002 => Self::ProtocolError,
+            1003 => Self::Unsupported,
+            1005 => Self::NoStatus,
+            1006 => Self::Abnormal,
+            1007 => Self::InvalidFramePayload,
+            1008 => Self::PolicyViolation,
+            1009 => Self::TooLarge,
+            1010 => Self::ExtensionRequired,
+            1012 => Self::ServiceRestart,
+            1013 => Self::TryAgainLater,
+            1014 => Self::BadGateway,
+            1015 => Self::TLSHandshakeFail,
+            4001 => Self::AuthenticationFailed,
+            4002 => Self::InvalidPayload,
+            4003 => Self::RateLimited,
+            4004 => Self::SessionTimeout,
+            4005 => Self::ServerRestart,
+            4006 => Self::SessionReplaced,
+            4007 => Self::InvalidShard,
+            4008 => Self::ResumeFailed,
+            _ => Self::ServerError,
+        }
+    }
+}
+
+impl Serialize for GatewayCloseCode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        (*self as u16).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for GatewayCloseCode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        u16::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GatewayCloseCode;
+
+    #[test]
+    fn test_round_trip() {
+        for code in [
+            GatewayCloseCode::Normal,
+            GatewayCloseCode::Unsupported,
+            GatewayCloseCode::AuthenticationFailed,
+            GatewayCloseCode::InvalidPayload,
+            GatewayCloseCode::RateLimited,
+            GatewayCloseCode::SessionTimeout,
+            GatewayCloseCode::ServerRestart,
+            GatewayCloseCode::SessionReplaced,
+            GatewayCloseCode::InvalidShard,
+            GatewayCloseCode::ResumeFailed,
+        ] {
+            assert_eq!(GatewayCloseCode::from(u16::from(code)), code);
+            let json = serde_json::to_string(&code).expect("Close code should serialize");
+            assert_eq!(json, u16::from(code).to_string());
+            let parsed: GatewayCloseCode = serde_json::from_str(&json).expect("Close code should deserialize");
+            assert_eq!(parsed, code);
+        }
+
+        assert_eq!(GatewayCloseCode::AuthenticationFailed.reconnect_after(), None);
+        assert!(GatewayCloseCode::SessionTimeout.reconnect_after().is_some());
+    }
+}
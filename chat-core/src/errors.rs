@@ -0,0 +1,126 @@
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// A machine-readable code included as `code` in every error response of the REST API.
+///
+/// Codes are stable, clients should branch on them instead of the human-readable error message.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// The server failed to handle the request.
+    InternalError,
+    /// A service the server depends on, such as the database or file storage, is unavailable.
+    ServiceUnavailable,
+    /// An upstream server, such as a proxied host, failed to respond.
+    UpstreamError,
+    /// The request took too long to handle.
+    RequestTimeout,
+    /// The request body is too large.
+    PayloadTooLarge,
+    /// The route does not support the request method.
+    MethodNotAllowed,
+    /// The request is malformed, such as a body that is not valid JSON, or a missing field.
+    BadRequest,
+    /// The request is well-formed, but failed validation.
+    ValidationFailed,
+    /// A field is shorter or longer than allowed.
+    InvalidLength,
+    /// A field is outside of the allowed range.
+    OutOfRange,
+    /// A field is required, but was not provided.
+    Required,
+    /// A field is longer than allowed.
+    TooLong,
+    /// A field contains too many items.
+    TooMany,
+    /// A field is not one of the allowed values.
+    UnknownReason,
+    /// The object cannot change to the requested state from its current state.
+    InvalidTransition,
+    /// The username is reserved and cannot be registered.
+    UsernameReserved,
+    /// The username or password is incorrect.
+    InvalidCredentials,
+    /// The request requires a token, but none was provided.
+    MissingCredentials,
+    /// The token is invalid or expired.
+    InvalidToken,
+    /// The request is authenticated by a session cookie, but its CSRF token is missing or invalid.
+    InvalidCsrfToken,
+    /// The user is not allowed to access the resource.
+    Forbidden,
+    /// The guild can only be joined with an invite.
+    InviteRequired,
+    /// The user's account is too new to join the guild.
+    AccountTooNew,
+    /// The resource does not exist, or is not available to the user.
+    NotFound,
+    /// The request conflicts with the current state of the resource.
+    Conflict,
+    /// The client made too many requests to a rate limit bucket.
+    RateLimited,
+    /// The guild is in raid mode, and the member sent messages faster than its slow mode allows.
+    SlowMode,
+    /// The request would exceed a limit set by the server operator.
+    LimitExceeded,
+}
+
+impl ErrorCode {
+    /// The most fitting code for an error response with the given status,
+    /// used for responses that were not created by a route, such as rejected requests.
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => Self::InvalidToken,
+            StatusCode::FORBIDDEN => Self::Forbidden,
+            StatusCode::NOT_FOUND => Self::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => Self::MethodNotAllowed,
+            StatusCode::REQUEST_TIMEOUT => Self::RequestTimeout,
+            StatusCode::CONFLICT => Self::Conflict,
+            StatusCode::PAYLOAD_TOO_LARGE => Self::PayloadTooLarge,
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited,
+            StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT => Self::UpstreamError,
+            StatusCode::SERVICE_UNAVAILABLE => Self::ServiceUnavailable,
+            status if status.is_client_error() => Self::BadRequest,
+            _ => Self::InternalError,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+
+    use super::ErrorCode;
+
+    #[test]
+    fn test_code_names() {
+        // Codes are part of the public API, renaming a variant must not change them
+        for (code, name) in [
+            (ErrorCode::InternalError, "INTERNAL_ERROR"),
+            (ErrorCode::UsernameReserved, "USERNAME_RESERVED"),
+            (ErrorCode::OutOfRange, "OUT_OF_RANGE"),
+            (ErrorCode::RateLimited, "RATE_LIMITED"),
+            (ErrorCode::LimitExceeded, "LIMIT_EXCEEDED"),
+            (ErrorCode::AccountTooNew, "ACCOUNT_TOO_NEW"),
+        ] {
+            assert_eq!(serde_json::to_value(code).expect("Codes should serialize"), name);
+        }
+    }
+
+    #[test]
+    fn test_from_status() {
+        assert_eq!(ErrorCode::from_status(StatusCode::NOT_FOUND), ErrorCode::NotFound);
+        assert_eq!(
+            ErrorCode::from_status(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+            ErrorCode::BadRequest
+        );
+        assert_eq!(
+            ErrorCode::from_status(StatusCode::PAYLOAD_TOO_LARGE),
+            ErrorCode::PayloadTooLarge
+        );
+        assert_eq!(
+            ErrorCode::from_status(StatusCode::NOT_IMPLEMENTED),
+            ErrorCode::InternalError
+        );
+    }
+}
@@ -0,0 +1,8 @@
+//! Types shared by the chat server and its clients.
+//!
+//! This crate has no dependencies on the server's web framework or database driver, so clients and bots
+//! can use it to speak the same protocol as the server. Enable the `sqlx` feature to store snowflakes in Postgres.
+
+pub mod close_code;
+pub mod errors;
+pub mod snowflake;
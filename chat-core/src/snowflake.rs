@@ -0,0 +1,414 @@
+#[cfg(feature = "sqlx")]
+use std::error::Error;
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display, Formatter},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    num::ParseIntError,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        LazyLock, Mutex, PoisonError,
+    },
+};
+
+use chrono::prelude::*;
+use serde::{de::Visitor, Deserialize, Serialize};
+use snowflake::SnowflakeIdGenerator;
+#[cfg(feature = "sqlx")]
+use sqlx::{postgres::PgHasArrayType, Decode, Encode};
+use std::time::SystemTime;
+
+/// The epoch snowflakes use unless configured otherwise, 2023-01-01T00:00:00Z in milliseconds.
+pub const DEFAULT_EPOCH: i64 = 1_672_531_200_000;
+/// The epoch of Discord snowflakes, 2015-01-01T00:00:00Z in milliseconds.
+pub const DISCORD_EPOCH: i64 = 1_420_070_400_000;
+/// The epoch of Twitter snowflakes, 2010-11-04T01:42:54.657Z in milliseconds.
+pub const TWITTER_EPOCH: i64 = 1_288_834_974_657;
+
+/// The bits of a snowflake below its timestamp.
+const TIMESTAMP_SHIFT: u32 = 22;
+/// The largest timestamp relative to the epoch that fits into a snowflake.
+const MAX_TIMESTAMP: i64 = (1 << 41) - 1;
+
+/// The epoch of all snowflakes of this process.
+static ACTIVE_EPOCH: AtomicI64 = AtomicI64::new(DEFAULT_EPOCH);
+
+/// Identifies a snowflake generator by machine ID, process ID and epoch.
+type GeneratorKey = (i32, i32, i64);
+
+/// The snowflake generators of every source.
+static GENERATORS: LazyLock<Mutex<HashMap<GeneratorKey, SnowflakeIdGenerator>>> = LazyLock::new(Mutex::default);
+
+/// The epoch snowflakes are generated and decoded with, as a UNIX timestamp in milliseconds.
+pub fn epoch() -> i64 {
+    ACTIVE_EPOCH.load(Ordering::Relaxed)
+}
+
+/// Set the epoch snowflakes are generated and decoded with for the whole process.
+///
+/// Servers set this from their configuration on startup. Clients should set it to the epoch the server
+/// reports, otherwise the creation times of snowflakes are off if the server does not use [`DEFAULT_EPOCH`].
+///
+/// ## Arguments
+///
+/// * `epoch` - UNIX timestamp in milliseconds.
+pub fn set_epoch(epoch: i64) {
+    ACTIVE_EPOCH.store(epoch, Ordering::Relaxed);
+}
+
+/// Parse an epoch as a UNIX timestamp in milliseconds, or as the name of the epoch of a compatible system:
+/// `default`, `discord` or `twitter`.
+///
+/// ## Returns
+///
+/// The epoch in milliseconds, or `None` if it is neither a known name nor a non-negative integer.
+pub fn parse_epoch(s: &str) -> Option<i64> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "default" => Some(DEFAULT_EPOCH),
+        "discord" => Some(DISCORD_EPOCH),
+        "twitter" => Some(TWITTER_EPOCH),
+        other => other.parse::<i64>().ok().filter(|epoch| *epoch >= 0),
+    }
+}
+
+/// Identifies the process generating snowflakes, so snowflakes generated by different processes never collide.
+pub trait SnowflakeSource {
+    /// The ID of the machine, encoded as the worker ID of generated snowflakes.
+    fn machine_id(&self) -> i32;
+
+    /// The ID of the process on its machine.
+    fn process_id(&self) -> i32;
+}
+
+/// A snowflake ID used to identify entities.
+///
+/// Snowflakes are 64-bit integers that are guaranteed to be unique.
+/// The first 41 bits are a timestamp, the next 10 are a worker ID, and the last 12 are a process ID.
+#[repr(transparent)]
+pub struct Snowflake<T> {
+    // Note: We are using i64 instead of u64 because postgres does not support unsigned integers.
+    value: i64,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Snowflake<T> {
+    /// Create a new snowflake from a 64-bit integer.
+    pub const fn new(value: i64) -> Self {
+        Self {
+            value,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Generate a new snowflake using the current time.
+    ///
+    /// ## Arguments
+    ///
+    /// * `source` - The process generating the snowflake, such as the server configuration.
+    pub fn gen_new(source: &impl SnowflakeSource) -> Self {
+        let key = (source.machine_id(), source.process_id(), epoch());
+        // Generators are shared, so that snowflakes generated within the same millisecond differ in their sequence
+        let mut generators = GENERATORS.lock().unwrap_or_else(PoisonError::into_inner);
+        generators
+            .entry(key)
+            .or_insert_with(|| get_generator(key.0, key.1, key.2))
+            .real_time_generate()
+            .into()
+    }
+
+    /// Create the smallest snowflake that could have been generated at the given time.
+    /// Useful for comparing snowflakes against a point in time.
+    ///
+    /// ## Arguments
+    ///
+    /// * `timestamp` - UNIX timestamp in milliseconds.
+    pub fn from_timestamp(timestamp: i64) -> Self {
+        Self::new((timestamp - epoch()) << TIMESTAMP_SHIFT)
+    }
+
+    /// Convert a snowflake of another system to a snowflake of this one, keeping its creation time.
+    /// The bits below the timestamp, such as the worker ID and sequence, are kept as they are.
+    ///
+    /// Useful to import data, such as messages, from systems with a different epoch without
+    /// changing when the imported objects appear to have been created.
+    ///
+    /// ## Arguments
+    ///
+    /// * `value` - The snowflake of the other system.
+    /// * `foreign_epoch` - The epoch of the other system, as a UNIX timestamp in milliseconds.
+    ///
+    /// ## Returns
+    ///
+    /// The converted snowflake, or `None` if its creation time cannot be represented with the current epoch,
+    /// such as if it was created before the epoch.
+    pub fn from_foreign(value: i64, foreign_epoch: i64) -> Option<Self> {
+        let timestamp = (value >> TIMESTAMP_SHIFT).checked_add(foreign_epoch)?;
+        let relative = timestamp.checked_sub(epoch())?;
+        if !(0..=MAX_TIMESTAMP).contains(&relative) {
+            return None;
+        }
+        Some(Self::new(
+            relative << TIMESTAMP_SHIFT | (value & ((1 << TIMESTAMP_SHIFT) - 1)),
+        ))
+    }
+
+    /// Convert this snowflake to a snowflake of another system, keeping its creation time.
+    /// This is the inverse of [`Snowflake::from_foreign`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `foreign_epoch` - The epoch of the other system, as a UNIX timestamp in milliseconds.
+    ///
+    /// ## Returns
+    ///
+    /// The converted snowflake, or `None` if its creation time cannot be represented with the other epoch.
+    pub fn to_foreign(self, foreign_epoch: i64) -> Option<i64> {
+        let relative = self.timestamp().checked_sub(foreign_epoch)?;
+        if !(0..=MAX_TIMESTAMP).contains(&relative) {
+            return None;
+        }
+        Some(relative << TIMESTAMP_SHIFT | (self.value & ((1 << TIMESTAMP_SHIFT) - 1)))
+    }
+
+    /// Cast this snowflake to a different marker type.
+    pub const fn cast<U>(self) -> Snowflake<U> {
+        Snowflake::new(self.value)
+    }
+
+    /// UNIX timestamp representing the time at which this snowflake was created in milliseconds.
+    pub fn timestamp(&self) -> i64 {
+        (self.value >> TIMESTAMP_SHIFT) + epoch()
+    }
+
+    /// Returns the creation time of this snowflake.
+    pub fn created_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(self.timestamp()).expect("Failed to convert timestamp to DateTime")
+    }
+
+    /// Returns the worker ID that generated this snowflake.
+    pub const fn worker_id(&self) -> i64 {
+        (self.value & 0x003E_0000) >> 17
+    }
+
+    /// Returns the process ID that generated this snowflake.
+    pub const fn process_id(&self) -> i64 {
+        (self.value & 0x1F000) >> 12
+    }
+}
+
+impl<T> From<i64> for Snowflake<T> {
+    fn from(value: i64) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T> From<Snowflake<T>> for i64 {
+    fn from(snowflake: Snowflake<T>) -> Self {
+        snowflake.value
+    }
+}
+
+impl<T> Clone for Snowflake<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Snowflake<T> {}
+
+impl<T> PartialEq for Snowflake<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T> Eq for Snowflake<T> {}
+
+impl<T> Hash for Snowflake<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.hash(state);
+    }
+}
+
+impl<T> Display for Snowflake<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl<T> Debug for Snowflake<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Snowflake({})", self.value)
+    }
+}
+
+impl<T> FromStr for Snowflake<T> {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        i64::from_str(s).map(Self::new)
+    }
+}
+
+impl<T> Serialize for Snowflake<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.to_string().serialize(serializer)
+    }
+}
+
+/// Accepts snowflakes both as strings and as integers.
+struct SnowflakeVisitor<T>(PhantomData<T>);
+
+impl<T> Visitor<'_> for SnowflakeVisitor<T> {
+    type Value = Snowflake<T>;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("a snowflake as a string or an integer")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(|_| E::custom("failed parsing snowflake from string"))
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Snowflake::new(v))
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        i64::try_from(v)
+            .map(Snowflake::new)
+            .map_err(|_| E::custom("snowflake out of range"))
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Snowflake<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(SnowflakeVisitor(PhantomData))
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl<DB: sqlx::Database, T> sqlx::Type<DB> for Snowflake<T>
+where
+    i64: sqlx::Type<DB>,
+{
+    fn type_info() -> <DB as sqlx::Database>::TypeInfo {
+        <i64 as sqlx::Type<DB>>::type_info()
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl<'q, DB: sqlx::Database, T> Encode<'q, DB> for Snowflake<T>
+where
+    i64: Encode<'q, DB>,
+{
+    fn encode_by_ref(
+        &self,
+        buf: &mut <DB as sqlx::Database>::ArgumentBuffer<'q>,
+    ) -> Result<sqlx::encode::IsNull, Box<dyn Error + Send + Sync>> {
+        <i64 as sqlx::Encode<DB>>::encode_by_ref(&self.value, buf)
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl<'r, DB: sqlx::Database, T> Decode<'r, DB> for Snowflake<T>
+where
+    i64: Decode<'r, DB>,
+{
+    fn decode(value: <DB as sqlx::Database>::ValueRef<'r>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let value = <i64 as Decode<DB>>::decode(value)?;
+        Ok(Self::new(value))
+    }
+}
+
+#[cfg(feature = "sqlx")]
+impl<T> PgHasArrayType for Snowflake<T> {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        <i64 as PgHasArrayType>::array_type_info()
+    }
+}
+
+/// Retrieve a new Snowflake ID generator that uses the given epoch.
+///
+/// ## Arguments
+///
+/// * `epoch` - UNIX timestamp in milliseconds, usually [`epoch`].
+#[inline]
+pub fn get_generator(worker_id: i32, process_id: i32, epoch: i64) -> SnowflakeIdGenerator {
+    SnowflakeIdGenerator::with_epoch(
+        worker_id,
+        process_id,
+        SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(epoch.unsigned_abs()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{parse_epoch, Snowflake, SnowflakeSource, DEFAULT_EPOCH, DISCORD_EPOCH};
+
+    struct Source;
+
+    impl SnowflakeSource for Source {
+        fn machine_id(&self) -> i32 {
+            1
+        }
+
+        fn process_id(&self) -> i32 {
+            1
+        }
+    }
+
+    #[test]
+    fn test_gen_new() {
+        // Snowflakes generated in quick succession are still unique
+        let snowflakes: HashSet<Snowflake<()>> = (0..1000).map(|_| Snowflake::gen_new(&Source)).collect();
+        assert_eq!(snowflakes.len(), 1000);
+    }
+
+    #[test]
+    fn test_serde() {
+        let snowflake: Snowflake<()> = Snowflake::new(9_007_199_254_740_993);
+        let serialized = serde_json::to_string(&snowflake).expect("Snowflake should serialize");
+        assert_eq!(serialized, "\"9007199254740993\"");
+
+        let from_str: Snowflake<()> = serde_json::from_str(&serialized).expect("Snowflake should deserialize");
+        let from_int: Snowflake<()> = serde_json::from_str("9007199254740993").expect("Snowflake should deserialize");
+        assert_eq!(from_str, snowflake);
+        assert_eq!(from_int, snowflake);
+
+        assert!(serde_json::from_str::<Snowflake<()>>("\"abc\"").is_err());
+        assert!(serde_json::from_str::<Snowflake<()>>("18446744073709551615").is_err());
+        assert!(serde_json::from_str::<Snowflake<()>>("1.5").is_err());
+    }
+
+    #[test]
+    fn test_foreign() {
+        // A Discord snowflake created at 2024-01-01T00:00:00Z by worker 1, process 2 with sequence 3
+        let created_at = 1_704_067_200_000;
+        let discord = ((created_at - DISCORD_EPOCH) << 22) | (1 << 17) | (2 << 12) | 3;
+
+        let snowflake: Snowflake<()> = Snowflake::from_foreign(discord, DISCORD_EPOCH).expect("Snowflake is in range");
+        assert_eq!(snowflake.timestamp(), created_at);
+        assert_eq!(snowflake.worker_id(), 1);
+        assert_eq!(snowflake.process_id(), 2);
+        assert_eq!(snowflake.to_foreign(DISCORD_EPOCH), Some(discord));
+
+        // Snowflakes created before the epoch cannot be represented
+        let before_epoch = (DEFAULT_EPOCH - DISCORD_EPOCH - 1) << 22;
+        assert!(Snowflake::<()>::from_foreign(before_epoch, DISCORD_EPOCH).is_none());
+        assert!(Snowflake::<()>::new(0).to_foreign(DEFAULT_EPOCH + 1).is_none());
+    }
+
+    #[test]
+    fn test_parse_epoch() {
+        assert_eq!(parse_epoch("discord"), Some(DISCORD_EPOCH));
+        assert_eq!(parse_epoch(" Default "), Some(DEFAULT_EPOCH));
+        assert_eq!(parse_epoch("1420070400000"), Some(DISCORD_EPOCH));
+        assert_eq!(parse_epoch("-1"), None);
+        assert_eq!(parse_epoch("yesterday"), None);
+    }
+}
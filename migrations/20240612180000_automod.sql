@@ -0,0 +1,27 @@
+-- Add automod rules and the audit log
+
+CREATE TABLE IF NOT EXISTS "automod_rules"
+(
+    "id" BIGINT PRIMARY KEY,
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "name" TEXT NOT NULL,
+    "trigger_type" SMALLINT NOT NULL,
+    "patterns" TEXT[] NOT NULL DEFAULT '{}',
+    "action" SMALLINT NOT NULL,
+    "timeout_duration" BIGINT,
+    "enabled" BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE INDEX IF NOT EXISTS "automod_rules_guild_id_idx" ON "automod_rules" ("guild_id");
+
+CREATE TABLE IF NOT EXISTS "audit_log"
+(
+    "id" BIGINT PRIMARY KEY,
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "user_id" BIGINT REFERENCES "users" ("id") ON DELETE SET NULL,
+    "action_type" SMALLINT NOT NULL,
+    "target_id" BIGINT,
+    "reason" TEXT
+);
+
+CREATE INDEX IF NOT EXISTS "audit_log_guild_id_idx" ON "audit_log" ("guild_id");
@@ -0,0 +1,4 @@
+-- Add timeouts to members
+
+ALTER TABLE "members"
+ADD COLUMN "timeout_until" BIGINT;
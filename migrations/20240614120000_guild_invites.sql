@@ -0,0 +1,12 @@
+-- Add pending guild invites for users
+
+CREATE TABLE IF NOT EXISTS "guild_invites"
+(
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "user_id" BIGINT NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    "inviter_id" BIGINT REFERENCES "users" ("id") ON DELETE SET NULL,
+    "created_at" BIGINT NOT NULL,
+    PRIMARY KEY ("guild_id", "user_id")
+);
+
+CREATE INDEX IF NOT EXISTS "guild_invites_user_id_idx" ON "guild_invites" ("user_id");
@@ -0,0 +1,14 @@
+-- Track the last message and message count of channels
+
+ALTER TABLE "channels"
+ADD COLUMN "last_message_id" BIGINT,
+ADD COLUMN "message_count" BIGINT NOT NULL DEFAULT 0;
+
+UPDATE "channels"
+SET "last_message_id" = "stats"."last_message_id", "message_count" = "stats"."message_count"
+FROM (
+    SELECT "channel_id", MAX("id") AS "last_message_id", COUNT(*) AS "message_count"
+    FROM "messages"
+    GROUP BY "channel_id"
+) AS "stats"
+WHERE "channels"."id" = "stats"."channel_id";
@@ -0,0 +1,14 @@
+-- Add an outbox for events that are dispatched after their transaction commits
+
+CREATE TABLE IF NOT EXISTS "outbox"
+(
+    "id" BIGSERIAL PRIMARY KEY,
+    "payload" TEXT NOT NULL,
+    "guild_id" BIGINT,
+    "user_id" BIGINT,
+    "channel_id" BIGINT,
+    "created_at" BIGINT NOT NULL,
+    "delivered_at" BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS "outbox_undelivered_idx" ON "outbox" ("id") WHERE "delivered_at" IS NULL;
@@ -0,0 +1,32 @@
+-- Add outgoing guild webhooks and their delivery log
+
+CREATE TABLE IF NOT EXISTS "webhooks"
+(
+    "id" BIGINT PRIMARY KEY,
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "name" TEXT NOT NULL,
+    "url" TEXT NOT NULL,
+    "secret" TEXT NOT NULL,
+    "events" SMALLINT[] NOT NULL DEFAULT '{}',
+    "enabled" BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+CREATE INDEX IF NOT EXISTS "webhooks_guild_id_idx" ON "webhooks" ("guild_id");
+
+CREATE TABLE IF NOT EXISTS "webhook_deliveries"
+(
+    "id" BIGINT PRIMARY KEY,
+    "webhook_id" BIGINT NOT NULL REFERENCES "webhooks" ("id") ON DELETE CASCADE,
+    "event" SMALLINT NOT NULL,
+    "payload" TEXT NOT NULL,
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "next_attempt_at" BIGINT,
+    "response_status" SMALLINT,
+    "error" TEXT,
+    "delivered_at" BIGINT,
+    "created_at" BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS "webhook_deliveries_webhook_id_idx" ON "webhook_deliveries" ("webhook_id", "id");
+CREATE INDEX IF NOT EXISTS "webhook_deliveries_pending_idx" ON "webhook_deliveries" ("next_attempt_at")
+    WHERE "next_attempt_at" IS NOT NULL;
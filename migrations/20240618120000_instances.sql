@@ -0,0 +1,11 @@
+-- Track running instances to detect machine and process ID collisions
+
+CREATE TABLE IF NOT EXISTS "instances"
+(
+    "machine_id" INTEGER NOT NULL,
+    "process_id" INTEGER NOT NULL,
+    "instance_id" BIGINT NOT NULL,
+    "started_at" BIGINT NOT NULL,
+    "last_heartbeat" BIGINT NOT NULL,
+    PRIMARY KEY ("machine_id", "process_id")
+);
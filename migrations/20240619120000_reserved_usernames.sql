@@ -0,0 +1,8 @@
+-- Add reserved usernames that cannot be registered
+
+CREATE TABLE IF NOT EXISTS "reserved_usernames"
+(
+    "username" TEXT PRIMARY KEY,
+    "reason" TEXT,
+    "created_at" BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT
+);
@@ -0,0 +1,10 @@
+-- Add per-guild emoji usage statistics
+
+CREATE TABLE IF NOT EXISTS "emoji_usage"
+(
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "emoji" TEXT NOT NULL,
+    "count" BIGINT NOT NULL DEFAULT 0,
+    "last_used_at" BIGINT NOT NULL,
+    PRIMARY KEY ("guild_id", "emoji")
+);
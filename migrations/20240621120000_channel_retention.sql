@@ -0,0 +1,6 @@
+-- Add per-channel message retention
+
+ALTER TABLE "channels"
+ADD COLUMN "retention_days" INTEGER CHECK ("retention_days" > 0);
+
+CREATE INDEX IF NOT EXISTS "messages_channel_id_id_idx" ON "messages" ("channel_id", "id");
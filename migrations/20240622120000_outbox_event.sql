@@ -0,0 +1,7 @@
+-- Store the type of events in the outbox, so it is known without parsing the payload
+
+ALTER TABLE "outbox" ADD COLUMN "event" TEXT;
+
+UPDATE "outbox" SET "event" = "payload"::JSONB ->> 'event';
+
+ALTER TABLE "outbox" ALTER COLUMN "event" SET NOT NULL;
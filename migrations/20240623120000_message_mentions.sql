@@ -0,0 +1,5 @@
+-- Record the users and channels referenced by each message
+
+ALTER TABLE "messages"
+ADD COLUMN "mentions" BIGINT [] NOT NULL DEFAULT '{}',
+ADD COLUMN "channel_mentions" BIGINT [] NOT NULL DEFAULT '{}';
@@ -0,0 +1,7 @@
+-- Store rendering metadata of image, video and audio attachments
+
+ALTER TABLE "attachments"
+ADD COLUMN "width" INTEGER,
+ADD COLUMN "height" INTEGER,
+ADD COLUMN "duration" DOUBLE PRECISION,
+ADD COLUMN "blurhash" TEXT;
@@ -0,0 +1,5 @@
+-- Requirements users must meet to join a guild
+
+ALTER TABLE "guilds"
+ADD COLUMN "min_account_age" INTEGER,
+ADD COLUMN "invite_only" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,8 @@
+-- Add an event bus for forwarding gateway state changes between gateway shards
+
+CREATE TABLE IF NOT EXISTS "event_bus"
+(
+    "id" BIGSERIAL PRIMARY KEY,
+    "message" TEXT NOT NULL,
+    "created_at" BIGINT NOT NULL
+);
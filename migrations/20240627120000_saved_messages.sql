@@ -0,0 +1,13 @@
+-- Add channels owned by a single user instead of a guild, used for saved messages
+
+ALTER TABLE "channels" ALTER COLUMN "guild_id" DROP NOT NULL;
+
+ALTER TABLE "channels"
+ADD COLUMN "owner_id" BIGINT REFERENCES "users" ("id") ON DELETE CASCADE,
+ADD CONSTRAINT "channels_guild_or_owner_check" CHECK (("guild_id" IS NULL) <> ("owner_id" IS NULL));
+
+CREATE UNIQUE INDEX IF NOT EXISTS "channels_saved_messages_idx" ON "channels" ("owner_id")
+WHERE "channel_type" = 'SAVED_MESSAGES';
+
+-- Events in user-owned channels are only sent to the owner
+ALTER TABLE "outbox" ADD COLUMN "recipient_id" BIGINT;
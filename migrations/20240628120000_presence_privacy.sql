@@ -0,0 +1,5 @@
+-- Let users control who can see their presence
+
+ALTER TABLE "users"
+ADD COLUMN "presence_visibility" SMALLINT NOT NULL DEFAULT 0,
+ADD COLUMN "presence_hidden_guilds" BIGINT[] NOT NULL DEFAULT '{}';
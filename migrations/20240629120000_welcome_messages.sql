@@ -0,0 +1,3 @@
+-- Add a message sent to users when they join a guild
+
+ALTER TABLE "guilds" ADD COLUMN "welcome_message" TEXT;
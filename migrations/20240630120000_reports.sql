@@ -0,0 +1,20 @@
+-- Add reports of messages, reviewed by the owner of the guild the message was sent in
+
+CREATE TABLE IF NOT EXISTS "reports"
+(
+    "id" BIGINT PRIMARY KEY,
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "channel_id" BIGINT NOT NULL,
+    -- Reports outlive the reported message, which is why the content is copied
+    "message_id" BIGINT NOT NULL,
+    "author_id" BIGINT REFERENCES "users" ("id") ON DELETE SET NULL,
+    "content" TEXT,
+    "reporter_id" BIGINT REFERENCES "users" ("id") ON DELETE SET NULL,
+    "reason" SMALLINT NOT NULL,
+    "details" TEXT,
+    "state" SMALLINT NOT NULL DEFAULT 0,
+    "resolved_by" BIGINT REFERENCES "users" ("id") ON DELETE SET NULL
+);
+
+CREATE INDEX IF NOT EXISTS "reports_guild_id_idx" ON "reports" ("guild_id", "state", "id");
+CREATE UNIQUE INDEX IF NOT EXISTS "reports_message_reporter_idx" ON "reports" ("message_id", "reporter_id");
@@ -0,0 +1,29 @@
+-- Add a full-text search index for messages, kept up to date by a background worker
+
+CREATE TABLE IF NOT EXISTS "message_search"
+(
+    "message_id" BIGINT PRIMARY KEY REFERENCES "messages" ("id") ON DELETE CASCADE,
+    "channel_id" BIGINT NOT NULL,
+    "document" TSVECTOR NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS "message_search_document_idx" ON "message_search" USING GIN ("document");
+CREATE INDEX IF NOT EXISTS "message_search_channel_id_idx" ON "message_search" ("channel_id", "message_id");
+
+-- Messages that were created, edited or deleted since they were last indexed
+CREATE TABLE IF NOT EXISTS "search_index_queue"
+(
+    "id" BIGSERIAL PRIMARY KEY,
+    "message_id" BIGINT NOT NULL
+);
+
+-- Progress of indexing the messages that existed before the index
+CREATE TABLE IF NOT EXISTS "search_backfill"
+(
+    "id" BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK ("id"),
+    "cursor" BIGINT NOT NULL DEFAULT 0,
+    "indexed" BIGINT NOT NULL DEFAULT 0,
+    "completed_at" BIGINT
+);
+
+INSERT INTO "search_backfill" DEFAULT VALUES ON CONFLICT DO NOTHING;
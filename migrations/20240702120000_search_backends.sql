@@ -0,0 +1,3 @@
+-- Track which search backend the backfill indexed messages into, so it restarts when the backend changes
+
+ALTER TABLE "search_backfill" ADD COLUMN "backend" TEXT NOT NULL DEFAULT 'postgres';
@@ -0,0 +1,2 @@
+-- Flags set by the server operator, such as exemptions from per-user limits
+ALTER TABLE users ADD COLUMN flags BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,34 @@
+-- Add strikes issued to members, the thresholds at which they are acted upon, and guild bans
+
+CREATE TABLE IF NOT EXISTS "strikes"
+(
+    "id" BIGINT PRIMARY KEY,
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "user_id" BIGINT NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    -- NULL if the strike was issued by automod
+    "issuer_id" BIGINT REFERENCES "users" ("id") ON DELETE SET NULL,
+    "reason" TEXT,
+    "expires_at" BIGINT
+);
+
+CREATE INDEX IF NOT EXISTS "strikes_guild_user_idx" ON "strikes" ("guild_id", "user_id");
+CREATE INDEX IF NOT EXISTS "strikes_expires_at_idx" ON "strikes" ("expires_at") WHERE "expires_at" IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS "strike_policies"
+(
+    "guild_id" BIGINT PRIMARY KEY REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "timeout_threshold" INTEGER,
+    "timeout_duration" BIGINT,
+    "ban_threshold" INTEGER,
+    "strike_duration" BIGINT
+);
+
+CREATE TABLE IF NOT EXISTS "guild_bans"
+(
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "user_id" BIGINT NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    "reason" TEXT,
+    PRIMARY KEY ("guild_id", "user_id")
+);
+
+ALTER TABLE "automod_rules" ADD COLUMN "issue_strike" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+-- Deleted guilds are kept until their grace period ends, so their owner can restore them
+ALTER TABLE "guilds" ADD COLUMN IF NOT EXISTS "deleted_at" BIGINT;
+
+CREATE INDEX IF NOT EXISTS "guilds_deleted_at_idx" ON "guilds" ("deleted_at") WHERE "deleted_at" IS NOT NULL;
@@ -0,0 +1,4 @@
+-- Add announcement channels, where only the guild owner may send messages
+
+ALTER TABLE "channels"
+ADD COLUMN "announcement" BOOLEAN NOT NULL DEFAULT FALSE;
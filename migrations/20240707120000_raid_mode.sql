@@ -0,0 +1,7 @@
+-- Add raid mode, which temporarily enforces stricter join requirements and slow mode in a guild
+
+ALTER TABLE "guilds"
+ADD COLUMN "raid_mode_until" BIGINT;
+
+-- Recent joins are counted on every join to detect raids
+CREATE INDEX IF NOT EXISTS "members_guild_id_joined_at_idx" ON "members" ("guild_id", "joined_at");
@@ -0,0 +1,7 @@
+-- Track the malware scan status of attachments, existing attachments are considered clean
+
+ALTER TABLE "attachments"
+ADD COLUMN "scan_status" SMALLINT NOT NULL DEFAULT 1;
+
+-- Attachments stuck in the pending state are rescanned periodically
+CREATE INDEX IF NOT EXISTS "attachments_pending_scan_idx" ON "attachments" ("message_id") WHERE "scan_status" = 0;
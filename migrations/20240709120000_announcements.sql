@@ -0,0 +1,9 @@
+-- Add persisted server announcements, which are included in READY until they expire
+
+CREATE TABLE IF NOT EXISTS "announcements" (
+    "id" BIGINT PRIMARY KEY,
+    "content" TEXT NOT NULL,
+    "expires_at" BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS "announcements_expires_at_idx" ON "announcements" ("expires_at");
@@ -0,0 +1,5 @@
+-- Add trigram matching of usernames, for fuzzy and prefix user search
+
+CREATE EXTENSION IF NOT EXISTS "pg_trgm";
+
+CREATE INDEX IF NOT EXISTS "users_username_trgm_idx" ON "users" USING GIN (LOWER("username") gin_trgm_ops);
@@ -0,0 +1,4 @@
+-- Let users control who can see their public profile
+
+ALTER TABLE "users"
+ADD COLUMN "profile_visibility" SMALLINT NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Cache translations of messages per language, so each message is only sent to the translation service once
+
+CREATE TABLE IF NOT EXISTS "message_translations" (
+    "message_id" BIGINT NOT NULL REFERENCES "messages" ("id") ON DELETE CASCADE,
+    "language" TEXT NOT NULL,
+    -- The SHA-256 hash of the content that was translated, to tell whether the translation is still up to date
+    "source_hash" BYTEA NOT NULL,
+    "content" TEXT NOT NULL,
+    "source_language" TEXT,
+    PRIMARY KEY ("message_id", "language")
+);
@@ -0,0 +1,16 @@
+-- Track the gateway sessions of users along with the client properties sent in IDENTIFY
+
+CREATE TABLE IF NOT EXISTS "gateway_sessions" (
+    "user_id" BIGINT NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    "shard_id" INTEGER NOT NULL,
+    "shard_count" INTEGER NOT NULL,
+    -- Identifies the session, as a new session replaces the previous one of the user on the same shard
+    "id" BIGINT NOT NULL,
+    -- The instance serving the session, sessions of instances that stopped are pruned
+    "instance_id" BIGINT NOT NULL,
+    "client_name" TEXT,
+    "client_version" TEXT,
+    "client_os" TEXT,
+    "client_device" SMALLINT NOT NULL DEFAULT 0,
+    PRIMARY KEY ("user_id", "shard_id")
+);
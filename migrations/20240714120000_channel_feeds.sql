@@ -0,0 +1,21 @@
+-- Add RSS/Atom feeds posting their entries to channels, and embeds on messages
+
+ALTER TABLE "messages" ADD COLUMN IF NOT EXISTS "embeds" TEXT;
+
+CREATE TABLE IF NOT EXISTS "channel_feeds"
+(
+    "id" BIGINT PRIMARY KEY,
+    "channel_id" BIGINT NOT NULL REFERENCES "channels" ("id") ON DELETE CASCADE,
+    "url" TEXT NOT NULL,
+    "title" TEXT,
+    "poll_interval" INTEGER NOT NULL,
+    "enabled" BOOLEAN NOT NULL DEFAULT TRUE,
+    "failures" INTEGER NOT NULL DEFAULT 0,
+    "last_error" TEXT,
+    "last_polled_at" BIGINT,
+    "next_poll_at" BIGINT NOT NULL,
+    "seen_entries" TEXT[] NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX IF NOT EXISTS "channel_feeds_channel_id_idx" ON "channel_feeds" ("channel_id");
+CREATE INDEX IF NOT EXISTS "channel_feeds_next_poll_at_idx" ON "channel_feeds" ("next_poll_at") WHERE "enabled";
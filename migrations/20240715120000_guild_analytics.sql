@@ -0,0 +1,14 @@
+-- Keep daily activity statistics per guild, so analytics do not have to scan the messages table
+
+CREATE TABLE IF NOT EXISTS "guild_daily_stats" (
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    -- UNIX timestamp of the start of the day, in UTC
+    "day" BIGINT NOT NULL,
+    -- Recomputed periodically from the messages sent on the day
+    "messages" BIGINT NOT NULL DEFAULT 0,
+    "active_members" BIGINT NOT NULL DEFAULT 0,
+    -- Incremented as members join and leave
+    "joins" BIGINT NOT NULL DEFAULT 0,
+    "leaves" BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY ("guild_id", "day")
+);
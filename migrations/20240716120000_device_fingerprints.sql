@@ -0,0 +1,23 @@
+-- Add salted hashes of the device identifiers and registration addresses of users,
+-- and flags of members who share them with a user banned from the guild
+
+CREATE TABLE IF NOT EXISTS "user_fingerprints"
+(
+    "user_id" BIGINT NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    "hash" TEXT NOT NULL,
+    PRIMARY KEY ("user_id", "hash")
+);
+
+CREATE INDEX IF NOT EXISTS "user_fingerprints_hash_idx" ON "user_fingerprints" ("hash");
+
+ALTER TABLE "strike_policies" ADD COLUMN "flag_evasion" BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS "evasion_flags"
+(
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "user_id" BIGINT NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    -- The banned user the flagged user shares a fingerprint with
+    "banned_user_id" BIGINT NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    "flagged_at" BIGINT NOT NULL,
+    PRIMARY KEY ("guild_id", "user_id")
+);
@@ -0,0 +1,26 @@
+-- Add the devices users receive push notifications on, and the queue of notifications to deliver to them
+
+CREATE TABLE IF NOT EXISTS "push_devices"
+(
+    "id" BIGINT PRIMARY KEY,
+    "user_id" BIGINT NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    "platform" SMALLINT NOT NULL,
+    -- The device token for APNs and FCM, or the serialized subscription for Web Push
+    "token" TEXT NOT NULL,
+    "created_at" BIGINT NOT NULL,
+    UNIQUE ("platform", "token")
+);
+
+CREATE INDEX IF NOT EXISTS "push_devices_user_id_idx" ON "push_devices" ("user_id");
+
+CREATE TABLE IF NOT EXISTS "push_deliveries"
+(
+    "id" BIGINT PRIMARY KEY,
+    "device_id" BIGINT NOT NULL REFERENCES "push_devices" ("id") ON DELETE CASCADE,
+    "payload" TEXT NOT NULL,
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "next_attempt_at" BIGINT NOT NULL,
+    "created_at" BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS "push_deliveries_next_attempt_at_idx" ON "push_deliveries" ("next_attempt_at");
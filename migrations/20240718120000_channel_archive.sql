@@ -0,0 +1,4 @@
+-- Add archived channels, which are read-only and hidden from the channel list of their guild
+
+ALTER TABLE "channels"
+ADD COLUMN "archived" BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,13 @@
+-- Add the order users arrange their guilds in, and the folders they group them into
+
+CREATE TABLE IF NOT EXISTS "guild_folders"
+(
+    "user_id" BIGINT NOT NULL REFERENCES "users" ("id") ON DELETE CASCADE,
+    "position" SMALLINT NOT NULL,
+    -- Chosen by the client, NULL for guilds that are not in a folder
+    "folder_id" BIGINT,
+    "name" TEXT,
+    "color" INTEGER,
+    "guild_ids" BIGINT[] NOT NULL,
+    PRIMARY KEY ("user_id", "position")
+);
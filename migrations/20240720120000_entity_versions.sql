@@ -0,0 +1,10 @@
+-- Add versions to guilds, channels and preferences, incremented on every settings update to detect concurrent updates
+
+ALTER TABLE "guilds"
+ADD COLUMN "version" INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE "channels"
+ADD COLUMN "version" INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE "prefs"
+ADD COLUMN "version" INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- Add sticky messages, which are kept visible at the top of a channel
+
+ALTER TABLE "channels"
+ADD COLUMN "sticky_message_id" BIGINT REFERENCES "messages"("id") ON DELETE SET NULL;
@@ -0,0 +1,4 @@
+-- Store the trace context of the request that caused an event, so its trace continues when the event is dispatched
+
+ALTER TABLE "outbox"
+ADD COLUMN "trace_context" TEXT;
@@ -0,0 +1,12 @@
+-- Remember the epoch snowflakes were generated with, as changing it would change the creation time of every ID
+
+CREATE TABLE IF NOT EXISTS "snowflake_epoch"
+(
+    "id" BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK ("id"),
+    "epoch" BIGINT NOT NULL
+);
+
+-- Existing IDs were generated with the epoch that was hard-coded until now
+INSERT INTO "snowflake_epoch" ("epoch")
+SELECT 1672531200000 WHERE EXISTS (SELECT 1 FROM "users")
+ON CONFLICT DO NOTHING;
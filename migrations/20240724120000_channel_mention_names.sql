@@ -0,0 +1,3 @@
+-- Store the names of mentioned channels, so links to channels of other guilds can be rendered
+
+ALTER TABLE "messages" ADD COLUMN IF NOT EXISTS "mention_channels" TEXT;
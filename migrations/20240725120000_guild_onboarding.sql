@@ -0,0 +1,22 @@
+-- Add the onboarding questions of guilds, and how members answered them
+
+CREATE TABLE IF NOT EXISTS "guild_onboarding"
+(
+    "guild_id" BIGINT PRIMARY KEY REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "enabled" BOOLEAN NOT NULL DEFAULT FALSE,
+    "default_channel_ids" BIGINT[] NOT NULL DEFAULT '{}',
+    -- JSON array of questions, validated by the server
+    "questions" TEXT NOT NULL DEFAULT '[]'
+);
+
+CREATE TABLE IF NOT EXISTS "member_onboarding"
+(
+    "guild_id" BIGINT NOT NULL,
+    "user_id" BIGINT NOT NULL,
+    -- JSON array of responses, validated by the server
+    "responses" TEXT NOT NULL DEFAULT '[]',
+    "channel_ids" BIGINT[] NOT NULL DEFAULT '{}',
+    "completed_at" BIGINT NOT NULL,
+    PRIMARY KEY ("guild_id", "user_id"),
+    FOREIGN KEY ("user_id", "guild_id") REFERENCES "members" ("user_id", "guild_id") ON DELETE CASCADE
+);
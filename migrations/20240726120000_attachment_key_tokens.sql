@@ -0,0 +1,7 @@
+-- Add a random component to the S3 keys of attachments, so they cannot be enumerated.
+-- Existing attachments have no token until they are moved to a new key in the background
+
+ALTER TABLE "attachments"
+ADD COLUMN "key_token" TEXT;
+
+CREATE INDEX IF NOT EXISTS "attachments_legacy_key_idx" ON "attachments" ("message_id") WHERE "key_token" IS NULL;
@@ -0,0 +1,11 @@
+-- Record every change to a message as it is dispatched, so clients can catch up on a channel in a single request
+
+CREATE TABLE IF NOT EXISTS "message_changes"
+(
+    "id" BIGINT PRIMARY KEY,
+    "channel_id" BIGINT NOT NULL,
+    "message_id" BIGINT NOT NULL,
+    "kind" SMALLINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS "message_changes_channel_idx" ON "message_changes" ("channel_id", "id");
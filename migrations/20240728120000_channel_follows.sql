@@ -0,0 +1,25 @@
+-- Let channels follow announcement channels, so messages sent in them are crossposted to the followers
+
+ALTER TABLE "messages" ADD COLUMN IF NOT EXISTS "crosspost" TEXT;
+
+CREATE TABLE IF NOT EXISTS "channel_follows"
+(
+    "id" BIGINT PRIMARY KEY,
+    "channel_id" BIGINT NOT NULL REFERENCES "channels" ("id") ON DELETE CASCADE,
+    "target_channel_id" BIGINT NOT NULL REFERENCES "channels" ("id") ON DELETE CASCADE,
+    "user_id" BIGINT REFERENCES "users" ("id") ON DELETE SET NULL,
+    UNIQUE ("channel_id", "target_channel_id")
+);
+
+CREATE INDEX IF NOT EXISTS "channel_follows_target_channel_id_idx" ON "channel_follows" ("target_channel_id");
+
+CREATE TABLE IF NOT EXISTS "crosspost_queue"
+(
+    "id" BIGSERIAL PRIMARY KEY,
+    "message_id" BIGINT NOT NULL REFERENCES "messages" ("id") ON DELETE CASCADE,
+    "follow_id" BIGINT NOT NULL REFERENCES "channel_follows" ("id") ON DELETE CASCADE,
+    "attempts" INTEGER NOT NULL DEFAULT 0,
+    "attempt_at" BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS "crosspost_queue_attempt_at_idx" ON "crosspost_queue" ("attempt_at");
@@ -0,0 +1,19 @@
+-- Let guilds hand out links that grant temporary guest membership, limited to a few channels
+
+CREATE TABLE IF NOT EXISTS "guest_links"
+(
+    "code" TEXT PRIMARY KEY,
+    "guild_id" BIGINT NOT NULL REFERENCES "guilds" ("id") ON DELETE CASCADE,
+    "creator_id" BIGINT REFERENCES "users" ("id") ON DELETE SET NULL,
+    "channel_ids" BIGINT[] NOT NULL,
+    "duration" INTEGER NOT NULL,
+    "created_at" BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS "guest_links_guild_id_idx" ON "guest_links" ("guild_id");
+
+ALTER TABLE "members"
+ADD COLUMN IF NOT EXISTS "guest_until" BIGINT,
+ADD COLUMN IF NOT EXISTS "guest_channel_ids" BIGINT[];
+
+CREATE INDEX IF NOT EXISTS "members_guest_until_idx" ON "members" ("guest_until") WHERE "guest_until" IS NOT NULL;
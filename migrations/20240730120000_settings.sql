@@ -0,0 +1,8 @@
+-- Let operators override some configuration values at runtime, without a redeploy
+
+CREATE TABLE IF NOT EXISTS "settings"
+(
+    "key" TEXT PRIMARY KEY,
+    "value" JSONB NOT NULL,
+    "updated_at" BIGINT NOT NULL
+);
@@ -0,0 +1,4 @@
+-- Let guilds choose the locale and timezone used for the messages the server writes on their behalf
+
+ALTER TABLE "guilds" ADD COLUMN IF NOT EXISTS "locale" TEXT NOT NULL DEFAULT 'en_US';
+ALTER TABLE "guilds" ADD COLUMN IF NOT EXISTS "timezone" TEXT NOT NULL DEFAULT 'UTC';
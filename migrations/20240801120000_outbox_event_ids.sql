@@ -0,0 +1,3 @@
+-- Keep the ID of events in the outbox, so an event dispatched twice carries the same ID both times
+
+ALTER TABLE "outbox" ADD COLUMN IF NOT EXISTS "event_id" BIGINT;
@@ -0,0 +1,279 @@
+//! A synthetic load generator for a running server.
+//!
+//! Registers a number of users, joins them all to one guild and connects each of them to the gateway,
+//! then sends messages to the guild at a fixed rate and measures how long it takes for every connected
+//! client to receive them.
+//!
+//! Rate limits should be disabled on the target server with `RATE_LIMITS_ENABLED=false`.
+//!
+//! Usage: `cargo run --release --features bench --bin loadgen -- [URL] [CLIENTS] [MESSAGES] [RATE]`
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures_util::{stream, SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+/// How many requests to have in flight at once while setting up users
+const SETUP_CONCURRENCY: usize = 32;
+/// How long to wait for deliveries after the last message was sent
+const DRAIN_PERIOD: Duration = Duration::from_secs(5);
+const BOUNDARY: &str = "chat-loadgen-boundary";
+const PASSWORD: &str = "correct-horse-battery-staple";
+
+struct Options {
+    /// The base URL of the server, for example `http://127.0.0.1:8080`
+    url: String,
+    /// The amount of gateway clients to connect
+    clients: usize,
+    /// The amount of messages to send
+    messages: usize,
+    /// The amount of messages to send per second
+    rate: f64,
+}
+
+impl Options {
+    fn from_args() -> Self {
+        let mut args = std::env::args().skip(1);
+        Self {
+            url: args.next().unwrap_or_else(|| "http://127.0.0.1:8080".into()),
+            clients: args
+                .next()
+                .map_or(100, |arg| arg.parse().expect("CLIENTS must be a number")),
+            messages: args
+                .next()
+                .map_or(100, |arg| arg.parse().expect("MESSAGES must be a number")),
+            rate: args
+                .next()
+                .map_or(10.0, |arg| arg.parse().expect("RATE must be a number")),
+        }
+    }
+}
+
+struct Api {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl Api {
+    /// Send a JSON request to the REST API and return the JSON response
+    async fn request(&self, method: reqwest::Method, path: &str, token: Option<&str>, body: Option<Value>) -> Value {
+        let mut request = self.http.request(method, format!("{}/api/v1{path}", self.url));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+
+        let response = request.send().await.expect("Failed to send request");
+        let status = response.status();
+        let text = response.text().await.expect("Failed to read response body");
+        assert!(status.is_success(), "Request to {path} failed with {status}: {text}");
+        serde_json::from_str(&text).unwrap_or(Value::Null)
+    }
+
+    /// Register a new user and return a token for them
+    async fn create_user(&self, username: &str) -> String {
+        let credentials = json!({ "username": username, "password": PASSWORD });
+        self.request(reqwest::Method::POST, "/users", None, Some(credentials.clone()))
+            .await;
+        let auth = self
+            .request(reqwest::Method::POST, "/users/auth", None, Some(credentials))
+            .await;
+        auth["token"].as_str().expect("Auth should return a token").to_string()
+    }
+
+    /// Send a message with the given nonce as multipart form data
+    async fn send_message(&self, token: &str, channel: &str, nonce: &str) {
+        let payload = json!({ "content": "Hello from the load generator", "nonce": nonce });
+        let body = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"json\"\r\nContent-Type: application/json\r\n\r\n\
+            {payload}\r\n--{BOUNDARY}--\r\n"
+        );
+
+        let response = self
+            .http
+            .post(format!("{}/api/v1/channels/{channel}/messages", self.url))
+            .bearer_auth(token)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to send message");
+        if !response.status().is_success() {
+            eprintln!("Sending message failed with {}", response.status());
+        }
+    }
+}
+
+/// Connect to the gateway, identify, and forward the delivery latency of every message sent by the generator
+async fn run_client(ws_url: String, token: String, start: Instant, latencies: mpsc::UnboundedSender<Duration>) {
+    let (socket, _) = tokio_tungstenite::connect_async(format!("{ws_url}/gateway/v1"))
+        .await
+        .expect("Failed to connect to the gateway");
+    let (mut sink, mut stream) = socket.split();
+
+    let Some(Ok(Message::Text(hello))) = stream.next().await else {
+        panic!("Expected HELLO");
+    };
+    let hello: Value = serde_json::from_str(&hello).expect("HELLO should be valid JSON");
+    let interval = Duration::from_millis(
+        hello["data"]["heartbeat_interval"]
+            .as_u64()
+            .expect("HELLO should contain the heartbeat interval"),
+    );
+
+    let identify = json!({ "event": "IDENTIFY", "data": { "token": token } });
+    sink.send(Message::Text(identify.to_string()))
+        .await
+        .expect("Failed to send IDENTIFY");
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval / 2);
+        loop {
+            ticker.tick().await;
+            let heartbeat = json!({ "event": "HEARTBEAT" });
+            if sink.send(Message::Text(heartbeat.to_string())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    while let Some(Ok(message)) = stream.next().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+        let Ok(event) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+        if event["event"] != "MESSAGE_CREATE" {
+            continue;
+        }
+        // The nonce carries the time the message was sent at, relative to the start of the run
+        if let Some(sent_at) = event["data"]["nonce"].as_str().and_then(|nonce| nonce.parse().ok()) {
+            let latency = start.elapsed().saturating_sub(Duration::from_micros(sent_at));
+            if latencies.send(latency).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// Get the given percentile of a sorted list of durations
+fn percentile(sorted: &[Duration], percentile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+    sorted[index]
+}
+
+#[tokio::main]
+async fn main() {
+    let options = Options::from_args();
+    let ws_url = options.url.replacen("http", "ws", 1);
+    let api = Arc::new(Api {
+        http: reqwest::Client::new(),
+        url: options.url.clone(),
+    });
+    let run_id = hex::encode(rand::random::<[u8; 4]>());
+
+    println!("Setting up a guild with {} members...", options.clients);
+    let owner = api.create_user(&format!("loadgen-{run_id}-owner")).await;
+    let guild = api
+        .request(
+            reqwest::Method::POST,
+            "/guilds",
+            Some(&owner),
+            Some(json!({ "name": format!("Load test {run_id}") })),
+        )
+        .await;
+    let guild_id = guild["id"].as_str().expect("Guild should have an ID").to_string();
+    let channel = api
+        .request(
+            reqwest::Method::POST,
+            &format!("/guilds/{guild_id}/channels"),
+            Some(&owner),
+            Some(json!({ "type": "GUILD_TEXT", "name": "load" })),
+        )
+        .await;
+    let channel_id = channel["id"].as_str().expect("Channel should have an ID").to_string();
+
+    let tokens: Vec<String> = stream::iter(0..options.clients)
+        .map(|i| {
+            let api = api.clone();
+            let (run_id, guild_id) = (run_id.clone(), guild_id.clone());
+            async move {
+                let token = api.create_user(&format!("loadgen-{run_id}-{i}")).await;
+                api.request(
+                    reqwest::Method::POST,
+                    &format!("/guilds/{guild_id}/members"),
+                    Some(&token),
+                    None,
+                )
+                .await;
+                token
+            }
+        })
+        .buffer_unordered(SETUP_CONCURRENCY)
+        .collect()
+        .await;
+
+    println!("Connecting {} gateway clients...", options.clients);
+    let start = Instant::now();
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    for token in tokens {
+        tokio::spawn(run_client(ws_url.clone(), token, start, sender.clone()));
+    }
+    drop(sender);
+    // Give the clients time to identify before sending anything
+    tokio::time::sleep(Duration::from_secs(2)).await;
+
+    println!(
+        "Sending {} messages at {} per second...",
+        options.messages, options.rate
+    );
+    let sending_since = Instant::now();
+    let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / options.rate));
+    for _ in 0..options.messages {
+        ticker.tick().await;
+        let nonce = start.elapsed().as_micros().to_string();
+        let (api, owner, channel_id) = (api.clone(), owner.clone(), channel_id.clone());
+        tokio::spawn(async move { api.send_message(&owner, &channel_id, &nonce).await });
+    }
+    let sent_in = sending_since.elapsed();
+
+    let mut latencies = Vec::with_capacity(options.clients * options.messages);
+    let deadline = tokio::time::Instant::now() + DRAIN_PERIOD;
+    while let Ok(Some(latency)) = tokio::time::timeout_at(deadline, receiver.recv()).await {
+        latencies.push(latency);
+    }
+    latencies.sort_unstable();
+
+    let expected = options.clients * options.messages;
+    println!();
+    println!("Deliveries: {}/{expected}", latencies.len());
+    println!(
+        "Send rate:  {:.1}/s",
+        options.messages as f64 / sent_in.as_secs_f64().max(f64::EPSILON)
+    );
+    println!("p50:        {:?}", percentile(&latencies, 0.5));
+    println!("p90:        {:?}", percentile(&latencies, 0.9));
+    println!("p99:        {:?}", percentile(&latencies, 0.99));
+    println!("max:        {:?}", latencies.last().copied().unwrap_or_default());
+}
@@ -0,0 +1,155 @@
+//! Command line interface of the server binary, including maintenance commands that run instead of the server.
+
+use std::io::BufRead;
+
+use chat_backend::{
+    models::{
+        auth::StoredCredentials,
+        requests::CreateUser,
+        snowflake::Snowflake,
+        state::{App, ApplicationState},
+        user::{User, UserFlags},
+    },
+    rest::auth::generate_hash,
+};
+use chrono::TimeDelta;
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{bail, eyre, Result};
+use secrecy::Secret;
+
+/// How old attachments must be before they are pruned, as their message may not be committed yet.
+const PRUNE_ATTACHMENTS_MIN_AGE: TimeDelta = TimeDelta::hours(1);
+
+/// A chat server. Serves the REST API and gateway unless a maintenance command is given.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Only run the startup checks and report the results, without applying migrations or starting the server.
+    #[arg(long)]
+    pub check: bool,
+
+    /// A maintenance command to run instead of the server.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Routine maintenance, using the same configuration as the server.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Apply all pending database migrations.
+    Migrate,
+    /// Create a user that is exempt from per-user limits. The password is read from standard input.
+    CreateAdmin {
+        /// The username of the new user.
+        username: String,
+    },
+    /// Delete attachments in S3 that no longer belong to a message.
+    PruneAttachments,
+    /// Index all messages in the search index again.
+    ReindexSearch,
+    /// Permanently delete a user, along with their saved messages and the guilds they own.
+    DeleteUser {
+        /// The ID of the user.
+        id: Snowflake<User>,
+    },
+}
+
+impl Command {
+    /// Run the command to completion.
+    ///
+    /// ## Errors
+    ///
+    /// If the application could not be initialized, or the command failed.
+    pub async fn run(self) -> Result<()> {
+        if matches!(self, Self::Migrate) {
+            ApplicationState::migrate().await?;
+            tracing::info!("Migrations applied");
+            return Ok(());
+        }
+
+        let app = ApplicationState::new_maintenance().await?;
+        let result = match self {
+            Self::Migrate => Ok(()),
+            Self::CreateAdmin { username } => create_admin(&app, username).await,
+            Self::PruneAttachments => prune_attachments(&app).await,
+            Self::ReindexSearch => reindex_search(&app).await,
+            Self::DeleteUser { id } => delete_user(&app, id).await,
+        };
+        app.close().await;
+        result
+    }
+}
+
+/// Create a user exempt from per-user limits, with the password read from the first line of standard input.
+async fn create_admin(app: &App, username: String) -> Result<()> {
+    eprint!("Password: ");
+    let mut password = String::new();
+    std::io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']).to_string();
+    if password.is_empty() {
+        bail!("Password must not be empty");
+    }
+
+    let payload = CreateUser {
+        username,
+        password: Secret::new(password),
+        device_id: None,
+    };
+    let user = User::from_payload(&app.config, &payload)?;
+    if app.ops().fetch_user_by_username(user.username()).await.is_some() {
+        bail!("User with username {} already exists", user.username());
+    }
+    app.ops().ensure_username_not_reserved(user.username()).await?;
+
+    let hash = generate_hash(app.config.argon2_params(), &payload.password)?;
+    let user = app.ops().create_user(payload).await?;
+    StoredCredentials::new(user.id(), hash, app.clock.now())
+        .commit(app.clone())
+        .await?;
+    app.ops()
+        .update_user_flags(user.id(), UserFlags::EXEMPT_FROM_LIMITS)
+        .await?;
+
+    tracing::info!(id = %user.id(), username = user.username(), "Created admin user");
+    Ok(())
+}
+
+/// Delete orphaned attachments from S3.
+async fn prune_attachments(app: &App) -> Result<()> {
+    let deleted = app
+        .s3
+        .prune_attachments(app.clock.now() - PRUNE_ATTACHMENTS_MIN_AGE)
+        .await?;
+
+    tracing::info!(deleted, "Pruned orphaned attachments");
+    Ok(())
+}
+
+/// Index all messages again, one batch at a time.
+async fn reindex_search(app: &App) -> Result<()> {
+    app.search.restart_backfill().await?;
+
+    while let Some(progress) = app.search.backfill().await? {
+        tracing::info!(indexed = progress.indexed, "Reindexing messages");
+        if progress.done {
+            break;
+        }
+    }
+
+    tracing::info!("Search index rebuilt");
+    Ok(())
+}
+
+/// Permanently delete a user.
+async fn delete_user(app: &App, id: Snowflake<User>) -> Result<()> {
+    let user = app
+        .ops()
+        .fetch_user(id)
+        .await
+        .ok_or_else(|| eyre!("User {id} does not exist"))?;
+
+    app.ops().delete_user(&user).await?;
+
+    tracing::info!(%id, username = user.username(), "Deleted user");
+    Ok(())
+}
@@ -0,0 +1,1231 @@
+//! Protocol conformance tests for the gateway, run against a real server with [`testkit`](super::testkit).
+//!
+//! These tests need a Postgres server and are ignored by default, run them with
+//! `DATABASE_URL=... cargo test -- --ignored`.
+
+use std::{sync::Arc, time::Duration};
+
+use chat_client::{async_trait, Context, Event, EventHandler, FetchMessages, Gateway, RestClient};
+use reqwest::Method;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+use super::testkit::{TestServer, EVENT_TIMEOUT, PASSWORD, QUIET_PERIOD};
+use crate::{
+    models::{
+        clock::{Clock, ManualClock},
+        close_code::GatewayCloseCode,
+        feed::ParsedFeed,
+        gateway_event::InvalidSessionPayload,
+        metrics,
+        snowflake::Snowflake,
+    },
+    tests::feeds::create_feed,
+};
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_handshake() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+
+    let mut client = server.connect().await;
+    let hello = client.expect_event("HELLO").await;
+    assert_eq!(hello["data"]["heartbeat_interval"], 45000);
+
+    client.identify(&alice.token).await;
+    let ready = client.expect_event("READY").await;
+    assert_eq!(ready["data"]["user"]["id"], alice.id);
+    assert_eq!(ready["data"]["guilds"][0]["id"], guild);
+
+    let guild_create = client.expect_event("GUILD_CREATE").await;
+    assert_eq!(guild_create["data"]["guild"]["id"], guild);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_handshake_invalid_token() {
+    let server = TestServer::start().await;
+
+    let mut client = server.connect().await;
+    client.expect_event("HELLO").await;
+    client.identify("not-a-token").await;
+
+    // The close code is repeated in an event, telling the client not to reconnect with the same token
+    let invalid_session = client.expect_event("INVALID_SESSION").await;
+    assert_eq!(
+        invalid_session["data"],
+        json!({ "code": 4001, "reason": "Invalid token", "reconnect_after_ms": null })
+    );
+    assert_eq!(client.expect_close().await, (4001, "Invalid token".into()));
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_handshake_requires_identify_first() {
+    let server = TestServer::start().await;
+
+    let mut client = server.connect().await;
+    client.expect_event("HELLO").await;
+    client.heartbeat().await;
+
+    assert_eq!(client.expect_close().await, (4002, "Invalid IDENTIFY payload".into()));
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_handshake_timeout() {
+    let server = TestServer::start().await;
+
+    let mut client = server.connect().await;
+    client.expect_event("HELLO").await;
+
+    assert_eq!(client.expect_close().await, (4004, "IDENTIFY expected".into()));
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_handshake_invalid_shard() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+
+    let mut client = server.connect().await;
+    client.expect_event("HELLO").await;
+    client
+        .send(&json!({ "event": "IDENTIFY", "data": { "token": alice.token, "shard": [1, 2] } }))
+        .await;
+
+    assert_eq!(client.expect_close().await, (4007, "Invalid shard".into()));
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_heartbeat_ack() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+
+    let (mut client, _) = server.identify(&alice).await;
+    client.heartbeat().await;
+    client.expect_event("HEARTBEAT_ACK").await;
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_heartbeat_timeout() {
+    let server = TestServer::start_with(|config| {
+        config.gateway_heartbeat_interval(Duration::from_secs(1));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+
+    let mut client = server.connect().await;
+    let hello = client.expect_event("HELLO").await;
+    assert_eq!(hello["data"]["heartbeat_interval"], 1000);
+    client.identify(&alice.token).await;
+    client.expect_event("READY").await;
+
+    // The heartbeat timeout is longer than `expect_event` waits for
+    let invalid_session = client
+        .next_event(Duration::from_secs(10))
+        .await
+        .expect("INVALID_SESSION should be sent before closing");
+    assert_eq!(invalid_session["event"], "INVALID_SESSION");
+    assert_eq!(invalid_session["data"]["code"], 4004);
+    assert_eq!(invalid_session["data"]["reconnect_after_ms"], 0);
+    assert_eq!(
+        client.expect_close().await,
+        (4004, "No HEARTBEAT received within timeframe".into())
+    );
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_invalid_payload() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+
+    // Invalid payloads are ignored until there are too many of them
+    let (mut client, _) = server.identify(&alice).await;
+    client.send(&json!({ "event": "NOT_AN_EVENT" })).await;
+    client.send_raw(Message::Text("{".into())).await;
+    client.heartbeat().await;
+    client.expect_event("HEARTBEAT_ACK").await;
+    client.send(&json!({ "event": "NOT_AN_EVENT" })).await;
+    let (code, _) = client.expect_close().await;
+    assert_eq!(code, 4002);
+
+    // Payloads larger than the limit close the connection right away, even before IDENTIFY
+    let too_large = Message::Text(" ".repeat(16 * 1024 + 1));
+    let expected_close = (1009, "Payload exceeds the maximum size of 16384 bytes".to_string());
+    let (mut client, _) = server.identify(&alice).await;
+    client.send_raw(too_large.clone()).await;
+    assert_eq!(client.expect_close().await, expected_close);
+
+    let mut client = server.connect().await;
+    client.expect_event("HELLO").await;
+    client.send_raw(too_large).await;
+    assert_eq!(client.expect_close().await, expected_close);
+
+    let (mut client, _) = server.identify(&alice).await;
+    client.send_raw(Message::Binary(vec![1, 2, 3])).await;
+    assert_eq!(
+        client.expect_close().await,
+        (1003, "Unsupported message encoding".into())
+    );
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_server_restart() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+
+    let (mut client, _) = server.identify(&alice).await;
+    server.app().gateway.close();
+
+    // Clients are told to reconnect after a random delay, so they do not all reconnect at once
+    let invalid_session = client.expect_event("INVALID_SESSION").await;
+    assert_eq!(invalid_session["data"]["code"], 4005);
+    let reconnect_after = invalid_session["data"]["reconnect_after_ms"]
+        .as_u64()
+        .expect("A reconnect hint should be sent");
+    assert!((1000..=10000).contains(&reconnect_after));
+    assert_eq!(client.expect_close().await, (4005, "Server shutting down".into()));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_guild_events_only_reach_members() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+
+    let (mut alice_client, _) = server.identify(&alice).await;
+    let (mut bob_client, _) = server.identify(&bob).await;
+    alice_client.expect_event("GUILD_CREATE").await;
+
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "announcements" })),
+        )
+        .await;
+
+    let channel_create = alice_client.expect_event("CHANNEL_CREATE").await;
+    assert_eq!(channel_create["data"]["name"], "announcements");
+    assert!(bob_client
+        .collect_events(QUIET_PERIOD)
+        .await
+        .iter()
+        .all(|event| event["event"] != "CHANNEL_CREATE"));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_guild_events_after_join() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+
+    let (mut alice_client, _) = server.identify(&alice).await;
+    let (mut bob_client, _) = server.identify(&bob).await;
+
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+
+    let guild_create = bob_client.expect_event("GUILD_CREATE").await;
+    assert_eq!(guild_create["data"]["guild"]["id"], guild);
+    let member_create = alice_client.expect_event("MEMBER_CREATE").await;
+    assert_eq!(member_create["data"]["user"]["id"], bob.id);
+
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "announcements" })),
+        )
+        .await;
+    bob_client.expect_event("CHANNEL_CREATE").await;
+
+    server
+        .request(
+            Method::DELETE,
+            &format!("/guilds/{guild}/members/@me"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    bob_client.expect_event("GUILD_REMOVE").await;
+
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "after-leave" })),
+        )
+        .await;
+    alice_client.expect_event("CHANNEL_CREATE").await;
+    assert!(bob_client
+        .collect_events(QUIET_PERIOD)
+        .await
+        .iter()
+        .all(|event| event["event"] != "CHANNEL_CREATE"));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_new_session_replaces_previous() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+
+    let (mut bob_client, _) = server.identify(&bob).await;
+    let (mut first, _) = server.identify(&alice).await;
+    let (mut second, _) = server.identify(&alice).await;
+    second.expect_event("GUILD_CREATE").await;
+
+    // Only one session is kept per user, the previous one is closed
+    assert_eq!(first.expect_close().await, (4006, "Session replaced".into()));
+
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "announcements" })),
+        )
+        .await;
+    second.expect_event("CHANNEL_CREATE").await;
+
+    second.heartbeat().await;
+    second.expect_event("HEARTBEAT_ACK").await;
+
+    // Closing the previous session must not make the user appear offline
+    assert!(bob_client
+        .collect_events(QUIET_PERIOD)
+        .await
+        .iter()
+        .all(|event| event["event"] != "PRESENCE_UPDATE" || event["data"]["presence"] != "OFFLINE"));
+
+    second.close().await;
+    let presence = bob_client.expect_event("PRESENCE_UPDATE").await;
+    assert_eq!(presence["data"]["user_id"], alice.id);
+    assert_eq!(presence["data"]["presence"], "OFFLINE");
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_presence_privacy() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+
+    let privacy = server
+        .request(
+            Method::PUT,
+            "/users/@me/presence/privacy",
+            Some(&alice.token),
+            Some(json!({ "visibility": "EVERYONE", "hidden_guilds": [guild] })),
+        )
+        .await;
+    assert_eq!(privacy["hidden_guilds"][0], guild);
+
+    let (_alice_client, ready) = server.identify(&alice).await;
+    assert_eq!(ready["data"]["presence_privacy"]["hidden_guilds"][0], guild);
+
+    // Alice hides her presence from the only guild she shares with Bob, so she appears offline to him
+    let (mut bob_client, _) = server.identify(&bob).await;
+    let guild_create = bob_client.expect_event("GUILD_CREATE").await;
+    let alice_member = guild_create["data"]["members"]
+        .as_array()
+        .expect("GUILD_CREATE should contain members")
+        .iter()
+        .find(|member| member["user"]["id"] == alice.id)
+        .expect("Alice should be a member");
+    assert_eq!(alice_member["user"]["presence"], "OFFLINE");
+
+    server
+        .request(
+            Method::PATCH,
+            "/users/@me/presence",
+            Some(&alice.token),
+            Some(json!("AWAY")),
+        )
+        .await;
+    assert!(bob_client
+        .collect_events(QUIET_PERIOD)
+        .await
+        .iter()
+        .all(|event| event["event"] != "PRESENCE_UPDATE" || event["data"]["user_id"] != alice.id));
+
+    // Revealing the presence again announces it to Bob
+    server
+        .request(
+            Method::PUT,
+            "/users/@me/presence/privacy",
+            Some(&alice.token),
+            Some(json!({ "visibility": "EVERYONE" })),
+        )
+        .await;
+    let presence = bob_client.expect_event("PRESENCE_UPDATE").await;
+    assert_eq!(presence["data"]["user_id"], alice.id);
+    assert_eq!(presence["data"]["presence"], "AWAY");
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_request_guild_members() {
+    let server = TestServer::start_with(|config| {
+        config.large_guild_threshold(2_usize);
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let bobby = server.create_user("bobby").await;
+    let carol = server.create_user("carol").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    for user in [&bob, &bobby] {
+        server
+            .request(
+                Method::POST,
+                &format!("/guilds/{guild}/members"),
+                Some(&user.token),
+                None,
+            )
+            .await;
+    }
+
+    // Members of large guilds are not sent on connect
+    let (mut alice_client, _) = server.identify(&alice).await;
+    let guild_create = alice_client.expect_event("GUILD_CREATE").await;
+    assert_eq!(guild_create["data"]["large"], true);
+    assert_eq!(guild_create["data"]["member_count"], 3);
+    assert_eq!(guild_create["data"]["members"], json!([]));
+
+    alice_client
+        .send(&json!({
+            "event": "REQUEST_GUILD_MEMBERS",
+            "data": { "guild_id": guild, "query": "BOB", "nonce": "search" }
+        }))
+        .await;
+    let chunk = alice_client.expect_event("GUILD_MEMBERS_CHUNK").await;
+    assert_eq!(chunk["data"]["guild_id"], guild);
+    assert_eq!(chunk["data"]["nonce"], "search");
+    assert_eq!(chunk["data"]["chunk_index"], 0);
+    assert_eq!(chunk["data"]["chunk_count"], 1);
+    let usernames: Vec<&str> = chunk["data"]["members"]
+        .as_array()
+        .expect("Chunk should contain members")
+        .iter()
+        .map(|m| m["user"]["username"].as_str().expect("Member should have a username"))
+        .collect();
+    assert_eq!(usernames, ["bob", "bobby"]);
+
+    alice_client
+        .send(&json!({ "event": "REQUEST_GUILD_MEMBERS", "data": { "guild_id": guild, "limit": 1 } }))
+        .await;
+    let chunk = alice_client.expect_event("GUILD_MEMBERS_CHUNK").await;
+    assert_eq!(chunk["data"]["members"][0]["user"]["id"], alice.id);
+    assert_eq!(chunk["data"]["members"].as_array().map(Vec::len), Some(1));
+
+    // Users who are not members receive an empty chunk
+    let (mut carol_client, _) = server.identify(&carol).await;
+    carol_client
+        .send(&json!({ "event": "REQUEST_GUILD_MEMBERS", "data": { "guild_id": guild } }))
+        .await;
+    let chunk = carol_client.expect_event("GUILD_MEMBERS_CHUNK").await;
+    assert_eq!(chunk["data"]["members"], json!([]));
+    assert_eq!(chunk["data"]["chunk_count"], 1);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_client_properties() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+
+    let mut client = server.connect().await;
+    client.expect_event("HELLO").await;
+    client
+        .send(&json!({
+            "event": "IDENTIFY",
+            "data": {
+                "token": alice.token,
+                "properties": { "name": "chat-mobile", "version": "1.4.2", "os": "Android", "device": "MOBILE" }
+            }
+        }))
+        .await;
+    client.expect_event("READY").await;
+
+    let sessions = server
+        .request(Method::GET, "/users/@me/sessions", Some(&alice.token), None)
+        .await;
+    assert_eq!(sessions.as_array().map(Vec::len), Some(1));
+    assert_eq!(sessions[0]["shard"], json!([0, 1]));
+    assert_eq!(
+        sessions[0]["properties"],
+        json!({ "name": "chat-mobile", "version": "1.4.2", "os": "Android", "device": "MOBILE" })
+    );
+    assert!(metrics::render().contains("chat_gateway_sessions_total{device=\"mobile\"}"));
+
+    // The session is removed once the client disconnects
+    client.close().await;
+    let mut sessions = Value::Null;
+    for _ in 0..50 {
+        sessions = server
+            .request(Method::GET, "/users/@me/sessions", Some(&alice.token), None)
+            .await;
+        if sessions.as_array().is_some_and(Vec::is_empty) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(sessions, json!([]));
+
+    let mut client = server.connect().await;
+    client.expect_event("HELLO").await;
+    client
+        .send(&json!({
+            "event": "IDENTIFY",
+            "data": { "token": alice.token, "properties": { "name": "x".repeat(65) } }
+        }))
+        .await;
+    assert_eq!(client.expect_close().await, (4002, "Invalid client properties".into()));
+
+    server.close().await;
+}
+
+/// A bot built with the `chat-client` crate, answering `!ping` with `pong`
+struct PingBot {
+    /// Notified whenever the bot received its guilds after (re)connecting
+    ready: mpsc::UnboundedSender<()>,
+}
+
+#[async_trait]
+impl EventHandler for PingBot {
+    async fn on_event(&self, ctx: Context, event: Event) {
+        match event {
+            Event::GuildCreate(_) => {
+                self.ready.send(()).ok();
+            }
+            Event::MessageCreate(message) if message.content.as_deref() == Some("!ping") => {
+                ctx.rest()
+                    .send_message(message.channel_id, "pong")
+                    .await
+                    .expect("Bot should be able to reply");
+            }
+            _ => {}
+        }
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_client_bot() {
+    // A short heartbeat interval makes sure the client keeps its connection alive on its own
+    let server = TestServer::start_with(|config| {
+        config.gateway_heartbeat_interval(Duration::from_secs(1));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+
+    let mut rest = RestClient::new(server.base_url());
+    let bot = rest.register("ping_bot", PASSWORD).await.expect("Bot should register");
+    let session = rest.login("ping_bot", PASSWORD).await.expect("Bot should log in");
+    assert_eq!(session.user_id, bot.id);
+    let guild_id: Snowflake<_> = guild.parse().expect("Guild ID should be a snowflake");
+    let member = rest.join_guild(guild_id).await.expect("Bot should join the guild");
+    assert_eq!(member.user.id, bot.id);
+
+    let (ready, mut ready_rx) = mpsc::unbounded_channel();
+    let gateway = Gateway::new(rest.clone()).expect("Client should be logged in");
+    let shutdown = gateway.shutdown_handle();
+    let bot_task = tokio::spawn(gateway.run(PingBot { ready }));
+    tokio::time::timeout(EVENT_TIMEOUT, ready_rx.recv())
+        .await
+        .expect("Bot should receive its guild");
+
+    let (mut client, _) = server.identify(&alice).await;
+    // Outlive a few heartbeat intervals
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    server.send_message(&alice, &guild, "!ping").await;
+    client.expect_event("MESSAGE_CREATE").await;
+    let pong = client.expect_event("MESSAGE_CREATE").await;
+    assert_eq!(pong["data"]["content"], "pong");
+    assert_eq!(pong["data"]["author"]["user"]["id"], bot.id.to_string());
+
+    // The client reconnects when the server asks it to
+    server.app().gateway.drop_session(
+        bot.id.cast(),
+        InvalidSessionPayload::new(GatewayCloseCode::SessionTimeout, "Test")
+            .with_reconnect_after(GatewayCloseCode::SessionTimeout.reconnect_after()),
+    );
+    tokio::time::timeout(EVENT_TIMEOUT, ready_rx.recv())
+        .await
+        .expect("Bot should reconnect");
+    server.send_message(&alice, &guild, "!ping").await;
+    client.expect_event("MESSAGE_CREATE").await;
+    let pong = client.expect_event("MESSAGE_CREATE").await;
+    assert_eq!(pong["data"]["content"], "pong");
+
+    // The first channel of a guild shares its ID
+    let history = rest
+        .fetch_messages(guild_id.cast(), FetchMessages::default().limit(10))
+        .await
+        .expect("Bot should fetch the history");
+    let replies = history
+        .iter()
+        .filter(|message| message.content.as_deref() == Some("pong"))
+        .count();
+    assert_eq!(replies, 2);
+
+    shutdown.shutdown();
+    bot_task
+        .await
+        .expect("Bot task should not panic")
+        .expect("Bot should stop cleanly");
+
+    server.close().await;
+}
+
+/// Poll the events of a long-polling gateway session, returning the status and the events
+async fn poll_events(server: &TestServer, session: &str) -> (reqwest::StatusCode, Value) {
+    let response = reqwest::Client::new()
+        .get(format!("{}/gateway/v1/poll", server.base_url()))
+        .query(&[("session", session)])
+        .send()
+        .await
+        .expect("Poll should be sent");
+    let status = response.status();
+    (status, response.json().await.unwrap_or(Value::Null))
+}
+
+/// Start a long-polling gateway session, returning the status and the response
+async fn identify_polling(server: &TestServer, token: &str) -> (reqwest::StatusCode, Value) {
+    let response = reqwest::Client::new()
+        .post(format!("{}/gateway/v1/poll", server.base_url()))
+        .json(&json!({ "event": "IDENTIFY", "data": { "token": token } }))
+        .send()
+        .await
+        .expect("IDENTIFY should be sent");
+    let status = response.status();
+    (status, response.json().await.expect("Response should be JSON"))
+}
+
+/// Send a message through a long-polling gateway session, returning the status
+async fn send_polling(server: &TestServer, session: &str, message: &Value) -> reqwest::StatusCode {
+    reqwest::Client::new()
+        .post(format!("{}/gateway/v1/poll/messages", server.base_url()))
+        .query(&[("session", session)])
+        .json(message)
+        .send()
+        .await
+        .expect("Message should be sent")
+        .status()
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_long_polling() {
+    let server = TestServer::start_with(|config| {
+        config.gateway_poll_timeout(Duration::from_secs(1));
+        config.gateway_idle_timeout(Duration::from_secs(3));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+
+    // Rejected sessions carry the same INVALID_SESSION a websocket client would receive
+    let (status, rejected) = identify_polling(&server, "invalid").await;
+    assert_eq!(status, reqwest::StatusCode::UNAUTHORIZED);
+    assert_eq!(rejected["event"], "INVALID_SESSION");
+    assert_eq!(rejected["data"]["code"], 4001);
+
+    let (status, started) = identify_polling(&server, &alice.token).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(started["poll_timeout"], 1000);
+    let session = started["session"].as_str().expect("Session key should be a string");
+
+    let mut names = Vec::new();
+    while !names.contains(&"GUILD_CREATE".to_string()) {
+        let (status, events) = poll_events(&server, session).await;
+        assert_eq!(status, reqwest::StatusCode::OK);
+        let events = events.as_array().expect("Events should be an array");
+        assert!(!events.is_empty(), "READY and GUILD_CREATE should be delivered");
+        names.extend(
+            events
+                .iter()
+                .map(|event| event["event"].as_str().unwrap_or_default().to_string()),
+        );
+    }
+    assert_eq!(names[0], "READY");
+
+    server.send_message(&alice, &guild, "Hello").await;
+    let (_, events) = poll_events(&server, session).await;
+    assert_eq!(events[0]["event"], "MESSAGE_CREATE");
+    assert_eq!(events[0]["data"]["content"], "Hello");
+
+    // Polls without events return once the poll timeout passes
+    let (status, events) = poll_events(&server, session).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(events, json!([]));
+
+    // Messages sent through the session are handled like on a websocket
+    let subscribe = json!({ "event": "SUBSCRIBE", "data": { "channel_ids": [] } });
+    assert_eq!(
+        send_polling(&server, session, &subscribe).await,
+        reqwest::StatusCode::NO_CONTENT
+    );
+    let identify = json!({ "event": "IDENTIFY", "data": { "token": alice.token } });
+    assert_eq!(
+        send_polling(&server, session, &identify).await,
+        reqwest::StatusCode::BAD_REQUEST
+    );
+    server.send_message(&alice, &guild, "Unsubscribed").await;
+    let (_, events) = poll_events(&server, session).await;
+    assert_eq!(events, json!([]));
+
+    // A websocket session replaces the polling session, which is told so on its next poll
+    let (_client, _) = server.identify(&alice).await;
+    let (_, events) = poll_events(&server, session).await;
+    let last = events
+        .as_array()
+        .and_then(|events| events.last())
+        .expect("INVALID_SESSION should be delivered");
+    assert_eq!(last["event"], "INVALID_SESSION");
+    assert_eq!(last["data"]["code"], 4006);
+    let (status, _) = poll_events(&server, session).await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    // Clients can end their session
+    let bob = server.create_user("bob").await;
+    let (_, started) = identify_polling(&server, &bob.token).await;
+    let session = started["session"].as_str().expect("Session key should be a string");
+    let status = reqwest::Client::new()
+        .delete(format!("{}/gateway/v1/poll", server.base_url()))
+        .query(&[("session", session)])
+        .send()
+        .await
+        .expect("Request should be sent")
+        .status();
+    assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+    let (status, _) = poll_events(&server, session).await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    // Sessions expire if the client stops polling
+    let (_, started) = identify_polling(&server, &bob.token).await;
+    let session = started["session"].as_str().expect("Session key should be a string");
+    poll_events(&server, session).await;
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    let (status, _) = poll_events(&server, session).await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    assert!(!server
+        .app()
+        .gateway
+        .is_connected(bob.id.parse::<Snowflake<_>>().expect("ID should be valid")));
+
+    server.close().await;
+}
+
+/// A Server-Sent Events stream opened for a test
+struct EventStream {
+    response: reqwest::Response,
+    buffer: String,
+}
+
+impl EventStream {
+    /// Open a stream with the given query, returning the status and the stream if it was opened
+    async fn open(server: &TestServer, token: Option<&str>, query: &str) -> (reqwest::StatusCode, Option<Self>) {
+        let mut request = reqwest::Client::new().get(format!("{}/gateway/v1/events{query}", server.base_url()));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.expect("Stream request should be sent");
+        let status = response.status();
+        let stream = status.is_success().then(|| Self {
+            response,
+            buffer: String::new(),
+        });
+        (status, stream)
+    }
+
+    /// Wait for the next event within the given time, returning its name and data
+    async fn next_event(&mut self, within: Duration) -> Option<(String, Value)> {
+        tokio::time::timeout(within, async {
+            loop {
+                if let Some(end) = self.buffer.find("\n\n") {
+                    let frame: String = self.buffer.drain(..end + 2).collect();
+                    let mut name = None;
+                    let mut data = None;
+                    for line in frame.lines() {
+                        if let Some(value) = line.strip_prefix("event: ") {
+                            name = Some(value.to_string());
+                        } else if let Some(value) = line.strip_prefix("data: ") {
+                            data = Some(serde_json::from_str(value).expect("Event data should be JSON"));
+                        }
+                    }
+                    // Keep-alive comments carry neither
+                    if let (Some(name), Some(data)) = (name, data) {
+                        return (name, data);
+                    }
+                    continue;
+                }
+                let chunk = self
+                    .response
+                    .chunk()
+                    .await
+                    .expect("Stream should be readable")
+                    .expect("Stream should not end");
+                self.buffer
+                    .push_str(std::str::from_utf8(&chunk).expect("Stream should be UTF-8"));
+            }
+        })
+        .await
+        .ok()
+    }
+
+    /// Wait for the next event, failing the test if it does not arrive in time
+    async fn expect_event(&mut self) -> (String, Value) {
+        self.next_event(EVENT_TIMEOUT)
+            .await
+            .expect("Expected an event on the stream")
+    }
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_event_stream() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let shared = server.create_guild(&alice, "Shared guild").await;
+    let private = server.create_guild(&alice, "Private guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{shared}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+
+    let (status, _) = EventStream::open(&server, None, "").await;
+    assert_eq!(status, reqwest::StatusCode::UNAUTHORIZED);
+    let (status, _) = EventStream::open(&server, Some(&bob.token), "?guild_ids=sus").await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    let (status, _) = EventStream::open(&server, Some(&bob.token), &format!("?guild_ids={private}")).await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    let (status, stream) = EventStream::open(&server, Some(&alice.token), &format!("?guild_ids={shared}")).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let mut stream = stream.expect("Stream should be opened");
+
+    // The stream is not a session, so it neither appears online nor gets replaced by one
+    let (mut alice_client, _) = server.identify(&alice).await;
+    alice_client.expect_event("GUILD_CREATE").await;
+    let (name, presence) = stream.expect_event().await;
+    assert_eq!(name, "PRESENCE_UPDATE");
+    assert_eq!(presence["data"]["user_id"], alice.id);
+
+    let (mut bob_client, _) = server.identify(&bob).await;
+    let (name, presence) = stream.expect_event().await;
+    assert_eq!(name, "PRESENCE_UPDATE");
+    assert_eq!(presence["data"]["user_id"], bob.id);
+    assert_eq!(presence["data"]["presence"], "ONLINE");
+
+    // Messages of guilds that were not selected are not streamed
+    server.send_message(&alice, &private, "secret").await;
+    server.send_message(&bob, &shared, "hello").await;
+    let (name, message) = stream.expect_event().await;
+    assert_eq!(name, "MESSAGE_CREATE");
+    assert_eq!(message["data"]["content"], "hello");
+
+    // Other events are not streamed either
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{shared}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "announcements" })),
+        )
+        .await;
+    bob_client.expect_event("CHANNEL_CREATE").await;
+    assert!(stream.next_event(QUIET_PERIOD).await.is_none());
+
+    // The websocket session of the user is still alive
+    alice_client.heartbeat().await;
+    alice_client.expect_event("HEARTBEAT_ACK").await;
+
+    // Leaving a guild stops its events from being streamed
+    let (status, stream) = EventStream::open(&server, Some(&bob.token), "").await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    let mut bob_stream = stream.expect("Stream should be opened");
+    server
+        .request(
+            Method::DELETE,
+            &format!("/guilds/{shared}/members/@me"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    server.send_message(&alice, &shared, "bye").await;
+    assert!(bob_stream.next_event(QUIET_PERIOD).await.is_none());
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_invisible_activity() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let (mut bob_client, _) = server.identify(&bob).await;
+    let (mut alice_client, _) = server.identify(&alice).await;
+    alice_client.expect_event("GUILD_CREATE").await;
+
+    server
+        .request(
+            Method::PATCH,
+            "/users/@me/presence",
+            Some(&bob.token),
+            Some(json!("OFFLINE")),
+        )
+        .await;
+    let events = alice_client.collect_events(QUIET_PERIOD).await;
+    let presence = events
+        .iter()
+        .find(|event| event["event"] == "PRESENCE_UPDATE" && event["data"]["user_id"] == bob.id)
+        .expect("Alice should see Bob going offline");
+    assert_eq!(presence["data"]["presence"], "OFFLINE");
+
+    // Invisible users do not reveal what they are doing
+    bob_client
+        .send(&json!({
+            "event": "UPDATE_ACTIVITY",
+            "data": { "name": "Among Us", "type": "PLAYING", "started_at": null },
+        }))
+        .await;
+    assert!(alice_client
+        .collect_events(QUIET_PERIOD)
+        .await
+        .iter()
+        .all(|event| event["event"] != "PRESENCE_UPDATE" || event["data"]["user_id"] != bob.id));
+    let (mut alice_client, _) = server.identify(&alice).await;
+    let guild_create = alice_client.expect_event("GUILD_CREATE").await;
+    let bob_member = guild_create["data"]["members"]
+        .as_array()
+        .expect("GUILD_CREATE should contain members")
+        .iter()
+        .find(|member| member["user"]["id"] == bob.id)
+        .expect("Bob should be a member");
+    assert_eq!(bob_member["user"]["presence"], "OFFLINE");
+    assert!(bob_member["user"]["activity"].is_null());
+
+    // Becoming visible again reveals the current activity
+    server
+        .request(
+            Method::PATCH,
+            "/users/@me/presence",
+            Some(&bob.token),
+            Some(json!("ONLINE")),
+        )
+        .await;
+    let events = alice_client.collect_events(QUIET_PERIOD).await;
+    let presence = events
+        .iter()
+        .find(|event| event["event"] == "PRESENCE_UPDATE" && event["data"]["user_id"] == bob.id)
+        .expect("Alice should see Bob coming online");
+    assert_eq!(presence["data"]["presence"], "ONLINE");
+    assert_eq!(presence["data"]["activity"]["name"], "Among Us");
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_data_saver() {
+    let clock = Arc::new(ManualClock::default());
+    let server = TestServer::start_with_clock(clock.clone(), |_| {}).await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let (channel, feed) = create_feed(&server, &alice, &guild, clock.timestamp()).await;
+
+    let document = |ids: &[u32]| {
+        let items = ids
+            .iter()
+            .map(|id| format!("<item><guid>{id}</guid><title>Post {id}</title></item>"))
+            .collect::<Vec<_>>()
+            .concat();
+        ParsedFeed::parse(&format!("<rss><channel><title>Example</title>{items}</channel></rss>"))
+            .expect("Feed should parse")
+    };
+    let feeds = &server.app().feeds;
+    feeds
+        .ingest(&feed, &document(&[1]))
+        .await
+        .expect("Feed should be ingested");
+    let feed = server
+        .app()
+        .ops()
+        .fetch_feed(feed.id())
+        .await
+        .expect("Feed should be fetched")
+        .expect("Feed should exist");
+
+    // Entries are compared to the seen entries of `feed`, which is not fetched again
+    // Sessions that are already connected reduce events once the flag is set
+    let (mut client, _) = server.identify(&alice).await;
+    server
+        .request(
+            Method::PATCH,
+            "/prefs",
+            Some(&alice.token),
+            Some(json!({ "flags": 19 })),
+        )
+        .await;
+    feeds
+        .ingest(&feed, &document(&[2, 1]))
+        .await
+        .expect("Feed should be ingested");
+    let message = client.expect_event("MESSAGE_CREATE").await;
+    assert_eq!(message["data"]["embeds"], json!([]));
+
+    // Only gateway events are reduced
+    let messages = server
+        .request(
+            Method::GET,
+            &format!("/channels/{channel}/messages"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(messages[0]["embeds"][0]["title"], "Post 2");
+
+    // New sessions read the flag when identifying
+    let (mut client, _) = server.identify(&alice).await;
+    feeds
+        .ingest(&feed, &document(&[3, 1]))
+        .await
+        .expect("Feed should be ingested");
+    let message = client.expect_event("MESSAGE_CREATE").await;
+    assert_eq!(message["data"]["embeds"], json!([]));
+
+    server
+        .request(Method::PATCH, "/prefs", Some(&alice.token), Some(json!({ "flags": 3 })))
+        .await;
+    feeds
+        .ingest(&feed, &document(&[4, 1]))
+        .await
+        .expect("Feed should be ingested");
+    let message = client.expect_event("MESSAGE_CREATE").await;
+    assert_eq!(message["data"]["embeds"][0]["title"], "Post 4");
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_resume_session() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let saved = server
+        .request(Method::GET, "/users/@me/saved-messages", Some(&alice.token), None)
+        .await;
+    let channel = saved["id"].as_str().expect("Channel should have an ID");
+
+    let (mut client, ready) = server.identify(&alice).await;
+    let session_id = ready["data"]["session_id"]
+        .as_str()
+        .expect("READY should include the session ID")
+        .to_string();
+    let resume_token = ready["data"]["resume_token"]
+        .as_str()
+        .expect("READY should include a resume token")
+        .to_string();
+    server.send_message(&alice, channel, "Buy milk").await;
+    let first = client.expect_event("MESSAGE_CREATE").await;
+    let seq = first["seq"].as_u64().expect("Events should be numbered");
+    assert!(first["id"].is_string());
+
+    // Events dispatched while the connection is lost are sent again once the session is resumed
+    drop(client);
+    server.send_message(&alice, channel, "Buy eggs").await;
+    server.send_message(&alice, channel, "Buy flour").await;
+    // Dispatch the events now, so they are sent again before `RESUMED` rather than after it
+    server.app().outbox.flush().await.expect("Outbox should flush");
+
+    let resume = |resume_token: String, seq| {
+        let server = &server;
+        let token = &alice.token;
+        let session_id = &session_id;
+        async move {
+            let mut client = server.connect().await;
+            client.expect_event("HELLO").await;
+            client.resume(token, session_id, &resume_token, seq).await;
+            let mut events = Vec::new();
+            let resumed = loop {
+                let event = client
+                    .next_event(EVENT_TIMEOUT)
+                    .await
+                    .expect("RESUMED should be sent after the missed events");
+                if event["event"] == "RESUMED" {
+                    break event;
+                }
+                events.push(event);
+            };
+            let messages: Vec<Value> = events
+                .into_iter()
+                .filter(|event| event["event"] == "MESSAGE_CREATE")
+                .collect();
+            let resume_token = resumed["data"]["resume_token"]
+                .as_str()
+                .expect("RESUMED should include a new resume token")
+                .to_string();
+            (client, messages, resume_token)
+        }
+    };
+
+    let (client, replayed, next_token) = resume(resume_token.clone(), seq).await;
+    assert_eq!(
+        replayed
+            .iter()
+            .map(|event| event["data"]["content"].clone())
+            .collect::<Vec<Value>>(),
+        [json!("Buy eggs"), json!("Buy flour")]
+    );
+    assert!(replayed[0]["seq"].as_u64() > Some(seq));
+    assert!(replayed[1]["seq"].as_u64() > replayed[0]["seq"].as_u64());
+    assert_ne!(next_token, resume_token);
+
+    // Resume tokens are no longer valid once the session was resumed with them
+    let mut stale = server.connect().await;
+    stale.expect_event("HELLO").await;
+    stale.resume(&alice.token, &session_id, &resume_token, seq).await;
+    assert_eq!(stale.expect_close().await, (4008, "Session cannot be resumed".into()));
+
+    // Resuming with a valid token closes the connection that served the session until then,
+    // and events are sent with the same ID and sequence number every time, so clients can skip duplicates
+    let mut previous = client;
+    let (mut client, again, resume_token) = resume(next_token, seq).await;
+    assert_eq!(again, replayed);
+    assert_eq!(
+        previous.expect_close().await,
+        (4006, "Session resumed on another connection".into())
+    );
+
+    // Acknowledged events are no longer kept
+    let last_seq = replayed[1]["seq"].as_u64().expect("Events should be numbered");
+    client
+        .send(&json!({ "event": "HEARTBEAT", "data": { "seq": last_seq } }))
+        .await;
+    client.expect_event("HEARTBEAT_ACK").await;
+    drop(client);
+    let mut client = server.connect().await;
+    client.expect_event("HELLO").await;
+    client.resume(&alice.token, &session_id, &resume_token, seq).await;
+    assert_eq!(
+        client.expect_close().await,
+        (4008, "Too many events were missed".into())
+    );
+
+    // Sessions that ended cannot be resumed
+    let mut client = server.connect().await;
+    client.expect_event("HELLO").await;
+    client.resume(&alice.token, &session_id, &resume_token, last_seq).await;
+    let invalid_session = client.expect_event("INVALID_SESSION").await;
+    assert_eq!(invalid_session["data"]["code"], 4008);
+    assert_eq!(client.expect_close().await, (4008, "Session cannot be resumed".into()));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_resume_closed_session() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+
+    // Closing the connection normally ends the session right away
+    let (client, ready) = server.identify(&alice).await;
+    let session_id = ready["data"]["session_id"]
+        .as_str()
+        .expect("READY should include the session ID")
+        .to_string();
+    let resume_token = ready["data"]["resume_token"]
+        .as_str()
+        .expect("READY should include a resume token")
+        .to_string();
+    client.close().await;
+    let mut client = server.connect().await;
+    client.expect_event("HELLO").await;
+    client.resume(&alice.token, &session_id, &resume_token, 0).await;
+    assert_eq!(client.expect_close().await, (4008, "Session cannot be resumed".into()));
+
+    server.close().await;
+}
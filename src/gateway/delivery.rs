@@ -0,0 +1,155 @@
+//! Delivery guarantees of gateway sessions.
+//!
+//! Every dispatched event carries an `id`, which is the same for every session receiving it and every time it is sent,
+//! so clients can recognize events they already applied. Events sent to a session are also numbered with a `seq`,
+//! counting up from 1. Clients acknowledge the last `seq` they received, and the server keeps the events after it
+//! in a [`ReplayBuffer`], so they can be sent again once the client resumes the session on a new connection.
+
+use std::{collections::VecDeque, sync::Arc};
+
+use crate::models::{gateway_event::GatewayEvent, snowflake::Snowflake};
+
+/// Add the ID and, if given, the sequence number of an event to its serialized payload
+///
+/// ## Arguments
+///
+/// * `id` - The ID of the event
+/// * `seq` - The sequence number of the event in the session it is sent to, if the transport numbers events
+/// * `payload` - The serialized event, a JSON object
+pub(super) fn envelope(id: Snowflake<GatewayEvent>, seq: Option<u64>, payload: &str) -> String {
+    let Some(fields) = payload.strip_prefix('{') else {
+        return payload.to_string();
+    };
+    seq.map_or_else(
+        || format!(r#"{{"id":"{id}",{fields}"#),
+        |seq| format!(r#"{{"id":"{id}","seq":{seq},{fields}"#),
+    )
+}
+
+/// The events sent to a session that the client did not acknowledge yet, in the order they were sent
+#[derive(Debug)]
+pub(super) struct ReplayBuffer {
+    /// The sequence numbers and serialized payloads of the events
+    events: VecDeque<(u64, Arc<str>)>,
+    /// The sequence number of the last event sent
+    last_seq: u64,
+    /// The maximum amount of events kept, older events are dropped
+    capacity: usize,
+}
+
+impl ReplayBuffer {
+    /// Create an empty buffer
+    ///
+    /// ## Arguments
+    ///
+    /// * `capacity` - The maximum amount of unacknowledged events kept
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::new(),
+            last_seq: 0,
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Number the next event sent and keep it until it is acknowledged.
+    /// If the buffer is full, the oldest event is dropped.
+    ///
+    /// ## Arguments
+    ///
+    /// * `encode` - Serializes the event with the given sequence number
+    ///
+    /// ## Returns
+    ///
+    /// The serialized event
+    pub(super) fn push(&mut self, encode: impl FnOnce(u64) -> String) -> Arc<str> {
+        self.last_seq += 1;
+        let payload: Arc<str> = encode(self.last_seq).into();
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back((self.last_seq, payload.clone()));
+        payload
+    }
+
+    /// Forget all events up to and including the given sequence number, as the client received them
+    pub(super) fn ack(&mut self, seq: u64) {
+        while self.events.front().is_some_and(|(event_seq, _)| *event_seq <= seq) {
+            self.events.pop_front();
+        }
+    }
+
+    /// The events sent after the given sequence number, to send them again
+    ///
+    /// ## Returns
+    ///
+    /// The events in the order they were sent, or `None` if some of them were already dropped,
+    /// or the sequence number is ahead of the last event sent
+    pub(super) fn since(&self, seq: u64) -> Option<Vec<Arc<str>>> {
+        if seq > self.last_seq {
+            return None;
+        }
+        let first_kept = self
+            .events
+            .front()
+            .map_or(self.last_seq + 1, |(event_seq, _)| *event_seq);
+        if seq + 1 < first_kept {
+            return None;
+        }
+        Some(
+            self.events
+                .iter()
+                .filter(|(event_seq, _)| *event_seq > seq)
+                .map(|(_, payload)| payload.clone())
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{envelope, ReplayBuffer};
+    use crate::models::snowflake::Snowflake;
+
+    #[test]
+    fn test_envelope() {
+        let payload = r#"{"event":"HEARTBEAT_ACK"}"#;
+        assert_eq!(
+            envelope(Snowflake::new(42), Some(7), payload),
+            r#"{"id":"42","seq":7,"event":"HEARTBEAT_ACK"}"#
+        );
+        assert_eq!(
+            envelope(Snowflake::new(42), None, payload),
+            r#"{"id":"42","event":"HEARTBEAT_ACK"}"#
+        );
+        let value: serde_json::Value = serde_json::from_str(&envelope(Snowflake::new(42), Some(7), payload))
+            .expect("Envelope should be valid JSON");
+        assert_eq!(value["seq"], 7);
+    }
+
+    #[test]
+    fn test_replay_buffer() {
+        let mut buffer = ReplayBuffer::new(3);
+        assert_eq!(buffer.since(0), Some(Vec::new()));
+        for _ in 0..3 {
+            buffer.push(|seq| seq.to_string());
+        }
+        assert_eq!(buffer.since(1).map(|events| events.len()), Some(2));
+        assert_eq!(buffer.since(4), None);
+
+        // Acknowledged events are not sent again
+        buffer.ack(2);
+        assert_eq!(buffer.since(2).map(|events| events.len()), Some(1));
+        assert_eq!(buffer.since(1), None);
+
+        // Once full, the oldest unacknowledged events are dropped
+        for _ in 0..3 {
+            buffer.push(|seq| seq.to_string());
+        }
+        assert_eq!(buffer.since(2), None);
+        let replayed = buffer.since(3).expect("The last three events should be kept");
+        assert_eq!(
+            replayed.iter().map(AsRef::as_ref).collect::<Vec<&str>>(),
+            ["4", "5", "6"]
+        );
+    }
+}
@@ -1,7 +1,11 @@
 use std::{
     borrow::Cow,
-    collections::HashSet,
-    sync::{Arc, Weak},
+    collections::{HashMap, HashSet},
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex, Weak,
+    },
     time::Duration,
 };
 
@@ -10,54 +14,173 @@ use axum::{
         ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
         State,
     },
-    response::IntoResponse,
-    routing::get,
-    Router,
+    http::{header, HeaderMap},
+    response::Response,
+    routing::{get, post},
+    Json, Router,
 };
 use dashmap::DashMap;
 use futures_util::{
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
-use secrecy::ExposeSecret;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
 use tokio::{
     sync::{
         broadcast,
         mpsc::{self, error::SendError},
         Mutex,
     },
-    time::timeout,
+    time::{sleep, timeout, Instant, MissedTickBehavior},
 };
-use tokio_stream::wrappers::UnboundedReceiverStream;
 
 use crate::{
     models::{
-        auth::Token,
-        errors::GatewayError,
+        auth::{self, Token, TokenSource},
+        channel::Channel,
+        close_code::{GatewayCloseCode, RESTART_RECONNECT_DELAY},
+        errors::{GatewayError, RESTError},
+        event_bus::BusMessage,
+        firehose::FirehosePayload,
         gateway_event::{
-            EventLike, GatewayEvent, GatewayMessage, GuildCreatePayload, HelloPayload, PresenceUpdatePayload,
-            ReadyPayload,
+            EventRouting, GatewayEvent, GatewayMessage, GuildCreatePayload, HelloPayload, IdentifyPayload,
+            InvalidSessionPayload, ReadyPayload, ResumePayload, ResumedPayload,
         },
+        guest_link::GuestAccess,
         guild::Guild,
-        snowflake::Snowflake,
-        state::{App, ApplicationState},
-        user::{Presence, User},
+        guild_folder::GuildSettings,
+        metrics,
+        prefs::PrefFlags,
+        presence_privacy::PresencePrivacy,
+        rate_limit::{RateLimitBucket, RateLimitKey},
+        session::{ClientProperties, DeviceType, GatewaySession},
+        shard::ShardInfo,
+        snowflake::{Snowflake, SnowflakeSource},
+        state::{App, ApplicationState, Config},
+        user::{Activity, Presence, User},
     },
-    utils::join_handle::JoinHandleExt,
+    utils::join_handle::{AbortingJoinHandle, JoinHandleExt},
+};
+
+use super::{
+    delivery::{self, ReplayBuffer},
+    polling::{self, PollSession},
+    shaping,
+    sse::{self, StreamHandle, STREAM_EVENTS},
 };
 
-/// Default heartbeat interval in milliseconds
-const HEARTBEAT_INTERVAL: u64 = 45000;
+type HmacSha256 = Hmac<Sha256>;
+
+/// The websocket subprotocol spoken by this version of the gateway
+pub const GATEWAY_SUBPROTOCOL: &str = "chat.v1.json";
+/// The maximum amount of channels a connection may subscribe to
+const MAX_SUBSCRIBED_CHANNELS: usize = 100;
+/// The maximum length of the query of a `REQUEST_GUILD_MEMBERS` message, in characters
+const MAX_MEMBER_QUERY_LENGTH: usize = 100;
+/// The maximum random delay added to the reconnect hint sent to clients when the server shuts down
+const RESTART_RECONNECT_JITTER: Duration = Duration::from_secs(9);
+/// How long a connection is kept open after closing it because of a payload that was too large
+const TOO_LARGE_CLOSE_LINGER: Duration = Duration::from_secs(1);
+/// How long to wait for the sink of a connection to close it, after its session was resumed on another connection
+const RESUMED_ELSEWHERE_CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Possible responses issued by the server to a client
-#[derive(Debug, Clone, Serialize)]
-#[serde(untagged)]
-enum GatewayResponse {
+#[derive(Debug, Clone)]
+pub(super) enum GatewayResponse {
     // If sent through a connection handle, the payload should be sent to the client
-    Event(Arc<GatewayEvent>),
-    // If sent through a connection handle, the connection should be closed
-    Close(GatewayCloseCode, String),
+    Event {
+        /// The ID of the event, the same for every recipient
+        id: Snowflake<GatewayEvent>,
+        event: Arc<GatewayEvent>,
+        /// When the event was queued, used to measure dispatch lag
+        queued_at: Instant,
+    },
+    // If sent through a connection handle, the already serialized payload should be sent to the client
+    Serialized {
+        /// The ID of the event, the same for every recipient
+        id: Snowflake<GatewayEvent>,
+        /// The name of the event, used to label dispatch lag
+        event: Arc<str>,
+        payload: Arc<str>,
+        /// When the event was queued, used to measure dispatch lag
+        queued_at: Instant,
+    },
+    // If sent through a connection handle, the connection should be closed after sending `INVALID_SESSION`
+    Close(InvalidSessionPayload),
+}
+
+impl GatewayResponse {
+    /// The name of the event sent to the client, or `None` if the connection should be closed
+    fn event_name(&self) -> Option<&str> {
+        match self {
+            Self::Event { event, .. } => Some(event.name()),
+            Self::Serialized { event, .. } => Some(event),
+            Self::Close(_) => None,
+        }
+    }
+
+    /// Serialize the response as sent to the client, along with the ID of its event
+    /// and the sequence number of the event in the session, if the transport numbers events.
+    /// `INVALID_SESSION` is neither identified nor numbered.
+    ///
+    /// ## Arguments
+    ///
+    /// * `seq` - The sequence number of the event in the session it is sent to
+    pub(super) fn encode(&self, seq: Option<u64>) -> String {
+        match self {
+            Self::Event { id, event, .. } => delivery::envelope(
+                *id,
+                seq,
+                &serde_json::to_string(event.as_ref()).expect("Failed to serialize gateway event"),
+            ),
+            Self::Serialized { id, payload, .. } => delivery::envelope(*id, seq, payload),
+            Self::Close(payload) => serde_json::to_string(&GatewayEvent::InvalidSession(payload.clone()))
+                .expect("Failed to serialize INVALID_SESSION payload"),
+        }
+    }
+
+    /// Record the time it took for the event to be sent after it was queued
+    pub(super) fn observe_lag(&self) {
+        match self {
+            Self::Event { event, queued_at, .. } => observe_lag(event.name(), *queued_at),
+            Self::Serialized { event, queued_at, .. } => observe_lag(event, *queued_at),
+            Self::Close(_) => {}
+        }
+    }
+
+    /// Reduce the payload of the response if the session it is sent to is in data saver mode
+    ///
+    /// ## Arguments
+    ///
+    /// * `data_saver` - Whether the session is in data saver mode
+    pub(super) fn shape(self, data_saver: bool) -> Self {
+        if !data_saver {
+            return self;
+        }
+        let shaped = match &self {
+            Self::Event { id, event, queued_at } => {
+                shaping::shape_event(event).map(|payload| (*id, Arc::from(event.name()), payload, *queued_at))
+            }
+            Self::Serialized {
+                id,
+                event,
+                payload,
+                queued_at,
+            } => shaping::shape_payload(event, payload).map(|payload| (*id, event.clone(), payload, *queued_at)),
+            Self::Close(_) => None,
+        };
+        shaped.map_or(self, |(id, event, payload, queued_at)| Self::Serialized {
+            id,
+            event,
+            payload: payload.into(),
+            queued_at,
+        })
+    }
 }
 
 /// Possible requests issued by the client to the server
@@ -68,78 +191,6 @@ enum GatewayRequest {
     Message(GatewayMessage),
 }
 
-#[derive(Debug, Clone, Copy)]
-#[repr(u16)]
-pub enum GatewayCloseCode {
-    /// Successful operation / regular socket shutdown
-    Normal = 1000,
-    /// Client/Server is leaving (browser tab closing, server shutting down, etc.)
-    GoingAway = 1001,
-    /// Endpoint received a malformed frame
-    ProtocolError = 1002,
-    /// Endpoint received an unsupported frame (e.g. binary-only endpoint received text frame)
-    Unsupported = 1003,
-    /// Expected close status, received none
-    NoStatus = 1005,
-    /// No close code frame has been receieved
-    Abnormal = 1006,
-    /// Endpoint received inconsistent message (e.g. malformed UTF-8)
-    InvalidPayload = 1007,
-    ///Generic code used for situations other than 1003 and 1009
-    PolicyViolation = 1008,
-    /// Endpoint won't process large frame
-    TooLarge = 1009,
-    /// Client wanted an extension which server did not negotiate
-    ExtensionRequired = 1010,
-    /// Internal server error while operating
-    ServerError = 1011,
-    /// Server/service is restarting
-    ServiceRestart = 1012,
-    /// Temporary server condition forced blocking client's request
-    TryAgainLater = 1013,
-    /// Server acting as gateway received an invalid response
-    BadGateway = 1014,
-    /// Transport Layer Security handshake failure
-    TLSHandshakeFail = 1015,
-}
-
-impl From<GatewayCloseCode> for u16 {
-    fn from(value: GatewayCloseCode) -> Self {
-        value as Self
-    }
-}
-
-impl From<u16> for GatewayCloseCode {
-    fn from(value: u16) -> Self {
-        match value {
-            1000 => Self::Normal,
-            1001 => Self::GoingAway,
-            1002 => Self::ProtocolError,
-            1003 => Self::Unsupported,
-            1005 => Self::NoStatus,
-            1006 => Self::Abnormal,
-            1007 => Self::InvalidPayload,
-            1008 => Self::PolicyViolation,
-            1009 => Self::TooLarge,
-            1010 => Self::ExtensionRequired,
-            1012 => Self::ServiceRestart,
-            1013 => Self::TryAgainLater,
-            1014 => Self::BadGateway,
-            1015 => Self::TLSHandshakeFail,
-            _ => Self::ServerError,
-        }
-    }
-}
-
-impl Serialize for GatewayCloseCode {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        (*self as u16).serialize(serializer)
-    }
-}
-
 /// A struct containing connection details for a user
 ///
 /// ## Fields
@@ -147,11 +198,23 @@ impl Serialize for GatewayCloseCode {
 /// * `sender` - The sender for sending messages to the client
 /// * `receiver` - The receiver for receiving messages from the client
 /// * `guild_ids` - The guilds the user is a member of, this is used to filter events
+/// * `channel_ids` - The channels the user is subscribed to, or `None` if subscribed to all channels
+/// * `activity` - The user's current activity, if any
+/// * `presence` - The presence the user set, `Offline` if they appear offline
+/// * `presence_privacy` - Who can see the user's presence
+/// * `data_saver` - Whether events sent to the user are reduced, shared with the task sending them
 #[derive(Debug, Clone)]
 struct ConnectionHandle {
     sender: mpsc::UnboundedSender<GatewayResponse>,
     broadcaster: Arc<broadcast::Sender<GatewayMessage>>,
     guild_ids: HashSet<Snowflake<Guild>>,
+    guests: HashMap<Snowflake<Guild>, GuestAccess>,
+    channel_ids: Option<HashSet<Snowflake<Channel>>>,
+    activity: Option<Activity>,
+    presence: Presence,
+    presence_privacy: PresencePrivacy,
+    data_saver: Arc<AtomicBool>,
+    device: DeviceType,
 }
 
 impl ConnectionHandle {
@@ -161,43 +224,60 @@ impl ConnectionHandle {
     ///
     /// * `sender` - The sender for sending messages to the client
     /// * `guilds` - The guilds the user is a member of
+    /// * `presence` - The presence the user set
+    /// * `presence_privacy` - Who can see the user's presence
+    /// * `data_saver` - Whether events sent to the user are reduced
+    /// * `device` - The device type reported by the client, used to label metrics
     pub fn new(
         sender: mpsc::UnboundedSender<GatewayResponse>,
         receiver: Arc<broadcast::Sender<GatewayMessage>>,
         guilds: HashSet<Snowflake<Guild>>,
+        presence: Presence,
+        presence_privacy: PresencePrivacy,
+        data_saver: Arc<AtomicBool>,
+        device: DeviceType,
     ) -> Self {
         Self {
             sender,
             broadcaster: receiver,
             guild_ids: guilds,
+            guests: HashMap::new(),
+            channel_ids: None,
+            activity: None,
+            presence,
+            presence_privacy,
+            data_saver,
+            device,
         }
     }
 
-    /// Send a message to the client
+    /// Set the access of the user in the guilds they joined as a guest
+    #[must_use]
+    pub fn with_guests(mut self, guests: HashMap<Snowflake<Guild>, GuestAccess>) -> Self {
+        self.guests = guests;
+        self
+    }
+
+    /// Send a response to the client
     ///
     /// ## Arguments
     ///
-    /// * `message` - The message to send
-    pub fn send(&self, message: Arc<GatewayEvent>) -> Result<(), SendError<GatewayResponse>> {
-        let resp = GatewayResponse::Event(message);
-        self.sender.send(resp)
+    /// * `response` - The response to send
+    fn respond(&self, response: GatewayResponse) -> Result<(), SendError<GatewayResponse>> {
+        self.sender.send(response)
     }
 
-    /// Close the connection with the given code and reason
+    /// Close the connection, sending `INVALID_SESSION` with the given payload first
     /// This will also remove the handle from the gateway state
     ///
     /// ## Arguments
     ///
-    /// * `code` - The close code to send
-    /// * `reason` - The reason for closing the connection
-    pub fn close(&self, code: GatewayCloseCode, reason: String) -> Result<(), SendError<GatewayResponse>> {
-        let resp = GatewayResponse::Close(code, reason);
-        self.sender.send(resp)
-    }
-
-    /// Get a receiver for incoming gateway messages from the user
-    pub fn get_receiver(&self) -> broadcast::Receiver<GatewayMessage> {
-        self.broadcaster.subscribe()
+    /// * `payload` - The close code and reason to send, along with a reconnect hint
+    pub fn close(&self, payload: InvalidSessionPayload) -> Result<(), SendError<GatewayResponse>> {
+        metrics::GATEWAY_SESSION_CLOSES
+            .with_label_values(&[self.device.name(), &u16::from(payload.code()).to_string()])
+            .inc();
+        self.sender.send(GatewayResponse::Close(payload))
     }
 
     /// Get the guilds the user is a member of
@@ -206,23 +286,100 @@ impl ConnectionHandle {
     }
 
     /// Get a mutable handle to the guilds the user is a member of
-    pub fn guild_ids_mut(&mut self) -> &mut HashSet<Snowflake<Guild>> {
+    pub const fn guild_ids_mut(&mut self) -> &mut HashSet<Snowflake<Guild>> {
         &mut self.guild_ids
     }
+
+    /// Get the channels the user is subscribed to, or `None` if subscribed to all channels
+    pub const fn channel_ids(&self) -> Option<&HashSet<Snowflake<Channel>>> {
+        self.channel_ids.as_ref()
+    }
+
+    /// Check if the user should receive events specific to the given channel
+    pub fn is_subscribed_to(&self, channel: Snowflake<Channel>) -> bool {
+        self.channel_ids().is_none_or(|ids| ids.contains(&channel))
+    }
+
+    /// Check if the user can see the given channel of a guild, guests only see the channels of their guest link
+    pub fn can_access(&self, guild: Snowflake<Guild>, channel: Snowflake<Channel>) -> bool {
+        self.guests.get(&guild).is_none_or(|guest| guest.can_access(channel))
+    }
+}
+
+/// Lets a new websocket connection take over a session, after the connection serving it was lost
+#[derive(Debug, Clone)]
+struct ResumeHandle {
+    /// The user the session belongs to
+    user_id: Snowflake<User>,
+    /// A MAC of the token the client has to send to resume the session,
+    /// replaced once a resumed connection received the new token
+    token: Vec<u8>,
+    /// Hands new connections to the task serving the session, along with the last sequence number the client received
+    sender: mpsc::UnboundedSender<(Box<WebSocket>, u64)>,
+}
+
+/// Generates the IDs of events dispatched by a gateway that is not bound to an application, such as in benchmarks
+struct UnboundSource;
+
+impl SnowflakeSource for UnboundSource {
+    fn machine_id(&self) -> i32 {
+        0
+    }
+
+    fn process_id(&self) -> i32 {
+        0
+    }
+}
+
+/// A peer registered without a websocket connection, which buffers the events dispatched to it in memory
+#[cfg(feature = "bench")]
+pub struct SimulatedPeer {
+    receiver: mpsc::UnboundedReceiver<GatewayResponse>,
+}
+
+#[cfg(feature = "bench")]
+impl SimulatedPeer {
+    /// Discard all buffered events
+    ///
+    /// ## Returns
+    ///
+    /// The amount of events that were buffered
+    pub fn drain(&mut self) -> usize {
+        let mut count = 0;
+        while self.receiver.try_recv().is_ok() {
+            count += 1;
+        }
+        count
+    }
 }
 
 /// A singleton representing the gateway state
+///
+/// If the gateway is sharded, changes made through the public methods are forwarded to
+/// all gateway processes through the event bus, and each process applies them to its own connections.
 #[derive(Debug, Clone)]
 pub struct Gateway {
     /// A map of currently connected users and their connection handles
     peers: DashMap<Snowflake<User>, ConnectionHandle>,
+    /// Sessions served through long-polling, keyed by their session key
+    poll_sessions: DashMap<String, Arc<PollSession>>,
+    /// Read-only event streams, which are not gateway sessions
+    streams: DashMap<Snowflake<StreamHandle>, StreamHandle>,
+    /// Websocket sessions that may be resumed on a new connection, keyed by their session ID
+    resumable: DashMap<Snowflake<GatewaySession>, ResumeHandle>,
+    /// The shard served by this process
+    shard: ShardInfo,
     app: Weak<ApplicationState>,
 }
 
 impl Gateway {
-    pub fn new() -> Self {
+    pub fn new(shard: ShardInfo) -> Self {
         Self {
             peers: DashMap::new(),
+            poll_sessions: DashMap::new(),
+            streams: DashMap::new(),
+            resumable: DashMap::new(),
+            shard,
             app: Weak::new(),
         }
     }
@@ -231,6 +388,126 @@ impl Gateway {
         self.app = app;
     }
 
+    /// The shard served by this process
+    pub const fn shard(&self) -> ShardInfo {
+        self.shard
+    }
+
+    /// Generate the ID of a new event, unique across all gateway processes
+    fn next_event_id(&self) -> Snowflake<GatewayEvent> {
+        self.app.upgrade().map_or_else(
+            || Snowflake::gen_new(&UnboundSource),
+            |app| Snowflake::gen_new(&app.config),
+        )
+    }
+
+    /// Apply a change to the connections of this process, or forward it to all gateway processes if the gateway is sharded
+    fn route(&self, message: BusMessage) {
+        if !self.shard.is_sharded() {
+            self.apply(message);
+        } else if let Some(app) = self.app.upgrade() {
+            app.bus.publish(message);
+        }
+    }
+
+    /// Apply a change received from the event bus to the connections of this process
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The change to apply
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    pub fn apply(&self, message: BusMessage) {
+        match message {
+            BusMessage::Dispatch {
+                routing,
+                id,
+                event,
+                payload,
+            } => {
+                tracing::debug!(?routing, event, "Dispatching serialized event");
+                self.fan_out(
+                    routing,
+                    &GatewayResponse::Serialized {
+                        id: id.unwrap_or_else(|| self.next_event_id()),
+                        event: event.into(),
+                        payload: payload.into(),
+                        queued_at: Instant::now(),
+                    },
+                );
+            }
+            BusMessage::SendTo {
+                user_id,
+                guild_id,
+                id,
+                event,
+                payload,
+            } => {
+                // Events belong to the shard of their guild, events without a guild to the first shard
+                if guild_id.map_or_else(|| self.shard.is_primary(), |guild| self.shard.owns(guild)) {
+                    self.respond_to(
+                        user_id,
+                        GatewayResponse::Serialized {
+                            id: id.unwrap_or_else(|| self.next_event_id()),
+                            event: event.into(),
+                            payload: payload.into(),
+                            queued_at: Instant::now(),
+                        },
+                    );
+                }
+            }
+            BusMessage::AddMember {
+                user_id,
+                guild_id,
+                guest,
+            } => {
+                if !self.shard.owns(guild_id) {
+                    return;
+                }
+                if let Some(mut handle) = self.peers.get_mut(&user_id) {
+                    handle.guild_ids_mut().insert(guild_id);
+                    match guest {
+                        Some(guest) => handle.guests.insert(guild_id, guest),
+                        None => handle.guests.remove(&guild_id),
+                    };
+                }
+            }
+            BusMessage::RemoveMember { user_id, guild_id } => {
+                if let Some(mut handle) = self.peers.get_mut(&user_id) {
+                    handle.guild_ids_mut().remove(&guild_id);
+                    handle.guests.remove(&guild_id);
+                }
+                for mut stream in self.streams.iter_mut() {
+                    if stream.user_id() == user_id {
+                        stream.guild_ids_mut().remove(&guild_id);
+                    }
+                }
+            }
+            BusMessage::SetActivity { user_id, activity } => {
+                if let Some(mut handle) = self.peers.get_mut(&user_id) {
+                    handle.activity = activity;
+                }
+            }
+            BusMessage::SetPresence { user_id, presence } => {
+                if let Some(mut handle) = self.peers.get_mut(&user_id) {
+                    handle.presence = presence;
+                }
+            }
+            BusMessage::SetPresencePrivacy { user_id, privacy } => {
+                if let Some(mut handle) = self.peers.get_mut(&user_id) {
+                    handle.presence_privacy = privacy;
+                }
+            }
+            BusMessage::SetDataSaver { user_id, enabled } => {
+                if let Some(handle) = self.peers.get(&user_id) {
+                    handle.data_saver.store(enabled, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
     /// Add a new connection handle to the gateway state
     ///
     /// ## Arguments
@@ -242,7 +519,15 @@ impl Gateway {
     ///
     /// * `peers` (write)
     fn add_handle(&self, user_id: Snowflake<User>, handle: ConnectionHandle) {
-        self.peers.insert(user_id, handle);
+        // Only one session per user is kept, the previous one is closed
+        if let Some(previous) = self.peers.insert(user_id, handle) {
+            previous
+                .close(InvalidSessionPayload::new(
+                    GatewayCloseCode::SessionReplaced,
+                    "Session replaced",
+                ))
+                .ok();
+        }
     }
 
     /// Remove a connection handle from the gateway state
@@ -258,193 +543,916 @@ impl Gateway {
         self.peers.remove(&user_id);
     }
 
-    /// Dispatch a new event originating from the given user to all other users
+    /// Register a session served through long-polling
     ///
     /// ## Arguments
     ///
-    /// * `payload` - The event payload
+    /// * `key` - The secret key clients poll the session with
+    /// * `session` - The session
     ///
     /// ## Locks
     ///
-    /// * `peers` (write)
-    pub fn dispatch(&self, event: GatewayEvent) {
-        tracing::debug!(?event, "Dispatching event");
-
-        // TODO: Figure out how to use the `DashMap::retain` method here without killing borrowck
-        let mut to_drop: Vec<Snowflake<User>> = Vec::new();
+    /// * `poll_sessions` (write)
+    pub(super) fn add_poll_session(&self, key: String, session: Arc<PollSession>) {
+        self.poll_sessions.insert(key, session);
+    }
 
-        // Avoid cloning the event for each user
-        let event: Arc<GatewayEvent> = Arc::new(event);
-        let event_guild_id = event.extract_guild_id();
-        let event_user_id = event.extract_user_id();
+    /// Get a session served through long-polling by its key
+    ///
+    /// ## Locks
+    ///
+    /// * `poll_sessions` (read)
+    pub(super) fn poll_session(&self, key: &str) -> Option<Arc<PollSession>> {
+        self.poll_sessions.get(key).map(|session| session.clone())
+    }
 
-        for peer in &self.peers {
-            let (uid, handle) = peer.pair();
-            // If the event is guild-specific, only send it to users that are members of that guild
-            if let Some(event_guild) = event_guild_id {
-                if !handle.guild_ids().contains(&event_guild) {
-                    continue;
-                }
-            }
-            // Avoid sending events to users that don't share any guilds with the event originator
-            else if let Some(user_id) = event_user_id {
-                if !self.shares_guilds_with(*uid, user_id) {
-                    continue;
-                }
-            }
+    /// Remove a session served through long-polling, so it can no longer be polled
+    ///
+    /// ## Locks
+    ///
+    /// * `poll_sessions` (write)
+    pub(super) fn remove_poll_session(&self, key: &str) {
+        self.poll_sessions.remove(key);
+    }
 
-            if let Err(err) = handle.send(event.clone()) {
-                tracing::warn!(error = %err, "Error dispatching event to user: {uid}");
-                to_drop.push(*uid);
-            }
-        }
+    /// Register a websocket session that may be resumed on a new connection
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session
+    /// * `handle` - Hands new connections to the task serving the session
+    ///
+    /// ## Locks
+    ///
+    /// * `resumable` (write)
+    fn add_resumable(&self, id: Snowflake<GatewaySession>, handle: ResumeHandle) {
+        self.resumable.insert(id, handle);
+    }
 
-        for uid in to_drop {
-            self.remove_handle(uid);
-        }
+    /// Remove a websocket session that ended, so it can no longer be resumed
+    ///
+    /// ## Locks
+    ///
+    /// * `resumable` (write)
+    fn remove_resumable(&self, id: Snowflake<GatewaySession>) {
+        self.resumable.remove(&id);
     }
 
-    /// Drop a user session with the given code and reason
+    /// Hand a new connection to a websocket session of the given user, to resume it
+    ///
+    /// The resume token is used up, the task serving the session sends a new one in `RESUMED`.
     ///
     /// ## Arguments
     ///
-    /// * `user_id` - The ID of the user to drop
-    /// * `code` - The close code to send
-    /// * `reason` - The reason for closing the connection
+    /// * `id` - The ID of the session
+    /// * `user_id` - The user the client authenticated as
+    /// * `token` - The resume token sent by the client
+    /// * `socket` - The new connection
+    /// * `seq` - The last sequence number the client received
+    ///
+    /// ## Returns
+    ///
+    /// The connection if the session does not exist, ended, belongs to another user, or the token is not valid
     ///
     /// ## Locks
     ///
-    /// * `peers` (write)
-    pub fn drop_session(&self, user_id: Snowflake<User>, code: GatewayCloseCode, reason: String) {
-        if let Some(handle) = self.peers.get(&user_id) {
-            handle.close(code, reason).ok();
+    /// * `resumable` (read)
+    fn resume(
+        &self,
+        id: Snowflake<GatewaySession>,
+        user_id: Snowflake<User>,
+        token: &Secret<String>,
+        socket: Box<WebSocket>,
+        seq: u64,
+    ) -> Result<(), Box<WebSocket>> {
+        let Some(handle) = self.resumable.get(&id) else {
+            return Err(socket);
+        };
+        // Comparing the MACs of the tokens takes the same time regardless of where they differ
+        let is_valid = resume_token_mac(token.expose_secret())
+            .verify_slice(&handle.token)
+            .is_ok();
+        if handle.user_id != user_id || !is_valid {
+            return Err(socket);
         }
+        // The token stays valid until the new connection received its replacement,
+        // so the session can still be resumed if this connection is lost before that
+        handle
+            .sender
+            .send((socket, seq))
+            .map_err(|SendError((socket, _))| socket)
     }
 
-    pub fn close(&self) {
-        for handle in &self.peers {
-            handle
-                .close(GatewayCloseCode::GoingAway, "Server shutting down".into())
-                .ok();
+    /// Replace the resume token of a websocket session, after the client received the new one
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the session
+    /// * `token` - The new token
+    ///
+    /// ## Locks
+    ///
+    /// * `resumable` (write)
+    fn replace_resume_token(&self, id: Snowflake<GatewaySession>, token: &str) {
+        if let Some(mut handle) = self.resumable.get_mut(&id) {
+            handle.token = resume_token_mac(token).finalize().into_bytes().to_vec();
         }
-        self.peers.clear();
     }
 
-    /// Registers a new guild member instance to an existing connection
+    /// Register a read-only event stream
     ///
     /// ## Arguments
     ///
-    /// * `user` - The user to add to the connection
-    /// * `guild` - The guild to add the user to
+    /// * `id` - The ID of the stream
+    /// * `stream` - The stream
     ///
     /// ## Locks
     ///
-    /// * `peers` (write)
-    pub fn add_member(&self, user: impl Into<Snowflake<User>>, guild: impl Into<Snowflake<Guild>>) {
-        if let Some(mut handle) = self.peers.get_mut(&user.into()) {
-            handle.guild_ids_mut().insert(guild.into());
-        }
+    /// * `streams` (write)
+    pub(super) fn add_stream(&self, id: Snowflake<StreamHandle>, stream: StreamHandle) {
+        self.streams.insert(id, stream);
     }
 
-    /// Removes a guild member instance from an existing connection
+    /// Remove a read-only event stream
+    ///
+    /// ## Locks
+    ///
+    /// * `streams` (write)
+    pub(super) fn remove_stream(&self, id: Snowflake<StreamHandle>) {
+        self.streams.remove(&id);
+    }
+
+    /// Register a peer without a websocket connection, for benchmarking dispatch without the network
     ///
     /// ## Arguments
     ///
-    /// * `user` - The user to remove from the connection
-    /// * `guild` - The guild to remove the user from
+    /// * `user` - The user the peer is connected as
+    /// * `guilds` - The guilds the user is a member of
     ///
     /// ## Locks
     ///
     /// * `peers` (write)
-    pub fn remove_member(&self, user: impl Into<Snowflake<User>>, guild: impl Into<Snowflake<Guild>>) {
-        if let Some(mut handle) = self.peers.get_mut(&user.into()) {
-            handle.guild_ids_mut().remove(&guild.into());
-        }
+    #[cfg(feature = "bench")]
+    pub fn add_simulated_peer(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        guilds: impl IntoIterator<Item = Snowflake<Guild>>,
+    ) -> SimulatedPeer {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (broadcaster, _) = broadcast::channel(1);
+        self.add_handle(
+            user.into(),
+            ConnectionHandle::new(
+                sender,
+                Arc::new(broadcaster),
+                guilds.into_iter().collect(),
+                Presence::default(),
+                PresencePrivacy::default(),
+                Arc::default(),
+                DeviceType::default(),
+            ),
+        );
+        SimulatedPeer { receiver }
     }
 
-    /// Send an event to a specific user. If they are not connected, the event is dropped.
+    /// Check if the given session is the current session of a user, and was not replaced by a newer one
     ///
     /// ## Arguments
     ///
-    /// * `user` - The user to send the event to
-    /// * `event` - The event to send
+    /// * `user_id` - The ID of the user
+    /// * `broadcaster` - The broadcaster of the session, identifying it
     ///
     /// ## Locks
     ///
-    /// * `peers` (write)
-    pub fn send_to(&self, user: impl Into<Snowflake<User>>, event: GatewayEvent) {
-        let user_id: Snowflake<User> = user.into();
-        if let Some(handle) = self.peers.get(&user_id) {
-            if let Err(err) = handle.send(Arc::new(event)) {
-                // Drop handle to prevent deadlock
-                std::mem::drop(handle);
-                tracing::warn!(error = %err, "Error sending event to user: {user_id}");
-                self.remove_handle(user_id);
-            }
-        }
+    /// * `peers` (read)
+    fn is_current_session(
+        &self,
+        user_id: Snowflake<User>,
+        broadcaster: &Arc<broadcast::Sender<GatewayMessage>>,
+    ) -> bool {
+        self.peers
+            .get(&user_id)
+            .is_some_and(|handle| Arc::ptr_eq(&handle.broadcaster, broadcaster))
     }
 
-    /// Query if a given user is connected
+    /// Remove the connection handle of a session that ended, unless it was already replaced by a newer session
     ///
     /// ## Arguments
     ///
-    /// * `user` - The user to check for
+    /// * `user_id` - The ID of the user whose session ended
+    /// * `broadcaster` - The broadcaster of the session that ended, identifying it
     ///
-    /// ## Returns
+    /// ## Locks
     ///
-    /// `true` if the user is connected, `false` otherwise
+    /// * `peers` (write)
+    fn remove_session(&self, user_id: Snowflake<User>, broadcaster: &Arc<broadcast::Sender<GatewayMessage>>) {
+        self.peers
+            .remove_if(&user_id, |_, handle| Arc::ptr_eq(&handle.broadcaster, broadcaster));
+    }
+
+    /// Dispatch a new event originating from the given user to all other users
+    ///
+    /// ## Arguments
+    ///
+    /// * `payload` - The event payload
     ///
     /// ## Locks
     ///
-    /// * `peers` (read)
-    pub fn is_connected(&self, user: impl Into<Snowflake<User>>) -> bool {
-        self.peers.contains_key(&user.into())
+    /// * `peers` (write)
+    #[tracing::instrument(name = "gateway.dispatch", skip_all, fields(event = event.name()))]
+    pub fn dispatch(&self, event: GatewayEvent) {
+        tracing::debug!(?event, "Dispatching event");
+
+        let routing = EventRouting::from(&event);
+
+        let id = self.next_event_id();
+
+        if self.shard.is_sharded() {
+            let payload = serde_json::to_string(&event).expect("Failed to serialize gateway event");
+            self.tap(routing, || FirehosePayload::Serialized(payload.clone()));
+            self.route(BusMessage::Dispatch {
+                routing,
+                id: Some(id),
+                event: event.name().into(),
+                payload,
+            });
+            return;
+        }
+
+        // Avoid cloning the event for each user
+        let event = Arc::new(event);
+        self.tap(routing, || FirehosePayload::Event(event.clone()));
+        self.fan_out(
+            routing,
+            &GatewayResponse::Event {
+                id,
+                event,
+                queued_at: Instant::now(),
+            },
+        );
     }
 
-    /// Determines if a given user shares any guilds with another user
+    /// Dispatch an event that was already serialized, such as one read from the outbox
     ///
     /// ## Arguments
     ///
-    /// * `a` - The first user to check
-    /// * `b` - The second user to check
+    /// * `routing` - Determines which users receive the event
+    /// * `id` - The ID of the event, kept if the event is dispatched again
+    /// * `event` - The name of the event
+    /// * `payload` - The serialized event payload
     ///
-    /// ## Returns
+    /// ## Locks
     ///
-    /// `true` if the users share any guilds, `false` otherwise
+    /// * `peers` (write)
+    #[tracing::instrument(name = "gateway.dispatch", skip_all, fields(event = %event))]
+    pub fn dispatch_serialized(
+        &self,
+        routing: EventRouting,
+        id: Snowflake<GatewayEvent>,
+        event: String,
+        payload: String,
+    ) {
+        self.tap(routing, || FirehosePayload::Serialized(payload.clone()));
+        self.route(BusMessage::Dispatch {
+            routing,
+            id: Some(id),
+            event,
+            payload,
+        });
+    }
+
+    /// Send a response to all users that should receive an event with the given routing
     ///
     /// ## Locks
     ///
-    /// * `peers` (read)
-    pub fn shares_guilds_with(&self, a: Snowflake<User>, b: Snowflake<User>) -> bool {
-        if let Some(a_handle) = self.peers.get(&a) {
-            if let Some(b_handle) = self.peers.get(&b) {
-                return a_handle.guild_ids().intersection(b_handle.guild_ids()).next().is_some();
+    /// * `peers` (write)
+    fn fan_out(&self, routing: EventRouting, response: &GatewayResponse) {
+        // Private events, such as messages in saved messages channels, only reach a single user
+        if let Some(recipient) = routing.recipient_id {
+            // Events without a guild are sent on the first shard
+            if routing
+                .guild_id
+                .map_or_else(|| self.shard.is_primary(), |guild| self.shard.owns(guild))
+            {
+                self.respond_to(recipient, response.clone());
             }
+            return;
         }
-        false
-    }
-}
 
-impl Default for Gateway {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+        // Users who appear offline must not reveal that they are active, except to themselves
+        let hidden_user = routing.activity_of.filter(|user| self.appears_offline(*user));
 
-/// Get router for handling the gateway
-///
-/// ## Returns
-///
-/// A filter that can be used to handle the gateway
-pub fn get_router() -> Router<App> {
-    Router::new().route("/", get(websocket_handler))
-}
+        self.fan_out_to_streams(routing, hidden_user, response);
 
-async fn websocket_handler(State(app): State<App>, ws: WebSocketUpgrade) -> impl IntoResponse {
-    ws.on_upgrade(|socket| async move { handle_connection(app, socket).await })
-}
+        // Events for all users, such as announcements, would otherwise reach sharded clients once per shard
+        if routing.guild_id.is_none() && routing.user_id.is_none() && !self.shard.is_primary() {
+            return;
+        }
 
-/// Send a serializable object to the client
+        // TODO: Figure out how to use the `DashMap::retain` method here without killing borrowck
+        let mut to_drop: Vec<Snowflake<User>> = Vec::new();
+
+        for peer in &self.peers {
+            let (uid, handle) = peer.pair();
+            if hidden_user.is_some_and(|user| user != *uid) {
+                continue;
+            }
+            // If the event is guild-specific, only send it to users that are members of that guild
+            if let Some(event_guild) = routing.guild_id {
+                if !handle.guild_ids().contains(&event_guild) {
+                    continue;
+                }
+                // Thin clients may only be interested in events from a few channels,
+                // and guests may only see the channels of their guest link
+                if routing
+                    .channel_id
+                    .is_some_and(|c| !handle.is_subscribed_to(c) || !handle.can_access(event_guild, c))
+                {
+                    continue;
+                }
+            }
+            // Avoid sending events to users that don't share any guilds with the event originator,
+            // or that the originator hides their presence from
+            else if let Some(user_id) = routing.user_id {
+                if !self.can_see_presence_of(*uid, user_id) {
+                    continue;
+                }
+            }
+
+            if let Err(err) = handle.respond(response.clone()) {
+                tracing::warn!(error = %err, "Error dispatching event to user: {uid}");
+                to_drop.push(*uid);
+            }
+        }
+
+        for uid in to_drop {
+            self.remove_handle(uid);
+        }
+    }
+
+    /// Send a response to all read-only event streams that should receive an event with the given routing
+    ///
+    /// Streams only receive the events in [`STREAM_EVENTS`] of the guilds they selected,
+    /// and the presence updates of users sharing one of those guilds with them.
+    /// Events revealing the activity of `hidden_user` only reach their own streams.
+    ///
+    /// ## Locks
+    ///
+    /// * `streams` (write)
+    /// * `peers` (read)
+    fn fan_out_to_streams(
+        &self,
+        routing: EventRouting,
+        hidden_user: Option<Snowflake<User>>,
+        response: &GatewayResponse,
+    ) {
+        let Some(name) = response.event_name().filter(|name| STREAM_EVENTS.contains(name)) else {
+            return;
+        };
+
+        let mut to_drop: Vec<Snowflake<StreamHandle>> = Vec::new();
+
+        for entry in &self.streams {
+            let (id, stream) = entry.pair();
+            if hidden_user.is_some_and(|user| user != stream.user_id()) {
+                continue;
+            }
+            let visible = if name == "PRESENCE_UPDATE" {
+                routing
+                    .user_id
+                    .is_some_and(|user| self.stream_can_see_presence_of(stream, user))
+            } else {
+                routing
+                    .guild_id
+                    .is_some_and(|guild| stream.guild_ids().contains(&guild))
+            };
+            if !visible {
+                continue;
+            }
+
+            if let Err(err) = stream.respond(response.clone()) {
+                tracing::warn!(error = %err, "Error dispatching event to stream of user: {}", stream.user_id());
+                to_drop.push(*id);
+            }
+        }
+
+        for id in to_drop {
+            self.remove_stream(id);
+        }
+    }
+
+    /// Determines if a read-only event stream can see the presence of a user,
+    /// through one of its guilds the user does not hide their presence from.
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (read)
+    fn stream_can_see_presence_of(&self, stream: &StreamHandle, user: Snowflake<User>) -> bool {
+        if stream.user_id() == user {
+            return true;
+        }
+        self.peers.get(&user).is_some_and(|handle| {
+            stream
+                .guild_ids()
+                .intersection(handle.guild_ids())
+                .any(|guild| handle.presence_privacy.is_visible_in(*guild))
+        })
+    }
+
+    /// Drop a user session, sending `INVALID_SESSION` with the given payload first
+    ///
+    /// ## Arguments
+    ///
+    /// * `user_id` - The ID of the user to drop
+    /// * `payload` - The close code and reason to send, along with a reconnect hint
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    pub fn drop_session(&self, user_id: Snowflake<User>, payload: InvalidSessionPayload) {
+        if let Some(handle) = self.peers.get(&user_id) {
+            handle.close(payload).ok();
+        }
+    }
+
+    /// Close all connections because the server is shutting down.
+    /// Clients are told to reconnect after a random delay, so they do not all reconnect at once.
+    pub fn close(&self) {
+        let max_jitter = RESTART_RECONNECT_JITTER.as_millis() as u64;
+        for handle in &self.peers {
+            let jitter = Duration::from_millis(rand::thread_rng().gen_range(0..=max_jitter));
+            handle
+                .close(
+                    InvalidSessionPayload::new(GatewayCloseCode::ServerRestart, "Server shutting down")
+                        .with_reconnect_after(Some(RESTART_RECONNECT_DELAY + jitter)),
+                )
+                .ok();
+        }
+        self.peers.clear();
+        // Dropping the handles of streams ends them
+        self.streams.clear();
+    }
+
+    /// Registers a new guild member instance to an existing connection
+    ///
+    /// If the user was already registered, their guest access is replaced.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to add to the connection
+    /// * `guild` - The guild to add the user to
+    /// * `guest` - The access of the user if they joined as a guest
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    pub fn add_member(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        guild: impl Into<Snowflake<Guild>>,
+        guest: Option<&GuestAccess>,
+    ) {
+        self.route(BusMessage::AddMember {
+            user_id: user.into(),
+            guild_id: guild.into(),
+            guest: guest.cloned(),
+        });
+    }
+
+    /// Removes a guild member instance from an existing connection
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to remove from the connection
+    /// * `guild` - The guild to remove the user from
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    pub fn remove_member(&self, user: impl Into<Snowflake<User>>, guild: impl Into<Snowflake<Guild>>) {
+        self.route(BusMessage::RemoveMember {
+            user_id: user.into(),
+            guild_id: guild.into(),
+        });
+    }
+
+    /// Send an event to a specific user. If they are not connected, the event is dropped.
+    ///
+    /// If the gateway is sharded, the event is sent on the shard of its guild,
+    /// or on the first shard if it does not belong to a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to send the event to
+    /// * `event` - The event to send
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    #[tracing::instrument(name = "gateway.send", skip_all, fields(event = event.name()))]
+    pub fn send_to(&self, user: impl Into<Snowflake<User>>, event: GatewayEvent) {
+        let user_id: Snowflake<User> = user.into();
+        let routing = EventRouting {
+            recipient_id: Some(user_id),
+            ..EventRouting::from(&event)
+        };
+
+        let id = self.next_event_id();
+
+        if self.shard.is_sharded() {
+            let payload = serde_json::to_string(&event).expect("Failed to serialize gateway event");
+            self.tap(routing, || FirehosePayload::Serialized(payload.clone()));
+            self.route(BusMessage::SendTo {
+                user_id,
+                guild_id: routing.guild_id,
+                id: Some(id),
+                event: event.name().into(),
+                payload,
+            });
+            return;
+        }
+
+        let event = Arc::new(event);
+        self.tap(routing, || FirehosePayload::Event(event.clone()));
+        self.respond_to(
+            user_id,
+            GatewayResponse::Event {
+                id,
+                event,
+                queued_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Send a copy of an event to the analytics firehose, if it is enabled.
+    /// Events are copied once by the process dispatching them, not by every process delivering them.
+    ///
+    /// ## Arguments
+    ///
+    /// * `routing` - Who the event is dispatched to
+    /// * `payload` - Creates the payload of the event, only called if the firehose is enabled
+    fn tap(&self, routing: EventRouting, payload: impl FnOnce() -> FirehosePayload) {
+        if let Some(app) = self.app.upgrade() {
+            app.firehose.publish(routing, app.clock.now(), payload);
+        }
+    }
+
+    /// Send a response to a user connected to this process. If they are not connected, the response is dropped.
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    fn respond_to(&self, user_id: Snowflake<User>, response: GatewayResponse) {
+        if let Some(handle) = self.peers.get(&user_id) {
+            if let Err(err) = handle.respond(response) {
+                // Drop handle to prevent deadlock
+                std::mem::drop(handle);
+                tracing::warn!(error = %err, "Error sending event to user: {user_id}");
+                self.remove_handle(user_id);
+            }
+        }
+    }
+
+    /// Set or clear the activity of a connected user. If they are not connected, this does nothing.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to set the activity for
+    /// * `activity` - The new activity, or `None` to clear it
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    pub fn set_activity(&self, user: impl Into<Snowflake<User>>, activity: Option<Activity>) {
+        self.route(BusMessage::SetActivity {
+            user_id: user.into(),
+            activity,
+        });
+    }
+
+    /// Set the presence a connected user chose. If they are not connected, this does nothing.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to set the presence for
+    /// * `presence` - The new presence
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    pub fn set_presence(&self, user: impl Into<Snowflake<User>>, presence: Presence) {
+        self.route(BusMessage::SetPresence {
+            user_id: user.into(),
+            presence,
+        });
+    }
+
+    /// Set the presence privacy settings of a connected user. If they are not connected, this does nothing.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to set the settings for
+    /// * `privacy` - The new settings
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    pub fn set_presence_privacy(&self, user: impl Into<Snowflake<User>>, privacy: PresencePrivacy) {
+        self.route(BusMessage::SetPresencePrivacy {
+            user_id: user.into(),
+            privacy,
+        });
+    }
+
+    /// Enable or disable data saver mode for the session of a connected user. If they are not connected, this does nothing.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to set data saver mode for
+    /// * `enabled` - Whether events sent to the user should be reduced
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (read)
+    pub fn set_data_saver(&self, user: impl Into<Snowflake<User>>, enabled: bool) {
+        self.route(BusMessage::SetDataSaver {
+            user_id: user.into(),
+            enabled,
+        });
+    }
+
+    /// Set the channels a connected user receives channel-specific events for.
+    /// If they are not connected, this does nothing.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to set the subscriptions for
+    /// * `channels` - The channels to subscribe to, or `None` to subscribe to all channels
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (write)
+    pub fn set_subscriptions(&self, user: impl Into<Snowflake<User>>, channels: Option<HashSet<Snowflake<Channel>>>) {
+        if let Some(mut handle) = self.peers.get_mut(&user.into()) {
+            handle.channel_ids = channels;
+        }
+    }
+
+    /// Get the activity of a connected user
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to get the activity of
+    ///
+    /// ## Returns
+    ///
+    /// The user's activity, or `None` if they have none or are not connected
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (read)
+    pub fn activity_of(&self, user: impl Into<Snowflake<User>>) -> Option<Activity> {
+        self.peers.get(&user.into()).and_then(|h| h.activity.clone())
+    }
+
+    /// Query if a given user is connected
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to check for
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the user is connected, `false` otherwise
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (read)
+    pub fn is_connected(&self, user: impl Into<Snowflake<User>>) -> bool {
+        self.peers.contains_key(&user.into())
+    }
+
+    /// Determines if a given user shares any guilds with another user
+    ///
+    /// ## Arguments
+    ///
+    /// * `a` - The first user to check
+    /// * `b` - The second user to check
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the users share any guilds, `false` otherwise
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (read)
+    pub fn shares_guilds_with(&self, a: Snowflake<User>, b: Snowflake<User>) -> bool {
+        if let Some(a_handle) = self.peers.get(&a) {
+            if let Some(b_handle) = self.peers.get(&b) {
+                return a_handle.guild_ids().intersection(b_handle.guild_ids()).next().is_some();
+            }
+        }
+        false
+    }
+
+    /// Determines if a given user can see the presence of another user.
+    /// Users see their own presence regardless of their settings,
+    /// others only through a shared guild the user does not hide their presence from.
+    ///
+    /// ## Arguments
+    ///
+    /// * `viewer` - The user looking at the presence
+    /// * `user` - The user whose presence is looked at
+    ///
+    /// ## Returns
+    ///
+    /// `true` if `viewer` can see the presence of `user`, `false` otherwise
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (read)
+    pub fn can_see_presence_of(&self, viewer: Snowflake<User>, user: Snowflake<User>) -> bool {
+        if viewer == user {
+            return self.shares_guilds_with(viewer, user);
+        }
+        if let Some(viewer_handle) = self.peers.get(&viewer) {
+            if let Some(user_handle) = self.peers.get(&user) {
+                return viewer_handle
+                    .guild_ids()
+                    .intersection(user_handle.guild_ids())
+                    .any(|guild| user_handle.presence_privacy.is_visible_in(*guild));
+            }
+        }
+        false
+    }
+
+    /// Determines if the presence of a user is shown to the members of a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to check
+    /// * `guild` - The guild to check
+    ///
+    /// ## Returns
+    ///
+    /// `false` if the user is connected and hides their presence from the guild, `true` otherwise
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (read)
+    pub fn is_presence_visible_in(&self, user: impl Into<Snowflake<User>>, guild: impl Into<Snowflake<Guild>>) -> bool {
+        self.peers
+            .get(&user.into())
+            .is_none_or(|handle| handle.presence_privacy.is_visible_in(guild.into()))
+    }
+
+    /// Determines if a user appears offline to everyone, because they are not connected or chose to be invisible.
+    ///
+    /// ## Locks
+    ///
+    /// * `peers` (read)
+    pub fn appears_offline(&self, user: impl Into<Snowflake<User>>) -> bool {
+        self.peers
+            .get(&user.into())
+            .is_none_or(|handle| handle.presence == Presence::Offline)
+    }
+}
+
+impl Default for Gateway {
+    fn default() -> Self {
+        Self::new(ShardInfo::default())
+    }
+}
+
+/// Get router for handling the gateway
+///
+/// ## Returns
+///
+/// A filter that can be used to handle the gateway
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/", get(websocket_handler))
+        .route("/bot", get(get_gateway_bot))
+        .route(
+            "/poll",
+            get(polling::poll).post(polling::identify).delete(polling::close),
+        )
+        .route("/poll/messages", post(polling::send_message))
+        .route("/events", get(sse::stream))
+}
+
+/// Get the amount of shards clients should open connections for.
+///
+/// ## Endpoint
+///
+/// GET `/gateway/v1/bot`
+async fn get_gateway_bot(State(app): State<App>) -> Json<Value> {
+    Json(json!({ "shards": app.config.gateway_shard().count() }))
+}
+
+/// Upgrade a request to a gateway connection.
+///
+/// Browsers may only connect from the configured allowed origins.
+/// If the client requests subprotocols, one of them must be [`GATEWAY_SUBPROTOCOL`].
+/// Clients that do not request a subprotocol are assumed to speak [`GATEWAY_SUBPROTOCOL`].
+/// Browser clients with a cookie session may omit the token from IDENTIFY.
+///
+/// ## Errors
+///
+/// * [`RESTError::Forbidden`] - If the origin of the request is not allowed
+/// * [`RESTError::BadRequest`] - If none of the requested subprotocols is supported
+async fn websocket_handler(
+    State(app): State<App>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Result<Response, RESTError> {
+    check_origin(&app.config, &headers)?;
+
+    let ws = match requested_protocols(&headers) {
+        None => ws,
+        Some(protocols) if protocols.contains(&GATEWAY_SUBPROTOCOL) => ws.protocols([GATEWAY_SUBPROTOCOL]),
+        Some(_) => {
+            return Err(RESTError::BadRequest(format!(
+                "Unsupported subprotocol, this server only supports '{GATEWAY_SUBPROTOCOL}'"
+            )));
+        }
+    };
+
+    // Larger frames are rejected as soon as their header is read, instead of being buffered
+    let ws = ws
+        .max_message_size(app.config.gateway_max_payload_size())
+        .max_frame_size(app.config.gateway_max_payload_size());
+    let session_token = cookie_token(&app.config, &headers);
+
+    Ok(ws.on_upgrade(|socket| async move { handle_connection(app, socket, session_token).await }))
+}
+
+/// The token of the session cookie sent with a request, if any
+///
+/// Browser clients with a cookie session may omit the token from IDENTIFY. Browsers send cookies
+/// along with connections opened by any site, so the cookie is only accepted from the allowed origins,
+/// to prevent cross-site websocket hijacking. If no origins are allowed, the cookie is never accepted.
+pub(super) fn cookie_token(config: &Config, headers: &HeaderMap) -> Option<Secret<String>> {
+    let origin = request_origin(headers)?;
+    if !config.gateway_allowed_origins().contains(&origin) {
+        return None;
+    }
+
+    match auth::find_token(config, headers) {
+        Some((token, TokenSource::Cookie)) => Some(Secret::new(token)),
+        _ => None,
+    }
+}
+
+/// Ensure that the origin of a request is allowed to connect to the gateway
+///
+/// ## Errors
+///
+/// * [`RESTError::Forbidden`] - If the origin is not in the configured allowed origins
+pub(super) fn check_origin(config: &Config, headers: &HeaderMap) -> Result<(), RESTError> {
+    let allowed = config.gateway_allowed_origins();
+
+    // Only browsers send an origin, other clients are not susceptible to cross-site websocket hijacking
+    let Some(origin) = request_origin(headers) else {
+        return Ok(());
+    };
+
+    if allowed.is_empty() || allowed.contains(&origin) {
+        Ok(())
+    } else {
+        Err(RESTError::Forbidden(
+            "Origin is not allowed to connect to the gateway.".into(),
+        ))
+    }
+}
+
+/// The origin a request was sent from, normalized like the configured allowed origins, if the client sent one
+fn request_origin(headers: &HeaderMap) -> Option<String> {
+    let origin = headers.get(header::ORIGIN)?;
+    Some(
+        origin
+            .to_str()
+            .unwrap_or_default()
+            .trim_end_matches('/')
+            .to_ascii_lowercase(),
+    )
+}
+
+/// The subprotocols requested by the client, or `None` if it did not request any
+fn requested_protocols(headers: &HeaderMap) -> Option<Vec<&str>> {
+    let protocols: Vec<&str> = headers
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .collect();
+
+    (!protocols.is_empty()).then_some(protocols)
+}
+
+/// Send a serializable object to the client
 ///
 /// ## Panics
 ///
@@ -466,237 +1474,634 @@ async fn send_serializable(
     ws_sink.send(Message::Text(message)).await
 }
 
-/// Send a close frame to the client
+/// Send `INVALID_SESSION` to the client, followed by a close frame with the same code and reason
 ///
 /// ## Arguments
 ///
 /// * `ws_sink` - The sink for sending messages to the client
+/// * `payload` - The close code and reason to send, along with a reconnect hint
 ///
 /// ## Returns
 ///
-/// `Ok(())` if the message was sent successfully, an error otherwise
-async fn send_close_frame(
+/// `Ok(())` if the messages were sent successfully, an error otherwise
+async fn close_session(
     ws_sink: &mut SplitSink<WebSocket, Message>,
-    code: GatewayCloseCode,
-    reason: impl Into<Cow<'static, str>>,
+    payload: InvalidSessionPayload,
 ) -> Result<(), axum::Error> {
-    ws_sink
-        .send(Message::Close(Some(CloseFrame {
-            code: code.into(),
-            reason: reason.into(),
-        })))
-        .await
+    let frame = CloseFrame {
+        code: payload.code().into(),
+        reason: Cow::Owned(payload.reason().to_string()),
+    };
+    send_serializable(ws_sink, GatewayEvent::InvalidSession(payload)).await?;
+    ws_sink.send(Message::Close(Some(frame))).await
+}
+
+/// How a client started a websocket connection
+enum Handshake {
+    /// The client identified as the given user, starting a new session
+    Identify(Box<User>, ClientProperties),
+    /// The client asked to continue a session whose connection was lost
+    Resume {
+        /// The user the client authenticated as
+        user_id: Snowflake<User>,
+        /// The session to continue
+        session_id: Snowflake<GatewaySession>,
+        /// The resume token of the session
+        resume_token: Secret<String>,
+        /// The last sequence number the client received
+        seq: u64,
+    },
 }
 
-/// Send HELLO, then wait for and validate the IDENTIFY payload
+/// Send HELLO, then wait for and validate the IDENTIFY or RESUME payload
 ///
 /// ## Arguments
 ///
 /// * `ws_sink` - The sink for sending messages to the client
 /// * `ws_stream` - The stream for receiving messages from the client
+/// * `session_token` - The token of the session cookie sent with the upgrade request, if any
 ///
 /// ## Returns
 ///
-/// The resolved user if the handshake was successful
+/// How the client started the connection if the handshake was successful
 async fn handle_handshake(
     app: App,
     ws_sink: &mut SplitSink<WebSocket, Message>,
     ws_stream: &mut SplitStream<WebSocket>,
-) -> Result<User, GatewayError> {
+    session_token: Option<Secret<String>>,
+) -> Result<Handshake, GatewayError> {
     // Send HELLO with the heartbeat interval
     ws_sink
         .send(Message::Text(
-            serde_json::to_string(&GatewayEvent::Hello(HelloPayload::new(HEARTBEAT_INTERVAL)))
-                .expect("Failed to serialize HELLO payload"),
+            serde_json::to_string(&GatewayEvent::Hello(HelloPayload::new(
+                app.config.gateway_heartbeat_interval().as_millis() as u64,
+            )))
+            .expect("Failed to serialize HELLO payload"),
         ))
         .await
         .ok();
 
     let maybe_ident = timeout(Duration::from_secs(5), ws_stream.next()).await;
 
+    if matches!(&maybe_ident, Ok(Some(Err(e))) if is_too_large(e)) {
+        close_too_large(ws_sink, app.config.gateway_max_payload_size()).await?;
+        return Err(GatewayError::MalformedFrame("IDENTIFY payload too large".into()));
+    }
+
     // IDENTIFY should be the first message sent
     let Ok(Some(Ok(ident))) = maybe_ident else {
-        send_close_frame(ws_sink, GatewayCloseCode::PolicyViolation, "IDENTIFY expected").await?;
+        close_session(
+            ws_sink,
+            InvalidSessionPayload::new(GatewayCloseCode::SessionTimeout, "IDENTIFY expected"),
+        )
+        .await?;
         return Err(GatewayError::HandshakeFailure("IDENTIFY expected".into()));
     };
 
     let Message::Text(text) = ident else {
-        send_close_frame(ws_sink, GatewayCloseCode::InvalidPayload, "Invalid IDENTIFY payload").await?;
+        close_session(
+            ws_sink,
+            InvalidSessionPayload::new(GatewayCloseCode::InvalidPayload, "Invalid IDENTIFY payload"),
+        )
+        .await?;
         return Err(GatewayError::MalformedFrame("Invalid IDENTIFY payload".into()));
     };
 
-    let Ok(GatewayMessage::Identify(payload)) = serde_json::from_str(&text) else {
-        send_close_frame(ws_sink, GatewayCloseCode::InvalidPayload, "Invalid IDENTIFY payload").await?;
-        return Err(GatewayError::MalformedFrame("Invalid IDENTIFY payload".into()));
+    let handshake = match serde_json::from_str(&text) {
+        Ok(GatewayMessage::Identify(payload)) => identify(&app, payload, session_token)
+            .await
+            .map(|(user, properties)| Handshake::Identify(Box::new(user), properties)),
+        Ok(GatewayMessage::Resume(ResumePayload {
+            token,
+            session_id,
+            resume_token,
+            seq,
+        })) => authenticate(&app, token, session_token)
+            .await
+            .map(|user| Handshake::Resume {
+                user_id: user.id(),
+                session_id,
+                resume_token,
+                seq,
+            }),
+        _ => {
+            close_session(
+                ws_sink,
+                InvalidSessionPayload::new(GatewayCloseCode::InvalidPayload, "Invalid IDENTIFY payload"),
+            )
+            .await?;
+            return Err(GatewayError::MalformedFrame("Invalid IDENTIFY payload".into()));
+        }
     };
 
-    let Ok(token) = Token::validate(app.clone(), payload.token.expose_secret()).await else {
-        send_close_frame(ws_sink, GatewayCloseCode::PolicyViolation, "Invalid token").await?;
-        return Err(GatewayError::AuthError("Invalid token".into()));
+    match handshake {
+        Ok(handshake) => Ok(handshake),
+        Err(payload) => {
+            let reason = payload.reason().to_string();
+            let error = match payload.code() {
+                GatewayCloseCode::AuthenticationFailed => GatewayError::AuthError(reason),
+                GatewayCloseCode::InvalidPayload => GatewayError::MalformedFrame(reason),
+                GatewayCloseCode::ServerError => GatewayError::InternalServerError(reason),
+                _ => GatewayError::HandshakeFailure(reason),
+            };
+            close_session(ws_sink, payload).await?;
+            Err(error)
+        }
+    }
+}
+
+/// Validate an `IDENTIFY` payload and resolve the user it authenticates as
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `payload` - The `IDENTIFY` payload sent by the client
+/// * `session_token` - The token of the session cookie sent by the client, if any
+///
+/// ## Returns
+///
+/// The user and the properties of their client, or the close code and reason to reject the session with
+pub(super) async fn identify(
+    app: &App,
+    payload: IdentifyPayload,
+    session_token: Option<Secret<String>>,
+) -> Result<(User, ClientProperties), InvalidSessionPayload> {
+    let Ok(properties) = payload.properties.validate() else {
+        return Err(InvalidSessionPayload::new(
+            GatewayCloseCode::InvalidPayload,
+            "Invalid client properties",
+        ));
+    };
+
+    // Clients must connect to the shard served by this process
+    if payload.shard.unwrap_or_default() != app.gateway.shard() {
+        return Err(InvalidSessionPayload::new(
+            GatewayCloseCode::InvalidShard,
+            "Invalid shard",
+        ));
+    }
+
+    let user = authenticate(app, payload.token, session_token).await?;
+    Ok((user, properties))
+}
+
+/// Resolve the user the token sent in `IDENTIFY` or `RESUME` authenticates as
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `token` - The token sent by the client, if any
+/// * `session_token` - The token of the session cookie sent by the client, if any
+///
+/// ## Returns
+///
+/// The user, or the close code and reason to reject the session with
+async fn authenticate(
+    app: &App,
+    token: Option<Secret<String>>,
+    session_token: Option<Secret<String>>,
+) -> Result<User, InvalidSessionPayload> {
+    let Some(token) = token.or(session_token) else {
+        return Err(InvalidSessionPayload::new(
+            GatewayCloseCode::AuthenticationFailed,
+            "Missing token",
+        ));
     };
 
-    let user_id = token.data().user_id();
-    let Some(user) = app.ops().fetch_user(user_id).await else {
-        send_close_frame(ws_sink, GatewayCloseCode::ServerError, "No user belongs to token").await?;
-        return Err(GatewayError::InternalServerError("No user belongs to token".into()));
+    let Ok(token) = Token::validate(app.clone(), token.expose_secret()).await else {
+        return Err(InvalidSessionPayload::new(
+            GatewayCloseCode::AuthenticationFailed,
+            "Invalid token",
+        ));
     };
 
-    Ok(user)
+    app.ops()
+        .fetch_user(token.data().user_id())
+        .await
+        .ok_or_else(|| InvalidSessionPayload::new(GatewayCloseCode::ServerError, "No user belongs to token"))
 }
 
-/// Handle the heartbeat mechanism for a given user
+/// Handle the heartbeat mechanism of a websocket connection
+///
+/// Heartbeats are acknowledged on the connection they were received on, regardless of sharding,
+/// and the events the client acknowledges along with them are no longer kept for resuming.
+/// This function will only return if the client failed to send a valid heartbeat within the timeframe.
+///
+/// ## Arguments
+///
+/// * `receiver` - The receiver for incoming gateway messages from the client
+/// * `ws_sink` - The sink for sending messages to the client
+/// * `buffer` - The events sent to the session that the client did not acknowledge yet
+/// * `heartbeat_interval` - The interval at which heartbeats should be received from the client
 ///
-/// This function will only return if the user failed to send a valid heartbeat within the timeframe
+/// ## Returns
+///
+/// The close code and reason to close the connection with
+async fn handle_heartbeating(
+    mut receiver: broadcast::Receiver<GatewayMessage>,
+    ws_sink: Arc<Mutex<SplitSink<WebSocket, Message>>>,
+    buffer: Arc<StdMutex<ReplayBuffer>>,
+    heartbeat_interval: Duration,
+) -> InvalidSessionPayload {
+    loop {
+        // Wait for a single heartbeat message
+        let heartbeat = timeout(heartbeat_interval + Duration::from_secs(5), async {
+            loop {
+                if let GatewayMessage::Heartbeat(payload) = receiver.recv().await? {
+                    return Ok::<_, broadcast::error::RecvError>(payload);
+                }
+            }
+        })
+        .await;
+
+        // Close if either the time runs out or an invalid payload is received
+        let payload = match heartbeat {
+            Ok(Ok(payload)) => payload,
+            Ok(Err(_)) => return InvalidSessionPayload::new(GatewayCloseCode::InvalidPayload, "Invalid payload"),
+            Err(_) => {
+                return InvalidSessionPayload::new(
+                    GatewayCloseCode::SessionTimeout,
+                    "No HEARTBEAT received within timeframe",
+                )
+            }
+        };
+
+        if let Some(payload) = payload {
+            buffer.lock().expect("Lock should not be poisoned").ack(payload.seq);
+        }
+        send_serializable(&mut *ws_sink.lock().await, GatewayEvent::HeartbeatAck)
+            .await
+            .ok();
+    }
+}
+
+/// Handle requests sent by the user that need to be processed by the server
 ///
 /// ## Arguments
 ///
 /// * `app` - The shared application state
-/// * `heartbeat_interval` - The interval at which heartbeats should be received from the user
-/// * `user_id` - The ID of the user to receive heartbeats from
-async fn handle_heartbeating(app: App, user_id: Snowflake<User>, heartbeat_interval: Duration) {
+/// * `user_id` - The ID of the user to handle requests for
+/// * `receiver` - The receiver for incoming gateway messages from the user
+pub(super) async fn handle_requests(
+    app: App,
+    user_id: Snowflake<User>,
+    mut receiver: broadcast::Receiver<GatewayMessage>,
+) {
     loop {
-        let Some(mut recv) = app.gateway.peers.get(&user_id).map(|h| h.get_receiver()) else {
-            return;
+        let msg = match receiver.recv().await {
+            Ok(msg) => msg,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
         };
-        let sleep_task = tokio::time::sleep(heartbeat_interval + Duration::from_secs(5));
-        // Wait for a single heartbeat message
-        let heartbeat_task = tokio::spawn(async move {
-            loop {
-                let msg = recv.recv().await.map_err(|_| GatewayCloseCode::InvalidPayload)?;
-                if matches!(msg, GatewayMessage::Heartbeat) {
-                    return Ok(()); // Heartbeat received, we're good
+
+        match msg {
+            GatewayMessage::UpdateActivity(activity) => {
+                if let Some(Err(e)) = activity.as_ref().map(|activity| activity.validate(app.clock.now())) {
+                    app.gateway.drop_session(
+                        user_id,
+                        InvalidSessionPayload::new(GatewayCloseCode::InvalidPayload, e.to_string()),
+                    );
+                    return;
                 }
+
+                app.presences().update_activity(user_id, activity).await;
             }
-        })
-        .abort_on_drop();
+            GatewayMessage::Subscribe(payload) => {
+                if payload
+                    .channel_ids
+                    .as_ref()
+                    .is_some_and(|ids| ids.len() > MAX_SUBSCRIBED_CHANNELS)
+                {
+                    app.gateway.drop_session(
+                        user_id,
+                        InvalidSessionPayload::new(
+                            GatewayCloseCode::InvalidPayload,
+                            format!("Cannot subscribe to more than {MAX_SUBSCRIBED_CHANNELS} channels"),
+                        ),
+                    );
+                    return;
+                }
 
-        // Close if either the time runs out or an invalid payload is received
-        let should_close = tokio::select! {
-            () = sleep_task => Err(GatewayCloseCode::PolicyViolation),
-            ret = heartbeat_task => ret.unwrap_or(Err(GatewayCloseCode::ServerError)),
-        };
+                app.gateway
+                    .set_subscriptions(user_id, payload.channel_ids.map(HashSet::from_iter));
+            }
+            GatewayMessage::RequestGuildMembers(payload) => {
+                if payload
+                    .query
+                    .as_ref()
+                    .is_some_and(|q| q.chars().count() > MAX_MEMBER_QUERY_LENGTH)
+                {
+                    app.gateway.drop_session(
+                        user_id,
+                        InvalidSessionPayload::new(
+                            GatewayCloseCode::InvalidPayload,
+                            format!("Member queries may not be longer than {MAX_MEMBER_QUERY_LENGTH} characters"),
+                        ),
+                    );
+                    return;
+                }
 
-        if let Err(close_code) = should_close {
-            let reason = match close_code {
-                GatewayCloseCode::InvalidPayload => "Invalid payload".into(),
-                GatewayCloseCode::PolicyViolation => "No HEARTBEAT received within timeframe".into(),
-                _ => "Unknown error".into(),
-            };
+                let status = app
+                    .rate_limiter
+                    .hit(RateLimitBucket::GuildMemberRequests, RateLimitKey::User(user_id));
+                if status.is_exceeded() {
+                    // Clients may reconnect once the rate limit resets
+                    app.gateway.drop_session(
+                        user_id,
+                        InvalidSessionPayload::new(GatewayCloseCode::RateLimited, "Requesting guild members too often")
+                            .with_reconnect_after(Some(status.reset_after())),
+                    );
+                    return;
+                }
 
-            app.gateway.drop_session(user_id, close_code, reason);
-            break;
+                // Requests are handled one at a time, so a single connection cannot stream several guilds at once
+                if let Err(e) = app.members().send_chunks(user_id, payload).await {
+                    tracing::error!(error = %e, "Failed to send guild member chunks to user: {user_id}");
+                }
+            }
+            _ => {}
         }
-
-        app.gateway.send_to(user_id, GatewayEvent::HeartbeatAck);
     }
 }
 
-/// Send the `READY` event, all `GUILD_CREATE` events, and dispatch a `PRESENCE_UPDATE` event for this user
+/// Queue the `READY` event and all `GUILD_CREATE` events, and dispatch a `PRESENCE_UPDATE` event for this user
 ///
 /// ## Arguments
 ///
 /// * `app` - The shared application state
 /// * `user` - The user to send the `READY` event to
-/// * `ws_sink` - The sink for sending messages to the user
+/// * `session_id` - The ID of the session, which the client needs to resume it
+/// * `resume_token` - The token the client needs to resume the session, if it can be resumed
+/// * `guests` - The access of the user in the guilds they joined as a guest, whose channels are filtered
+/// * `presence_privacy` - The presence privacy settings of the user
+/// * `sender` - The sender of the user's connection handle
 async fn send_ready(
     app: App,
     user: User,
-    ws_sink: Arc<Mutex<SplitSink<WebSocket, Message>>>,
-) -> Result<(), axum::Error> {
-    let guilds = app
+    session_id: Snowflake<GatewaySession>,
+    resume_token: Option<String>,
+    guests: HashMap<Snowflake<Guild>, GuestAccess>,
+    presence_privacy: PresencePrivacy,
+    sender: mpsc::UnboundedSender<GatewayResponse>,
+) {
+    let mut guilds = app
         .ops()
         .fetch_guilds_for(&user)
         .await
         .expect("Failed to fetch guilds during socket connection handling");
+    guilds.retain(|guild| app.gateway.shard().owns(guild.id()));
+
+    // Like other events without a guild, announcements and guild settings are only sent on the first shard
+    let (announcements, guild_settings) = if app.gateway.shard().is_primary() {
+        let announcements = app.ops().fetch_active_announcements().await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Failed to fetch announcements during socket connection handling");
+            Vec::new()
+        });
+        let guild_settings = app.guilds().fetch_settings(user.id()).await.unwrap_or_else(|e| {
+            tracing::error!(error = %e, "Failed to fetch guild settings during socket connection handling");
+            GuildSettings::default()
+        });
+        (announcements, guild_settings)
+    } else {
+        (Vec::new(), GuildSettings::default())
+    };
+
+    let queue = |event: GatewayEvent| {
+        sender.send(GatewayResponse::Event {
+            id: app.gateway.next_event_id(),
+            event: Arc::new(event),
+            queued_at: Instant::now(),
+        })
+    };
 
     // Send READY
-    send_serializable(
-        &mut *ws_sink.lock().await,
-        GatewayEvent::Ready(ReadyPayload::new(user.clone(), guilds.clone())),
-    )
-    .await?;
+    let ready = ReadyPayload::new(
+        session_id,
+        resume_token,
+        user.clone(),
+        guilds.clone(),
+        presence_privacy,
+        announcements,
+        guild_settings,
+    );
+    if queue(GatewayEvent::Ready(ready)).is_err() {
+        return;
+    }
 
     // Send GUILD_CREATE events for all guilds the user is in
     for guild in guilds {
-        let payload = GuildCreatePayload::from_guild(&app, guild)
+        let guest = guests.get(&guild.id());
+        let mut payload = GuildCreatePayload::from_guild(&app, guild)
             .await
             .expect("Failed to fetch guild payload data");
+        if let Some(guest) = guest {
+            payload = payload.for_guest(guest);
+        }
 
-        send_serializable(&mut *ws_sink.lock().await, GatewayEvent::GuildCreate(payload)).await?;
+        if queue(GatewayEvent::GuildCreate(payload)).is_err() {
+            return;
+        }
     }
 
     // Send the presence update for the user if they were not invisible when last logging off
-    match user.last_presence() {
-        Presence::Offline => {}
-        _ => {
-            app.gateway
-                .dispatch(GatewayEvent::PresenceUpdate(PresenceUpdatePayload {
-                    user_id: user.id(),
-                    presence: *user.last_presence(),
-                }));
-        }
+    // Sharded clients connect to every shard, so presence is only announced by the first one
+    if app.gateway.shard().is_primary() {
+        app.presences().announce_online(&user);
     }
-    Ok(())
 }
 
-/// Forward events received through the `ConnectionHandle` receiver to the user
+/// How a websocket connection serving a session ended
+enum ConnectionEnd {
+    /// The connection was lost, the client may resume the session on a new connection
+    Lost,
+    /// The session was closed with the given code and cannot be resumed
+    Closed(GatewayCloseCode),
+    /// The client resumed the session on a new connection, which replaces this one after it was closed
+    Resumed(Box<WebSocket>, u64),
+}
+
+/// Forward events received through the `ConnectionHandle` receiver to the user,
+/// and periodically ping the user to detect dead connections
+///
+/// Events are numbered and kept until the client acknowledges them, so they can be sent again if the connection is lost.
 ///
 /// ## Arguments
 ///
 /// * `user_id` - The ID of the user to send events to
 /// * `receiver` - The receiver for incoming gateway responses to send
+/// * `data_saver` - Whether events are reduced before they are sent, see [`GatewayResponse::shape`]
+/// * `buffer` - The events sent to the session that the client did not acknowledge yet
 /// * `ws_sink` - The sink for sending messages to the user
+/// * `ping_interval` - The interval at which websocket pings are sent to the user
+///
+/// ## Returns
+///
+/// How the connection ended
 async fn send_events(
     user_id: Snowflake<User>,
-    mut receiver: UnboundedReceiverStream<GatewayResponse>,
-    ws_sink: Arc<Mutex<SplitSink<WebSocket, Message>>>,
-) -> Result<GatewayCloseCode, axum::Error> {
-    while let Some(payload) = receiver.next().await {
-        match payload {
-            GatewayResponse::Close(code, reason) => {
-                send_close_frame(&mut *ws_sink.lock().await, code, reason).await.ok();
-                return Ok(code);
-            }
-            GatewayResponse::Event(event) => {
-                if let Err(e) = send_serializable(&mut *ws_sink.lock().await, event).await {
-                    tracing::warn!(error = %e, "Error sending event to user {user_id}: {e}");
-                    return Err(e);
+    receiver: &mut mpsc::UnboundedReceiver<GatewayResponse>,
+    data_saver: &AtomicBool,
+    buffer: &StdMutex<ReplayBuffer>,
+    ws_sink: &Mutex<SplitSink<WebSocket, Message>>,
+    ping_interval: Duration,
+) -> ConnectionEnd {
+    let mut ping = tokio::time::interval_at(Instant::now() + ping_interval, ping_interval);
+    ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let response = tokio::select! {
+            response = receiver.recv() => {
+                let Some(response) = response else {
+                    return ConnectionEnd::Closed(GatewayCloseCode::Normal);
+                };
+                response
+            }
+            _ = ping.tick() => {
+                if let Err(e) = ws_sink.lock().await.send(Message::Ping(Vec::new())).await {
+                    tracing::debug!(error = %e, "Error sending ping to user {user_id}: {e}");
+                    return ConnectionEnd::Lost;
                 }
+                continue;
             }
+        };
+
+        let response = response.shape(data_saver.load(Ordering::Relaxed));
+        if let GatewayResponse::Close(payload) = response {
+            let code = payload.code();
+            close_session(&mut *ws_sink.lock().await, payload).await.ok();
+            return ConnectionEnd::Closed(code);
+        }
+
+        // The event is kept before it is sent, in case it is lost along with the connection
+        let payload = buffer
+            .lock()
+            .expect("Lock should not be poisoned")
+            .push(|seq| response.encode(Some(seq)));
+        if let Err(e) = ws_sink.lock().await.send(Message::Text(payload.to_string())).await {
+            tracing::warn!(error = %e, "Error sending event to user {user_id}: {e}");
+            return ConnectionEnd::Lost;
         }
+        response.observe_lag();
     }
-    Ok(GatewayCloseCode::Normal)
+}
+
+/// Record the time it took for an event to be written to a client's socket after it was queued
+pub(super) fn observe_lag(event: &str, queued_at: Instant) {
+    metrics::GATEWAY_EVENT_LAG
+        .with_label_values(&[event])
+        .observe(queued_at.elapsed().as_secs_f64());
+}
+
+/// Whether reading from a socket failed because the client sent a message larger than the configured limit
+fn is_too_large(error: &axum::Error) -> bool {
+    error
+        .source()
+        .and_then(|source| source.downcast_ref::<tungstenite::Error>())
+        .is_some_and(|error| matches!(error, tungstenite::Error::Capacity(_)))
+}
+
+/// Close a connection after the client sent a message larger than the configured limit
+///
+/// The rest of the message is never read, so the connection is kept open for a moment before it is dropped.
+/// Otherwise the unread data makes the OS reset the connection, and the client may never see the close frame.
+///
+/// ## Arguments
+///
+/// * `ws_sink` - The sink for sending messages to the client
+/// * `max_size` - The maximum size of a payload in bytes
+async fn close_too_large(ws_sink: &mut SplitSink<WebSocket, Message>, max_size: usize) -> Result<(), axum::Error> {
+    let result = close_session(
+        ws_sink,
+        InvalidSessionPayload::new(
+            GatewayCloseCode::TooLarge,
+            format!("Payload exceeds the maximum size of {max_size} bytes"),
+        ),
+    )
+    .await;
+    tokio::time::sleep(TOO_LARGE_CLOSE_LINGER).await;
+    result
 }
 
 /// Parse & forward events received through the socket to the `ConnectionHandle` sender
 ///
+/// Closes the connection if the user does not send anything, including pongs, within `idle_timeout`,
+/// sends a payload larger than the configured limit, or sends too many payloads that cannot be parsed.
+/// The session may be resumed if the connection timed out, broke, or was closed by the client
+/// with a code other than `1000` or `1001`.
+///
 /// ## Arguments
 ///
+/// * `app` - The shared application state
 /// * `user_id` - The ID of the user to receive events for
 /// * `ws_stream` - The stream for receiving messages from the user
 /// * `ws_sink` - The sink for sending messages to the user
+/// * `broadcaster` - The sender to forward parsed gateway messages to
+///
+/// ## Returns
+///
+/// How the connection ended
 async fn receive_events(
+    app: App,
     user_id: Snowflake<User>,
     mut ws_stream: SplitStream<WebSocket>,
     ws_sink: Arc<Mutex<SplitSink<WebSocket, Message>>>,
     broadcaster: Arc<broadcast::Sender<GatewayMessage>>,
-) {
-    while let Some(msg) = ws_stream.next().await {
-        // Close if the user sends a close frame
+) -> ConnectionEnd {
+    let idle_timeout = app.config.gateway_idle_timeout();
+    // Payloads that could not be parsed, the connection is closed once there are too many
+    let mut invalid_payloads = 0;
+
+    loop {
+        let Ok(next) = timeout(idle_timeout, ws_stream.next()).await else {
+            tracing::debug!("Gateway connection of {user_id} timed out");
+            // The sink may be stuck sending to the dead connection, so the close frame is best-effort
+            if let Ok(mut sink) = ws_sink.try_lock() {
+                close_session(
+                    &mut sink,
+                    InvalidSessionPayload::new(GatewayCloseCode::SessionTimeout, "Connection timed out"),
+                )
+                .await
+                .ok();
+            }
+            return ConnectionEnd::Lost;
+        };
+
+        let Some(msg) = next else {
+            return ConnectionEnd::Lost;
+        };
+
+        // Close if the user sends a close frame, only normal closures end the session
         if let Ok(Message::Close(f)) = msg {
             tracing::debug!(close_frame = ?f, "Gateway stream closed by {user_id}: {f:?}");
-            break;
+            return match f.map(|f| GatewayCloseCode::from(f.code)) {
+                Some(code @ (GatewayCloseCode::Normal | GatewayCloseCode::GoingAway)) => ConnectionEnd::Closed(code),
+                _ => ConnectionEnd::Lost,
+            };
+        }
+        // Pings are answered automatically, pongs only serve to keep the connection alive
+        if let Ok(Message::Ping(_) | Message::Pong(_)) = msg {
+            continue;
+        }
+        if matches!(&msg, Err(e) if is_too_large(e)) {
+            tracing::debug!("Gateway payload of {user_id} is too large");
+            close_too_large(&mut *ws_sink.lock().await, app.config.gateway_max_payload_size())
+                .await
+                .ok();
+            return ConnectionEnd::Closed(GatewayCloseCode::TooLarge);
+        }
+        if let Err(e) = &msg {
+            tracing::debug!(error = %e, "Gateway connection of {user_id} failed");
+            return ConnectionEnd::Lost;
         }
         // Otherwise attempt to parse the message and send it
         let Ok(Message::Text(text)) = msg else {
-            send_close_frame(
+            close_session(
                 &mut *ws_sink.lock().await,
-                GatewayCloseCode::Unsupported,
-                "Unsupported message encoding",
+                InvalidSessionPayload::new(GatewayCloseCode::Unsupported, "Unsupported message encoding"),
             )
             .await
             .ok();
-            break;
+            return ConnectionEnd::Closed(GatewayCloseCode::Unsupported);
         };
 
         match serde_json::from_str::<GatewayRequest>(&text) {
@@ -705,112 +2110,532 @@ async fn receive_events(
                 broadcaster.send(msg).ok();
             }
             Err(e) => {
-                send_close_frame(
+                invalid_payloads += 1;
+                tracing::debug!(error = %e, "Invalid gateway payload from {user_id} ({invalid_payloads} so far)");
+                // Clients sending an event added by a newer version of the server keep their session
+                if invalid_payloads < app.config.gateway_max_invalid_payloads() {
+                    continue;
+                }
+                close_session(
                     &mut *ws_sink.lock().await,
-                    GatewayCloseCode::InvalidPayload,
-                    format!("Invalid request payload: {e}"),
+                    InvalidSessionPayload::new(
+                        GatewayCloseCode::InvalidPayload,
+                        format!("Invalid request payload: {e}"),
+                    ),
                 )
                 .await
                 .ok();
-                break;
+                return ConnectionEnd::Closed(GatewayCloseCode::InvalidPayload);
             }
         }
     }
 }
 
-/// Handle a new websocket connection
+/// A gateway session of an identified user, independent of the transport its events are delivered through
+pub(super) struct ActiveSession {
+    /// The user the session belongs to
+    user: User,
+    /// The session as recorded in the database
+    session: GatewaySession,
+    /// Forwards messages sent by the client to the tasks serving the session, this also identifies the session
+    broadcaster: Arc<broadcast::Sender<GatewayMessage>>,
+    /// Queues `READY` and `GUILD_CREATE` for the client, aborted once the session ends
+    send_ready: AbortingJoinHandle<()>,
+    /// Whether events are reduced before they are sent to the client, shared with the connection handle
+    data_saver: Arc<AtomicBool>,
+}
+
+impl ActiveSession {
+    /// The ID of the session
+    pub(super) const fn id(&self) -> Snowflake<GatewaySession> {
+        self.session.id()
+    }
+
+    /// The ID of the user the session belongs to
+    pub(super) const fn user_id(&self) -> Snowflake<User> {
+        self.user.id()
+    }
+
+    /// The sender forwarding messages sent by the client to the tasks serving the session
+    pub(super) const fn broadcaster(&self) -> &Arc<broadcast::Sender<GatewayMessage>> {
+        &self.broadcaster
+    }
+
+    /// Whether events are reduced before they are sent to the client, see [`GatewayResponse::shape`]
+    pub(super) const fn data_saver(&self) -> &Arc<AtomicBool> {
+        &self.data_saver
+    }
+}
+
+/// Register a new session for an identified user, replacing their previous session, and queue `READY` for it
 ///
 /// ## Arguments
 ///
 /// * `app` - The shared application state
-/// * `socket` - The websocket connection to handle
-async fn handle_connection(app: App, socket: WebSocket) {
-    let (mut ws_sink, mut ws_stream) = socket.split();
-    // Handle handshake and get user
-    let Ok(user) = handle_handshake(app.clone(), &mut ws_sink, &mut ws_stream).await else {
-        ws_sink
-            .reunite(ws_stream)
-            .expect("WS sink and stream should be reuniteable")
-            .close()
-            .await
-            .ok();
-        return;
-    };
-
+/// * `user` - The user the session belongs to
+/// * `properties` - The properties of the user's client
+/// * `resume_token` - The token the client needs to resume the session, sent in `READY`, if it can be resumed
+///
+/// ## Returns
+///
+/// The session and the receiver of the responses to send to the client
+pub(super) async fn start_session(
+    app: &App,
+    user: User,
+    properties: ClientProperties,
+    resume_token: Option<String>,
+) -> (ActiveSession, mpsc::UnboundedReceiver<GatewayResponse>) {
     tracing::debug!(?user, "Connected: {} ({})", user.username(), user.id());
 
     let (sender, receiver) = mpsc::unbounded_channel::<GatewayResponse>();
     let (broadcaster, _) = broadcast::channel::<GatewayMessage>(100);
     let broadcaster = Arc::new(broadcaster);
 
-    // turn receiver into a stream for easier handling
-    let receiver = UnboundedReceiverStream::new(receiver);
-
-    let guild_ids = sqlx::query!(
-        "SELECT guild_id FROM members WHERE user_id = $1",
+    let memberships = sqlx::query!(
+        "SELECT guild_id, guest_until, guest_channel_ids FROM members WHERE user_id = $1",
         user.id() as Snowflake<User>
     )
-    .fetch_all(app.db.pool())
+    .fetch_all(app.db.executor())
     .await
     .expect("Failed to fetch guilds during socket connection handling")
     .into_iter()
-    .map(|row| row.guild_id.into())
-    .collect::<HashSet<Snowflake<Guild>>>();
+    .map(|row| {
+        let guild_id: Snowflake<Guild> = row.guild_id.into();
+        (
+            guild_id,
+            GuestAccess::from_columns(row.guest_until, row.guest_channel_ids),
+        )
+    })
+    .filter(|(guild_id, _)| app.gateway.shard().owns(*guild_id))
+    .collect::<Vec<_>>();
+    let guild_ids = memberships
+        .iter()
+        .map(|(guild_id, _)| *guild_id)
+        .collect::<HashSet<_>>();
+    let guests = memberships
+        .into_iter()
+        .filter_map(|(guild_id, guest)| Some((guild_id, guest?)))
+        .collect::<HashMap<_, _>>();
+
+    let presence_privacy = app.ops().fetch_presence_privacy(&user).await.unwrap_or_default();
+    let data_saver = app
+        .ops()
+        .fetch_pref_flags(&user)
+        .await
+        .is_ok_and(|flags| flags.contains(PrefFlags::DATA_SAVER));
+    let data_saver = Arc::new(AtomicBool::new(data_saver));
+
+    let device = properties.device();
+    let session = GatewaySession::new(&app.config, &user, app.gateway.shard(), properties);
 
     // Add user to peermap
     app.gateway.add_handle(
         user.id(),
-        ConnectionHandle::new(sender, broadcaster.clone(), guild_ids.clone()),
+        ConnectionHandle::new(
+            sender.clone(),
+            broadcaster.clone(),
+            guild_ids,
+            *user.last_presence(),
+            presence_privacy.clone(),
+            data_saver.clone(),
+            device,
+        )
+        .with_guests(guests.clone()),
     );
+    metrics::GATEWAY_SESSIONS.with_label_values(&[device.name()]).inc();
+    if let Err(e) = app.ops().create_gateway_session(&session).await {
+        tracing::warn!(error = %e, "Failed to record gateway session of {}", user.id());
+    }
 
     let user = user.include_presence(&app.gateway);
-    let user_id = user.id();
+
+    // Send READY and guild creates to user
+    let send_ready = tokio::spawn(send_ready(
+        app.clone(),
+        user.clone(),
+        session.id(),
+        resume_token,
+        guests,
+        presence_privacy,
+        sender,
+    ))
+    .abort_on_drop();
+
+    let session = ActiveSession {
+        user,
+        session,
+        broadcaster,
+        send_ready,
+        data_saver,
+    };
+    (session, receiver)
+}
+
+/// Clean up after a session ended, announcing the user as offline unless a newer session replaced it
+///
+/// This should not be called if the server is shutting down, to not spam out presence updates.
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `session` - The session that ended
+pub(super) async fn end_session(app: &App, session: ActiveSession) {
+    let ActiveSession {
+        user,
+        session,
+        broadcaster,
+        send_ready,
+        ..
+    } = session;
+    send_ready.abort();
+
+    // Disconnection logic
+    tracing::debug!(?user, "Disconnected: {} ({})", user.username(), user.id());
+
+    if let Err(e) = app.ops().delete_gateway_session(&session).await {
+        tracing::warn!(error = %e, "Failed to remove gateway session of {}", user.id());
+    }
+
+    // The user is still online through the session that replaced this one
+    if !app.gateway.is_current_session(user.id(), &broadcaster) {
+        return;
+    }
+
+    // Send presence update to OFFLINE
+    // This must happen before the handle is removed, as it determines which users share guilds with the user
+    if app.gateway.shard().is_primary() {
+        if let Err(e) = app.presences().announce_offline(&user).await {
+            tracing::warn!(error = %e, "Failed to announce disconnect of {}", user.id());
+        }
+    }
+
+    app.gateway.remove_session(user.id(), &broadcaster);
+}
+
+/// Serve a session over a websocket connection until the connection ends or the client resumes the session elsewhere
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `session` - The session to serve
+/// * `ws_sink` - The sink for sending messages to the client
+/// * `ws_stream` - The stream for receiving messages from the client
+/// * `receiver` - The receiver of the responses to send to the client
+/// * `buffer` - The events sent to the session that the client did not acknowledge yet
+/// * `resumes` - Receives the connections the client resumes the session on
+///
+/// ## Returns
+///
+/// How the connection ended
+async fn serve_connection(
+    app: &App,
+    session: &ActiveSession,
+    ws_sink: SplitSink<WebSocket, Message>,
+    ws_stream: SplitStream<WebSocket>,
+    receiver: &mut mpsc::UnboundedReceiver<GatewayResponse>,
+    buffer: &Arc<StdMutex<ReplayBuffer>>,
+    resumes: &mut mpsc::UnboundedReceiver<(Box<WebSocket>, u64)>,
+) -> ConnectionEnd {
+    let user_id = session.user_id();
 
     // We want to use the same sink in multiple tasks, so we wrap it in an Arc<Mutex>
     let ws_sink = Arc::new(Mutex::new(ws_sink));
 
-    // Send READY and guild creates to user
-    let send_ready = tokio::spawn(send_ready(app.clone(), user.clone(), ws_sink.clone()));
-
     // The tasks need to be dropped when their joinhandles are dropped by select!
-    let send_events = tokio::spawn(send_events(user_id, receiver, ws_sink.clone())).abort_on_drop();
-    let receive_events = tokio::spawn(receive_events(user_id, ws_stream, ws_sink, broadcaster)).abort_on_drop();
-    let handle_heartbeat = tokio::spawn(handle_heartbeating(
+    let receive_events = tokio::spawn(receive_events(
         app.clone(),
         user_id,
-        Duration::from_millis(HEARTBEAT_INTERVAL),
+        ws_stream,
+        ws_sink.clone(),
+        session.broadcaster().clone(),
+    ))
+    .abort_on_drop();
+    let handle_heartbeat = tokio::spawn(handle_heartbeating(
+        session.broadcaster().subscribe(),
+        ws_sink.clone(),
+        buffer.clone(),
+        app.config.gateway_heartbeat_interval(),
     ))
     .abort_on_drop();
 
-    let is_server_shutting_down = tokio::select! {
-        res = send_events => { matches!(res, Ok(Ok(GatewayCloseCode::GoingAway))) },
-        _ = receive_events => { false },
-        _ = handle_heartbeat => { false },
-    };
+    tokio::select! {
+        end = send_events(
+            user_id,
+            receiver,
+            session.data_saver(),
+            buffer,
+            &ws_sink,
+            app.config.gateway_ping_interval(),
+        ) => end,
+        end = receive_events => end.unwrap_or(ConnectionEnd::Closed(GatewayCloseCode::ServerError)),
+        payload = handle_heartbeat => {
+            let Ok(payload) = payload else {
+                return ConnectionEnd::Closed(GatewayCloseCode::ServerError);
+            };
+            let code = payload.code();
+            // The sink may be stuck sending to the dead connection, so the close frame is best-effort
+            if let Ok(mut sink) = ws_sink.try_lock() {
+                close_session(&mut sink, payload).await.ok();
+            }
+            // Missing heartbeats most likely mean that the connection was lost
+            if code == GatewayCloseCode::SessionTimeout {
+                ConnectionEnd::Lost
+            } else {
+                ConnectionEnd::Closed(code)
+            }
+        },
+        Some((socket, seq)) = resumes.recv() => {
+            // The client sent a valid resume token, so this connection most likely died without being noticed yet
+            if let Ok(mut sink) = timeout(RESUMED_ELSEWHERE_CLOSE_TIMEOUT, ws_sink.lock()).await {
+                close_session(
+                    &mut sink,
+                    InvalidSessionPayload::new(GatewayCloseCode::SessionReplaced, "Session resumed on another connection"),
+                )
+                .await
+                .ok();
+            }
+            ConnectionEnd::Resumed(socket, seq)
+        },
+    }
+}
 
-    send_ready.abort();
+/// Keep a session whose connection was lost until the client resumes it,
+/// numbering and keeping the events sent to the session meanwhile
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `session` - The session whose connection was lost
+/// * `receiver` - The receiver of the responses to send to the client
+/// * `buffer` - The events sent to the session that the client did not acknowledge yet
+/// * `resumes` - Receives the connections the client resumes the session on
+///
+/// ## Returns
+///
+/// The new connection and the last sequence number the client received,
+/// or the code the session was closed with if it was not resumed in time
+async fn wait_for_resume(
+    app: &App,
+    session: &ActiveSession,
+    receiver: &mut mpsc::UnboundedReceiver<GatewayResponse>,
+    buffer: &StdMutex<ReplayBuffer>,
+    resumes: &mut mpsc::UnboundedReceiver<(Box<WebSocket>, u64)>,
+) -> Result<(Box<WebSocket>, u64), GatewayCloseCode> {
+    let deadline = sleep(app.config.gateway_resume_timeout());
+    tokio::pin!(deadline);
 
-    // If we're shutting down, don't spam out presence updates
-    if is_server_shutting_down {
-        return;
+    loop {
+        tokio::select! {
+            () = &mut deadline => return Err(GatewayCloseCode::SessionTimeout),
+            Some(resume) = resumes.recv() => return Ok(resume),
+            response = receiver.recv() => {
+                let Some(response) = response else {
+                    return Err(GatewayCloseCode::Normal);
+                };
+                let response = response.shape(session.data_saver().load(Ordering::Relaxed));
+                if let GatewayResponse::Close(payload) = response {
+                    return Err(payload.code());
+                }
+                buffer
+                    .lock()
+                    .expect("Lock should not be poisoned")
+                    .push(|seq| response.encode(Some(seq)));
+            }
+        }
     }
+}
 
-    // Disconnection logic
-    app.gateway.remove_handle(user.id());
-    tracing::debug!(?user, "Disconnected: {} ({})", user.username(), user.id());
+/// Send the events a session missed to the connection the client resumed it on, followed by `RESUMED`,
+/// then serve the session over the connection
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `session` - The session to resume
+/// * `socket` - The connection the client resumed the session on
+/// * `seq` - The last sequence number the client received
+/// * `receiver` - The receiver of the responses to send to the client
+/// * `buffer` - The events sent to the session that the client did not acknowledge yet
+/// * `resumes` - Receives the connections the client resumes the session on
+///
+/// ## Returns
+///
+/// How the connection ended
+async fn resume_connection(
+    app: &App,
+    session: &ActiveSession,
+    socket: WebSocket,
+    seq: u64,
+    receiver: &mut mpsc::UnboundedReceiver<GatewayResponse>,
+    buffer: &Arc<StdMutex<ReplayBuffer>>,
+    resumes: &mut mpsc::UnboundedReceiver<(Box<WebSocket>, u64)>,
+) -> ConnectionEnd {
+    let (mut ws_sink, ws_stream) = socket.split();
+
+    let missed = {
+        let mut buffer = buffer.lock().expect("Lock should not be poisoned");
+        buffer.ack(seq);
+        buffer.since(seq)
+    };
+    let Some(missed) = missed else {
+        tracing::debug!(
+            "Session {} of {} missed too many events to resume",
+            session.id(),
+            session.user_id()
+        );
+        close_session(
+            &mut ws_sink,
+            InvalidSessionPayload::new(GatewayCloseCode::ResumeFailed, "Too many events were missed"),
+        )
+        .await
+        .ok();
+        return ConnectionEnd::Closed(GatewayCloseCode::ResumeFailed);
+    };
+
+    for payload in missed {
+        if ws_sink.send(Message::Text(payload.to_string())).await.is_err() {
+            return ConnectionEnd::Lost;
+        }
+    }
+    let resume_token = new_resume_token();
+    if send_serializable(
+        &mut ws_sink,
+        GatewayEvent::Resumed(ResumedPayload::new(resume_token.clone())),
+    )
+    .await
+    .is_err()
+    {
+        return ConnectionEnd::Lost;
+    }
+    app.gateway.replace_resume_token(session.id(), &resume_token);
+
+    serve_connection(app, session, ws_sink, ws_stream, receiver, buffer, resumes).await
+}
 
-    // Refetch presence in case it changed
-    let presence = app.ops().fetch_presence(&user).await.expect("Failed to fetch presence");
+/// Generate a new random token, which the client needs to resume a session
+fn new_resume_token() -> String {
+    hex::encode(rand::thread_rng().gen::<[u8; 32]>())
+}
 
-    // Send presence update to OFFLINE
-    match presence {
-        Presence::Offline => {}
-        _ => {
-            app.gateway
-                .dispatch(GatewayEvent::PresenceUpdate(PresenceUpdatePayload {
-                    user_id: user.id(),
-                    presence: Presence::Offline,
-                }));
+/// Create a MAC keyed with a resume token, so tokens can be compared in constant time
+fn resume_token_mac(token: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(token.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(b"chat-gateway-resume");
+    mac
+}
+
+/// Tell a client that the session it sent in `RESUME` cannot be resumed, and close the connection
+async fn reject_resume(socket: WebSocket) {
+    let (mut ws_sink, ws_stream) = socket.split();
+    close_session(
+        &mut ws_sink,
+        InvalidSessionPayload::new(GatewayCloseCode::ResumeFailed, "Session cannot be resumed"),
+    )
+    .await
+    .ok();
+    ws_sink
+        .reunite(ws_stream)
+        .expect("WS sink and stream should be reuniteable")
+        .close()
+        .await
+        .ok();
+}
+
+/// Serve a new websocket session until it ends
+///
+/// If the connection is lost, the session is kept for the configured resume timeout,
+/// so the client can resume it on a new connection without missing any events.
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `user` - The user the client identified as
+/// * `properties` - The properties of the user's client
+/// * `socket` - The connection the client identified on
+async fn serve_session(app: App, user: User, properties: ClientProperties, socket: WebSocket) {
+    let resume_token = new_resume_token();
+    let (session, mut receiver) = start_session(&app, user, properties, Some(resume_token.clone())).await;
+    let buffer = Arc::new(StdMutex::new(ReplayBuffer::new(
+        app.config.gateway_replay_buffer_size(),
+    )));
+    let (sender, mut resumes) = mpsc::unbounded_channel();
+    app.gateway.add_resumable(
+        session.id(),
+        ResumeHandle {
+            user_id: session.user_id(),
+            token: resume_token_mac(&resume_token).finalize().into_bytes().to_vec(),
+            sender,
+        },
+    );
+    // Requests keep being handled while the session waits to be resumed
+    let _handle_requests = tokio::spawn(handle_requests(
+        app.clone(),
+        session.user_id(),
+        session.broadcaster().subscribe(),
+    ))
+    .abort_on_drop();
+
+    let (ws_sink, ws_stream) = socket.split();
+    let mut end = serve_connection(&app, &session, ws_sink, ws_stream, &mut receiver, &buffer, &mut resumes).await;
+    let code = loop {
+        let (socket, seq) = match end {
+            ConnectionEnd::Closed(code) => break code,
+            ConnectionEnd::Resumed(socket, seq) => (socket, seq),
+            ConnectionEnd::Lost => match wait_for_resume(&app, &session, &mut receiver, &buffer, &mut resumes).await {
+                Ok(resume) => resume,
+                Err(code) => break code,
+            },
+        };
+        tracing::debug!("Resuming session {} of {}", session.id(), session.user_id());
+        end = resume_connection(&app, &session, *socket, seq, &mut receiver, &buffer, &mut resumes).await;
+    };
+
+    app.gateway.remove_resumable(session.id());
+    // Clients that tried to resume while the session ended need to identify instead
+    resumes.close();
+    while let Ok((socket, _)) = resumes.try_recv() {
+        reject_resume(*socket).await;
+    }
+
+    // If we're shutting down, don't spam out presence updates
+    if code != GatewayCloseCode::ServerRestart {
+        end_session(&app, session).await;
+    }
+}
+
+/// Handle a new websocket connection
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `socket` - The websocket connection to handle
+/// * `session_token` - The token of the session cookie sent with the upgrade request, if any
+async fn handle_connection(app: App, socket: WebSocket, session_token: Option<Secret<String>>) {
+    let (mut ws_sink, mut ws_stream) = socket.split();
+    // Handle handshake and get user
+    let handshake = handle_handshake(app.clone(), &mut ws_sink, &mut ws_stream, session_token).await;
+    let socket = ws_sink
+        .reunite(ws_stream)
+        .expect("WS sink and stream should be reuniteable");
+
+    match handshake {
+        Ok(Handshake::Identify(user, properties)) => serve_session(app, *user, properties, socket).await,
+        Ok(Handshake::Resume {
+            user_id,
+            session_id,
+            resume_token,
+            seq,
+        }) => {
+            // The task serving the session takes over the connection
+            if let Err(socket) = app
+                .gateway
+                .resume(session_id, user_id, &resume_token, Box::new(socket), seq)
+            {
+                reject_resume(*socket).await;
+            }
+        }
+        Err(_) => {
+            socket.close().await.ok();
         }
     }
 }
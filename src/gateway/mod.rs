@@ -1,2 +1,11 @@
+mod delivery;
 pub mod handler;
 // pub mod handler_v2;
+mod polling;
+mod shaping;
+mod sse;
+#[cfg(test)]
+pub mod testkit;
+
+#[cfg(test)]
+mod conformance;
@@ -0,0 +1,362 @@
+//! A fallback transport for the gateway over HTTP long-polling, for clients behind networks that do not allow websockets.
+//!
+//! A session is started by sending `IDENTIFY` to `POST /gateway/v1/poll`, which returns a session key.
+//! Clients then repeatedly call `GET /gateway/v1/poll?session=...`, which drains the same queue a websocket
+//! connection would send from, and send messages with `POST /gateway/v1/poll/messages?session=...`.
+//! Polling keeps the session alive, so `HEARTBEAT` is not needed.
+//!
+//! Events are numbered like on a websocket connection. Polls may pass the `seq` of the last event received,
+//! in which case the events after it are returned again, so a poll whose response was lost does not lose events.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex as StdMutex,
+    },
+    time::Duration,
+};
+
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{
+    sync::{broadcast, mpsc, watch, Mutex},
+    time::{sleep_until, timeout, Instant},
+};
+
+use super::{
+    delivery::ReplayBuffer,
+    handler::{
+        check_origin, cookie_token, end_session, handle_requests, identify as identify_user, start_session,
+        ActiveSession, GatewayResponse,
+    },
+};
+use crate::{
+    models::{
+        close_code::GatewayCloseCode,
+        errors::RESTError,
+        gateway_event::{GatewayEvent, GatewayMessage, InvalidSessionPayload},
+        state::App,
+    },
+    utils::join_handle::JoinHandleExt,
+};
+
+/// The maximum amount of events returned by a single poll
+const MAX_POLL_EVENTS: usize = 100;
+
+/// A gateway session whose events are delivered through long-polling
+#[derive(Debug)]
+pub struct PollSession {
+    /// The responses queued for the client, locked while a poll is in progress
+    receiver: Mutex<mpsc::UnboundedReceiver<GatewayResponse>>,
+    /// Forwards messages sent by the client to the tasks serving the session
+    broadcaster: Arc<broadcast::Sender<GatewayMessage>>,
+    /// Whether events are reduced before they are returned, shared with the connection handle
+    data_saver: Arc<AtomicBool>,
+    /// The events returned that the client did not acknowledge yet
+    buffer: StdMutex<ReplayBuffer>,
+    /// When the client last polled or sent a message
+    last_seen: StdMutex<Instant>,
+    /// Set to the close code once the session was closed and the client was told so, if it polled
+    closed: watch::Sender<Option<GatewayCloseCode>>,
+}
+
+impl PollSession {
+    fn new(
+        receiver: mpsc::UnboundedReceiver<GatewayResponse>,
+        broadcaster: Arc<broadcast::Sender<GatewayMessage>>,
+        data_saver: Arc<AtomicBool>,
+        buffer_size: usize,
+    ) -> Self {
+        Self {
+            receiver: Mutex::new(receiver),
+            broadcaster,
+            data_saver,
+            buffer: StdMutex::new(ReplayBuffer::new(buffer_size)),
+            last_seen: StdMutex::new(Instant::now()),
+            closed: watch::channel(None).0,
+        }
+    }
+
+    /// Record that the client is still around
+    fn touch(&self) {
+        *self.last_seen.lock().expect("Lock should not be poisoned") = Instant::now();
+    }
+
+    /// End the session with the given code
+    fn close(&self, code: GatewayCloseCode) {
+        self.closed.send_replace(Some(code));
+    }
+
+    /// Wait until the client neither polled nor sent a message within the idle timeout
+    ///
+    /// ## Arguments
+    ///
+    /// * `idle_timeout` - How long the client may stay away between polls
+    async fn expired(&self, idle_timeout: Duration) {
+        loop {
+            let last_seen = *self.last_seen.lock().expect("Lock should not be poisoned");
+            sleep_until(last_seen + idle_timeout).await;
+
+            // A poll in progress keeps the session alive
+            if self.receiver.try_lock().is_err() {
+                self.touch();
+            } else if *self.last_seen.lock().expect("Lock should not be poisoned") == last_seen {
+                return;
+            }
+        }
+    }
+}
+
+/// The query of requests to a long-polling session
+#[derive(Deserialize)]
+pub(super) struct SessionQuery {
+    /// The key returned when the session was started
+    session: String,
+    /// The sequence number of the last event the client received
+    seq: Option<u64>,
+}
+
+/// Find the long-polling session with the given key
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the session does not exist or already ended
+fn find_session(app: &App, key: &str) -> Result<Arc<PollSession>, RESTError> {
+    app.gateway
+        .poll_session(key)
+        .filter(|session| session.closed.borrow().is_none())
+        .ok_or_else(|| RESTError::NotFound("Unknown or expired gateway session".into()))
+}
+
+/// Start a new gateway session served through long-polling.
+///
+/// If the session is rejected, the response carries the `INVALID_SESSION` event a websocket client would receive.
+///
+/// ## Endpoint
+///
+/// POST `/gateway/v1/poll`
+///
+/// ## Errors
+///
+/// * [`RESTError::Forbidden`] - If the origin of the request is not allowed
+/// * [`RESTError::BadRequest`] - If the payload is not an `IDENTIFY` message
+pub(super) async fn identify(
+    State(app): State<App>,
+    headers: HeaderMap,
+    Json(message): Json<GatewayMessage>,
+) -> Result<Response, RESTError> {
+    check_origin(&app.config, &headers)?;
+
+    let GatewayMessage::Identify(payload) = message else {
+        return Err(RESTError::BadRequest("Expected an IDENTIFY message".into()));
+    };
+
+    let (user, properties) = match identify_user(&app, payload, cookie_token(&app.config, &headers)).await {
+        Ok(identified) => identified,
+        Err(payload) => {
+            let status = match payload.code() {
+                GatewayCloseCode::AuthenticationFailed => StatusCode::UNAUTHORIZED,
+                GatewayCloseCode::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            return Ok((status, Json(GatewayEvent::InvalidSession(payload))).into_response());
+        }
+    };
+
+    let (session, receiver) = start_session(&app, user, properties, None).await;
+    let poll_session = Arc::new(PollSession::new(
+        receiver,
+        session.broadcaster().clone(),
+        session.data_saver().clone(),
+        app.config.gateway_replay_buffer_size(),
+    ));
+    let key = hex::encode(rand::thread_rng().gen::<[u8; 32]>());
+    app.gateway.add_poll_session(key.clone(), poll_session.clone());
+    tokio::spawn(run_session(app.clone(), key.clone(), poll_session, session));
+
+    Ok(Json(json!({
+        "session": key,
+        "poll_timeout": app.config.gateway_poll_timeout().as_millis() as u64,
+        "idle_timeout": app.config.gateway_idle_timeout().as_millis() as u64,
+    }))
+    .into_response())
+}
+
+/// Wait for events queued for a long-polling session and return them as a JSON array.
+///
+/// Returns as soon as at least one event is queued, or with an empty array once the poll timeout passes.
+/// If the session was closed, the last event returned is `INVALID_SESSION`.
+///
+/// If `seq` is given, the events after it are returned again before any new events. If some of them
+/// are no longer kept, the session is closed and only `INVALID_SESSION` with code `4008` is returned.
+///
+/// ## Endpoint
+///
+/// GET `/gateway/v1/poll?session=...&seq=...`
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the session does not exist or already ended
+/// * [`RESTError::BadRequest`] - If another poll of the session is in progress
+pub(super) async fn poll(State(app): State<App>, Query(query): Query<SessionQuery>) -> Result<Response, RESTError> {
+    let session = find_session(&app, &query.session)?;
+    let Ok(mut receiver) = session.receiver.try_lock() else {
+        return Err(RESTError::BadRequest(
+            "Another poll of this session is in progress".into(),
+        ));
+    };
+    session.touch();
+
+    let missed = query.seq.map(|seq| {
+        let mut buffer = session.buffer.lock().expect("Lock should not be poisoned");
+        buffer.ack(seq);
+        buffer.since(seq)
+    });
+    let mut events: Vec<Arc<str>> = match missed {
+        Some(Some(missed)) => missed,
+        Some(None) => {
+            let payload = InvalidSessionPayload::new(GatewayCloseCode::ResumeFailed, "Too many events were missed");
+            session.close(payload.code());
+            return Ok(Json([GatewayEvent::InvalidSession(payload)]).into_response());
+        }
+        None => Vec::new(),
+    };
+
+    // Events the client missed are returned right away
+    let mut next = if events.is_empty() {
+        timeout(app.config.gateway_poll_timeout(), receiver.recv())
+            .await
+            .ok()
+            .flatten()
+    } else {
+        receiver.try_recv().ok()
+    };
+
+    while let Some(response) = next {
+        let response = response.shape(session.data_saver.load(Ordering::Relaxed));
+        if let GatewayResponse::Close(payload) = &response {
+            events.push(response.encode(None).into());
+            session.close(payload.code());
+            break;
+        }
+        events.push(
+            session
+                .buffer
+                .lock()
+                .expect("Lock should not be poisoned")
+                .push(|seq| response.encode(Some(seq))),
+        );
+        response.observe_lag();
+
+        if events.len() >= MAX_POLL_EVENTS {
+            break;
+        }
+        next = receiver.try_recv().ok();
+    }
+
+    drop(receiver);
+    session.touch();
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/json")],
+        format!("[{}]", events.join(",")),
+    )
+        .into_response())
+}
+
+/// Send a message to the gateway through a long-polling session, such as `SUBSCRIBE` or `UPDATE_ACTIVITY`.
+///
+/// ## Endpoint
+///
+/// POST `/gateway/v1/poll/messages?session=...`
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the session does not exist or already ended
+/// * [`RESTError::BadRequest`] - If the message is an `IDENTIFY` or `RESUME` message
+pub(super) async fn send_message(
+    State(app): State<App>,
+    Query(query): Query<SessionQuery>,
+    Json(message): Json<GatewayMessage>,
+) -> Result<StatusCode, RESTError> {
+    let session = find_session(&app, &query.session)?;
+    if matches!(message, GatewayMessage::Identify(_) | GatewayMessage::Resume(_)) {
+        return Err(RESTError::BadRequest("The session is already identified".into()));
+    }
+    if let GatewayMessage::Heartbeat(Some(payload)) = &message {
+        session
+            .buffer
+            .lock()
+            .expect("Lock should not be poisoned")
+            .ack(payload.seq);
+    }
+
+    session.touch();
+    session.broadcaster.send(message).ok();
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// End a long-polling session.
+///
+/// ## Endpoint
+///
+/// DELETE `/gateway/v1/poll?session=...`
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the session does not exist or already ended
+pub(super) async fn close(State(app): State<App>, Query(query): Query<SessionQuery>) -> Result<StatusCode, RESTError> {
+    find_session(&app, &query.session)?.close(GatewayCloseCode::Normal);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Serve a long-polling session until it is closed or the client stops polling
+///
+/// ## Arguments
+///
+/// * `app` - The shared application state
+/// * `key` - The key of the session
+/// * `poll_session` - The session
+/// * `session` - The session state shared with the websocket transport
+async fn run_session(app: App, key: String, poll_session: Arc<PollSession>, session: ActiveSession) {
+    let idle_timeout = app.config.gateway_idle_timeout();
+    let mut closed = poll_session.closed.subscribe();
+    let handle_requests = tokio::spawn(handle_requests(
+        app.clone(),
+        session.user_id(),
+        session.broadcaster().subscribe(),
+    ))
+    .abort_on_drop();
+
+    let mut closed_by_server = false;
+    let code = tokio::select! {
+        code = closed.wait_for(Option::is_some) => code.ok().and_then(|code| *code),
+        () = poll_session.expired(idle_timeout) => None,
+        _ = handle_requests => {
+            closed_by_server = true;
+            None
+        },
+    };
+
+    // The session was closed by the server, give the client the chance to receive INVALID_SESSION
+    if closed_by_server {
+        tokio::select! {
+            _ = closed.wait_for(Option::is_some) => {},
+            () = poll_session.expired(idle_timeout) => {},
+        }
+    }
+
+    app.gateway.remove_poll_session(&key);
+
+    // If we're shutting down, don't spam out presence updates
+    if code != Some(GatewayCloseCode::ServerRestart) {
+        end_session(&app, session).await;
+    }
+}
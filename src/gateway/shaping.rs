@@ -0,0 +1,137 @@
+//! Reduces the payloads of gateway events sent to sessions of users who enabled the `DATA_SAVER` preference flag.
+//!
+//! Events are shaped right before they are written to the client, so the events dispatched to other users,
+//! and the serialized payloads shared between connections, are not affected.
+
+use serde_json::Value;
+
+use crate::models::gateway_event::GatewayEvent;
+
+/// The events whose payloads are reduced in data saver mode
+const SHAPED_EVENTS: [&str; 3] = ["MESSAGE_CREATE", "MESSAGE_UPDATE", "GUILD_CREATE"];
+/// Guilds with more members than this are sent without their members in data saver mode, like large guilds
+pub(super) const DATA_SAVER_MEMBER_LIMIT: usize = 50;
+
+/// Reduce the payload of an event for a session in data saver mode
+///
+/// ## Returns
+///
+/// The reduced payload, or `None` if the event is sent unchanged
+pub(super) fn shape_event(event: &GatewayEvent) -> Option<String> {
+    if !SHAPED_EVENTS.contains(&event.name()) {
+        return None;
+    }
+    let mut value = serde_json::to_value(event).ok()?;
+    shape_value(event.name(), &mut value);
+    serde_json::to_string(&value).ok()
+}
+
+/// Reduce an already serialized event payload for a session in data saver mode
+///
+/// ## Returns
+///
+/// The reduced payload, or `None` if the event is sent unchanged
+pub(super) fn shape_payload(event: &str, payload: &str) -> Option<String> {
+    if !SHAPED_EVENTS.contains(&event) {
+        return None;
+    }
+    let mut value = serde_json::from_str(payload).ok()?;
+    shape_value(event, &mut value);
+    serde_json::to_string(&value).ok()
+}
+
+/// Reduce the `data` of a deserialized event in place
+fn shape_value(event: &str, value: &mut Value) {
+    let Some(data) = value.get_mut("data") else {
+        return;
+    };
+    if event == "GUILD_CREATE" {
+        shape_guild(data);
+    } else {
+        shape_message(data);
+    }
+}
+
+/// Leave out the embeds of a message, and the blurhash previews of its attachments
+fn shape_message(message: &mut Value) {
+    if let Some(embeds) = message.get_mut("embeds").and_then(Value::as_array_mut) {
+        embeds.clear();
+    }
+    let attachments = message.get_mut("attachments").and_then(Value::as_array_mut);
+    for attachment in attachments.into_iter().flatten().filter_map(Value::as_object_mut) {
+        attachment.remove("blurhash");
+    }
+}
+
+/// Leave out the members of a guild with more than [`DATA_SAVER_MEMBER_LIMIT`] members,
+/// clients request them on demand as for large guilds
+fn shape_guild(payload: &mut Value) {
+    let Some(members) = payload.get_mut("members").and_then(Value::as_array_mut) else {
+        return;
+    };
+    if members.len() > DATA_SAVER_MEMBER_LIMIT {
+        members.clear();
+        payload["large"] = Value::Bool(true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{shape_payload, DATA_SAVER_MEMBER_LIMIT};
+
+    fn shape(event: &str, payload: &Value) -> Option<Value> {
+        shape_payload(event, &payload.to_string()).map(|shaped| serde_json::from_str(&shaped).expect("Should parse"))
+    }
+
+    #[test]
+    fn test_shape_message() {
+        let payload = json!({
+            "event": "MESSAGE_CREATE",
+            "data": {
+                "id": "1",
+                "content": "Hello",
+                "embeds": [{"title": "Feed entry"}],
+                "attachments": [{"id": 0, "filename": "cat.png", "width": 64, "height": 64, "blurhash": "LKO2?U%2Tw=w"}],
+            },
+        });
+
+        let shaped = shape("MESSAGE_CREATE", &payload).expect("Message events should be shaped");
+        assert_eq!(shaped["data"]["content"], "Hello");
+        assert_eq!(shaped["data"]["embeds"], json!([]));
+        assert_eq!(
+            shaped["data"]["attachments"],
+            json!([{"id": 0, "filename": "cat.png", "width": 64, "height": 64}])
+        );
+    }
+
+    #[test]
+    fn test_shape_guild() {
+        let members = |count: usize| {
+            (0..count)
+                .map(|id| json!({"user": {"id": id.to_string()}}))
+                .collect::<Vec<_>>()
+        };
+        let payload = |count: usize| {
+            json!({
+                "event": "GUILD_CREATE",
+                "data": {"guild": {"id": "1"}, "members": members(count), "member_count": count, "large": false},
+            })
+        };
+
+        let shaped = shape("GUILD_CREATE", &payload(DATA_SAVER_MEMBER_LIMIT)).expect("Should be shaped");
+        assert_eq!(shaped, payload(DATA_SAVER_MEMBER_LIMIT));
+
+        let shaped = shape("GUILD_CREATE", &payload(DATA_SAVER_MEMBER_LIMIT + 1)).expect("Should be shaped");
+        assert_eq!(shaped["data"]["members"], json!([]));
+        assert_eq!(shaped["data"]["member_count"], DATA_SAVER_MEMBER_LIMIT + 1);
+        assert_eq!(shaped["data"]["large"], true);
+    }
+
+    #[test]
+    fn test_unshaped_events() {
+        let payload = json!({"event": "MEMBER_CREATE", "data": {"embeds": [{"title": "Not a message"}]}});
+        assert!(shape("MEMBER_CREATE", &payload).is_none());
+    }
+}
@@ -0,0 +1,179 @@
+//! A read-only stream of gateway events over Server-Sent Events, for lightweight clients such as dashboards.
+//!
+//! Streams are opened with `GET /gateway/v1/events` and are not gateway sessions: they do not replace
+//! the session of the user, do not make the user appear online, and only receive the events in [`STREAM_EVENTS`].
+
+use std::{collections::HashSet, convert::Infallible};
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::Stream;
+use serde::Deserialize;
+use tokio::sync::mpsc::{self, error::SendError};
+use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
+
+use super::handler::{check_origin, GatewayResponse};
+use crate::models::{auth::Token, errors::RESTError, guild::Guild, snowflake::Snowflake, state::App, user::User};
+
+/// The events delivered to streams
+pub(super) const STREAM_EVENTS: [&str; 2] = ["PRESENCE_UPDATE", "MESSAGE_CREATE"];
+/// The maximum amount of guilds a stream may select
+const MAX_STREAM_GUILDS: usize = 100;
+
+/// The connection details of a read-only event stream
+///
+/// ## Fields
+///
+/// * `sender` - The sender for sending events to the client
+/// * `user_id` - The user the stream is authenticated as
+/// * `guild_ids` - The guilds the stream receives message events of
+#[derive(Debug, Clone)]
+pub(super) struct StreamHandle {
+    sender: mpsc::UnboundedSender<GatewayResponse>,
+    user_id: Snowflake<User>,
+    guild_ids: HashSet<Snowflake<Guild>>,
+}
+
+impl StreamHandle {
+    /// The user the stream is authenticated as
+    pub(super) const fn user_id(&self) -> Snowflake<User> {
+        self.user_id
+    }
+
+    /// Get the guilds the stream receives message events of
+    pub(super) const fn guild_ids(&self) -> &HashSet<Snowflake<Guild>> {
+        &self.guild_ids
+    }
+
+    /// Get a mutable handle to the guilds the stream receives message events of
+    pub(super) const fn guild_ids_mut(&mut self) -> &mut HashSet<Snowflake<Guild>> {
+        &mut self.guild_ids
+    }
+
+    /// Send an event to the client
+    ///
+    /// ## Arguments
+    ///
+    /// * `response` - The event to send
+    pub(super) fn respond(&self, response: GatewayResponse) -> Result<(), SendError<GatewayResponse>> {
+        self.sender.send(response)
+    }
+}
+
+/// Removes a stream from the gateway state once its response is dropped, such as when the client disconnects
+struct StreamGuard {
+    app: App,
+    id: Snowflake<StreamHandle>,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.app.gateway.remove_stream(self.id);
+    }
+}
+
+/// The query of requests opening a stream
+#[derive(Deserialize)]
+pub(super) struct StreamQuery {
+    /// Comma-separated IDs of the guilds to receive message events of, all guilds of the user if omitted
+    guild_ids: Option<String>,
+}
+
+/// Open a read-only stream of the presence updates and messages the user can see.
+///
+/// Guilds the user joined as a guest are not available to streams.
+///
+/// Each event is sent as an SSE event named after the gateway event, with the gateway event as its data.
+///
+/// ## Endpoint
+///
+/// GET `/gateway/v1/events?guild_ids=...`
+///
+/// ## Errors
+///
+/// * [`RESTError::Forbidden`] - If the origin of the request is not allowed, or the user is not a member of a selected guild
+/// * [`RESTError::BadRequest`] - If a guild ID is invalid or too many guilds are selected
+pub(super) async fn stream(
+    State(app): State<App>,
+    headers: HeaderMap,
+    token: Token,
+    Query(query): Query<StreamQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, RESTError> {
+    check_origin(&app.config, &headers)?;
+    let user_id = token.data().user_id();
+
+    let member_of = sqlx::query!(
+        "SELECT guild_id FROM members WHERE user_id = $1 AND guest_until IS NULL",
+        user_id as Snowflake<User>
+    )
+    .fetch_all(app.db.executor())
+    .await?
+    .into_iter()
+    .map(|row| row.guild_id.into())
+    .collect::<HashSet<Snowflake<Guild>>>();
+
+    let guild_ids = match query.guild_ids {
+        Some(ids) => {
+            let selected = ids
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(str::parse)
+                .collect::<Result<HashSet<Snowflake<Guild>>, _>>()
+                .map_err(|_| RESTError::BadRequest("Invalid guild ID".into()))?;
+
+            if selected.len() > MAX_STREAM_GUILDS {
+                return Err(RESTError::BadRequest(format!(
+                    "A stream may select at most {MAX_STREAM_GUILDS} guilds"
+                )));
+            }
+            if let Some(guild) = selected.iter().find(|guild| !member_of.contains(guild)) {
+                return Err(RESTError::Forbidden(format!("Not a member of guild {guild}")));
+            }
+            selected
+        }
+        None => member_of,
+    };
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let id = Snowflake::gen_new(&app.config);
+    app.gateway.add_stream(
+        id,
+        StreamHandle {
+            sender,
+            user_id,
+            guild_ids,
+        },
+    );
+    let guard = StreamGuard { app, id };
+
+    let events = UnboundedReceiverStream::new(receiver).filter_map(move |response| {
+        // The stream is removed from the gateway state once the client disconnects
+        let _guard = &guard;
+        to_event(&response).map(Ok)
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Convert a response queued for a stream into an SSE event
+///
+/// ## Returns
+///
+/// `None` if the response is not an event
+fn to_event(response: &GatewayResponse) -> Option<Event> {
+    let (id, name) = match response {
+        GatewayResponse::Event { id, event, .. } => (*id, event.name()),
+        GatewayResponse::Serialized { id, event, .. } => (*id, event.as_ref()),
+        GatewayResponse::Close(_) => return None,
+    };
+    let event = Event::default()
+        .event(name)
+        .id(id.to_string())
+        .data(response.encode(None));
+    response.observe_lag();
+    Some(event)
+}
@@ -0,0 +1,547 @@
+//! Utilities for testing the gateway against a running server.
+//!
+//! Tests using the kit need a Postgres server to connect to, set with `DATABASE_URL`.
+//! Every [`TestServer`] creates its own database, so tests may run in parallel.
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use argon2::Params;
+use axum::{middleware, Router};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use sqlx::{Connection, Executor, PgConnection};
+use tokio::{net::TcpStream, task::JoinHandle, time::timeout};
+use tokio_tungstenite::{
+    tungstenite::{
+        client::IntoClientRequest,
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Message,
+    },
+    MaybeTlsStream, WebSocketStream,
+};
+use url::Url;
+
+use crate::{
+    gateway::handler,
+    models::{
+        clock::{Clock, SystemClock},
+        state::{appstate::ConfigBuilder, App, ApplicationState, Config},
+    },
+    rest,
+};
+
+/// How long to wait for an expected event before failing the test
+pub const EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait when asserting that an event is *not* received
+pub const QUIET_PERIOD: Duration = Duration::from_secs(1);
+/// How long to wait for the server to close a connection before failing the test
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
+/// The password of all users created by [`TestServer::create_user`]
+pub const PASSWORD: &str = "correct-horse-battery-staple";
+
+/// A user created through the REST API
+#[derive(Debug, Clone)]
+pub struct TestUser {
+    /// The ID of the user, as returned by the API
+    pub id: String,
+    /// A valid session token for the user
+    pub token: String,
+}
+
+/// An application serving the gateway and REST API on a random local port, backed by its own database
+pub struct TestServer {
+    app: App,
+    addr: SocketAddr,
+    http: reqwest::Client,
+    admin_url: String,
+    database: String,
+    server: JoinHandle<()>,
+}
+
+impl TestServer {
+    /// Start a new server with the default test configuration
+    pub async fn start() -> Self {
+        Self::start_with(|_| {}).await
+    }
+
+    /// Start a new server, allowing the test configuration to be changed before it is built
+    ///
+    /// ## Arguments
+    ///
+    /// * `configure` - Changes the configuration, for example to shorten timeouts
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `DATABASE_URL` is not set or the database cannot be created
+    pub async fn start_with(configure: impl FnOnce(&mut ConfigBuilder)) -> Self {
+        Self::start_with_clock(Arc::new(SystemClock), configure).await
+    }
+
+    /// Start a new server that takes the current time from the given clock,
+    /// such as a [`ManualClock`](crate::models::clock::ManualClock) the test moves forward
+    ///
+    /// ## Arguments
+    ///
+    /// * `clock` - The source of the current time of the server
+    /// * `configure` - Changes the configuration, for example to shorten timeouts
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `DATABASE_URL` is not set or the database cannot be created
+    pub async fn start_with_clock(clock: Arc<dyn Clock>, configure: impl FnOnce(&mut ConfigBuilder)) -> Self {
+        let admin_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set to run gateway tests");
+        let database = format!("chat_test_{:016x}", rand::random::<u64>());
+
+        let mut conn = PgConnection::connect(&admin_url)
+            .await
+            .expect("Failed to connect to the database server");
+        conn.execute(format!("CREATE DATABASE {database}").as_str())
+            .await
+            .expect("Failed to create test database");
+        conn.close().await.ok();
+
+        let mut database_url = Url::parse(&admin_url).expect("DATABASE_URL should be a valid URL");
+        database_url.set_path(&database);
+
+        let mut builder = Config::builder();
+        builder
+            .database_url(database_url.to_string())
+            .minio_url("http://127.0.0.1:9000")
+            .minio_access_key(String::from("minioadmin"))
+            .minio_secret_key(String::from("minioadmin"))
+            .listen_addr(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .machine_id(0)
+            .process_id(0)
+            .app_secret(hex::encode(rand::random::<[u8; 32]>()))
+            .database_connect_attempts(1_u32)
+            .rate_limits_enabled(false)
+            // Hashing passwords with the default cost would dominate the runtime of the tests
+            .argon2_params(
+                Params::new(Params::MIN_M_COST, Params::MIN_T_COST, Params::MIN_P_COST, None)
+                    .expect("Minimum Argon2 parameters should be valid"),
+            );
+        configure(&mut builder);
+        let config = builder.build().expect("Test configuration should be valid");
+
+        let app = ApplicationState::new_test(config, clock)
+            .await
+            .expect("Failed to initialize application state");
+
+        let router = Router::new()
+            .nest("/gateway/v1", handler::get_router())
+            .nest(
+                "/api/v1",
+                rest::routes::get_router(&app)
+                    .merge(rest::routes::get_upload_router(&app))
+                    .layer(middleware::map_response(rest::middleware::json_errors)),
+            )
+            .with_state(app.clone());
+
+        let listener = tokio::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .expect("Failed to bind test server");
+        let addr = listener.local_addr().expect("Test server should have a local address");
+
+        let server = tokio::spawn(async move {
+            axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .expect("Test server failed");
+        });
+
+        Self {
+            app,
+            addr,
+            http: reqwest::Client::new(),
+            admin_url,
+            database,
+            server,
+        }
+    }
+
+    /// The application state of the server
+    pub const fn app(&self) -> &App {
+        &self.app
+    }
+
+    /// The URL the server is reachable at, such as `http://127.0.0.1:1234`
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Send a request to the REST API and return the JSON response
+    ///
+    /// ## Arguments
+    ///
+    /// * `method` - The HTTP method of the request
+    /// * `path` - The path of the endpoint, relative to `/api/v1`
+    /// * `token` - The token to authenticate with, if any
+    /// * `body` - The JSON body of the request, if any
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the request fails or the response status is not successful
+    pub async fn request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> Value {
+        let (status, response) = self.try_request(method, path, token, body).await;
+        assert!(
+            status.is_success(),
+            "Request to {path} failed with {status}: {response}"
+        );
+        response
+    }
+
+    /// Start building a request to the REST API, for requests that need more control than [`Self::try_request`]
+    ///
+    /// ## Arguments
+    ///
+    /// * `method` - The HTTP method of the request
+    /// * `path` - The path of the endpoint, relative to `/api/v1`
+    pub fn build_request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.http.request(method, format!("http://{}/api/v1{path}", self.addr))
+    }
+
+    /// Send a request to the REST API and return the status and JSON response, without asserting on the status
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the request fails
+    pub async fn try_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> (reqwest::StatusCode, Value) {
+        let mut request = self.build_request(method, path);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+
+        let response = request.send().await.expect("Failed to send request");
+        let status = response.status();
+        let text = response.text().await.expect("Failed to read response body");
+
+        (status, serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    /// Send a message without attachments to a channel and return it
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The author of the message
+    /// * `channel` - The ID of the channel to send the message in
+    /// * `content` - The content of the message
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the request fails or the response status is not successful
+    pub async fn send_message(&self, user: &TestUser, channel: &str, content: &str) -> Value {
+        let (status, message) = self.try_send_message(user, channel, content).await;
+        assert!(status.is_success(), "Sending message failed with {status}: {message}");
+        message
+    }
+
+    /// Send a message without attachments to a channel and return the status and JSON response,
+    /// without asserting on the status
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the request fails
+    pub async fn try_send_message(
+        &self,
+        user: &TestUser,
+        channel: &str,
+        content: &str,
+    ) -> (reqwest::StatusCode, Value) {
+        const BOUNDARY: &str = "chat-test-boundary";
+        let payload = json!({ "content": content });
+        let body = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"json\"\r\nContent-Type: application/json\r\n\r\n\
+            {payload}\r\n--{BOUNDARY}--\r\n"
+        );
+
+        let response = self
+            .http
+            .post(format!("http://{}/api/v1/channels/{channel}/messages", self.addr))
+            .bearer_auth(&user.token)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to send message");
+        let status = response.status();
+        let text = response.text().await.expect("Failed to read response body");
+
+        (status, serde_json::from_str(&text).unwrap_or(Value::Null))
+    }
+
+    /// Register a new user and log in as them
+    ///
+    /// ## Arguments
+    ///
+    /// * `username` - The username of the new user
+    pub async fn create_user(&self, username: &str) -> TestUser {
+        self.register(json!({ "username": username, "password": PASSWORD }))
+            .await
+    }
+
+    /// Create a new user registering from the given device and log in as them
+    ///
+    /// ## Arguments
+    ///
+    /// * `username` - The username of the new user
+    /// * `device_id` - The identifier of the device the user registers from
+    pub async fn create_user_on_device(&self, username: &str, device_id: &str) -> TestUser {
+        self.register(json!({ "username": username, "password": PASSWORD, "device_id": device_id }))
+            .await
+    }
+
+    /// Register a new user with the given payload and log in as them
+    async fn register(&self, payload: Value) -> TestUser {
+        let user = self
+            .request(reqwest::Method::POST, "/users", None, Some(payload.clone()))
+            .await;
+        let credentials = json!({ "username": payload["username"], "password": PASSWORD });
+        let auth = self
+            .request(reqwest::Method::POST, "/users/auth", None, Some(credentials))
+            .await;
+
+        TestUser {
+            id: user["id"].as_str().expect("User should have an ID").to_string(),
+            token: auth["token"].as_str().expect("Auth should return a token").to_string(),
+        }
+    }
+
+    /// Create a new guild owned by the given user and return its ID
+    ///
+    /// ## Arguments
+    ///
+    /// * `owner` - The user creating the guild
+    /// * `name` - The name of the guild
+    pub async fn create_guild(&self, owner: &TestUser, name: &str) -> String {
+        let guild = self
+            .request(
+                reqwest::Method::POST,
+                "/guilds",
+                Some(&owner.token),
+                Some(json!({ "name": name })),
+            )
+            .await;
+        guild["id"].as_str().expect("Guild should have an ID").to_string()
+    }
+
+    /// Open a new gateway connection without identifying
+    pub async fn connect(&self) -> GatewayClient {
+        let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/gateway/v1", self.addr))
+            .await
+            .expect("Failed to connect to the gateway");
+        GatewayClient { socket }
+    }
+
+    /// Open a new gateway connection without identifying, sending a `Cookie` header with the upgrade request
+    /// like a browser would, along with the origin of the page opening the connection
+    ///
+    /// ## Arguments
+    ///
+    /// * `cookie` - The value of the `Cookie` header
+    /// * `origin` - The value of the `Origin` header
+    pub async fn connect_with_cookie(&self, cookie: &str, origin: &str) -> GatewayClient {
+        let mut request = format!("ws://{}/gateway/v1", self.addr)
+            .into_client_request()
+            .expect("Gateway URL should be a valid request");
+        request.headers_mut().insert(
+            http::header::COOKIE,
+            cookie.parse().expect("Cookie should be a valid header value"),
+        );
+        request.headers_mut().insert(
+            http::header::ORIGIN,
+            origin.parse().expect("Origin should be a valid header value"),
+        );
+
+        let (socket, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .expect("Failed to connect to the gateway");
+        GatewayClient { socket }
+    }
+
+    /// Open a new gateway connection and identify as the given user, waiting for `READY`
+    ///
+    /// ## Returns
+    ///
+    /// The connected client and the `READY` event
+    pub async fn identify(&self, user: &TestUser) -> (GatewayClient, Value) {
+        let mut client = self.connect().await;
+        client.expect_event("HELLO").await;
+        client.identify(&user.token).await;
+        let ready = client.expect_event("READY").await;
+        (client, ready)
+    }
+
+    /// Shut down the server and drop its database
+    pub async fn close(self) {
+        self.app.close().await;
+        self.server.abort();
+
+        let mut conn = PgConnection::connect(&self.admin_url)
+            .await
+            .expect("Failed to connect to the database server");
+        conn.execute(format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", self.database).as_str())
+            .await
+            .expect("Failed to drop test database");
+        conn.close().await.ok();
+    }
+}
+
+/// A gateway client, used to send requests and assert on the events and close frames received
+pub struct GatewayClient {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl GatewayClient {
+    /// Send a JSON payload to the server
+    pub async fn send(&mut self, payload: &Value) {
+        self.socket
+            .send(Message::Text(payload.to_string()))
+            .await
+            .expect("Failed to send gateway payload");
+    }
+
+    /// Send a raw websocket message to the server
+    pub async fn send_raw(&mut self, message: Message) {
+        self.socket.send(message).await.expect("Failed to send gateway message");
+    }
+
+    /// Send an `IDENTIFY` with the given token
+    pub async fn identify(&mut self, token: &str) {
+        self.send(&json!({ "event": "IDENTIFY", "data": { "token": token } }))
+            .await;
+    }
+
+    /// Send a `HEARTBEAT`
+    pub async fn heartbeat(&mut self) {
+        self.send(&json!({ "event": "HEARTBEAT" })).await;
+    }
+
+    /// Send a `RESUME` for the given session with its resume token, acknowledging all events up to `seq`
+    pub async fn resume(&mut self, token: &str, session_id: &str, resume_token: &str, seq: u64) {
+        self.send(&json!({
+            "event": "RESUME",
+            "data": { "token": token, "session_id": session_id, "resume_token": resume_token, "seq": seq }
+        }))
+        .await;
+    }
+
+    /// Close the connection normally, ending the session, and wait for the server to acknowledge it
+    pub async fn close(mut self) {
+        self.socket
+            .close(Some(CloseFrame {
+                code: CloseCode::Normal,
+                reason: "".into(),
+            }))
+            .await
+            .ok();
+        while let Ok(Some(Ok(_))) = timeout(CLOSE_TIMEOUT, self.socket.next()).await {}
+    }
+
+    /// Wait for the next event, skipping websocket pings
+    ///
+    /// ## Returns
+    ///
+    /// The event, or `None` if none was received within `within`
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the connection is closed
+    pub async fn next_event(&mut self, within: Duration) -> Option<Value> {
+        let deadline = tokio::time::Instant::now() + within;
+
+        loop {
+            let message = timeout(deadline - tokio::time::Instant::now(), self.socket.next())
+                .await
+                .ok()?;
+
+            match message {
+                Some(Ok(Message::Text(text))) => {
+                    return Some(serde_json::from_str(&text).expect("Gateway events should be valid JSON"));
+                }
+                Some(Ok(Message::Ping(_) | Message::Pong(_))) => {}
+                other => panic!("Expected an event, got {other:?}"),
+            }
+        }
+    }
+
+    /// Wait for an event with the given name, skipping all other events
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the event is not received within [`EVENT_TIMEOUT`] or the connection is closed
+    pub async fn expect_event(&mut self, name: &str) -> Value {
+        let deadline = tokio::time::Instant::now() + EVENT_TIMEOUT;
+
+        loop {
+            let event = self
+                .next_event(deadline - tokio::time::Instant::now())
+                .await
+                .unwrap_or_else(|| panic!("Expected {name} within {EVENT_TIMEOUT:?}"));
+
+            if event["event"] == name {
+                return event;
+            }
+        }
+    }
+
+    /// Collect all events received within the given duration
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the connection is closed
+    pub async fn collect_events(&mut self, within: Duration) -> Vec<Value> {
+        let deadline = tokio::time::Instant::now() + within;
+        let mut events = Vec::new();
+
+        while let Some(event) = self.next_event(deadline - tokio::time::Instant::now()).await {
+            events.push(event);
+        }
+        events
+    }
+
+    /// Wait for the server to close the connection, skipping all events
+    ///
+    /// ## Returns
+    ///
+    /// The close code and reason sent by the server
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the connection is not closed within [`CLOSE_TIMEOUT`] or is closed without a close frame
+    pub async fn expect_close(&mut self) -> (u16, String) {
+        let deadline = tokio::time::Instant::now() + CLOSE_TIMEOUT;
+
+        loop {
+            let message = timeout(deadline - tokio::time::Instant::now(), self.socket.next())
+                .await
+                .unwrap_or_else(|_| panic!("Expected the connection to be closed within {CLOSE_TIMEOUT:?}"));
+
+            match message {
+                Some(Ok(Message::Close(Some(frame)))) => return (frame.code.into(), frame.reason.into_owned()),
+                Some(Ok(Message::Close(None)) | Err(_)) | None => {
+                    panic!("Expected the connection to be closed with a close frame")
+                }
+                Some(Ok(_)) => {}
+            }
+        }
+    }
+}
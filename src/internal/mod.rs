@@ -0,0 +1,164 @@
+//! An internal gRPC API for trusted first-party services, such as bots and bridges run by the operator.
+//!
+//! The API is served on its own address, which should not be reachable from the internet.
+//! Callers are authenticated with a shared secret, a client certificate, or both.
+
+pub mod proto;
+mod service;
+
+use std::future::Future;
+
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use tonic::{
+    service::Interceptor,
+    transport::{Certificate, Identity, Server, ServerTlsConfig},
+    Request, Status,
+};
+
+pub use self::service::InternalService;
+use crate::models::state::{App, Config};
+use proto::internal_server::InternalServer;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Serve the internal API on [`Config::internal_api_addr`] until `shutdown` completes.
+///
+/// ## Errors
+///
+/// * [`tonic::transport::Error`] - If the TLS configuration is invalid or the server fails.
+///
+/// ## Panics
+///
+/// Panics if the internal API is not configured, callers would not be authenticated,
+/// or its certificates cannot be read.
+pub async fn serve(app: App, shutdown: impl Future<Output = ()>) -> Result<(), tonic::transport::Error> {
+    let config = &app.config;
+    let addr = config
+        .internal_api_addr()
+        .expect("INTERNAL_API_ADDR must be set to serve the internal API");
+    assert!(
+        config.internal_api_secret().is_some() || config.internal_api_client_ca().is_some(),
+        "INTERNAL_API_SECRET or INTERNAL_API_CLIENT_CA must be set if INTERNAL_API_ADDR is set"
+    );
+    assert!(
+        config.internal_api_client_ca().is_none()
+            || (config.internal_api_tls_cert().is_some() && config.internal_api_tls_key().is_some()),
+        "INTERNAL_API_TLS_CERT and INTERNAL_API_TLS_KEY must be set if INTERNAL_API_CLIENT_CA is set"
+    );
+
+    let mut server = Server::builder();
+    if let Some(tls) = tls_config(config) {
+        server = server.tls_config(tls)?;
+    }
+
+    let interceptor = SecretInterceptor::new(config.internal_api_secret());
+    server
+        .add_service(InternalServer::with_interceptor(
+            InternalService::new(app.clone()),
+            interceptor,
+        ))
+        .serve_with_shutdown(addr, shutdown)
+        .await
+}
+
+/// Build the TLS configuration of the internal API, if a certificate is configured.
+/// If a client CA is configured, callers must present a certificate signed by it.
+fn tls_config(config: &Config) -> Option<ServerTlsConfig> {
+    let (cert, key) = config.internal_api_tls_cert().zip(config.internal_api_tls_key())?;
+
+    let cert = std::fs::read(cert).expect("Failed to read INTERNAL_API_TLS_CERT");
+    let key = std::fs::read(key).expect("Failed to read INTERNAL_API_TLS_KEY");
+    let mut tls = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+
+    if let Some(ca) = config.internal_api_client_ca() {
+        let ca = std::fs::read(ca).expect("Failed to read INTERNAL_API_CLIENT_CA");
+        tls = tls.client_ca_root(Certificate::from_pem(ca));
+    }
+    Some(tls)
+}
+
+/// Rejects requests that do not carry the shared secret as a bearer token.
+/// If no secret is configured, callers are only authenticated by their client certificate.
+#[derive(Clone)]
+pub struct SecretInterceptor {
+    /// A MAC of the secret, so tokens can be compared against it in constant time.
+    expected: Option<Vec<u8>>,
+}
+
+impl SecretInterceptor {
+    /// The message authenticated with the secret and the presented token.
+    const CHALLENGE: &'static [u8] = b"chat-internal-api";
+
+    /// Create a new interceptor.
+    ///
+    /// ## Arguments
+    ///
+    /// * `secret` - The secret callers must present, or `None` to accept all requests.
+    pub fn new(secret: Option<&Secret<String>>) -> Self {
+        Self {
+            expected: secret.map(|secret| Self::mac(secret.expose_secret()).finalize().into_bytes().to_vec()),
+        }
+    }
+
+    fn mac(key: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC can take a key of any size");
+        mac.update(Self::CHALLENGE);
+        mac
+    }
+}
+
+impl Interceptor for SecretInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(expected) = &self.expected else {
+            return Ok(request);
+        };
+
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match token {
+            // Comparing the MACs of the token and the secret takes the same time regardless of where they differ
+            Some(token) if Self::mac(token).verify_slice(expected).is_ok() => Ok(request),
+            _ => Err(Status::unauthenticated("Missing or invalid internal API secret.")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::Secret;
+    use tonic::{service::Interceptor, Code, Request};
+
+    use super::SecretInterceptor;
+
+    fn request(authorization: Option<&str>) -> Request<()> {
+        let mut request = Request::new(());
+        if let Some(authorization) = authorization {
+            request
+                .metadata_mut()
+                .insert("authorization", authorization.parse().expect("Header should be valid"));
+        }
+        request
+    }
+
+    #[test]
+    fn test_secret_interceptor() {
+        let mut interceptor = SecretInterceptor::new(Some(&Secret::new("hunter2".to_string())));
+
+        assert!(interceptor.call(request(Some("Bearer hunter2"))).is_ok());
+        for authorization in [None, Some("Bearer hunter3"), Some("hunter2"), Some("Bearer ")] {
+            let status = interceptor
+                .call(request(authorization))
+                .expect_err("Request should be rejected");
+            assert_eq!(status.code(), Code::Unauthenticated);
+        }
+
+        let mut interceptor = SecretInterceptor::new(None);
+        assert!(interceptor.call(request(None)).is_ok());
+    }
+}
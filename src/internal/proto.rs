@@ -0,0 +1,154 @@
+//! The messages of the internal gRPC API.
+//!
+//! These are written by hand instead of being generated from `.proto` files,
+//! the equivalent protobuf definitions are listed in the documentation of the internal API.
+
+use crate::models::user::{Activity, Presence as UserPresence, User as UserModel};
+
+pub use self::generated::*;
+
+/// The server generated by `build.rs`.
+#[allow(clippy::all, clippy::pedantic, clippy::nursery)]
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/chat.internal.v1.Internal.rs"));
+}
+
+/// Look up a user by their ID or their username. If both are set, the ID takes precedence.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct FetchUserRequest {
+    #[prost(int64, optional, tag = "1")]
+    pub user_id: Option<i64>,
+    #[prost(string, optional, tag = "2")]
+    pub username: Option<String>,
+}
+
+/// A user, without their presence.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct User {
+    #[prost(int64, tag = "1")]
+    pub id: i64,
+    #[prost(string, tag = "2")]
+    pub username: String,
+    #[prost(string, optional, tag = "3")]
+    pub display_name: Option<String>,
+    /// UNIX timestamp of when the user was created, in milliseconds.
+    #[prost(int64, tag = "4")]
+    pub created_at: i64,
+}
+
+impl From<&UserModel> for User {
+    fn from(user: &UserModel) -> Self {
+        Self {
+            id: user.id().into(),
+            username: user.username().clone(),
+            display_name: user.display_name().cloned(),
+            created_at: user.created_at().timestamp_millis(),
+        }
+    }
+}
+
+/// Send a message on behalf of a user.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct CreateMessageRequest {
+    #[prost(int64, tag = "1")]
+    pub channel_id: i64,
+    /// The user sending the message. They must be able to send messages in the channel.
+    #[prost(int64, tag = "2")]
+    pub author_id: i64,
+    #[prost(string, tag = "3")]
+    pub content: String,
+    /// Forwarded to clients in the `MESSAGE_CREATE` event, to let the sender recognize the message.
+    #[prost(string, optional, tag = "4")]
+    pub nonce: Option<String>,
+}
+
+/// The message that was sent.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct CreateMessageResponse {
+    #[prost(int64, tag = "1")]
+    pub message_id: i64,
+}
+
+/// Announce something to all connected users, such as an upcoming maintenance window.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct CreateAnnouncementRequest {
+    #[prost(string, tag = "1")]
+    pub content: String,
+    /// How long the announcement stays active in seconds. Required if `persist` is set.
+    #[prost(int64, optional, tag = "2")]
+    pub duration: Option<i64>,
+    /// Also include the announcement in the `READY` event of users connecting until it expires.
+    #[prost(bool, tag = "3")]
+    pub persist: bool,
+}
+
+/// The announcement that was dispatched.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct CreateAnnouncementResponse {
+    #[prost(int64, tag = "1")]
+    pub announcement_id: i64,
+}
+
+/// Look up the presences of users.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct FetchPresencesRequest {
+    #[prost(int64, repeated, tag = "1")]
+    pub user_ids: Vec<i64>,
+}
+
+/// The presences of the users that exist.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct FetchPresencesResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub presences: Vec<PresenceEntry>,
+}
+
+/// The presence of a single user, as seen by other users.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct PresenceEntry {
+    #[prost(int64, tag = "1")]
+    pub user_id: i64,
+    #[prost(enumeration = "Presence", tag = "2")]
+    pub presence: i32,
+    /// The name of the activity of the user, if they have one and do not appear offline.
+    #[prost(string, optional, tag = "3")]
+    pub activity: Option<String>,
+}
+
+impl PresenceEntry {
+    /// Create a new presence entry.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user the presence belongs to.
+    /// * `presence` - The presence of the user, taking their gateway connection into account.
+    /// * `activity` - The activity of the user, if they have one and do not appear offline.
+    pub fn new(user: &UserModel, presence: UserPresence, activity: Option<&Activity>) -> Self {
+        Self {
+            user_id: user.id().into(),
+            presence: Presence::from(presence).into(),
+            activity: activity.map(|activity| activity.name().to_string()),
+        }
+    }
+}
+
+/// The presence of a user. Offline is the default, as users that are not connected are offline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, prost::Enumeration)]
+#[repr(i32)]
+pub enum Presence {
+    Offline = 0,
+    Online = 1,
+    Away = 2,
+    Busy = 3,
+}
+
+impl From<UserPresence> for Presence {
+    fn from(presence: UserPresence) -> Self {
+        match presence {
+            UserPresence::Online => Self::Online,
+            UserPresence::Away => Self::Away,
+            UserPresence::Busy => Self::Busy,
+            UserPresence::Offline => Self::Offline,
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use tonic::{Request, Response, Status};
+
+use super::proto::{
+    internal_server::Internal, CreateAnnouncementRequest, CreateAnnouncementResponse, CreateMessageRequest,
+    CreateMessageResponse, FetchPresencesRequest, FetchPresencesResponse, FetchUserRequest, PresenceEntry, User,
+};
+use crate::models::{
+    announcement::Announcement, channel::ChannelLike, errors::AppError, gateway_event::GatewayEvent, message::Message,
+    snowflake::Snowflake, state::App,
+};
+
+/// The maximum amount of users whose presences can be fetched at once.
+pub const MAX_PRESENCE_BATCH: usize = 100;
+
+/// Implements the internal API on top of the same services as the REST API and the gateway.
+/// Callers are trusted, so requests are not rate limited, but still have to be valid.
+pub struct InternalService {
+    app: App,
+}
+
+impl InternalService {
+    /// Create a new internal API service.
+    pub const fn new(app: App) -> Self {
+        Self { app }
+    }
+}
+
+#[tonic::async_trait]
+impl Internal for InternalService {
+    async fn fetch_user(&self, request: Request<FetchUserRequest>) -> Result<Response<User>, Status> {
+        let request = request.into_inner();
+
+        let user = match (request.user_id, request.username.as_deref()) {
+            (Some(id), _) => self.app.ops().fetch_user(Snowflake::new(id)).await,
+            (None, Some(username)) => self.app.ops().fetch_user_by_username(username).await,
+            (None, None) => return Err(Status::invalid_argument("Either user_id or username must be set.")),
+        };
+
+        user.map(|user| Response::new(User::from(&user)))
+            .ok_or_else(|| Status::not_found("User does not exist."))
+    }
+
+    async fn create_message(
+        &self,
+        request: Request<CreateMessageRequest>,
+    ) -> Result<Response<CreateMessageResponse>, Status> {
+        let request = request.into_inner();
+
+        let (channel, author) = self
+            .app
+            .messages()
+            .fetch_sendable_channel(Snowflake::new(request.channel_id), Snowflake::new(request.author_id))
+            .await?;
+
+        let message = Message::builder()
+            .id(Snowflake::gen_new(&self.app.config))
+            .channel_id(channel.id())
+            .author(author)
+            .content(Some(request.content))
+            .nonce(request.nonce)
+            .build()
+            .map_err(AppError::from)?;
+
+        let message = self.app.messages().create(&channel, message).await?;
+
+        Ok(Response::new(CreateMessageResponse {
+            message_id: message.id().into(),
+        }))
+    }
+
+    async fn fetch_presences(
+        &self,
+        request: Request<FetchPresencesRequest>,
+    ) -> Result<Response<FetchPresencesResponse>, Status> {
+        let request = request.into_inner();
+
+        if request.user_ids.len() > MAX_PRESENCE_BATCH {
+            return Err(Status::invalid_argument(format!(
+                "At most {MAX_PRESENCE_BATCH} presences can be fetched at once."
+            )));
+        }
+
+        let ids: Vec<_> = request.user_ids.into_iter().map(Snowflake::new).collect();
+        let users = self.app.ops().fetch_users(&ids).await.map_err(AppError::from)?;
+
+        let presences = users
+            .iter()
+            .map(|user| {
+                let gateway = &self.app.gateway;
+                PresenceEntry::new(user, *user.presence(gateway), user.activity(gateway).as_ref())
+            })
+            .collect();
+
+        Ok(Response::new(FetchPresencesResponse { presences }))
+    }
+
+    async fn create_announcement(
+        &self,
+        request: Request<CreateAnnouncementRequest>,
+    ) -> Result<Response<CreateAnnouncementResponse>, Status> {
+        let request = request.into_inner();
+
+        let announcement = Announcement::new(
+            &self.app.config,
+            request.content,
+            request.duration,
+            self.app.clock.now(),
+        )
+        .map_err(AppError::from)?;
+
+        if request.persist {
+            self.app.ops().create_announcement(&announcement).await?;
+        }
+
+        let announcement_id = announcement.id().into();
+        // Announcements are not tied to a guild or user, so every connected session receives them
+        self.app
+            .gateway
+            .dispatch(GatewayEvent::SystemAnnouncement(announcement));
+
+        Ok(Response::new(CreateAnnouncementResponse { announcement_id }))
+    }
+}
@@ -0,0 +1,11 @@
+#![allow(async_fn_in_trait)]
+
+pub mod gateway;
+pub mod internal;
+pub mod models;
+pub mod rest;
+pub mod services;
+pub mod utils;
+
+#[cfg(test)]
+mod tests;
@@ -1,24 +1,29 @@
-#![allow(async_fn_in_trait)]
-
-pub mod gateway;
-pub mod models;
-pub mod rest;
-pub mod utils;
-
-use axum::Router;
+mod cli;
+
+use std::net::SocketAddr;
+
+use axum::{middleware, Router};
+use chat_backend::{
+    gateway, internal,
+    models::{
+        log_filter::{LogFilter, LogFilterHandle},
+        state::{App, ApplicationState},
+        telemetry,
+    },
+    rest,
+};
+use clap::Parser;
+use cli::Cli;
 use color_eyre::eyre::Result;
-use models::state::App;
-use tokio::signal::ctrl_c;
+use opentelemetry_sdk::trace::TracerProvider;
+use tokio::{signal::ctrl_c, sync::watch::Receiver, task::JoinHandle};
+use tower::limit::GlobalConcurrencyLimitLayer;
 use tower_http::trace::TraceLayer;
-
-#[cfg(debug_assertions)]
-use tracing::level_filters::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
 
 #[cfg(unix)]
 use tokio::signal::unix::{signal, SignalKind};
 
-use crate::models::state::ApplicationState;
-
 #[cfg(unix)]
 async fn handle_signals(state: App) {
     let mut sigterm = signal(SignalKind::terminate()).expect("Failed to create SIGTERM signal listener");
@@ -40,38 +45,133 @@ async fn handle_signals(state: App) {
     state.close().await;
 }
 
+/// Serve the internal API in the background, if it is enabled.
+fn spawn_internal_server(state: &App, mut shutdown_rx: Receiver<()>) -> Option<JoinHandle<()>> {
+    let addr = state.config.internal_api_addr()?;
+    tracing::info!("Serving internal API on {addr}");
+
+    let state = state.clone();
+    Some(tokio::spawn(async move {
+        internal::serve(state, async move {
+            shutdown_rx.changed().await.ok();
+        })
+        .await
+        .expect("Failed creating internal API server");
+    }))
+}
+
+/// Install the global tracing subscriber, which logs to stdout
+/// and exports spans if an OpenTelemetry collector is configured.
+///
+/// ## Returns
+///
+/// The tracer provider, if spans are exported, and the handle to change the log verbosity with.
+fn init_tracing() -> Result<(Option<TracerProvider>, LogFilterHandle)> {
+    let tracer_provider = telemetry::tracer_provider()?;
+
+    // The verbosity can be changed at runtime through the admin endpoints
+    let (log_filter, log_filter_handle) = LogFilter::layer();
+
+    let subscriber = tracing_subscriber::registry()
+        .with(log_filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .compact()
+                .with_target(false)
+                .without_time(),
+        )
+        .with(tracer_provider.as_ref().map(telemetry::layer));
+
+    /* console_subscriber::init(); */
+    tracing::subscriber::set_global_default(subscriber).expect("Failed to set subscriber");
+    Ok((tracer_provider, log_filter_handle))
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     color_eyre::install()?;
+    let cli = Cli::parse();
 
-    #[cfg(debug_assertions)]
-    let subscriber = tracing_subscriber::fmt()
-        .compact()
-        .with_target(false)
-        .with_max_level(LevelFilter::DEBUG)
-        .without_time()
-        .finish();
-
-    #[cfg(not(debug_assertions))]
-    let subscriber = tracing_subscriber::fmt()
-        .compact()
-        .with_target(false)
-        .without_time()
-        .finish();
+    let (tracer_provider, log_filter_handle) = init_tracing()?;
 
-    /* console_subscriber::init(); */
-    tracing::subscriber::set_global_default(subscriber).expect("Failed to set subscriber");
+    // Only run the startup checks and report the results
+    if cli.check {
+        let report = ApplicationState::check().await?;
+        std::process::exit(i32::from(report.failures() > 0));
+    }
 
-    let gateway_routes = gateway::handler::get_router();
-    let rest_routes = rest::routes::get_router();
+    let result = match cli.command {
+        // Run a maintenance command instead of the server
+        Some(command) => command.run().await,
+        None => serve(log_filter_handle).await,
+    };
+
+    // Export the spans that are still pending
+    if let Some(tracer_provider) = tracer_provider {
+        tracer_provider.shutdown().ok();
+    }
+
+    result
+}
 
+/// Serve the REST API, gateway and media until the process is terminated.
+///
+/// ## Arguments
+///
+/// * `log_filter_handle` - The handle to change the log verbosity with, controlled by the admin endpoints.
+async fn serve(log_filter_handle: LogFilterHandle) -> Result<()> {
     // Initialize the application state
     let state = ApplicationState::new_shared().await?;
+    state.log_filter.attach(log_filter_handle);
+    let config = &state.config;
 
-    let router = Router::new()
+    let gateway_routes = gateway::handler::get_router();
+    let rest_routes = rest::routes::get_router(&state)
+        .layer(rest::middleware::request_limits(
+            config.request_timeout(),
+            config.max_body_size(),
+        ))
+        .merge(
+            rest::routes::get_upload_router(&state).layer(rest::middleware::request_limits(
+                config.upload_timeout(),
+                config.max_upload_size(),
+            )),
+        )
+        .layer(middleware::map_response(rest::middleware::json_errors));
+    let health_routes = rest::routes::health::get_router();
+    // Media does not depend on the database, so it is served regardless of its availability
+    let media_routes = Router::new().nest(
+        "/media",
+        rest::routes::get_media_router().layer(rest::middleware::request_limits(
+            config.request_timeout(),
+            config.max_body_size(),
+        )),
+    );
+
+    let mut router = Router::new()
         .nest("/gateway/v1", gateway_routes)
         .nest("/api/v1", rest_routes)
-        .layer(TraceLayer::new_for_http())
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            rest::middleware::require_database,
+        ))
+        .nest("/api/v1", health_routes);
+
+    // Media is either served alongside everything else, or on its own address
+    let separate_media_routes = if config.media_listen_addr().is_some() {
+        Some(media_routes)
+    } else {
+        router = router.merge(media_routes);
+        None
+    };
+
+    let router = router
+        .layer(GlobalConcurrencyLimitLayer::new(config.max_concurrent_requests()))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(telemetry::request_span)
+                .on_response(telemetry::record_response),
+        )
         .with_state(state.clone());
 
     let listener = tokio::net::TcpListener::bind(state.config.listen_addr())
@@ -80,10 +180,53 @@ async fn main() -> Result<()> {
 
     tracing::info!("Listening on {}", state.config.listen_addr());
 
-    axum::serve(listener, router)
-        .with_graceful_shutdown(handle_signals(state))
+    // Notifies the media and internal servers once the main server begins shutting down
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(());
+    let internal_server = spawn_internal_server(&state, shutdown_rx.clone());
+
+    let media_server = if let (Some(routes), Some(addr)) = (separate_media_routes, config.media_listen_addr()) {
+        let media_app = routes
+            .layer(GlobalConcurrencyLimitLayer::new(config.max_concurrent_requests()))
+            .layer(
+                TraceLayer::new_for_http()
+                    .make_span_with(telemetry::request_span)
+                    .on_response(telemetry::record_response),
+            )
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .expect("Failed to bind to media address");
+
+        tracing::info!("Serving media on {addr}");
+
+        Some(tokio::spawn(async move {
+            axum::serve(listener, media_app)
+                .with_graceful_shutdown(async move {
+                    shutdown_rx.changed().await.ok();
+                })
+                .await
+                .expect("Failed creating media server");
+        }))
+    } else {
+        None
+    };
+
+    // Client addresses are used to rate limit unauthenticated requests
+    axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            handle_signals(state).await;
+            shutdown_tx.send(()).ok();
+        })
         .await
         .expect("Failed creating server");
 
+    if let Some(media_server) = media_server {
+        media_server.await.ok();
+    }
+    if let Some(internal_server) = internal_server {
+        internal_server.await.ok();
+    }
+
     Ok(())
 }
@@ -0,0 +1,232 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// The amount of days analytics are returned for if not specified.
+pub const DEFAULT_ANALYTICS_DAYS: u32 = 30;
+/// The maximum amount of days analytics can be returned for.
+pub const MAX_ANALYTICS_DAYS: u32 = 90;
+/// The length of a day in seconds. Days are counted in UTC.
+pub const SECONDS_PER_DAY: i64 = 86400;
+
+/// The UNIX timestamp of the start of the day the given time falls on, in UTC.
+pub const fn day_start(now: DateTime<Utc>) -> i64 {
+    let timestamp = now.timestamp();
+    timestamp - timestamp.rem_euclid(SECONDS_PER_DAY)
+}
+
+/// Represents a row of daily guild statistics stored in the database.
+pub struct DailyStatsRecord {
+    pub day: i64,
+    pub messages: i64,
+    pub active_members: i64,
+    pub joins: i64,
+    pub leaves: i64,
+}
+
+/// The activity of a guild on a single day.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DailyStats {
+    /// The UNIX timestamp of the start of the day, in UTC.
+    day: i64,
+    /// The amount of messages sent on the day.
+    messages: i64,
+    /// The amount of distinct members who sent at least one message on the day.
+    active_members: i64,
+    /// The amount of members who joined on the day.
+    joins: i64,
+    /// The amount of members who left on the day.
+    leaves: i64,
+}
+
+impl DailyStats {
+    /// Create new daily statistics from a database record.
+    pub const fn from_record(record: &DailyStatsRecord) -> Self {
+        Self {
+            day: record.day,
+            messages: record.messages,
+            active_members: record.active_members,
+            joins: record.joins,
+            leaves: record.leaves,
+        }
+    }
+
+    /// Statistics for a day without any activity.
+    const fn empty(day: i64) -> Self {
+        Self {
+            day,
+            messages: 0,
+            active_members: 0,
+            joins: 0,
+            leaves: 0,
+        }
+    }
+
+    /// The UNIX timestamp of the start of the day, in UTC.
+    pub const fn day(&self) -> i64 {
+        self.day
+    }
+
+    /// The amount of messages sent on the day.
+    pub const fn messages(&self) -> i64 {
+        self.messages
+    }
+
+    /// The amount of distinct members who sent at least one message on the day.
+    pub const fn active_members(&self) -> i64 {
+        self.active_members
+    }
+
+    /// The amount of members who joined on the day.
+    pub const fn joins(&self) -> i64 {
+        self.joins
+    }
+
+    /// The amount of members who left on the day.
+    pub const fn leaves(&self) -> i64 {
+        self.leaves
+    }
+}
+
+/// The activity of a guild summed up over a window of days.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnalyticsTotals {
+    /// The amount of messages sent in the window.
+    messages: i64,
+    /// The amount of members who joined in the window.
+    joins: i64,
+    /// The amount of members who left in the window.
+    leaves: i64,
+}
+
+impl AnalyticsTotals {
+    /// The amount of messages sent in the window.
+    pub const fn messages(&self) -> i64 {
+        self.messages
+    }
+
+    /// The amount of members who joined in the window.
+    pub const fn joins(&self) -> i64 {
+        self.joins
+    }
+
+    /// The amount of members who left in the window.
+    pub const fn leaves(&self) -> i64 {
+        self.leaves
+    }
+}
+
+/// The activity of a guild over a window of days, see `GET /guilds/{guild_id}/analytics`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuildAnalytics {
+    /// The statistics of every day in the window, oldest first.
+    days: Vec<DailyStats>,
+    /// The statistics summed up over the window.
+    totals: AnalyticsTotals,
+}
+
+impl GuildAnalytics {
+    /// Build the analytics of a window of days from the stored daily statistics.
+    /// Days without a record are included with no activity.
+    ///
+    /// ## Arguments
+    ///
+    /// * `records` - The records of the days in the window, in any order.
+    /// * `first_day` - The UNIX timestamp of the start of the first day in the window.
+    /// * `days` - The amount of days in the window.
+    pub fn from_records(records: Vec<DailyStatsRecord>, first_day: i64, days: u32) -> Self {
+        let mut stats: Vec<DailyStats> = (0..i64::from(days))
+            .map(|offset| DailyStats::empty(first_day + offset * SECONDS_PER_DAY))
+            .collect();
+
+        for record in records {
+            let offset = (record.day - first_day).div_euclid(SECONDS_PER_DAY);
+            if let Some(day) = usize::try_from(offset).ok().and_then(|offset| stats.get_mut(offset)) {
+                *day = DailyStats::from_record(&record);
+            }
+        }
+
+        let totals = stats
+            .iter()
+            .fold(AnalyticsTotals::default(), |totals, day| AnalyticsTotals {
+                messages: totals.messages + day.messages,
+                joins: totals.joins + day.joins,
+                leaves: totals.leaves + day.leaves,
+            });
+
+        Self { days: stats, totals }
+    }
+
+    /// The statistics of every day in the window, oldest first.
+    pub fn days(&self) -> &[DailyStats] {
+        &self.days
+    }
+
+    /// The statistics summed up over the window.
+    pub const fn totals(&self) -> &AnalyticsTotals {
+        &self.totals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::{day_start, DailyStatsRecord, GuildAnalytics, SECONDS_PER_DAY};
+
+    #[test]
+    fn test_from_records() {
+        let now = Utc
+            .with_ymd_and_hms(2024, 7, 15, 18, 30, 0)
+            .single()
+            .expect("Date should be valid");
+        let today = day_start(now);
+        assert_eq!(
+            today,
+            Utc.with_ymd_and_hms(2024, 7, 15, 0, 0, 0)
+                .single()
+                .expect("Date should be valid")
+                .timestamp()
+        );
+
+        let first_day = today - 2 * SECONDS_PER_DAY;
+        let records = vec![
+            DailyStatsRecord {
+                day: today,
+                messages: 10,
+                active_members: 3,
+                joins: 1,
+                leaves: 0,
+            },
+            DailyStatsRecord {
+                day: first_day,
+                messages: 4,
+                active_members: 2,
+                joins: 2,
+                leaves: 1,
+            },
+            // Outside of the window
+            DailyStatsRecord {
+                day: first_day - SECONDS_PER_DAY,
+                messages: 100,
+                active_members: 20,
+                joins: 5,
+                leaves: 5,
+            },
+        ];
+
+        let analytics = GuildAnalytics::from_records(records, first_day, 3);
+        let days = analytics.days();
+        assert_eq!(days.len(), 3);
+        assert_eq!(days[0].day(), first_day);
+        assert_eq!(days[0].messages(), 4);
+        // Days without activity are filled in
+        assert_eq!(days[1].day(), first_day + SECONDS_PER_DAY);
+        assert_eq!(days[1].messages(), 0);
+        assert_eq!(days[2].day(), today);
+        assert_eq!(days[2].active_members(), 3);
+
+        assert_eq!(analytics.totals().messages(), 14);
+        assert_eq!(analytics.totals().joins(), 3);
+        assert_eq!(analytics.totals().leaves(), 1);
+    }
+}
@@ -0,0 +1,108 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::{
+    errors::{BuildError, ErrorCode},
+    snowflake::Snowflake,
+    state::Config,
+};
+
+/// The maximum length of the content of an announcement, in characters.
+pub const MAX_CONTENT_LENGTH: usize = 4000;
+/// The longest time an announcement may stay active, 30 days.
+pub const MAX_ANNOUNCEMENT_DURATION: i64 = 30 * 24 * 60 * 60;
+
+/// Represents an announcement record stored in the database.
+pub struct AnnouncementRecord {
+    pub id: Snowflake<Announcement>,
+    pub content: String,
+    pub expires_at: i64,
+}
+
+/// A notice from the operators of the server to all users, such as an upcoming maintenance window.
+///
+/// Announcements are dispatched to every connected user regardless of the guilds they are in.
+/// Persisted announcements are also included in the `READY` event until they expire.
+#[derive(Serialize, Debug, Clone)]
+pub struct Announcement {
+    /// The ID of the announcement. This also encodes when the announcement was made.
+    id: Snowflake<Self>,
+    /// The content of the announcement.
+    content: String,
+    /// UNIX timestamp of when the announcement expires, if it does.
+    /// Clients should stop displaying the announcement after this time.
+    expires_at: Option<i64>,
+}
+
+impl Announcement {
+    /// Create a new announcement. Assigns a new snowflake to the announcement.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration.
+    /// * `content` - The content of the announcement.
+    /// * `duration` - How long the announcement stays active in seconds, if it expires.
+    /// * `now` - The time the announcement is made at.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If the content is empty or too long, or the duration is out of range.
+    pub fn new(
+        config: &Config,
+        content: String,
+        duration: Option<i64>,
+        now: DateTime<Utc>,
+    ) -> Result<Self, BuildError> {
+        if content.trim().is_empty() {
+            return Err(BuildError::InvalidField {
+                field: "content",
+                code: ErrorCode::Required,
+                message: "Announcement content must not be empty.".into(),
+            });
+        }
+        if content.chars().count() > MAX_CONTENT_LENGTH {
+            return Err(BuildError::InvalidField {
+                field: "content",
+                code: ErrorCode::TooLong,
+                message: format!("Announcement content must be at most {MAX_CONTENT_LENGTH} characters."),
+            });
+        }
+        if duration.is_some_and(|duration| !(1..=MAX_ANNOUNCEMENT_DURATION).contains(&duration)) {
+            return Err(BuildError::InvalidField {
+                field: "duration",
+                code: ErrorCode::OutOfRange,
+                message: format!("Announcement duration must be between 1 and {MAX_ANNOUNCEMENT_DURATION} seconds."),
+            });
+        }
+
+        Ok(Self {
+            id: Snowflake::gen_new(config),
+            content,
+            expires_at: duration.map(|duration| now.timestamp() + duration),
+        })
+    }
+
+    /// Build an announcement directly from a database record.
+    pub fn from_record(record: AnnouncementRecord) -> Self {
+        Self {
+            id: record.id,
+            content: record.content,
+            expires_at: Some(record.expires_at),
+        }
+    }
+
+    /// The ID of the announcement.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The content of the announcement.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// UNIX timestamp of when the announcement expires, if it does.
+    pub const fn expires_at(&self) -> Option<i64> {
+        self.expires_at
+    }
+}
@@ -4,22 +4,98 @@ use super::{
     bucket::Buckets,
     channel::Channel,
     errors::{AppError, BuildError, RESTError},
+    limits::{Limit, LimitExceeded},
+    media_metadata::MediaMetadata,
     message::{ExtendedMessageRecord, Message},
-    state::App,
+    state::{App, ApplicationState},
+    upload_throttle::UploadThrottle,
+    user::User,
 };
 use axum::extract::multipart::Field;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use derive_builder::Builder;
 use enum_dispatch::enum_dispatch;
+use futures::StreamExt;
 use mime::Mime;
 use regex::Regex;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use super::snowflake::Snowflake;
 
 static ATTACH_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"attachment-(?P<id>[0-9])").expect("Failed to compile attachment regex"));
 
+/// Generate a new random key token, making the S3 key and media URL of an attachment unguessable.
+pub fn generate_key_token() -> String {
+    hex::encode(rand::random::<[u8; 16]>())
+}
+
+/// Format the S3 key of an attachment.
+///
+/// Keys are formatted as `{channel_id}/{message_id}/{attachment_id}/{key_token}/{filename}`.
+/// Attachments uploaded before key tokens were introduced have no token until they are migrated,
+/// and are stored under `{channel_id}/{message_id}/{attachment_id}/{filename}`.
+pub fn format_s3_key(
+    channel: impl Into<Snowflake<Channel>>,
+    message: impl Into<Snowflake<Message>>,
+    id: impl std::fmt::Display,
+    key_token: Option<&str>,
+    filename: &str,
+) -> String {
+    let (channel, message) = (channel.into(), message.into());
+    let mut key = format!("{channel}/{message}/{id}/");
+    if let Some(token) = key_token {
+        key.push_str(token);
+        key.push('/');
+    }
+    key.push_str(filename);
+    key
+}
+
+/// The state of scanning an attachment for malware.
+///
+/// Attachments are only served once they are clean, unless the server is configured to serve unscanned attachments.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum ScanStatus {
+    /// The attachment has not been scanned yet.
+    Pending = 0,
+    /// No malware was found, or scanning is disabled.
+    #[default]
+    Clean = 1,
+    /// Malware was found, the contents of the attachment were removed.
+    Infected = 2,
+    /// The attachment could not be scanned.
+    Failed = 3,
+}
+
+impl ScanStatus {
+    /// Whether the contents of an attachment in this state may be served.
+    ///
+    /// ## Arguments
+    ///
+    /// * `serve_unscanned` - Whether pending and failed attachments are served.
+    pub const fn is_servable(self, serve_unscanned: bool) -> bool {
+        match self {
+            Self::Clean => true,
+            Self::Pending | Self::Failed => serve_unscanned,
+            Self::Infected => false,
+        }
+    }
+}
+
+impl From<i16> for ScanStatus {
+    fn from(status: i16) -> Self {
+        match status {
+            0 => Self::Pending,
+            1 => Self::Clean,
+            2 => Self::Infected,
+            _ => Self::Failed,
+        }
+    }
+}
+
 /// Trait used for enum dispatch
 #[enum_dispatch(Attachment)]
 pub trait AttachmentLike {
@@ -34,14 +110,20 @@ pub trait AttachmentLike {
     fn channel_id(&self) -> Snowflake<Channel>;
     /// The MIME-type of the file.
     fn mime(&self) -> Mime;
+    /// Rendering metadata of the file, if it is an image, video or audio file.
+    fn metadata(&self) -> &MediaMetadata;
+    /// The state of scanning the file for malware.
+    fn scan_status(&self) -> ScanStatus;
+    /// The random component of the S3 key and media URL, `None` if the attachment was not migrated yet.
+    fn key_token(&self) -> Option<&str>;
     /// The path to the attachment in S3.
     fn s3_key(&self) -> String {
-        format!(
-            "{}/{}/{}/{}",
+        format_s3_key(
             self.channel_id(),
             self.message_id(),
             self.id(),
-            self.filename()
+            self.key_token(),
+            self.filename(),
         )
     }
 }
@@ -75,10 +157,20 @@ pub struct FullAttachment {
     /// The ID of the channel the message was sent to.
     #[serde(skip)]
     channel_id: Snowflake<Channel>,
+    /// Rendering metadata of the file, extracted in the background after upload.
+    #[serde(flatten)]
+    #[builder(default)]
+    metadata: MediaMetadata,
+    /// The state of scanning the file for malware.
+    #[builder(default)]
+    scan_status: ScanStatus,
+    /// The random component of the S3 key and media URL.
+    #[builder(default = "Some(generate_key_token())")]
+    key_token: Option<String>,
 }
 
 impl FullAttachment {
-    /// Create a new attachment with the given ID, filename, and content.
+    /// Create a new attachment with the given ID, filename, and content, under a new key token.
     pub fn new(
         id: u8,
         filename: String,
@@ -94,6 +186,9 @@ impl FullAttachment {
             content_type,
             channel_id: channel.into(),
             message_id: message.into(),
+            metadata: MediaMetadata::default(),
+            scan_status: ScanStatus::default(),
+            key_token: Some(generate_key_token()),
         }
     }
 
@@ -101,6 +196,26 @@ impl FullAttachment {
         FullAttachmentBuilder::default()
     }
 
+    /// The contents of the file.
+    pub const fn content(&self) -> &Bytes {
+        &self.content
+    }
+
+    /// Set the rendering metadata of the file.
+    pub fn set_metadata(&mut self, metadata: MediaMetadata) {
+        self.metadata = metadata;
+    }
+
+    /// Set the state of scanning the file for malware.
+    pub const fn set_scan_status(&mut self, scan_status: ScanStatus) {
+        self.scan_status = scan_status;
+    }
+
+    /// Set the random component of the S3 key and media URL.
+    pub fn set_key_token(&mut self, key_token: Option<String>) {
+        self.key_token = key_token;
+    }
+
     /// Try to build a new [`Attachment`] from a multipart/form-data field.
     ///
     /// ## Arguments
@@ -108,6 +223,9 @@ impl FullAttachment {
     /// * `field` - The field to build from.
     /// * `channel` - The ID of the channel the message was sent to.
     /// * `message` - The ID of the message this attachment belongs to.
+    /// * `throttle` - The throttle limiting the rate at which the field contents are read.
+    /// * `uploader` - The user uploading the attachment.
+    /// * `max_size` - The maximum size of the attachment in bytes.
     ///
     /// ## Returns
     ///
@@ -117,11 +235,14 @@ impl FullAttachment {
     ///
     /// * [`RESTError::MissingField`] - If a required field is missing.
     /// * [`RESTError::MalformedField`] - If the attachment ID could not be parsed from the field name.
-    /// * [`RESTError::App`] - If the field contents could not be read.
+    /// * [`RESTError::App`] - If the field contents could not be read, or the attachment is larger than `max_size`.
     pub async fn try_from_field(
         field: Field<'_>,
         channel: impl Into<Snowflake<Channel>> + Send,
         message: impl Into<Snowflake<Message>> + Send,
+        throttle: &UploadThrottle,
+        uploader: impl Into<Snowflake<User>>,
+        max_size: usize,
     ) -> Result<Self, RESTError> {
         let mut builder = Self::builder();
 
@@ -151,15 +272,31 @@ impl FullAttachment {
             .parse::<Mime>()
             .map_err(|_| RESTError::MalformedField("content type could not be parsed".into()))?;
 
-        Ok(builder
+        builder
             .channel_id(channel)
             .message_id(message)
-            .content_type(content_type)
-            .content(field.bytes().await?)
-            .build()?)
+            .content_type(content_type);
+
+        let mut content = BytesMut::new();
+        let mut chunks = std::pin::pin!(throttle.throttle(uploader, field));
+
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            // Stop reading as soon as the attachment is too large
+            if content.len() + chunk.len() > max_size {
+                return Err(LimitExceeded {
+                    limit: Limit::MaxAttachmentSize,
+                    max: max_size,
+                }
+                .into());
+            }
+            content.extend_from_slice(&chunk);
+        }
+
+        Ok(builder.content(content.freeze()).build()?)
     }
 
-    /// Upload the attachment content to S3. This function is called implicitly by [`Attachment`]`::commit`.
+    /// Upload the attachment content to S3. This function is called implicitly by `Attachment::commit`.
     ///
     /// ## Errors
     ///
@@ -212,6 +349,18 @@ impl AttachmentLike for FullAttachment {
     fn mime(&self) -> Mime {
         self.content_type.parse().expect("Invalid MIME type")
     }
+
+    fn metadata(&self) -> &MediaMetadata {
+        &self.metadata
+    }
+
+    fn scan_status(&self) -> ScanStatus {
+        self.scan_status
+    }
+
+    fn key_token(&self) -> Option<&str> {
+        self.key_token.as_deref()
+    }
 }
 
 /// A partial attachment, as stored in the database.
@@ -221,6 +370,12 @@ pub struct PartialAttachmentRecord {
     message_id: Snowflake<Message>,
     channel_id: Snowflake<Channel>,
     content_type: String,
+    width: Option<i32>,
+    height: Option<i32>,
+    duration: Option<f64>,
+    blurhash: Option<String>,
+    scan_status: i16,
+    key_token: Option<String>,
 }
 
 /// A partial attachment, with the binary content not loaded.
@@ -239,6 +394,16 @@ pub struct PartialAttachment {
     /// The ID of the channel the message was sent to.
     #[serde(skip)]
     channel_id: Snowflake<Channel>,
+    /// Rendering metadata of the file, if it was extracted already.
+    #[serde(flatten)]
+    #[builder(default)]
+    metadata: MediaMetadata,
+    /// The state of scanning the file for malware.
+    #[builder(default)]
+    scan_status: ScanStatus,
+    /// The random component of the S3 key and media URL, `None` if the attachment was not migrated yet.
+    #[builder(default)]
+    key_token: Option<String>,
 }
 
 impl PartialAttachment {
@@ -256,9 +421,17 @@ impl PartialAttachment {
             content_type,
             channel_id: channel.into(),
             message_id: message.into(),
+            metadata: MediaMetadata::default(),
+            scan_status: ScanStatus::default(),
+            key_token: Some(generate_key_token()),
         }
     }
 
+    /// Set the random component of the S3 key and media URL.
+    pub fn set_key_token(&mut self, key_token: Option<String>) {
+        self.key_token = key_token;
+    }
+
     /// Download the attachment content from S3, turning this into a full attachment.
     ///
     /// ## Errors
@@ -273,6 +446,9 @@ impl PartialAttachment {
             self.channel_id,
             self.message_id,
         );
+        attachment.set_metadata(self.metadata);
+        attachment.set_scan_status(self.scan_status);
+        attachment.set_key_token(self.key_token);
         attachment.download(buckets).await?;
         Ok(attachment)
     }
@@ -292,13 +468,13 @@ impl PartialAttachment {
 
         Ok(sqlx::query_as!(
             PartialAttachmentRecord,
-            "SELECT id, filename, message_id, channel_id, content_type
+            "SELECT id, filename, message_id, channel_id, content_type, width, height, duration, blurhash, scan_status, key_token
             FROM attachments
             WHERE id = $1 AND message_id = $2",
             i32::from(id),
             message_id
         )
-        .fetch_optional(app.db.pool())
+        .fetch_optional(app.db.executor())
         .await?
         .map(Into::into))
     }
@@ -317,12 +493,69 @@ impl PartialAttachment {
 
         Ok(sqlx::query_as!(
             PartialAttachmentRecord,
-            "SELECT id, filename, message_id, channel_id, content_type
+            "SELECT id, filename, message_id, channel_id, content_type, width, height, duration, blurhash, scan_status, key_token
             FROM attachments
             WHERE message_id = $1",
             message_id
         )
-        .fetch_all(app.db.pool())
+        .fetch_all(app.db.executor())
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+    }
+
+    /// Fetches all attachments belonging to any of the given messages from the database,
+    /// ordered by message and then by their position within the message.
+    ///
+    /// ## Arguments
+    ///
+    /// * `messages` - The IDs of the messages to fetch attachments for
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the SQL query fails.
+    pub async fn fetch_all_for(
+        app: &ApplicationState,
+        messages: &[Snowflake<Message>],
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let message_ids: Vec<i64> = messages.iter().copied().map(Into::into).collect();
+
+        Ok(sqlx::query_as!(
+            PartialAttachmentRecord,
+            "SELECT id, filename, message_id, channel_id, content_type, width, height, duration, blurhash, scan_status, key_token
+            FROM attachments
+            WHERE message_id = ANY($1)
+            ORDER BY message_id, id",
+            &message_ids
+        )
+        .fetch_all(app.db.executor())
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+    }
+
+    /// Fetches attachments without a key token from the database, oldest first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `limit` - The maximum amount of attachments to fetch
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the SQL query fails.
+    pub async fn fetch_without_key_token(app: &ApplicationState, limit: i64) -> Result<Vec<Self>, sqlx::Error> {
+        Ok(sqlx::query_as!(
+            PartialAttachmentRecord,
+            "SELECT id, filename, message_id, channel_id, content_type, width, height, duration, blurhash, scan_status, key_token
+            FROM attachments
+            WHERE key_token IS NULL
+            ORDER BY message_id, id
+            LIMIT $1",
+            limit
+        )
+        .fetch_all(app.db.executor())
         .await?
         .into_iter()
         .map(Into::into)
@@ -338,6 +571,9 @@ impl From<FullAttachment> for PartialAttachment {
             channel_id: attachment.channel_id,
             message_id: attachment.message_id,
             content_type: attachment.content_type,
+            metadata: attachment.metadata,
+            scan_status: attachment.scan_status,
+            key_token: attachment.key_token,
         }
     }
 }
@@ -350,6 +586,14 @@ impl From<PartialAttachmentRecord> for PartialAttachment {
             channel_id: record.channel_id,
             message_id: record.message_id,
             content_type: record.content_type,
+            metadata: MediaMetadata::new(
+                record.width.map(|w| w as u32),
+                record.height.map(|h| h as u32),
+                record.duration,
+                record.blurhash,
+            ),
+            scan_status: record.scan_status.into(),
+            key_token: record.key_token,
         }
     }
 }
@@ -372,6 +616,16 @@ impl TryFrom<&ExtendedMessageRecord> for PartialAttachment {
                 .attachment_content_type
                 .clone()
                 .unwrap_or_else(|| "application/octet-stream".into()),
+            metadata: MediaMetadata::new(
+                record.attachment_width.map(|w| w as u32),
+                record.attachment_height.map(|h| h as u32),
+                record.attachment_duration,
+                record.attachment_blurhash.clone(),
+            ),
+            scan_status: record
+                .attachment_scan_status
+                .map_or_else(ScanStatus::default, Into::into),
+            key_token: record.attachment_key_token.clone(),
         })
     }
 }
@@ -398,4 +652,40 @@ impl AttachmentLike for PartialAttachment {
             .parse()
             .expect("Invalid MIME type stored in content_type")
     }
+
+    fn metadata(&self) -> &MediaMetadata {
+        &self.metadata
+    }
+
+    fn scan_status(&self) -> ScanStatus {
+        self.scan_status
+    }
+
+    fn key_token(&self) -> Option<&str> {
+        self.key_token.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_s3_key, generate_key_token, Channel, Message, Snowflake};
+
+    #[test]
+    fn test_format_s3_key() {
+        let (channel, message) = (Snowflake::<Channel>::new(1), Snowflake::<Message>::new(2));
+
+        assert_eq!(
+            format_s3_key(channel, message, 0, Some("abc"), "cat.png"),
+            "1/2/0/abc/cat.png"
+        );
+        assert_eq!(format_s3_key(channel, message, 0, None, "cat.png"), "1/2/0/cat.png");
+    }
+
+    #[test]
+    fn test_generate_key_token() {
+        let token = generate_key_token();
+        assert_eq!(token.len(), 32);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, generate_key_token());
+    }
 }
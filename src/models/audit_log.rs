@@ -0,0 +1,215 @@
+use serde::{Deserialize, Serialize};
+
+use super::{guild::Guild, snowflake::Snowflake, state::Config, user::User};
+
+/// The type of action recorded by an audit log entry.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum AuditLogAction {
+    /// An action that is not known to this version of the server.
+    Unknown = -1,
+    /// An automod rule was created.
+    AutoModRuleCreate = 0,
+    /// An automod rule was updated.
+    AutoModRuleUpdate = 1,
+    /// An automod rule was deleted.
+    AutoModRuleDelete = 2,
+    /// A message was blocked by automod.
+    AutoModBlockMessage = 3,
+    /// A message was flagged by automod.
+    AutoModFlagMessage = 4,
+    /// A message was deleted by automod.
+    AutoModDeleteMessage = 5,
+    /// A member was timed out by automod.
+    AutoModTimeoutMember = 6,
+    /// A member's timeout was set or cleared.
+    MemberTimeoutUpdate = 7,
+    /// A webhook was created.
+    WebhookCreate = 8,
+    /// A webhook was updated.
+    WebhookUpdate = 9,
+    /// A webhook was deleted.
+    WebhookDelete = 10,
+    /// A channel's message retention period was set or cleared.
+    ChannelRetentionUpdate = 11,
+    /// A report was reviewed, and action was taken.
+    ReportAction = 12,
+    /// A report was reviewed, and no action was needed.
+    ReportDismiss = 13,
+    /// A strike was issued to a member.
+    StrikeCreate = 14,
+    /// A strike was removed before it expired.
+    StrikeDelete = 15,
+    /// A member was banned for reaching the ban threshold of the guild's strike policy.
+    MemberBan = 16,
+    /// A ban was lifted.
+    MemberUnban = 17,
+    /// The guild's strike policy was updated.
+    StrikePolicyUpdate = 18,
+    /// A channel's settings were updated.
+    ChannelUpdate = 19,
+    /// Raid mode was enabled or disabled, either by the owner or automatically.
+    RaidModeUpdate = 20,
+    /// A channel was subscribed to a feed.
+    FeedCreate = 21,
+    /// A channel feed was updated.
+    FeedUpdate = 22,
+    /// A channel feed was deleted.
+    FeedDelete = 23,
+    /// A member was flagged for sharing a fingerprint with a user banned from the guild.
+    MemberEvasionFlag = 24,
+    /// The evasion flag of a member was dismissed.
+    MemberEvasionFlagDismiss = 25,
+    /// A channel was archived.
+    ChannelArchive = 26,
+    /// A channel was unarchived.
+    ChannelUnarchive = 27,
+    /// The sticky message of a channel was set or cleared.
+    ChannelStickyUpdate = 28,
+    /// The onboarding of the guild was updated.
+    OnboardingUpdate = 29,
+    /// A channel started following an announcement channel.
+    ChannelFollowCreate = 30,
+    /// A channel stopped following an announcement channel.
+    ChannelFollowDelete = 31,
+}
+
+impl From<i16> for AuditLogAction {
+    fn from(action: i16) -> Self {
+        match action {
+            0 => Self::AutoModRuleCreate,
+            1 => Self::AutoModRuleUpdate,
+            2 => Self::AutoModRuleDelete,
+            3 => Self::AutoModBlockMessage,
+            4 => Self::AutoModFlagMessage,
+            5 => Self::AutoModDeleteMessage,
+            6 => Self::AutoModTimeoutMember,
+            7 => Self::MemberTimeoutUpdate,
+            8 => Self::WebhookCreate,
+            9 => Self::WebhookUpdate,
+            10 => Self::WebhookDelete,
+            11 => Self::ChannelRetentionUpdate,
+            12 => Self::ReportAction,
+            13 => Self::ReportDismiss,
+            14 => Self::StrikeCreate,
+            15 => Self::StrikeDelete,
+            16 => Self::MemberBan,
+            17 => Self::MemberUnban,
+            18 => Self::StrikePolicyUpdate,
+            19 => Self::ChannelUpdate,
+            20 => Self::RaidModeUpdate,
+            21 => Self::FeedCreate,
+            22 => Self::FeedUpdate,
+            23 => Self::FeedDelete,
+            24 => Self::MemberEvasionFlag,
+            25 => Self::MemberEvasionFlagDismiss,
+            26 => Self::ChannelArchive,
+            27 => Self::ChannelUnarchive,
+            28 => Self::ChannelStickyUpdate,
+            29 => Self::OnboardingUpdate,
+            30 => Self::ChannelFollowCreate,
+            31 => Self::ChannelFollowDelete,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Represents an audit log record stored in the database.
+pub struct AuditLogRecord {
+    pub id: Snowflake<AuditLogEntry>,
+    pub guild_id: Snowflake<Guild>,
+    pub user_id: Option<i64>,
+    pub action_type: i16,
+    pub target_id: Option<i64>,
+    pub reason: Option<String>,
+}
+
+/// An entry in a guild's audit log.
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditLogEntry {
+    /// The ID of the entry. This also encodes when the action was taken.
+    id: Snowflake<Self>,
+    /// The guild the action was taken in.
+    guild_id: Snowflake<Guild>,
+    /// The user who performed the action, if any. Actions taken by the system have no user.
+    user_id: Option<Snowflake<User>>,
+    /// The type of action taken.
+    action_type: AuditLogAction,
+    /// The ID of the object the action was taken on, if any.
+    target_id: Option<Snowflake<()>>,
+    /// The reason for the action, if any.
+    reason: Option<String>,
+}
+
+impl AuditLogEntry {
+    /// Create a new audit log entry. Assigns a new snowflake to the entry.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration.
+    /// * `guild` - The guild the action was taken in.
+    /// * `user` - The user who performed the action, if any.
+    /// * `action_type` - The type of action taken.
+    /// * `target` - The ID of the object the action was taken on, if any.
+    /// * `reason` - The reason for the action, if any.
+    pub fn new(
+        config: &Config,
+        guild: impl Into<Snowflake<Guild>>,
+        user: Option<Snowflake<User>>,
+        action_type: AuditLogAction,
+        target: Option<Snowflake<()>>,
+        reason: Option<String>,
+    ) -> Self {
+        Self {
+            id: Snowflake::gen_new(config),
+            guild_id: guild.into(),
+            user_id: user,
+            action_type,
+            target_id: target,
+            reason,
+        }
+    }
+
+    /// Build an audit log entry directly from a database record.
+    pub fn from_record(record: AuditLogRecord) -> Self {
+        Self {
+            id: record.id,
+            guild_id: record.guild_id,
+            user_id: record.user_id.map(Snowflake::new),
+            action_type: AuditLogAction::from(record.action_type),
+            target_id: record.target_id.map(Snowflake::new),
+            reason: record.reason,
+        }
+    }
+
+    /// The ID of the entry.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The guild the action was taken in.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The user who performed the action, if any.
+    pub const fn user_id(&self) -> Option<Snowflake<User>> {
+        self.user_id
+    }
+
+    /// The type of action taken.
+    pub const fn action_type(&self) -> AuditLogAction {
+        self.action_type
+    }
+
+    /// The ID of the object the action was taken on, if any.
+    pub const fn target_id(&self) -> Option<Snowflake<()>> {
+        self.target_id
+    }
+
+    /// The reason for the action, if any.
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+}
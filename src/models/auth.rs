@@ -1,28 +1,116 @@
 use core::fmt::Debug;
+use std::{fmt, str::FromStr};
 
-use axum::{extract::FromRequestParts, http::request::Parts, RequestPartsExt};
-use axum_extra::{
-    headers::{authorization::Bearer, Authorization},
-    TypedHeader,
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, HeaderName},
 };
+use axum_extra::headers::{authorization::Bearer, Authorization, HeaderMapExt};
 use chrono::prelude::*;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, decode_header, encode, errors::ErrorKind, DecodingKey, EncodingKey, Header, Validation};
 use secrecy::{ExposeSecret, Secret};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 
 use super::{
     errors::{AuthError, RESTError},
     snowflake::Snowflake,
-    state::App,
+    state::{App, Config},
     user::User,
 };
 
+/// How long a token is valid for after it was issued, in seconds.
+pub const TOKEN_LIFETIME: i64 = 86400;
+/// The name of the cookie holding the session token of browser clients. It is not readable by scripts.
+pub const SESSION_COOKIE: &str = "chat_session";
+/// The name of the cookie holding the CSRF token of the session, readable by scripts.
+pub const CSRF_COOKIE: &str = "chat_csrf";
+/// The header requests authenticated by the session cookie must repeat the CSRF token in, unless they are read-only.
+pub const CSRF_HEADER: HeaderName = HeaderName::from_static("x-csrf-token");
+
+/// A secret tokens can be signed with, identified by the key ID in the `kid` header of the tokens it signed.
+#[derive(Debug, Clone)]
+pub struct SigningKey {
+    id: String,
+    secret: Secret<String>,
+}
+
+impl SigningKey {
+    /// Create a new signing key.
+    pub fn new(id: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            secret: Secret::new(secret.into()),
+        }
+    }
+
+    /// The ID of the key.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The secret of the key.
+    pub const fn secret(&self) -> &Secret<String> {
+        &self.secret
+    }
+}
+
+impl FromStr for SigningKey {
+    type Err = String;
+
+    /// Parse a key in the `id:secret` format.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((id, secret)) if !id.is_empty() && !secret.is_empty() => Ok(Self::new(id, secret)),
+            _ => Err("Signing keys must be in the 'id:secret' format".into()),
+        }
+    }
+}
+
+/// The keys tokens are signed and verified with.
+///
+/// New tokens are signed with the active key, while tokens signed with any of the previous keys remain valid.
+/// This allows the active key to be rotated without ending every session at once.
+#[derive(Debug, Clone, Copy)]
+pub struct SigningKeys<'a> {
+    active: &'a SigningKey,
+    previous: &'a [SigningKey],
+}
+
+impl<'a> SigningKeys<'a> {
+    /// Create a new set of signing keys.
+    ///
+    /// # Arguments
+    ///
+    /// * `active` - The key new tokens are signed with
+    /// * `previous` - Keys that are no longer used for signing, but tokens signed with them are still accepted
+    pub const fn new(active: &'a SigningKey, previous: &'a [SigningKey]) -> Self {
+        Self { active, previous }
+    }
+
+    /// The key new tokens are signed with.
+    pub const fn active(&self) -> &'a SigningKey {
+        self.active
+    }
+
+    /// Find a key by its ID.
+    pub fn get(&self, id: &str) -> Option<&'a SigningKey> {
+        self.iter().find(|key| key.id() == id)
+    }
+
+    /// All keys tokens are accepted from, starting with the active key.
+    pub fn iter(&self) -> impl Iterator<Item = &'a SigningKey> {
+        std::iter::once(self.active).chain(self.previous)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TokenData {
     /// The user id of the token owner
     user_id: Snowflake<User>,
     /// The expiration time of the token in seconds
-    /// Note: This field is validated by the jsonwebtoken crate
+    /// Note: This field is validated against the application clock in [`Token::decode`]
     exp: usize,
     /// Issued at time of the token in seconds
     /// Note: This field is validated by the jsonwebtoken crate
@@ -30,17 +118,17 @@ pub struct TokenData {
 }
 
 impl TokenData {
-    /// Create a new token data struct with the given user id and iat
+    /// Create a new token data struct with the given user id, issued at the given time
     ///
     /// # Arguments
     ///
     /// * `user_id` - The user id to store in the token
-    /// * `iat` - The issuer time of the token
-    fn new(user_id: Snowflake<User>, iat: usize) -> Self {
+    /// * `issued_at` - The issuer time of the token, the expiration time is derived from it
+    const fn new(user_id: Snowflake<User>, issued_at: DateTime<Utc>) -> Self {
         Self {
             user_id,
-            iat,
-            exp: Utc::now().timestamp() as usize + 86400,
+            iat: issued_at.timestamp() as usize,
+            exp: (issued_at.timestamp() + TOKEN_LIFETIME) as usize,
         }
     }
 
@@ -75,52 +163,83 @@ impl Token {
     /// # Arguments
     ///
     /// * `data` - The data to store in the token
-    /// * `secret` - The secret to sign the token with
+    /// * `key` - The key to sign the token with, its ID is stored in the `kid` header
     ///
     /// # Errors
     ///
     /// [`jsonwebtoken::errors::Error`] - If the token could not be generated.
-    fn new(secret: &Secret<String>, data: &TokenData) -> Result<Self, jsonwebtoken::errors::Error> {
+    fn new(key: &SigningKey, data: &TokenData) -> Result<Self, jsonwebtoken::errors::Error> {
+        let header = Header {
+            kid: Some(key.id().to_string()),
+            ..Header::default()
+        };
+
         Ok(Self {
             data: data.clone(),
             token: Secret::new(encode(
-                &Header::default(),
+                &header,
                 &data,
-                &EncodingKey::from_secret(secret.expose_secret().as_ref()),
+                &EncodingKey::from_secret(key.secret().expose_secret().as_ref()),
             )?),
         })
     }
 
-    /// Generate a new token for the given user, with the current time as the issue time.
+    /// Generate a new token for the given user.
     ///
     /// # Arguments
     ///
+    /// * `keys` - The signing keys, the token is signed with the active key
     /// * `user_id` - The id of the user to generate the token for
-    /// * `secret` - The secret to sign the token with
+    /// * `now` - The issue time of the token
     ///
     /// # Errors
     ///
     /// [`jsonwebtoken::errors::Error`] - If the token could not be generated.
-    pub fn new_for(secret: &Secret<String>, user_id: Snowflake<User>) -> Result<Self, jsonwebtoken::errors::Error> {
-        Self::new(secret, &TokenData::new(user_id, Utc::now().timestamp() as usize))
+    pub fn new_for(
+        keys: SigningKeys<'_>,
+        user_id: Snowflake<User>,
+        now: DateTime<Utc>,
+    ) -> Result<Self, jsonwebtoken::errors::Error> {
+        Self::new(keys.active(), &TokenData::new(user_id, now))
     }
 
-    /// Decode an existing token and return it. This will not validate the token.
+    /// Decode an existing token and return it.
+    /// This checks the signature and expiry, but not whether the token was revoked by a password change.
     ///
     /// # Arguments
     ///
+    /// * `keys` - The signing keys, the token must be signed with one of them
     /// * `token` - The token to decode
-    /// * `secret` - The secret to decode the token with
+    /// * `now` - The current time, the token must not have expired before it
     ///
     /// # Errors
     ///
-    /// [`jsonwebtoken::errors::Error`] - If the token could not be decoded.
-    fn decode(secret: &Secret<String>, token: &str) -> Result<Self, jsonwebtoken::errors::Error> {
-        let decoded = decode::<TokenData>(
-            token,
-            &DecodingKey::from_secret(secret.expose_secret().as_ref()),
-            &Validation::default(),
-        )?;
+    /// [`jsonwebtoken::errors::Error`] - If the token could not be decoded, was signed with an unknown key or has expired.
+    pub fn decode(keys: SigningKeys<'_>, token: &str, now: DateTime<Utc>) -> Result<Self, jsonwebtoken::errors::Error> {
+        // The expiry is checked against the application clock instead of the system time
+        let mut validation = Validation::default();
+        validation.validate_exp = false;
+
+        let decode_with = |key: &SigningKey| {
+            decode::<TokenData>(
+                token,
+                &DecodingKey::from_secret(key.secret().expose_secret().as_ref()),
+                &validation,
+            )
+        };
+
+        let decoded = match decode_header(token)?.kid {
+            Some(kid) => decode_with(keys.get(&kid).ok_or(ErrorKind::InvalidSignature)?)?,
+            // Tokens issued before key IDs were introduced have no kid, so all keys are tried
+            None => keys
+                .iter()
+                .map(decode_with)
+                .find(Result::is_ok)
+                .unwrap_or_else(|| Err(ErrorKind::InvalidSignature.into()))?,
+        };
+        if decoded.claims.exp() + (validation.leeway as usize) < now.timestamp() as usize {
+            return Err(ErrorKind::ExpiredSignature.into());
+        }
         Ok(Self {
             data: decoded.claims,
             token: Secret::new(token.to_string()),
@@ -140,7 +259,7 @@ impl Token {
     /// [`AuthError::InvalidToken`] - If the token is invalid.
     /// [`RESTError::NotFound`] - If the user entry for the token could not be found.
     pub async fn validate(app: App, token: &str) -> Result<Self, RESTError> {
-        let token = Self::decode(app.config.app_secret(), token)?;
+        let token = Self::decode(app.config.signing_keys(), token, app.clock.now())?;
         let stored_creds = StoredCredentials::fetch(app, token.data().user_id())
             .await
             .ok_or(RESTError::NotFound("User entry for token not found".into()))?;
@@ -155,6 +274,150 @@ impl Token {
     pub const fn data(&self) -> &TokenData {
         &self.data
     }
+
+    /// The CSRF token belonging to this token, which must be sent along with the session cookie.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - The signing keys, the CSRF token is derived with the active key
+    pub fn csrf_token(&self, keys: SigningKeys<'_>) -> String {
+        hex::encode(
+            csrf_mac(keys.active().secret(), self.expose_secret())
+                .finalize()
+                .into_bytes(),
+        )
+    }
+}
+
+/// Where the token of a request was found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenSource {
+    /// The `Authorization` header.
+    Header,
+    /// The session cookie, which browsers send automatically and therefore requires CSRF protection.
+    Cookie,
+}
+
+/// Find the token of a request, without decoding or validating it.
+/// The `Authorization` header takes precedence over the session cookie,
+/// which is only considered if cookie sessions are enabled.
+///
+/// # Arguments
+///
+/// * `config` - The application configuration
+/// * `headers` - The headers of the request
+pub fn find_token(config: &Config, headers: &HeaderMap) -> Option<(String, TokenSource)> {
+    if let Some(Authorization(bearer)) = headers.typed_get::<Authorization<Bearer>>() {
+        return Some((bearer.token().to_string(), TokenSource::Header));
+    }
+
+    if !config.auth_cookies_enabled() {
+        return None;
+    }
+    cookie_value(headers, SESSION_COOKIE).map(|token| (token.to_string(), TokenSource::Cookie))
+}
+
+/// Find the value of a cookie sent with a request.
+///
+/// # Arguments
+///
+/// * `headers` - The headers of the request
+/// * `name` - The name of the cookie
+pub fn cookie_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find_map(|(key, value)| (key == name && !value.is_empty()).then_some(value))
+}
+
+/// Check in constant time whether a CSRF token belongs to a session token.
+///
+/// # Arguments
+///
+/// * `keys` - The signing keys, the CSRF token may be derived with any of them
+/// * `token` - The session token
+/// * `csrf_token` - The CSRF token sent by the client
+pub fn verify_csrf_token(keys: SigningKeys<'_>, token: &str, csrf_token: &str) -> bool {
+    hex::decode(csrf_token).is_ok_and(|csrf_token| {
+        keys.iter()
+            .any(|key| csrf_mac(key.secret(), token).verify_slice(&csrf_token).is_ok())
+    })
+}
+
+/// The MAC a CSRF token is derived from. CSRF tokens are bound to their session, so they need not be stored.
+fn csrf_mac(secret: &Secret<String>, token: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(b"csrf:");
+    mac.update(token.as_bytes());
+    mac
+}
+
+/// The `SameSite` attribute of the session cookies, which controls whether browsers send them on cross-site requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SameSite {
+    /// Cookies are only sent on same-site requests.
+    Strict,
+    /// Cookies are also sent when navigating to the site from another site.
+    #[default]
+    Lax,
+    /// Cookies are sent on all requests. Requires the `Secure` attribute.
+    None,
+}
+
+impl FromStr for SameSite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "strict" => Ok(Self::Strict),
+            "lax" => Ok(Self::Lax),
+            "none" => Ok(Self::None),
+            _ => Err(format!("Unknown SameSite value: {s}")),
+        }
+    }
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Strict => write!(f, "Strict"),
+            Self::Lax => write!(f, "Lax"),
+            Self::None => write!(f, "None"),
+        }
+    }
+}
+
+/// The `Set-Cookie` header values that start a cookie session for the token, or end it if `token` is `None`.
+///
+/// # Arguments
+///
+/// * `config` - The application configuration, which determines the cookie attributes
+/// * `token` - The session token, or `None` to remove the cookies
+pub fn session_cookies(config: &Config, token: Option<&Token>) -> [String; 2] {
+    let (session, csrf, max_age) = token.map_or_else(
+        || (String::new(), String::new(), 0),
+        |token| {
+            (
+                token.expose_secret().clone(),
+                token.csrf_token(config.signing_keys()),
+                TOKEN_LIFETIME,
+            )
+        },
+    );
+
+    let mut attributes = format!("Path=/; Max-Age={max_age}; SameSite={}", config.auth_cookie_same_site());
+    if config.auth_cookie_secure() {
+        attributes.push_str("; Secure");
+    }
+
+    [
+        format!("{SESSION_COOKIE}={session}; {attributes}; HttpOnly"),
+        format!("{CSRF_COOKIE}={csrf}; {attributes}"),
+    ]
 }
 
 impl ExposeSecret<String> for Token {
@@ -177,14 +440,12 @@ impl Debug for Token {
 impl FromRequestParts<App> for Token {
     type Rejection = RESTError;
 
-    /// Extract a token from request Authorization header
+    /// Extract a token from the request Authorization header, or the session cookie of browser clients.
+    /// CSRF protection of cookie sessions is handled by the [`csrf_protection`](crate::rest::middleware::csrf_protection) middleware.
     async fn from_request_parts(parts: &mut Parts, state: &App) -> Result<Self, Self::Rejection> {
-        let TypedHeader(Authorization(bearer)) = parts
-            .extract::<TypedHeader<Authorization<Bearer>>>()
-            .await
-            .map_err(|_| AuthError::MissingCredentials)?;
+        let (token, _) = find_token(&state.config, &parts.headers).ok_or(AuthError::MissingCredentials)?;
         // Decode the user data
-        Self::validate(state.clone(), bearer.token()).await
+        Self::validate(state.clone(), &token).await
     }
 }
 
@@ -223,12 +484,12 @@ pub struct StoredCredentials {
 }
 
 impl StoredCredentials {
-    /// Create a new set of stored credentials.
-    pub fn new(user: impl Into<Snowflake<User>>, hash: String) -> Self {
+    /// Create a new set of stored credentials, changed at the given time.
+    pub fn new(user: impl Into<Snowflake<User>>, hash: String, now: DateTime<Utc>) -> Self {
         Self {
             user_id: user.into(),
             hash: Secret::new(hash),
-            last_changed: Utc::now(),
+            last_changed: now,
         }
     }
 
@@ -260,7 +521,7 @@ impl StoredCredentials {
             WHERE user_id = $1",
             user_id
         )
-        .fetch_optional(app.db.pool())
+        .fetch_optional(app.db.executor())
         .await
         .ok()??;
 
@@ -288,7 +549,7 @@ impl StoredCredentials {
             WHERE users.username = $1",
             username
         )
-        .fetch_optional(app.db.pool())
+        .fetch_optional(app.db.executor())
         .await
         .ok()??;
 
@@ -315,15 +576,151 @@ impl StoredCredentials {
             self.hash.expose_secret(),
             self.last_changed.timestamp()
         )
-        .execute(app.db.pool())
+        .execute(app.db.executor())
         .await?;
 
         Ok(())
     }
 
-    /// Update the password hash of the credentials, changing the last changed field with it.
-    pub fn update_hash(&mut self, new_hash: Secret<String>) {
+    /// Replace the password hash with a new hash of the same password, for example after the hashing parameters changed.
+    /// Unlike [`StoredCredentials::update_hash`], existing sessions remain valid.
+    ///
+    /// The hash is only replaced in the database if the password was not changed since the credentials were fetched.
+    ///
+    /// # Errors
+    ///
+    /// * [`sqlx::Error`] - If the query fails.
+    pub async fn rehash(&mut self, app: App, new_hash: Secret<String>) -> Result<(), sqlx::Error> {
+        let user_id: i64 = self.user_id.into();
+
+        sqlx::query!(
+            "UPDATE secrets SET password = $3 WHERE user_id = $1 AND password = $2",
+            user_id,
+            self.hash.expose_secret(),
+            new_hash.expose_secret(),
+        )
+        .execute(app.db.executor())
+        .await?;
+
+        self.hash = new_hash;
+        Ok(())
+    }
+
+    /// Update the password hash of the credentials, setting the last changed field to `now`.
+    pub fn update_hash(&mut self, new_hash: Secret<String>, now: DateTime<Utc>) {
         self.hash = new_hash;
-        self.last_changed = Utc::now();
+        self.last_changed = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, HeaderMap, HeaderValue};
+    use chrono::{TimeDelta, Utc};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use secrecy::ExposeSecret;
+
+    use super::{cookie_value, verify_csrf_token, SigningKey, SigningKeys, Token, TokenData, TOKEN_LIFETIME};
+    use crate::models::snowflake::Snowflake;
+
+    #[test]
+    fn test_cookie_value() {
+        let mut headers = HeaderMap::new();
+        headers.append(header::COOKIE, HeaderValue::from_static("theme=dark; chat_session=abc"));
+        headers.append(header::COOKIE, HeaderValue::from_static("chat_csrf=def; empty="));
+
+        assert_eq!(cookie_value(&headers, "chat_session"), Some("abc"));
+        assert_eq!(cookie_value(&headers, "chat_csrf"), Some("def"));
+        assert_eq!(cookie_value(&headers, "empty"), None);
+        assert_eq!(cookie_value(&headers, "session"), None);
+    }
+
+    #[test]
+    fn test_csrf_token() {
+        let key = SigningKey::new("a", "secret");
+        let other_key = SigningKey::new("b", "other");
+        let keys = SigningKeys::new(&key, &[]);
+        let token = Token::new_for(keys, Snowflake::new(1), Utc::now()).expect("Token should be created");
+        let other = Token::new_for(keys, Snowflake::new(2), Utc::now()).expect("Token should be created");
+        let csrf_token = token.csrf_token(keys);
+
+        assert!(verify_csrf_token(keys, token.expose_secret(), &csrf_token));
+        assert!(!verify_csrf_token(keys, other.expose_secret(), &csrf_token));
+        assert!(!verify_csrf_token(
+            SigningKeys::new(&other_key, &[]),
+            token.expose_secret(),
+            &csrf_token
+        ));
+        assert!(!verify_csrf_token(keys, token.expose_secret(), ""));
+        assert!(!verify_csrf_token(keys, token.expose_secret(), "not hex"));
+
+        // CSRF tokens derived with a previous key remain valid
+        let previous = [key.clone()];
+        assert!(verify_csrf_token(
+            SigningKeys::new(&other_key, &previous),
+            token.expose_secret(),
+            &csrf_token
+        ));
+    }
+
+    #[test]
+    fn test_decode_expiry() {
+        let key = SigningKey::new("a", "secret");
+        let keys = SigningKeys::new(&key, &[]);
+        let issued_at = Utc::now() - TimeDelta::days(7);
+        let token = Token::new_for(keys, Snowflake::new(1), issued_at).expect("Token should be created");
+
+        let decoded = Token::decode(keys, token.expose_secret(), issued_at + TimeDelta::hours(1))
+            .expect("Token should be valid before it expires");
+        assert_eq!(decoded.data().iat(), issued_at.timestamp() as usize);
+        assert_eq!(decoded.data().exp(), (issued_at.timestamp() + TOKEN_LIFETIME) as usize);
+
+        assert!(Token::decode(keys, token.expose_secret(), issued_at + TimeDelta::days(2)).is_err());
+        let other_key = SigningKey::new("a", "other");
+        assert!(Token::decode(SigningKeys::new(&other_key, &[]), token.expose_secret(), issued_at).is_err());
+    }
+
+    #[test]
+    fn test_decode_rotation() {
+        let old = SigningKey::new("old", "secret");
+        let new = SigningKey::new("new", "other");
+        let previous = [old.clone()];
+        let rotated = SigningKeys::new(&new, &previous);
+        let now = Utc::now();
+
+        // Tokens signed with a previous key remain valid until the key is removed
+        let token =
+            Token::new_for(SigningKeys::new(&old, &[]), Snowflake::new(1), now).expect("Token should be created");
+        assert!(Token::decode(rotated, token.expose_secret(), now).is_ok());
+        assert!(Token::decode(SigningKeys::new(&new, &[]), token.expose_secret(), now).is_err());
+
+        // New tokens are signed with the active key
+        let token = Token::new_for(rotated, Snowflake::new(1), now).expect("Token should be created");
+        assert!(Token::decode(SigningKeys::new(&new, &[]), token.expose_secret(), now).is_ok());
+
+        // The key ID must match the key the token was signed with
+        let renamed = SigningKey::new("renamed", "other");
+        assert!(Token::decode(SigningKeys::new(&renamed, &[]), token.expose_secret(), now).is_err());
+
+        // Tokens without a key ID are checked against every key
+        let legacy = encode(
+            &Header::default(),
+            &TokenData::new(Snowflake::new(1), now),
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .expect("Token should be created");
+        assert!(Token::decode(rotated, &legacy, now).is_ok());
+        assert!(Token::decode(SigningKeys::new(&new, &[]), &legacy, now).is_err());
+    }
+
+    #[test]
+    fn test_parse_signing_key() {
+        let key: SigningKey = "2024-07:some:secret".parse().expect("Key should be valid");
+        assert_eq!(key.id(), "2024-07");
+        assert_eq!(key.secret().expose_secret(), "some:secret");
+
+        assert!("secret".parse::<SigningKey>().is_err());
+        assert!(":secret".parse::<SigningKey>().is_err());
+        assert!("id:".parse::<SigningKey>().is_err());
     }
 }
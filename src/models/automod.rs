@@ -0,0 +1,443 @@
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::{Hash, Hasher},
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use regex::{Regex, RegexSet, RegexSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    errors::BuildError,
+    guild::Guild,
+    requests::{CreateAutoModRule, UpdateAutoModRule},
+    snowflake::Snowflake,
+    state::Config,
+    user::User,
+};
+
+/// The maximum amount of automod rules a guild may have.
+pub const MAX_RULES_PER_GUILD: usize = 25;
+/// The maximum amount of patterns a single rule may have.
+const MAX_PATTERNS: usize = 100;
+/// The maximum length of a single pattern.
+const MAX_PATTERN_LEN: usize = 256;
+/// The maximum compiled size of a rule's patterns, in bytes.
+const MAX_COMPILED_SIZE: usize = 1024 * 1024;
+/// The maximum duration of a member timeout, in seconds. (28 days)
+pub const MAX_TIMEOUT_DURATION: i64 = 28 * 24 * 60 * 60;
+
+/// The window in which messages are counted towards the spam heuristics.
+const SPAM_WINDOW: Duration = Duration::from_secs(5);
+/// The amount of messages within [`SPAM_WINDOW`] that are considered spam.
+const SPAM_MAX_MESSAGES: usize = 5;
+/// The amount of consecutive identical messages within [`SPAM_WINDOW`] that are considered spam.
+const SPAM_MAX_DUPLICATES: usize = 3;
+/// The amount of tracked members after which stale spam history is pruned.
+const SPAM_PRUNE_THRESHOLD: usize = 10_000;
+
+static INVITE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)(?:discord(?:app)?\.com/invite|discord\.gg|t\.me|chat\.whatsapp\.com)/[a-z0-9_+-]+")
+        .expect("Failed to compile invite regex")
+});
+
+/// The condition that causes an automod rule to trigger.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum AutoModTrigger {
+    /// The message contains one of the keywords in the rule's patterns. Matching is case-insensitive.
+    Keyword = 0,
+    /// The message matches one of the regular expressions in the rule's patterns.
+    Regex = 1,
+    /// The message contains an invite link to another platform.
+    InviteLink = 2,
+    /// The author is sending messages too quickly, or is repeating the same message.
+    Spam = 3,
+}
+
+impl From<i16> for AutoModTrigger {
+    fn from(trigger: i16) -> Self {
+        match trigger {
+            0 => Self::Keyword,
+            1 => Self::Regex,
+            2 => Self::InviteLink,
+            _ => Self::Spam,
+        }
+    }
+}
+
+/// The action taken when an automod rule triggers.
+///
+/// Actions are ordered by severity, if multiple rules trigger, the most severe action is taken.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum AutoModAction {
+    /// The message is sent, but an entry is added to the audit log.
+    Flag = 0,
+    /// The message appears to be sent to the author, but is silently discarded.
+    Delete = 1,
+    /// The message is rejected and the author is notified.
+    Block = 2,
+    /// The message is rejected and the author is timed out.
+    Timeout = 3,
+}
+
+impl From<i16> for AutoModAction {
+    fn from(action: i16) -> Self {
+        match action {
+            0 => Self::Flag,
+            1 => Self::Delete,
+            3 => Self::Timeout,
+            _ => Self::Block,
+        }
+    }
+}
+
+/// Represents an automod rule record stored in the database.
+pub struct AutoModRuleRecord {
+    pub id: Snowflake<AutoModRule>,
+    pub guild_id: Snowflake<Guild>,
+    pub name: String,
+    pub trigger_type: i16,
+    pub patterns: Vec<String>,
+    pub action: i16,
+    pub timeout_duration: Option<i64>,
+    pub enabled: bool,
+    pub issue_strike: bool,
+}
+
+/// A rule that is evaluated against every message sent in a guild.
+#[derive(Serialize, Debug, Clone)]
+pub struct AutoModRule {
+    /// The ID of the rule.
+    id: Snowflake<Self>,
+    /// The guild this rule belongs to.
+    guild_id: Snowflake<Guild>,
+    /// The name of the rule.
+    name: String,
+    /// The condition that causes the rule to trigger.
+    trigger_type: AutoModTrigger,
+    /// The keywords or regular expressions to match, depending on the trigger type.
+    patterns: Vec<String>,
+    /// The action taken when the rule triggers.
+    action: AutoModAction,
+    /// The duration of the timeout in seconds, if the action is a timeout.
+    timeout_duration: Option<i64>,
+    /// Whether the author is issued a strike when the rule triggers.
+    issue_strike: bool,
+    /// Whether the rule is currently enabled.
+    enabled: bool,
+    /// The compiled form of the rule's patterns.
+    #[serde(skip)]
+    matcher: Option<RegexSet>,
+}
+
+impl AutoModRule {
+    /// The ID of the rule.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The guild this rule belongs to.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The name of the rule.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The condition that causes the rule to trigger.
+    pub const fn trigger_type(&self) -> AutoModTrigger {
+        self.trigger_type
+    }
+
+    /// The keywords or regular expressions to match, depending on the trigger type.
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// The action taken when the rule triggers.
+    pub const fn action(&self) -> AutoModAction {
+        self.action
+    }
+
+    /// The duration of the timeout in seconds, if the action is a timeout.
+    pub const fn timeout_duration(&self) -> Option<i64> {
+        self.timeout_duration
+    }
+
+    /// Whether the author is issued a strike when the rule triggers.
+    pub const fn issue_strike(&self) -> bool {
+        self.issue_strike
+    }
+
+    /// Whether the rule is currently enabled.
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Build a rule from a database record.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the stored patterns fail to compile.
+    pub fn from_record(record: AutoModRuleRecord) -> Result<Self, BuildError> {
+        let trigger_type = AutoModTrigger::from(record.trigger_type);
+        let matcher = compile_patterns(trigger_type, &record.patterns)?;
+
+        Ok(Self {
+            id: record.id,
+            guild_id: record.guild_id,
+            name: record.name,
+            trigger_type,
+            patterns: record.patterns,
+            action: AutoModAction::from(record.action),
+            timeout_duration: record.timeout_duration,
+            issue_strike: record.issue_strike,
+            enabled: record.enabled,
+            matcher,
+        })
+    }
+
+    /// Create a new rule from a creation payload. Assigns a new snowflake to the rule.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration.
+    /// * `guild` - The guild the rule belongs to.
+    /// * `payload` - The payload to create the rule from.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the payload is invalid.
+    pub fn from_payload(
+        config: &Config,
+        guild: impl Into<Snowflake<Guild>>,
+        payload: CreateAutoModRule,
+    ) -> Result<Self, BuildError> {
+        let mut rule = Self {
+            id: Snowflake::gen_new(config),
+            guild_id: guild.into(),
+            name: payload.name,
+            trigger_type: payload.trigger_type,
+            patterns: payload.patterns,
+            action: payload.action,
+            timeout_duration: payload.timeout_duration,
+            issue_strike: payload.issue_strike.unwrap_or(false),
+            enabled: payload.enabled.unwrap_or(true),
+            matcher: None,
+        };
+        rule.validate()?;
+        Ok(rule)
+    }
+
+    /// Update the rule with the given payload.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the resulting rule is invalid.
+    pub fn update(&mut self, payload: UpdateAutoModRule) -> Result<(), BuildError> {
+        if let Some(name) = payload.name {
+            self.name = name;
+        }
+        if let Some(patterns) = payload.patterns {
+            self.patterns = patterns;
+        }
+        if let Some(action) = payload.action {
+            self.action = action;
+        }
+        if let Some(timeout_duration) = payload.timeout_duration {
+            self.timeout_duration = Some(timeout_duration);
+        }
+        if let Some(issue_strike) = payload.issue_strike {
+            self.issue_strike = issue_strike;
+        }
+        if let Some(enabled) = payload.enabled {
+            self.enabled = enabled;
+        }
+        self.validate()
+    }
+
+    /// Validate the rule and compile its patterns.
+    fn validate(&mut self) -> Result<(), BuildError> {
+        self.name = self.name.trim().to_string();
+
+        if self.name.is_empty() || self.name.len() > 100 {
+            return Err(BuildError::ValidationError(
+                "Rule name must be between 1 and 100 characters long".into(),
+            ));
+        }
+
+        match self.trigger_type {
+            AutoModTrigger::Keyword | AutoModTrigger::Regex => {
+                if self.patterns.is_empty() || self.patterns.len() > MAX_PATTERNS {
+                    return Err(BuildError::ValidationError(format!(
+                        "Rule must have between 1 and {MAX_PATTERNS} patterns"
+                    )));
+                }
+                if self.patterns.iter().any(|p| p.is_empty() || p.len() > MAX_PATTERN_LEN) {
+                    return Err(BuildError::ValidationError(format!(
+                        "Patterns must be between 1 and {MAX_PATTERN_LEN} characters long"
+                    )));
+                }
+            }
+            AutoModTrigger::InviteLink | AutoModTrigger::Spam => {
+                if !self.patterns.is_empty() {
+                    return Err(BuildError::ValidationError(
+                        "Patterns are not supported for this trigger type".into(),
+                    ));
+                }
+            }
+        }
+
+        if self.action == AutoModAction::Timeout {
+            match self.timeout_duration {
+                Some(duration) if (1..=MAX_TIMEOUT_DURATION).contains(&duration) => {}
+                _ => {
+                    return Err(BuildError::ValidationError(format!(
+                        "Timeout duration must be between 1 and {MAX_TIMEOUT_DURATION} seconds"
+                    )))
+                }
+            }
+        } else {
+            self.timeout_duration = None;
+        }
+
+        self.matcher = compile_patterns(self.trigger_type, &self.patterns)?;
+        Ok(())
+    }
+
+    /// Check if the given message content matches this rule's patterns.
+    ///
+    /// Spam rules are not content-based and never match here, see [`AutoMod::evaluate`].
+    pub fn matches(&self, content: &str) -> bool {
+        match self.trigger_type {
+            AutoModTrigger::Keyword | AutoModTrigger::Regex => {
+                self.matcher.as_ref().is_some_and(|m| m.is_match(content))
+            }
+            AutoModTrigger::InviteLink => INVITE_REGEX.is_match(content),
+            AutoModTrigger::Spam => false,
+        }
+    }
+}
+
+/// Compile the patterns of a rule into a single [`RegexSet`].
+fn compile_patterns(trigger_type: AutoModTrigger, patterns: &[String]) -> Result<Option<RegexSet>, BuildError> {
+    let patterns: Vec<String> = match trigger_type {
+        AutoModTrigger::Keyword => patterns.iter().map(|p| regex::escape(p)).collect(),
+        AutoModTrigger::Regex => patterns.to_vec(),
+        AutoModTrigger::InviteLink | AutoModTrigger::Spam => return Ok(None),
+    };
+
+    RegexSetBuilder::new(patterns)
+        .case_insensitive(trigger_type == AutoModTrigger::Keyword)
+        .size_limit(MAX_COMPILED_SIZE)
+        .build()
+        .map(Some)
+        .map_err(|e| BuildError::ValidationError(format!("Invalid pattern: {e}")))
+}
+
+/// The time and content hash of the recent messages of a member.
+type MessageHistory = VecDeque<(Instant, Option<u64>)>;
+
+/// Evaluates automod rules and keeps track of the state required by the spam heuristics.
+#[derive(Debug, Clone, Default)]
+pub struct AutoMod {
+    /// The time and content hash of recent messages sent by each member.
+    history: DashMap<(Snowflake<Guild>, Snowflake<User>), MessageHistory>,
+}
+
+impl AutoMod {
+    /// Create a new automod evaluator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Evaluate the given rules against a message that is about to be sent.
+    ///
+    /// ## Arguments
+    ///
+    /// * `rules` - The rules of the guild the message is sent in.
+    /// * `guild` - The guild the message is sent in.
+    /// * `user` - The author of the message.
+    /// * `content` - The content of the message, if any.
+    ///
+    /// ## Returns
+    ///
+    /// All enabled rules that were triggered by the message.
+    pub fn evaluate<'a>(
+        &self,
+        rules: &'a [AutoModRule],
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        content: Option<&str>,
+    ) -> Vec<&'a AutoModRule> {
+        let enabled = rules.iter().filter(|r| r.enabled());
+
+        let is_spam = if enabled.clone().any(|r| r.trigger_type() == AutoModTrigger::Spam) {
+            self.record_message(guild.into(), user.into(), content)
+        } else {
+            false
+        };
+
+        enabled
+            .filter(|r| match r.trigger_type() {
+                AutoModTrigger::Spam => is_spam,
+                _ => content.is_some_and(|c| r.matches(c)),
+            })
+            .collect()
+    }
+
+    /// Record a message in the spam history of a member.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the member's recent messages are considered spam.
+    fn record_message(&self, guild: Snowflake<Guild>, user: Snowflake<User>, content: Option<&str>) -> bool {
+        let now = Instant::now();
+        let hash = content.filter(|c| !c.is_empty()).map(|c| {
+            let mut hasher = DefaultHasher::new();
+            c.hash(&mut hasher);
+            hasher.finish()
+        });
+
+        if self.history.len() > SPAM_PRUNE_THRESHOLD {
+            self.prune();
+        }
+
+        let mut history = self.history.entry((guild, user)).or_default();
+
+        while history
+            .front()
+            .is_some_and(|(t, _)| now.duration_since(*t) > SPAM_WINDOW)
+        {
+            history.pop_front();
+        }
+        history.push_back((now, hash));
+
+        if history.len() > SPAM_MAX_MESSAGES {
+            history.pop_front();
+            return true;
+        }
+
+        hash.is_some()
+            && history.len() >= SPAM_MAX_DUPLICATES
+            && history.iter().rev().take(SPAM_MAX_DUPLICATES).all(|(_, h)| *h == hash)
+    }
+
+    /// Remove the spam history of members that have not sent a message recently.
+    fn prune(&self) {
+        let now = Instant::now();
+        self.history.retain(|_, history| {
+            history
+                .back()
+                .is_some_and(|(t, _)| now.duration_since(*t) <= SPAM_WINDOW)
+        });
+    }
+}
@@ -2,7 +2,7 @@ use std::fmt::Debug;
 use std::hash::{DefaultHasher, Hash, Hasher};
 
 use super::{
-    bucket::{Bucket, Buckets},
+    bucket::{Bucket, Buckets, ContentClass},
     data_uri::DataUri,
     errors::{AppError, BuildError},
     guild::Guild,
@@ -41,8 +41,8 @@ where
     /// The kind of object that holds this avatar.
     type HolderType;
 
-    /// The bucket this kind of avatar is stored in.
-    fn bucket(&self) -> &'static str;
+    /// The class of content this kind of avatar is stored as.
+    fn content_class(&self) -> ContentClass;
 }
 
 /// Represents a guild's icon
@@ -53,8 +53,8 @@ impl AvatarKind for GuildAvatar {
     type HolderType = Guild;
 
     #[inline]
-    fn bucket(&self) -> &'static str {
-        "guilds"
+    fn content_class(&self) -> ContentClass {
+        ContentClass::Icons
     }
 }
 
@@ -66,8 +66,8 @@ impl AvatarKind for UserAvatar {
     type HolderType = User;
 
     #[inline]
-    fn bucket(&self) -> &'static str {
-        "users"
+    fn content_class(&self) -> ContentClass {
+        ContentClass::Avatars
     }
 }
 
@@ -86,7 +86,7 @@ pub trait AvatarLike<K: AvatarKind> {
 
     /// The bucket this avatar is stored in S3.
     fn bucket<'a>(&self, s3: &'a Buckets) -> Bucket<'a> {
-        s3.get_bucket(self.kind().bucket())
+        s3.get(self.kind().content_class())
     }
 
     /// The path to the attachment in S3.
@@ -255,7 +255,7 @@ impl<K: AvatarKind> PartialAvatar<K> {
     /// * If the MIME type is not an image.
     pub fn new(avatar_hash: String, holder_id: impl Into<Snowflake<K::HolderType>>) -> Result<Self, BuildError> {
         let mime = {
-            avatar_hash.split('_').last().map_or_else(
+            avatar_hash.split('_').next_back().map_or_else(
                 || Err(BuildError::ValidationError("no MIME type at end of avatar hash".into())),
                 |file_ext| match file_ext {
                     "png" => Ok(mime::IMAGE_PNG),
@@ -1,30 +1,206 @@
-use std::sync::{Arc, Weak};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Weak},
+};
 
 use aws_sdk_s3::{
-    primitives::ByteStream,
-    types::{Delete, Object, ObjectIdentifier},
+    config::http::HttpResponse,
+    error::SdkError,
+    primitives::{ByteStream, ByteStreamError},
+    types::{
+        BucketLifecycleConfiguration, Delete, ExpirationStatus, LifecycleExpiration, LifecycleRule,
+        LifecycleRuleFilter, Object, ObjectIdentifier,
+    },
     Client,
 };
 use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use futures::Stream;
 use mime::Mime;
 
-use super::{channel::Channel, errors::AppError, guild::Guild, snowflake::Snowflake, state::ApplicationState};
+use super::{
+    attachment::format_s3_key, channel::Channel, circuit_breaker::CircuitBreaker, errors::AppError, guild::Guild,
+    message::Message, snowflake::Snowflake, state::ApplicationState,
+};
 
 pub type S3Client = Client;
 
+/// The amount of consecutive failed S3 requests after which S3 is considered unavailable.
+const FAILURE_THRESHOLD: u32 = 5;
+
+/// The maximum amount of objects S3 deletes in a single request.
+const DELETE_BATCH_SIZE: usize = 1000;
+
+/// The ID of the lifecycle rule the application manages on its buckets.
+const LIFECYCLE_RULE_ID: &str = "chat-expire";
+
+/// A class of content stored in S3. Each class is stored in its own bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentClass {
+    /// Message attachments.
+    Attachments,
+    /// User avatars.
+    Avatars,
+    /// Guild icons.
+    Icons,
+    /// Custom emojis.
+    Emoji,
+    /// Data exports, which are only kept until they are downloaded.
+    Exports,
+    /// External media cached by the media proxy.
+    Proxy,
+}
+
+impl ContentClass {
+    /// All content classes.
+    pub const ALL: [Self; 6] = [
+        Self::Attachments,
+        Self::Avatars,
+        Self::Icons,
+        Self::Emoji,
+        Self::Exports,
+        Self::Proxy,
+    ];
+
+    /// The name of the class, as used in the names of its environment variables.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Attachments => "attachments",
+            Self::Avatars => "avatars",
+            Self::Icons => "icons",
+            Self::Emoji => "emoji",
+            Self::Exports => "exports",
+            Self::Proxy => "proxy",
+        }
+    }
+
+    /// The name of the bucket this class is stored in, unless configured otherwise.
+    /// Avatars and icons keep the bucket names they had before content classes were configurable.
+    pub const fn default_bucket_name(self) -> &'static str {
+        match self {
+            Self::Avatars => "users",
+            Self::Icons => "guilds",
+            _ => self.as_str(),
+        }
+    }
+
+    /// The amount of days after which objects of this class expire, unless configured otherwise.
+    pub const fn default_expire_after_days(self) -> Option<i32> {
+        match self {
+            Self::Exports => Some(7),
+            _ => None,
+        }
+    }
+}
+
+/// Where a class of content is stored, and for how long.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketConfig {
+    name: String,
+    expire_after_days: Option<i32>,
+}
+
+impl BucketConfig {
+    /// Create a new bucket configuration.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `expire_after_days` - The amount of days after which objects are deleted by S3. If `None`, they are kept forever.
+    pub fn new(name: impl Into<String>, expire_after_days: Option<i32>) -> Self {
+        Self {
+            name: name.into(),
+            expire_after_days,
+        }
+    }
+
+    /// The name of the bucket.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The amount of days after which objects are deleted by S3, if they expire.
+    pub const fn expire_after_days(&self) -> Option<i32> {
+        self.expire_after_days
+    }
+}
+
+/// The bucket configuration of every content class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketConfigs([BucketConfig; ContentClass::ALL.len()]);
+
+impl BucketConfigs {
+    /// Create the bucket configuration of every content class.
+    ///
+    /// ## Arguments
+    ///
+    /// * `f` - Returns the configuration of a content class.
+    pub fn from_fn(f: impl Fn(ContentClass) -> BucketConfig) -> Self {
+        Self(ContentClass::ALL.map(f))
+    }
+
+    /// The configuration of a content class.
+    pub const fn get(&self, class: ContentClass) -> &BucketConfig {
+        &self.0[class as usize]
+    }
+
+    /// Check that all bucket names are valid S3 bucket names, and that no two classes share a bucket.
+    ///
+    /// ## Errors
+    ///
+    /// A description of the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, class) in ContentClass::ALL.into_iter().enumerate() {
+            let name = self.get(class).name();
+            if !is_valid_bucket_name(name) {
+                return Err(format!("'{name}' is not a valid bucket name for {}", class.as_str()));
+            }
+            if let Some(other) = ContentClass::ALL[..i].iter().find(|c| self.get(**c).name() == name) {
+                return Err(format!(
+                    "{} and {} are both stored in bucket '{name}'",
+                    other.as_str(),
+                    class.as_str()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for BucketConfigs {
+    fn default() -> Self {
+        Self::from_fn(|class| BucketConfig::new(class.default_bucket_name(), class.default_expire_after_days()))
+    }
+}
+
+/// Check whether a name is a valid S3 bucket name: 3 to 63 lowercase letters, digits, dots and hyphens,
+/// starting and ending with a letter or digit.
+fn is_valid_bucket_name(name: &str) -> bool {
+    (3..=63).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'.' || b == b'-')
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+}
+
 /// All S3 buckets used by the application.
 #[derive(Debug, Clone)]
 pub struct Buckets {
     app: Weak<ApplicationState>,
     client: S3Client,
+    configs: BucketConfigs,
+    breaker: CircuitBreaker,
 }
 
 impl Buckets {
-    /// Create all buckets from the given config.
-    pub fn new(client: S3Client) -> Self {
+    /// Create all buckets from the given configuration.
+    pub fn new(client: S3Client, configs: BucketConfigs) -> Self {
         Self {
             client,
             app: Weak::new(),
+            configs,
+            breaker: CircuitBreaker::new("s3", FAILURE_THRESHOLD),
         }
     }
 
@@ -40,18 +216,65 @@ impl Buckets {
         &self.client
     }
 
-    pub const fn get_bucket(&self, name: &'static str) -> Bucket {
-        Bucket::new(self, name)
+    /// Whether S3 is currently considered available.
+    /// If not, all bucket operations fail with [`AppError::StorageUnavailable`] until S3 recovers.
+    pub fn is_available(&self) -> bool {
+        !self.breaker.is_open()
+    }
+
+    /// Fail fast if S3 is known to be unavailable.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
+    fn ensure_available(&self) -> Result<(), AppError> {
+        if self.is_available() {
+            Ok(())
+        } else {
+            Err(AppError::StorageUnavailable)
+        }
+    }
+
+    /// Record the outcome of an S3 request. Only errors indicating an outage count as failures,
+    /// errors caused by the request itself (e.g. a missing key) do not.
+    fn record<T, E>(&self, result: &Result<T, SdkError<E, HttpResponse>>) {
+        match result {
+            Err(SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_)) => {
+                self.breaker.record_failure();
+            }
+            Err(SdkError::ServiceError(e)) if e.raw().status().is_server_error() => {
+                self.breaker.record_failure();
+            }
+            _ => self.breaker.record_success(),
+        }
+    }
+
+    /// Check if S3 is reachable, updating its availability accordingly.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if S3 is reachable, `false` otherwise.
+    pub async fn probe(&self) -> bool {
+        let result = self.client.list_buckets().send().await;
+        self.record(&result);
+        result.is_ok()
+    }
+
+    /// The bucket a class of content is stored in.
+    pub fn get(&self, class: ContentClass) -> Bucket<'_> {
+        Bucket::new(self, self.configs.get(class))
     }
 
     /// The attachments bucket.
     /// It is responsible for storing all message attachments.
-    pub const fn attachments(&self) -> Bucket {
-        self.get_bucket("attachments")
+    pub fn attachments(&self) -> Bucket<'_> {
+        self.get(ContentClass::Attachments)
     }
 
-    pub const fn users(&self) -> Bucket {
-        self.get_bucket("users")
+    /// The media proxy bucket.
+    /// It is responsible for caching external media fetched by the media proxy.
+    pub fn proxy(&self) -> Bucket<'_> {
+        self.get(ContentClass::Proxy)
     }
 
     /// Remove all S3 data for the given channel.
@@ -63,6 +286,7 @@ impl Buckets {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
     pub async fn remove_all_for_channel(&self, channel: impl Into<Snowflake<Channel>>) -> Result<(), AppError> {
         let bucket = self.attachments();
         let channel_id: Snowflake<Channel> = channel.into();
@@ -91,11 +315,12 @@ impl Buckets {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
     pub async fn remove_all_for_guild(&self, guild: impl Into<Snowflake<Guild>>) -> Result<(), AppError> {
         let guild_id: i64 = guild.into().into();
 
         let channel_ids: Vec<i64> = sqlx::query!("SELECT id FROM channels WHERE guild_id = $1", guild_id)
-            .fetch_all(self.app().db.pool())
+            .fetch_all(self.app().db.executor())
             .await?
             .into_iter()
             .map(|r| r.id)
@@ -107,18 +332,83 @@ impl Buckets {
 
         Ok(())
     }
+
+    /// Delete the attachments in S3 that do not belong to an attachment in the database,
+    /// such as those left behind by a failed deletion.
+    ///
+    /// ## Arguments
+    ///
+    /// * `sent_before` - Only attachments of messages sent before this time are deleted.
+    ///   Attachments are uploaded before their message is committed, so recent ones may not be in the database yet.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of attachments deleted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If an S3 request fails.
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn prune_attachments(&self, sent_before: DateTime<Utc>) -> Result<usize, AppError> {
+        let bucket = self.attachments();
+
+        // Keys start with {channel_id}/{message_id}/, see `format_s3_key`
+        let candidates: Vec<(String, Snowflake<Message>)> = bucket
+            .list_objects("", None)
+            .await?
+            .into_iter()
+            .filter_map(|object| {
+                let key = object.key?;
+                let message: Snowflake<Message> = key.split('/').nth(1)?.parse().ok()?;
+                (message.created_at() < sent_before).then_some((key, message))
+            })
+            .collect();
+
+        let mut orphaned = Vec::new();
+        for batch in candidates.chunks(DELETE_BATCH_SIZE) {
+            let messages: Vec<Snowflake<Message>> = batch.iter().map(|(_, message)| *message).collect();
+            // Objects left behind under the previous key of a moved attachment are orphaned as well
+            let stored: HashSet<String> = sqlx::query!(
+                "SELECT channel_id, message_id, id, filename, key_token FROM attachments WHERE message_id = ANY($1)",
+                &messages as &[Snowflake<Message>]
+            )
+            .fetch_all(self.app().db.executor())
+            .await?
+            .into_iter()
+            .map(|r| format_s3_key(r.channel_id, r.message_id, r.id, r.key_token.as_deref(), &r.filename))
+            .collect();
+
+            orphaned.extend(
+                batch
+                    .iter()
+                    .filter(|(key, _)| !stored.contains(key))
+                    .map(|(key, _)| key.clone()),
+            );
+        }
+
+        for keys in orphaned.chunks(DELETE_BATCH_SIZE) {
+            bucket.delete_objects(keys.to_vec()).await?;
+        }
+        Ok(orphaned.len())
+    }
 }
 
 /// An abstraction for S3 buckets.
 #[derive(Clone, Debug)]
 pub struct Bucket<'a> {
-    name: &'static str,
+    name: &'a str,
+    expire_after_days: Option<i32>,
     buckets: &'a Buckets,
 }
 
 impl<'a> Bucket<'a> {
-    pub const fn new(buckets: &'a Buckets, name: &'static str) -> Self {
-        Self { name, buckets }
+    pub fn new(buckets: &'a Buckets, config: &'a BucketConfig) -> Self {
+        Self {
+            name: config.name(),
+            expire_after_days: config.expire_after_days(),
+            buckets,
+        }
     }
 
     /// The name of this bucket.
@@ -126,6 +416,78 @@ impl<'a> Bucket<'a> {
         self.name
     }
 
+    /// Apply the configured lifecycle policy to this bucket, so S3 deletes expired objects.
+    /// Buckets whose objects do not expire are left untouched, so policies set by the operator are kept.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if a lifecycle policy was applied.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(name = "s3.apply_lifecycle", skip_all, fields(otel.kind = "client", s3.bucket = self.name))]
+    pub async fn apply_lifecycle(&self) -> Result<bool, AppError> {
+        let Some(days) = self.expire_after_days else {
+            return Ok(false);
+        };
+
+        let rule = LifecycleRule::builder()
+            .id(LIFECYCLE_RULE_ID)
+            .status(ExpirationStatus::Enabled)
+            .filter(LifecycleRuleFilter::Prefix(String::new()))
+            .expiration(LifecycleExpiration::builder().days(days).build())
+            .build()
+            .expect("Failed to build LifecycleRule");
+
+        let result = self
+            .buckets
+            .client()
+            .put_bucket_lifecycle_configuration()
+            .bucket(self.name)
+            .lifecycle_configuration(
+                BucketLifecycleConfiguration::builder()
+                    .rules(rule)
+                    .build()
+                    .expect("Failed to build BucketLifecycleConfiguration"),
+            )
+            .send()
+            .await;
+        self.buckets.record(&result);
+        result?;
+        Ok(true)
+    }
+
+    /// Check if this bucket exists.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(name = "s3.exists", skip_all, fields(otel.kind = "client", s3.bucket = self.name))]
+    pub async fn exists(&self) -> Result<bool, AppError> {
+        let result = self.buckets.client().head_bucket().bucket(self.name).send().await;
+        self.buckets.record(&result);
+
+        match result {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError(e)) if e.err().is_not_found() => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Create this bucket.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    #[tracing::instrument(name = "s3.create", skip_all, fields(otel.kind = "client", s3.bucket = self.name))]
+    pub async fn create(&self) -> Result<(), AppError> {
+        let result = self.buckets.client().create_bucket().bucket(self.name).send().await;
+        self.buckets.record(&result);
+        result?;
+        Ok(())
+    }
+
     /// Fetch an object from this bucket.
     ///
     /// ## Arguments
@@ -140,15 +502,20 @@ impl<'a> Bucket<'a> {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
+    #[tracing::instrument(name = "s3.get_object", skip_all, fields(otel.kind = "client", s3.bucket = self.name))]
     pub async fn get_object(&self, key: impl Into<String>) -> Result<Bytes, AppError> {
-        let mut resp = self
+        self.buckets.ensure_available()?;
+        let result = self
             .buckets
             .client()
             .get_object()
             .bucket(self.name)
             .key(key)
             .send()
-            .await?;
+            .await;
+        self.buckets.record(&result);
+        let mut resp = result?;
 
         let mut bytes = BytesMut::new();
         while let Some(chunk) = resp.body.next().await {
@@ -158,6 +525,101 @@ impl<'a> Bucket<'a> {
         Ok(bytes.freeze())
     }
 
+    /// Fetch an object and its content type from this bucket, if it exists.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object to fetch.
+    ///
+    /// ## Returns
+    ///
+    /// The object data and its content type, or `None` if the object does not exist.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
+    #[tracing::instrument(name = "s3.try_get_object", skip_all, fields(otel.kind = "client", s3.bucket = self.name))]
+    pub async fn try_get_object(&self, key: impl Into<String>) -> Result<Option<(Bytes, Option<String>)>, AppError> {
+        self.buckets.ensure_available()?;
+        let result = self
+            .buckets
+            .client()
+            .get_object()
+            .bucket(self.name)
+            .key(key)
+            .send()
+            .await;
+        self.buckets.record(&result);
+
+        let mut resp = match result {
+            Ok(resp) => resp,
+            Err(SdkError::ServiceError(e)) if e.err().is_no_such_key() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut bytes = BytesMut::new();
+        while let Some(chunk) = resp.body.next().await {
+            bytes.extend_from_slice(&chunk.map_err(|e| AppError::S3(e.to_string()))?);
+        }
+
+        Ok(Some((bytes.freeze(), resp.content_type)))
+    }
+
+    /// Stream an object from this bucket, optionally only a part of it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The key of the object to fetch.
+    /// * `range` - The value of an HTTP `Range` header, to only fetch part of the object.
+    /// * `if_none_match` - The value of an HTTP `If-None-Match` header, to skip fetching an unchanged object.
+    ///
+    /// ## Returns
+    ///
+    /// [`ObjectResponse`] - The object stream, or the reason it was not returned.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
+    #[tracing::instrument(name = "s3.stream_object", skip_all, fields(otel.kind = "client", s3.bucket = self.name))]
+    pub async fn stream_object(
+        &self,
+        key: impl Into<String>,
+        range: Option<String>,
+        if_none_match: Option<String>,
+    ) -> Result<ObjectResponse, AppError> {
+        self.buckets.ensure_available()?;
+        let result = self
+            .buckets
+            .client()
+            .get_object()
+            .bucket(self.name)
+            .key(key)
+            .set_range(range)
+            .set_if_none_match(if_none_match)
+            .send()
+            .await;
+        self.buckets.record(&result);
+
+        match result {
+            Ok(resp) => Ok(ObjectResponse::Found(StreamedObject {
+                content_type: resp.content_type,
+                content_length: resp.content_length,
+                content_range: resp.content_range,
+                e_tag: resp.e_tag,
+                body: resp.body,
+            })),
+            Err(SdkError::ServiceError(e)) => match e.raw().status().as_u16() {
+                304 => Ok(ObjectResponse::NotModified),
+                404 => Ok(ObjectResponse::NotFound),
+                416 => Ok(ObjectResponse::RangeNotSatisfiable),
+                _ => Err(SdkError::ServiceError(e).into()),
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
     /// Upload an object to this bucket.
     ///
     /// ## Arguments
@@ -169,13 +631,17 @@ impl<'a> Bucket<'a> {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
+    #[tracing::instrument(name = "s3.put_object", skip_all, fields(otel.kind = "client", s3.bucket = self.name))]
     pub async fn put_object(
         &self,
         key: impl Into<String>,
         data: impl Into<ByteStream>,
         content_type: &Mime,
     ) -> Result<(), AppError> {
-        self.buckets
+        self.buckets.ensure_available()?;
+        let result = self
+            .buckets
             .client()
             .put_object()
             .bucket(self.name)
@@ -183,7 +649,9 @@ impl<'a> Bucket<'a> {
             .key(key)
             .body(data.into())
             .send()
-            .await?;
+            .await;
+        self.buckets.record(&result);
+        result?;
 
         Ok(())
     }
@@ -203,7 +671,10 @@ impl<'a> Bucket<'a> {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
+    #[tracing::instrument(name = "s3.list_objects", skip_all, fields(otel.kind = "client", s3.bucket = self.name))]
     pub async fn list_objects(&self, prefix: impl Into<String>, limit: Option<i32>) -> Result<Vec<Object>, AppError> {
+        self.buckets.ensure_available()?;
         let mut objects = Vec::new();
 
         // AWS-SDK has a nice pagination API to send continuation tokens implicitly, so we use that
@@ -216,6 +687,7 @@ impl<'a> Bucket<'a> {
         let mut paginator = req.into_paginator().send();
 
         while let Some(resp) = paginator.next().await {
+            self.buckets.record(&resp);
             if let Some(contents) = resp?.contents {
                 objects.extend(contents);
             }
@@ -234,14 +706,20 @@ impl<'a> Bucket<'a> {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
+    #[tracing::instrument(name = "s3.delete_object", skip_all, fields(otel.kind = "client", s3.bucket = self.name))]
     pub async fn delete_object(&self, key: impl Into<String>) -> Result<(), AppError> {
-        self.buckets
+        self.buckets.ensure_available()?;
+        let result = self
+            .buckets
             .client()
             .delete_object()
             .bucket(self.name)
             .key(key)
             .send()
-            .await?;
+            .await;
+        self.buckets.record(&result);
+        result?;
 
         Ok(())
     }
@@ -256,7 +734,10 @@ impl<'a> Bucket<'a> {
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request fails.
+    /// * [`AppError::StorageUnavailable`] - If S3 is currently unavailable.
+    #[tracing::instrument(name = "s3.delete_objects", skip_all, fields(otel.kind = "client", s3.bucket = self.name))]
     pub async fn delete_objects(&self, keys: Vec<impl Into<String>>) -> Result<(), AppError> {
+        self.buckets.ensure_available()?;
         let objects: Vec<ObjectIdentifier> = keys
             .into_iter()
             .map(|k| {
@@ -267,7 +748,8 @@ impl<'a> Bucket<'a> {
             })
             .collect();
 
-        self.buckets
+        let result = self
+            .buckets
             .client()
             .delete_objects()
             .bucket(self.name)
@@ -278,8 +760,91 @@ impl<'a> Bucket<'a> {
                     .expect("Failed to build Delete"),
             )
             .send()
-            .await?;
+            .await;
+        self.buckets.record(&result);
+        result?;
 
         Ok(())
     }
 }
+
+/// The outcome of streaming an object from a bucket.
+#[derive(Debug)]
+pub enum ObjectResponse {
+    /// The object, or the requested part of it.
+    Found(StreamedObject),
+    /// The object did not change since the client last fetched it.
+    NotModified,
+    /// The object does not exist.
+    NotFound,
+    /// The requested range lies outside of the object.
+    RangeNotSatisfiable,
+}
+
+/// An object streamed from a bucket.
+#[derive(Debug)]
+pub struct StreamedObject {
+    content_type: Option<String>,
+    content_length: Option<i64>,
+    content_range: Option<String>,
+    e_tag: Option<String>,
+    body: ByteStream,
+}
+
+impl StreamedObject {
+    /// The MIME type of the object.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// The size of the returned data in bytes.
+    pub const fn content_length(&self) -> Option<i64> {
+        self.content_length
+    }
+
+    /// The part of the object that was returned, if only a range was requested.
+    pub fn content_range(&self) -> Option<&str> {
+        self.content_range.as_deref()
+    }
+
+    /// The entity tag of the object, which changes whenever the object does.
+    pub fn e_tag(&self) -> Option<&str> {
+        self.e_tag.as_deref()
+    }
+
+    /// Consume the object, returning its data as a stream of chunks.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Bytes, ByteStreamError>> {
+        futures::stream::unfold(self.body, |mut body| async move {
+            body.next().await.map(|chunk| (chunk, body))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_valid_bucket_name, BucketConfig, BucketConfigs, ContentClass};
+
+    #[test]
+    fn test_is_valid_bucket_name() {
+        assert!(is_valid_bucket_name("attachments"));
+        assert!(is_valid_bucket_name("chat-media.eu-1"));
+        assert!(!is_valid_bucket_name("ab"));
+        assert!(!is_valid_bucket_name("Attachments"));
+        assert!(!is_valid_bucket_name("-attachments"));
+        assert!(!is_valid_bucket_name("attachments_old"));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(BucketConfigs::default().validate(), Ok(()));
+
+        let shared = BucketConfigs::from_fn(|class| match class {
+            ContentClass::Emoji => BucketConfig::new("guilds", None),
+            _ => BucketConfig::new(class.default_bucket_name(), None),
+        });
+        assert_eq!(
+            shared.validate(),
+            Err("icons and emoji are both stored in bucket 'guilds'".into())
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use axum::{
+    body::{self, Body},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+
+use super::{channel::Channel, errors::AppError};
+
+/// The maximum amount of operations a single bulk request may contain.
+pub const MAX_BULK_OPERATIONS: usize = 50;
+
+/// The outcome of an operation of a bulk request.
+///
+/// Operations are prepared before anything is written, and only committed once all of them succeeded.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BulkResult {
+    /// A channel was created.
+    CreateChannel { channel: Channel },
+    /// A channel was updated.
+    UpdateChannel { channel: Channel },
+}
+
+impl BulkResult {
+    /// The channel affected by the operation.
+    pub const fn channel(&self) -> &Channel {
+        match self {
+            Self::CreateChannel { channel } | Self::UpdateChannel { channel } => channel,
+        }
+    }
+}
+
+/// An error that caused a bulk request to be rolled back.
+#[derive(Debug, Error)]
+#[error("{source}")]
+pub struct BulkError {
+    /// The index of the operation that failed, if the error is specific to one operation.
+    pub index: Option<usize>,
+    /// The error the operation failed with.
+    pub source: AppError,
+}
+
+impl BulkError {
+    /// Create a new error for the operation at the given index.
+    pub fn at(index: usize, source: impl Into<AppError>) -> Self {
+        Self {
+            index: Some(index),
+            source: source.into(),
+        }
+    }
+
+    /// Convert the error into a response. The response is the same as that of the underlying error,
+    /// with the index of the failed operation added to the body.
+    pub async fn into_response(self) -> Response {
+        let response = self.source.into_response();
+        let Some(index) = self.index else {
+            return response;
+        };
+
+        let (mut parts, body) = response.into_parts();
+        let Ok(bytes) = body::to_bytes(body, usize::MAX).await else {
+            return parts.status.into_response();
+        };
+        let Ok(mut value) = serde_json::from_slice::<Value>(&bytes) else {
+            return (parts, bytes).into_response();
+        };
+        value["index"] = index.into();
+
+        // The body changed size, the length is recomputed when the response is sent
+        parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+        Response::from_parts(parts, Body::from(value.to_string()))
+    }
+}
+
+// Errors that are not specific to an operation, such as database errors, are converted implicitly
+impl<T: Into<AppError>> From<T> for BulkError {
+    fn from(e: T) -> Self {
+        Self {
+            index: None,
+            source: e.into(),
+        }
+    }
+}
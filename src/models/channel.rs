@@ -2,28 +2,59 @@ use enum_dispatch::enum_dispatch;
 use serde::{Deserialize, Serialize};
 
 use super::snowflake::Snowflake;
-use super::{guild::Guild, requests::CreateChannel, state::Config};
+use super::{guild::Guild, message::Message, requests::CreateChannel, state::Config, user::User};
+
+/// The longest message retention period a channel may have, in days.
+pub const MAX_RETENTION_DAYS: i32 = 3650;
 
 #[enum_dispatch(Channel)]
 pub trait ChannelLike {
     /// The Snowflake ID of a channel.
     fn id(&self) -> Snowflake<Channel>;
-    /// The Snowflake ID of the guild this channel belongs to.
-    fn guild_id(&self) -> Snowflake<Guild>;
+    /// The Snowflake ID of the guild this channel belongs to, if any.
+    fn guild_id(&self) -> Option<Snowflake<Guild>>;
+    /// The Snowflake ID of the user this channel belongs to, if it does not belong to a guild.
+    fn owner_id(&self) -> Option<Snowflake<User>>;
     /// The name of the channel.
     fn name(&self) -> &str;
     /// The name of the channel.
     fn name_mut(&mut self) -> &mut String;
     /// The type of channel.
     fn channel_type(&self) -> &'static str;
+    /// The ID of the last message sent in the channel, if any.
+    fn last_message_id(&self) -> Option<Snowflake<Message>>;
+    /// The amount of messages in the channel.
+    fn message_count(&self) -> i64;
+    /// The amount of days after which messages in the channel are deleted, if any.
+    fn retention_days(&self) -> Option<i32>;
+    /// The amount of days after which messages in the channel are deleted, if any.
+    fn retention_days_mut(&mut self) -> &mut Option<i32>;
+    /// Whether only the owner of the channel's guild may send messages in the channel.
+    fn is_announcement(&self) -> bool;
+    /// Whether the channel is archived. Archived channels are read-only and hidden from the channel list of their guild.
+    fn is_archived(&self) -> bool;
+    /// The ID of the message kept visible at the top of the channel, if any.
+    fn sticky_message_id(&self) -> Option<Snowflake<Message>>;
+    /// Incremented every time the settings of the channel are updated.
+    fn version(&self) -> i32;
+    /// Incremented every time the settings of the channel are updated.
+    fn version_mut(&mut self) -> &mut i32;
 }
 
 /// Represents a row representing a channel.
 pub struct ChannelRecord {
     pub id: Snowflake<Channel>,
-    pub guild_id: Snowflake<Guild>,
+    pub guild_id: Option<i64>,
+    pub owner_id: Option<i64>,
     pub name: String,
     pub channel_type: String,
+    pub last_message_id: Option<i64>,
+    pub message_count: i64,
+    pub retention_days: Option<i32>,
+    pub announcement: bool,
+    pub archived: bool,
+    pub version: i32,
+    pub sticky_message_id: Option<i64>,
 }
 
 #[non_exhaustive]
@@ -32,12 +63,37 @@ pub struct ChannelRecord {
 #[enum_dispatch]
 pub enum Channel {
     GuildText(TextChannel),
+    SavedMessages(SavedMessagesChannel),
 }
 
 impl Channel {
     pub fn from_record(record: ChannelRecord) -> Self {
         match record.channel_type.as_str() {
-            "TEXT_CHANNEL" => Self::GuildText(TextChannel::new(record.id, record.guild_id, record.name)),
+            "TEXT_CHANNEL" => Self::GuildText(TextChannel {
+                id: record.id,
+                guild_id: Snowflake::new(record.guild_id.expect("Text channels should belong to a guild")),
+                name: record.name,
+                last_message_id: record.last_message_id.map(Snowflake::new),
+                message_count: record.message_count,
+                retention_days: record.retention_days,
+                announcement: record.announcement,
+                archived: record.archived,
+                version: record.version,
+                sticky_message_id: record.sticky_message_id.map(Snowflake::new),
+            }),
+            "SAVED_MESSAGES" => Self::SavedMessages(SavedMessagesChannel {
+                id: record.id,
+                owner_id: Snowflake::new(
+                    record
+                        .owner_id
+                        .expect("Saved messages channels should belong to a user"),
+                ),
+                name: record.name,
+                last_message_id: record.last_message_id.map(Snowflake::new),
+                message_count: record.message_count,
+                retention_days: record.retention_days,
+                version: record.version,
+            }),
             _ => panic!("Invalid channel type"),
         }
     }
@@ -56,6 +112,20 @@ pub struct TextChannel {
     id: Snowflake<Channel>,
     guild_id: Snowflake<Guild>,
     name: String,
+    #[serde(default)]
+    last_message_id: Option<Snowflake<Message>>,
+    #[serde(default)]
+    message_count: i64,
+    #[serde(default)]
+    retention_days: Option<i32>,
+    #[serde(default)]
+    announcement: bool,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    version: i32,
+    #[serde(default)]
+    sticky_message_id: Option<Snowflake<Message>>,
 }
 
 impl TextChannel {
@@ -64,8 +134,30 @@ impl TextChannel {
             id,
             guild_id: guild.into(),
             name,
+            last_message_id: None,
+            message_count: 0,
+            retention_days: None,
+            announcement: false,
+            archived: false,
+            version: 0,
+            sticky_message_id: None,
         }
     }
+
+    /// Whether only the owner of the guild may send messages in the channel.
+    pub const fn announcement_mut(&mut self) -> &mut bool {
+        &mut self.announcement
+    }
+
+    /// Whether the channel is archived.
+    pub const fn archived_mut(&mut self) -> &mut bool {
+        &mut self.archived
+    }
+
+    /// The ID of the message kept visible at the top of the channel, if any.
+    pub const fn sticky_message_id_mut(&mut self) -> &mut Option<Snowflake<Message>> {
+        &mut self.sticky_message_id
+    }
 }
 
 impl ChannelLike for TextChannel {
@@ -73,8 +165,12 @@ impl ChannelLike for TextChannel {
         self.id
     }
 
-    fn guild_id(&self) -> Snowflake<Guild> {
-        self.guild_id
+    fn guild_id(&self) -> Option<Snowflake<Guild>> {
+        Some(self.guild_id)
+    }
+
+    fn owner_id(&self) -> Option<Snowflake<User>> {
+        None
     }
 
     fn name(&self) -> &str {
@@ -88,6 +184,135 @@ impl ChannelLike for TextChannel {
     fn channel_type(&self) -> &'static str {
         "TEXT_CHANNEL"
     }
+
+    fn last_message_id(&self) -> Option<Snowflake<Message>> {
+        self.last_message_id
+    }
+
+    fn message_count(&self) -> i64 {
+        self.message_count
+    }
+
+    fn retention_days(&self) -> Option<i32> {
+        self.retention_days
+    }
+
+    fn retention_days_mut(&mut self) -> &mut Option<i32> {
+        &mut self.retention_days
+    }
+
+    fn is_announcement(&self) -> bool {
+        self.announcement
+    }
+
+    fn is_archived(&self) -> bool {
+        self.archived
+    }
+
+    fn sticky_message_id(&self) -> Option<Snowflake<Message>> {
+        self.sticky_message_id
+    }
+
+    fn version(&self) -> i32 {
+        self.version
+    }
+
+    fn version_mut(&mut self) -> &mut i32 {
+        &mut self.version
+    }
+}
+
+/// A channel private to a single user, where they can keep notes and links across devices.
+/// Every user has exactly one, created when they register.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SavedMessagesChannel {
+    id: Snowflake<Channel>,
+    owner_id: Snowflake<User>,
+    name: String,
+    #[serde(default)]
+    last_message_id: Option<Snowflake<Message>>,
+    #[serde(default)]
+    message_count: i64,
+    #[serde(default)]
+    retention_days: Option<i32>,
+    #[serde(default)]
+    version: i32,
+}
+
+impl SavedMessagesChannel {
+    pub fn new(id: Snowflake<Channel>, owner: impl Into<Snowflake<User>>) -> Self {
+        Self {
+            id,
+            owner_id: owner.into(),
+            name: "Saved Messages".into(),
+            last_message_id: None,
+            message_count: 0,
+            retention_days: None,
+            version: 0,
+        }
+    }
+}
+
+impl ChannelLike for SavedMessagesChannel {
+    fn id(&self) -> Snowflake<Channel> {
+        self.id
+    }
+
+    fn guild_id(&self) -> Option<Snowflake<Guild>> {
+        None
+    }
+
+    fn owner_id(&self) -> Option<Snowflake<User>> {
+        Some(self.owner_id)
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn name_mut(&mut self) -> &mut String {
+        &mut self.name
+    }
+
+    fn channel_type(&self) -> &'static str {
+        "SAVED_MESSAGES"
+    }
+
+    fn last_message_id(&self) -> Option<Snowflake<Message>> {
+        self.last_message_id
+    }
+
+    fn message_count(&self) -> i64 {
+        self.message_count
+    }
+
+    fn retention_days(&self) -> Option<i32> {
+        self.retention_days
+    }
+
+    fn retention_days_mut(&mut self) -> &mut Option<i32> {
+        &mut self.retention_days
+    }
+
+    fn is_announcement(&self) -> bool {
+        false
+    }
+
+    fn is_archived(&self) -> bool {
+        false
+    }
+
+    fn sticky_message_id(&self) -> Option<Snowflake<Message>> {
+        None
+    }
+
+    fn version(&self) -> i32 {
+        self.version
+    }
+
+    fn version_mut(&mut self) -> &mut i32 {
+        &mut self.version
+    }
 }
 
 impl From<Channel> for Snowflake<Channel> {
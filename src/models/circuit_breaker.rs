@@ -0,0 +1,58 @@
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc,
+};
+
+/// Tracks consecutive failures of an external service and opens once too many happen in a row.
+///
+/// While the breaker is open, callers are expected to fail fast instead of contacting the service.
+/// It is closed again once a request or a background probe succeeds.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    threshold: u32,
+    failures: Arc<AtomicU32>,
+    open: Arc<AtomicBool>,
+}
+
+impl CircuitBreaker {
+    /// Create a new closed circuit breaker.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name of the guarded service, used for logging.
+    /// * `threshold` - The amount of consecutive failures after which the breaker opens.
+    pub fn new(name: &'static str, threshold: u32) -> Self {
+        Self {
+            name,
+            threshold,
+            failures: Arc::new(AtomicU32::new(0)),
+            open: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether the breaker is open, meaning the service is considered unavailable.
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    /// Record a successful request, closing the breaker if it was open.
+    pub fn record_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+        if self.open.swap(false, Ordering::Relaxed) {
+            tracing::info!(service = self.name, "Service recovered, closing circuit breaker");
+        }
+    }
+
+    /// Record a failed request, opening the breaker if the failure threshold is reached.
+    pub fn record_failure(&self) {
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.threshold && !self.open.swap(true, Ordering::Relaxed) {
+            tracing::error!(
+                service = self.name,
+                failures,
+                "Service is unavailable, opening circuit breaker"
+            );
+        }
+    }
+}
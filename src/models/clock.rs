@@ -0,0 +1,104 @@
+use std::{
+    fmt::Debug,
+    sync::atomic::{AtomicI64, Ordering},
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// The source of the current wall-clock time, see [`ApplicationState::clock`](super::state::ApplicationState).
+///
+/// All time-dependent logic, such as token expiry, timeouts and retention periods, takes the current time
+/// from the application's clock, so tests can control it with a [`ManualClock`].
+///
+/// Snowflake IDs are the exception: they are always generated from the system time,
+/// as a clock that stands still or goes back would hand out the same ID twice.
+/// Durations that are only measured within the process, such as rate limit windows, use [`std::time::Instant`].
+pub trait Clock: Debug + Send + Sync {
+    /// The current time.
+    fn now(&self) -> DateTime<Utc>;
+
+    /// The current time as a UNIX timestamp in seconds, the format timestamps are stored in.
+    fn timestamp(&self) -> i64 {
+        self.now().timestamp()
+    }
+}
+
+/// The clock used in production, reading the system time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests.
+#[derive(Debug)]
+pub struct ManualClock {
+    /// The current time as a UNIX timestamp in milliseconds.
+    now: AtomicI64,
+}
+
+impl ManualClock {
+    /// Create a new clock standing at the given time.
+    ///
+    /// ## Arguments
+    ///
+    /// * `now` - The time the clock starts at. Precision beyond milliseconds is discarded.
+    pub const fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: AtomicI64::new(now.timestamp_millis()),
+        }
+    }
+
+    /// Set the clock to the given time.
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.now.store(now.timestamp_millis(), Ordering::SeqCst);
+    }
+
+    /// Move the clock forward, or backward if `delta` is negative.
+    pub fn advance(&self, delta: TimeDelta) {
+        self.now.fetch_add(delta.num_milliseconds(), Ordering::SeqCst);
+    }
+}
+
+impl Default for ManualClock {
+    /// A clock standing at the current system time.
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(self.now.load(Ordering::SeqCst)).expect("Clock should be within range")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, TimeZone, Utc};
+
+    use super::{Clock, ManualClock};
+
+    #[test]
+    fn test_manual_clock() {
+        let start = Utc
+            .with_ymd_and_hms(2024, 7, 1, 12, 0, 0)
+            .single()
+            .expect("Date should be valid");
+        let clock = ManualClock::new(start);
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.timestamp(), start.timestamp());
+
+        clock.advance(TimeDelta::minutes(90));
+        assert_eq!(clock.now(), start + TimeDelta::minutes(90));
+
+        clock.advance(TimeDelta::seconds(-30));
+        assert_eq!(clock.timestamp(), start.timestamp() + 90 * 60 - 30);
+
+        clock.set(start);
+        assert_eq!(clock.now(), start);
+    }
+}
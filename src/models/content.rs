@@ -0,0 +1,516 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::LazyLock,
+};
+
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{channel::Channel, emoji, guild::Guild, snowflake::Snowflake, user::User};
+
+/// Matches HTML comments and tags. Mentions (`<@id>`, `<#id>`), attachment references (`<attachment:id>`)
+/// and autolinks (`<https://...>`) are not tags.
+static HTML_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?s)<!--.*?-->|</?[a-zA-Z][a-zA-Z0-9-]*(?:\s[^<>]*)?/?>").expect("Failed to compile HTML regex")
+});
+
+/// Matches all supported mention syntaxes.
+static MENTION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"<@!?(?P<user_id>[0-9]{1,20})>|<#(?P<channel_id>[0-9]{1,20})>|@(?P<username>[a-zA-Z0-9]+(?:[._][a-zA-Z0-9]+)*)|#(?P<channel_name>[a-zA-Z0-9_-]+)",
+    )
+    .expect("Failed to compile mention regex")
+});
+
+/// Matches references to custom emojis, as in `<:name:id>` or `<a:name:id>` for animated ones.
+static CUSTOM_EMOJI_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<a?:(?P<name>[a-zA-Z0-9_+-]{1,32}):[0-9]{1,20}>").expect("Failed to compile custom emoji regex")
+});
+
+/// Matches URLs, either bare or as autolinks (`<https://...>`).
+static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<(?P<autolink>https?://[^\s<>]+)>|(?P<bare>https?://[^\s<>]+)").expect("Failed to compile URL regex")
+});
+
+/// The file extensions of images, videos and audio files that links may point to directly.
+const MEDIA_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "webp", "avif", "mp4", "webm", "mov", "mp3", "ogg", "wav", "flac", "m4a",
+];
+
+/// A mention of a user or channel in message content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mention<'a> {
+    /// A user mentioned by ID, as in `<@123>`.
+    User(Snowflake<User>),
+    /// A channel mentioned by ID, as in `<#123>`.
+    Channel(Snowflake<Channel>),
+    /// A user mentioned by username, as in `@username`.
+    Username(&'a str),
+    /// A channel mentioned by name, as in `#channel`.
+    ChannelName(&'a str),
+}
+
+/// A channel mentioned in message content, with the name it had when the message was sent.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChannelMention {
+    /// The ID of the channel.
+    pub id: Snowflake<Channel>,
+    /// The guild the channel belongs to, which may differ from the guild the message was sent in.
+    pub guild_id: Snowflake<Guild>,
+    /// The name of the channel when the message was sent.
+    pub name: String,
+}
+
+/// The users and channels mentions may refer to, usually the members and channels of a guild.
+#[derive(Debug, Clone, Default)]
+pub struct MentionTargets {
+    users: HashMap<String, Snowflake<User>>,
+    channels: HashMap<String, Snowflake<Channel>>,
+    user_ids: HashSet<Snowflake<User>>,
+    channel_ids: HashMap<Snowflake<Channel>, ChannelMention>,
+}
+
+impl MentionTargets {
+    /// Create an empty set of mention targets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow mentioning a user. Usernames are matched case-insensitively.
+    pub fn add_user(&mut self, id: Snowflake<User>, username: &str) {
+        self.users.insert(username.to_lowercase(), id);
+        self.user_ids.insert(id);
+    }
+
+    /// Allow mentioning a channel by name or ID. If multiple channels share a name, the first one added is mentioned.
+    pub fn add_channel(&mut self, channel: ChannelMention) {
+        self.channels.entry(channel.name.to_lowercase()).or_insert(channel.id);
+        self.channel_ids.insert(channel.id, channel);
+    }
+
+    /// Allow linking to a channel by ID only, such as a channel of another guild.
+    pub fn add_linked_channel(&mut self, channel: ChannelMention) {
+        self.channel_ids.entry(channel.id).or_insert(channel);
+    }
+}
+
+/// Message content after sanitization and mention canonicalization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessedContent {
+    /// The processed content.
+    pub content: String,
+    /// The users mentioned in the content, in order of first mention.
+    pub mentions: Vec<Snowflake<User>>,
+    /// The channels mentioned in the content, in order of first mention.
+    pub channel_mentions: Vec<Snowflake<Channel>>,
+    /// The channels mentioned in the content along with their names, in the same order as `channel_mentions`.
+    pub mention_channels: Vec<ChannelMention>,
+}
+
+/// Remove all HTML tags and comments from markdown content. Code spans and blocks are left untouched.
+///
+/// ## Arguments
+///
+/// * `content` - The markdown content to sanitize.
+///
+/// ## Returns
+///
+/// The sanitized content, with leading and trailing whitespace removed.
+pub fn sanitize(content: &str) -> String {
+    let mut sanitized = String::with_capacity(content.len());
+    for (range, is_code) in split_code(content) {
+        if is_code {
+            sanitized.push_str(&content[range]);
+        } else {
+            // Removing a tag may join the text around it into a new tag, as in `<<b>script>`
+            let mut text = Cow::Borrowed(&content[range]);
+            while let Cow::Owned(stripped) = HTML_REGEX.replace_all(&text, "") {
+                text = Cow::Owned(stripped);
+            }
+            sanitized.push_str(&text);
+        }
+    }
+    sanitized.trim().to_string()
+}
+
+/// Replace emoji shortcodes, such as `:tada:`, with the unicode emoji they stand for.
+/// Unknown shortcodes and emojis inside code spans and blocks are left as they are.
+///
+/// Custom emoji references (`<:name:id>`) are replaced by the shortcode `:name:`, as this server does not host
+/// custom emojis and they could not be rendered. If the name is a known shortcode, the unicode emoji is used instead.
+///
+/// ## Arguments
+///
+/// * `content` - The markdown content to normalize.
+pub fn normalize_emojis(content: &str) -> String {
+    let mut normalized = String::with_capacity(content.len());
+    for (range, is_code) in split_code(content) {
+        if is_code {
+            normalized.push_str(&content[range]);
+        } else {
+            let text = CUSTOM_EMOJI_REGEX.replace_all(&content[range], ":$name:");
+            replace_shortcodes(&text, &mut normalized);
+        }
+    }
+    normalized
+}
+
+/// Push `text` to `out`, replacing every known shortcode with its emoji.
+fn replace_shortcodes(text: &str, out: &mut String) {
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        let after = &rest[start + 1..];
+        let Some(end) = after.find(':') else {
+            break;
+        };
+        if let Some(emoji) = emoji::from_shortcode(&after[..end]) {
+            out.push_str(&rest[..start]);
+            out.push_str(emoji);
+            rest = &after[end + 1..];
+        } else {
+            // The closing colon may open the next shortcode, as in `:not_an_emoji:tada:`
+            out.push_str(&rest[..=start]);
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+}
+
+/// Find all mentions in markdown content, in order of occurrence. Mentions in code spans and blocks are ignored.
+///
+/// ## Arguments
+///
+/// * `content` - The markdown content to search for mentions.
+pub fn find_mentions(content: &str) -> Vec<Mention<'_>> {
+    let mut mentions = Vec::new();
+    for_each_mention(content, |mention, _| mentions.push(mention));
+    mentions
+}
+
+/// Rewrite all mentions of known users and channels into their ID-based syntax, `<@id>` and `<#id>`.
+/// Mentions that do not refer to any of the targets are left as they are, and are not recorded.
+///
+/// ## Arguments
+///
+/// * `content` - The markdown content to canonicalize.
+/// * `targets` - The users and channels that may be mentioned.
+///
+/// ## Returns
+///
+/// The canonicalized content, along with the mentioned users and channels.
+pub fn canonicalize(content: &str, targets: &MentionTargets) -> ProcessedContent {
+    let mut processed = ProcessedContent {
+        content: String::with_capacity(content.len()),
+        mentions: Vec::new(),
+        channel_mentions: Vec::new(),
+        mention_channels: Vec::new(),
+    };
+    let mut last = 0;
+
+    for_each_mention(content, |mention, range| {
+        let user = match mention {
+            Mention::User(id) => targets.user_ids.contains(&id).then_some(id),
+            Mention::Username(name) => targets.users.get(&name.to_lowercase()).copied(),
+            _ => None,
+        };
+        let channel = match mention {
+            Mention::Channel(id) => targets.channel_ids.get(&id),
+            Mention::ChannelName(name) => targets
+                .channels
+                .get(&name.to_lowercase())
+                .and_then(|id| targets.channel_ids.get(id)),
+            _ => None,
+        };
+
+        let replacement = if let Some(id) = user {
+            if !processed.mentions.contains(&id) {
+                processed.mentions.push(id);
+            }
+            format!("<@{id}>")
+        } else if let Some(channel) = channel {
+            if !processed.channel_mentions.contains(&channel.id) {
+                processed.channel_mentions.push(channel.id);
+                processed.mention_channels.push(channel.clone());
+            }
+            format!("<#{}>", channel.id)
+        } else {
+            return;
+        };
+
+        processed.content.push_str(&content[last..range.start]);
+        processed.content.push_str(&replacement);
+        last = range.end;
+    });
+
+    processed.content.push_str(&content[last..]);
+    processed
+}
+
+/// A direct link to an external image, video or audio file in message content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaLink {
+    /// The URL of the media.
+    pub url: Url,
+    /// The byte range the link occupies in the content, including the angle brackets of autolinks.
+    pub range: Range<usize>,
+}
+
+/// Find all links that point directly to an image, video or audio file, in order of occurrence.
+///
+/// Only bare URLs and autolinks whose path ends in a media file extension are found.
+/// Links with text, such as `[text](url)`, and links in code spans and blocks are ignored.
+///
+/// ## Arguments
+///
+/// * `content` - The markdown content to search for media links.
+pub fn find_media_links(content: &str) -> Vec<MediaLink> {
+    let mut links = Vec::new();
+    for (range, is_code) in split_code(content) {
+        if is_code {
+            continue;
+        }
+        for captures in URL_REGEX.captures_iter(&content[range.clone()]) {
+            let whole = captures.get(0).expect("Match should have a capture group 0");
+            let start = range.start + whole.start();
+
+            let (url, end) = if let Some(autolink) = captures.name("autolink") {
+                (autolink.as_str(), range.start + whole.end())
+            } else {
+                // Bare URLs must stand on their own, and do not include trailing punctuation
+                if content[..start].chars().next_back().is_some_and(|c| !c.is_whitespace()) {
+                    continue;
+                }
+                let url = whole.as_str().trim_end_matches(['.', ',', ';', ':', '!', '?', ')']);
+                (url, start + url.len())
+            };
+
+            let Ok(url) = Url::parse(url) else {
+                continue;
+            };
+            if is_media_path(url.path()) {
+                links.push(MediaLink { url, range: start..end });
+            }
+        }
+    }
+    links
+}
+
+/// Check if a URL path ends in the file extension of an image, video or audio file.
+fn is_media_path(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or_default();
+    name.rsplit_once('.')
+        .is_some_and(|(_, ext)| MEDIA_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+/// Call `f` with every mention outside of code, and the byte range it occupies in `content`.
+fn for_each_mention<'a>(content: &'a str, mut f: impl FnMut(Mention<'a>, Range<usize>)) {
+    for (range, is_code) in split_code(content) {
+        if is_code {
+            continue;
+        }
+        for captures in MENTION_REGEX.captures_iter(&content[range.clone()]) {
+            let whole = captures.get(0).expect("Match should have a capture group 0");
+            let start = range.start + whole.start();
+
+            // Names must not be part of a longer word, like in email addresses
+            let is_named = captures.name("username").is_some() || captures.name("channel_name").is_some();
+            if is_named && content[..start].chars().next_back().is_some_and(is_word_char) {
+                continue;
+            }
+
+            if let Some(mention) = to_mention(&captures) {
+                f(mention, start..range.start + whole.end());
+            }
+        }
+    }
+}
+
+/// Turn the captures of [`MENTION_REGEX`] into a mention.
+fn to_mention<'a>(captures: &Captures<'a>) -> Option<Mention<'a>> {
+    let id = |group| captures.name(group).and_then(|m| m.as_str().parse::<i64>().ok());
+
+    id("user_id")
+        .map(|id| Mention::User(id.into()))
+        .or_else(|| id("channel_id").map(|id| Mention::Channel(id.into())))
+        .or_else(|| captures.name("username").map(|m| Mention::Username(m.as_str())))
+        .or_else(|| captures.name("channel_name").map(|m| Mention::ChannelName(m.as_str())))
+}
+
+const fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Split markdown content into alternating text and code segments.
+/// A run of backticks opens a code span or block that is closed by the next run of the same length.
+/// Unclosed runs are treated as text.
+fn split_code(content: &str) -> Vec<(Range<usize>, bool)> {
+    let bytes = content.as_bytes();
+    let backtick_run = |from: usize| bytes[from..].iter().take_while(|b| **b == b'`').count();
+
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'`' {
+            i += 1;
+            continue;
+        }
+
+        let len = backtick_run(i);
+        let mut j = i + len;
+        let mut close = None;
+        while j < bytes.len() {
+            if bytes[j] == b'`' {
+                let run = backtick_run(j);
+                if run == len {
+                    close = Some(j + run);
+                    break;
+                }
+                j += run;
+            } else {
+                j += 1;
+            }
+        }
+
+        match close {
+            Some(end) => {
+                if text_start < i {
+                    segments.push((text_start..i, false));
+                }
+                segments.push((i..end, true));
+                text_start = end;
+                i = end;
+            }
+            None => i += len,
+        }
+    }
+
+    if text_start < bytes.len() {
+        segments.push((text_start..bytes.len(), false));
+    }
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        canonicalize, find_media_links, find_mentions, normalize_emojis, sanitize, ChannelMention, Mention,
+        MentionTargets,
+    };
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("**bold** <b>html</b>"), "**bold** html");
+        assert_eq!(sanitize("a<!-- hidden\ncomment -->b<br/>c"), "abc");
+        assert_eq!(sanitize("<img src=x onerror=alert(1)> hi"), "hi");
+        assert_eq!(sanitize("<<b>script>alert(1)<</b>/script>"), "alert(1)");
+        // Code, mentions, autolinks and comparisons are kept
+        assert_eq!(sanitize("`<b>` and\n```\n<div>\n```"), "`<b>` and\n```\n<div>\n```");
+        assert_eq!(
+            sanitize("<@123> <#456> <attachment:0> <https://example.com>"),
+            "<@123> <#456> <attachment:0> <https://example.com>"
+        );
+        assert_eq!(sanitize("1 < 2 and 3 > 2"), "1 < 2 and 3 > 2");
+    }
+
+    #[test]
+    fn test_normalize_emojis() {
+        assert_eq!(normalize_emojis("gg :tada::+1: :Tada:"), "gg 🎉👍 🎉");
+        assert_eq!(normalize_emojis("at 12:30:45 :nope:wave:"), "at 12:30:45 :nope👋");
+        assert_eq!(
+            normalize_emojis("`:tada:` and\n```\n:tada:\n```"),
+            "`:tada:` and\n```\n:tada:\n```"
+        );
+        // Custom emojis are not hosted by the server
+        assert_eq!(
+            normalize_emojis("<:party_parrot:123> <a:fire:456>"),
+            ":party_parrot: 🔥"
+        );
+        assert_eq!(normalize_emojis("<:bad name:123>"), "<:bad name:123>");
+    }
+
+    #[test]
+    fn test_find_mentions() {
+        assert_eq!(
+            find_mentions("hi @alice and <@!123>, see #general or <#456>"),
+            vec![
+                Mention::Username("alice"),
+                Mention::User(123.into()),
+                Mention::ChannelName("general"),
+                Mention::Channel(456.into()),
+            ]
+        );
+        // Email addresses, code and trailing punctuation
+        assert_eq!(find_mentions("mail me@example.com `@bob`"), vec![]);
+        assert_eq!(find_mentions("@carol."), vec![Mention::Username("carol")]);
+    }
+
+    #[test]
+    fn test_find_media_links() {
+        let content = "look https://a.example/cat.PNG. and <https://b.example/clip.mp4?s=1> \
+            [text](https://c.example/x.gif) `https://d.example/y.png` https://e.example/page";
+        let links = find_media_links(content);
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].url.as_str(), "https://a.example/cat.PNG");
+        assert_eq!(&content[links[0].range.clone()], "https://a.example/cat.PNG");
+        assert_eq!(links[1].url.as_str(), "https://b.example/clip.mp4?s=1");
+        assert_eq!(&content[links[1].range.clone()], "<https://b.example/clip.mp4?s=1>");
+        assert!(find_media_links("https://example.com/archive.zip https://example.com/png").is_empty());
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let mut targets = MentionTargets::new();
+        targets.add_user(1.into(), "Alice");
+        targets.add_channel(ChannelMention {
+            id: 2.into(),
+            guild_id: 10.into(),
+            name: "general".into(),
+        });
+
+        let processed = canonicalize("@alice @nobody <@1> <@!1> <@9> #general #random `@alice`", &targets);
+        assert_eq!(processed.content, "<@1> @nobody <@1> <@1> <@9> <#2> #random `@alice`");
+        assert_eq!(processed.mentions, vec![1.into()]);
+        assert_eq!(processed.channel_mentions, vec![2.into()]);
+        assert_eq!(processed.mention_channels[0].name, "general");
+    }
+
+    #[test]
+    fn test_canonicalize_linked_channels() {
+        let mut targets = MentionTargets::new();
+        targets.add_channel(ChannelMention {
+            id: 2.into(),
+            guild_id: 10.into(),
+            name: "general".into(),
+        });
+        targets.add_linked_channel(ChannelMention {
+            id: 3.into(),
+            guild_id: 20.into(),
+            name: "elsewhere".into(),
+        });
+
+        // Channels of other guilds can only be linked by ID
+        let processed = canonicalize("<#3> #elsewhere <#2> <#3> <#4>", &targets);
+        assert_eq!(processed.content, "<#3> #elsewhere <#2> <#3> <#4>");
+        assert_eq!(processed.channel_mentions, vec![3.into(), 2.into()]);
+        assert_eq!(
+            processed.mention_channels,
+            vec![
+                ChannelMention {
+                    id: 3.into(),
+                    guild_id: 20.into(),
+                    name: "elsewhere".into(),
+                },
+                ChannelMention {
+                    id: 2.into(),
+                    guild_id: 10.into(),
+                    name: "general".into(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use std::sync::{Arc, Weak};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, Postgres};
+use tokio::sync::Notify;
+
+use super::{
+    channel::{Channel, ChannelLike},
+    errors::{AppError, BuildError},
+    guild::Guild,
+    message::Message,
+    snowflake::Snowflake,
+    state::{ApplicationState, Config},
+    user::User,
+};
+
+/// The maximum amount of announcement channels a channel may follow.
+pub const MAX_FOLLOWS_PER_CHANNEL: usize = 10;
+/// The maximum amount of queued crossposts sent at once.
+const BATCH_SIZE: i64 = 20;
+/// How long a claimed crosspost is reserved, in seconds. Failed crossposts are retried after this period.
+const LEASE_DURATION: i64 = 60;
+/// The amount of attempts after which a crosspost is dropped.
+const MAX_ATTEMPTS: i32 = 5;
+
+/// Where a crossposted message was originally sent.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrosspostSource {
+    /// The ID of the original message.
+    pub message_id: Snowflake<Message>,
+    /// The ID of the announcement channel the message was sent in.
+    pub channel_id: Snowflake<Channel>,
+    /// The ID of the guild the announcement channel belongs to.
+    pub guild_id: Snowflake<Guild>,
+    /// The name of the announcement channel when the message was crossposted.
+    pub channel_name: String,
+    /// The name of the guild when the message was crossposted.
+    pub guild_name: String,
+}
+
+/// Represents a channel follow record stored in the database.
+pub struct ChannelFollowRecord {
+    pub id: Snowflake<ChannelFollow>,
+    pub channel_id: Snowflake<Channel>,
+    pub target_channel_id: Snowflake<Channel>,
+    pub user_id: Option<i64>,
+}
+
+/// A channel following an announcement channel. Messages sent in the announcement channel
+/// are crossposted to the following channel in the background.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChannelFollow {
+    /// The ID of the follow.
+    id: Snowflake<Self>,
+    /// The announcement channel that is followed.
+    channel_id: Snowflake<Channel>,
+    /// The channel messages are crossposted to.
+    target_channel_id: Snowflake<Channel>,
+    /// The user who followed the channel, if they still exist.
+    user_id: Option<Snowflake<User>>,
+}
+
+impl ChannelFollow {
+    /// Create a new follow. Assigns a new snowflake to the follow.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration.
+    /// * `channel` - The announcement channel to follow.
+    /// * `target` - The channel to crosspost messages to.
+    /// * `user` - The user following the channel.
+    pub fn new(
+        config: &Config,
+        channel: impl Into<Snowflake<Channel>>,
+        target: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Self {
+        Self {
+            id: Snowflake::gen_new(config),
+            channel_id: channel.into(),
+            target_channel_id: target.into(),
+            user_id: Some(user.into()),
+        }
+    }
+
+    /// Build a follow from a database record.
+    pub fn from_record(record: &ChannelFollowRecord) -> Self {
+        Self {
+            id: record.id,
+            channel_id: record.channel_id,
+            target_channel_id: record.target_channel_id,
+            user_id: record.user_id.map(Snowflake::new),
+        }
+    }
+
+    /// The ID of the follow.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The announcement channel that is followed.
+    pub const fn channel_id(&self) -> Snowflake<Channel> {
+        self.channel_id
+    }
+
+    /// The channel messages are crossposted to.
+    pub const fn target_channel_id(&self) -> Snowflake<Channel> {
+        self.target_channel_id
+    }
+
+    /// The user who followed the channel, if they still exist.
+    pub const fn user_id(&self) -> Option<Snowflake<User>> {
+        self.user_id
+    }
+}
+
+/// A queued crosspost as claimed for sending.
+struct CrosspostRecord {
+    id: i64,
+    message_id: i64,
+    target_channel_id: i64,
+    attempts: i32,
+}
+
+/// Crossposts messages sent in announcement channels to the channels following them, in the background.
+///
+/// Crossposts are never published again, even if they are posted to an announcement channel,
+/// so channels following each other cannot cause a loop.
+#[derive(Debug, Clone)]
+pub struct Crossposter {
+    notify: Arc<Notify>,
+    app: Weak<ApplicationState>,
+}
+
+impl Crossposter {
+    /// Create a new crossposter.
+    pub fn new() -> Self {
+        Self {
+            notify: Arc::new(Notify::new()),
+            app: Weak::new(),
+        }
+    }
+
+    pub fn bind_to(&mut self, app: Weak<ApplicationState>) {
+        self.app = app;
+    }
+
+    pub fn app(&self) -> Arc<ApplicationState> {
+        self.app.upgrade().expect("Application state has been dropped.")
+    }
+
+    /// Queue a message to be crossposted to every follower of its channel, as part of the transaction creating it.
+    /// Call [`Crossposter::notify`] after the transaction is committed to crosspost it without delay.
+    /// Messages that are crossposts themselves are never queued.
+    ///
+    /// ## Arguments
+    ///
+    /// * `conn` - The transaction creating the message.
+    /// * `message` - The message sent in an announcement channel.
+    /// * `now` - The current time as a UNIX timestamp.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the message was queued for any follower.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn enqueue(
+        conn: impl Executor<'_, Database = Postgres>,
+        message: &Message,
+        now: i64,
+    ) -> Result<bool, sqlx::Error> {
+        if message.crosspost().is_some() {
+            return Ok(false);
+        }
+
+        let result = sqlx::query!(
+            "INSERT INTO crosspost_queue (message_id, follow_id, attempt_at)
+            SELECT $1, id, $3 FROM channel_follows WHERE channel_id = $2",
+            message.id() as Snowflake<Message>,
+            message.channel_id() as Snowflake<Channel>,
+            now,
+        )
+        .execute(conn)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Wake up the crossposter after messages were queued.
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Wait until [`Crossposter::notify`] is called.
+    pub async fn notified(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Send all queued crossposts that are due.
+    ///
+    /// Failed crossposts are retried after [`LEASE_DURATION`], and dropped after [`MAX_ATTEMPTS`].
+    /// Crossposts of deleted messages, and to archived channels, are dropped.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of queued crossposts processed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn process_queue(&self) -> Result<usize, sqlx::Error> {
+        let app = self.app();
+        let mut processed = 0;
+
+        loop {
+            let now = app.clock.timestamp();
+
+            // Claim due crossposts, so they are not sent twice if another process is still sending them
+            let claimed = sqlx::query_as!(
+                CrosspostRecord,
+                "WITH due AS (
+                    SELECT id FROM crosspost_queue
+                    WHERE attempt_at <= $1
+                    ORDER BY id LIMIT $2
+                    FOR UPDATE SKIP LOCKED
+                )
+                UPDATE crosspost_queue AS q
+                SET attempts = q.attempts + 1, attempt_at = $3
+                FROM due, channel_follows AS f
+                WHERE q.id = due.id AND f.id = q.follow_id
+                RETURNING q.id, q.message_id, q.attempts, f.target_channel_id",
+                now,
+                BATCH_SIZE,
+                now + LEASE_DURATION,
+            )
+            .fetch_all(app.db.executor())
+            .await?;
+
+            let count = claimed.len();
+
+            for record in claimed {
+                match self.crosspost(&app, &record).await {
+                    Ok(()) => {}
+                    Err(e) if record.attempts >= MAX_ATTEMPTS => {
+                        tracing::warn!(message = record.message_id, error = %e, "Dropping crosspost after too many attempts");
+                    }
+                    Err(e) => {
+                        tracing::debug!(message = record.message_id, error = %e, "Crosspost failed, retrying later");
+                        continue;
+                    }
+                }
+                sqlx::query!("DELETE FROM crosspost_queue WHERE id = $1", record.id)
+                    .execute(app.db.executor())
+                    .await?;
+            }
+
+            processed += count;
+
+            if count < usize::try_from(BATCH_SIZE).expect("Batch size should fit into usize") {
+                return Ok(processed);
+            }
+        }
+    }
+
+    /// Post a copy of the queued message to the following channel.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::MessageCreate`] - To all members who can view the following channel
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError`] - If the message could not be fetched or committed.
+    ///
+    /// [`GatewayEvent::MessageCreate`]: crate::models::gateway_event::GatewayEvent::MessageCreate
+    async fn crosspost(&self, app: &ApplicationState, record: &CrosspostRecord) -> Result<(), AppError> {
+        let Some(original) = app.ops().fetch_message(record.message_id).await? else {
+            return Ok(());
+        };
+        let Some(channel) = app.ops().fetch_channel(original.channel_id()).await else {
+            return Ok(());
+        };
+        let Some(guild_id) = channel.guild_id() else {
+            return Ok(());
+        };
+        let Some(guild) = app.ops().fetch_guild(guild_id).await else {
+            return Ok(());
+        };
+        let Some(target) = app
+            .ops()
+            .fetch_channel(record.target_channel_id)
+            .await
+            .filter(|target| !target.is_archived())
+        else {
+            return Ok(());
+        };
+
+        let source = CrosspostSource {
+            message_id: original.id(),
+            channel_id: channel.id(),
+            guild_id: guild.id(),
+            channel_name: channel.name().to_string(),
+            guild_name: guild.name().to_string(),
+        };
+        match original.to_crosspost(Snowflake::gen_new(&app.config), target.id(), source) {
+            Ok(message) => app.ops().update_message(&message).await,
+            // Attachments are not crossposted, messages with nothing else are skipped
+            Err(BuildError::ValidationError(_)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Default for Crossposter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
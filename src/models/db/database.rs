@@ -1,15 +1,53 @@
-use std::sync::{Arc, Weak};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    time::Duration,
+};
 
-use sqlx::{migrate, postgres::PgPool};
+use secrecy::ExposeSecret;
+use serde::Serialize;
+use sqlx::{
+    migrate,
+    migrate::Migrator,
+    postgres::{PgPool, PgPoolOptions},
+    Executor, Postgres,
+};
 
-use crate::models::state::ApplicationState;
+use super::instrumented::Instrumented;
+
+use crate::models::state::{ApplicationState, Config};
+
+/// The migrations embedded into the binary.
+pub static MIGRATOR: Migrator = migrate!("./migrations");
+
+/// The delay before the first connection retry. Doubled after every failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// The maximum delay between two connection attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
 
 #[derive(Clone, Debug)]
 pub struct Database {
     pool: Option<PgPool>,
+    healthy: Arc<AtomicBool>,
+    slow_query_threshold: Duration,
     app: Weak<ApplicationState>,
 }
 
+/// Statistics about the database connection pool.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct PoolStats {
+    /// Whether the last health check succeeded.
+    pub healthy: bool,
+    /// The amount of open connections, including idle ones.
+    pub size: u32,
+    /// The amount of idle connections.
+    pub idle: usize,
+    /// The maximum amount of connections the pool may open.
+    pub max_size: u32,
+}
+
 impl Database {
     /// Creates a new database instance
     ///
@@ -17,6 +55,8 @@ impl Database {
     pub fn new() -> Self {
         Self {
             pool: None,
+            healthy: Arc::new(AtomicBool::new(false)),
+            slow_query_threshold: Duration::MAX,
             app: Weak::new(),
         }
     }
@@ -34,36 +74,153 @@ impl Database {
     /// ## Panics
     ///
     /// If the database is not connected
-    pub fn pool(&self) -> &PgPool {
+    pub const fn pool(&self) -> &PgPool {
         self.pool
             .as_ref()
             .expect("Database is not connected or has been closed.")
     }
 
+    /// The database pool, with query timing instrumentation.
+    /// All queries should be executed through this, see [`Instrumented`].
+    ///
+    /// ## Panics
+    ///
+    /// If the database is not connected
+    pub const fn executor(&self) -> Instrumented<&PgPool> {
+        self.instrument(self.pool())
+    }
+
+    /// Add query timing instrumentation to an executor, such as a transaction.
+    pub const fn instrument<'c, E: Executor<'c, Database = Postgres>>(&self, executor: E) -> Instrumented<E> {
+        Instrumented::new(executor, self.slow_query_threshold)
+    }
+
     /// Checks if the database is connected
     ///
     /// ## Returns
     ///
     /// `true` if the database is connected, `false` otherwise
     pub fn is_connected(&self) -> bool {
-        self.pool.as_ref().map_or(false, |pool| !pool.is_closed())
+        self.pool.as_ref().is_some_and(|pool| !pool.is_closed())
+    }
+
+    /// Checks if the database is connected and the last health check succeeded
+    ///
+    /// ## Returns
+    ///
+    /// `false` if the database is known to be unreachable, `true` otherwise
+    pub fn is_healthy(&self) -> bool {
+        self.is_connected() && self.healthy.load(Ordering::Relaxed)
     }
 
-    /// Connects to the database
+    /// Statistics about the connection pool
+    ///
+    /// ## Panics
+    ///
+    /// If the database is not connected
+    pub fn stats(&self) -> PoolStats {
+        let pool = self.pool();
+        PoolStats {
+            healthy: self.is_healthy(),
+            size: pool.size(),
+            idle: pool.num_idle(),
+            max_size: pool.options().get_max_connections(),
+        }
+    }
+
+    /// Connects to the database, retrying with exponential backoff if it is not reachable yet.
+    /// Migrations are not applied, see [`Database::migrate`].
     ///
     /// ## Arguments
     ///
-    /// * `url` - The postgres connection URL
+    /// * `config` - The application configuration, containing the connection URL and pool settings
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If all connection attempts fail
+    pub async fn connect(&mut self, config: &Config) -> Result<(), sqlx::Error> {
+        let options = PgPoolOptions::new()
+            .min_connections(config.database_min_connections())
+            .max_connections(config.database_max_connections())
+            .acquire_timeout(config.database_acquire_timeout());
+
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 1;
+
+        let pool = loop {
+            match options.clone().connect(config.database_url().expose_secret()).await {
+                Ok(pool) => break pool,
+                Err(e) if attempt < config.database_connect_attempts() => {
+                    tracing::warn!(
+                        error = %e,
+                        attempt,
+                        "Failed to connect to database, retrying in {}ms",
+                        backoff.as_millis()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+
+        self.pool = Some(pool);
+        self.slow_query_threshold = config.database_slow_query_threshold();
+        self.healthy.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Applies all pending migrations.
     ///
     /// ## Errors
     ///
-    /// * [`sqlx::Error`] - If the database connection fails
-    pub async fn connect(&mut self, url: &str) -> Result<(), sqlx::Error> {
-        self.pool = Some(PgPool::connect(url).await?);
-        migrate!("./migrations").run(self.pool()).await?;
+    /// * [`sqlx::Error`] - If a migration fails to apply
+    pub async fn migrate(&self) -> Result<(), sqlx::Error> {
+        MIGRATOR.run(self.pool()).await?;
         Ok(())
     }
 
+    /// Checks if the database is reachable and updates the health status accordingly.
+    /// Status changes are logged.
+    ///
+    /// ## Arguments
+    ///
+    /// * `timeout` - The maximum time to wait for the database to respond
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the database is reachable, `false` otherwise
+    pub async fn check_health(&self, timeout: Duration) -> bool {
+        let result = tokio::time::timeout(timeout, sqlx::query("SELECT 1").execute(self.executor())).await;
+
+        let healthy = matches!(result, Ok(Ok(_)));
+        let was_healthy = self.healthy.swap(healthy, Ordering::Relaxed);
+
+        match (was_healthy, healthy) {
+            (true, false) => {
+                if let Ok(Err(e)) = result {
+                    tracing::error!(error = %e, "Database became unreachable");
+                } else {
+                    tracing::error!("Database became unreachable: health check timed out");
+                }
+            }
+            (false, true) => tracing::info!("Database is reachable again"),
+            _ => {}
+        }
+
+        let stats = self.stats();
+        tracing::debug!(
+            healthy = stats.healthy,
+            size = stats.size,
+            idle = stats.idle,
+            max_size = stats.max_size,
+            "Database pool stats"
+        );
+
+        healthy
+    }
+
     /// Closes the database connection
     pub async fn close(&self) {
         self.pool().close().await;
@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
+use sqlx::{
+    postgres::{PgQueryResult, PgRow, PgStatement, PgTypeInfo},
+    Describe, Either, Execute, Executor, Postgres,
+};
+
+use crate::models::metrics::DB_QUERY_DURATION;
+
+/// An executor that measures the time taken by every query it runs.
+///
+/// Durations are recorded in [`DB_QUERY_DURATION`], and queries slower than the threshold are logged.
+/// Only the SQL of the query is logged, bound parameters are never included.
+#[derive(Debug)]
+pub struct Instrumented<E> {
+    inner: E,
+    slow_threshold: Duration,
+}
+
+impl<E> Instrumented<E> {
+    /// Wrap an executor, logging queries that take longer than `slow_threshold`.
+    pub const fn new(inner: E, slow_threshold: Duration) -> Self {
+        Self { inner, slow_threshold }
+    }
+}
+
+/// Records the duration of a query when dropped, so that cancelled queries are also accounted for.
+/// The query is traced in a span that is open for as long as the timer exists.
+struct QueryTimer {
+    sql: String,
+    start: Instant,
+    slow_threshold: Duration,
+    _span: tracing::Span,
+}
+
+impl QueryTimer {
+    fn start(sql: &str, slow_threshold: Duration) -> Self {
+        let sql = normalize(sql);
+        let span = tracing::info_span!(
+            "db.query",
+            otel.kind = "client",
+            db.system = "postgresql",
+            db.statement = %sql,
+        );
+
+        Self {
+            sql,
+            start: Instant::now(),
+            slow_threshold,
+            _span: span,
+        }
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+
+        DB_QUERY_DURATION
+            .with_label_values(&[&self.sql])
+            .observe(elapsed.as_secs_f64());
+
+        if elapsed >= self.slow_threshold {
+            tracing::warn!(elapsed_ms = elapsed.as_millis(), query = %self.sql, "Slow database query");
+        }
+    }
+}
+
+/// Collapse all whitespace in an SQL statement, so that it fits on a single line.
+fn normalize(sql: &str) -> String {
+    sql.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl<'c, E> Executor<'c> for Instrumented<E>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    type Database = Postgres;
+
+    fn fetch_many<'e, 'q: 'e, Q>(self, query: Q) -> BoxStream<'e, Result<Either<PgQueryResult, PgRow>, sqlx::Error>>
+    where
+        'c: 'e,
+        Q: 'q + Execute<'q, Postgres>,
+    {
+        let timer = QueryTimer::start(query.sql(), self.slow_threshold);
+        self.inner
+            .fetch_many(query)
+            .map(move |item| {
+                let _ = &timer;
+                item
+            })
+            .boxed()
+    }
+
+    fn fetch_optional<'e, 'q: 'e, Q>(self, query: Q) -> BoxFuture<'e, Result<Option<PgRow>, sqlx::Error>>
+    where
+        'c: 'e,
+        Q: 'q + Execute<'q, Postgres>,
+    {
+        let timer = QueryTimer::start(query.sql(), self.slow_threshold);
+        let fut = self.inner.fetch_optional(query);
+        async move {
+            let result = fut.await;
+            drop(timer);
+            result
+        }
+        .boxed()
+    }
+
+    fn prepare_with<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+        parameters: &'e [PgTypeInfo],
+    ) -> BoxFuture<'e, Result<PgStatement<'q>, sqlx::Error>>
+    where
+        'c: 'e,
+    {
+        self.inner.prepare_with(sql, parameters)
+    }
+
+    fn describe<'e, 'q: 'e>(self, sql: &'q str) -> BoxFuture<'e, Result<Describe<Postgres>, sqlx::Error>>
+    where
+        'c: 'e,
+    {
+        self.inner.describe(sql)
+    }
+}
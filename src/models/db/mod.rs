@@ -1,3 +1,5 @@
 pub mod database;
+pub mod instrumented;
 
 pub use database::Database;
+pub use instrumented::Instrumented;
@@ -0,0 +1,440 @@
+use std::collections::{HashMap, HashSet};
+
+use secrecy::ExposeSecret;
+
+use super::{
+    bucket::ContentClass,
+    db::database::MIGRATOR,
+    instance::InstanceLease,
+    snowflake,
+    state::{ApplicationState, Config},
+};
+
+/// The minimum length of the app secret.
+const MIN_SECRET_LEN: usize = 32;
+/// The minimum estimated entropy of the app secret in bits.
+const MIN_SECRET_ENTROPY: f64 = 128.0;
+/// The largest machine or process ID that fits into a snowflake.
+const MAX_SNOWFLAKE_NODE_ID: i32 = 31;
+
+/// When the self-check is run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// On startup, after migrations were applied. Claims the machine and process IDs for this instance.
+    Startup,
+    /// As a standalone check with `--check`. Does not apply migrations or claim IDs.
+    Check,
+}
+
+/// The outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// The check passed.
+    Passed,
+    /// The check failed, but the problem was fixed automatically.
+    Fixed,
+    /// The check could not be completed, or found a problem that does not prevent startup.
+    Warning,
+    /// The check failed, the server should not be started.
+    Failed,
+}
+
+/// The result of a single check.
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    name: &'static str,
+    status: CheckStatus,
+    message: String,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, status: CheckStatus, message: impl Into<String>) -> Self {
+        Self {
+            name,
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// The name of the check.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The outcome of the check.
+    pub const fn status(&self) -> CheckStatus {
+        self.status
+    }
+
+    /// A human-readable description of the outcome.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// The results of all checks.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    results: Vec<CheckResult>,
+}
+
+impl Report {
+    /// The results of all checks, in the order they were run.
+    pub fn results(&self) -> &[CheckResult] {
+        &self.results
+    }
+
+    /// The amount of failed checks.
+    pub fn failures(&self) -> usize {
+        self.results.iter().filter(|r| r.status == CheckStatus::Failed).count()
+    }
+
+    /// Log the result of every check.
+    pub fn log(&self) {
+        for result in &self.results {
+            match result.status {
+                CheckStatus::Passed | CheckStatus::Fixed => {
+                    tracing::info!(check = result.name, "{}", result.message);
+                }
+                CheckStatus::Warning => tracing::warn!(check = result.name, "{}", result.message),
+                CheckStatus::Failed => tracing::error!(check = result.name, "{}", result.message),
+            }
+        }
+    }
+}
+
+/// Verify that the database schema, storage and configuration are fit to run the server.
+///
+/// ## Arguments
+///
+/// * `app` - The application state. The database must be connected.
+/// * `mode` - When the check is run.
+///
+/// ## Returns
+///
+/// A [`Report`] containing the results of all checks.
+pub async fn run(app: &ApplicationState, mode: Mode) -> Report {
+    let schema = check_schema(app).await;
+    let schema_ok = schema.status == CheckStatus::Passed;
+
+    let results = vec![
+        schema,
+        check_storage(app).await,
+        check_app_secret(&app.config),
+        check_instance(app, mode, schema_ok).await,
+        check_snowflake_epoch(app, mode, schema_ok).await,
+    ];
+
+    Report { results }
+}
+
+/// Check that exactly the migrations embedded into the binary were applied to the database.
+async fn check_schema(app: &ApplicationState) -> CheckResult {
+    const NAME: &str = "schema";
+
+    let applied = match fetch_applied_migrations(app).await {
+        Ok(applied) => applied,
+        Err(e) => return CheckResult::new(NAME, CheckStatus::Failed, format!("Failed to query migrations: {e}")),
+    };
+
+    let mut pending = 0;
+    let mut modified = Vec::new();
+    let mut known = HashSet::new();
+
+    for migration in MIGRATOR.iter().filter(|m| !m.migration_type.is_down_migration()) {
+        known.insert(migration.version);
+        match applied.get(&migration.version) {
+            None => pending += 1,
+            Some((checksum, _)) if checksum.as_slice() != &*migration.checksum => modified.push(migration.version),
+            Some(_) => {}
+        }
+    }
+
+    let mut problems = Vec::new();
+
+    if pending > 0 {
+        problems.push(format!(
+            "{pending} pending migration(s), start the server to apply them"
+        ));
+    }
+    if !modified.is_empty() {
+        problems.push(format!("migration(s) changed after being applied: {modified:?}"));
+    }
+
+    let mut unknown: Vec<i64> = applied.keys().filter(|v| !known.contains(*v)).copied().collect();
+    if !unknown.is_empty() {
+        unknown.sort_unstable();
+        problems.push(format!(
+            "migration(s) applied by a newer version of the server: {unknown:?}"
+        ));
+    }
+
+    let mut dirty: Vec<i64> = applied.iter().filter(|(_, (_, ok))| !ok).map(|(v, _)| *v).collect();
+    if !dirty.is_empty() {
+        dirty.sort_unstable();
+        problems.push(format!("migration(s) that failed to apply: {dirty:?}"));
+    }
+
+    if problems.is_empty() {
+        CheckResult::new(NAME, CheckStatus::Passed, format!("{} migrations applied", known.len()))
+    } else {
+        CheckResult::new(
+            NAME,
+            CheckStatus::Failed,
+            format!("Schema does not match this version: {}", problems.join(", ")),
+        )
+    }
+}
+
+/// Fetch the checksum and success of all applied migrations by version.
+async fn fetch_applied_migrations(app: &ApplicationState) -> Result<HashMap<i64, (Vec<u8>, bool)>, sqlx::Error> {
+    // The migrations table does not exist until the first migration is applied
+    let exists: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(app.db.executor())
+        .await?;
+
+    if !exists {
+        return Ok(HashMap::new());
+    }
+
+    let rows: Vec<(i64, Vec<u8>, bool)> = sqlx::query_as("SELECT version, checksum, success FROM _sqlx_migrations")
+        .fetch_all(app.db.executor())
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(version, checksum, success)| (version, (checksum, success)))
+        .collect())
+}
+
+/// Check that all buckets are configured correctly and exist, creating them if configured to do so.
+/// Lifecycle policies are applied to every bucket whose objects expire.
+async fn check_storage(app: &ApplicationState) -> CheckResult {
+    const NAME: &str = "storage";
+
+    if let Err(e) = app.config.buckets().validate() {
+        return CheckResult::new(NAME, CheckStatus::Failed, format!("Invalid bucket configuration: {e}"));
+    }
+
+    let mut missing = Vec::new();
+    let mut created = Vec::new();
+
+    for class in ContentClass::ALL {
+        let bucket = app.s3.get(class);
+        let name = bucket.name().to_string();
+
+        let exists = match bucket.exists().await {
+            Ok(exists) => exists,
+            // Storage outages are handled at runtime, so they do not prevent startup
+            Err(e) => return CheckResult::new(NAME, CheckStatus::Warning, format!("Storage is unreachable: {e}")),
+        };
+
+        if !exists {
+            if !app.config.s3_create_buckets() {
+                missing.push(name);
+                continue;
+            }
+
+            if let Err(e) = bucket.create().await {
+                return CheckResult::new(
+                    NAME,
+                    CheckStatus::Failed,
+                    format!("Failed to create bucket '{name}': {e}"),
+                );
+            }
+            created.push(name.clone());
+        }
+
+        if let Err(e) = bucket.apply_lifecycle().await {
+            return CheckResult::new(
+                NAME,
+                CheckStatus::Warning,
+                format!("Failed to apply lifecycle policy to bucket '{name}': {e}"),
+            );
+        }
+    }
+    if !missing.is_empty() {
+        CheckResult::new(
+            NAME,
+            CheckStatus::Failed,
+            format!(
+                "Missing bucket(s): {}. Set S3_CREATE_BUCKETS=true to create them on startup",
+                missing.join(", ")
+            ),
+        )
+    } else if !created.is_empty() {
+        CheckResult::new(
+            NAME,
+            CheckStatus::Fixed,
+            format!("Created missing bucket(s): {}", created.join(", ")),
+        )
+    } else {
+        CheckResult::new(NAME, CheckStatus::Passed, "All buckets exist")
+    }
+}
+
+/// Check that the app secrets are long and random enough to not be guessed.
+fn check_app_secret(config: &Config) -> CheckResult {
+    const NAME: &str = "app_secret";
+
+    let mut weakest = f64::INFINITY;
+    for key in config.signing_keys().iter() {
+        let secret = key.secret().expose_secret();
+        let len = secret.chars().count();
+        let entropy = estimate_entropy(secret);
+
+        if len < MIN_SECRET_LEN || entropy < MIN_SECRET_ENTROPY {
+            return CheckResult::new(
+                NAME,
+                CheckStatus::Failed,
+                format!(
+                    "The secret of signing key '{}' is too weak ({len} characters, ~{entropy:.0} bits of entropy), \
+                    it must be at least {MIN_SECRET_LEN} characters with {MIN_SECRET_ENTROPY:.0} bits of entropy. \
+                    Generate one with 'openssl rand -hex 32'",
+                    key.id()
+                ),
+            );
+        }
+        weakest = weakest.min(entropy);
+    }
+
+    CheckResult::new(NAME, CheckStatus::Passed, format!("~{weakest:.0} bits of entropy"))
+}
+
+/// Estimate the entropy of a string in bits, based on the frequency of its characters.
+fn estimate_entropy(s: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in s.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+
+    let len = s.chars().count() as f64;
+    let per_char: f64 = counts
+        .values()
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum();
+
+    per_char * len
+}
+
+/// Check that the machine and process IDs are valid and not used by another live instance.
+async fn check_instance(app: &ApplicationState, mode: Mode, schema_ok: bool) -> CheckResult {
+    const NAME: &str = "instance";
+
+    let config = &app.config;
+
+    for (key, id) in [("MACHINE_ID", config.machine_id()), ("PROCESS_ID", config.process_id())] {
+        if !(0..=MAX_SNOWFLAKE_NODE_ID).contains(&id) {
+            return CheckResult::new(
+                NAME,
+                CheckStatus::Failed,
+                format!("{key} must be between 0 and {MAX_SNOWFLAKE_NODE_ID}, got {id}"),
+            );
+        }
+    }
+
+    if !schema_ok {
+        return CheckResult::new(
+            NAME,
+            CheckStatus::Warning,
+            "Skipped instance check, the database schema is not up to date",
+        );
+    }
+
+    let holder = match mode {
+        Mode::Startup => app.instance.claim(&app.db, config, app.clock.now()).await,
+        Mode::Check => InstanceLease::last_heartbeat(&app.db, config, app.clock.now()).await,
+    };
+
+    match holder {
+        Ok(None) => CheckResult::new(
+            NAME,
+            CheckStatus::Passed,
+            format!(
+                "MACHINE_ID={} and PROCESS_ID={} are not used by another instance",
+                config.machine_id(),
+                config.process_id()
+            ),
+        ),
+        Ok(Some(last_heartbeat)) => CheckResult::new(
+            NAME,
+            CheckStatus::Failed,
+            format!(
+                "Another live instance uses MACHINE_ID={} and PROCESS_ID={} (last heartbeat {}s ago), \
+                snowflake IDs would collide",
+                config.machine_id(),
+                config.process_id(),
+                app.clock.timestamp() - last_heartbeat
+            ),
+        ),
+        Err(e) => CheckResult::new(NAME, CheckStatus::Failed, format!("Failed to query instances: {e}")),
+    }
+}
+
+/// Check that the snowflake epoch is in the past and matches the epoch existing snowflakes were generated with.
+/// On startup, the epoch is recorded if no server has been started on the database yet.
+async fn check_snowflake_epoch(app: &ApplicationState, mode: Mode, schema_ok: bool) -> CheckResult {
+    const NAME: &str = "snowflake_epoch";
+
+    let configured = app.config.snowflake_epoch();
+
+    if configured > app.clock.now().timestamp_millis() {
+        return CheckResult::new(
+            NAME,
+            CheckStatus::Failed,
+            format!("SNOWFLAKE_EPOCH must not be in the future, got {configured}"),
+        );
+    }
+
+    if !schema_ok {
+        return CheckResult::new(
+            NAME,
+            CheckStatus::Warning,
+            "Skipped snowflake epoch check, the database schema is not up to date",
+        );
+    }
+
+    let stored = match mode {
+        Mode::Startup => snowflake::store_epoch(&app.db, configured).await.map(Some),
+        Mode::Check => snowflake::fetch_stored_epoch(&app.db).await,
+    };
+
+    match stored {
+        Ok(Some(stored)) if stored != configured => CheckResult::new(
+            NAME,
+            CheckStatus::Failed,
+            format!(
+                "SNOWFLAKE_EPOCH is {configured}, but existing snowflake IDs were generated with epoch {stored}. \
+                Changing the epoch would change the creation time of every ID, set SNOWFLAKE_EPOCH={stored}"
+            ),
+        ),
+        Ok(_) => CheckResult::new(
+            NAME,
+            CheckStatus::Passed,
+            format!("Snowflake IDs use epoch {configured}"),
+        ),
+        Err(e) => CheckResult::new(
+            NAME,
+            CheckStatus::Failed,
+            format!("Failed to query snowflake epoch: {e}"),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_entropy, MIN_SECRET_ENTROPY};
+
+    #[test]
+    fn test_estimate_entropy() {
+        assert!(estimate_entropy("") < 1.0);
+        assert!(estimate_entropy("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa") < 1.0);
+        assert!(estimate_entropy("set_me_to_something_random") < MIN_SECRET_ENTROPY);
+        assert!(
+            estimate_entropy("3f9c1a7e5b2d8f04c6e1a9b7d3f5c2e80a4b6d8f1c3e5a7b9d0f2a4c6e8b1d3f") >= MIN_SECRET_ENTROPY
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+use super::errors::BuildError;
+
+/// The maximum length of an embed's title, in characters.
+pub const MAX_TITLE_LENGTH: usize = 256;
+/// The maximum length of an embed's description, in characters.
+pub const MAX_DESCRIPTION_LENGTH: usize = 1000;
+/// The maximum length of an embed's author and provider names, in characters.
+pub const MAX_NAME_LENGTH: usize = 100;
+
+/// Rich content attached to a message, such as a preview of a linked page.
+///
+/// Embeds are created by the server, for example for the entries of a [`Feed`](super::feed::Feed),
+/// users cannot send them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Builder)]
+#[builder(default, setter(into, strip_option), build_fn(error = "BuildError"))]
+pub struct Embed {
+    /// The title of the embed, at most [`MAX_TITLE_LENGTH`] characters.
+    title: Option<String>,
+    /// The text of the embed, at most [`MAX_DESCRIPTION_LENGTH`] characters.
+    description: Option<String>,
+    /// The URL the title links to.
+    url: Option<String>,
+    /// The name of the author of the embedded content.
+    author: Option<String>,
+    /// The name of the source of the embed, such as the title of a feed.
+    provider: Option<String>,
+    /// UNIX timestamp of when the embedded content was published.
+    timestamp: Option<i64>,
+}
+
+impl Embed {
+    /// Create a new builder for an embed.
+    pub fn builder() -> EmbedBuilder {
+        EmbedBuilder::default()
+    }
+
+    /// The title of the embed.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// The text of the embed.
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The URL the title links to.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// The name of the author of the embedded content.
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    /// The name of the source of the embed.
+    pub fn provider(&self) -> Option<&str> {
+        self.provider.as_deref()
+    }
+
+    /// UNIX timestamp of when the embedded content was published.
+    pub const fn timestamp(&self) -> Option<i64> {
+        self.timestamp
+    }
+}
+
+/// Trim a value and cut it off after `max` characters, discarding it if it is empty.
+///
+/// ## Arguments
+///
+/// * `value` - The value to truncate.
+/// * `max` - The maximum length of the value in characters, not counting the ellipsis added when it is cut off.
+pub fn truncate(value: &str, max: usize) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return None;
+    }
+    match value.char_indices().nth(max) {
+        Some((end, _)) => Some(format!("{}…", value[..end].trim_end())),
+        None => Some(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::truncate;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("  hello ", 10).as_deref(), Some("hello"));
+        assert_eq!(truncate("hello world", 6).as_deref(), Some("hello…"));
+        assert_eq!(truncate("héllo", 2).as_deref(), Some("hé…"));
+        assert_eq!(truncate("   ", 10), None);
+    }
+}
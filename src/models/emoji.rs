@@ -0,0 +1,362 @@
+use std::{collections::HashMap, sync::LazyLock};
+
+use serde::Serialize;
+
+/// Joins emojis into a single sequence, such as a family.
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+/// Requests the emoji presentation of the preceding character.
+const VARIATION_SELECTOR_16: char = '\u{FE0F}';
+/// Turns a preceding digit, `#` or `*` into a keycap emoji.
+const COMBINING_KEYCAP: char = '\u{20E3}';
+
+/// The maximum length of a shortcode's name, excluding the colons.
+pub const MAX_SHORTCODE_LENGTH: usize = 32;
+
+/// Shortcodes that are replaced by the unicode emoji they stand for, sorted by name.
+///
+/// The names follow the ones most chat clients use, some emojis have more than one.
+const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("airplane", "✈️"),
+    ("alarm_clock", "⏰"),
+    ("angry", "😠"),
+    ("apple", "🍎"),
+    ("balloon", "🎈"),
+    ("beer", "🍺"),
+    ("beers", "🍻"),
+    ("bell", "🔔"),
+    ("birthday", "🎂"),
+    ("blush", "😊"),
+    ("bomb", "💣"),
+    ("book", "📖"),
+    ("boom", "💥"),
+    ("bow", "🙇"),
+    ("broken_heart", "💔"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("cake", "🍰"),
+    ("calendar", "📆"),
+    ("camera", "📷"),
+    ("cat", "🐱"),
+    ("check", "✔️"),
+    ("christmas_tree", "🎄"),
+    ("clap", "👏"),
+    ("cloud", "☁️"),
+    ("coffee", "☕"),
+    ("cold_sweat", "😰"),
+    ("computer", "💻"),
+    ("confused", "😕"),
+    ("cookie", "🍪"),
+    ("cool", "🆒"),
+    ("crown", "👑"),
+    ("cry", "😢"),
+    ("dancer", "💃"),
+    ("disappointed", "😞"),
+    ("dog", "🐶"),
+    ("exclamation", "❗"),
+    ("eyes", "👀"),
+    ("facepalm", "🤦"),
+    ("fire", "🔥"),
+    ("fist", "✊"),
+    ("flushed", "😳"),
+    ("frowning", "😦"),
+    ("gem", "💎"),
+    ("ghost", "👻"),
+    ("gift", "🎁"),
+    ("grimacing", "😬"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("handshake", "🤝"),
+    ("heart", "❤️"),
+    ("heart_eyes", "😍"),
+    ("hourglass", "⌛"),
+    ("hugs", "🤗"),
+    ("hushed", "😯"),
+    ("innocent", "😇"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("kiss", "💋"),
+    ("kissing_heart", "😘"),
+    ("laughing", "😆"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("moneybag", "💰"),
+    ("moon", "🌙"),
+    ("muscle", "💪"),
+    ("nerd_face", "🤓"),
+    ("neutral_face", "😐"),
+    ("no_entry", "⛔"),
+    ("ok_hand", "👌"),
+    ("open_mouth", "😮"),
+    ("partying_face", "🥳"),
+    ("pensive", "😔"),
+    ("pizza", "🍕"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("point_up", "☝️"),
+    ("poop", "💩"),
+    ("pray", "🙏"),
+    ("question", "❓"),
+    ("rage", "😡"),
+    ("rainbow", "🌈"),
+    ("raised_hands", "🙌"),
+    ("relaxed", "☺️"),
+    ("relieved", "😌"),
+    ("robot", "🤖"),
+    ("rocket", "🚀"),
+    ("rofl", "🤣"),
+    ("rose", "🌹"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("shrug", "🤷"),
+    ("skull", "💀"),
+    ("sleeping", "😴"),
+    ("slightly_smiling_face", "🙂"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("smirk", "😏"),
+    ("sob", "😭"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("star_struck", "🤩"),
+    ("stuck_out_tongue", "😛"),
+    ("sunglasses", "😎"),
+    ("sunny", "☀️"),
+    ("sweat", "😓"),
+    ("sweat_smile", "😅"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("trophy", "🏆"),
+    ("unamused", "😒"),
+    ("upside_down_face", "🙃"),
+    ("v", "✌️"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("weary", "😩"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("worried", "😟"),
+    ("x", "❌"),
+    ("yum", "😋"),
+    ("zap", "⚡"),
+    ("zipper_mouth_face", "🤐"),
+    ("zzz", "💤"),
+];
+
+/// Maps the name of each shortcode to its emoji.
+static SHORTCODE_MAP: LazyLock<HashMap<&'static str, &'static str>> =
+    LazyLock::new(|| SHORTCODES.iter().copied().collect());
+
+/// Represents an emoji usage record stored in the database.
+pub struct EmojiUsageRecord {
+    pub emoji: String,
+    pub count: i64,
+    pub last_used_at: i64,
+}
+
+/// How often an emoji was used in a guild.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct EmojiUsage {
+    /// The emoji.
+    emoji: String,
+    /// The amount of times the emoji was used.
+    count: i64,
+    /// The UNIX timestamp of when the emoji was last used.
+    last_used_at: i64,
+}
+
+impl EmojiUsage {
+    /// Create a new emoji usage from a database record.
+    pub fn from_record(record: EmojiUsageRecord) -> Self {
+        Self {
+            emoji: record.emoji,
+            count: record.count,
+            last_used_at: record.last_used_at,
+        }
+    }
+
+    /// The emoji.
+    pub fn emoji(&self) -> &str {
+        &self.emoji
+    }
+
+    /// The amount of times the emoji was used.
+    pub const fn count(&self) -> i64 {
+        self.count
+    }
+
+    /// The UNIX timestamp of when the emoji was last used.
+    pub const fn last_used_at(&self) -> i64 {
+        self.last_used_at
+    }
+}
+
+/// Look up the unicode emoji a shortcode stands for.
+///
+/// ## Arguments
+///
+/// * `name` - The name of the shortcode, without the surrounding colons, such as `tada`. Matched case-insensitively.
+pub fn from_shortcode(name: &str) -> Option<&'static str> {
+    if name.len() > MAX_SHORTCODE_LENGTH {
+        return None;
+    }
+    SHORTCODE_MAP.get(name.to_ascii_lowercase().as_str()).copied()
+}
+
+/// Whether the string is a valid shortcode or custom emoji name.
+/// Names are made of ASCII letters, digits, `_`, `+` and `-`, and are at most [`MAX_SHORTCODE_LENGTH`] long.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_SHORTCODE_LENGTH
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'+' | b'-'))
+}
+
+/// Count how often each emoji occurs in a string.
+///
+/// Emoji sequences, such as flags, keycaps, skin tones and ZWJ sequences, are counted as a single emoji.
+///
+/// ## Arguments
+///
+/// * `content` - The string to search for emojis.
+///
+/// ## Returns
+///
+/// A map of each emoji to the amount of times it occurs.
+pub fn count_emojis(content: &str) -> HashMap<String, i64> {
+    let mut counts = HashMap::new();
+    for emoji in find_emojis(content) {
+        *counts.entry(emoji.to_string()).or_default() += 1;
+    }
+    counts
+}
+
+/// Find all emojis in a string, in order of occurrence.
+fn find_emojis(content: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = content.char_indices().collect();
+    let end_of = |i: usize| chars.get(i).map_or(content.len(), |(idx, _)| *idx);
+    let char_at = |i: usize| chars.get(i).map(|(_, c)| *c);
+
+    let mut emojis = Vec::new();
+    let mut i = 0;
+
+    while let Some(c) = char_at(i) {
+        let start = i;
+
+        if is_regional_indicator(c) {
+            // Flags are made of two regional indicators, lone ones are not emojis
+            if char_at(i + 1).is_some_and(is_regional_indicator) {
+                emojis.push(&content[chars[start].0..end_of(i + 2)]);
+                i += 2;
+            } else {
+                i += 1;
+            }
+        } else if is_keycap_base(c) {
+            let mut j = i + 1;
+            if char_at(j) == Some(VARIATION_SELECTOR_16) {
+                j += 1;
+            }
+            if char_at(j) == Some(COMBINING_KEYCAP) {
+                emojis.push(&content[chars[start].0..end_of(j + 1)]);
+                i = j + 1;
+            } else {
+                i += 1;
+            }
+        } else if is_pictographic(c) {
+            i = skip_modifiers(&chars, i + 1);
+            while char_at(i) == Some(ZERO_WIDTH_JOINER) && char_at(i + 1).is_some_and(is_pictographic) {
+                i = skip_modifiers(&chars, i + 2);
+            }
+            emojis.push(&content[chars[start].0..end_of(i)]);
+        } else {
+            i += 1;
+        }
+    }
+
+    emojis
+}
+
+/// Skip over characters modifying the preceding emoji, returning the index of the first other character.
+fn skip_modifiers(chars: &[(usize, char)], mut i: usize) -> usize {
+    while chars.get(i).is_some_and(|(_, c)| is_modifier(*c)) {
+        i += 1;
+    }
+    i
+}
+
+/// Whether the character is a variation selector, skin tone or tag that modifies the preceding emoji.
+const fn is_modifier(c: char) -> bool {
+    matches!(c, VARIATION_SELECTOR_16 | '\u{1F3FB}'..='\u{1F3FF}' | '\u{E0020}'..='\u{E007F}')
+}
+
+/// Whether the character is one of the regional indicators flags are made of.
+const fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1F1E6}'..='\u{1F1FF}')
+}
+
+/// Whether the character can be turned into a keycap emoji.
+const fn is_keycap_base(c: char) -> bool {
+    matches!(c, '0'..='9' | '#' | '*')
+}
+
+/// Whether the character is an emoji on its own.
+const fn is_pictographic(c: char) -> bool {
+    !is_modifier(c)
+        && !is_regional_indicator(c)
+        && matches!(
+            c,
+            '\u{2300}'..='\u{23FF}' | '\u{2600}'..='\u{27BF}' | '\u{2B00}'..='\u{2BFF}' | '\u{1F000}'..='\u{1FAFF}'
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_emojis, find_emojis, from_shortcode, is_valid_name, SHORTCODES};
+
+    #[test]
+    fn test_find_emojis() {
+        assert!(find_emojis("Hello, World! 123 #tag").is_empty());
+        assert_eq!(find_emojis("hi 👋 there 🎉🎉"), vec!["👋", "🎉", "🎉"]);
+        // Skin tones, ZWJ sequences, flags and keycaps are single emojis
+        assert_eq!(find_emojis("👍🏽"), vec!["👍🏽"]);
+        assert_eq!(find_emojis("👨‍👩‍👧"), vec!["👨‍👩‍👧"]);
+        assert_eq!(find_emojis("🇭🇺🇩🇪"), vec!["🇭🇺", "🇩🇪"]);
+        assert_eq!(find_emojis("1️⃣ 2"), vec!["1️⃣"]);
+        assert_eq!(find_emojis("❤️!"), vec!["❤️"]);
+        // Lone regional indicators and modifiers are not emojis
+        assert!(find_emojis("🇭 🏽").is_empty());
+    }
+
+    #[test]
+    fn test_count_emojis() {
+        let counts = count_emojis("🎉 party 🎉 👋");
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["🎉"], 2);
+        assert_eq!(counts["👋"], 1);
+    }
+
+    #[test]
+    fn test_shortcodes() {
+        assert!(
+            SHORTCODES.windows(2).all(|w| w[0].0 < w[1].0),
+            "Shortcodes should be sorted"
+        );
+        assert!(SHORTCODES.iter().all(|(name, _)| is_valid_name(name)));
+        // Every shortcode must stand for exactly one emoji
+        assert!(SHORTCODES.iter().all(|(_, emoji)| find_emojis(emoji) == vec![*emoji]));
+
+        assert_eq!(from_shortcode("tada"), Some("🎉"));
+        assert_eq!(from_shortcode("TADA"), Some("🎉"));
+        assert_eq!(from_shortcode("+1"), from_shortcode("thumbsup"));
+        assert_eq!(from_shortcode("not_an_emoji"), None);
+        assert!(!is_valid_name("no spaces"));
+        assert!(!is_valid_name(""));
+    }
+}
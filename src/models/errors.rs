@@ -13,17 +13,23 @@ use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 use thiserror::Error;
 
+use super::{join_requirements::JoinRequirementError, limits::LimitExceeded, raid_mode::SlowModeError};
+
+pub use chat_core::errors::ErrorCode;
+
 /// An error response returned by the REST API.
 #[derive(Debug, Clone)]
 pub struct ErrResponse {
     status: StatusCode,
+    code: ErrorCode,
     error: String,
 }
 
 impl ErrResponse {
-    pub fn new(status: StatusCode, error: impl Into<String>) -> Self {
+    pub fn new(status: StatusCode, code: ErrorCode, error: impl Into<String>) -> Self {
         Self {
             error: error.into(),
+            code,
             status,
         }
     }
@@ -35,6 +41,11 @@ impl ErrResponse {
         self.status
     }
 
+    /// The machine-readable code of the error.
+    pub const fn code(&self) -> ErrorCode {
+        self.code
+    }
+
     /// The error message.
     pub fn error(&self) -> &str {
         &self.error
@@ -58,7 +69,8 @@ impl IntoResponse for ErrResponse {
             self.status,
             Json(json!(
                 {
-                    "error": self.error
+                    "error": self.error,
+                    "code": self.code,
                 }
             )),
         )
@@ -77,7 +89,8 @@ impl IntoResponse for ErrResponse {
             self.status,
             Json(json!(
                 {
-                    "error": reason
+                    "error": reason,
+                    "code": self.code,
                 }
             )),
         )
@@ -95,6 +108,17 @@ pub enum BuildError {
     /// A validation check failed.
     #[error("Validation error: {0}")]
     ValidationError(String),
+    /// A field has a value that is not allowed. Unlike [`BuildError::ValidationError`],
+    /// the response includes the field and a machine-readable error code.
+    #[error("Validation error: {message}")]
+    InvalidField {
+        /// The name of the invalid field.
+        field: &'static str,
+        /// A machine-readable code describing why the field is invalid.
+        code: ErrorCode,
+        /// A human-readable description of the error.
+        message: String,
+    },
 }
 
 impl From<UninitializedFieldError> for BuildError {
@@ -113,14 +137,26 @@ impl IntoResponse for BuildError {
     fn into_response(self) -> Response {
         let status = match self {
             Self::UninitializedField(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::ValidationError(_) | Self::InvalidField { .. } => StatusCode::BAD_REQUEST,
         };
         if status == StatusCode::INTERNAL_SERVER_ERROR {
             tracing::error!(error = %self);
         }
-        let body = Json(json!({
-            "error": self.to_string()
-        }));
+        let body = match self {
+            Self::InvalidField { field, code, .. } => Json(json!({
+                "error": self.to_string(),
+                "field": field,
+                "code": code,
+            })),
+            Self::ValidationError(_) => Json(json!({
+                "error": self.to_string(),
+                "code": ErrorCode::ValidationFailed,
+            })),
+            Self::UninitializedField(_) => Json(json!({
+                "error": self.to_string(),
+                "code": ErrorCode::InternalError,
+            })),
+        };
         (status, body).into_response()
     }
 }
@@ -141,6 +177,9 @@ pub enum AuthError {
     /// Sent when the user provides an invalid token.
     #[error("Invalid token")]
     InvalidToken,
+    /// Sent when a request authenticated by a session cookie lacks a valid CSRF token.
+    #[error("Missing or invalid CSRF token")]
+    InvalidCsrfToken,
     /// Sent when the server fails to hash a password.
     #[error("Failed to generate password hash: {0}")]
     PasswordHash(#[from] argon2::password_hash::Error),
@@ -148,13 +187,15 @@ pub enum AuthError {
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
-        let status = match self {
-            Self::MissingCredentials | Self::TokenCreation | Self::InvalidToken | Self::InvalidCredentials => {
-                StatusCode::UNAUTHORIZED
-            }
-            Self::PasswordHash(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        let (status, code) = match self {
+            Self::MissingCredentials => (StatusCode::UNAUTHORIZED, ErrorCode::MissingCredentials),
+            Self::InvalidToken => (StatusCode::UNAUTHORIZED, ErrorCode::InvalidToken),
+            Self::InvalidCredentials => (StatusCode::UNAUTHORIZED, ErrorCode::InvalidCredentials),
+            Self::TokenCreation => (StatusCode::UNAUTHORIZED, ErrorCode::InternalError),
+            Self::InvalidCsrfToken => (StatusCode::FORBIDDEN, ErrorCode::InvalidCsrfToken),
+            Self::PasswordHash(_) => (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError),
         };
-        ErrResponse::new(status, self.to_string()).into_response()
+        ErrResponse::new(status, code, self.to_string()).into_response()
     }
 }
 
@@ -184,25 +225,96 @@ pub enum AppError {
     Axum(#[from] axum::Error),
     #[error("Not Found: {0}")]
     NotFound(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Join requirement not met: {0}")]
+    JoinRequirement(#[from] JoinRequirementError),
+    #[error("Limit exceeded: {0}")]
+    LimitExceeded(#[from] LimitExceeded),
+    #[error("Slow mode: {0}")]
+    SlowMode(#[from] SlowModeError),
+    #[error("Upstream request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Database is currently unavailable")]
+    DatabaseUnavailable,
+    #[error("Storage is currently unavailable")]
+    StorageUnavailable,
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let status = match self {
-            Self::Multipart(_) => StatusCode::UNPROCESSABLE_ENTITY,
-            Self::Regex(_) | Self::ParseInt(_) | Self::JWT(_) | Self::JSON(_) => StatusCode::BAD_REQUEST,
+        let (status, code) = match self {
+            Self::Multipart(_) => (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::BadRequest),
+            Self::Regex(_) | Self::ParseInt(_) | Self::JSON(_) => (StatusCode::BAD_REQUEST, ErrorCode::BadRequest),
+            Self::JWT(_) => (StatusCode::BAD_REQUEST, ErrorCode::InvalidToken),
             Self::Build(e) => return e.into_response(),
-            Self::Axum(_) | Self::Database(_) | Self::S3(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::JoinRequirement(e) => return e.into_response(),
+            Self::LimitExceeded(e) => return e.into_response(),
+            Self::SlowMode(e) => return e.into_response(),
+            Self::Database(sqlx::Error::PoolTimedOut) | Self::DatabaseUnavailable | Self::StorageUnavailable => {
+                (StatusCode::SERVICE_UNAVAILABLE, ErrorCode::ServiceUnavailable)
+            }
+            Self::Axum(_) | Self::Database(_) | Self::S3(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
+            }
             Self::Auth(e) => return e.into_response(),
-            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::NotFound),
+            Self::Forbidden(_) => (StatusCode::FORBIDDEN, ErrorCode::Forbidden),
+            Self::Conflict(_) => (StatusCode::CONFLICT, ErrorCode::Conflict),
+            Self::Http(_) => (StatusCode::BAD_GATEWAY, ErrorCode::UpstreamError),
         };
         if status == StatusCode::INTERNAL_SERVER_ERROR {
             tracing::error!(error = %self);
         }
-        ErrResponse::new(status, self.to_string()).into_response()
+        ErrResponse::new(status, code, self.to_string()).into_response()
     }
 }
 
+impl From<AppError> for tonic::Status {
+    fn from(error: AppError) -> Self {
+        let message = error.to_string();
+        match error {
+            AppError::Multipart(_)
+            | AppError::Regex(_)
+            | AppError::ParseInt(_)
+            | AppError::JWT(_)
+            | AppError::JSON(_)
+            | AppError::Build(_) => Self::invalid_argument(message),
+            AppError::JoinRequirement(_) => Self::failed_precondition(message),
+            AppError::LimitExceeded(_) | AppError::SlowMode(_) => Self::resource_exhausted(message),
+            AppError::Database(sqlx::Error::PoolTimedOut)
+            | AppError::DatabaseUnavailable
+            | AppError::StorageUnavailable
+            | AppError::Http(_) => Self::unavailable(message),
+            AppError::Auth(_) => Self::unauthenticated(message),
+            AppError::NotFound(_) => Self::not_found(message),
+            AppError::Forbidden(_) => Self::permission_denied(message),
+            AppError::Conflict(_) => Self::already_exists(message),
+            AppError::Axum(_) | AppError::Database(_) | AppError::S3(_) => {
+                tracing::error!(error = %message);
+                Self::internal(message)
+            }
+        }
+    }
+}
+
+/// An error that prevents the server from starting.
+#[derive(Debug, Error)]
+pub enum StartupError {
+    #[error("Failed to initialize the database: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("{0} startup check(s) failed")]
+    ChecksFailed(usize),
+    #[error(
+        "Another live instance uses MACHINE_ID={machine_id} and PROCESS_ID={process_id}, snowflake IDs would collide"
+    )]
+    InstanceInUse { machine_id: i32, process_id: i32 },
+    #[error("SNOWFLAKE_EPOCH is {configured}, but existing snowflake IDs were generated with epoch {stored}")]
+    EpochMismatch { configured: i64, stored: i64 },
+}
+
 /// Hacky workaround for `SdkError` having a generic type parameter
 impl<E, R> From<SdkError<E, R>> for AppError
 where
@@ -270,18 +382,18 @@ impl<T: Into<AppError>> From<T> for RESTError {
 
 impl IntoResponse for RESTError {
     fn into_response(self) -> Response {
-        let status = match self {
+        let (status, code) = match self {
             Self::App(e) => return e.into_response(),
             Self::InternalServerError(ref message) => {
                 tracing::error!(error = %message);
-                StatusCode::INTERNAL_SERVER_ERROR
+                (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::InternalError)
             }
             Self::MissingField(_) | Self::MalformedField(_) | Self::DuplicateField(_) | Self::BadRequest(_) => {
-                StatusCode::BAD_REQUEST
+                (StatusCode::BAD_REQUEST, ErrorCode::BadRequest)
             }
-            Self::NotFound(_) => StatusCode::NOT_FOUND,
-            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotFound(_) => (StatusCode::NOT_FOUND, ErrorCode::NotFound),
+            Self::Forbidden(_) => (StatusCode::FORBIDDEN, ErrorCode::Forbidden),
         };
-        ErrResponse::new(status, self.to_string()).into_response()
+        ErrResponse::new(status, code, self.to_string()).into_response()
     }
 }
@@ -0,0 +1,227 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, Weak,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgListener, Executor, Postgres};
+use tokio::sync::mpsc;
+
+use super::{
+    errors::AppError,
+    gateway_event::{EventRouting, GatewayEvent},
+    guest_link::GuestAccess,
+    guild::Guild,
+    presence_privacy::PresencePrivacy,
+    snowflake::Snowflake,
+    state::ApplicationState,
+    user::{Activity, Presence, User},
+};
+
+/// The Postgres channel new messages are announced on.
+const CHANNEL: &str = "event_bus";
+/// How long messages are kept on the bus, in seconds. Messages are only read right after they are published.
+const RETENTION: i64 = 60;
+
+/// A change to the gateway state that every gateway process needs to apply.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BusMessage {
+    /// Dispatch a serialized event to all users that should receive it.
+    Dispatch {
+        routing: EventRouting,
+        /// The ID of the event, the same on every process. Messages of older processes may not carry one.
+        #[serde(default)]
+        id: Option<Snowflake<GatewayEvent>>,
+        event: String,
+        payload: String,
+    },
+    /// Send a serialized event to a single user.
+    SendTo {
+        user_id: Snowflake<User>,
+        /// The guild the event belongs to, which determines the shard it is sent on.
+        guild_id: Option<Snowflake<Guild>>,
+        /// The ID of the event, the same on every process. Messages of older processes may not carry one.
+        #[serde(default)]
+        id: Option<Snowflake<GatewayEvent>>,
+        event: String,
+        payload: String,
+    },
+    /// Register a new guild member to an existing connection.
+    AddMember {
+        user_id: Snowflake<User>,
+        guild_id: Snowflake<Guild>,
+        /// The access of the member if they joined as a guest.
+        #[serde(default)]
+        guest: Option<GuestAccess>,
+    },
+    /// Remove a guild member from an existing connection.
+    RemoveMember {
+        user_id: Snowflake<User>,
+        guild_id: Snowflake<Guild>,
+    },
+    /// Set or clear the activity of a connected user.
+    SetActivity {
+        user_id: Snowflake<User>,
+        activity: Option<Activity>,
+    },
+    /// Set the presence a connected user chose.
+    SetPresence {
+        user_id: Snowflake<User>,
+        presence: Presence,
+    },
+    /// Set the presence privacy settings of a connected user.
+    SetPresencePrivacy {
+        user_id: Snowflake<User>,
+        privacy: PresencePrivacy,
+    },
+    /// Enable or disable data saver mode for the sessions of a connected user.
+    SetDataSaver { user_id: Snowflake<User>, enabled: bool },
+}
+
+/// Forwards gateway state changes between gateway processes when the gateway is sharded.
+///
+/// Messages are stored in the `event_bus` table and announced with `NOTIFY`.
+/// Every process, including the one publishing, listens for announcements and applies the messages to its own connections.
+/// Messages published by the same process are applied in the order they were published.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: mpsc::UnboundedSender<BusMessage>,
+    /// The messages waiting to be published, taken by the publishing task.
+    queue: Arc<Mutex<Option<mpsc::UnboundedReceiver<BusMessage>>>>,
+    /// The amount of messages in the queue.
+    queued: Arc<AtomicUsize>,
+    app: Weak<ApplicationState>,
+}
+
+impl EventBus {
+    /// Create a new event bus. Nothing is published until the publishing task is started.
+    pub fn new() -> Self {
+        let (sender, queue) = mpsc::unbounded_channel();
+        Self {
+            sender,
+            queue: Arc::new(Mutex::new(Some(queue))),
+            queued: Arc::new(AtomicUsize::new(0)),
+            app: Weak::new(),
+        }
+    }
+
+    pub fn bind_to(&mut self, app: Weak<ApplicationState>) {
+        self.app = app;
+    }
+
+    pub fn app(&self) -> Arc<ApplicationState> {
+        self.app.upgrade().expect("Application state has been dropped.")
+    }
+
+    /// Queue a message to be published to all gateway processes.
+    pub fn publish(&self, message: BusMessage) {
+        if self.sender.send(message).is_err() {
+            tracing::warn!("Event bus is closed, dropping message");
+            return;
+        }
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Mark a message taken from the queue as published.
+    pub fn mark_published(&self) {
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// The amount of messages waiting to be published.
+    pub fn queue_depth(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    /// Take the queue of messages waiting to be published. Returns `None` if it was already taken.
+    pub fn take_queue(&self) -> Option<mpsc::UnboundedReceiver<BusMessage>> {
+        self.queue.lock().expect("Event bus queue lock poisoned").take()
+    }
+
+    /// Publish a message to all gateway processes.
+    /// If called in a transaction, the message is only announced once the transaction is committed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `conn` - The connection or transaction to publish the message in.
+    /// * `message` - The message to publish.
+    /// * `now` - The time the message is published at.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn send(
+        conn: impl Executor<'_, Database = Postgres>,
+        message: &BusMessage,
+        now: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "WITH inserted AS (
+                INSERT INTO event_bus (message, created_at) VALUES ($1, $2) RETURNING id
+            )
+            SELECT pg_notify('event_bus', id::TEXT) FROM inserted",
+            serde_json::to_string(message).expect("Failed to serialize event bus message"),
+            now.timestamp(),
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Connect a new listener for messages announced on the bus.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the listener could not connect.
+    pub async fn listen(&self) -> Result<PgListener, sqlx::Error> {
+        let mut listener = PgListener::connect_with(self.app().db.pool()).await?;
+        listener.listen(CHANNEL).await?;
+        Ok(listener)
+    }
+
+    /// Apply an announced message to the connections of this process.
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the announced message.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the message was already pruned.
+    /// * [`AppError::JSON`] - If the message could not be deserialized.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn receive(&self, id: i64) -> Result<(), AppError> {
+        let app = self.app();
+        let message = sqlx::query_scalar!("SELECT message FROM event_bus WHERE id = $1", id)
+            .fetch_optional(app.db.executor())
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Event bus message {id} does not exist.")))?;
+
+        app.gateway.apply(serde_json::from_str(&message)?);
+        Ok(())
+    }
+
+    /// Remove messages that are older than the retention period.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn prune(&self) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM event_bus WHERE created_at < $1",
+            self.app().clock.timestamp() - RETENTION
+        )
+        .execute(self.app().db.executor())
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,841 @@
+use std::{
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use chrono::DateTime;
+use futures::StreamExt;
+use reqwest::{header, Client, Url};
+use roxmltree::{Document, Node};
+use serde::Serialize;
+
+use super::{
+    channel::Channel,
+    content,
+    embed::{self, Embed},
+    errors::{AppError, BuildError, RESTError},
+    media_proxy::{ensure_public_host, ensure_public_url, public_client, PublicResolver},
+    message::Message,
+    requests::{CreateFeed, UpdateFeed},
+    snowflake::Snowflake,
+    state::{ApplicationState, Config},
+};
+
+/// The maximum amount of feeds a channel may subscribe to.
+pub const MAX_FEEDS_PER_CHANNEL: usize = 5;
+/// The shortest interval feeds may be polled at, in seconds.
+pub const MIN_POLL_INTERVAL: u32 = 5 * 60;
+/// The longest interval feeds may be polled at, in seconds. (1 day)
+pub const MAX_POLL_INTERVAL: u32 = 24 * 60 * 60;
+/// The interval feeds are polled at if none is given, in seconds.
+const DEFAULT_POLL_INTERVAL: u32 = 30 * 60;
+/// The maximum length of a feed URL.
+const MAX_URL_LEN: usize = 2048;
+/// The longest a failing feed waits before it is polled again, in seconds. (1 day)
+const MAX_BACKOFF: i64 = 24 * 60 * 60;
+/// The amount of consecutive failed polls after which a feed is disabled.
+const MAX_FAILURES: i32 = 10;
+/// The maximum amount of new entries posted per poll. Older new entries are skipped.
+const MAX_ENTRIES_PER_POLL: usize = 5;
+/// The maximum amount of entries of a feed that are considered, newest first.
+const MAX_SEEN_ENTRIES: usize = 200;
+/// The maximum size of a feed document, in bytes. (5 MiB)
+const MAX_FEED_SIZE: usize = 5 * 1024 * 1024;
+/// The maximum amount of feeds polled at once.
+const BATCH_SIZE: i64 = 20;
+/// The maximum amount of feeds fetched concurrently.
+const CONCURRENCY: usize = 4;
+/// How long a claimed feed is reserved for a poll, in seconds. Polls interrupted by a crash are retried after this period.
+const LEASE_DURATION: i64 = 5 * 60;
+/// The maximum length of a recorded poll error.
+const MAX_ERROR_LEN: usize = 500;
+
+/// Represents a channel feed record stored in the database.
+pub struct FeedRecord {
+    pub id: Snowflake<Feed>,
+    pub channel_id: Snowflake<Channel>,
+    pub url: String,
+    pub title: Option<String>,
+    pub poll_interval: i32,
+    pub enabled: bool,
+    pub failures: i32,
+    pub last_error: Option<String>,
+    pub last_polled_at: Option<i64>,
+    pub next_poll_at: i64,
+    pub seen_entries: Vec<String>,
+}
+
+/// An RSS or Atom feed a channel is subscribed to. New entries of the feed are posted to the channel.
+#[derive(Serialize, Debug, Clone)]
+pub struct Feed {
+    /// The ID of the feed.
+    id: Snowflake<Self>,
+    /// The channel entries are posted to.
+    channel_id: Snowflake<Channel>,
+    /// The URL of the feed document.
+    url: String,
+    /// The title of the feed, as of the last successful poll.
+    title: Option<String>,
+    /// How often the feed is polled, in seconds.
+    poll_interval: u32,
+    /// Whether the feed is currently polled.
+    enabled: bool,
+    /// The amount of consecutive failed polls.
+    failures: i32,
+    /// The reason the last poll failed, if it did.
+    last_error: Option<String>,
+    /// UNIX timestamp of the last successful poll, if any.
+    last_polled_at: Option<i64>,
+    /// UNIX timestamp of when the feed is polled next.
+    next_poll_at: i64,
+    /// The IDs of the entries in the feed as of the last successful poll.
+    #[serde(skip)]
+    seen_entries: Vec<String>,
+}
+
+impl Feed {
+    /// The ID of the feed.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The channel entries are posted to.
+    pub const fn channel_id(&self) -> Snowflake<Channel> {
+        self.channel_id
+    }
+
+    /// The URL of the feed document.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The title of the feed, as of the last successful poll.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// How often the feed is polled, in seconds.
+    pub const fn poll_interval(&self) -> u32 {
+        self.poll_interval
+    }
+
+    /// Whether the feed is currently polled.
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The amount of consecutive failed polls.
+    pub const fn failures(&self) -> i32 {
+        self.failures
+    }
+
+    /// The reason the last poll failed, if it did.
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    /// UNIX timestamp of the last successful poll, if any.
+    pub const fn last_polled_at(&self) -> Option<i64> {
+        self.last_polled_at
+    }
+
+    /// UNIX timestamp of when the feed is polled next.
+    pub const fn next_poll_at(&self) -> i64 {
+        self.next_poll_at
+    }
+
+    /// The IDs of the entries in the feed as of the last successful poll.
+    pub fn seen_entries(&self) -> &[String] {
+        &self.seen_entries
+    }
+
+    /// Build a feed from a database record.
+    pub fn from_record(record: FeedRecord) -> Self {
+        Self {
+            id: record.id,
+            channel_id: record.channel_id,
+            url: record.url,
+            title: record.title,
+            poll_interval: u32::try_from(record.poll_interval).unwrap_or(DEFAULT_POLL_INTERVAL),
+            enabled: record.enabled,
+            failures: record.failures,
+            last_error: record.last_error,
+            last_polled_at: record.last_polled_at,
+            next_poll_at: record.next_poll_at,
+            seen_entries: record.seen_entries,
+        }
+    }
+
+    /// Create a new feed from a creation payload. Assigns a new snowflake to the feed.
+    /// The feed is polled for the first time right away, entries already in the feed at that time are not posted.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration.
+    /// * `channel` - The channel entries are posted to.
+    /// * `payload` - The payload to create the feed from.
+    /// * `now` - The current time as a UNIX timestamp.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the payload is invalid.
+    pub fn from_payload(
+        config: &Config,
+        channel: impl Into<Snowflake<Channel>>,
+        payload: CreateFeed,
+        now: i64,
+    ) -> Result<Self, BuildError> {
+        let mut feed = Self {
+            id: Snowflake::gen_new(config),
+            channel_id: channel.into(),
+            url: payload.url,
+            title: None,
+            poll_interval: payload.poll_interval.unwrap_or(DEFAULT_POLL_INTERVAL),
+            enabled: payload.enabled.unwrap_or(true),
+            failures: 0,
+            last_error: None,
+            last_polled_at: None,
+            next_poll_at: now,
+            seen_entries: Vec::new(),
+        };
+        feed.validate()?;
+        Ok(feed)
+    }
+
+    /// Update the feed with the given payload.
+    ///
+    /// Changing the URL starts over as if the feed was new. Enabling a disabled feed clears its failures,
+    /// and it is polled right away.
+    ///
+    /// ## Arguments
+    ///
+    /// * `payload` - The payload to update the feed with.
+    /// * `now` - The current time as a UNIX timestamp.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the resulting feed is invalid.
+    pub fn update(&mut self, payload: UpdateFeed, now: i64) -> Result<(), BuildError> {
+        if let Some(url) = payload.url.map(|url| url.trim().to_string()) {
+            if url != self.url {
+                self.url = url;
+                self.title = None;
+                self.last_polled_at = None;
+                self.seen_entries.clear();
+                self.reset(now);
+            }
+        }
+        if let Some(poll_interval) = payload.poll_interval {
+            self.poll_interval = poll_interval;
+        }
+        if let Some(enabled) = payload.enabled {
+            if enabled && !self.enabled {
+                self.reset(now);
+            }
+            self.enabled = enabled;
+        }
+        self.validate()
+    }
+
+    /// Clear the failures of the feed and schedule it to be polled right away.
+    fn reset(&mut self, now: i64) {
+        self.failures = 0;
+        self.last_error = None;
+        self.next_poll_at = now;
+    }
+
+    /// Ensure that the feed URL points to a publicly routable host.
+    ///
+    /// ## Errors
+    ///
+    /// * [`RESTError::BadRequest`] - If the URL is malformed or its host cannot be resolved.
+    /// * [`RESTError::Forbidden`] - If the URL points to a non-public address.
+    pub async fn ensure_public_url(&self) -> Result<(), RESTError> {
+        let url = Url::parse(&self.url).map_err(|_| RESTError::MalformedField("url".into()))?;
+        ensure_public_host(&url).await
+    }
+
+    /// Validate the feed.
+    fn validate(&mut self) -> Result<(), BuildError> {
+        self.url = self.url.trim().to_string();
+
+        if self.url.len() > MAX_URL_LEN {
+            return Err(BuildError::ValidationError(format!(
+                "Feed URL must be at most {MAX_URL_LEN} characters long"
+            )));
+        }
+
+        match Url::parse(&self.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => {
+                return Err(BuildError::ValidationError(
+                    "Feed URL must be a valid HTTP(S) URL".into(),
+                ))
+            }
+        }
+
+        if !(MIN_POLL_INTERVAL..=MAX_POLL_INTERVAL).contains(&self.poll_interval) {
+            return Err(BuildError::ValidationError(format!(
+                "Feed poll interval must be between {MIN_POLL_INTERVAL} and {MAX_POLL_INTERVAL} seconds"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// An entry of a feed, such as a blog post.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEntry {
+    /// A unique identifier of the entry within the feed.
+    pub id: String,
+    /// The title of the entry.
+    pub title: Option<String>,
+    /// The link to the entry.
+    pub url: Option<String>,
+    /// A summary of the entry, as plain text.
+    pub summary: Option<String>,
+    /// The name of the author of the entry.
+    pub author: Option<String>,
+    /// UNIX timestamp of when the entry was published.
+    pub published: Option<i64>,
+}
+
+impl FeedEntry {
+    /// Turn the entry into an embed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `provider` - The title of the feed the entry is from.
+    fn to_embed(&self, provider: Option<&str>) -> Result<Embed, BuildError> {
+        let mut builder = Embed::builder();
+        if let Some(title) = self
+            .title
+            .as_deref()
+            .and_then(|t| embed::truncate(t, embed::MAX_TITLE_LENGTH))
+        {
+            builder.title(title);
+        }
+        if let Some(summary) = self
+            .summary
+            .as_deref()
+            .and_then(|s| embed::truncate(s, embed::MAX_DESCRIPTION_LENGTH))
+        {
+            builder.description(summary);
+        }
+        if let Some(url) = &self.url {
+            builder.url(url.clone());
+        }
+        if let Some(author) = self
+            .author
+            .as_deref()
+            .and_then(|a| embed::truncate(a, embed::MAX_NAME_LENGTH))
+        {
+            builder.author(author);
+        }
+        if let Some(provider) = provider.and_then(|p| embed::truncate(p, embed::MAX_NAME_LENGTH)) {
+            builder.provider(provider);
+        }
+        if let Some(published) = self.published {
+            builder.timestamp(published);
+        }
+        builder.build()
+    }
+}
+
+/// A parsed feed document.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParsedFeed {
+    /// The title of the feed.
+    pub title: Option<String>,
+    /// The entries of the feed, in document order.
+    pub entries: Vec<FeedEntry>,
+}
+
+impl ParsedFeed {
+    /// Parse an RSS 2.0, RSS 1.0 or Atom document. Entries without an identifier, link or title are skipped.
+    ///
+    /// ## Errors
+    ///
+    /// A description of the problem if the document is not well-formed XML or not a known feed format.
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        let document = Document::parse(xml).map_err(|e| format!("Feed is not valid XML: {e}"))?;
+        let root = document.root_element();
+
+        match root.tag_name().name() {
+            "rss" => {
+                let channel = child(root, "channel").ok_or("RSS feed has no channel")?;
+                Ok(Self {
+                    title: child_text(channel, "title"),
+                    entries: children(channel, "item").filter_map(parse_rss_item).collect(),
+                })
+            }
+            // RSS 1.0, items are siblings of the channel
+            "RDF" => Ok(Self {
+                title: child(root, "channel").and_then(|channel| child_text(channel, "title")),
+                entries: children(root, "item").filter_map(parse_rss_item).collect(),
+            }),
+            "feed" => Ok(Self {
+                title: child_text(root, "title"),
+                entries: children(root, "entry").filter_map(parse_atom_entry).collect(),
+            }),
+            _ => Err("Document is not an RSS or Atom feed".into()),
+        }
+    }
+
+    /// The entries that were not in the feed when it was last polled, oldest first.
+    /// At most [`MAX_ENTRIES_PER_POLL`] of the newest entries are returned.
+    ///
+    /// ## Arguments
+    ///
+    /// * `seen` - The IDs of the entries in the feed as of the last poll.
+    pub fn new_entries(&self, seen: &[String]) -> Vec<&FeedEntry> {
+        let mut entries: Vec<&FeedEntry> = self
+            .entries
+            .iter()
+            .take(MAX_SEEN_ENTRIES)
+            .filter(|entry| !seen.contains(&entry.id))
+            .collect();
+
+        // Feeds usually list their newest entries first, but only the publication date is reliable
+        entries.reverse();
+        if entries.iter().all(|entry| entry.published.is_some()) {
+            entries.sort_by_key(|entry| entry.published);
+        }
+
+        let skip = entries.len().saturating_sub(MAX_ENTRIES_PER_POLL);
+        entries.split_off(skip)
+    }
+
+    /// The IDs of the entries in the feed, to be stored as the seen entries of the feed.
+    pub fn entry_ids(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .take(MAX_SEEN_ENTRIES)
+            .map(|entry| entry.id.clone())
+            .collect()
+    }
+}
+
+/// Parse an `<item>` of an RSS feed.
+fn parse_rss_item(item: Node) -> Option<FeedEntry> {
+    let title = child_text(item, "title");
+    let url = child_text(item, "link");
+    let id = child_text(item, "guid")
+        .or_else(|| url.clone())
+        .or_else(|| title.clone())?;
+    let published = child_text(item, "pubDate")
+        .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+        .or_else(|| child_text(item, "date").and_then(|date| DateTime::parse_from_rfc3339(&date).ok()))
+        .map(|date| date.timestamp());
+
+    Some(FeedEntry {
+        id,
+        title,
+        url,
+        summary: child_text(item, "description").map(|html| content::sanitize(&html)),
+        author: child_text(item, "author").or_else(|| child_text(item, "creator")),
+        published,
+    })
+}
+
+/// Parse an `<entry>` of an Atom feed.
+fn parse_atom_entry(entry: Node) -> Option<FeedEntry> {
+    let title = child_text(entry, "title");
+    let url = children(entry, "link")
+        .find(|link| link.attribute("rel").is_none_or(|rel| rel == "alternate"))
+        .and_then(|link| link.attribute("href"))
+        .map(str::to_string);
+    let id = child_text(entry, "id")
+        .or_else(|| url.clone())
+        .or_else(|| title.clone())?;
+    let published = child_text(entry, "published")
+        .or_else(|| child_text(entry, "updated"))
+        .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+        .map(|date| date.timestamp());
+
+    Some(FeedEntry {
+        id,
+        title,
+        url,
+        summary: child_text(entry, "summary")
+            .or_else(|| child_text(entry, "content"))
+            .map(|html| content::sanitize(&html)),
+        author: child(entry, "author").and_then(|author| child_text(author, "name")),
+        published,
+    })
+}
+
+/// The child elements of a node with the given local name, ignoring namespaces.
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// The first child element of a node with the given local name, ignoring namespaces.
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// The trimmed text of the first child element with the given local name, if it is not empty.
+fn child_text(node: Node, name: &str) -> Option<String> {
+    let text: String = child(node, name)?
+        .descendants()
+        .filter_map(|node| node.text().filter(|_| node.is_text()))
+        .collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Polls feeds in the background and posts their new entries to the subscribed channels.
+///
+/// Feeds that fail to be polled are retried with exponential backoff,
+/// and are disabled after [`MAX_FAILURES`] consecutive failures.
+#[derive(Debug, Clone)]
+pub struct FeedPoller {
+    client: Client,
+    app: Weak<ApplicationState>,
+}
+
+impl FeedPoller {
+    /// Create a new feed poller.
+    ///
+    /// Note: The poller only connects to publicly routable addresses, and does not follow redirects
+    /// to avoid being pointed at internal hosts.
+    pub fn new() -> Self {
+        Self::with_resolver(PublicResolver::new())
+    }
+
+    /// Create a new feed poller connecting to the addresses returned by the given resolver.
+    pub fn with_resolver(resolver: PublicResolver) -> Self {
+        let client = public_client(resolver)
+            .timeout(Duration::from_secs(15))
+            .user_agent(concat!("chat-feeds/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build feed HTTP client");
+
+        Self {
+            client,
+            app: Weak::new(),
+        }
+    }
+
+    pub fn bind_to(&mut self, app: Weak<ApplicationState>) {
+        self.app = app;
+    }
+
+    pub fn app(&self) -> Arc<ApplicationState> {
+        self.app.upgrade().expect("Application state has been dropped.")
+    }
+
+    /// Poll all enabled feeds that are due. Feeds of archived channels are paused until the channel is unarchived.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of feeds polled.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn poll_due(&self) -> Result<usize, sqlx::Error> {
+        let app = self.app();
+        let now = app.clock.timestamp();
+
+        // Claim due feeds, so they are not polled twice if another poll is still running
+        let claimed = sqlx::query_as!(
+            FeedRecord,
+            "WITH due AS (
+                SELECT id FROM channel_feeds
+                WHERE enabled AND next_poll_at <= $1
+                AND NOT EXISTS (SELECT 1 FROM channels WHERE channels.id = channel_feeds.channel_id AND channels.archived)
+                ORDER BY next_poll_at LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            UPDATE channel_feeds AS f
+            SET next_poll_at = $3
+            FROM due
+            WHERE f.id = due.id
+            RETURNING f.*",
+            now,
+            BATCH_SIZE,
+            now + LEASE_DURATION,
+        )
+        .fetch_all(app.db.executor())
+        .await?;
+
+        let count = claimed.len();
+
+        futures::stream::iter(claimed.into_iter().map(Feed::from_record))
+            .for_each_concurrent(CONCURRENCY, |feed| async move {
+                let id = feed.id();
+                if let Err(e) = self.poll(feed).await {
+                    tracing::error!(feed = %id, error = %e, "Failed to record feed poll");
+                }
+            })
+            .await;
+
+        Ok(count)
+    }
+
+    /// Fetch a feed and post its new entries, or record the failure.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError`] - If the outcome of the poll could not be recorded.
+    pub async fn poll(&self, feed: Feed) -> Result<(), AppError> {
+        let parsed = match self.fetch(feed.url()).await {
+            Ok(xml) => ParsedFeed::parse(&xml),
+            Err(e) => Err(e),
+        };
+
+        match parsed {
+            Ok(parsed) => self.ingest(&feed, &parsed).await,
+            Err(error) => Ok(self.record_failure(&feed, error).await?),
+        }
+    }
+
+    /// Post the new entries of a feed to its channel, and record the successful poll.
+    /// If the feed was never polled before, no entries are posted.
+    ///
+    /// ## Arguments
+    ///
+    /// * `feed` - The feed that was polled.
+    /// * `parsed` - The feed document as fetched.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::MessageCreate`] - For every posted entry, to all members who can view the channel
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    ///
+    /// [`GatewayEvent::MessageCreate`]: crate::models::gateway_event::GatewayEvent::MessageCreate
+    pub async fn ingest(&self, feed: &Feed, parsed: &ParsedFeed) -> Result<(), AppError> {
+        let app = self.app();
+
+        if feed.last_polled_at().is_some() {
+            let title = parsed.title.as_deref().or_else(|| feed.title());
+            for entry in parsed.new_entries(feed.seen_entries()) {
+                let message = Message::builder()
+                    .id(Snowflake::gen_new(&app.config))
+                    .channel_id(feed.channel_id())
+                    .embeds(vec![entry.to_embed(title)?])
+                    .build()?;
+                app.ops().update_message(&message).await?;
+            }
+        }
+
+        let now = app.clock.timestamp();
+        sqlx::query!(
+            "UPDATE channel_feeds
+            SET title = $2, seen_entries = $3, failures = 0, last_error = NULL, last_polled_at = $4, next_poll_at = $5
+            WHERE id = $1",
+            feed.id() as Snowflake<Feed>,
+            parsed.title.as_deref().or_else(|| feed.title()),
+            &parsed.entry_ids(),
+            now,
+            now + i64::from(feed.poll_interval()),
+        )
+        .execute(app.db.executor())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed poll. The feed is polled again after a delay that doubles with every consecutive failure.
+    async fn record_failure(&self, feed: &Feed, mut error: String) -> Result<(), sqlx::Error> {
+        tracing::debug!(feed = %feed.id(), error, "Feed poll failed");
+        error.truncate(error.floor_char_boundary(MAX_ERROR_LEN));
+
+        let app = self.app();
+        let failures = feed.failures() + 1;
+        let delay = (i64::from(feed.poll_interval()) << failures.min(16)).min(MAX_BACKOFF);
+
+        sqlx::query!(
+            "UPDATE channel_feeds
+            SET failures = $2, last_error = $3, next_poll_at = $4, enabled = enabled AND $5
+            WHERE id = $1",
+            feed.id() as Snowflake<Feed>,
+            failures,
+            error,
+            app.clock.timestamp() + delay,
+            failures < MAX_FAILURES,
+        )
+        .execute(app.db.executor())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch a feed document.
+    ///
+    /// ## Returns
+    ///
+    /// The document, or the reason it could not be fetched.
+    async fn fetch(&self, url: &str) -> Result<String, String> {
+        let url = Url::parse(url).map_err(|_| "Malformed feed URL".to_string())?;
+
+        // The addresses of host names are checked by the resolver of the client on every poll,
+        // as DNS records may have changed since the feed was created
+        ensure_public_url(&url).map_err(|e| e.to_string())?;
+
+        let response = self
+            .client
+            .get(url)
+            .header(
+                header::ACCEPT,
+                "application/rss+xml, application/atom+xml, application/xml;q=0.9, text/xml;q=0.9, */*;q=0.8",
+            )
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("Feed responded with {status}"));
+        }
+
+        let mut body = Vec::new();
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| e.to_string())?;
+            if body.len() + chunk.len() > MAX_FEED_SIZE {
+                return Err(format!("Feed is larger than {MAX_FEED_SIZE} bytes"));
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        String::from_utf8(body).map_err(|_| "Feed is not valid UTF-8".to_string())
+    }
+}
+
+impl Default for FeedPoller {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::{FeedPoller, ParsedFeed, MAX_ENTRIES_PER_POLL};
+    use crate::models::media_proxy::testing::RebindingHost;
+
+    const RSS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <channel>
+    <title>Example blog</title>
+    <item>
+      <title>Second post</title>
+      <link>https://example.com/2</link>
+      <guid>post-2</guid>
+      <description>&lt;p&gt;Hello &lt;b&gt;again&lt;/b&gt;&lt;/p&gt;</description>
+      <dc:creator>Alice</dc:creator>
+      <pubDate>Tue, 02 Jul 2024 12:00:00 +0000</pubDate>
+    </item>
+    <item>
+      <title>First post</title>
+      <link>https://example.com/1</link>
+      <pubDate>Mon, 01 Jul 2024 12:00:00 +0000</pubDate>
+    </item>
+  </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Example releases</title>
+  <entry>
+    <title>v1.0</title>
+    <id>urn:release:1</id>
+    <link rel="self" href="https://example.com/self"/>
+    <link href="https://example.com/releases/1"/>
+    <updated>2024-07-01T12:00:00Z</updated>
+    <author><name>Bob</name></author>
+    <content type="html"><![CDATA[<p>First release</p>]]></content>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn test_parse_rss() {
+        let feed = ParsedFeed::parse(RSS).expect("RSS should parse");
+        assert_eq!(feed.title.as_deref(), Some("Example blog"));
+        assert_eq!(feed.entries.len(), 2);
+
+        let entry = &feed.entries[0];
+        assert_eq!(entry.id, "post-2");
+        assert_eq!(entry.summary.as_deref(), Some("Hello again"));
+        assert_eq!(entry.author.as_deref(), Some("Alice"));
+        assert_eq!(entry.published, Some(1_719_921_600));
+        // Items without a guid are identified by their link
+        assert_eq!(feed.entries[1].id, "https://example.com/1");
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        let feed = ParsedFeed::parse(ATOM).expect("Atom should parse");
+        assert_eq!(feed.title.as_deref(), Some("Example releases"));
+
+        let entry = &feed.entries[0];
+        assert_eq!(entry.id, "urn:release:1");
+        assert_eq!(entry.url.as_deref(), Some("https://example.com/releases/1"));
+        assert_eq!(entry.summary.as_deref(), Some("First release"));
+        assert_eq!(entry.author.as_deref(), Some("Bob"));
+        assert_eq!(entry.published, Some(1_719_835_200));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(ParsedFeed::parse("not xml").is_err());
+        assert!(ParsedFeed::parse("<html><body/></html>").is_err());
+        // Entity expansion is not supported
+        assert!(ParsedFeed::parse(r#"<!DOCTYPE rss [<!ENTITY a "a">]><rss><channel/></rss>"#).is_err());
+    }
+
+    #[test]
+    fn test_new_entries() {
+        let feed = ParsedFeed::parse(RSS).expect("RSS should parse");
+        let new = feed.new_entries(&["https://example.com/1".to_string()]);
+        assert_eq!(new.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(), vec!["post-2"]);
+
+        // Oldest first
+        let new = feed.new_entries(&[]);
+        assert_eq!(
+            new.iter().map(|e| e.id.as_str()).collect::<Vec<_>>(),
+            vec!["https://example.com/1", "post-2"]
+        );
+        assert!(feed.new_entries(&feed.entry_ids()).is_empty());
+
+        let items = (0..10)
+            .map(|i| format!("<item><guid>{i}</guid></item>"))
+            .collect::<Vec<_>>()
+            .concat();
+        let feed = ParsedFeed::parse(&format!("<rss><channel>{items}</channel></rss>")).expect("RSS should parse");
+        let new = feed.new_entries(&[]);
+        assert_eq!(new.len(), MAX_ENTRIES_PER_POLL);
+        // Only the newest entries are posted, which are listed first
+        assert_eq!(new.last().map(|e| e.id.as_str()), Some("0"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_connects_to_checked_address() {
+        let host = RebindingHost::serve(
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/rss+xml\r\nContent-Length: 21\r\nConnection: close\r\n\r\n<rss><channel/></rss>",
+        )
+        .await;
+        let poller = FeedPoller::with_resolver(host.resolver);
+        let url = format!("http://feeds.example:{}/rss", host.port);
+
+        // Feeds are fetched from the address that was checked, and only from it
+        let document = poller.fetch(&url).await.expect("Feed should be fetched");
+        assert!(ParsedFeed::parse(&document).is_ok());
+        assert_eq!(host.checked.load(Ordering::SeqCst), 1);
+
+        // Once the host resolves to the internal address, no connection is made at all
+        assert!(poller.fetch(&url).await.is_err());
+        assert_eq!(host.internal.load(Ordering::SeqCst), 0);
+        assert_eq!(host.lookups.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,125 @@
+use std::net::IpAddr;
+
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+use sha2::Sha256;
+
+use super::{guild::Guild, media_proxy::is_public_ip, snowflake::Snowflake, user::User};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The maximum length of a device identifier supplied by a client, in characters.
+pub const MAX_DEVICE_ID_LENGTH: usize = 256;
+
+/// Hash the identifiers a user registered with, so they can be matched against those of other users
+/// without storing the identifiers themselves.
+///
+/// Addresses that are not publicly routable are skipped, as they usually belong to a reverse proxy
+/// and would match every user registering through it.
+///
+/// ## Arguments
+///
+/// * `salt` - The salt to hash the identifiers with, see [`Config::fingerprint_salt`](super::state::Config::fingerprint_salt)
+/// * `device_id` - The device identifier supplied by the client, if any
+/// * `ip` - The address the user registered from, if known
+pub fn fingerprints(salt: &Secret<String>, device_id: Option<&str>, ip: Option<IpAddr>) -> Vec<String> {
+    let device = device_id
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| hash(salt, "device", id));
+    let address = ip.filter(|ip| is_public_ip(*ip)).map(|ip| {
+        // IPv4 clients may connect through IPv6 sockets
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            IpAddr::V4(_) => ip,
+        };
+        hash(salt, "ip", &ip.to_string())
+    });
+
+    device.into_iter().chain(address).collect()
+}
+
+/// Hash an identifier of the given kind, so identifiers of different kinds never match.
+fn hash(salt: &Secret<String>, kind: &str, value: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(salt.expose_secret().as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(kind.as_bytes());
+    mac.update(b":");
+    mac.update(value.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Represents an evasion flag record stored in the database.
+#[derive(Clone, Copy)]
+pub struct EvasionFlagRecord {
+    pub guild_id: Snowflake<Guild>,
+    pub user_id: Snowflake<User>,
+    pub banned_user_id: Snowflake<User>,
+    pub flagged_at: i64,
+}
+
+/// A member who shares a fingerprint with a user banned from the guild,
+/// flagged for review by the owner of the guild when they joined.
+#[derive(Serialize, Debug, Clone)]
+pub struct EvasionFlag {
+    /// The guild the member joined.
+    pub guild_id: Snowflake<Guild>,
+    /// The flagged member.
+    pub user_id: Snowflake<User>,
+    /// The banned user the member shares a fingerprint with.
+    pub banned_user_id: Snowflake<User>,
+    /// UNIX timestamp of when the member was flagged.
+    pub flagged_at: i64,
+}
+
+impl EvasionFlag {
+    /// Build an evasion flag directly from a database record.
+    pub const fn from_record(record: EvasionFlagRecord) -> Self {
+        Self {
+            guild_id: record.guild_id,
+            user_id: record.user_id,
+            banned_user_id: record.banned_user_id,
+            flagged_at: record.flagged_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use secrecy::Secret;
+
+    use super::fingerprints;
+
+    #[test]
+    fn test_fingerprints() {
+        let salt = Secret::new("salt".to_string());
+        let ip = IpAddr::from([81, 2, 69, 160]);
+
+        let first = fingerprints(&salt, Some("device"), Some(ip));
+        assert_eq!(first.len(), 2);
+        assert_eq!(first, fingerprints(&salt, Some(" device "), Some(ip)));
+        assert_ne!(
+            first,
+            fingerprints(&Secret::new("pepper".to_string()), Some("device"), Some(ip))
+        );
+
+        // Identifiers of different kinds never match
+        assert_ne!(
+            fingerprints(&salt, Some("81.2.69.160"), None),
+            fingerprints(&salt, None, Some(ip))
+        );
+
+        // IPv4-mapped addresses are hashed like the IPv4 address
+        let mapped = IpAddr::V6(Ipv4Addr::new(81, 2, 69, 160).to_ipv6_mapped());
+        assert_eq!(
+            fingerprints(&salt, None, Some(ip)),
+            fingerprints(&salt, None, Some(mapped))
+        );
+
+        // Addresses of reverse proxies would match everyone
+        let local = IpAddr::from([127, 0, 0, 1]);
+        assert!(fingerprints(&salt, Some(""), Some(local)).is_empty());
+    }
+}
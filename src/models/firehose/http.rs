@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use reqwest::{header, Client};
+use secrecy::{ExposeSecret, Secret};
+use serde_json::{json, Value};
+
+use crate::models::errors::AppError;
+
+use super::FirehoseSink;
+
+/// Sends batches of events to an HTTP endpoint, such as the ingestion service of an analytics pipeline.
+///
+/// Every batch is sent as a single `POST` request with a JSON body of the form `{"events": [...]}`.
+#[derive(Debug)]
+pub struct HttpSink {
+    client: Client,
+    url: String,
+    secret: Option<Secret<String>>,
+}
+
+impl HttpSink {
+    /// Create a new HTTP sink.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - The URL to send batches to.
+    /// * `secret` - Sent as a bearer token with every batch, if set.
+    pub fn new(url: &str, secret: Option<Secret<String>>) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(concat!("chat-firehose/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build firehose HTTP client");
+
+        Self {
+            client,
+            url: url.to_string(),
+            secret,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl FirehoseSink for HttpSink {
+    async fn send(&self, events: &[Value]) -> Result<(), AppError> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(json!({ "events": events }).to_string());
+        if let Some(secret) = &self.secret {
+            request = request.bearer_auth(secret.expose_secret());
+        }
+
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
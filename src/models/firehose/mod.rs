@@ -0,0 +1,304 @@
+mod http;
+
+use std::{
+    fmt::Debug,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::sync::mpsc;
+
+pub use self::http::HttpSink;
+
+use super::{
+    errors::AppError,
+    gateway_event::{EventRouting, GatewayEvent},
+    metrics,
+    state::Config,
+};
+
+/// Fields that may contain personal information, filtered out of events according to [`PiiFilter`].
+const PII_FIELDS: &[&str] = &[
+    "content",
+    "username",
+    "display_name",
+    "avatar_hash",
+    "filename",
+    "nonce",
+    "details",
+    "activity",
+];
+
+/// How personal information is removed from events before they are sent to the firehose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PiiFilter {
+    /// Events are sent as they were dispatched.
+    None,
+    /// Personal information is replaced with `null`.
+    #[default]
+    Redact,
+    /// Personal information is replaced with a keyed hash, so equal values can still be counted.
+    /// Values that are not strings are replaced with `null`.
+    Hash,
+}
+
+impl FromStr for PiiFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "redact" => Ok(Self::Redact),
+            "hash" => Ok(Self::Hash),
+            _ => Err(format!("Unknown PII filter: {s}")),
+        }
+    }
+}
+
+/// An event as it was dispatched, either still in memory or already serialized.
+#[derive(Debug, Clone)]
+pub enum FirehosePayload {
+    Event(Arc<GatewayEvent>),
+    /// A serialized [`GatewayEvent`], such as one read from the outbox.
+    Serialized(String),
+}
+
+/// A copy of a dispatched event, waiting to be sent to the firehose.
+#[derive(Debug, Clone)]
+pub struct FirehoseEntry {
+    routing: EventRouting,
+    /// UNIX timestamp of when the event was dispatched, in milliseconds.
+    timestamp: i64,
+    payload: FirehosePayload,
+}
+
+/// Receives batches of events from the firehose.
+#[async_trait::async_trait]
+pub trait FirehoseSink: Debug + Send + Sync {
+    /// Send a batch of events to the sink.
+    ///
+    /// ## Arguments
+    ///
+    /// * `events` - The events to send, oldest events first.
+    async fn send(&self, events: &[Value]) -> Result<(), AppError>;
+}
+
+/// Sends a copy of every dispatched gateway event to an analytics sink.
+///
+/// Dispatching an event only queues it. A background task filters the events,
+/// and sends them to the sink in batches. If the queue is full, because the sink is slow or unavailable,
+/// new events are dropped instead of slowing down the gateway.
+#[derive(Debug, Clone)]
+pub struct Firehose {
+    sink: Option<Arc<dyn FirehoseSink>>,
+    sender: Option<mpsc::Sender<FirehoseEntry>>,
+    /// The events waiting to be sent, taken by the forwarding task.
+    queue: Arc<Mutex<Option<mpsc::Receiver<FirehoseEntry>>>>,
+    filter: PiiFilter,
+    /// The key used to hash personal information, derived from the application secret.
+    hash_key: Arc<[u8]>,
+}
+
+impl Firehose {
+    /// Create a new firehose. It is disabled unless a sink is configured.
+    pub fn new(config: &Config) -> Self {
+        let sink: Option<Arc<dyn FirehoseSink>> = config
+            .firehose_url()
+            .map(|url| Arc::new(HttpSink::new(url, config.firehose_secret().cloned())) as Arc<dyn FirehoseSink>);
+
+        let (sender, queue) = match sink {
+            Some(_) => {
+                let (sender, queue) = mpsc::channel(config.firehose_buffer_size().max(1));
+                (Some(sender), Some(queue))
+            }
+            None => (None, None),
+        };
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(config.app_secret().expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(b"firehose-pii");
+
+        Self {
+            sink,
+            sender,
+            queue: Arc::new(Mutex::new(queue)),
+            filter: config.firehose_pii_filter(),
+            hash_key: mac.finalize().into_bytes().to_vec().into(),
+        }
+    }
+
+    /// Queue a copy of a dispatched event. If the firehose is disabled, this does nothing.
+    ///
+    /// ## Arguments
+    ///
+    /// * `routing` - Who the event was dispatched to.
+    /// * `now` - The time the event was dispatched at.
+    /// * `payload` - Creates the payload of the event, only called if the firehose is enabled.
+    pub fn publish(&self, routing: EventRouting, now: DateTime<Utc>, payload: impl FnOnce() -> FirehosePayload) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        let entry = FirehoseEntry {
+            routing,
+            timestamp: now.timestamp_millis(),
+            payload: payload(),
+        };
+        if sender.try_send(entry).is_err() {
+            metrics::FIREHOSE_DROPPED_EVENTS.inc();
+        }
+    }
+
+    /// The amount of events waiting to be sent.
+    pub fn queue_depth(&self) -> usize {
+        self.sender
+            .as_ref()
+            .map_or(0, |sender| sender.max_capacity() - sender.capacity())
+    }
+
+    /// Take the queue of events waiting to be sent. Returns `None` if the firehose is disabled,
+    /// or the queue was already taken.
+    pub fn take_queue(&self) -> Option<mpsc::Receiver<FirehoseEntry>> {
+        self.queue.lock().expect("Firehose queue lock poisoned").take()
+    }
+
+    /// Filter and serialize a batch of events, in the format they are sent to the sink.
+    pub fn records(&self, entries: &[FirehoseEntry]) -> Vec<Value> {
+        entries.iter().filter_map(|entry| self.record(entry)).collect()
+    }
+
+    /// Send a batch of records to the sink.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Http`] - If the request to the sink fails.
+    pub async fn send(&self, records: &[Value]) -> Result<(), AppError> {
+        match &self.sink {
+            Some(sink) if !records.is_empty() => sink.send(records).await,
+            _ => Ok(()),
+        }
+    }
+
+    fn record(&self, entry: &FirehoseEntry) -> Option<Value> {
+        let event = match &entry.payload {
+            FirehosePayload::Event(event) => serde_json::to_value(&**event),
+            FirehosePayload::Serialized(payload) => serde_json::from_str(payload),
+        };
+        let mut event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to serialize firehose event");
+                return None;
+            }
+        };
+
+        let mut data = event.get_mut("data").map_or(Value::Null, Value::take);
+        self.filter_value(&mut data);
+
+        Some(json!({
+            "event": event.get("event").cloned().unwrap_or(Value::Null),
+            "guild_id": entry.routing.guild_id,
+            "channel_id": entry.routing.channel_id,
+            "timestamp": entry.timestamp,
+            "data": data,
+        }))
+    }
+
+    /// Remove personal information from a value, according to the configured [`PiiFilter`].
+    fn filter_value(&self, value: &mut Value) {
+        if self.filter == PiiFilter::None {
+            return;
+        }
+
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if PII_FIELDS.contains(&key.as_str()) {
+                        *value = match (self.filter, &*value) {
+                            (PiiFilter::Hash, Value::String(s)) => Value::String(self.hash(s)),
+                            _ => Value::Null,
+                        };
+                    } else {
+                        self.filter_value(value);
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.filter_value(item)),
+            _ => {}
+        }
+    }
+
+    fn hash(&self, value: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.hash_key).expect("HMAC can take a key of any size");
+        mac.update(value.as_bytes());
+        hex::encode(&mac.finalize().into_bytes()[..16])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{Firehose, PiiFilter};
+    use crate::models::state::Config;
+
+    fn firehose(filter: PiiFilter) -> Firehose {
+        let config = Config::builder()
+            .database_url("postgres://localhost".to_string())
+            .minio_url("http://localhost".to_string())
+            .minio_access_key("access".to_string())
+            .minio_secret_key("secret".to_string())
+            .listen_addr(([127, 0, 0, 1], 8080))
+            .machine_id(0)
+            .process_id(0)
+            .app_secret("hunter2".to_string())
+            .firehose_pii_filter(filter)
+            .build()
+            .expect("Config should be valid");
+        Firehose::new(&config)
+    }
+
+    #[test]
+    fn test_pii_filter() {
+        let event = json!({
+            "id": "1",
+            "content": "Hello there",
+            "author": { "id": "2", "username": "alice", "display_name": null },
+            "attachments": [{ "id": 0, "filename": "cat.png" }],
+        });
+
+        let mut redacted = event.clone();
+        firehose(PiiFilter::Redact).filter_value(&mut redacted);
+        assert_eq!(
+            redacted,
+            json!({
+                "id": "1",
+                "content": null,
+                "author": { "id": "2", "username": null, "display_name": null },
+                "attachments": [{ "id": 0, "filename": null }],
+            })
+        );
+
+        let hashing = firehose(PiiFilter::Hash);
+        let mut hashed = event.clone();
+        hashing.filter_value(&mut hashed);
+        assert_eq!(hashed["author"]["username"], hashing.hash("alice"));
+        assert_ne!(hashed["author"]["username"], "alice");
+        assert_eq!(hashed["author"]["display_name"], json!(null));
+
+        let mut unfiltered = event.clone();
+        firehose(PiiFilter::None).filter_value(&mut unfiltered);
+        assert_eq!(unfiltered, event);
+    }
+
+    #[test]
+    fn test_parse_pii_filter() {
+        assert_eq!("HASH".parse::<PiiFilter>(), Ok(PiiFilter::Hash));
+        assert!("scramble".parse::<PiiFilter>().is_err());
+    }
+}
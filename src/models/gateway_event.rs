@@ -1,15 +1,28 @@
+use std::time::Duration;
+
 use secrecy::Secret;
 use serde::{Deserialize, Serialize};
 
 use super::{
+    announcement::Announcement,
     channel::{Channel, ChannelLike},
+    close_code::GatewayCloseCode,
     errors::AppError,
+    fingerprint::EvasionFlag,
+    guest_link::GuestAccess,
     guild::Guild,
+    guild_folder::GuildSettings,
+    invite::GuildInvite,
     member::{Member, UserLike},
     message::Message,
+    presence_privacy::PresencePrivacy,
+    report::Report,
+    session::{ClientProperties, GatewaySession},
+    shard::ShardInfo,
     snowflake::Snowflake,
     state::ApplicationState,
-    user::{Presence, User},
+    strike::Strike,
+    user::{Activity, Presence, User},
 };
 
 pub trait EventLike {
@@ -29,57 +42,214 @@ pub enum GatewayEvent {
     HeartbeatAck,
     /// A chat message.
     MessageCreate(Message),
+    /// A chat message was updated.
+    MessageUpdate(Message),
+    /// Multiple messages were deleted from a channel at once.
+    MessageBulkRemove(MessageBulkRemovePayload),
     /// A peer has joined the chat.
     MemberCreate(Member),
+    /// A member was updated.
+    MemberUpdate(Member),
     /// A peer has left the chat.
     MemberRemove(DeletePayload<User>),
     /// A guild was created.
     GuildCreate(GuildCreatePayload),
+    /// A page of the members of a guild, in response to a `REQUEST_GUILD_MEMBERS` message.
+    GuildMembersChunk(GuildMembersChunkPayload),
     /// A guild was deleted.
     GuildRemove(Guild),
     /// A channel was created.
     ChannelCreate(Channel),
+    /// A channel was updated.
+    ChannelUpdate(Channel),
     /// A channel was deleted.
     ChannelRemove(Channel),
+    /// Multiple channels of a guild were created or updated at once.
+    ChannelBulkUpdate(ChannelBulkUpdatePayload),
+    /// The sticky message of a channel was set or cleared.
+    ChannelStickyUpdate(ChannelStickyUpdatePayload),
     // A user's presence was updated.
     PresenceUpdate(PresenceUpdatePayload),
+    /// The user was invited to a guild.
+    InviteCreate(GuildInvite),
+    /// Progress report of a member import job.
+    MemberImportProgress(MemberImportProgressPayload),
+    /// A message in a guild owned by the user was reported.
+    ReportCreate(Report),
+    /// The user was issued a strike in a guild.
+    StrikeCreate(Strike),
+    /// A member who joined a guild owned by the user shares a fingerprint with a banned user.
+    EvasionFlagCreate(EvasionFlag),
+    /// The welcome message of a guild the user joined.
+    WelcomeMessage(WelcomeMessagePayload),
+    /// Raid mode of a guild was enabled or disabled.
+    RaidModeUpdate(RaidModeUpdatePayload),
+    /// An announcement from the operators of the server, sent to all connected users.
+    SystemAnnouncement(Announcement),
+    /// The user rearranged their guilds in another session.
+    GuildSettingsUpdate(GuildSettings),
     /// The server is ready to accept messages.
     Ready(ReadyPayload),
-    /// The server has closed the connection.
-    InvalidSession(String),
+    /// A session was resumed, all events it missed were sent again.
+    Resumed(ResumedPayload),
+    /// The server is about to close the connection.
+    InvalidSession(InvalidSessionPayload),
+}
+
+impl GatewayEvent {
+    /// The name of the event, as sent to clients in the `event` field.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Hello(_) => "HELLO",
+            Self::HeartbeatAck => "HEARTBEAT_ACK",
+            Self::MessageCreate(_) => "MESSAGE_CREATE",
+            Self::MessageUpdate(_) => "MESSAGE_UPDATE",
+            Self::MessageBulkRemove(_) => "MESSAGE_BULK_REMOVE",
+            Self::MemberCreate(_) => "MEMBER_CREATE",
+            Self::MemberUpdate(_) => "MEMBER_UPDATE",
+            Self::MemberRemove(_) => "MEMBER_REMOVE",
+            Self::GuildCreate(_) => "GUILD_CREATE",
+            Self::GuildMembersChunk(_) => "GUILD_MEMBERS_CHUNK",
+            Self::GuildRemove(_) => "GUILD_REMOVE",
+            Self::ChannelCreate(_) => "CHANNEL_CREATE",
+            Self::ChannelUpdate(_) => "CHANNEL_UPDATE",
+            Self::ChannelRemove(_) => "CHANNEL_REMOVE",
+            Self::ChannelBulkUpdate(_) => "CHANNEL_BULK_UPDATE",
+            Self::ChannelStickyUpdate(_) => "CHANNEL_STICKY_UPDATE",
+            Self::PresenceUpdate(_) => "PRESENCE_UPDATE",
+            Self::InviteCreate(_) => "INVITE_CREATE",
+            Self::MemberImportProgress(_) => "MEMBER_IMPORT_PROGRESS",
+            Self::WelcomeMessage(_) => "WELCOME_MESSAGE",
+            Self::RaidModeUpdate(_) => "RAID_MODE_UPDATE",
+            Self::SystemAnnouncement(_) => "SYSTEM_ANNOUNCEMENT",
+            Self::GuildSettingsUpdate(_) => "GUILD_SETTINGS_UPDATE",
+            Self::ReportCreate(_) => "REPORT_CREATE",
+            Self::StrikeCreate(_) => "STRIKE_CREATE",
+            Self::EvasionFlagCreate(_) => "EVASION_FLAG_CREATE",
+            Self::Ready(_) => "READY",
+            Self::Resumed(_) => "RESUMED",
+            Self::InvalidSession(_) => "INVALID_SESSION",
+        }
+    }
+
+    /// The channel this event is specific to, if any.
+    ///
+    /// Only events that clients may want to filter by channel return a value here,
+    /// guild and channel structure events are never channel-specific.
+    pub const fn extract_channel_id(&self) -> Option<Snowflake<Channel>> {
+        match self {
+            Self::MessageCreate(message) | Self::MessageUpdate(message) => Some(message.channel_id()),
+            Self::MessageBulkRemove(payload) => Some(payload.channel_id),
+            _ => None,
+        }
+    }
+
+    /// The user this event reveals to be active, if any.
+    ///
+    /// Users who appear offline must not be seen doing anything,
+    /// so the gateway only sends these events to the user themselves while they appear offline.
+    pub fn reveals_activity_of(&self) -> Option<Snowflake<User>> {
+        match self {
+            Self::PresenceUpdate(payload) if payload.presence != Presence::Offline || payload.activity.is_some() => {
+                Some(payload.user_id)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The information needed to determine which users should receive an event.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct EventRouting {
+    /// If set, only members of this guild receive the event.
+    pub guild_id: Option<Snowflake<Guild>>,
+    /// If set and the event is not guild-specific, only users sharing a guild with this user receive the event,
+    /// unless this user hides their presence from that guild.
+    pub user_id: Option<Snowflake<User>>,
+    /// If set, only users subscribed to this channel receive the event.
+    pub channel_id: Option<Snowflake<Channel>>,
+    /// If set, only this user receives the event, regardless of the other fields.
+    #[serde(default)]
+    pub recipient_id: Option<Snowflake<User>>,
+    /// If set, the event reveals that this user is active, such as coming online.
+    /// While the user appears offline, the event only reaches the user themselves.
+    #[serde(default)]
+    pub activity_of: Option<Snowflake<User>>,
+}
+
+impl From<&GatewayEvent> for EventRouting {
+    fn from(event: &GatewayEvent) -> Self {
+        Self {
+            guild_id: event.extract_guild_id(),
+            user_id: event.extract_user_id(),
+            channel_id: event.extract_channel_id(),
+            recipient_id: None,
+            activity_of: event.reveals_activity_of(),
+        }
+    }
 }
 
 // pain x_x
 impl EventLike for GatewayEvent {
     fn extract_guild_id(&self) -> Option<Snowflake<Guild>> {
         match self {
-            Self::MessageCreate(message) => message.extract_guild_id(),
-            Self::MemberCreate(member) => member.extract_guild_id(),
+            Self::MessageCreate(message) | Self::MessageUpdate(message) => message.extract_guild_id(),
+            Self::MessageBulkRemove(payload) => payload.guild_id,
+            Self::MemberCreate(member) | Self::MemberUpdate(member) => member.extract_guild_id(),
             Self::MemberRemove(payload) => payload.extract_guild_id(),
             Self::GuildCreate(guild) => guild.extract_guild_id(),
             Self::GuildRemove(payload) => payload.extract_guild_id(),
-            Self::ChannelCreate(channel) => channel.extract_guild_id(),
+            Self::ChannelCreate(channel) | Self::ChannelUpdate(channel) => channel.extract_guild_id(),
             Self::ChannelRemove(payload) => payload.extract_guild_id(),
+            Self::ChannelBulkUpdate(payload) => Some(payload.guild_id),
+            Self::ChannelStickyUpdate(payload) => Some(payload.guild_id),
+            Self::MemberImportProgress(payload) => Some(payload.guild_id),
+            Self::GuildMembersChunk(payload) => Some(payload.guild_id),
+            Self::WelcomeMessage(payload) => Some(payload.guild_id),
+            Self::RaidModeUpdate(payload) => Some(payload.guild_id),
+            Self::ReportCreate(report) => Some(report.guild_id()),
+            Self::StrikeCreate(strike) => Some(strike.guild_id()),
+            Self::EvasionFlagCreate(flag) => Some(flag.guild_id),
             Self::PresenceUpdate(_)
+            | Self::InviteCreate(_)
+            | Self::SystemAnnouncement(_)
+            | Self::GuildSettingsUpdate(_)
             | Self::Hello(_)
             | Self::Ready(_)
             | Self::InvalidSession(_)
+            | Self::Resumed(_)
             | Self::HeartbeatAck => None,
         }
     }
 
     fn extract_user_id(&self) -> Option<Snowflake<User>> {
         match self {
-            Self::MessageCreate(message) => message.extract_user_id(),
-            Self::MemberCreate(member) => member.extract_user_id(),
+            Self::MessageCreate(message) | Self::MessageUpdate(message) => message.extract_user_id(),
+            Self::MemberCreate(member) | Self::MemberUpdate(member) => member.extract_user_id(),
             Self::MemberRemove(payload) => payload.extract_user_id(),
             Self::GuildCreate(guild) => guild.extract_user_id(),
             Self::GuildRemove(payload) => payload.extract_user_id(),
-            Self::ChannelCreate(channel) => channel.extract_user_id(),
+            Self::ChannelCreate(channel) | Self::ChannelUpdate(channel) => channel.extract_user_id(),
             Self::ChannelRemove(payload) => payload.extract_user_id(),
             Self::PresenceUpdate(payload) => Some(payload.user_id),
+            Self::InviteCreate(invite) => Some(invite.user_id()),
             Self::Ready(payload) => payload.extract_user_id(),
-            Self::InvalidSession(_) | Self::HeartbeatAck | Self::Hello(_) => None,
+            Self::MessageBulkRemove(_)
+            | Self::ChannelBulkUpdate(_)
+            | Self::ChannelStickyUpdate(_)
+            | Self::MemberImportProgress(_)
+            | Self::GuildMembersChunk(_)
+            | Self::WelcomeMessage(_)
+            | Self::RaidModeUpdate(_)
+            | Self::SystemAnnouncement(_)
+            | Self::GuildSettingsUpdate(_)
+            | Self::ReportCreate(_)
+            | Self::StrikeCreate(_)
+            | Self::EvasionFlagCreate(_)
+            | Self::InvalidSession(_)
+            | Self::Resumed(_)
+            | Self::HeartbeatAck
+            | Self::Hello(_) => None,
         }
     }
 }
@@ -108,7 +278,7 @@ impl EventLike for Member {
 
 impl EventLike for Channel {
     fn extract_guild_id(&self) -> Option<Snowflake<Guild>> {
-        Some(self.guild_id())
+        self.guild_id()
     }
     fn extract_user_id(&self) -> Option<Snowflake<User>> {
         None
@@ -144,6 +314,60 @@ impl HelloPayload {
     }
 }
 
+/// Sent right before the server closes a connection, repeating the close code and reason of the close frame.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InvalidSessionPayload {
+    /// The code the connection is closed with.
+    code: GatewayCloseCode,
+    /// Why the connection is closed.
+    reason: String,
+    /// How long the client should wait before reconnecting, in milliseconds.
+    /// `None` if the client should not reconnect without changing something first, such as its token.
+    reconnect_after_ms: Option<u64>,
+}
+
+impl InvalidSessionPayload {
+    /// Create a new payload, with the reconnect hint suggested by the close code.
+    ///
+    /// ## Arguments
+    ///
+    /// * `code` - The code the connection is closed with.
+    /// * `reason` - Why the connection is closed.
+    pub fn new(code: GatewayCloseCode, reason: impl Into<String>) -> Self {
+        Self {
+            code,
+            reason: reason.into(),
+            reconnect_after_ms: code.reconnect_after().map(|after| after.as_millis() as u64),
+        }
+    }
+
+    /// Replace the reconnect hint suggested by the close code.
+    ///
+    /// ## Arguments
+    ///
+    /// * `after` - How long the client should wait before reconnecting, or `None` if it should not.
+    #[must_use]
+    pub fn with_reconnect_after(mut self, after: Option<Duration>) -> Self {
+        self.reconnect_after_ms = after.map(|after| after.as_millis() as u64);
+        self
+    }
+
+    /// The code the connection is closed with.
+    pub const fn code(&self) -> GatewayCloseCode {
+        self.code
+    }
+
+    /// Why the connection is closed.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// How long the client should wait before reconnecting, or `None` if it should not.
+    pub fn reconnect_after(&self) -> Option<Duration> {
+        self.reconnect_after_ms.map(Duration::from_millis)
+    }
+}
+
 /// A wrapper object around an ID with an optional `guild_id` to aid gateway event filtering.
 /// The `guild_id` field is not serialized and sent through the API.
 #[derive(Debug, Clone, Serialize)]
@@ -174,6 +398,102 @@ impl EventLike for DeletePayload<User> {
 pub struct PresenceUpdatePayload {
     pub user_id: Snowflake<User>,
     pub presence: Presence,
+    /// The user's current activity, if any. Always `None` if the user appears offline.
+    pub activity: Option<Activity>,
+}
+
+/// Represents the payload of a `MESSAGE_BULK_REMOVE` event.
+#[derive(Serialize, Clone, Debug)]
+pub struct MessageBulkRemovePayload {
+    /// The IDs of the removed messages.
+    pub ids: Vec<Snowflake<Message>>,
+    /// The channel the messages were removed from.
+    pub channel_id: Snowflake<Channel>,
+    /// The guild the channel belongs to, if any.
+    pub guild_id: Option<Snowflake<Guild>>,
+}
+
+/// Represents the payload of a `CHANNEL_BULK_UPDATE` event.
+///
+/// Sent instead of individual `CHANNEL_CREATE` and `CHANNEL_UPDATE` events when channels are changed in bulk.
+#[derive(Serialize, Clone, Debug)]
+pub struct ChannelBulkUpdatePayload {
+    /// The guild the channels belong to.
+    pub guild_id: Snowflake<Guild>,
+    /// The channels that were created, in the order they were created in.
+    pub created: Vec<Channel>,
+    /// The channels that were updated.
+    pub updated: Vec<Channel>,
+}
+
+/// Represents the payload of a `CHANNEL_STICKY_UPDATE` event.
+#[derive(Serialize, Clone, Debug)]
+pub struct ChannelStickyUpdatePayload {
+    /// The channel whose sticky message changed.
+    pub channel_id: Snowflake<Channel>,
+    /// The guild the channel belongs to.
+    pub guild_id: Snowflake<Guild>,
+    /// The new sticky message of the channel, or `None` if it was cleared.
+    pub message: Option<Message>,
+}
+
+/// Represents the payload of a `MEMBER_IMPORT_PROGRESS` event.
+///
+/// This event is only sent to the user who started the import.
+#[derive(Serialize, Clone, Debug)]
+pub struct MemberImportProgressPayload {
+    /// The ID of the import job.
+    pub job_id: Snowflake<()>,
+    /// The guild members are being invited to.
+    pub guild_id: Snowflake<Guild>,
+    /// The total amount of usernames to process.
+    pub total: usize,
+    /// The amount of usernames processed so far.
+    pub processed: usize,
+    /// The amount of users that were invited.
+    pub invited: usize,
+    /// The amount of usernames that were skipped, because the user does not exist,
+    /// is already a member, or already has a pending invite.
+    pub skipped: usize,
+    /// Whether the import has finished.
+    pub done: bool,
+}
+
+/// Represents the payload of a `WELCOME_MESSAGE` event.
+///
+/// This event is only sent to the user who joined the guild.
+#[derive(Serialize, Clone, Debug)]
+pub struct WelcomeMessagePayload {
+    /// The guild the user joined.
+    pub guild_id: Snowflake<Guild>,
+    /// The welcome message of the guild, filled in for the user.
+    pub content: String,
+}
+
+/// Represents the payload of a `RAID_MODE_UPDATE` event.
+#[derive(Serialize, Clone, Debug)]
+pub struct RaidModeUpdatePayload {
+    /// The guild whose raid mode changed.
+    pub guild_id: Snowflake<Guild>,
+    /// UNIX timestamp of when raid mode ends. If it is not in the future, raid mode was disabled.
+    pub raid_mode_until: i64,
+}
+
+/// Represents the payload of a `GUILD_MEMBERS_CHUNK` event.
+///
+/// This event is only sent to the user who requested the members.
+#[derive(Serialize, Clone, Debug)]
+pub struct GuildMembersChunkPayload {
+    /// The guild the members belong to.
+    pub guild_id: Snowflake<Guild>,
+    /// The members in this chunk, ordered by their user ID.
+    pub members: Vec<Member>,
+    /// The index of this chunk, starting from 0.
+    pub chunk_index: usize,
+    /// The total amount of chunks sent in response to the request.
+    pub chunk_count: usize,
+    /// The nonce sent with the request, if any.
+    pub nonce: Option<String>,
 }
 
 /// Represents a `GUILD_CREATE` payload.
@@ -182,36 +502,64 @@ pub struct PresenceUpdatePayload {
 #[derive(Serialize, Debug, Clone)]
 pub struct GuildCreatePayload {
     pub guild: Guild,
+    /// The members of the guild. Empty if the guild is large, clients request its members on demand instead.
     pub members: Vec<Member>,
     pub channels: Vec<Channel>,
+    /// The amount of members in the guild.
+    pub member_count: usize,
+    /// Whether the guild has more members than the large guild threshold.
+    pub large: bool,
 }
 
 impl GuildCreatePayload {
-    pub fn new(guild: Guild, members: Vec<Member>, channels: Vec<Channel>) -> Self {
+    pub const fn new(guild: Guild, members: Vec<Member>, channels: Vec<Channel>) -> Self {
         Self {
             guild,
+            member_count: members.len(),
             members,
             channels,
+            large: false,
         }
     }
 
     /// Create a new guild create payload by fetching all relevant data from the database.
+    /// Members of large guilds are not included.
     ///
     /// ## Errors
     ///
     /// * [`sqlx::Error`] - If the database query fails.
     pub async fn from_guild(app: &ApplicationState, guild: Guild) -> Result<Self, AppError> {
+        let member_count = app.ops().fetch_member_count(&guild).await?;
+        let large = member_count > app.config.large_guild_threshold();
+
         // Presences need to be included in the payload
-        let members = app
-            .ops()
-            .fetch_members_for(&guild)
-            .await?
-            .into_iter()
-            .map(|m| m.include_presence(&app.gateway))
-            .collect();
+        let members = if large {
+            Vec::new()
+        } else {
+            app.ops()
+                .fetch_members_for(&guild)
+                .await?
+                .into_iter()
+                .map(|m| m.include_presence(&app.gateway))
+                .collect()
+        };
 
         let channels = app.ops().fetch_channels_for(&guild).await?;
-        Ok(Self::new(guild, members, channels))
+        Ok(Self {
+            guild,
+            members,
+            channels,
+            member_count,
+            large,
+        })
+    }
+
+    /// Restrict the payload to what a guest of the guild can see, leaving out the channels
+    /// their guest link does not grant access to.
+    #[must_use]
+    pub fn for_guest(mut self, guest: &GuestAccess) -> Self {
+        self.channels.retain(|c| guest.can_access(c.id()));
+        self
     }
 }
 
@@ -227,13 +575,53 @@ impl EventLike for GuildCreatePayload {
 /// A payload sent by the server to the client after a handshake.
 #[derive(Serialize, Debug, Clone)]
 pub struct ReadyPayload {
+    /// The ID of the session, needed to resume it after the connection is lost.
+    pub session_id: Snowflake<GatewaySession>,
+    /// The token needed to resume the session, only sent to websocket sessions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resume_token: Option<String>,
     pub user: User,
     pub guilds: Vec<Guild>,
+    /// Who can see the user's presence.
+    pub presence_privacy: PresencePrivacy,
+    /// Server announcements that did not expire yet, oldest first.
+    pub announcements: Vec<Announcement>,
+    /// How the user arranges their guilds.
+    pub guild_settings: GuildSettings,
 }
 
 impl ReadyPayload {
-    pub fn new(user: User, guilds: Vec<Guild>) -> Self {
-        Self { user, guilds }
+    pub const fn new(
+        session_id: Snowflake<GatewaySession>,
+        resume_token: Option<String>,
+        user: User,
+        guilds: Vec<Guild>,
+        presence_privacy: PresencePrivacy,
+        announcements: Vec<Announcement>,
+        guild_settings: GuildSettings,
+    ) -> Self {
+        Self {
+            session_id,
+            resume_token,
+            user,
+            guilds,
+            presence_privacy,
+            announcements,
+            guild_settings,
+        }
+    }
+}
+
+/// A payload sent by the server once a session was resumed.
+#[derive(Serialize, Debug, Clone)]
+pub struct ResumedPayload {
+    /// The token needed to resume the session again, replacing the one sent in `RESUME`.
+    pub resume_token: String,
+}
+
+impl ResumedPayload {
+    pub const fn new(resume_token: String) -> Self {
+        Self { resume_token }
     }
 }
 
@@ -253,11 +641,66 @@ impl EventLike for ReadyPayload {
 pub enum GatewayMessage {
     /// Identify with the server. This should be the first event sent by the client.
     Identify(IdentifyPayload),
-    /// A heartbeat message to indicate that the client is still active.
-    Heartbeat,
+    /// A heartbeat message to indicate that the client is still active, optionally acknowledging the events it received.
+    Heartbeat(Option<HeartbeatPayload>),
+    /// Set or clear the user's activity. The activity is cleared when the connection closes.
+    UpdateActivity(Option<Activity>),
+    /// Only receive channel-specific events for the given channels.
+    Subscribe(SubscribePayload),
+    /// Request the members of a guild, which are sent in `GUILD_MEMBERS_CHUNK` events.
+    RequestGuildMembers(RequestGuildMembersPayload),
+    /// Continue a session after its connection was lost. This may be sent instead of `IDENTIFY`.
+    Resume(ResumePayload),
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct IdentifyPayload {
-    pub token: Secret<String>,
+    /// The token to authenticate with. May be omitted if the upgrade request carried a session cookie.
+    pub token: Option<Secret<String>>,
+    /// The shard to connect to. Required if the gateway is sharded.
+    pub shard: Option<ShardInfo>,
+    /// Information about the client, used to tell sessions apart and to label metrics.
+    #[serde(default)]
+    pub properties: ClientProperties,
+}
+
+/// A payload sent by the client to continue a session after its connection was lost.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ResumePayload {
+    /// The token to authenticate with. May be omitted if the upgrade request carried a session cookie.
+    pub token: Option<Secret<String>>,
+    /// The ID of the session to resume, as sent in `READY`.
+    pub session_id: Snowflake<GatewaySession>,
+    /// The token sent in `READY` or the last `RESUMED` of the session. Each token can only be used once.
+    pub resume_token: Secret<String>,
+    /// The sequence number of the last event the client received. All events after it are sent again.
+    pub seq: u64,
+}
+
+/// A payload sent by the client along with a heartbeat.
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct HeartbeatPayload {
+    /// The sequence number of the last event the client received. These events are not sent again on `RESUME`.
+    pub seq: u64,
+}
+
+/// A payload sent by the client to narrow down which channel-specific events it receives.
+/// Guild and channel structure events are not affected by subscriptions.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SubscribePayload {
+    /// The channels to receive events for, or `None` to receive events for all channels.
+    pub channel_ids: Option<Vec<Snowflake<Channel>>>,
+}
+
+/// A payload sent by the client to request the members of a guild it is a member of.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RequestGuildMembersPayload {
+    /// The guild to request the members of.
+    pub guild_id: Snowflake<Guild>,
+    /// Only return members whose username, display name or nickname starts with this string, case-insensitively.
+    pub query: Option<String>,
+    /// The maximum amount of members to return. If `None` or `0`, all matching members are returned.
+    pub limit: Option<usize>,
+    /// A nonce that is sent back in every chunk, to match chunks to requests.
+    pub nonce: Option<String>,
 }
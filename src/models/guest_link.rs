@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    channel::Channel, errors::BuildError, guild::Guild, requests::CreateGuestLink, snowflake::Snowflake, user::User,
+};
+
+/// The longest guest membership a link may grant, in hours.
+pub const MAX_GUEST_DURATION: u32 = 24 * 7;
+/// The maximum amount of channels a guest link may grant access to.
+pub const MAX_GUEST_CHANNELS: usize = 10;
+/// The maximum amount of guest links a guild may have at once.
+pub const MAX_GUEST_LINKS_PER_GUILD: usize = 25;
+
+/// Represents a guest link record stored in the database.
+pub struct GuestLinkRecord {
+    pub code: String,
+    pub guild_id: Snowflake<Guild>,
+    pub creator_id: Option<i64>,
+    pub channel_ids: Vec<i64>,
+    pub duration: i32,
+    pub created_at: i64,
+}
+
+/// A link that lets users join a guild as guests.
+///
+/// Guests can only view and send messages in the channels of the link, and are removed from the guild
+/// once their membership expires.
+#[derive(Serialize, Debug, Clone)]
+pub struct GuestLink {
+    /// The random code identifying the link.
+    code: String,
+    /// The guild the link grants guest membership of.
+    guild_id: Snowflake<Guild>,
+    /// The user that created the link, if they still exist.
+    creator_id: Option<Snowflake<User>>,
+    /// The channels guests can access.
+    channel_ids: Vec<Snowflake<Channel>>,
+    /// How long guest membership lasts after joining, in hours.
+    duration: u32,
+    /// UNIX timestamp of when the link was created.
+    created_at: i64,
+}
+
+impl GuestLink {
+    /// Create a new guest link from a creation payload. Assigns a random code to the link.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the link grants guest membership of.
+    /// * `creator` - The user creating the link.
+    /// * `payload` - The payload to create the link from.
+    /// * `now` - The current time as a UNIX timestamp.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the payload is invalid.
+    pub fn from_payload(
+        guild: impl Into<Snowflake<Guild>>,
+        creator: impl Into<Snowflake<User>>,
+        mut payload: CreateGuestLink,
+        now: i64,
+    ) -> Result<Self, BuildError> {
+        payload.channel_ids.sort_unstable_by_key(|channel| i64::from(*channel));
+        payload.channel_ids.dedup();
+
+        if payload.channel_ids.is_empty() || payload.channel_ids.len() > MAX_GUEST_CHANNELS {
+            return Err(BuildError::ValidationError(format!(
+                "Guest links must grant access to between 1 and {MAX_GUEST_CHANNELS} channels"
+            )));
+        }
+        if !(1..=MAX_GUEST_DURATION).contains(&payload.duration) {
+            return Err(BuildError::ValidationError(format!(
+                "Guest membership must last between 1 and {MAX_GUEST_DURATION} hours"
+            )));
+        }
+
+        Ok(Self {
+            code: hex::encode(rand::random::<[u8; 16]>()),
+            guild_id: guild.into(),
+            creator_id: Some(creator.into()),
+            channel_ids: payload.channel_ids,
+            duration: payload.duration,
+            created_at: now,
+        })
+    }
+
+    /// Build a guest link from a database record.
+    pub fn from_record(record: GuestLinkRecord) -> Self {
+        Self {
+            code: record.code,
+            guild_id: record.guild_id,
+            creator_id: record.creator_id.map(Snowflake::new),
+            channel_ids: record.channel_ids.into_iter().map(Snowflake::new).collect(),
+            duration: u32::try_from(record.duration).unwrap_or_default(),
+            created_at: record.created_at,
+        }
+    }
+
+    /// The random code identifying the link.
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /// The guild the link grants guest membership of.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The user that created the link, if they still exist.
+    pub const fn creator_id(&self) -> Option<Snowflake<User>> {
+        self.creator_id
+    }
+
+    /// The channels guests can access.
+    pub fn channel_ids(&self) -> &[Snowflake<Channel>] {
+        &self.channel_ids
+    }
+
+    /// How long guest membership lasts after joining, in hours.
+    pub const fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    /// UNIX timestamp of when the link was created.
+    pub const fn created_at(&self) -> i64 {
+        self.created_at
+    }
+
+    /// The access granted to a user joining through the link.
+    ///
+    /// ## Arguments
+    ///
+    /// * `now` - The time the user joins at, as a UNIX timestamp.
+    pub fn grant(&self, now: i64) -> GuestAccess {
+        GuestAccess {
+            expires_at: now + i64::from(self.duration) * 3600,
+            channel_ids: self.channel_ids.clone(),
+        }
+    }
+}
+
+/// The access of a member who joined a guild through a guest link.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuestAccess {
+    /// UNIX timestamp of when the member is removed from the guild.
+    expires_at: i64,
+    /// The channels the member can access.
+    channel_ids: Vec<Snowflake<Channel>>,
+}
+
+impl GuestAccess {
+    /// Build the guest access of a member from its database columns.
+    ///
+    /// ## Returns
+    ///
+    /// The access of the member, or `None` if they are a full member.
+    pub fn from_columns(guest_until: Option<i64>, channel_ids: Option<Vec<i64>>) -> Option<Self> {
+        Some(Self {
+            expires_at: guest_until?,
+            channel_ids: channel_ids
+                .unwrap_or_default()
+                .into_iter()
+                .map(Snowflake::new)
+                .collect(),
+        })
+    }
+
+    /// UNIX timestamp of when the member is removed from the guild.
+    pub const fn expires_at(&self) -> i64 {
+        self.expires_at
+    }
+
+    /// The channels the member can access.
+    pub fn channel_ids(&self) -> &[Snowflake<Channel>] {
+        &self.channel_ids
+    }
+
+    /// Whether the member can view and send messages in the given channel.
+    pub fn can_access(&self, channel: impl Into<Snowflake<Channel>>) -> bool {
+        self.channel_ids.contains(&channel.into())
+    }
+
+    /// Whether the membership expired at the given time.
+    pub const fn is_expired(&self, now: i64) -> bool {
+        self.expires_at <= now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GuestAccess, GuestLink, MAX_GUEST_CHANNELS, MAX_GUEST_DURATION};
+    use crate::models::{requests::CreateGuestLink, snowflake::Snowflake};
+
+    fn payload(channels: impl IntoIterator<Item = i64>, duration: u32) -> CreateGuestLink {
+        CreateGuestLink {
+            channel_ids: channels.into_iter().map(Snowflake::new).collect(),
+            duration,
+        }
+    }
+
+    #[test]
+    fn test_from_payload() {
+        let link = GuestLink::from_payload(Snowflake::new(1), Snowflake::new(2), payload([4, 3, 4], 24), 1000)
+            .expect("Link should be valid");
+        assert_eq!(link.channel_ids(), [Snowflake::new(3), Snowflake::new(4)]);
+        assert_eq!(link.code().len(), 32);
+
+        let access = link.grant(2000);
+        assert_eq!(access.expires_at(), 2000 + 24 * 3600);
+        assert!(access.can_access(Snowflake::new(3)));
+        assert!(!access.can_access(Snowflake::new(5)));
+        assert!(!access.is_expired(2000));
+        assert!(access.is_expired(2000 + 24 * 3600));
+
+        assert!(GuestLink::from_payload(Snowflake::new(1), Snowflake::new(2), payload([], 24), 0).is_err());
+        let too_many = payload(0..=i64::try_from(MAX_GUEST_CHANNELS).expect("Should fit"), 24);
+        assert!(GuestLink::from_payload(Snowflake::new(1), Snowflake::new(2), too_many, 0).is_err());
+        assert!(GuestLink::from_payload(Snowflake::new(1), Snowflake::new(2), payload([3], 0), 0).is_err());
+        let too_long = payload([3], MAX_GUEST_DURATION + 1);
+        assert!(GuestLink::from_payload(Snowflake::new(1), Snowflake::new(2), too_long, 0).is_err());
+    }
+
+    #[test]
+    fn test_from_columns() {
+        assert_eq!(GuestAccess::from_columns(None, Some(vec![1])), None);
+        let access = GuestAccess::from_columns(Some(10), Some(vec![1, 2])).expect("Should be a guest");
+        assert_eq!(access.channel_ids(), [Snowflake::new(1), Snowflake::new(2)]);
+    }
+}
@@ -1,12 +1,16 @@
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 
 use super::{
     avatar::{Avatar, FullAvatar, GuildAvatar, PartialAvatar},
     errors::AppError,
+    i18n::{Locale, Timezone},
+    join_requirements::JoinRequirements,
     requests::{CreateGuild, UpdateGuild},
     snowflake::Snowflake,
     state::Config,
     user::User,
+    welcome_message::WelcomeMessage,
 };
 
 pub struct GuildRecord {
@@ -14,6 +18,13 @@ pub struct GuildRecord {
     pub name: String,
     pub owner_id: Snowflake<User>,
     pub avatar_hash: Option<String>,
+    pub min_account_age: Option<i32>,
+    pub invite_only: bool,
+    pub welcome_message: Option<String>,
+    pub raid_mode_until: Option<i64>,
+    pub locale: String,
+    pub timezone: String,
+    pub version: i32,
 }
 
 /// Represents a guild.
@@ -25,6 +36,24 @@ pub struct Guild {
 
     #[serde(rename = "avatar_hash")]
     avatar: Option<Avatar<GuildAvatar>>,
+
+    /// The requirements users must meet to join the guild.
+    join_requirements: JoinRequirements,
+
+    /// The message sent to users when they join the guild.
+    welcome_message: WelcomeMessage,
+
+    /// UNIX timestamp of when raid mode ends or ended, if it was ever enabled.
+    raid_mode_until: Option<i64>,
+
+    /// The locale the server writes dates and times in, in messages sent on behalf of the guild.
+    locale: Locale,
+
+    /// The timezone the server writes times in, in messages sent on behalf of the guild.
+    timezone: Timezone,
+
+    /// Incremented every time the settings of the guild are updated.
+    version: i32,
 }
 
 impl Guild {
@@ -41,6 +70,12 @@ impl Guild {
             name,
             owner_id: owner.into(),
             avatar: None,
+            join_requirements: JoinRequirements::default(),
+            welcome_message: WelcomeMessage::default(),
+            raid_mode_until: None,
+            locale: Locale::default(),
+            timezone: Timezone::default(),
+            version: 0,
         }
     }
 
@@ -64,6 +99,41 @@ impl Guild {
         self.avatar.as_ref()
     }
 
+    /// The requirements users must meet to join the guild.
+    pub const fn join_requirements(&self) -> &JoinRequirements {
+        &self.join_requirements
+    }
+
+    /// The message sent to users when they join the guild.
+    pub const fn welcome_message(&self) -> &WelcomeMessage {
+        &self.welcome_message
+    }
+
+    /// UNIX timestamp of when raid mode ends or ended, if it was ever enabled.
+    pub const fn raid_mode_until(&self) -> Option<i64> {
+        self.raid_mode_until
+    }
+
+    /// The locale the server writes dates and times in, in messages sent on behalf of the guild.
+    pub const fn locale(&self) -> &Locale {
+        &self.locale
+    }
+
+    /// The timezone the server writes times in, in messages sent on behalf of the guild.
+    pub const fn timezone(&self) -> Timezone {
+        self.timezone
+    }
+
+    /// Incremented every time the settings of the guild are updated.
+    pub const fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Whether the guild is in raid mode at the given time, enforcing stricter join requirements and slow mode.
+    pub fn is_raid_mode_active(&self, now: DateTime<Utc>) -> bool {
+        self.raid_mode_until.is_some_and(|until| until > now.timestamp())
+    }
+
     /// Create a new guild object from a database record.
     pub fn from_record(record: GuildRecord) -> Self {
         Self {
@@ -75,6 +145,14 @@ impl Guild {
                     PartialAvatar::<GuildAvatar>::new(h, record.id).expect("Database should have valid avatar hash"),
                 )
             }),
+            join_requirements: JoinRequirements::new(record.min_account_age, record.invite_only)
+                .expect("Database should have valid join requirements"),
+            welcome_message: WelcomeMessage::new(record.welcome_message)
+                .expect("Database should have a valid welcome message"),
+            raid_mode_until: record.raid_mode_until,
+            locale: record.locale.parse().expect("Database should have a valid locale"),
+            timezone: record.timezone.parse().expect("Database should have a valid timezone"),
+            version: record.version,
         }
     }
 
@@ -96,8 +174,13 @@ impl Guild {
     ///
     /// ## Errors
     ///
-    /// * [`AppError::Build`] - If the avatar data URI is invalid.
+    /// * [`AppError::Build`] - If the avatar data URI, the join requirements, the welcome message,
+    ///   the locale or the timezone are invalid.
+    /// * [`AppError::Conflict`] - If the payload expects a different version of the guild.
     pub fn update(&mut self, payload: UpdateGuild) -> Result<(), AppError> {
+        if payload.version.is_some_and(|version| version != self.version) {
+            return Err(AppError::Conflict("The guild was updated since it was fetched".into()));
+        }
         if let Some(name) = payload.name {
             self.name = name;
         }
@@ -107,6 +190,20 @@ impl Guild {
         if let Some(avatar) = payload.avatar {
             self.avatar = Some(Avatar::Full(FullAvatar::from_data_uri(self.id(), avatar)?));
         }
+        if let Some(join_requirements) = payload.join_requirements {
+            join_requirements.validate()?;
+            self.join_requirements = join_requirements;
+        }
+        if let Some(welcome_message) = payload.welcome_message {
+            welcome_message.validate()?;
+            self.welcome_message = welcome_message;
+        }
+        if let Some(locale) = payload.locale {
+            self.locale = locale.parse()?;
+        }
+        if let Some(timezone) = payload.timezone {
+            self.timezone = timezone.parse()?;
+        }
         Ok(())
     }
 }
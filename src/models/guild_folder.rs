@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    errors::{BuildError, ErrorCode},
+    guild::Guild,
+    snowflake::Snowflake,
+};
+
+/// The most entries a user's guild list may have, counting folders and guilds outside of folders.
+pub const MAX_GUILD_FOLDERS: usize = 200;
+/// The maximum length of the name of a folder, in characters.
+pub const MAX_FOLDER_NAME_LENGTH: usize = 32;
+/// The largest color a folder may have, as a `0xRRGGBB` integer.
+const MAX_FOLDER_COLOR: u32 = 0x00FF_FFFF;
+
+/// Represents a guild folder record stored in the database.
+pub struct GuildFolderRecord {
+    pub folder_id: Option<i64>,
+    pub name: Option<String>,
+    pub color: Option<i32>,
+    pub guild_ids: Vec<i64>,
+}
+
+/// An entry of a user's guild list, either a folder of guilds or a single guild outside of any folder.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GuildFolder {
+    /// The ID of the folder, chosen by the client. `None` if the entry is a single guild outside of any folder.
+    #[serde(default)]
+    pub id: Option<u32>,
+    /// The name of the folder, if any.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The color of the folder as a `0xRRGGBB` integer, if any.
+    #[serde(default)]
+    pub color: Option<u32>,
+    /// The guilds in the folder, in order.
+    pub guild_ids: Vec<Snowflake<Guild>>,
+}
+
+impl GuildFolder {
+    /// Build a folder from a database record.
+    pub fn from_record(record: GuildFolderRecord) -> Self {
+        Self {
+            id: record.folder_id.and_then(|id| u32::try_from(id).ok()),
+            name: record.name,
+            color: record.color.and_then(|color| u32::try_from(color).ok()),
+            guild_ids: record.guild_ids.into_iter().map(Snowflake::new).collect(),
+        }
+    }
+}
+
+/// How a user arranges the guilds in their sidebar. Synced across all sessions of the user.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct GuildSettings {
+    /// The guilds of the user in sidebar order, grouped into folders.
+    /// Guilds that are not listed, such as newly joined guilds, are not ordered.
+    folders: Vec<GuildFolder>,
+}
+
+impl GuildSettings {
+    /// Create new guild settings. Folder names are trimmed, and empty names are removed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `folders` - The guilds of the user in sidebar order, grouped into folders.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If there are too many entries, an entry is malformed,
+    ///   or a guild or folder ID is listed more than once.
+    pub fn new(folders: Vec<GuildFolder>) -> Result<Self, BuildError> {
+        let folders = folders
+            .into_iter()
+            .map(|mut folder| {
+                folder.name = folder
+                    .name
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty());
+                folder
+            })
+            .collect();
+
+        let settings = Self { folders };
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Build the guild settings of a user from their folder records, in order.
+    pub fn from_records(records: impl IntoIterator<Item = GuildFolderRecord>) -> Self {
+        Self {
+            folders: records.into_iter().map(GuildFolder::from_record).collect(),
+        }
+    }
+
+    /// The guilds of the user in sidebar order, grouped into folders.
+    pub fn folders(&self) -> &[GuildFolder] {
+        &self.folders
+    }
+
+    /// Remove all guilds that are not in the given set, such as guilds the user left.
+    /// Folders that end up empty are removed.
+    pub fn retain_guilds(&mut self, guilds: &HashSet<Snowflake<Guild>>) {
+        for folder in &mut self.folders {
+            folder.guild_ids.retain(|guild| guilds.contains(guild));
+        }
+        self.folders.retain(|folder| !folder.guild_ids.is_empty());
+    }
+
+    /// Ensure the settings are within the allowed bounds.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If there are too many entries, an entry is malformed,
+    ///   or a guild or folder ID is listed more than once.
+    pub fn validate(&self) -> Result<(), BuildError> {
+        let invalid = |code: ErrorCode, message: &str| BuildError::InvalidField {
+            field: "folders",
+            code,
+            message: message.into(),
+        };
+
+        let guild_count: usize = self.folders.iter().map(|folder| folder.guild_ids.len()).sum();
+        if self.folders.len() > MAX_GUILD_FOLDERS || guild_count > MAX_GUILD_FOLDERS {
+            return Err(invalid(
+                ErrorCode::TooMany,
+                &format!("At most {MAX_GUILD_FOLDERS} folders and guilds may be listed."),
+            ));
+        }
+
+        let mut folder_ids = HashSet::new();
+        let mut guild_ids = HashSet::new();
+
+        for folder in &self.folders {
+            if folder.guild_ids.is_empty() {
+                return Err(invalid(ErrorCode::Required, "Folders must contain at least one guild."));
+            }
+            if folder
+                .name
+                .as_ref()
+                .is_some_and(|name| name.chars().count() > MAX_FOLDER_NAME_LENGTH)
+            {
+                return Err(invalid(
+                    ErrorCode::TooLong,
+                    &format!("Folder names must be at most {MAX_FOLDER_NAME_LENGTH} characters long."),
+                ));
+            }
+            if folder.color.is_some_and(|color| color > MAX_FOLDER_COLOR) {
+                return Err(invalid(
+                    ErrorCode::OutOfRange,
+                    "Folder colors must be between 0 and 0xFFFFFF.",
+                ));
+            }
+
+            if folder.id.is_some_and(|id| !folder_ids.insert(id)) {
+                return Err(invalid(ErrorCode::ValidationFailed, "Folder IDs must be unique."));
+            }
+            // Guilds outside of folders cannot be named or colored
+            if folder.id.is_none() && (folder.guild_ids.len() > 1 || folder.name.is_some() || folder.color.is_some()) {
+                return Err(invalid(
+                    ErrorCode::ValidationFailed,
+                    "Entries without an ID must contain a single guild, and no name or color.",
+                ));
+            }
+
+            if !folder.guild_ids.iter().all(|guild| guild_ids.insert(*guild)) {
+                return Err(invalid(ErrorCode::ValidationFailed, "Guilds may only be listed once."));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{GuildFolder, GuildSettings, MAX_GUILD_FOLDERS};
+    use crate::models::snowflake::Snowflake;
+
+    fn folder(id: Option<u32>, name: Option<&str>, guilds: &[i64]) -> GuildFolder {
+        GuildFolder {
+            id,
+            name: name.map(String::from),
+            color: None,
+            guild_ids: guilds.iter().copied().map(Snowflake::new).collect(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let settings = GuildSettings::new(vec![
+            folder(None, None, &[1]),
+            folder(Some(1), Some("  Games  "), &[2, 3]),
+        ])
+        .expect("Settings should be valid");
+        assert_eq!(settings.folders()[1].name.as_deref(), Some("Games"));
+        assert_eq!(
+            GuildSettings::new(vec![folder(Some(1), Some(" "), &[1])])
+                .expect("Settings should be valid")
+                .folders()[0]
+                .name,
+            None
+        );
+
+        // Guilds and folder IDs must be unique
+        assert!(GuildSettings::new(vec![folder(None, None, &[1]), folder(Some(1), None, &[1])]).is_err());
+        assert!(GuildSettings::new(vec![folder(Some(1), None, &[1]), folder(Some(1), None, &[2])]).is_err());
+        // Entries without an ID are single guilds
+        assert!(GuildSettings::new(vec![folder(None, None, &[1, 2])]).is_err());
+        assert!(GuildSettings::new(vec![folder(None, Some("Games"), &[1])]).is_err());
+        assert!(GuildSettings::new(vec![folder(Some(1), None, &[])]).is_err());
+        assert!(GuildSettings::new(vec![folder(Some(1), Some(&"a".repeat(33)), &[1])]).is_err());
+
+        let mut colored = folder(Some(1), None, &[1]);
+        colored.color = Some(0x0100_0000);
+        assert!(GuildSettings::new(vec![colored]).is_err());
+
+        let too_many = (0..=MAX_GUILD_FOLDERS as i64)
+            .map(|id| folder(None, None, &[id]))
+            .collect();
+        assert!(GuildSettings::new(too_many).is_err());
+    }
+
+    #[test]
+    fn test_retain_guilds() {
+        let mut settings = GuildSettings::new(vec![folder(None, None, &[1]), folder(Some(1), None, &[2, 3])])
+            .expect("Settings should be valid");
+        settings.retain_guilds(&HashSet::from([Snowflake::new(3)]));
+        assert_eq!(settings.folders(), &[folder(Some(1), None, &[3])]);
+    }
+}
@@ -0,0 +1,190 @@
+use std::{fmt, str::FromStr, sync::LazyLock};
+
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::errors::{BuildError, ErrorCode};
+
+/// Matches a language code, optionally followed by a region code, as in `de` or `en_US`.
+static LOCALE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^[a-z]{2,3}(?:[_-][A-Z]{2})?$").expect("Failed to compile locale regex"));
+
+/// A language with an optional region, such as `en_US` or `de`. Determines how the server writes dates and times.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct Locale(String);
+
+impl Locale {
+    /// The language code of the locale, such as `en`.
+    pub fn language(&self) -> &str {
+        self.0.split(['_', '-']).next().unwrap_or_default()
+    }
+
+    /// The region code of the locale, such as `US`, if it has one.
+    pub fn region(&self) -> Option<&str> {
+        self.0.split_once(['_', '-']).map(|(_, region)| region)
+    }
+
+    /// The name of the locale, with the region separated by an underscore.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Write a point in time the way it is usually written in the locale, followed by the abbreviation of its timezone.
+    pub fn format_datetime<Z: TimeZone>(&self, time: &DateTime<Z>) -> String
+    where
+        Z::Offset: fmt::Display,
+    {
+        let format = match (self.language(), self.region()) {
+            ("en", Some("US")) => "%m/%d/%Y %-I:%M %p %Z",
+            ("en" | "fr" | "es" | "it" | "pt" | "el", _) => "%d/%m/%Y %H:%M %Z",
+            ("de" | "ru" | "pl" | "cs" | "fi" | "nb" | "tr" | "uk", _) => "%d.%m.%Y %H:%M %Z",
+            ("nl", _) => "%d-%m-%Y %H:%M %Z",
+            ("ja" | "zh" | "ko" | "hu", _) => "%Y/%m/%d %H:%M %Z",
+            _ => "%Y-%m-%d %H:%M %Z",
+        };
+        time.format(format).to_string()
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self(String::from("en_US"))
+    }
+}
+
+impl FromStr for Locale {
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !LOCALE_REGEX.is_match(s) {
+            return Err(BuildError::InvalidField {
+                field: "locale",
+                code: ErrorCode::ValidationFailed,
+                message: "Locale must be a language code with an optional region, such as 'en_US' or 'de'.".into(),
+            });
+        }
+        Ok(Self(s.replace('-', "_")))
+    }
+}
+
+impl TryFrom<String> for Locale {
+    type Error = BuildError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Locale> for String {
+    fn from(locale: Locale) -> Self {
+        locale.0
+    }
+}
+
+/// A timezone of the IANA timezone database, such as `Europe/Berlin`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct Timezone(Tz);
+
+impl Timezone {
+    /// The IANA name of the timezone.
+    pub fn name(self) -> &'static str {
+        self.0.name()
+    }
+
+    /// Convert a UNIX timestamp in seconds to the local time of the timezone.
+    pub fn localize(self, timestamp: i64) -> DateTime<Tz> {
+        DateTime::<Utc>::from_timestamp(timestamp, 0)
+            .unwrap_or_default()
+            .with_timezone(&self.0)
+    }
+}
+
+impl Default for Timezone {
+    fn default() -> Self {
+        Self(Tz::UTC)
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = BuildError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<Tz>().map(Self).map_err(|_| BuildError::InvalidField {
+            field: "timezone",
+            code: ErrorCode::ValidationFailed,
+            message: "Timezone must be the name of an IANA timezone, such as 'Europe/Berlin'.".into(),
+        })
+    }
+}
+
+impl TryFrom<String> for Timezone {
+    type Error = BuildError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Timezone> for String {
+    fn from(timezone: Timezone) -> Self {
+        timezone.name().to_string()
+    }
+}
+
+/// Write a UNIX timestamp in seconds as a date and time, in the given locale and timezone.
+///
+/// ## Arguments
+///
+/// * `timestamp` - The UNIX timestamp to write, in seconds.
+/// * `locale` - The locale determining the order and separators of the date and time.
+/// * `timezone` - The timezone the time is written in.
+pub fn format_timestamp(timestamp: i64, locale: &Locale, timezone: Timezone) -> String {
+    locale.format_datetime(&timezone.localize(timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_timestamp, Locale, Timezone};
+
+    #[test]
+    fn test_parse() {
+        let locale: Locale = "pt-BR".parse().expect("Locale should be valid");
+        assert_eq!(locale.as_str(), "pt_BR");
+        assert_eq!((locale.language(), locale.region()), ("pt", Some("BR")));
+        assert!("english".parse::<Locale>().is_err());
+        assert!("en_us".parse::<Locale>().is_err());
+
+        let timezone: Timezone = "Europe/Berlin".parse().expect("Timezone should be valid");
+        assert_eq!(timezone.name(), "Europe/Berlin");
+        assert!("Mars/Olympus_Mons".parse::<Timezone>().is_err());
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        // 2024-07-01 18:30:00 UTC
+        let timestamp = 1_719_858_600;
+        let berlin: Timezone = "Europe/Berlin".parse().expect("Timezone should be valid");
+        let locale = |name: &str| name.parse::<Locale>().expect("Locale should be valid");
+
+        assert_eq!(
+            format_timestamp(timestamp, &Locale::default(), Timezone::default()),
+            "07/01/2024 6:30 PM UTC"
+        );
+        assert_eq!(
+            format_timestamp(timestamp, &locale("de"), berlin),
+            "01.07.2024 20:30 CEST"
+        );
+        assert_eq!(
+            format_timestamp(timestamp, &locale("en_GB"), berlin),
+            "01/07/2024 20:30 CEST"
+        );
+        assert_eq!(
+            format_timestamp(timestamp, &locale("sv"), berlin),
+            "2024-07-01 20:30 CEST"
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use chrono::{DateTime, Utc};
+
+use super::{db::Database, state::Config};
+
+/// How long an instance may go without a heartbeat before its machine and process IDs can be claimed by another.
+pub const INSTANCE_TIMEOUT: i64 = 30;
+
+/// A claim on the machine and process IDs of this instance, preventing other instances
+/// from generating snowflakes with the same IDs while it is running.
+///
+/// The claim is kept alive by sending heartbeats to the `instances` table.
+#[derive(Debug, Clone, Copy)]
+pub struct InstanceLease {
+    /// A random ID identifying this instance, as the machine and process IDs may be shared by accident.
+    id: i64,
+}
+
+impl InstanceLease {
+    /// Create a new, unclaimed lease.
+    pub fn new() -> Self {
+        Self { id: rand::random() }
+    }
+
+    /// The random ID identifying this instance.
+    pub const fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Claim the machine and process IDs of the configuration for this instance.
+    /// Instances whose last heartbeat is more than [`INSTANCE_TIMEOUT`] seconds before `now` are considered dead.
+    ///
+    /// ## Returns
+    ///
+    /// `None` if the IDs were claimed, or the UNIX timestamp of the last heartbeat
+    /// of the live instance holding them otherwise.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn claim(&self, db: &Database, config: &Config, now: DateTime<Utc>) -> Result<Option<i64>, sqlx::Error> {
+        let claimed = sqlx::query!(
+            "INSERT INTO instances (machine_id, process_id, instance_id, started_at, last_heartbeat)
+            VALUES ($1, $2, $3, $4, $4)
+            ON CONFLICT (machine_id, process_id) DO UPDATE
+            SET instance_id = $3, started_at = $4, last_heartbeat = $4
+            WHERE instances.instance_id = $3 OR instances.last_heartbeat < $5
+            RETURNING instance_id",
+            config.machine_id(),
+            config.process_id(),
+            self.id,
+            now.timestamp(),
+            now.timestamp() - INSTANCE_TIMEOUT,
+        )
+        .fetch_optional(db.executor())
+        .await?;
+
+        if claimed.is_some() {
+            return Ok(None);
+        }
+
+        Self::last_heartbeat(db, config, now).await
+    }
+
+    /// Fetch the last heartbeat of a live instance using the machine and process IDs of the configuration.
+    /// Instances whose last heartbeat is more than [`INSTANCE_TIMEOUT`] seconds before `now` are considered dead.
+    ///
+    /// ## Returns
+    ///
+    /// The UNIX timestamp of the last heartbeat, or `None` if no live instance uses the IDs.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn last_heartbeat(
+        db: &Database,
+        config: &Config,
+        now: DateTime<Utc>,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let record = sqlx::query!(
+            "SELECT last_heartbeat FROM instances
+            WHERE machine_id = $1 AND process_id = $2 AND last_heartbeat >= $3",
+            config.machine_id(),
+            config.process_id(),
+            now.timestamp() - INSTANCE_TIMEOUT,
+        )
+        .fetch_optional(db.executor())
+        .await?;
+
+        Ok(record.map(|r| r.last_heartbeat))
+    }
+
+    /// Keep the claim of this instance alive, recording `now` as its last heartbeat.
+    ///
+    /// ## Returns
+    ///
+    /// `false` if the claim was lost to another instance, `true` otherwise.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn heartbeat(&self, db: &Database, config: &Config, now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE instances SET last_heartbeat = $4
+            WHERE machine_id = $1 AND process_id = $2 AND instance_id = $3",
+            config.machine_id(),
+            config.process_id(),
+            self.id,
+            now.timestamp(),
+        )
+        .execute(db.executor())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Release the claim of this instance, so that the IDs can be reused immediately.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn release(&self, db: &Database) -> Result<(), sqlx::Error> {
+        sqlx::query!("DELETE FROM instances WHERE instance_id = $1", self.id)
+            .execute(db.executor())
+            .await?;
+        Ok(())
+    }
+}
+
+impl Default for InstanceLease {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::{
+    guild::{Guild, GuildRecord},
+    snowflake::Snowflake,
+    user::User,
+};
+
+/// Represents a guild invite record with associated guild data as queried.
+pub struct ExtendedGuildInviteRecord {
+    pub guild_id: Snowflake<Guild>,
+    pub user_id: Snowflake<User>,
+    pub inviter_id: Option<i64>,
+    pub created_at: i64,
+    pub name: String,
+    pub owner_id: Snowflake<User>,
+    pub avatar_hash: Option<String>,
+    pub min_account_age: Option<i32>,
+    pub invite_only: bool,
+    pub welcome_message: Option<String>,
+    pub raid_mode_until: Option<i64>,
+    pub locale: String,
+    pub timezone: String,
+    pub version: i32,
+}
+
+/// A pending invitation for a user to join a guild.
+///
+/// Invites do not add the user to the guild, the user has to accept them by joining the guild.
+#[derive(Serialize, Debug, Clone)]
+pub struct GuildInvite {
+    /// The guild the user was invited to.
+    guild: Guild,
+    /// The user that was invited.
+    user_id: Snowflake<User>,
+    /// The user that created the invite, if they still exist.
+    inviter_id: Option<Snowflake<User>>,
+    /// UNIX timestamp of when the invite was created.
+    created_at: i64,
+}
+
+impl GuildInvite {
+    /// Create a new invite for the given user to join the given guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the user is invited to.
+    /// * `user` - The user that is invited.
+    /// * `inviter` - The user that created the invite.
+    /// * `now` - The time the invite is created at.
+    pub fn new(
+        guild: Guild,
+        user: impl Into<Snowflake<User>>,
+        inviter: impl Into<Snowflake<User>>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            guild,
+            user_id: user.into(),
+            inviter_id: Some(inviter.into()),
+            created_at: now.timestamp(),
+        }
+    }
+
+    /// Build an invite object directly from a database record.
+    pub fn from_extended_record(record: ExtendedGuildInviteRecord) -> Self {
+        let guild = Guild::from_record(GuildRecord {
+            id: record.guild_id,
+            name: record.name,
+            owner_id: record.owner_id,
+            avatar_hash: record.avatar_hash,
+            min_account_age: record.min_account_age,
+            invite_only: record.invite_only,
+            welcome_message: record.welcome_message,
+            raid_mode_until: record.raid_mode_until,
+            locale: record.locale,
+            timezone: record.timezone,
+            version: record.version,
+        });
+
+        Self {
+            guild,
+            user_id: record.user_id,
+            inviter_id: record.inviter_id.map(Snowflake::new),
+            created_at: record.created_at,
+        }
+    }
+
+    /// The guild the user was invited to.
+    pub const fn guild(&self) -> &Guild {
+        &self.guild
+    }
+
+    /// The user that was invited.
+    pub const fn user_id(&self) -> Snowflake<User> {
+        self.user_id
+    }
+
+    /// The user that created the invite, if they still exist.
+    pub const fn inviter_id(&self) -> Option<Snowflake<User>> {
+        self.inviter_id
+    }
+
+    /// UNIX timestamp of when the invite was created.
+    pub const fn created_at(&self) -> i64 {
+        self.created_at
+    }
+}
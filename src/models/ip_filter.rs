@@ -0,0 +1,128 @@
+use std::{net::IpAddr, str::FromStr};
+
+use ipnet::IpNet;
+
+use super::state::Config;
+
+/// Restricts which client addresses may use a sensitive group of routes.
+///
+/// Networks are given in CIDR notation, single addresses are treated as networks containing only that address.
+/// Addresses in a denied network are always rejected. If any networks are allowed, addresses outside of them
+/// are rejected as well.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpFilter {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl IpFilter {
+    /// Create a new filter from lists of networks.
+    ///
+    /// ## Arguments
+    ///
+    /// * `allow` - The networks clients must connect from. If empty, all networks not denied are allowed.
+    /// * `deny` - The networks clients may not connect from.
+    ///
+    /// ## Errors
+    ///
+    /// * [`ipnet::AddrParseError`] - If an entry is neither a network in CIDR notation nor an address.
+    pub fn new<S: AsRef<str>>(allow: &[S], deny: &[S]) -> Result<Self, ipnet::AddrParseError> {
+        Ok(Self {
+            allow: parse_networks(allow)?,
+            deny: parse_networks(deny)?,
+        })
+    }
+
+    /// Whether the filter restricts any addresses.
+    pub const fn is_enabled(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    /// Whether a client connecting from the given address passes the filter.
+    /// IPv4 addresses mapped to IPv6 are matched as IPv4 addresses.
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+
+        if self.deny.iter().any(|net| net.contains(&ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Parse networks in CIDR notation, or single addresses.
+fn parse_networks<S: AsRef<str>>(entries: &[S]) -> Result<Vec<IpNet>, ipnet::AddrParseError> {
+    entries
+        .iter()
+        .map(|entry| {
+            let entry = entry.as_ref();
+            IpAddr::from_str(entry).map_or_else(|_| IpNet::from_str(entry), |ip| Ok(IpNet::from(ip)))
+        })
+        .collect()
+}
+
+/// A sensitive group of routes that operators may restrict to some client addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpFilterScope {
+    /// The admin endpoints, configured with `ADMIN_ALLOWED_IPS` and `ADMIN_DENIED_IPS`.
+    Admin,
+    /// The webhook endpoints of guilds, configured with `WEBHOOK_ALLOWED_IPS` and `WEBHOOK_DENIED_IPS`.
+    Webhooks,
+}
+
+impl IpFilterScope {
+    /// The name of the scope, as used in logs.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::Webhooks => "webhooks",
+        }
+    }
+
+    /// The filter configured for the scope.
+    pub const fn filter(self, config: &Config) -> &IpFilter {
+        match self {
+            Self::Admin => config.admin_ip_filter(),
+            Self::Webhooks => config.webhook_ip_filter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::IpFilter;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().expect("Should be a valid address")
+    }
+
+    #[test]
+    fn test_permits() {
+        let open = IpFilter::default();
+        assert!(!open.is_enabled());
+        assert!(open.permits(ip("203.0.113.7")));
+
+        let filter = IpFilter::new(&["10.0.0.0/8", "2001:db8::/32"], &["10.0.0.5", "10.1.0.0/16"])
+            .expect("Networks should be valid");
+        assert!(filter.is_enabled());
+        assert!(filter.permits(ip("10.0.0.4")));
+        assert!(filter.permits(ip("2001:db8::1")));
+        assert!(filter.permits(ip("::ffff:10.0.0.4")));
+        assert!(!filter.permits(ip("10.0.0.5")));
+        assert!(!filter.permits(ip("10.1.2.3")));
+        assert!(!filter.permits(ip("::ffff:10.1.2.3")));
+        assert!(!filter.permits(ip("203.0.113.7")));
+
+        let deny_only = IpFilter::new(&[], &["203.0.113.0/24"]).expect("Networks should be valid");
+        assert!(deny_only.permits(ip("198.51.100.1")));
+        assert!(!deny_only.permits(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn test_invalid_networks() {
+        assert!(IpFilter::new(&["10.0.0.0/33"], &[]).is_err());
+        assert!(IpFilter::new(&[], &["localhost"]).is_err());
+    }
+}
@@ -0,0 +1,1039 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use chrono::TimeDelta;
+use tokio::{
+    task::{AbortHandle, JoinHandle},
+    time::MissedTickBehavior,
+};
+use tracing::Instrument;
+
+use super::{
+    analytics::{self, SECONDS_PER_DAY},
+    attachment::{generate_key_token, Attachment, AttachmentLike, FullAttachment, PartialAttachment, ScanStatus},
+    channel::ChannelLike,
+    content::MediaLink,
+    emoji,
+    errors::AppError,
+    event_bus::EventBus,
+    gateway_event::{GatewayEvent, MemberImportProgressPayload},
+    guild::Guild,
+    invite::GuildInvite,
+    limits::Limit,
+    media_metadata::MediaMetadata,
+    message::Message,
+    metrics,
+    scanner::ScanVerdict,
+    snowflake::Snowflake,
+    state::{ApplicationState, Config},
+    user::User,
+};
+
+/// How often expired member timeouts are cleared.
+const EXPIRE_TIMEOUTS_INTERVAL: Duration = Duration::from_secs(30);
+/// How often guests whose membership expired are removed from their guilds.
+/// Expired guests are denied access immediately, this removes their membership.
+const EXPIRE_GUESTS_INTERVAL: Duration = Duration::from_secs(30);
+/// How often setting overrides are reloaded, so changes made through other instances are picked up.
+const REFRESH_SETTINGS_INTERVAL: Duration = Duration::from_secs(30);
+/// How often expired strikes are deleted. Expired strikes stop counting immediately, this only frees up storage.
+const PRUNE_STRIKES_INTERVAL: Duration = Duration::from_mins(10);
+/// How often changes to messages older than their retention period are deleted.
+const PRUNE_MESSAGE_CHANGES_INTERVAL: Duration = Duration::from_hours(1);
+/// How often expired announcements are deleted.
+const PRUNE_ANNOUNCEMENTS_INTERVAL: Duration = Duration::from_hours(1);
+/// How often gateway sessions of instances that stopped are deleted.
+const PRUNE_GATEWAY_SESSIONS_INTERVAL: Duration = Duration::from_mins(1);
+/// How often deleted guilds whose grace period has passed are purged.
+const PURGE_GUILDS_INTERVAL: Duration = Duration::from_mins(10);
+/// How often the outbox is checked for undelivered events if no new events were committed.
+const OUTBOX_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often delivered events are pruned from the outbox.
+const PRUNE_OUTBOX_INTERVAL: Duration = Duration::from_mins(10);
+/// How often old messages are pruned from the event bus.
+const PRUNE_EVENT_BUS_INTERVAL: Duration = Duration::from_mins(1);
+/// How long to wait before listening to the event bus again after the connection was lost.
+const EVENT_BUS_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// How often the delivery log is checked for webhook deliveries that are due for a retry.
+const WEBHOOK_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the push notification queue is checked for notifications that are due for a retry.
+const PUSH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the crosspost queue is checked for crossposts that are due for a retry.
+const CROSSPOST_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How often channel feeds are checked for feeds that are due to be polled.
+const POLL_FEEDS_INTERVAL: Duration = Duration::from_secs(30);
+/// How often old webhook deliveries are pruned from the delivery log.
+const PRUNE_WEBHOOK_DELIVERIES_INTERVAL: Duration = Duration::from_hours(1);
+/// How often the message statistics of guilds are recomputed for today and yesterday.
+const AGGREGATE_GUILD_STATS_INTERVAL: Duration = Duration::from_mins(10);
+/// How often messages older than the retention period of their channel are deleted.
+const SWEEP_RETENTION_INTERVAL: Duration = Duration::from_mins(10);
+/// The maximum amount of messages deleted from a channel at once by the retention sweeper.
+const RETENTION_BATCH_SIZE: i64 = 100;
+/// How often the search index queue is checked for changes if no new changes were committed.
+const SEARCH_INDEX_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// How long to wait between two batches of the search index backfill, so it does not starve other queries.
+const SEARCH_BACKFILL_DELAY: Duration = Duration::from_millis(100);
+/// How long to wait before retrying the search index backfill after a batch failed.
+const SEARCH_BACKFILL_RETRY_DELAY: Duration = Duration::from_secs(10);
+/// How many times a batch of events is sent to the firehose before it is dropped.
+const FIREHOSE_ATTEMPTS: u32 = 3;
+/// How long to wait before sending a batch of events to the firehose again, multiplied by the attempt.
+const FIREHOSE_RETRY_DELAY: Duration = Duration::from_secs(2);
+/// How often this instance renews its claim on its machine and process IDs.
+const INSTANCE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// How often attachments stuck pending a malware scan, such as after a restart, are scanned again.
+const RESCAN_ATTACHMENTS_INTERVAL: Duration = Duration::from_mins(5);
+/// How long an attachment must have been pending a scan before it is scanned again.
+const RESCAN_ATTACHMENTS_GRACE_PERIOD: TimeDelta = TimeDelta::minutes(5);
+/// The maximum amount of messages whose attachments are scanned again at once.
+const RESCAN_ATTACHMENTS_BATCH_SIZE: i64 = 50;
+/// How often attachments stored under a key without a key token are moved to a new key.
+const MIGRATE_ATTACHMENT_KEYS_INTERVAL: Duration = Duration::from_mins(1);
+/// The maximum amount of attachments moved to a new key at once.
+const MIGRATE_ATTACHMENT_KEYS_BATCH_SIZE: i64 = 50;
+/// How often S3 is probed for recovery while it is unavailable.
+const PROBE_STORAGE_INTERVAL: Duration = Duration::from_secs(10);
+/// How many usernames are processed between two progress reports of a member import.
+const IMPORT_PROGRESS_INTERVAL: usize = 50;
+/// The maximum number of links re-hosted as attachments per message.
+const MAX_REHOSTED_MEDIA_PER_MESSAGE: usize = 4;
+
+/// Runs periodic maintenance jobs and one-off jobs in the background.
+#[derive(Debug, Clone, Default)]
+pub struct JobRunner {
+    handles: Arc<Mutex<Vec<AbortHandle>>>,
+    app: Weak<ApplicationState>,
+}
+
+impl JobRunner {
+    /// Create a new job runner. No jobs are running until [`JobRunner::start`] is called.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind_to(&mut self, app: Weak<ApplicationState>) {
+        self.app = app;
+    }
+
+    /// Start all periodic jobs.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration, used to determine job intervals.
+    pub fn start(&self, config: &Config) {
+        self.schedule("expire_timeouts", EXPIRE_TIMEOUTS_INTERVAL, expire_timeouts);
+        self.schedule("expire_guests", EXPIRE_GUESTS_INTERVAL, expire_guests);
+        self.schedule("refresh_settings", REFRESH_SETTINGS_INTERVAL, refresh_settings);
+        self.schedule("prune_strikes", PRUNE_STRIKES_INTERVAL, prune_strikes);
+        self.schedule("prune_announcements", PRUNE_ANNOUNCEMENTS_INTERVAL, prune_announcements);
+        self.schedule(
+            "prune_message_changes",
+            PRUNE_MESSAGE_CHANGES_INTERVAL,
+            prune_message_changes,
+        );
+        self.schedule(
+            "prune_gateway_sessions",
+            PRUNE_GATEWAY_SESSIONS_INTERVAL,
+            prune_gateway_sessions,
+        );
+        self.schedule("purge_deleted_guilds", PURGE_GUILDS_INTERVAL, purge_deleted_guilds);
+        self.schedule(
+            "database_health",
+            config.database_health_check_interval(),
+            check_database_health,
+        );
+        self.schedule("probe_storage", PROBE_STORAGE_INTERVAL, probe_storage);
+        self.schedule("instance_heartbeat", INSTANCE_HEARTBEAT_INTERVAL, instance_heartbeat);
+        self.schedule("prune_outbox", PRUNE_OUTBOX_INTERVAL, prune_outbox);
+        self.schedule("sweep_retention", SWEEP_RETENTION_INTERVAL, sweep_retention);
+        self.schedule("poll_feeds", POLL_FEEDS_INTERVAL, poll_feeds);
+        self.schedule(
+            "aggregate_guild_stats",
+            AGGREGATE_GUILD_STATS_INTERVAL,
+            aggregate_guild_stats,
+        );
+        self.schedule(
+            "prune_webhook_deliveries",
+            PRUNE_WEBHOOK_DELIVERIES_INTERVAL,
+            prune_webhook_deliveries,
+        );
+        self.schedule(
+            "migrate_attachment_keys",
+            MIGRATE_ATTACHMENT_KEYS_INTERVAL,
+            migrate_attachment_keys,
+        );
+        if config.clamav_addr().is_some() {
+            self.schedule(
+                "rescan_pending_attachments",
+                RESCAN_ATTACHMENTS_INTERVAL,
+                rescan_pending_attachments,
+            );
+        }
+        self.track(&tokio::spawn(dispatch_outbox(self.app.clone())));
+        self.track(&tokio::spawn(deliver_webhooks(self.app.clone())));
+        self.track(&tokio::spawn(deliver_push_notifications(self.app.clone())));
+        self.track(&tokio::spawn(crosspost_messages(self.app.clone())));
+        self.track(&tokio::spawn(index_messages(self.app.clone())));
+        self.track(&tokio::spawn(backfill_search_index(self.app.clone())));
+        self.track(&tokio::spawn(forward_firehose(self.app.clone())));
+
+        if config.gateway_shard().is_sharded() {
+            self.schedule("prune_event_bus", PRUNE_EVENT_BUS_INTERVAL, prune_event_bus);
+            self.track(&tokio::spawn(publish_bus_messages(self.app.clone())));
+            self.track(&tokio::spawn(receive_bus_messages(self.app.clone())));
+        }
+    }
+
+    /// Schedule a job to run periodically until the runner is closed or the application is dropped.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name of the job, used for logging.
+    /// * `period` - The time between two runs of the job.
+    /// * `job` - The job to run.
+    pub fn schedule<F, Fut>(&self, name: &'static str, period: Duration, job: F)
+    where
+        F: Fn(Arc<ApplicationState>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), AppError>> + Send,
+    {
+        let app = self.app.clone();
+
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+                let Some(app) = app.upgrade() else {
+                    break;
+                };
+                // Every run is traced on its own, as runs are not caused by a request
+                let span = tracing::info_span!(parent: None, "job", job = name);
+                if let Err(e) = job(app).instrument(span).await {
+                    tracing::error!(job = name, error = %e, "Background job failed");
+                }
+            }
+        });
+
+        self.track(&handle);
+    }
+
+    /// Keep track of a long-running task, so it is stopped when the runner is closed.
+    fn track<T>(&self, handle: &JoinHandle<T>) {
+        self.handles
+            .lock()
+            .expect("Job runner lock poisoned")
+            .push(handle.abort_handle());
+    }
+
+    /// Run a one-off job in the background. Errors are logged.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name of the job, used for logging.
+    /// * `job` - The job to run.
+    pub fn spawn<Fut>(&self, name: &'static str, job: Fut)
+    where
+        Fut: Future<Output = Result<(), AppError>> + Send + 'static,
+    {
+        // The job continues the trace of whatever spawned it, such as a request
+        let span = tracing::info_span!("job", job = name);
+        tokio::spawn(
+            async move {
+                if let Err(e) = job.await {
+                    tracing::error!(job = name, error = %e, "Background job failed");
+                }
+            }
+            .instrument(span),
+        );
+    }
+
+    /// Run a one-off job that needs the application state in the background. Errors are logged.
+    /// The job is skipped if the application is shutting down.
+    ///
+    /// ## Arguments
+    ///
+    /// * `name` - The name of the job, used for logging.
+    /// * `job` - The job to run.
+    pub fn spawn_with<F, Fut>(&self, name: &'static str, job: F)
+    where
+        F: FnOnce(Arc<ApplicationState>) -> Fut,
+        Fut: Future<Output = Result<(), AppError>> + Send + 'static,
+    {
+        if let Some(app) = self.app.upgrade() {
+            self.spawn(name, job(app));
+        }
+    }
+
+    /// Stop all running jobs.
+    pub fn close(&self) {
+        for handle in self.handles.lock().expect("Job runner lock poisoned").drain(..) {
+            handle.abort();
+        }
+    }
+}
+
+/// Clear expired member timeouts. Clients are notified through the outbox.
+async fn expire_timeouts(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    app.ops().clear_expired_timeouts().await?;
+    Ok(())
+}
+
+/// Remove guests whose membership expired from their guilds.
+async fn expire_guests(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    let removed = app.members().expire_guests().await?;
+    if removed > 0 {
+        tracing::debug!(removed, "Removed expired guests");
+    }
+    Ok(())
+}
+
+/// Delete strikes that have expired.
+async fn prune_strikes(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    let deleted = app.ops().delete_expired_strikes().await?;
+    if deleted > 0 {
+        tracing::debug!(deleted, "Deleted expired strikes");
+    }
+    Ok(())
+}
+
+/// Delete announcements that have expired. They are no longer included in `READY` either way.
+async fn prune_announcements(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    let deleted = app.ops().delete_expired_announcements().await?;
+    if deleted > 0 {
+        tracing::debug!(deleted, "Deleted expired announcements");
+    }
+    Ok(())
+}
+
+/// Reload the setting overrides from the database.
+async fn refresh_settings(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    app.refresh_settings().await
+}
+
+/// Delete changes to messages that are older than the retention period, they can no longer be fetched.
+async fn prune_message_changes(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    let deleted = app.ops().delete_old_message_changes().await?;
+    if deleted > 0 {
+        tracing::debug!(deleted, "Deleted old message changes");
+    }
+    Ok(())
+}
+
+/// Delete the gateway sessions of instances that stopped without ending them, so they are no longer listed.
+async fn prune_gateway_sessions(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    let deleted = app.ops().delete_orphaned_gateway_sessions().await?;
+    if deleted > 0 {
+        tracing::debug!(deleted, "Deleted orphaned gateway sessions");
+    }
+    Ok(())
+}
+
+/// Dispatch events from the outbox whenever new events are committed.
+/// The outbox is also checked periodically, to deliver events left over from a crash.
+async fn dispatch_outbox(app: Weak<ApplicationState>) {
+    loop {
+        let Some(app) = app.upgrade() else {
+            break;
+        };
+
+        if let Err(e) = app.outbox.flush().await {
+            tracing::error!(job = "dispatch_outbox", error = %e, "Background job failed");
+        }
+
+        // Do not keep the application alive while waiting
+        let outbox = app.outbox.clone();
+        drop(app);
+
+        tokio::select! {
+            () = outbox.notified() => {}
+            () = tokio::time::sleep(OUTBOX_POLL_INTERVAL) => {}
+        }
+    }
+}
+
+/// Publish messages queued on the event bus, one at a time so they are received in order.
+async fn publish_bus_messages(app: Weak<ApplicationState>) {
+    let Some(mut queue) = app.upgrade().and_then(|app| app.bus.take_queue()) else {
+        return;
+    };
+
+    while let Some(message) = queue.recv().await {
+        let Some(app) = app.upgrade() else {
+            break;
+        };
+
+        if let Err(e) = EventBus::send(app.db.executor(), &message, app.clock.now()).await {
+            tracing::error!(job = "publish_bus_messages", error = %e, "Background job failed");
+        }
+        app.bus.mark_published();
+    }
+}
+
+/// Apply messages announced on the event bus to the connections of this process.
+async fn receive_bus_messages(app: Weak<ApplicationState>) {
+    let listener = match app.upgrade() {
+        Some(app) => app.bus.listen().await,
+        None => return,
+    };
+    let mut listener = match listener {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!(job = "receive_bus_messages", error = %e, "Failed to listen to the event bus");
+            return;
+        }
+    };
+
+    loop {
+        // The listener reconnects by itself, messages announced while disconnected are lost
+        let notification = match listener.recv().await {
+            Ok(notification) => notification,
+            Err(e) => {
+                tracing::warn!(job = "receive_bus_messages", error = %e, "Lost connection to the event bus");
+                tokio::time::sleep(EVENT_BUS_RETRY_DELAY).await;
+                continue;
+            }
+        };
+
+        let Some(app) = app.upgrade() else {
+            break;
+        };
+
+        let Ok(id) = notification.payload().parse::<i64>() else {
+            tracing::warn!(payload = notification.payload(), "Invalid event bus notification");
+            continue;
+        };
+
+        if let Err(e) = app.bus.receive(id).await {
+            tracing::error!(job = "receive_bus_messages", error = %e, "Background job failed");
+        }
+    }
+}
+
+/// Deliver events to webhooks whenever new events are queued.
+/// The delivery log is also checked periodically, to retry failed deliveries.
+async fn deliver_webhooks(app: Weak<ApplicationState>) {
+    loop {
+        let Some(app) = app.upgrade() else {
+            break;
+        };
+
+        if let Err(e) = app.webhooks.deliver_pending().await {
+            tracing::error!(job = "deliver_webhooks", error = %e, "Background job failed");
+        }
+
+        // Do not keep the application alive while waiting
+        let webhooks = app.webhooks.clone();
+        drop(app);
+
+        tokio::select! {
+            () = webhooks.notified() => {}
+            () = tokio::time::sleep(WEBHOOK_POLL_INTERVAL) => {}
+        }
+    }
+}
+
+/// Deliver push notifications whenever new notifications are queued.
+/// The queue is also checked periodically, to retry failed deliveries.
+async fn deliver_push_notifications(app: Weak<ApplicationState>) {
+    loop {
+        let Some(app) = app.upgrade() else {
+            break;
+        };
+
+        if let Err(e) = app.push.deliver_pending().await {
+            tracing::error!(job = "deliver_push_notifications", error = %e, "Background job failed");
+        }
+
+        // Do not keep the application alive while waiting
+        let push = app.push.clone();
+        drop(app);
+
+        tokio::select! {
+            () = push.notified() => {}
+            () = tokio::time::sleep(PUSH_POLL_INTERVAL) => {}
+        }
+    }
+}
+
+/// Crosspost messages to the channels following their channel whenever new messages are queued.
+/// The queue is also checked periodically, to retry failed crossposts.
+async fn crosspost_messages(app: Weak<ApplicationState>) {
+    loop {
+        let Some(app) = app.upgrade() else {
+            break;
+        };
+
+        if let Err(e) = app.crossposts.process_queue().await {
+            tracing::error!(job = "crosspost_messages", error = %e, "Background job failed");
+        }
+
+        // Do not keep the application alive while waiting
+        let crossposts = app.crossposts.clone();
+        drop(app);
+
+        tokio::select! {
+            () = crossposts.notified() => {}
+            () = tokio::time::sleep(CROSSPOST_POLL_INTERVAL) => {}
+        }
+    }
+}
+
+/// Index messages in the search index whenever changes to messages are committed.
+/// The queue is also checked periodically, to index changes left over from a crash.
+async fn index_messages(app: Weak<ApplicationState>) {
+    loop {
+        let Some(app) = app.upgrade() else {
+            break;
+        };
+
+        if let Err(e) = app.search.process_queue().await {
+            tracing::error!(job = "index_messages", error = %e, "Background job failed");
+        }
+
+        // Do not keep the application alive while waiting
+        let search = app.search.clone();
+        drop(app);
+
+        tokio::select! {
+            () = search.notified() => {}
+            () = tokio::time::sleep(SEARCH_INDEX_POLL_INTERVAL) => {}
+        }
+    }
+}
+
+/// Send the events queued for the firehose to its sink in batches.
+/// A batch is sent once it is full, or once its oldest event waited for the flush interval.
+/// Only one batch is sent at a time, so a slow sink fills up the queue instead of piling up requests.
+async fn forward_firehose(app: Weak<ApplicationState>) {
+    let Some((firehose, mut queue, batch_size, flush_interval)) = app.upgrade().and_then(|app| {
+        let queue = app.firehose.take_queue()?;
+        Some((
+            app.firehose.clone(),
+            queue,
+            app.config.firehose_batch_size(),
+            app.config.firehose_flush_interval(),
+        ))
+    }) else {
+        return;
+    };
+    let mut batch = Vec::with_capacity(batch_size);
+
+    // The queue is closed once the application is dropped
+    while queue.recv_many(&mut batch, batch_size).await > 0 {
+        let deadline = tokio::time::Instant::now() + flush_interval;
+        while batch.len() < batch_size {
+            let limit = batch_size - batch.len();
+            match tokio::time::timeout_at(deadline, queue.recv_many(&mut batch, limit)).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+        }
+
+        let records = firehose.records(&batch);
+        for attempt in 1..=FIREHOSE_ATTEMPTS {
+            match firehose.send(&records).await {
+                Ok(()) => break,
+                Err(e) if attempt < FIREHOSE_ATTEMPTS => {
+                    tracing::warn!(job = "forward_firehose", error = %e, attempt, "Failed to send firehose batch");
+                    tokio::time::sleep(FIREHOSE_RETRY_DELAY * attempt).await;
+                }
+                Err(e) => {
+                    tracing::error!(job = "forward_firehose", error = %e, events = records.len(), "Dropping firehose batch");
+                    metrics::FIREHOSE_DROPPED_EVENTS.inc_by(records.len() as u64);
+                }
+            }
+        }
+        batch.clear();
+    }
+}
+
+/// Index the messages that existed before the search index, one batch at a time, until all of them are indexed.
+async fn backfill_search_index(app: Weak<ApplicationState>) {
+    loop {
+        let Some(app) = app.upgrade() else {
+            break;
+        };
+
+        let delay = match app.search.backfill().await {
+            Ok(None) => break,
+            Ok(Some(progress)) if progress.done => {
+                tracing::info!(indexed = progress.indexed, "Search index backfill complete");
+                break;
+            }
+            Ok(Some(progress)) => {
+                tracing::debug!(
+                    indexed = progress.indexed,
+                    cursor = %progress.cursor,
+                    "Search index backfill progress"
+                );
+                SEARCH_BACKFILL_DELAY
+            }
+            Err(e) => {
+                tracing::error!(job = "backfill_search_index", error = %e, "Background job failed");
+                SEARCH_BACKFILL_RETRY_DELAY
+            }
+        };
+
+        drop(app);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Permanently delete guilds whose deletion grace period has passed, along with their attachments.
+async fn purge_deleted_guilds(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    let purged = app.guilds().purge_deleted().await?;
+    if purged > 0 {
+        tracing::info!(purged, "Purged deleted guilds");
+    }
+    Ok(())
+}
+
+/// Remove old finished deliveries from the webhook delivery log.
+async fn prune_webhook_deliveries(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    app.webhooks.prune().await?;
+    Ok(())
+}
+
+/// Poll the channel feeds that are due, posting their new entries.
+async fn poll_feeds(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    let polled = app.feeds.poll_due().await?;
+    if polled > 0 {
+        tracing::debug!(polled, "Polled channel feeds");
+    }
+    Ok(())
+}
+
+/// Recompute the message statistics of guilds from the messages sent today.
+/// Yesterday is recomputed as well, so messages sent shortly before midnight are counted.
+async fn aggregate_guild_stats(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    let today = analytics::day_start(app.clock.now());
+    let mut updated = app.ops().aggregate_guild_stats(today - SECONDS_PER_DAY).await?;
+    updated += app.ops().aggregate_guild_stats(today).await?;
+    if updated > 0 {
+        tracing::debug!(updated, "Aggregated guild statistics");
+    }
+    Ok(())
+}
+
+/// Remove old delivered events from the outbox.
+async fn prune_outbox(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    app.outbox.prune().await?;
+    Ok(())
+}
+
+/// Remove old messages from the event bus.
+async fn prune_event_bus(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    app.bus.prune().await?;
+    Ok(())
+}
+
+/// Delete messages that are older than the retention period of their channel.
+/// Messages are deleted in batches, clients are notified of every batch through the outbox.
+async fn sweep_retention(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    for channel in app.ops().fetch_channels_with_retention().await? {
+        let Some(days) = channel.retention_days() else {
+            continue;
+        };
+        let cutoff = Snowflake::from_timestamp((app.clock.now() - TimeDelta::days(days.into())).timestamp_millis());
+
+        let batch_size = usize::try_from(RETENTION_BATCH_SIZE).expect("Batch size should fit into usize");
+
+        while app
+            .ops()
+            .delete_messages_before(&channel, cutoff, RETENTION_BATCH_SIZE)
+            .await?
+            >= batch_size
+        {}
+    }
+    Ok(())
+}
+
+/// Check whether the database is reachable, so requests can fail fast during an outage.
+async fn check_database_health(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    app.db.check_health(app.config.database_acquire_timeout()).await;
+    Ok(())
+}
+
+/// Check whether S3 has recovered from an outage. Does nothing while S3 is available.
+async fn probe_storage(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    if !app.s3.is_available() {
+        app.s3.probe().await;
+    }
+    Ok(())
+}
+
+/// Renew the claim of this instance on its machine and process IDs.
+async fn instance_heartbeat(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    if !app.db.is_healthy() {
+        return Ok(());
+    }
+
+    if !app.instance.heartbeat(&app.db, &app.config, app.clock.now()).await? {
+        tracing::error!(
+            machine_id = app.config.machine_id(),
+            process_id = app.config.process_id(),
+            "Another instance claimed the machine and process IDs of this instance, snowflake IDs may collide"
+        );
+    }
+    Ok(())
+}
+
+/// Add the emojis used in a message to the emoji usage statistics of its guild.
+///
+/// ## Arguments
+///
+/// * `app` - The application state.
+/// * `guild` - The guild the message was sent in.
+/// * `content` - The content of the message.
+///
+/// ## Errors
+///
+/// * [`AppError::Database`] - If the database query fails.
+pub async fn record_emoji_usage(
+    app: Arc<ApplicationState>,
+    guild: Snowflake<Guild>,
+    content: String,
+) -> Result<(), AppError> {
+    let usage = emoji::count_emojis(&content);
+
+    if !usage.is_empty() {
+        app.ops().record_emoji_usage(guild, &usage).await?;
+    }
+    Ok(())
+}
+
+/// Extract the rendering metadata of the image, video and audio attachments of a newly sent message.
+///
+/// ## Arguments
+///
+/// * `app` - The application state.
+/// * `message` - The message, with the contents of its attachments still loaded.
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::MessageUpdate`] - To all members who can view the channel, if any metadata was extracted
+///
+/// ## Errors
+///
+/// * [`AppError::Database`] - If the database query fails.
+pub async fn extract_attachment_metadata(app: Arc<ApplicationState>, mut message: Message) -> Result<(), AppError> {
+    let mut extracted = false;
+
+    for attachment in message.attachments_mut() {
+        let Attachment::Full(attachment) = attachment else {
+            continue;
+        };
+        let mime = attachment.mime();
+        // Files that may be malicious are never decoded
+        if attachment.scan_status() != ScanStatus::Clean || !MediaMetadata::is_supported(&mime) {
+            continue;
+        }
+
+        let content = attachment.content().clone();
+        let metadata = tokio::task::spawn_blocking(move || MediaMetadata::extract(&content, &mime))
+            .await
+            .expect("Failed to join metadata extraction task");
+
+        if !metadata.is_empty() {
+            attachment.set_metadata(metadata);
+            extracted = true;
+        }
+    }
+
+    if extracted {
+        app.ops().update_attachment_metadata(&message).await?;
+    }
+    Ok(())
+}
+
+/// Scan the attachments of a message for malware, then extract the metadata of the clean ones.
+/// The contents of infected attachments are removed from storage.
+///
+/// ## Arguments
+///
+/// * `app` - The application state.
+/// * `message` - The message, with the contents of the attachments to scan loaded.
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::MessageUpdate`] - To all members who can view the channel, once the attachments are scanned
+///
+/// ## Errors
+///
+/// * [`AppError::Database`] - If the database query fails.
+/// * [`AppError::S3`] - If the contents of an infected attachment could not be removed.
+pub async fn scan_attachments(app: Arc<ApplicationState>, mut message: Message) -> Result<(), AppError> {
+    for attachment in message.attachments_mut() {
+        let Attachment::Full(attachment) = attachment else {
+            continue;
+        };
+
+        let status = match app.scanner.scan(attachment.content()).await {
+            Ok(ScanVerdict::Clean) => ScanStatus::Clean,
+            Ok(ScanVerdict::Infected(signature)) => {
+                tracing::warn!(
+                    message_id = %attachment.message_id(),
+                    attachment_id = attachment.id(),
+                    %signature,
+                    "Removing infected attachment"
+                );
+                ScanStatus::Infected
+            }
+            Err(e) => {
+                tracing::error!(
+                    error = %e,
+                    message_id = %attachment.message_id(),
+                    attachment_id = attachment.id(),
+                    "Failed to scan attachment"
+                );
+                ScanStatus::Failed
+            }
+        };
+        attachment.set_scan_status(status);
+    }
+
+    // The status is committed first, so infected attachments stop being served before they are removed
+    app.ops().update_attachment_scan_status(&message).await?;
+
+    for attachment in message.attachments() {
+        if let Attachment::Full(attachment) = attachment {
+            if attachment.scan_status() == ScanStatus::Infected {
+                attachment.delete(&app.s3).await?;
+            }
+        }
+    }
+
+    extract_attachment_metadata(app, message).await
+}
+
+/// Re-host the media linked in a newly sent message as attachments, replacing the links with references to them,
+/// then scan the attachments of the message and extract their metadata.
+///
+/// Links whose media cannot be downloaded, or that exceed the free attachment IDs of the message, are left as they are.
+///
+/// ## Arguments
+///
+/// * `app` - The application state.
+/// * `message` - The message, with the contents of its attachments still loaded.
+/// * `links` - The media links in the content of the message, in order of occurrence.
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::MessageUpdate`] - To all members who can view the channel, once the media is re-hosted,
+///   and once the attachments are scanned or their metadata is extracted
+///
+/// ## Errors
+///
+/// * [`AppError::Database`] - If the database query fails.
+/// * [`AppError::S3`] - If the S3 request to upload one of the attachments fails.
+pub async fn rehost_media(
+    app: Arc<ApplicationState>,
+    mut message: Message,
+    links: Vec<MediaLink>,
+) -> Result<(), AppError> {
+    let max_size = app.settings.limits().max(Limit::MaxAttachmentSize);
+    let free_ids: Vec<u8> = (0..10)
+        .filter(|id| !message.attachments().iter().any(|a| a.id() == *id))
+        .collect();
+    let mut rehosted: Vec<(MediaLink, FullAttachment)> = Vec::new();
+
+    for link in links.into_iter().take(MAX_REHOSTED_MEDIA_PER_MESSAGE) {
+        let Some(&id) = free_ids.get(rehosted.len()) else {
+            break;
+        };
+
+        let media = match app.media_proxy.download(&link.url, max_size).await {
+            Ok(media) => media,
+            Err(e) => {
+                tracing::debug!(error = %e, url = %link.url, "Failed to re-host linked media");
+                continue;
+            }
+        };
+
+        let filename = link
+            .url
+            .path_segments()
+            .and_then(Iterator::last)
+            .filter(|name| !name.is_empty())
+            .unwrap_or("media")
+            .to_string();
+        let mut attachment = FullAttachment::new(
+            id,
+            filename,
+            media.content().clone(),
+            media.content_type().to_string(),
+            message.channel_id(),
+            message.id(),
+        );
+        if app.config.clamav_addr().is_some() {
+            attachment.set_scan_status(ScanStatus::Pending);
+        }
+        rehosted.push((link, attachment));
+    }
+
+    if !rehosted.is_empty() {
+        if let Some(content) = message.content_mut() {
+            // Replace from the back, so the ranges of earlier links stay valid
+            for (link, attachment) in rehosted.iter().rev() {
+                content.replace_range(link.range.clone(), &format!("<attachment:{}>", attachment.id()));
+            }
+        }
+
+        let attachments: Vec<FullAttachment> = rehosted.into_iter().map(|(_, attachment)| attachment).collect();
+        for attachment in &attachments {
+            message.add_attachment(Attachment::Full(attachment.clone()));
+        }
+
+        if !app.ops().update_rehosted_media(&message, &attachments).await? {
+            return Ok(());
+        }
+    }
+
+    if message
+        .attachments()
+        .iter()
+        .any(|a| a.scan_status() == ScanStatus::Pending)
+    {
+        scan_attachments(app, message).await
+    } else {
+        extract_attachment_metadata(app, message).await
+    }
+}
+
+/// Scan the attachments that are still pending a scan long after they were uploaded,
+/// as the scan is lost if the instance handling the upload stops before it completes.
+async fn rescan_pending_attachments(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    let before = Snowflake::from_timestamp((app.clock.now() - RESCAN_ATTACHMENTS_GRACE_PERIOD).timestamp_millis());
+
+    for id in app
+        .ops()
+        .fetch_messages_pending_scan(before, RESCAN_ATTACHMENTS_BATCH_SIZE)
+        .await?
+    {
+        let Some(mut message) = app.ops().fetch_message(id).await? else {
+            continue;
+        };
+
+        for attachment in message.attachments_mut() {
+            if let Attachment::Partial(partial) = attachment {
+                if partial.scan_status() == ScanStatus::Pending {
+                    *attachment = Attachment::Full(partial.clone().download(&app.s3).await?);
+                }
+            }
+        }
+
+        scan_attachments(app.clone(), message).await?;
+    }
+    Ok(())
+}
+
+/// Move attachments uploaded before key tokens were introduced to a new, unguessable key.
+///
+/// The contents are uploaded under the new key before the database is updated, and the old key is deleted last,
+/// so the attachment stays available throughout. If another instance moved the attachment first,
+/// the copy made by this instance is deleted instead.
+async fn migrate_attachment_keys(app: Arc<ApplicationState>) -> Result<(), AppError> {
+    let attachments = PartialAttachment::fetch_without_key_token(&app, MIGRATE_ATTACHMENT_KEYS_BATCH_SIZE).await?;
+
+    for attachment in &attachments {
+        let old_key = attachment.s3_key();
+        let key_token = generate_key_token();
+
+        // Infected attachments and attachments lost to a failed upload have no contents to move
+        let content = app.s3.attachments().try_get_object(&old_key).await?;
+        let mut moved = attachment.clone();
+        moved.set_key_token(Some(key_token.clone()));
+        if let Some((content, _)) = &content {
+            app.s3
+                .attachments()
+                .put_object(moved.s3_key(), content.clone(), &attachment.mime())
+                .await?;
+        }
+
+        if app.ops().set_attachment_key_token(attachment, &key_token).await? {
+            if content.is_some() {
+                app.s3.attachments().delete_object(old_key).await?;
+            }
+        } else if content.is_some() {
+            app.s3.attachments().delete_object(moved.s3_key()).await?;
+        }
+    }
+
+    if !attachments.is_empty() {
+        tracing::debug!(moved = attachments.len(), "Moved attachments to keys with a key token");
+    }
+    Ok(())
+}
+
+/// Invite users to a guild by their usernames, reporting progress to the inviter over the gateway.
+///
+/// ## Arguments
+///
+/// * `app` - The application state.
+/// * `job_id` - The ID of the import job, included in progress reports.
+/// * `guild` - The guild to invite the users to.
+/// * `inviter` - The user who started the import.
+/// * `usernames` - The usernames of the users to invite.
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::InviteCreate`] - To every invited user
+/// * [`GatewayEvent::MemberImportProgress`] - To the inviter, periodically and once the import is done
+///
+/// ## Errors
+///
+/// * [`AppError::Database`] - If a database query fails. The import is aborted.
+/// * [`AppError::Build`] - If a member could not be built. The import is aborted.
+pub async fn import_members(
+    app: Arc<ApplicationState>,
+    job_id: Snowflake<()>,
+    guild: Guild,
+    inviter: Snowflake<User>,
+    usernames: Vec<String>,
+) -> Result<(), AppError> {
+    let mut progress = MemberImportProgressPayload {
+        job_id,
+        guild_id: guild.id(),
+        total: usernames.len(),
+        processed: 0,
+        invited: 0,
+        skipped: 0,
+        done: false,
+    };
+
+    for username in usernames {
+        let was_invited = match app.ops().fetch_user_by_username(&username).await {
+            Some(user) if app.ops().fetch_member(&user, &guild).await?.is_none() => {
+                let invite = GuildInvite::new(guild.clone(), &user, inviter, app.clock.now());
+                let created = app.ops().create_guild_invite(&invite).await?;
+                if created {
+                    app.gateway.send_to(&user, GatewayEvent::InviteCreate(invite));
+                }
+                created
+            }
+            _ => false,
+        };
+
+        if was_invited {
+            progress.invited += 1;
+        } else {
+            progress.skipped += 1;
+        }
+        progress.processed += 1;
+
+        if progress.processed.is_multiple_of(IMPORT_PROGRESS_INTERVAL) && progress.processed < progress.total {
+            app.gateway
+                .send_to(inviter, GatewayEvent::MemberImportProgress(progress.clone()));
+        }
+    }
+
+    progress.done = true;
+    app.gateway
+        .send_to(inviter, GatewayEvent::MemberImportProgress(progress));
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+
+use super::{
+    errors::{BuildError, ErrorCode},
+    snowflake::Snowflake,
+    user::User,
+};
+
+/// The longest minimum account age a guild may require, one year.
+pub const MAX_MIN_ACCOUNT_AGE: i32 = 365 * 24 * 60 * 60;
+
+/// Requirements a user must meet to join a guild.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct JoinRequirements {
+    /// The minimum age of a user's account in seconds, if any.
+    min_account_age: Option<i32>,
+    /// Whether users need a pending invite to join.
+    invite_only: bool,
+}
+
+impl JoinRequirements {
+    /// Create new join requirements.
+    ///
+    /// ## Arguments
+    ///
+    /// * `min_account_age` - The minimum age of a user's account in seconds, if any.
+    /// * `invite_only` - Whether users need a pending invite to join.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If the minimum account age is negative or longer than [`MAX_MIN_ACCOUNT_AGE`].
+    pub fn new(min_account_age: Option<i32>, invite_only: bool) -> Result<Self, BuildError> {
+        let requirements = Self {
+            min_account_age,
+            invite_only,
+        };
+        requirements.validate()?;
+        Ok(requirements)
+    }
+
+    /// The minimum age of a user's account in seconds, if any.
+    pub const fn min_account_age(&self) -> Option<i32> {
+        self.min_account_age
+    }
+
+    /// Whether users need a pending invite to join.
+    pub const fn invite_only(&self) -> bool {
+        self.invite_only
+    }
+
+    /// The stricter requirements enforced while the guild is in raid mode.
+    /// Users need a pending invite, and their account must be at least `min_account_age` seconds old.
+    ///
+    /// ## Arguments
+    ///
+    /// * `min_account_age` - The minimum account age enforced in raid mode, in seconds.
+    #[must_use]
+    pub fn during_raid(&self, min_account_age: i32) -> Self {
+        Self {
+            min_account_age: Some(
+                self.min_account_age
+                    .map_or(min_account_age, |age| age.max(min_account_age)),
+            ),
+            invite_only: true,
+        }
+    }
+
+    /// Ensure the requirements are within the allowed bounds.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If the minimum account age is negative or longer than [`MAX_MIN_ACCOUNT_AGE`].
+    pub fn validate(&self) -> Result<(), BuildError> {
+        if self
+            .min_account_age
+            .is_some_and(|age| !(0..=MAX_MIN_ACCOUNT_AGE).contains(&age))
+        {
+            return Err(BuildError::InvalidField {
+                field: "join_requirements.min_account_age",
+                code: ErrorCode::OutOfRange,
+                message: format!("Minimum account age must be between 0 and {MAX_MIN_ACCOUNT_AGE} seconds."),
+            });
+        }
+        Ok(())
+    }
+
+    /// Check whether a user may join a guild with these requirements.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user joining the guild. The account age is derived from it.
+    /// * `invited` - Whether the user has a pending invite to the guild.
+    /// * `now` - The time the user is joining at.
+    ///
+    /// ## Errors
+    ///
+    /// * [`JoinRequirementError`] - The first requirement the user does not meet.
+    pub fn check(&self, user: Snowflake<User>, invited: bool, now: DateTime<Utc>) -> Result<(), JoinRequirementError> {
+        if self.invite_only && !invited {
+            return Err(JoinRequirementError::InviteRequired);
+        }
+
+        if let Some(min_account_age) = self.min_account_age {
+            let eligible_at = user.created_at().timestamp() + i64::from(min_account_age);
+            if eligible_at > now.timestamp() {
+                return Err(JoinRequirementError::AccountTooNew {
+                    min_account_age,
+                    eligible_at,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A guild join requirement the user does not meet.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum JoinRequirementError {
+    /// The guild can only be joined with a pending invite.
+    #[error("This guild can only be joined with an invite.")]
+    InviteRequired,
+    /// The user's account is younger than the guild's minimum account age.
+    #[error("Your account must be at least {min_account_age} seconds old to join this guild.")]
+    AccountTooNew {
+        /// The minimum account age of the guild, in seconds.
+        min_account_age: i32,
+        /// UNIX timestamp of when the user's account is old enough to join.
+        eligible_at: i64,
+    },
+}
+
+impl JoinRequirementError {
+    /// A machine-readable code describing the requirement that is not met.
+    pub const fn code(&self) -> ErrorCode {
+        match self {
+            Self::InviteRequired => ErrorCode::InviteRequired,
+            Self::AccountTooNew { .. } => ErrorCode::AccountTooNew,
+        }
+    }
+}
+
+impl IntoResponse for JoinRequirementError {
+    fn into_response(self) -> Response {
+        let mut body = json!({
+            "error": self.to_string(),
+            "code": self.code(),
+        });
+        if let Self::AccountTooNew { eligible_at, .. } = self {
+            body["eligible_at"] = json!(eligible_at);
+        }
+        (StatusCode::FORBIDDEN, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+
+    use super::{JoinRequirementError, JoinRequirements, MAX_MIN_ACCOUNT_AGE};
+    use crate::models::{snowflake::Snowflake, user::User};
+
+    #[test]
+    fn test_check() {
+        let user: Snowflake<User> = Snowflake::new(502_225_724_397_195_265);
+        let created_at = user.created_at();
+
+        let requirements = JoinRequirements::new(Some(3600), false).expect("Requirements should be valid");
+        assert_eq!(
+            requirements.check(user, false, created_at + TimeDelta::minutes(30)),
+            Err(JoinRequirementError::AccountTooNew {
+                min_account_age: 3600,
+                eligible_at: created_at.timestamp() + 3600,
+            })
+        );
+        assert_eq!(
+            requirements.check(user, false, created_at + TimeDelta::hours(1)),
+            Ok(())
+        );
+
+        let requirements = JoinRequirements::new(None, true).expect("Requirements should be valid");
+        assert_eq!(
+            requirements.check(user, false, created_at),
+            Err(JoinRequirementError::InviteRequired)
+        );
+        assert_eq!(requirements.check(user, true, created_at), Ok(()));
+        assert_eq!(JoinRequirements::default().check(user, false, created_at), Ok(()));
+    }
+
+    #[test]
+    fn test_during_raid() {
+        let raid = JoinRequirements::default().during_raid(86400);
+        assert!(raid.invite_only());
+        assert_eq!(raid.min_account_age(), Some(86400));
+
+        // Stricter requirements set by the guild are kept
+        let requirements = JoinRequirements::new(Some(7 * 86400), false).expect("Requirements should be valid");
+        assert_eq!(requirements.during_raid(86400).min_account_age(), Some(7 * 86400));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(JoinRequirements::new(Some(-1), false).is_err());
+        assert!(JoinRequirements::new(Some(MAX_MIN_ACCOUNT_AGE + 1), false).is_err());
+        assert!(JoinRequirements::new(Some(MAX_MIN_ACCOUNT_AGE), false).is_ok());
+    }
+}
@@ -0,0 +1,157 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::json;
+use thiserror::Error;
+
+use super::errors::ErrorCode;
+
+/// A limit on the amount or size of resources, set by the server operator.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Limit {
+    /// The amount of guilds a user may be a member of.
+    MaxGuildsPerUser,
+    /// The amount of channels a guild may have.
+    MaxChannelsPerGuild,
+    /// The amount of members a guild may have.
+    MaxMembersPerGuild,
+    /// The size of a single attachment in bytes.
+    MaxAttachmentSize,
+}
+
+/// The limits enforced when creating resources.
+#[allow(clippy::struct_field_names)] // Serialized as is, named after their environment variables
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    max_guilds_per_user: usize,
+    max_channels_per_guild: usize,
+    max_members_per_guild: usize,
+    max_attachment_size: usize,
+}
+
+impl Limits {
+    /// Create a new set of limits.
+    ///
+    /// ## Arguments
+    ///
+    /// * `max_guilds_per_user` - The amount of guilds a user may be a member of.
+    /// * `max_channels_per_guild` - The amount of channels a guild may have.
+    /// * `max_members_per_guild` - The amount of members a guild may have.
+    /// * `max_attachment_size` - The size of a single attachment in bytes.
+    pub const fn new(
+        max_guilds_per_user: usize,
+        max_channels_per_guild: usize,
+        max_members_per_guild: usize,
+        max_attachment_size: usize,
+    ) -> Self {
+        Self {
+            max_guilds_per_user,
+            max_channels_per_guild,
+            max_members_per_guild,
+            max_attachment_size,
+        }
+    }
+
+    /// The maximum value of a limit.
+    pub const fn max(&self, limit: Limit) -> usize {
+        match limit {
+            Limit::MaxGuildsPerUser => self.max_guilds_per_user,
+            Limit::MaxChannelsPerGuild => self.max_channels_per_guild,
+            Limit::MaxMembersPerGuild => self.max_members_per_guild,
+            Limit::MaxAttachmentSize => self.max_attachment_size,
+        }
+    }
+
+    /// Change the maximum value of a limit.
+    pub const fn set(&mut self, limit: Limit, max: usize) {
+        match limit {
+            Limit::MaxGuildsPerUser => self.max_guilds_per_user = max,
+            Limit::MaxChannelsPerGuild => self.max_channels_per_guild = max,
+            Limit::MaxMembersPerGuild => self.max_members_per_guild = max,
+            Limit::MaxAttachmentSize => self.max_attachment_size = max,
+        }
+    }
+
+    /// Ensure that a resource stays within a limit.
+    ///
+    /// ## Arguments
+    ///
+    /// * `limit` - The limit to check.
+    /// * `value` - The amount or size of the resource after the change, for example the amount of guilds after joining one.
+    ///
+    /// ## Errors
+    ///
+    /// * [`LimitExceeded`] - If the value is larger than the limit.
+    pub const fn check(&self, limit: Limit, value: usize) -> Result<(), LimitExceeded> {
+        let max = self.max(limit);
+        if value > max {
+            return Err(LimitExceeded { limit, max });
+        }
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::new(100, 500, 10_000, 8 * 1024 * 1024)
+    }
+}
+
+/// A request would exceed one of the [`Limits`].
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("{}", match self.limit {
+    Limit::MaxGuildsPerUser => format!("Cannot be a member of more than {} guilds.", self.max),
+    Limit::MaxChannelsPerGuild => format!("A guild may not have more than {} channels.", self.max),
+    Limit::MaxMembersPerGuild => format!("A guild may not have more than {} members.", self.max),
+    Limit::MaxAttachmentSize => format!("Attachments may not be larger than {} bytes.", self.max),
+})]
+pub struct LimitExceeded {
+    /// The limit that would be exceeded.
+    pub limit: Limit,
+    /// The maximum value of the limit.
+    pub max: usize,
+}
+
+impl IntoResponse for LimitExceeded {
+    fn into_response(self) -> Response {
+        let status = match self.limit {
+            Limit::MaxAttachmentSize => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        let body = json!({
+            "error": self.to_string(),
+            "code": ErrorCode::LimitExceeded,
+            "limit": self.limit,
+            "max": self.max,
+        });
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Limit, LimitExceeded, Limits};
+
+    #[test]
+    fn test_check() {
+        let limits = Limits::new(2, 10, 100, 1024);
+
+        assert_eq!(limits.check(Limit::MaxGuildsPerUser, 2), Ok(()));
+        assert_eq!(
+            limits.check(Limit::MaxGuildsPerUser, 3),
+            Err(LimitExceeded {
+                limit: Limit::MaxGuildsPerUser,
+                max: 2
+            })
+        );
+        assert!(limits.check(Limit::MaxAttachmentSize, 1025).is_err());
+        assert_eq!(
+            serde_json::to_value(limits).expect("Limits should serialize")["max_attachment_size"],
+            1024
+        );
+    }
+}
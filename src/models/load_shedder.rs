@@ -0,0 +1,155 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use thiserror::Error;
+
+use super::{errors::ErrorCode, state::Config};
+
+/// Tracks how loaded the server is, so low priority requests can be rejected
+/// before they slow down sending messages and dispatching events.
+#[derive(Debug, Clone, Default)]
+pub struct LoadShedder {
+    /// The amount of in-flight requests above which low priority requests are shed, or `None` to never shed them.
+    max_in_flight: Option<usize>,
+    /// The amount of queued events above which low priority requests are shed, or `None` to never shed them.
+    max_queue_depth: Option<usize>,
+    /// How long clients are told to wait before retrying a shed request.
+    retry_after: Duration,
+    /// The amount of REST requests currently being handled.
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl LoadShedder {
+    /// Create a new load shedder from the configured thresholds.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            max_in_flight: (config.load_shed_max_in_flight() > 0).then_some(config.load_shed_max_in_flight()),
+            max_queue_depth: (config.load_shed_max_queue_depth() > 0).then_some(config.load_shed_max_queue_depth()),
+            retry_after: config.load_shed_retry_after(),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Count a request as in-flight until the returned guard is dropped.
+    pub fn track(&self) -> InFlightGuard {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard {
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    /// The amount of REST requests currently being handled.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Check whether a low priority request may be handled.
+    ///
+    /// ## Arguments
+    ///
+    /// * `queue_depth` - The amount of events waiting to be dispatched.
+    ///
+    /// ## Errors
+    ///
+    /// * [`OverloadedError`] - If either the in-flight requests or the queued events exceed their threshold.
+    pub fn check(&self, queue_depth: usize) -> Result<(), OverloadedError> {
+        let overloaded = self.max_in_flight.is_some_and(|max| self.in_flight() > max)
+            || self.max_queue_depth.is_some_and(|max| queue_depth > max);
+
+        if overloaded {
+            return Err(OverloadedError {
+                retry_after: self.retry_after.as_secs().max(1),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Counts a request as in-flight while it is alive.
+#[derive(Debug)]
+pub struct InFlightGuard {
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Sent when a low priority request is rejected because the server is overloaded.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("The server is overloaded, try again in {retry_after} seconds.")]
+pub struct OverloadedError {
+    /// The amount of seconds after which the request may be retried.
+    pub retry_after: u64,
+}
+
+impl IntoResponse for OverloadedError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "error": self.to_string(),
+            "code": ErrorCode::ServiceUnavailable,
+            "retry_after": self.retry_after,
+        });
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, HeaderValue::from(self.retry_after))],
+            Json(body),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::{LoadShedder, OverloadedError};
+
+    fn shedder(max_in_flight: Option<usize>, max_queue_depth: Option<usize>) -> LoadShedder {
+        LoadShedder {
+            max_in_flight,
+            max_queue_depth,
+            retry_after: Duration::from_secs(5),
+            in_flight: Arc::default(),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let shedder = shedder(Some(1), Some(100));
+        assert_eq!(shedder.check(0), Ok(()));
+
+        let first = shedder.track();
+        assert_eq!(shedder.check(100), Ok(()));
+        assert_eq!(shedder.check(101), Err(OverloadedError { retry_after: 5 }));
+
+        let second = shedder.track();
+        assert_eq!(shedder.in_flight(), 2);
+        assert_eq!(shedder.check(0), Err(OverloadedError { retry_after: 5 }));
+
+        drop(first);
+        drop(second);
+        assert_eq!(shedder.in_flight(), 0);
+        assert_eq!(shedder.check(0), Ok(()));
+    }
+
+    #[test]
+    fn test_check_disabled() {
+        let shedder = shedder(None, None);
+        let _guards: Vec<_> = (0..10).map(|_| shedder.track()).collect();
+        assert_eq!(shedder.check(usize::MAX), Ok(()));
+    }
+}
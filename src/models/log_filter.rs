@@ -0,0 +1,248 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::task::AbortHandle;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{filter::Targets, reload, Registry};
+
+/// The layer that filters log records, which can be swapped out at runtime through a [`LogFilter`].
+pub type LogFilterLayer = reload::Layer<Targets, Registry>;
+/// Changes the filter of a [`LogFilterLayer`].
+pub type LogFilterHandle = reload::Handle<Targets, Registry>;
+
+/// The verbosity of log records.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => Self::OFF,
+            LogLevel::Error => Self::ERROR,
+            LogLevel::Warn => Self::WARN,
+            LogLevel::Info => Self::INFO,
+            LogLevel::Debug => Self::DEBUG,
+            LogLevel::Trace => Self::TRACE,
+        }
+    }
+}
+
+/// A subsystem whose verbosity can be set separately from the rest of the server.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    /// The gateway and its connections.
+    Gateway,
+    /// The REST API.
+    Rest,
+    /// Database queries made by sqlx.
+    Sqlx,
+}
+
+impl LogTarget {
+    /// The module path of the target, records of the module and its submodules are affected.
+    pub const fn module(self) -> &'static str {
+        match self {
+            Self::Gateway => "chat_backend::gateway",
+            Self::Rest => "chat_backend::rest",
+            Self::Sqlx => "sqlx",
+        }
+    }
+}
+
+/// The verbosity of the server, and of subsystems that differ from it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogLevels {
+    /// The verbosity of everything not listed in `targets`.
+    pub level: LogLevel,
+    /// The verbosity of subsystems that differ from `level`.
+    #[serde(default)]
+    pub targets: BTreeMap<LogTarget, LogLevel>,
+}
+
+impl Default for LogLevels {
+    /// The verbosity the server starts with, which is more verbose in debug builds.
+    fn default() -> Self {
+        Self {
+            level: if cfg!(debug_assertions) {
+                LogLevel::Debug
+            } else {
+                LogLevel::Info
+            },
+            targets: BTreeMap::new(),
+        }
+    }
+}
+
+impl LogLevels {
+    /// Build the filter that only lets through log records of the configured verbosity.
+    pub fn filter(&self) -> Targets {
+        self.targets.iter().fold(
+            Targets::new().with_default(LevelFilter::from(self.level)),
+            |filter, (target, level)| filter.with_target(target.module(), LevelFilter::from(*level)),
+        )
+    }
+}
+
+/// The current verbosity, and when it is reverted to the default.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogFilterState {
+    #[serde(flatten)]
+    pub levels: LogLevels,
+    /// When the verbosity is reverted to the default as a UNIX timestamp in seconds, or `None` if it is the default.
+    pub revert_at: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    levels: LogLevels,
+    revert_at: Option<i64>,
+    revert_task: Option<AbortHandle>,
+}
+
+/// Controls the verbosity of the server at runtime.
+/// Changes are reverted to the default after a while, so verbose logging is not left on by accident.
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    handle: Arc<OnceLock<LogFilterHandle>>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl LogFilter {
+    /// Create a new log filter at the default verbosity. Changes only take effect once [`LogFilter::attach`] is called.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the layer that filters log records at the default verbosity, and a handle to change it with.
+    /// The layer must be added to the global subscriber before any other layer.
+    pub fn layer() -> (LogFilterLayer, LogFilterHandle) {
+        reload::Layer::new(LogLevels::default().filter())
+    }
+
+    /// Take control of the filter of the global subscriber, created by [`LogFilter::layer`].
+    pub fn attach(&self, handle: LogFilterHandle) {
+        if self.handle.set(handle).is_ok() {
+            self.apply(&self.inner.lock().expect("Log filter lock poisoned").levels);
+        }
+    }
+
+    /// The current verbosity, and when it is reverted to the default.
+    pub fn state(&self) -> LogFilterState {
+        let inner = self.inner.lock().expect("Log filter lock poisoned");
+        LogFilterState {
+            levels: inner.levels.clone(),
+            revert_at: inner.revert_at,
+        }
+    }
+
+    /// Change the verbosity, and revert it to the default once `duration` passed.
+    /// The revert of a previous change is cancelled.
+    ///
+    /// ## Arguments
+    ///
+    /// * `levels` - The new verbosity
+    /// * `duration` - How long the new verbosity is kept for
+    /// * `now` - The current time
+    pub fn set(&self, levels: LogLevels, duration: Duration, now: DateTime<Utc>) -> LogFilterState {
+        let mut inner = self.inner.lock().expect("Log filter lock poisoned");
+
+        // The revert waits for the lock, so it cannot run before the change is applied
+        let filter = self.clone();
+        let revert_task = tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            filter.reset();
+        });
+        if let Some(task) = inner.revert_task.replace(revert_task.abort_handle()) {
+            task.abort();
+        }
+
+        // Logged before the change, so it is recorded even if the new verbosity is lower
+        tracing::warn!(?levels, revert_after = ?duration, "Log verbosity changed");
+        self.apply(&levels);
+
+        inner.levels = levels;
+        inner.revert_at = Some(
+            now.timestamp()
+                .saturating_add(i64::try_from(duration.as_secs()).unwrap_or(i64::MAX)),
+        );
+        LogFilterState {
+            levels: inner.levels.clone(),
+            revert_at: inner.revert_at,
+        }
+    }
+
+    /// Revert the verbosity to the default.
+    pub fn reset(&self) -> LogFilterState {
+        let mut inner = self.inner.lock().expect("Log filter lock poisoned");
+        if let Some(task) = inner.revert_task.take() {
+            task.abort();
+        }
+        inner.levels = LogLevels::default();
+        inner.revert_at = None;
+        tracing::warn!("Log verbosity reverted to the default");
+        self.apply(&inner.levels);
+
+        LogFilterState {
+            levels: inner.levels.clone(),
+            revert_at: None,
+        }
+    }
+
+    /// Swap out the filter of the global subscriber, if it is attached.
+    fn apply(&self, levels: &LogLevels) {
+        let Some(handle) = self.handle.get() else {
+            return;
+        };
+        if let Err(e) = handle.reload(levels.filter()) {
+            tracing::error!(error = %e, "Failed to change log verbosity");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tracing::{level_filters::LevelFilter, Level};
+
+    use super::{LogLevel, LogLevels, LogTarget};
+
+    #[test]
+    fn test_filter() {
+        let levels = LogLevels {
+            level: LogLevel::Warn,
+            targets: BTreeMap::from([(LogTarget::Gateway, LogLevel::Trace), (LogTarget::Sqlx, LogLevel::Off)]),
+        };
+        let filter = levels.filter();
+
+        assert!(filter.would_enable("chat_backend::gateway::handler", &Level::TRACE));
+        assert!(filter.would_enable("chat_backend::rest", &Level::WARN));
+        assert!(!filter.would_enable("chat_backend::rest", &Level::INFO));
+        assert!(!filter.would_enable("sqlx::query", &Level::ERROR));
+        assert_eq!(filter.default_level(), Some(LevelFilter::WARN));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let levels: LogLevels = serde_json::from_str(r#"{"level": "info", "targets": {"rest": "debug"}}"#)
+            .expect("Levels should deserialize");
+        assert_eq!(levels.level, LogLevel::Info);
+        assert_eq!(levels.targets.get(&LogTarget::Rest), Some(&LogLevel::Debug));
+        assert!(serde_json::from_str::<LogLevels>(r#"{"level": "loud"}"#).is_err());
+        assert!(serde_json::from_str::<LogLevels>(r#"{"level": "info", "targets": {"other": "debug"}}"#).is_err());
+    }
+}
@@ -0,0 +1,339 @@
+use std::io::Cursor;
+
+use bytes::Bytes;
+use image::{imageops::FilterType, ImageFormat, ImageReader, Limits};
+use mime::Mime;
+use serde::Serialize;
+use symphonia::core::{
+    formats::FormatOptions,
+    io::{MediaSourceStream, MediaSourceStreamOptions},
+    meta::MetadataOptions,
+    probe::Hint,
+    units::TimeBase,
+};
+
+/// The largest width or height of an image that is decoded to compute its blurhash.
+const MAX_IMAGE_DIMENSION: u32 = 16384;
+/// The maximum amount of memory that may be allocated while decoding an image.
+const MAX_IMAGE_ALLOC: u64 = 256 * 1024 * 1024;
+/// The size images are downscaled to before computing their blurhash.
+const BLURHASH_THUMBNAIL_SIZE: u32 = 64;
+/// The amount of blurhash components along the longer side of an image.
+const BLURHASH_COMPONENTS: u32 = 4;
+
+/// Information about an image, video or audio file that lets clients render it before it is downloaded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MediaMetadata {
+    /// The width of the image or video, in pixels.
+    width: Option<u32>,
+    /// The height of the image or video, in pixels.
+    height: Option<u32>,
+    /// The duration of the video or audio, in seconds.
+    duration: Option<f64>,
+    /// A blurhash placeholder of the image.
+    blurhash: Option<String>,
+}
+
+impl MediaMetadata {
+    /// Create new media metadata.
+    pub const fn new(width: Option<u32>, height: Option<u32>, duration: Option<f64>, blurhash: Option<String>) -> Self {
+        Self {
+            width,
+            height,
+            duration,
+            blurhash,
+        }
+    }
+
+    /// The width of the image or video, in pixels.
+    pub const fn width(&self) -> Option<u32> {
+        self.width
+    }
+
+    /// The height of the image or video, in pixels.
+    pub const fn height(&self) -> Option<u32> {
+        self.height
+    }
+
+    /// The duration of the video or audio, in seconds.
+    pub const fn duration(&self) -> Option<f64> {
+        self.duration
+    }
+
+    /// A blurhash placeholder of the image.
+    pub fn blurhash(&self) -> Option<&str> {
+        self.blurhash.as_deref()
+    }
+
+    /// Whether no metadata is known.
+    pub const fn is_empty(&self) -> bool {
+        self.width.is_none() && self.height.is_none() && self.duration.is_none() && self.blurhash.is_none()
+    }
+
+    /// Whether metadata can be extracted from files of the given type.
+    pub fn is_supported(mime: &Mime) -> bool {
+        matches!(mime.type_(), mime::IMAGE | mime::VIDEO | mime::AUDIO)
+    }
+
+    /// Extract metadata from the contents of a file. This is CPU-bound, and should not be called on an async task.
+    ///
+    /// Images in PNG, JPEG, GIF and WebP format, videos in MP4 and MOV format,
+    /// and audio in MP4, MP3, WAV, FLAC and Ogg format are supported.
+    ///
+    /// ## Arguments
+    ///
+    /// * `content` - The contents of the file.
+    /// * `mime` - The MIME type of the file.
+    ///
+    /// ## Returns
+    ///
+    /// The extracted metadata. If the file is of an unsupported type or malformed, it is empty.
+    pub fn extract(content: &Bytes, mime: &Mime) -> Self {
+        match (mime.type_(), mime.subtype().as_str()) {
+            (mime::IMAGE, _) => extract_image(content, mime).unwrap_or_default(),
+            (mime::VIDEO | mime::AUDIO, "mp4" | "quicktime" | "x-m4a") => extract_mp4(content).unwrap_or_default(),
+            (mime::AUDIO, _) => Self {
+                duration: extract_audio_duration(content, mime),
+                ..Self::default()
+            },
+            _ => Self::default(),
+        }
+    }
+}
+
+/// Read the dimensions of an image and compute its blurhash.
+fn extract_image(content: &[u8], mime: &Mime) -> Option<MediaMetadata> {
+    let format = ImageFormat::from_mime_type(mime.essence_str())?;
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    limits.max_alloc = Some(MAX_IMAGE_ALLOC);
+
+    let mut reader = ImageReader::with_format(Cursor::new(content), format);
+    reader.limits(limits);
+    let image = reader.decode().ok()?;
+
+    let thumbnail = image
+        .resize(BLURHASH_THUMBNAIL_SIZE, BLURHASH_THUMBNAIL_SIZE, FilterType::Triangle)
+        .to_rgba8();
+
+    // Use less components along the shorter side, so the placeholder keeps the aspect ratio
+    let (components_x, components_y) = if image.width() >= image.height() {
+        (BLURHASH_COMPONENTS, BLURHASH_COMPONENTS - 1)
+    } else {
+        (BLURHASH_COMPONENTS - 1, BLURHASH_COMPONENTS)
+    };
+    let blurhash = blurhash::encode(
+        components_x,
+        components_y,
+        thumbnail.width(),
+        thumbnail.height(),
+        thumbnail.as_raw(),
+    )
+    .ok();
+
+    Some(MediaMetadata::new(
+        Some(image.width()),
+        Some(image.height()),
+        None,
+        blurhash,
+    ))
+}
+
+/// Read the duration of an audio file by demuxing it, without decoding any samples.
+fn extract_audio_duration(content: &Bytes, mime: &Mime) -> Option<f64> {
+    let mut hint = Hint::new();
+    hint.mime_type(mime.essence_str());
+
+    let source = MediaSourceStream::new(
+        Box::new(Cursor::new(content.clone())),
+        MediaSourceStreamOptions::default(),
+    );
+    let mut format = symphonia::default::get_probe()
+        .format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?
+        .format;
+
+    let track = format.default_track()?;
+    let (track_id, time_base) = (track.id, track.codec_params.time_base?);
+    if let Some(frames) = track.codec_params.n_frames {
+        return Some(seconds(time_base, frames));
+    }
+
+    // Not all formats store the length of the stream, the end of the last packet is used instead
+    let mut end = None;
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() == track_id {
+            end = Some(packet.ts() + packet.dur());
+        }
+    }
+    end.map(|end| seconds(time_base, end))
+}
+
+fn seconds(time_base: TimeBase, timestamp: u64) -> f64 {
+    let time = time_base.calc_time(timestamp);
+    time.seconds as f64 + time.frac
+}
+
+/// Read the duration and dimensions of an MP4 or MOV file from its `moov` box.
+fn extract_mp4(content: &[u8]) -> Option<MediaMetadata> {
+    let moov = boxes(content).find(|(kind, _)| kind == b"moov")?.1;
+    let mut metadata = MediaMetadata::default();
+
+    for (kind, data) in boxes(moov) {
+        match &kind {
+            b"mvhd" => metadata.duration = parse_mvhd(data),
+            // The first track with dimensions is the video track, audio tracks have a size of zero
+            b"trak" if metadata.width.is_none() => {
+                if let Some((width, height)) = boxes(data)
+                    .find(|(kind, _)| kind == b"tkhd")
+                    .and_then(|(_, tkhd)| parse_tkhd(tkhd))
+                {
+                    metadata.width = Some(width);
+                    metadata.height = Some(height);
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(metadata)
+}
+
+/// Parse the duration from the contents of a `mvhd` box, in seconds.
+fn parse_mvhd(data: &[u8]) -> Option<f64> {
+    let (timescale, duration) = match *data.first()? {
+        0 => (read_u32(data, 12)?, u64::from(read_u32(data, 16)?)),
+        1 => (read_u32(data, 20)?, read_u64(data, 24)?),
+        _ => return None,
+    };
+    (timescale != 0).then(|| duration as f64 / f64::from(timescale))
+}
+
+/// Parse the dimensions from the contents of a `tkhd` box, if the track has any.
+fn parse_tkhd(data: &[u8]) -> Option<(u32, u32)> {
+    let offset = match *data.first()? {
+        0 => 76,
+        1 => 88,
+        _ => return None,
+    };
+    // Dimensions are stored as 16.16 fixed-point numbers
+    let (width, height) = (read_u32(data, offset)? >> 16, read_u32(data, offset + 4)? >> 16);
+    (width != 0 && height != 0).then_some((width, height))
+}
+
+/// Iterate over the ISO base media file format boxes in `data`, yielding their type and contents.
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        let size = read_u32(data, 0)?;
+        let kind: [u8; 4] = data.get(4..8)?.try_into().ok()?;
+
+        let (header, size) = match size {
+            // The box extends to the end of the file
+            0 => (8, data.len()),
+            // The size is stored as a 64-bit integer after the type
+            1 => (16, usize::try_from(read_u64(data, 8)?).ok()?),
+            size => (8, size as usize),
+        };
+        if size < header || size > data.len() {
+            return None;
+        }
+
+        let contents = &data[header..size];
+        data = &data[size..];
+        Some((kind, contents))
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use bytes::Bytes;
+    use image::{ImageFormat, Rgb, RgbImage};
+
+    use super::MediaMetadata;
+
+    /// Build an ISO base media file format box.
+    fn mp4_box(kind: [u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut data = ((contents.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(&kind);
+        data.extend_from_slice(contents);
+        data
+    }
+
+    #[test]
+    fn test_extract_image() {
+        let image = RgbImage::from_fn(40, 20, |x, _| Rgb([(x * 6) as u8, 0, 255]));
+        let mut png = Cursor::new(Vec::new());
+        image
+            .write_to(&mut png, ImageFormat::Png)
+            .expect("Failed to encode PNG");
+
+        let metadata = MediaMetadata::extract(&Bytes::from(png.into_inner()), &mime::IMAGE_PNG);
+        assert_eq!(metadata.width(), Some(40));
+        assert_eq!(metadata.height(), Some(20));
+        assert_eq!(metadata.duration(), None);
+        // A blurhash with 4x3 components is 28 characters long
+        assert_eq!(metadata.blurhash().map(str::len), Some(28));
+
+        // Malformed and unsupported files have no metadata
+        assert!(MediaMetadata::extract(&Bytes::from_static(b"not a png"), &mime::IMAGE_PNG).is_empty());
+        assert!(MediaMetadata::extract(&Bytes::from_static(b"hello"), &mime::TEXT_PLAIN).is_empty());
+    }
+
+    #[test]
+    fn test_extract_mp4() {
+        // Version 0 mvhd with a timescale of 1000 and a duration of 2500
+        let mut mvhd = vec![0; 100];
+        mvhd[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        mvhd[16..20].copy_from_slice(&2500u32.to_be_bytes());
+
+        // An audio track without dimensions, followed by a 1280x720 video track
+        let audio_tkhd = vec![0; 84];
+        let mut video_tkhd = vec![0; 84];
+        video_tkhd[76..80].copy_from_slice(&(1280u32 << 16).to_be_bytes());
+        video_tkhd[80..84].copy_from_slice(&(720u32 << 16).to_be_bytes());
+
+        let moov = [
+            mp4_box(*b"mvhd", &mvhd),
+            mp4_box(*b"trak", &mp4_box(*b"tkhd", &audio_tkhd)),
+            mp4_box(*b"trak", &mp4_box(*b"tkhd", &video_tkhd)),
+        ]
+        .concat();
+        let file = [mp4_box(*b"ftyp", b"isom"), mp4_box(*b"moov", &moov)].concat();
+
+        let metadata = MediaMetadata::extract(&Bytes::from(file), &"video/mp4".parse().expect("Invalid MIME type"));
+        assert_eq!(metadata, MediaMetadata::new(Some(1280), Some(720), Some(2.5), None));
+    }
+
+    #[test]
+    fn test_extract_audio() {
+        // One second of 8kHz 16-bit mono silence
+        let samples = vec![0; 16000];
+        let mut wav = b"RIFF".to_vec();
+        wav.extend_from_slice(&(36 + samples.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&8000u32.to_le_bytes());
+        wav.extend_from_slice(&16000u32.to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(samples.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&samples);
+
+        let metadata = MediaMetadata::extract(&Bytes::from(wav), &"audio/wav".parse().expect("Invalid MIME type"));
+        assert_eq!(metadata, MediaMetadata::new(None, None, Some(1.0), None));
+    }
+}
@@ -0,0 +1,689 @@
+use std::{
+    fmt, io,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use futures_util::{future::BoxFuture, FutureExt, StreamExt};
+use hmac::{Hmac, Mac};
+use mime::Mime;
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    header,
+    redirect::Policy,
+    Client, ClientBuilder, Url,
+};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+
+use super::{errors::RESTError, state::ApplicationState};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A piece of external media, as served by the media proxy.
+#[derive(Debug, Clone)]
+pub struct ProxiedMedia {
+    content: Bytes,
+    content_type: String,
+}
+
+impl ProxiedMedia {
+    /// The contents of the media.
+    pub const fn content(&self) -> &Bytes {
+        &self.content
+    }
+
+    /// The MIME type of the media.
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+}
+
+/// Looks up the addresses of a host name.
+type Lookup = dyn Fn(String) -> BoxFuture<'static, io::Result<Vec<IpAddr>>> + Send + Sync;
+
+/// Resolves the hosts of outgoing requests to user-supplied URLs, refusing hosts with non-public addresses.
+///
+/// Installed as the resolver of an HTTP client, the addresses are checked when the connection is made.
+/// Checking them before sending the request is not enough, as the host may resolve to a public address
+/// when it is checked, and to an internal one when the client resolves it again to connect.
+#[derive(Clone)]
+pub struct PublicResolver {
+    lookup: Arc<Lookup>,
+    /// Whether connections to an address are allowed
+    is_allowed: fn(IpAddr) -> bool,
+}
+
+impl fmt::Debug for PublicResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PublicResolver").finish_non_exhaustive()
+    }
+}
+
+impl PublicResolver {
+    /// Create a resolver using the resolver of the system.
+    pub fn new() -> Self {
+        Self {
+            lookup: Arc::new(|host| {
+                async move {
+                    Ok(tokio::net::lookup_host((host, 0))
+                        .await?
+                        .map(|addr| addr.ip())
+                        .collect())
+                }
+                .boxed()
+            }),
+            is_allowed: is_public_ip,
+        }
+    }
+
+    /// Create a resolver with the given lookup and address policy, to test how hosts are resolved.
+    #[cfg(test)]
+    pub(crate) fn with_lookup(
+        lookup: impl Fn(String) -> BoxFuture<'static, io::Result<Vec<IpAddr>>> + Send + Sync + 'static,
+        is_allowed: fn(IpAddr) -> bool,
+    ) -> Self {
+        Self {
+            lookup: Arc::new(lookup),
+            is_allowed,
+        }
+    }
+
+    /// Resolve a host name, ensuring that all of its addresses are publicly routable.
+    ///
+    /// ## Errors
+    ///
+    /// * [`RESTError::BadRequest`] - If the host cannot be resolved.
+    /// * [`RESTError::Forbidden`] - If the host resolves to a non-public address.
+    pub async fn resolve_public(&self, host: &str) -> Result<Vec<IpAddr>, RESTError> {
+        let addrs = (self.lookup)(host.to_string())
+            .await
+            .map_err(|_| RESTError::BadRequest("Failed to resolve host".into()))?;
+
+        if addrs.is_empty() || !addrs.iter().all(|ip| (self.is_allowed)(*ip)) {
+            return Err(RESTError::Forbidden("Host is not publicly routable".into()));
+        }
+        Ok(addrs)
+    }
+}
+
+impl Default for PublicResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        async move {
+            let addrs = resolver
+                .resolve_public(name.as_str())
+                .await
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            // The port is filled in by the client
+            Ok(Box::new(addrs.into_iter().map(|ip| SocketAddr::new(ip, 0))) as Addrs)
+        }
+        .boxed()
+    }
+}
+
+/// Fetches and re-serves external images through the server,
+/// so that client IPs are not leaked to arbitrary third-party hosts.
+///
+/// Only URLs signed by the server can be proxied, this prevents the proxy from being used as an open relay.
+#[derive(Debug, Clone)]
+pub struct MediaProxy {
+    client: Client,
+    app: Weak<ApplicationState>,
+}
+
+impl MediaProxy {
+    /// Create a new media proxy.
+    ///
+    /// Note: The proxy does not follow redirects to avoid being pointed at internal hosts.
+    pub fn new() -> Self {
+        Self::with_resolver(PublicResolver::new())
+    }
+
+    /// Create a new media proxy connecting to the addresses returned by the given resolver.
+    pub fn with_resolver(resolver: PublicResolver) -> Self {
+        let client = public_client(resolver)
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("chat-media-proxy/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build media proxy HTTP client");
+
+        Self {
+            client,
+            app: Weak::new(),
+        }
+    }
+
+    pub fn bind_to(&mut self, app: Weak<ApplicationState>) {
+        self.app = app;
+    }
+
+    pub fn app(&self) -> Arc<ApplicationState> {
+        self.app.upgrade().expect("Application state has been dropped.")
+    }
+
+    /// Create the proxied path for the given external URL.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - The external URL to proxy.
+    ///
+    /// ## Returns
+    ///
+    /// The path to the proxied media, relative to the server root.
+    pub fn proxied_url(&self, url: &Url) -> String {
+        let app = self.app();
+        format!(
+            "/api/v1/proxy/{}/{}",
+            sign(app.config.app_secret().expose_secret(), url.as_str()),
+            hex::encode(url.as_str())
+        )
+    }
+
+    /// Decode and verify a proxied URL created by [`MediaProxy::proxied_url`].
+    ///
+    /// ## Arguments
+    ///
+    /// * `signature` - The signature of the URL.
+    /// * `encoded_url` - The hex-encoded external URL.
+    ///
+    /// ## Errors
+    ///
+    /// * [`RESTError::BadRequest`] - If the URL could not be decoded.
+    /// * [`RESTError::Forbidden`] - If the signature is invalid.
+    pub fn verify(&self, signature: &str, encoded_url: &str) -> Result<Url, RESTError> {
+        let url = hex::decode(encoded_url)
+            .ok()
+            .and_then(|b| String::from_utf8(b).ok())
+            .ok_or(RESTError::BadRequest("Malformed proxy URL".into()))?;
+
+        // URLs signed with a previous key remain valid, as they may be stored in messages
+        let app = self.app();
+        if !app
+            .config
+            .signing_keys()
+            .iter()
+            .any(|key| verify(key.secret().expose_secret(), &url, signature))
+        {
+            return Err(RESTError::Forbidden("Invalid proxy signature".into()));
+        }
+
+        Url::parse(&url).map_err(|_| RESTError::BadRequest("Malformed proxy URL".into()))
+    }
+
+    /// Fetch an external image, serving it from the S3 cache if possible.
+    ///
+    /// Only raster images are proxied. Other images, such as SVGs, may run scripts when opened directly,
+    /// and would do so on the origin of the API.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - The external URL to fetch.
+    ///
+    /// ## Errors
+    ///
+    /// * [`RESTError::BadRequest`] - If the URL is not a valid HTTP(S) URL, or the media is not a raster image.
+    /// * [`RESTError::Forbidden`] - If the URL points to a non-public address.
+    /// * [`RESTError::App`] - If the upstream or S3 request fails.
+    pub async fn fetch(&self, url: &Url) -> Result<ProxiedMedia, RESTError> {
+        let app = self.app();
+        let key = hex::encode(Sha256::digest(url.as_str()));
+
+        if let Some((content, content_type)) = app.s3.proxy().try_get_object(&key).await? {
+            // Media cached before only raster images were proxied is not served either
+            let Some(mime) = content_type
+                .and_then(|ct| ct.parse::<Mime>().ok())
+                .filter(is_raster_image)
+            else {
+                return Err(RESTError::BadRequest("Proxied media must be a raster image".into()));
+            };
+            return Ok(ProxiedMedia {
+                content,
+                content_type: mime.to_string(),
+            });
+        }
+
+        let (content, mime) = self
+            .download_as(url, app.config.media_proxy_max_size(), is_raster_image)
+            .await?
+            .ok_or(RESTError::BadRequest("Proxied media must be a raster image".into()))?;
+
+        app.s3.proxy().put_object(&key, content.clone(), &mime).await?;
+
+        Ok(ProxiedMedia {
+            content,
+            content_type: mime.to_string(),
+        })
+    }
+
+    /// Download an external image, video or audio file without caching it, so that it can be re-hosted.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - The external URL to download.
+    /// * `max_size` - The maximum size of the media in bytes.
+    ///
+    /// ## Errors
+    ///
+    /// * [`RESTError::BadRequest`] - If the URL is not a valid HTTP(S) URL, or the media is not an image,
+    ///   video or audio file, or is too large.
+    /// * [`RESTError::Forbidden`] - If the URL points to a non-public address.
+    /// * [`RESTError::App`] - If the upstream request fails.
+    pub async fn download(&self, url: &Url, max_size: usize) -> Result<ProxiedMedia, RESTError> {
+        let (content, mime) = self
+            .download_as(url, max_size, |mime| {
+                [mime::IMAGE, mime::VIDEO, mime::AUDIO].contains(&mime.type_())
+            })
+            .await?
+            .ok_or(RESTError::BadRequest(
+                "Media must be an image, video or audio file".into(),
+            ))?;
+
+        Ok(ProxiedMedia {
+            content,
+            content_type: mime.to_string(),
+        })
+    }
+
+    /// Download external media if its MIME type is accepted.
+    /// The download is aborted as soon as the media is larger than `max_size`.
+    ///
+    /// ## Returns
+    ///
+    /// The contents and MIME type of the media, or `None` if the media has a different type.
+    async fn download_as(
+        &self,
+        url: &Url,
+        max_size: usize,
+        accept: fn(&Mime) -> bool,
+    ) -> Result<Option<(Bytes, Mime)>, RESTError> {
+        // Host names are checked by the resolver of the client when it connects
+        ensure_public_url(url)?;
+
+        let resp = self.client.get(url.clone()).send().await?.error_for_status()?;
+
+        let Some(mime) = resp
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|ct| ct.to_str().ok())
+            .and_then(|ct| ct.parse::<Mime>().ok())
+            .filter(accept)
+        else {
+            return Ok(None);
+        };
+
+        if resp.content_length().is_some_and(|len| len as usize > max_size) {
+            return Err(RESTError::BadRequest("Media is too large".into()));
+        }
+
+        let mut stream = resp.bytes_stream();
+        let mut content = BytesMut::new();
+
+        while let Some(chunk) = stream.next().await {
+            content.extend_from_slice(&chunk?);
+            if content.len() > max_size {
+                return Err(RESTError::BadRequest("Media is too large".into()));
+            }
+        }
+
+        Ok(Some((content.freeze(), mime)))
+    }
+}
+
+impl Default for MediaProxy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Check if a MIME type is a raster image format that browsers display, and that cannot contain scripts.
+fn is_raster_image(mime: &Mime) -> bool {
+    mime.type_() == mime::IMAGE && matches!(mime.subtype().as_str(), "png" | "jpeg" | "gif" | "webp" | "avif")
+}
+
+/// Sign a URL or other message with the given secret.
+pub(super) fn sign(secret: &str, url: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(url.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Verify the signature of a URL in constant time.
+fn verify(secret: &str, url: &str, signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(url.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Start building an HTTP client for requests to user-supplied URLs, which only connects to the addresses
+/// returned by the given resolver.
+///
+/// Redirects are not followed, and requests are never sent through a proxy server, as it would resolve hosts on its own.
+/// URLs with an IP address as their host are not resolved, so they must be checked with [`ensure_public_url`] first.
+pub(super) fn public_client(resolver: PublicResolver) -> ClientBuilder {
+    Client::builder()
+        .redirect(Policy::none())
+        .no_proxy()
+        .dns_resolver(Arc::new(resolver))
+}
+
+/// Ensure that the URL is HTTP(S) and, if its host is an IP address, that it is publicly routable.
+/// Host names are not resolved, requests to them must be sent by a client built with [`public_client`].
+///
+/// ## Errors
+///
+/// * [`RESTError::BadRequest`] - If the URL is not HTTP(S) or has no host.
+/// * [`RESTError::Forbidden`] - If the host is a non-public address.
+pub(super) fn ensure_public_url(url: &Url) -> Result<(), RESTError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(RESTError::BadRequest("Only HTTP(S) URLs are supported".into()));
+    }
+
+    let ip = match url.host() {
+        Some(url::Host::Domain(_)) => return Ok(()),
+        Some(url::Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(url::Host::Ipv6(ip)) => IpAddr::V6(ip),
+        None => return Err(RESTError::BadRequest("URL has no host".into())),
+    };
+
+    if !is_public_ip(ip) {
+        return Err(RESTError::Forbidden("Host is not publicly routable".into()));
+    }
+    Ok(())
+}
+
+/// Ensure that the URL is HTTP(S) and all addresses it resolves to are publicly routable.
+///
+/// ## Errors
+///
+/// * [`RESTError::BadRequest`] - If the URL is not HTTP(S) or its host cannot be resolved.
+/// * [`RESTError::Forbidden`] - If the host resolves to a non-public address.
+pub(super) async fn ensure_public_host(url: &Url) -> Result<(), RESTError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(RESTError::BadRequest("Only HTTP(S) URLs are supported".into()));
+    }
+
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let addrs: Vec<IpAddr> = match url.host() {
+        Some(url::Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|_| RESTError::BadRequest("Failed to resolve host".into()))?
+            .map(|addr| addr.ip())
+            .collect(),
+        Some(url::Host::Ipv4(ip)) => vec![IpAddr::V4(ip)],
+        Some(url::Host::Ipv6(ip)) => vec![IpAddr::V6(ip)],
+        None => return Err(RESTError::BadRequest("URL has no host".into())),
+    };
+
+    if addrs.is_empty() || !addrs.into_iter().all(is_public_ip) {
+        return Err(RESTError::Forbidden("Host is not publicly routable".into()));
+    }
+    Ok(())
+}
+
+/// Check if an IP address is publicly routable.
+///
+/// IPv6 addresses that embed an IPv4 address, such as NAT64 and 6to4 addresses, are judged by the embedded address.
+pub(super) fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                // "This network" (RFC 1122)
+                || a == 0
+                // Reserved (RFC 1112), including the broadcast address
+                || a >= 240
+                // Shared address space (RFC 6598)
+                || (a == 100 && (b & 0xC0) == 64)
+                // Benchmarking (RFC 2544)
+                || (a == 198 && (b & 0xFE) == 18)
+                // IETF protocol assignments (RFC 6890)
+                || (a == 192 && b == 0 && c == 0))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let segments = ip.segments();
+            let embedded = |high: u16, low: u16| {
+                let [a, b] = high.to_be_bytes();
+                let [c, d] = low.to_be_bytes();
+                IpAddr::from([a, b, c, d])
+            };
+            // NAT64 (64:ff9b::/96)
+            if segments[..6] == [0x64, 0xFF9B, 0, 0, 0, 0] {
+                return is_public_ip(embedded(segments[6], segments[7]));
+            }
+            // 6to4 (2002::/16)
+            if segments[0] == 0x2002 {
+                return is_public_ip(embedded(segments[1], segments[2]));
+            }
+            !(ip.is_multicast()
+                // Unspecified, loopback and the deprecated IPv4-compatible addresses (::/96)
+                || segments[..6] == [0; 6]
+                // Unique local (fc00::/7)
+                || (segments[0] & 0xFE00) == 0xFC00
+                // Link local (fe80::/10)
+                || (segments[0] & 0xFFC0) == 0xFE80
+                // Site local (fec0::/10)
+                || (segments[0] & 0xFFC0) == 0xFEC0)
+        }
+    }
+}
+
+/// Helpers for testing that clients built with [`public_client`] only connect to the addresses that were checked.
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::{
+        net::IpAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use futures_util::FutureExt;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::PublicResolver;
+
+    /// A host that resolves to an allowed address when first looked up, and to an internal address afterwards.
+    /// 127.0.0.2 stands in for the public address, 127.0.0.1 for the internal one.
+    pub struct RebindingHost {
+        /// Resolves the host, only allowing connections to the public address
+        pub resolver: PublicResolver,
+        /// The port served on both addresses
+        pub port: u16,
+        /// The amount of connections made to the public address
+        pub checked: Arc<AtomicUsize>,
+        /// The amount of connections made to the internal address
+        pub internal: Arc<AtomicUsize>,
+        /// The amount of lookups made
+        pub lookups: Arc<AtomicUsize>,
+    }
+
+    impl RebindingHost {
+        /// Serve both addresses, answering every connection with the given raw HTTP response
+        pub async fn serve(response: &'static [u8]) -> Self {
+            let checked = TcpListener::bind("127.0.0.2:0").await.expect("Failed to bind listener");
+            let port = checked.local_addr().expect("Listener should have an address").port();
+            let internal = TcpListener::bind(("127.0.0.1", port))
+                .await
+                .expect("Failed to bind listener");
+
+            let lookups = Arc::new(AtomicUsize::new(0));
+            let counter = lookups.clone();
+            let resolver = PublicResolver::with_lookup(
+                move |_| {
+                    let ip = if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                        "127.0.0.2"
+                    } else {
+                        "127.0.0.1"
+                    };
+                    async move { Ok(vec![ip.parse().expect("valid IP")]) }.boxed()
+                },
+                |ip| ip == IpAddr::from([127, 0, 0, 2]),
+            );
+
+            Self {
+                resolver,
+                port,
+                checked: serve(checked, response),
+                internal: serve(internal, response),
+                lookups,
+            }
+        }
+    }
+
+    /// Answer every connection with the given raw HTTP response, counting the connections made
+    fn serve(listener: TcpListener, response: &'static [u8]) -> Arc<AtomicUsize> {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                // The request is small enough to arrive at once, its contents do not matter
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).await;
+                stream.write_all(response).await.ok();
+            }
+        });
+        connections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::IpAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use futures_util::FutureExt;
+    use reqwest::{dns::Resolve, Url};
+
+    use super::{is_public_ip, is_raster_image, sign, testing::RebindingHost, verify, MediaProxy, PublicResolver};
+    use crate::models::errors::RESTError;
+
+    #[test]
+    fn test_sign_verify() {
+        let signature = sign("secret", "https://example.com/image.png");
+        assert!(verify("secret", "https://example.com/image.png", &signature));
+        assert!(!verify("other", "https://example.com/image.png", &signature));
+        assert!(!verify("secret", "https://example.com/other.png", &signature));
+        assert!(!verify("secret", "https://example.com/image.png", "not-hex"));
+    }
+
+    #[test]
+    fn test_is_public_ip() {
+        let public: [IpAddr; 4] = [
+            "1.1.1.1".parse().expect("valid IP"),
+            "2606:4700::1111".parse().expect("valid IP"),
+            "64:ff9b::101:101".parse().expect("valid IP"),
+            "2002:101:101::1".parse().expect("valid IP"),
+        ];
+        let private: [IpAddr; 20] = [
+            "127.0.0.1".parse().expect("valid IP"),
+            "10.0.0.1".parse().expect("valid IP"),
+            "169.254.169.254".parse().expect("valid IP"),
+            "100.64.0.1".parse().expect("valid IP"),
+            "0.1.2.3".parse().expect("valid IP"),
+            "224.0.0.1".parse().expect("valid IP"),
+            "240.0.0.1".parse().expect("valid IP"),
+            "255.255.255.255".parse().expect("valid IP"),
+            "198.19.0.1".parse().expect("valid IP"),
+            "192.0.0.8".parse().expect("valid IP"),
+            "::".parse().expect("valid IP"),
+            "::1".parse().expect("valid IP"),
+            "::ffff:192.168.0.1".parse().expect("valid IP"),
+            "::127.0.0.1".parse().expect("valid IP"),
+            "ff02::1".parse().expect("valid IP"),
+            "fec0::1".parse().expect("valid IP"),
+            "fd00::1".parse().expect("valid IP"),
+            "64:ff9b::a9fe:a9fe".parse().expect("valid IP"),
+            "64:ff9b::7f00:1".parse().expect("valid IP"),
+            "2002:a00:1::1".parse().expect("valid IP"),
+        ];
+        assert!(public.into_iter().all(is_public_ip));
+        assert!(!private.into_iter().any(is_public_ip));
+    }
+
+    #[test]
+    fn test_is_raster_image() {
+        let mime = |ct: &str| ct.parse().expect("valid MIME type");
+        assert!(is_raster_image(&mime("image/png")));
+        assert!(is_raster_image(&mime("image/webp")));
+        assert!(!is_raster_image(&mime("image/svg+xml")));
+        assert!(!is_raster_image(&mime("text/html")));
+    }
+
+    #[tokio::test]
+    async fn test_resolver_checks_every_lookup() {
+        // A host that rebinds to an internal address after it was first looked up
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counter = lookups.clone();
+        let resolver = PublicResolver::with_lookup(
+            move |_| {
+                let ip = if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    "1.1.1.1"
+                } else {
+                    "169.254.169.254"
+                };
+                async move { Ok(vec![ip.parse().expect("valid IP")]) }.boxed()
+            },
+            is_public_ip,
+        );
+
+        assert!(resolver.resolve_public("rebind.example").await.is_ok());
+        assert!(matches!(
+            resolver.resolve_public("rebind.example").await,
+            Err(RESTError::Forbidden(_))
+        ));
+        // The lookup made to connect is checked the same way
+        let name = "rebind.example".parse().expect("valid host name");
+        assert!(Resolve::resolve(&resolver, name).await.is_err());
+        assert_eq!(lookups.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_download_connects_to_checked_address() {
+        let host = RebindingHost::serve(
+            b"HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: 4\r\nConnection: close\r\n\r\nPNG!",
+        )
+        .await;
+        let proxy = MediaProxy::with_resolver(host.resolver);
+        let url = Url::parse(&format!("http://media.example:{}/cat.png", host.port)).expect("valid URL");
+
+        // Re-hosting downloads from the address that was checked, and only from it
+        let media = proxy.download(&url, 1024).await.expect("Download should succeed");
+        assert_eq!(media.content().as_ref(), b"PNG!");
+        assert_eq!(host.checked.load(Ordering::SeqCst), 1);
+
+        // Once the host resolves to the internal address, no connection is made at all
+        assert!(proxy.download(&url, 1024).await.is_err());
+        assert_eq!(host.internal.load(Ordering::SeqCst), 0);
+        assert_eq!(host.lookups.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,4 +1,4 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 
 use crate::gateway::handler::Gateway;
@@ -6,10 +6,11 @@ use crate::gateway::handler::Gateway;
 use super::{
     avatar::{Avatar, PartialAvatar},
     errors::BuildError,
+    guest_link::GuestAccess,
     guild::Guild,
 };
 
-use super::{snowflake::Snowflake, user::User};
+use super::{channel::Channel, snowflake::Snowflake, user::User};
 
 /// Represents a guild member record stored in the database.
 pub struct MemberRecord {
@@ -17,6 +18,9 @@ pub struct MemberRecord {
     pub guild_id: Snowflake<Guild>,
     pub nickname: Option<String>,
     pub joined_at: i64,
+    pub timeout_until: Option<i64>,
+    pub guest_until: Option<i64>,
+    pub guest_channel_ids: Option<Vec<i64>>,
 }
 
 /// Represents a guild member record with associated user data as queried.
@@ -25,6 +29,9 @@ pub struct ExtendedMemberRecord {
     pub guild_id: Snowflake<Guild>,
     pub nickname: Option<String>,
     pub joined_at: i64,
+    pub timeout_until: Option<i64>,
+    pub guest_until: Option<i64>,
+    pub guest_channel_ids: Option<Vec<i64>>,
     pub username: String,
     pub display_name: Option<String>,
     pub avatar_hash: Option<String>,
@@ -41,6 +48,10 @@ pub struct Member {
     nickname: Option<String>,
     /// UNIX timestmap of when the user joined the guild
     joined_at: i64,
+    /// UNIX timestamp of when the member's timeout expires, if they are timed out
+    timeout_until: Option<i64>,
+    /// The channels the member can access and when they are removed, if they joined through a guest link
+    guest: Option<GuestAccess>,
 }
 
 impl Member {
@@ -51,6 +62,8 @@ impl Member {
             guild_id: guild.into(),
             nickname,
             joined_at,
+            timeout_until: None,
+            guest: None,
         }
     }
 
@@ -74,18 +87,60 @@ impl Member {
         self.joined_at
     }
 
+    /// UNIX timestamp of when the member's timeout expires, if they are timed out
+    pub const fn timeout_until(&self) -> Option<i64> {
+        self.timeout_until
+    }
+
+    /// Set or clear the member's timeout.
+    ///
+    /// ## Arguments
+    ///
+    /// * `until` - UNIX timestamp of when the timeout expires, or `None` to clear it
+    /// * `now` - The current time. Timeouts that already expired are cleared.
+    pub fn set_timeout_until(&mut self, until: Option<i64>, now: DateTime<Utc>) {
+        self.timeout_until = until.filter(|t| *t > now.timestamp());
+    }
+
+    /// Whether the member is timed out at the given time.
+    /// Timed out members may not send messages in the guild.
+    pub fn is_timed_out(&self, now: DateTime<Utc>) -> bool {
+        self.timeout_until.is_some_and(|t| t > now.timestamp())
+    }
+
+    /// The channels the member can access and when they are removed, if they joined through a guest link
+    pub const fn guest(&self) -> Option<&GuestAccess> {
+        self.guest.as_ref()
+    }
+
+    /// Whether the member joined through a guest link and was not made a full member since.
+    pub const fn is_guest(&self) -> bool {
+        self.guest.is_some()
+    }
+
+    /// Whether the member can view and send messages in the given channel of their guild.
+    /// Guests can only access the channels of the link they joined through.
+    pub fn can_access(&self, channel: impl Into<Snowflake<Channel>>) -> bool {
+        self.guest.as_ref().is_none_or(|guest| guest.can_access(channel))
+    }
+
     /// Mutable handle to the user this guild member represents
-    pub fn user_mut(&mut self) -> &mut User {
+    pub const fn user_mut(&mut self) -> &mut User {
         &mut self.user
     }
 
-    /// Build a member object directly from a database record and a user
-    pub fn from_record(user: User, record: MemberRecord) -> Self {
-        Self::new(user, record.guild_id, record.nickname, record.joined_at)
+    /// Build a member object directly from a database record and a user.
+    /// Expired timeouts are cleared based on `now`.
+    pub fn from_record(user: User, record: MemberRecord, now: DateTime<Utc>) -> Self {
+        let mut member = Self::new(user, record.guild_id, record.nickname, record.joined_at);
+        member.set_timeout_until(record.timeout_until, now);
+        member.guest = GuestAccess::from_columns(record.guest_until, record.guest_channel_ids);
+        member
     }
 
     /// Build a member object directly from a database record.
     /// The user is contained in the record, so it will not be fetched from the database.
+    /// Expired timeouts are cleared based on `now`.
     ///
     /// # Returns
     ///
@@ -94,7 +149,7 @@ impl Member {
     /// # Errors
     ///
     /// Returns a `BuildError` if the user object could not be built.
-    pub fn from_extended_record(record: ExtendedMemberRecord) -> Result<Self, BuildError> {
+    pub fn from_extended_record(record: ExtendedMemberRecord, now: DateTime<Utc>) -> Result<Self, BuildError> {
         let mut builder = User::builder();
 
         if let Some(display_name) = record.display_name {
@@ -112,19 +167,27 @@ impl Member {
             .build()
             .expect("Failed to build user object.");
 
-        Ok(Self::new(user, record.guild_id, record.nickname, record.joined_at))
+        let mut member = Self::new(user, record.guild_id, record.nickname, record.joined_at);
+        member.set_timeout_until(record.timeout_until, now);
+        member.guest = GuestAccess::from_columns(record.guest_until, record.guest_channel_ids);
+        Ok(member)
     }
 
     /// Convert a user into a member with the given guild id.
-    /// The join date of the member will be set to the current time.
-    pub fn from_user(user: User, guild: impl Into<Snowflake<Guild>>) -> Self {
-        Self::new(user, guild.into(), None, Utc::now().timestamp())
+    /// The join date of the member will be set to `now`.
+    pub fn from_user(user: User, guild: impl Into<Snowflake<Guild>>, now: DateTime<Utc>) -> Self {
+        Self::new(user, guild.into(), None, now.timestamp())
     }
 
     /// Include the user's presence field in the member payload.
+    /// Users who hide their presence from the member's guild appear offline.
     #[must_use]
     pub fn include_presence(self, gateway: &Gateway) -> Self {
-        let user = self.user.include_presence(gateway);
+        let user = if gateway.is_presence_visible_in(self.user.id(), self.guild_id) {
+            self.user.include_presence(gateway)
+        } else {
+            self.user.include_hidden_presence()
+        };
         Self { user, ..self }
     }
 }
@@ -5,14 +5,19 @@ use serde::Serialize;
 use slice_group_by::GroupBy;
 
 use super::{
-    attachment::{Attachment, AttachmentLike, FullAttachment},
+    attachment::{Attachment, AttachmentLike, FullAttachment, ScanStatus},
     avatar::{Avatar, PartialAvatar, UserAvatar},
     channel::Channel,
+    content::{ChannelMention, ProcessedContent},
+    crosspost::CrosspostSource,
+    embed::Embed,
     errors::{BuildError, RESTError},
+    limits::{Limit, Limits},
     member::UserLike,
     requests::CreateMessage,
     snowflake::Snowflake,
     state::Config,
+    upload_throttle::UploadThrottle,
     user::User,
 };
 
@@ -30,6 +35,11 @@ pub struct ExtendedMessageRecord {
     pub id: i64,
     pub channel_id: i64,
     pub content: Option<String>,
+    pub mentions: Vec<i64>,
+    pub channel_mentions: Vec<i64>,
+    pub embeds: Option<String>,
+    pub mention_channels: Option<String>,
+    pub crosspost: Option<String>,
     pub user_id: Option<Snowflake<User>>,
     pub username: Option<String>,
     pub display_name: Option<String>,
@@ -37,11 +47,99 @@ pub struct ExtendedMessageRecord {
     pub attachment_id: Option<i32>,
     pub attachment_filename: Option<String>,
     pub attachment_content_type: Option<String>,
+    pub attachment_width: Option<i32>,
+    pub attachment_height: Option<i32>,
+    pub attachment_duration: Option<f64>,
+    pub attachment_blurhash: Option<String>,
+    pub attachment_scan_status: Option<i16>,
+    pub attachment_key_token: Option<String>,
+}
+
+/// Represents a message record as queried when only some of its fields are selected.
+/// The author fields are `None` if the author was not selected, or has been deleted.
+pub struct PartialMessageRecord {
+    pub id: i64,
+    pub channel_id: i64,
+    pub content: Option<String>,
+    pub mentions: Vec<i64>,
+    pub channel_mentions: Vec<i64>,
+    pub embeds: Option<String>,
+    pub mention_channels: Option<String>,
+    pub crosspost: Option<String>,
+    pub user_id: Option<Snowflake<User>>,
+    pub username: Option<String>,
+    pub display_name: Option<String>,
+    pub avatar_hash: Option<String>,
+}
+
+/// The optional fields of a message a client may leave out when fetching messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageFields {
+    /// The author's user data. The ID of the author is always included.
+    pub author: bool,
+    /// The attachments of the message.
+    pub attachments: bool,
+    /// The embeds of the message.
+    pub embeds: bool,
+}
+
+impl Default for MessageFields {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl MessageFields {
+    /// Every optional field, as included in a full [`Message`].
+    pub const fn all() -> Self {
+        Self {
+            author: true,
+            attachments: true,
+            embeds: true,
+        }
+    }
+
+    /// None of the optional fields, leaving only the IDs, content and mentions of a message.
+    pub const fn none() -> Self {
+        Self {
+            author: false,
+            attachments: false,
+            embeds: false,
+        }
+    }
+
+    /// Parse the selected fields of a query, given as comma-separated lists of field names.
+    ///
+    /// ## Arguments
+    ///
+    /// * `fields` - The fields to include, all fields are included if `None`
+    /// * `exclude` - The fields to leave out of `fields`
+    ///
+    /// ## Errors
+    ///
+    /// * [`RESTError::BadRequest`] - If a field name is unknown
+    pub fn from_query(fields: Option<&str>, exclude: Option<&str>) -> Result<Self, RESTError> {
+        let mut selected = if fields.is_some() { Self::none() } else { Self::all() };
+
+        for (names, included) in [(fields, true), (exclude, false)] {
+            for name in names.into_iter().flat_map(|n| n.split(',')).map(str::trim) {
+                match name {
+                    "author" => selected.author = included,
+                    "attachments" => selected.attachments = included,
+                    "embeds" => selected.embeds = included,
+                    "" => {}
+                    _ => return Err(RESTError::BadRequest(format!("Unknown message field: {name}"))),
+                }
+            }
+        }
+        Ok(selected)
+    }
 }
 
 /// A chat message.
 #[derive(Serialize, Debug, Clone, Builder)]
 #[builder(setter(into), build_fn(validate = "Self::validate", error = "BuildError"))]
+#[allow(clippy::use_self)] // Self would refer to the builder in the generated code
 pub struct Message {
     /// The id of the message.
     id: Snowflake<Message>,
@@ -49,8 +147,9 @@ pub struct Message {
     /// The id of the channel this message was sent in.
     channel_id: Snowflake<Channel>,
 
-    /// The author of the message. This may be none if the author has been deleted since.
-    #[builder(setter(strip_option))]
+    /// The author of the message. This may be none if the author has been deleted since,
+    /// or if the message was posted by the server, such as by a feed.
+    #[builder(default, setter(strip_option))]
     author: Option<UserLike>,
 
     /// A nonce that can be used by a client to determine if the message was sent.
@@ -65,14 +164,35 @@ pub struct Message {
     /// Attachments sent with this message.
     #[builder(default)]
     attachments: Vec<Attachment>,
+
+    /// The users mentioned in the content of the message.
+    #[builder(default)]
+    mentions: Vec<Snowflake<User>>,
+
+    /// The channels mentioned in the content of the message.
+    #[builder(default)]
+    channel_mentions: Vec<Snowflake<Channel>>,
+
+    /// The channels mentioned in the content of the message, with their names when it was sent.
+    #[builder(default)]
+    mention_channels: Vec<ChannelMention>,
+
+    /// Rich content attached to the message by the server.
+    #[builder(default)]
+    embeds: Vec<Embed>,
+
+    /// Where the message was originally sent, if it was crossposted from an announcement channel.
+    #[builder(default, setter(strip_option))]
+    crosspost: Option<CrosspostSource>,
 }
 
 impl MessageBuilder {
     fn validate(&self) -> Result<(), String> {
         if self.content.is_none()
             && (self.attachments.is_none() || self.attachments.as_ref().is_some_and(Vec::is_empty))
+            && (self.embeds.is_none() || self.embeds.as_ref().is_some_and(Vec::is_empty))
         {
-            Err("Message must have content or attachments".to_string())
+            Err("Message must have content, attachments or embeds".to_string())
         } else {
             Ok(())
         }
@@ -92,7 +212,7 @@ impl Message {
 
     /// The user who sent this message.
     ///
-    /// This may be `None` if the author has been deleted since.
+    /// This may be `None` if the author has been deleted since, or if the message was posted by the server.
     pub const fn author(&self) -> Option<&UserLike> {
         self.author.as_ref()
     }
@@ -112,13 +232,18 @@ impl Message {
         self.nonce.as_ref()
     }
 
+    /// Remove the nonce of the message. The nonce should only be sent to clients when the message is created.
+    pub fn clear_nonce(&mut self) {
+        self.nonce = None;
+    }
+
     /// The content of the message.
     pub const fn content(&self) -> Option<&String> {
         self.content.as_ref()
     }
 
     /// Mutable handle to the content of the message.
-    pub fn content_mut(&mut self) -> Option<&mut String> {
+    pub const fn content_mut(&mut self) -> Option<&mut String> {
         self.content.as_mut()
     }
 
@@ -127,6 +252,89 @@ impl Message {
         &self.attachments
     }
 
+    /// Mutable handle to the attachments sent with this message.
+    pub fn attachments_mut(&mut self) -> &mut [Attachment] {
+        &mut self.attachments
+    }
+
+    /// Add an attachment to this message. The attachment ID must not be taken by another attachment.
+    pub fn add_attachment(&mut self, attachment: Attachment) {
+        self.attachments.push(attachment);
+    }
+
+    /// The users mentioned in the content of the message.
+    pub fn mentions(&self) -> &[Snowflake<User>] {
+        &self.mentions
+    }
+
+    /// The channels mentioned in the content of the message.
+    pub fn channel_mentions(&self) -> &[Snowflake<Channel>] {
+        &self.channel_mentions
+    }
+
+    /// The channels mentioned in the content of the message, with their names when it was sent.
+    pub fn mention_channels(&self) -> &[ChannelMention] {
+        &self.mention_channels
+    }
+
+    /// Rich content attached to the message by the server.
+    pub fn embeds(&self) -> &[Embed] {
+        &self.embeds
+    }
+
+    /// Where the message was originally sent, if it was crossposted from an announcement channel.
+    pub const fn crosspost(&self) -> Option<&CrosspostSource> {
+        self.crosspost.as_ref()
+    }
+
+    /// Create a copy of the message to post in a channel following its channel.
+    /// The copy mentions nobody, and attachments are not copied.
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the copy.
+    /// * `channel` - The channel to post the copy in.
+    /// * `source` - Where the message was originally sent.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the message has neither content nor embeds.
+    pub fn to_crosspost(
+        &self,
+        id: Snowflake<Self>,
+        channel: impl Into<Snowflake<Channel>>,
+        source: CrosspostSource,
+    ) -> Result<Self, BuildError> {
+        Self::builder()
+            .id(id)
+            .channel_id(channel)
+            .content(self.content.clone())
+            .channel_mentions(self.channel_mentions.clone())
+            .mention_channels(self.mention_channels.clone())
+            .embeds(self.embeds.clone())
+            .crosspost(source)
+            .build()
+    }
+
+    /// Replace the content of the message with its processed form, including the mentioned entities.
+    /// Empty content is removed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the message would have neither content nor attachments.
+    pub fn set_processed_content(&mut self, processed: ProcessedContent) -> Result<(), BuildError> {
+        if processed.content.is_empty() && self.attachments.is_empty() && self.embeds.is_empty() {
+            return Err(BuildError::ValidationError(
+                "Message must have content or attachments".to_string(),
+            ));
+        }
+        self.content = Some(processed.content).filter(|c| !c.is_empty());
+        self.mentions = processed.mentions;
+        self.channel_mentions = processed.channel_mentions;
+        self.mention_channels = processed.mention_channels;
+        Ok(())
+    }
+
     /// Create a new message or messages from the given records. Multiple records are linked together by their ID.
     ///
     /// ## Errors
@@ -140,25 +348,12 @@ impl Message {
         records
             .linear_group_by(|a, b| a.id == b.id)
             .map(|group| {
-                let author = {
-                    if let Some(user_id) = group[0].user_id {
-                        let avatar: Option<Avatar<UserAvatar>> = group[0]
-                            .avatar_hash
-                            .clone()
-                            .map(|h| PartialAvatar::new(h, user_id).map(Avatar::Partial))
-                            .transpose()?;
-
-                        let user = User::builder()
-                            .id(user_id)
-                            .username(group[0].username.clone().expect("User should have username")) // SAFETY: This is safe because user_id is not None.
-                            .display_name(group[0].display_name.clone())
-                            .avatar(avatar)
-                            .build()?;
-                        Some(UserLike::User(user))
-                    } else {
-                        None
-                    }
-                };
+                let author = build_author(
+                    group[0].user_id,
+                    group[0].username.as_ref(),
+                    group[0].display_name.as_ref(),
+                    group[0].avatar_hash.as_ref(),
+                )?;
 
                 let attachments = group
                     .iter()
@@ -173,23 +368,38 @@ impl Message {
                     content: group[0].content.clone(),
                     nonce: None,
                     attachments,
+                    mentions: group[0].mentions.iter().copied().map(Snowflake::from).collect(),
+                    channel_mentions: group[0].channel_mentions.iter().copied().map(Snowflake::from).collect(),
+                    mention_channels: parse_mention_channels(group[0].mention_channels.as_deref()),
+                    // Embeds are only written by the server, a malformed value is treated as having none
+                    embeds: group[0]
+                        .embeds
+                        .as_deref()
+                        .and_then(|embeds| serde_json::from_str(embeds).ok())
+                        .unwrap_or_default(),
+                    crosspost: parse_crosspost(group[0].crosspost.as_deref()),
                 })
             })
             .collect()
     }
 
     /// Create a new message from the given formdata. Assigns a new snowflake to the message.
+    /// Attachments are read at the rate allowed by the upload throttle, and are pending a scan if scanning is enabled.
+    /// Attachments larger than the `MaxAttachmentSize` of `limits` are rejected.
     ///
     /// ## Errors
     ///
     /// * [`RESTError`] - If the formdata is invalid
     pub async fn from_formdata(
         config: &Config,
+        limits: &Limits,
+        throttle: &UploadThrottle,
         author: UserLike,
         channel: impl Into<Snowflake<Channel>>,
         mut form: Multipart,
     ) -> Result<Self, RESTError> {
         let id = Snowflake::gen_new(config);
+        let author_id = author.id();
         let channel_id: Snowflake<Channel> = channel.into();
         let mut attachments: Vec<Attachment> = Vec::new();
         let mut builder = Self::builder();
@@ -206,8 +416,19 @@ impl Message {
                 let payload = serde_json::from_slice::<CreateMessage>(&data)?;
                 builder.content(payload.content).nonce(payload.nonce.clone());
             } else {
-                let attachment = FullAttachment::try_from_field(part, channel_id, id).await?;
-
+                let mut attachment = FullAttachment::try_from_field(
+                    part,
+                    channel_id,
+                    id,
+                    throttle,
+                    author_id,
+                    limits.max(Limit::MaxAttachmentSize),
+                )
+                .await?;
+
+                if config.clamav_addr().is_some() {
+                    attachment.set_scan_status(ScanStatus::Pending);
+                }
                 if attachments.iter().any(|a| a.id() == attachment.id()) {
                     return Err(RESTError::DuplicateField("attachment.id".to_string()));
                 }
@@ -236,6 +457,166 @@ impl Message {
     }
 }
 
+/// Build the author of a message from its queried user data, or `None` if the author has been deleted.
+fn build_author(
+    user_id: Option<Snowflake<User>>,
+    username: Option<&String>,
+    display_name: Option<&String>,
+    avatar_hash: Option<&String>,
+) -> Result<Option<UserLike>, BuildError> {
+    let Some(user_id) = user_id else {
+        return Ok(None);
+    };
+    let avatar: Option<Avatar<UserAvatar>> = avatar_hash
+        .cloned()
+        .map(|h| PartialAvatar::new(h, user_id).map(Avatar::Partial))
+        .transpose()?;
+
+    let user = User::builder()
+        .id(user_id)
+        .username(username.cloned().expect("User should have username")) // SAFETY: This is safe because user_id is not None.
+        .display_name(display_name.cloned())
+        .avatar(avatar)
+        .build()?;
+    Ok(Some(UserLike::User(user)))
+}
+
+/// Parse the stored names of mentioned channels.
+/// They are only written by the server, a malformed value is treated as mentioning none.
+fn parse_mention_channels(mention_channels: Option<&str>) -> Vec<ChannelMention> {
+    mention_channels
+        .and_then(|channels| serde_json::from_str(channels).ok())
+        .unwrap_or_default()
+}
+
+/// Parse the stored source of a crossposted message.
+/// It is only written by the server, a malformed value is treated as not being a crosspost.
+fn parse_crosspost(crosspost: Option<&str>) -> Option<CrosspostSource> {
+    crosspost.and_then(|source| serde_json::from_str(source).ok())
+}
+
+/// A chat message with only some of its optional fields, as selected by [`MessageFields`].
+/// Fields that were not selected are left out when serialized.
+#[derive(Serialize, Debug, Clone)]
+pub struct PartialMessage {
+    /// The id of the message.
+    id: Snowflake<Message>,
+
+    /// The id of the channel this message was sent in.
+    channel_id: Snowflake<Channel>,
+
+    /// The ID of the author of the message, or `None` if the author has been deleted or the message was posted by the server.
+    author_id: Option<Snowflake<User>>,
+
+    /// The author of the message, if selected and the author still exists.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<UserLike>,
+
+    /// The content of the message.
+    content: Option<String>,
+
+    /// The users mentioned in the content of the message.
+    mentions: Vec<Snowflake<User>>,
+
+    /// The channels mentioned in the content of the message.
+    channel_mentions: Vec<Snowflake<Channel>>,
+
+    /// The channels mentioned in the content of the message, with their names when it was sent.
+    mention_channels: Vec<ChannelMention>,
+
+    /// Attachments sent with this message, if selected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attachments: Option<Vec<Attachment>>,
+
+    /// Rich content attached to the message by the server, if selected.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embeds: Option<Vec<Embed>>,
+
+    /// Where the message was originally sent, if it was crossposted from an announcement channel.
+    crosspost: Option<CrosspostSource>,
+}
+
+impl PartialMessage {
+    /// The unique ID of this message.
+    pub const fn id(&self) -> Snowflake<Message> {
+        self.id
+    }
+
+    /// The ID of the user who sent this message.
+    pub const fn author_id(&self) -> Option<Snowflake<User>> {
+        self.author_id
+    }
+
+    /// The user who sent this message, if selected.
+    pub const fn author(&self) -> Option<&UserLike> {
+        self.author.as_ref()
+    }
+
+    /// The content of the message.
+    pub const fn content(&self) -> Option<&String> {
+        self.content.as_ref()
+    }
+
+    /// The attachments sent with this message, if selected.
+    pub fn attachments(&self) -> Option<&[Attachment]> {
+        self.attachments.as_deref()
+    }
+
+    /// Rich content attached to the message by the server, if selected.
+    pub fn embeds(&self) -> Option<&[Embed]> {
+        self.embeds.as_deref()
+    }
+
+    /// Create a new partial message from the given record.
+    ///
+    /// ## Arguments
+    ///
+    /// * `record` - The record of the message
+    /// * `fields` - The optional fields that were selected
+    /// * `attachments` - The attachments of the message, ignored if they were not selected
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError`] - If the record is invalid
+    pub fn from_record(
+        record: PartialMessageRecord,
+        fields: MessageFields,
+        attachments: Vec<Attachment>,
+    ) -> Result<Self, BuildError> {
+        let author = if fields.author {
+            build_author(
+                record.user_id,
+                record.username.as_ref(),
+                record.display_name.as_ref(),
+                record.avatar_hash.as_ref(),
+            )?
+        } else {
+            None
+        };
+
+        Ok(Self {
+            id: record.id.into(),
+            channel_id: record.channel_id.into(),
+            author_id: record.user_id,
+            author,
+            content: record.content,
+            mentions: record.mentions.into_iter().map(Snowflake::from).collect(),
+            channel_mentions: record.channel_mentions.into_iter().map(Snowflake::from).collect(),
+            mention_channels: parse_mention_channels(record.mention_channels.as_deref()),
+            attachments: fields.attachments.then_some(attachments),
+            // Embeds are only written by the server, a malformed value is treated as having none
+            embeds: fields.embeds.then(|| {
+                record
+                    .embeds
+                    .as_deref()
+                    .and_then(|embeds| serde_json::from_str(embeds).ok())
+                    .unwrap_or_default()
+            }),
+            crosspost: parse_crosspost(record.crosspost.as_deref()),
+        })
+    }
+}
+
 impl From<Message> for Snowflake<Message> {
     fn from(message: Message) -> Self {
         message.id()
@@ -247,3 +628,32 @@ impl From<&Message> for Snowflake<Message> {
         message.id()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::MessageFields;
+
+    #[test]
+    fn test_fields_from_query() {
+        assert_eq!(
+            MessageFields::from_query(None, None).expect("Should parse"),
+            MessageFields::all()
+        );
+        assert_eq!(
+            MessageFields::from_query(Some(""), None).expect("Should parse"),
+            MessageFields::none()
+        );
+
+        let fields = MessageFields::from_query(Some("author, embeds"), None).expect("Should parse");
+        assert!(fields.author && fields.embeds && !fields.attachments);
+
+        let fields = MessageFields::from_query(None, Some("attachments,author")).expect("Should parse");
+        assert!(!fields.author && fields.embeds && !fields.attachments);
+
+        let fields = MessageFields::from_query(Some("author,attachments"), Some("author")).expect("Should parse");
+        assert!(!fields.author && !fields.embeds && fields.attachments);
+
+        assert!(MessageFields::from_query(Some("content"), None).is_err());
+        assert!(MessageFields::from_query(None, Some("nonce")).is_err());
+    }
+}
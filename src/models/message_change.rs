@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{Executor, Postgres};
+
+use super::{
+    channel::Channel,
+    message::Message,
+    outbox::OutboxRecord,
+    snowflake::{Snowflake, SnowflakeSource},
+};
+
+/// How long changes to messages are kept, in seconds.
+pub const MESSAGE_CHANGE_RETENTION: i64 = 7 * 24 * 60 * 60;
+/// The amount of changes returned at once if not specified.
+pub const DEFAULT_CHANGES_LIMIT: u32 = 100;
+/// The maximum amount of changes returned at once.
+pub const MAX_CHANGES_LIMIT: u32 = 1000;
+
+/// What happened to a message.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum MessageChangeKind {
+    /// The message was sent.
+    Create = 0,
+    /// The message was edited, or its attachments were processed.
+    Update = 1,
+    /// The message was deleted.
+    Delete = 2,
+}
+
+impl From<i16> for MessageChangeKind {
+    fn from(kind: i16) -> Self {
+        match kind {
+            0 => Self::Create,
+            1 => Self::Update,
+            _ => Self::Delete,
+        }
+    }
+}
+
+/// The parts of the events in the outbox needed to record changes to messages.
+#[derive(Deserialize)]
+#[serde(tag = "event", content = "data")]
+enum ChangedMessages {
+    #[serde(rename = "MESSAGE_CREATE")]
+    Create { id: Snowflake<Message> },
+    #[serde(rename = "MESSAGE_UPDATE")]
+    Update { id: Snowflake<Message> },
+    #[serde(rename = "MESSAGE_BULK_REMOVE")]
+    BulkRemove { ids: Vec<Snowflake<Message>> },
+}
+
+/// A change to a message that is about to be dispatched, and has no ID yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingMessageChange {
+    channel_id: Snowflake<Channel>,
+    message_id: Snowflake<Message>,
+    kind: MessageChangeKind,
+}
+
+impl PendingMessageChange {
+    /// The changes to messages an event in the outbox announces. Events about anything else announce none.
+    ///
+    /// ## Arguments
+    ///
+    /// * `record` - The event read from the outbox.
+    pub fn from_outbox(record: &OutboxRecord) -> Vec<Self> {
+        let Some(channel_id) = record.channel_id.map(Snowflake::new) else {
+            return Vec::new();
+        };
+        if !matches!(
+            record.event.as_str(),
+            "MESSAGE_CREATE" | "MESSAGE_UPDATE" | "MESSAGE_BULK_REMOVE"
+        ) {
+            return Vec::new();
+        }
+
+        let change = |message_id, kind| Self {
+            channel_id,
+            message_id,
+            kind,
+        };
+        match serde_json::from_str(&record.payload) {
+            Ok(ChangedMessages::Create { id }) => vec![change(id, MessageChangeKind::Create)],
+            Ok(ChangedMessages::Update { id }) => vec![change(id, MessageChangeKind::Update)],
+            Ok(ChangedMessages::BulkRemove { ids }) => ids
+                .into_iter()
+                .map(|id| change(id, MessageChangeKind::Delete))
+                .collect(),
+            Err(e) => {
+                tracing::warn!(error = %e, event = %record.event, "Failed to read changed messages from event");
+                Vec::new()
+            }
+        }
+    }
+
+    /// Record changes as part of the transaction dispatching them. Assigns a new snowflake to every change.
+    ///
+    /// Changes must be recorded in the order they are dispatched, so that their IDs can be used as a cursor.
+    ///
+    /// ## Arguments
+    ///
+    /// * `conn` - The transaction dispatching the changes.
+    /// * `source` - The source of the snowflakes, usually the application configuration.
+    /// * `changes` - The changes to record, in the order they are dispatched.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn record(
+        conn: impl Executor<'_, Database = Postgres>,
+        source: &impl SnowflakeSource,
+        changes: &[Self],
+    ) -> Result<(), sqlx::Error> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let ids: Vec<i64> = changes
+            .iter()
+            .map(|_| Snowflake::<MessageChange>::gen_new(source).into())
+            .collect();
+        let channel_ids: Vec<i64> = changes.iter().map(|change| change.channel_id.into()).collect();
+        let message_ids: Vec<i64> = changes.iter().map(|change| change.message_id.into()).collect();
+        let kinds: Vec<i16> = changes.iter().map(|change| change.kind as i16).collect();
+
+        sqlx::query!(
+            "INSERT INTO message_changes (id, channel_id, message_id, kind)
+            SELECT * FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::BIGINT[], $4::SMALLINT[])",
+            &ids,
+            &channel_ids,
+            &message_ids,
+            &kinds
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Represents a change to a message stored in the database.
+pub struct MessageChangeRecord {
+    pub id: i64,
+    pub message_id: i64,
+    pub kind: i16,
+}
+
+/// A change to a message in a channel.
+#[derive(Serialize, Debug, Clone)]
+pub struct MessageChange {
+    /// The ID of the change, changes are returned in the order of their IDs.
+    id: Snowflake<Self>,
+    /// What happened to the message.
+    #[serde(rename = "type")]
+    kind: MessageChangeKind,
+    /// The ID of the changed message.
+    message_id: Snowflake<Message>,
+    /// The current state of the message, or `None` if it was deleted.
+    message: Option<Message>,
+}
+
+impl MessageChange {
+    /// Build a change from its record and the current state of the message.
+    pub fn from_record(record: &MessageChangeRecord, message: Option<Message>) -> Self {
+        let kind = MessageChangeKind::from(record.kind);
+        Self {
+            id: Snowflake::new(record.id),
+            kind,
+            message_id: Snowflake::new(record.message_id),
+            message: message.filter(|_| kind != MessageChangeKind::Delete),
+        }
+    }
+
+    /// The ID of the change.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+}
+
+/// A page of changes to the messages of a channel.
+#[derive(Serialize, Debug, Clone)]
+pub struct MessageChanges {
+    /// The changes, oldest first.
+    changes: Vec<MessageChange>,
+    /// The ID to pass as `since` to fetch the changes after this page.
+    cursor: Snowflake<MessageChange>,
+}
+
+impl MessageChanges {
+    /// Create a new page of changes.
+    ///
+    /// ## Arguments
+    ///
+    /// * `changes` - The changes, oldest first.
+    /// * `since` - The ID the changes were fetched after, this is the cursor if there are no changes.
+    pub fn new(changes: Vec<MessageChange>, since: Snowflake<MessageChange>) -> Self {
+        let cursor = changes.last().map_or(since, MessageChange::id);
+        Self { changes, cursor }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MessageChangeKind, PendingMessageChange};
+    use crate::models::{outbox::OutboxRecord, snowflake::Snowflake};
+
+    fn record(event: &str, payload: &str, channel_id: Option<i64>) -> OutboxRecord {
+        OutboxRecord {
+            id: 1,
+            event_id: None,
+            event: event.into(),
+            payload: payload.into(),
+            guild_id: None,
+            user_id: None,
+            channel_id,
+            recipient_id: None,
+            trace_context: None,
+        }
+    }
+
+    #[test]
+    fn test_from_outbox() {
+        let created = record(
+            "MESSAGE_CREATE",
+            r#"{"event":"MESSAGE_CREATE","data":{"id":"12","channel_id":"5","content":"Hi"}}"#,
+            Some(5),
+        );
+        let changes = PendingMessageChange::from_outbox(&created);
+        assert_eq!(
+            changes,
+            [PendingMessageChange {
+                channel_id: Snowflake::new(5),
+                message_id: Snowflake::new(12),
+                kind: MessageChangeKind::Create,
+            }]
+        );
+
+        let removed = record(
+            "MESSAGE_BULK_REMOVE",
+            r#"{"event":"MESSAGE_BULK_REMOVE","data":{"ids":["12","13"],"channel_id":"5","guild_id":null}}"#,
+            Some(5),
+        );
+        let changes = PendingMessageChange::from_outbox(&removed);
+        assert_eq!(
+            changes.iter().map(|change| change.message_id).collect::<Vec<_>>(),
+            [Snowflake::new(12), Snowflake::new(13)]
+        );
+        assert!(changes.iter().all(|change| change.kind == MessageChangeKind::Delete));
+
+        // Other events, and events without a channel, change no messages
+        let typing = record("TYPING_START", r#"{"event":"TYPING_START","data":{}}"#, Some(5));
+        assert!(PendingMessageChange::from_outbox(&typing).is_empty());
+        assert!(PendingMessageChange::from_outbox(&record("MESSAGE_CREATE", "{}", None)).is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::LazyLock;
+
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter, register_int_counter_vec, Encoder, HistogramVec,
+    IntCounter, IntCounterVec, TextEncoder,
+};
+
+/// The time taken by database queries, labelled by the normalized SQL of the query.
+pub static DB_QUERY_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "chat_db_query_duration_seconds",
+        "Time taken to execute database queries, in seconds.",
+        &["query"],
+        // 1ms to ~4s
+        exponential_buckets(0.001, 2.0, 13).expect("Histogram buckets are valid")
+    )
+    .expect("Metric is only registered once")
+});
+
+/// The time between an event being queued for a client and it being written to the client's socket,
+/// labelled by the name of the event.
+pub static GATEWAY_EVENT_LAG: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "chat_gateway_event_lag_seconds",
+        "Time between queueing a gateway event for a client and writing it to the client's socket, in seconds.",
+        &["event"],
+        // 100µs to ~6.5s
+        exponential_buckets(0.0001, 2.0, 17).expect("Histogram buckets are valid")
+    )
+    .expect("Metric is only registered once")
+});
+
+/// The amount of gateway sessions that were started, labelled by the device type reported by the client.
+pub static GATEWAY_SESSIONS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "chat_gateway_sessions_total",
+        "Gateway sessions that were started, by the device type reported by the client.",
+        &["device"]
+    )
+    .expect("Metric is only registered once")
+});
+
+/// The amount of gateway sessions closed by the server, labelled by the device type reported by the client
+/// and the close code, to tell which clients fail to heartbeat or send invalid payloads.
+pub static GATEWAY_SESSION_CLOSES: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "chat_gateway_session_closes_total",
+        "Gateway sessions closed by the server, by the device type reported by the client and the close code.",
+        &["device", "code"]
+    )
+    .expect("Metric is only registered once")
+});
+
+/// The amount of events that were not sent to the firehose, because its queue was full or its sink failed.
+pub static FIREHOSE_DROPPED_EVENTS: LazyLock<IntCounter> = LazyLock::new(|| {
+    register_int_counter!(
+        "chat_firehose_dropped_events_total",
+        "Events that were not sent to the firehose, because its queue was full or its sink failed."
+    )
+    .expect("Metric is only registered once")
+});
+
+/// Render all registered metrics in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .expect("Encoding metrics into a buffer cannot fail");
+    String::from_utf8(buffer).expect("Prometheus text format is valid UTF-8")
+}
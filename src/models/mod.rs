@@ -1,17 +1,70 @@
+pub mod analytics;
+pub mod announcement;
 pub mod attachment;
+pub mod audit_log;
 pub mod auth;
+pub mod automod;
 pub mod avatar;
 pub mod bucket;
+pub mod bulk;
 pub mod channel;
+pub mod circuit_breaker;
+pub mod clock;
+pub mod content;
+pub mod crosspost;
 pub mod data_uri;
 pub mod db;
+pub mod doctor;
+pub mod embed;
+pub mod emoji;
 pub mod errors;
+pub mod event_bus;
+pub mod feed;
+pub mod fingerprint;
+pub mod firehose;
 pub mod gateway_event;
+pub mod guest_link;
 pub mod guild;
+pub mod guild_folder;
+pub mod i18n;
+pub mod instance;
+pub mod invite;
+pub mod ip_filter;
+pub mod jobs;
+pub mod join_requirements;
+pub mod limits;
+pub mod load_shedder;
+pub mod log_filter;
+pub mod media_metadata;
+pub mod media_proxy;
 pub mod member;
 pub mod message;
+pub mod message_change;
+pub mod metrics;
+pub mod onboarding;
+pub mod outbox;
 pub mod prefs;
+pub mod presence_privacy;
+pub mod profile;
+pub mod push;
+pub mod raid_mode;
+pub mod rate_limit;
+pub mod report;
 pub mod requests;
+pub mod scanner;
+pub mod search;
+pub mod session;
+pub mod settings;
+pub mod shard;
 pub mod snowflake;
 pub mod state;
+pub mod strike;
+pub mod telemetry;
+pub mod translation;
+pub mod upload_throttle;
 pub mod user;
+pub mod webhook;
+pub mod welcome_message;
+
+// Shared with clients, see the `chat-core` crate
+pub use chat_core::close_code;
@@ -0,0 +1,446 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    channel::Channel,
+    errors::{BuildError, ErrorCode},
+    guild::Guild,
+    snowflake::Snowflake,
+};
+
+/// The most questions the onboarding of a guild may have.
+pub const MAX_ONBOARDING_QUESTIONS: usize = 15;
+/// The most options a single onboarding question may have.
+pub const MAX_ONBOARDING_OPTIONS: usize = 25;
+/// The most channels new members may be subscribed to by default, or by a single option.
+pub const MAX_ONBOARDING_CHANNELS: usize = 50;
+/// The maximum length of the title of a question or option, in characters.
+pub const MAX_ONBOARDING_TITLE_LENGTH: usize = 100;
+/// The maximum length of the description of an option, in characters.
+pub const MAX_ONBOARDING_DESCRIPTION_LENGTH: usize = 200;
+
+/// Represents the onboarding record of a guild stored in the database.
+pub struct OnboardingRecord {
+    pub enabled: bool,
+    pub default_channel_ids: Vec<i64>,
+    pub questions: String,
+}
+
+/// Represents the onboarding record of a member stored in the database.
+pub struct MemberOnboardingRecord {
+    pub guild_id: i64,
+    pub responses: String,
+    pub channel_ids: Vec<i64>,
+    pub completed_at: i64,
+}
+
+/// The questions a guild asks new members, and the channels they are subscribed to depending on their answers.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Onboarding {
+    /// Whether new members are asked to complete the onboarding.
+    enabled: bool,
+    /// The channels every member who completes the onboarding is subscribed to.
+    default_channel_ids: Vec<Snowflake<Channel>>,
+    /// The questions asked, in order.
+    questions: Vec<OnboardingQuestion>,
+}
+
+/// A question of the onboarding of a guild.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OnboardingQuestion {
+    /// The ID of the question, chosen by the guild owner and unique within the onboarding.
+    pub id: u32,
+    /// The question asked.
+    pub title: String,
+    /// Whether at least one option must be chosen.
+    #[serde(default)]
+    pub required: bool,
+    /// Whether at most one option may be chosen.
+    #[serde(default)]
+    pub single_select: bool,
+    /// The answers to choose from.
+    pub options: Vec<OnboardingOption>,
+}
+
+/// An answer to an onboarding question.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OnboardingOption {
+    /// The ID of the option, unique within its question.
+    pub id: u32,
+    /// The answer shown to the member.
+    pub title: String,
+    /// A longer explanation of the answer, if any.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// The channels members choosing this option are subscribed to.
+    #[serde(default)]
+    pub channel_ids: Vec<Snowflake<Channel>>,
+}
+
+/// The options a member chose for a single onboarding question.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OnboardingResponse {
+    /// The ID of the question answered.
+    pub question_id: u32,
+    /// The IDs of the chosen options.
+    pub option_ids: Vec<u32>,
+}
+
+/// How a member completed the onboarding of a guild.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct MemberOnboarding {
+    /// The guild the onboarding was completed in.
+    guild_id: Snowflake<Guild>,
+    /// The answers of the member.
+    responses: Vec<OnboardingResponse>,
+    /// The channels the member is subscribed to.
+    channel_ids: Vec<Snowflake<Channel>>,
+    /// UNIX timestamp of when the member completed the onboarding, in seconds.
+    completed_at: i64,
+}
+
+impl Onboarding {
+    /// Build the onboarding of a guild from a database record.
+    /// The questions are only written by the server, malformed questions are treated as having none.
+    pub fn from_record(record: OnboardingRecord) -> Self {
+        Self {
+            enabled: record.enabled,
+            default_channel_ids: record.default_channel_ids.into_iter().map(Snowflake::new).collect(),
+            questions: serde_json::from_str(&record.questions).unwrap_or_default(),
+        }
+    }
+
+    /// Whether new members are asked to complete the onboarding.
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// The channels every member who completes the onboarding is subscribed to.
+    pub fn default_channel_ids(&self) -> &[Snowflake<Channel>] {
+        &self.default_channel_ids
+    }
+
+    /// The questions asked, in order.
+    pub fn questions(&self) -> &[OnboardingQuestion] {
+        &self.questions
+    }
+
+    /// Every channel referenced by the onboarding, either by default or by an option.
+    pub fn channel_ids(&self) -> impl Iterator<Item = Snowflake<Channel>> + '_ {
+        self.default_channel_ids.iter().copied().chain(
+            self.questions
+                .iter()
+                .flat_map(|q| q.options.iter())
+                .flat_map(|o| o.channel_ids.iter().copied()),
+        )
+    }
+
+    /// Remove every reference to channels that are not in `channels`, such as deleted or archived channels.
+    pub fn retain_channels(&mut self, channels: &HashSet<Snowflake<Channel>>) {
+        self.default_channel_ids.retain(|id| channels.contains(id));
+        for option in self.questions.iter_mut().flat_map(|q| q.options.iter_mut()) {
+            option.channel_ids.retain(|id| channels.contains(id));
+        }
+    }
+
+    /// Ensure the onboarding is within the allowed bounds.
+    /// Titles and descriptions are trimmed, and empty descriptions are removed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If there are too many questions, options or channels,
+    ///   a title or description is too long, or an ID is not unique.
+    pub fn validate(&mut self) -> Result<(), BuildError> {
+        if self.questions.len() > MAX_ONBOARDING_QUESTIONS {
+            return Err(invalid(
+                "questions",
+                ErrorCode::TooMany,
+                format!("Onboarding may have at most {MAX_ONBOARDING_QUESTIONS} questions."),
+            ));
+        }
+        validate_channel_ids("default_channel_ids", &self.default_channel_ids)?;
+
+        let mut question_ids = HashSet::new();
+        for question in &mut self.questions {
+            if !question_ids.insert(question.id) {
+                return Err(invalid(
+                    "questions.id",
+                    ErrorCode::ValidationFailed,
+                    format!("Question ID {} is not unique.", question.id),
+                ));
+            }
+            validate_title("questions.title", &mut question.title)?;
+
+            if question.options.is_empty() || question.options.len() > MAX_ONBOARDING_OPTIONS {
+                return Err(invalid(
+                    "questions.options",
+                    ErrorCode::InvalidLength,
+                    format!("Questions must have between 1 and {MAX_ONBOARDING_OPTIONS} options."),
+                ));
+            }
+
+            let mut option_ids = HashSet::new();
+            for option in &mut question.options {
+                if !option_ids.insert(option.id) {
+                    return Err(invalid(
+                        "questions.options.id",
+                        ErrorCode::ValidationFailed,
+                        format!("Option ID {} is not unique within its question.", option.id),
+                    ));
+                }
+                validate_title("questions.options.title", &mut option.title)?;
+                option.description = option
+                    .description
+                    .take()
+                    .map(|d| d.trim().to_string())
+                    .filter(|d| !d.is_empty());
+                if option
+                    .description
+                    .as_ref()
+                    .is_some_and(|d| d.chars().count() > MAX_ONBOARDING_DESCRIPTION_LENGTH)
+                {
+                    return Err(invalid(
+                        "questions.options.description",
+                        ErrorCode::TooLong,
+                        format!("Descriptions must be at most {MAX_ONBOARDING_DESCRIPTION_LENGTH} characters."),
+                    ));
+                }
+                validate_channel_ids("questions.options.channel_ids", &option.channel_ids)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Resolve the answers of a member into the channels they are subscribed to.
+    ///
+    /// ## Arguments
+    ///
+    /// * `responses` - The options the member chose for each question. Questions that are not answered
+    ///   are treated as if no option was chosen.
+    ///
+    /// ## Returns
+    ///
+    /// The default channels, followed by the channels of every chosen option, without duplicates.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If a question or option does not exist, a question is answered twice,
+    ///   a required question is not answered, or more than one option is chosen for a single-select question.
+    pub fn resolve(&self, responses: &[OnboardingResponse]) -> Result<Vec<Snowflake<Channel>>, BuildError> {
+        let mut answered = HashSet::new();
+        for response in responses {
+            if !answered.insert(response.question_id) {
+                return Err(invalid(
+                    "responses.question_id",
+                    ErrorCode::ValidationFailed,
+                    format!("Question {} is answered more than once.", response.question_id),
+                ));
+            }
+            if !self.questions.iter().any(|q| q.id == response.question_id) {
+                return Err(invalid(
+                    "responses.question_id",
+                    ErrorCode::ValidationFailed,
+                    format!("Question {} does not exist.", response.question_id),
+                ));
+            }
+        }
+
+        let mut channels = self.default_channel_ids.clone();
+        for question in &self.questions {
+            let chosen = responses
+                .iter()
+                .find(|r| r.question_id == question.id)
+                .map_or(&[][..], |r| &r.option_ids);
+
+            if question.required && chosen.is_empty() {
+                return Err(invalid(
+                    "responses",
+                    ErrorCode::Required,
+                    format!("Question {} must be answered.", question.id),
+                ));
+            }
+            if question.single_select && chosen.len() > 1 {
+                return Err(invalid(
+                    "responses.option_ids",
+                    ErrorCode::TooMany,
+                    format!("Only one option may be chosen for question {}.", question.id),
+                ));
+            }
+
+            for option_id in chosen {
+                let Some(option) = question.options.iter().find(|o| o.id == *option_id) else {
+                    return Err(invalid(
+                        "responses.option_ids",
+                        ErrorCode::ValidationFailed,
+                        format!("Option {option_id} of question {} does not exist.", question.id),
+                    ));
+                };
+                channels.extend(&option.channel_ids);
+            }
+        }
+
+        let mut seen = HashSet::new();
+        channels.retain(|id| seen.insert(*id));
+        Ok(channels)
+    }
+}
+
+impl MemberOnboarding {
+    /// Create a new record of a member completing the onboarding of a guild.
+    pub const fn new(
+        guild_id: Snowflake<Guild>,
+        responses: Vec<OnboardingResponse>,
+        channel_ids: Vec<Snowflake<Channel>>,
+        completed_at: i64,
+    ) -> Self {
+        Self {
+            guild_id,
+            responses,
+            channel_ids,
+            completed_at,
+        }
+    }
+
+    /// Build the onboarding of a member from a database record.
+    /// The responses are only written by the server, malformed responses are treated as having none.
+    pub fn from_record(record: MemberOnboardingRecord) -> Self {
+        Self {
+            guild_id: record.guild_id.into(),
+            responses: serde_json::from_str(&record.responses).unwrap_or_default(),
+            channel_ids: record.channel_ids.into_iter().map(Snowflake::new).collect(),
+            completed_at: record.completed_at,
+        }
+    }
+
+    /// The guild the onboarding was completed in.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The answers of the member.
+    pub fn responses(&self) -> &[OnboardingResponse] {
+        &self.responses
+    }
+
+    /// The channels the member is subscribed to.
+    pub fn channel_ids(&self) -> &[Snowflake<Channel>] {
+        &self.channel_ids
+    }
+
+    /// UNIX timestamp of when the member completed the onboarding, in seconds.
+    pub const fn completed_at(&self) -> i64 {
+        self.completed_at
+    }
+}
+
+/// Trim a title and ensure it is not empty or too long.
+fn validate_title(field: &'static str, title: &mut String) -> Result<(), BuildError> {
+    *title = title.trim().to_string();
+    if title.is_empty() || title.chars().count() > MAX_ONBOARDING_TITLE_LENGTH {
+        return Err(invalid(
+            field,
+            ErrorCode::InvalidLength,
+            format!("Titles must be between 1 and {MAX_ONBOARDING_TITLE_LENGTH} characters."),
+        ));
+    }
+    Ok(())
+}
+
+/// Ensure a list of channels is not too long.
+fn validate_channel_ids(field: &'static str, channel_ids: &[Snowflake<Channel>]) -> Result<(), BuildError> {
+    if channel_ids.len() > MAX_ONBOARDING_CHANNELS {
+        return Err(invalid(
+            field,
+            ErrorCode::TooMany,
+            format!("At most {MAX_ONBOARDING_CHANNELS} channels may be listed."),
+        ));
+    }
+    Ok(())
+}
+
+const fn invalid(field: &'static str, code: ErrorCode, message: String) -> BuildError {
+    BuildError::InvalidField { field, code, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Onboarding, OnboardingResponse};
+
+    fn onboarding() -> Onboarding {
+        serde_json::from_value(serde_json::json!({
+            "enabled": true,
+            "default_channel_ids": ["1"],
+            "questions": [
+                {
+                    "id": 1,
+                    "title": " What brings you here? ",
+                    "required": true,
+                    "single_select": true,
+                    "options": [
+                        { "id": 1, "title": "Gaming", "description": " ", "channel_ids": ["2", "1"] },
+                        { "id": 2, "title": "Art", "channel_ids": ["3"] }
+                    ]
+                },
+                {
+                    "id": 2,
+                    "title": "Anything else?",
+                    "options": [{ "id": 1, "title": "News", "channel_ids": ["4"] }]
+                }
+            ]
+        }))
+        .expect("Onboarding should deserialize")
+    }
+
+    const fn response(question_id: u32, option_ids: Vec<u32>) -> OnboardingResponse {
+        OnboardingResponse {
+            question_id,
+            option_ids,
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut valid = onboarding();
+        assert!(valid.validate().is_ok());
+        assert_eq!(valid.questions()[0].title, "What brings you here?");
+        assert_eq!(valid.questions()[0].options[0].description, None);
+
+        let mut duplicate = onboarding();
+        duplicate.questions[1].id = 1;
+        assert!(duplicate.validate().is_err());
+
+        let mut no_options = onboarding();
+        no_options.questions[1].options.clear();
+        assert!(no_options.validate().is_err());
+
+        let mut blank = onboarding();
+        blank.questions[0].options[1].title = "  ".into();
+        assert!(blank.validate().is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let onboarding = onboarding();
+
+        let channels = onboarding
+            .resolve(&[response(1, vec![1]), response(2, vec![1])])
+            .expect("Responses should be valid");
+        assert_eq!(channels, vec![1.into(), 2.into(), 4.into()]);
+
+        let channels = onboarding
+            .resolve(&[response(1, vec![2])])
+            .expect("Responses should be valid");
+        assert_eq!(channels, vec![1.into(), 3.into()]);
+
+        // Required, single-select, unknown and duplicate answers
+        assert!(onboarding.resolve(&[]).is_err());
+        assert!(onboarding.resolve(&[response(1, vec![1, 2])]).is_err());
+        assert!(onboarding.resolve(&[response(1, vec![3])]).is_err());
+        assert!(onboarding.resolve(&[response(3, vec![1])]).is_err());
+        assert!(onboarding
+            .resolve(&[response(1, vec![1]), response(1, vec![2])])
+            .is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use std::sync::{Arc, Weak};
+
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, Postgres};
+use tokio::sync::{Mutex, Notify};
+
+use super::{
+    errors::AppError,
+    event_bus::{BusMessage, EventBus},
+    gateway_event::{EventRouting, GatewayEvent},
+    message_change::PendingMessageChange,
+    snowflake::Snowflake,
+    state::{ApplicationState, Config},
+    telemetry,
+    user::User,
+};
+
+/// The maximum amount of events read from the outbox at once.
+const BATCH_SIZE: i64 = 100;
+/// How long delivered events are kept in the outbox, in seconds.
+const RETENTION: i64 = 60 * 60;
+/// The advisory lock held while flushing, so that only one process dispatches events at a time.
+const FLUSH_LOCK: i64 = 0x6f75_7462_6f78;
+
+/// Represents an event stored in the outbox.
+pub struct OutboxRecord {
+    pub id: i64,
+    /// The ID the event is dispatched with, `None` for events written before events had IDs.
+    pub event_id: Option<i64>,
+    pub event: String,
+    pub payload: String,
+    pub guild_id: Option<i64>,
+    pub user_id: Option<i64>,
+    pub channel_id: Option<i64>,
+    pub recipient_id: Option<i64>,
+    pub trace_context: Option<String>,
+}
+
+/// A transactional outbox for gateway events.
+///
+/// Events are written to the outbox in the same transaction as the change that caused them,
+/// and dispatched by a background task once the transaction is committed.
+/// This ensures that no events are lost if the server crashes between committing a change and dispatching it.
+#[derive(Debug, Clone, Default)]
+pub struct Outbox {
+    notify: Arc<Notify>,
+    /// Ensures that flushes of this process do not overlap.
+    lock: Arc<Mutex<()>>,
+    app: Weak<ApplicationState>,
+}
+
+impl Outbox {
+    /// Create a new outbox.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind_to(&mut self, app: Weak<ApplicationState>) {
+        self.app = app;
+    }
+
+    pub fn app(&self) -> Arc<ApplicationState> {
+        self.app.upgrade().expect("Application state has been dropped.")
+    }
+
+    /// Write an event to the outbox as part of a transaction.
+    /// Call [`Outbox::notify`] after the transaction is committed to dispatch it without delay.
+    ///
+    /// ## Arguments
+    ///
+    /// * `conn` - The transaction to write the event in.
+    /// * `config` - The application configuration, used to generate the ID of the event.
+    /// * `event` - The event to dispatch.
+    /// * `now` - The time the event is written at.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::JSON`] - If the event could not be serialized.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn enqueue(
+        conn: impl Executor<'_, Database = Postgres>,
+        config: &Config,
+        event: &GatewayEvent,
+        now: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        Self::enqueue_to(conn, config, None, event, now).await
+    }
+
+    /// Write an event to the outbox as part of a transaction, optionally sending it to a single user only.
+    /// Call [`Outbox::notify`] after the transaction is committed to dispatch it without delay.
+    /// The trace of the current span is continued when the event is dispatched.
+    /// The ID of the event is assigned here, so it stays the same if the event is ever dispatched again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `conn` - The transaction to write the event in.
+    /// * `config` - The application configuration, used to generate the ID of the event.
+    /// * `recipient` - If set, only this user receives the event. Otherwise it is routed based on its contents.
+    /// * `event` - The event to dispatch.
+    /// * `now` - The time the event is written at.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::JSON`] - If the event could not be serialized.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn enqueue_to(
+        conn: impl Executor<'_, Database = Postgres>,
+        config: &Config,
+        recipient: Option<Snowflake<User>>,
+        event: &GatewayEvent,
+        now: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        let routing = EventRouting {
+            recipient_id: recipient,
+            ..EventRouting::from(event)
+        };
+
+        sqlx::query!(
+            "INSERT INTO outbox (event, payload, guild_id, user_id, channel_id, recipient_id, created_at, trace_context, event_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            event.name(),
+            serde_json::to_string(event)?,
+            routing.guild_id.map(i64::from),
+            routing.user_id.map(i64::from),
+            routing.channel_id.map(i64::from),
+            routing.recipient_id.map(i64::from),
+            now.timestamp(),
+            telemetry::current_trace_context(),
+            Snowflake::gen_new(config) as Snowflake<GatewayEvent>,
+        )
+        .execute(conn)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Wake up the outbox dispatcher after events were committed.
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Wait until [`Outbox::notify`] is called.
+    pub async fn notified(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Dispatch all committed, undelivered events in the outbox, and mark them as delivered.
+    /// If the gateway is sharded, events are published to the event bus once the flush is committed.
+    ///
+    /// Events written by the same transaction are dispatched in the order they were written.
+    /// Events of concurrent transactions are not ordered relative to each other: IDs are assigned when an event
+    /// is written, not when its transaction commits, so an event may be dispatched before one with a lower ID
+    /// whose transaction commits later.
+    /// Changes to messages are recorded as they are dispatched, see [`PendingMessageChange`].
+    ///
+    /// ## Returns
+    ///
+    /// The amount of events dispatched.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn flush(&self) -> Result<usize, sqlx::Error> {
+        let _guard = self.lock.lock().await;
+        let app = self.app();
+        let mut dispatched = 0;
+
+        let mut tx = app.db.pool().begin().await?;
+        // Other processes may flush the same outbox, wait for them to finish
+        sqlx::query!("SELECT pg_advisory_xact_lock($1)", FLUSH_LOCK)
+            .execute(app.db.instrument(&mut *tx))
+            .await?;
+
+        loop {
+            let records = sqlx::query_as!(
+                OutboxRecord,
+                "SELECT id, event_id, event, payload, guild_id, user_id, channel_id, recipient_id, trace_context FROM outbox
+                WHERE delivered_at IS NULL
+                ORDER BY id ASC LIMIT $1",
+                BATCH_SIZE
+            )
+            .fetch_all(app.db.instrument(&mut *tx))
+            .await?;
+
+            let ids: Vec<i64> = records.iter().map(|r| r.id).collect();
+            // Flushes never overlap, so changes are recorded in the order they are dispatched
+            let changes: Vec<PendingMessageChange> =
+                records.iter().flat_map(PendingMessageChange::from_outbox).collect();
+            PendingMessageChange::record(app.db.instrument(&mut *tx), &app.config, &changes).await?;
+
+            for record in records {
+                let routing = EventRouting {
+                    guild_id: record.guild_id.map(Snowflake::new),
+                    user_id: record.user_id.map(Snowflake::new),
+                    channel_id: record.channel_id.map(Snowflake::new),
+                    recipient_id: record.recipient_id.map(Snowflake::new),
+                    // Events revealing the activity of users are dispatched right away, never through the outbox
+                    activity_of: None,
+                };
+                // Events written before events had IDs get a new one
+                let id = record
+                    .event_id
+                    .map_or_else(|| Snowflake::gen_new(&app.config), Snowflake::new);
+                let span = tracing::info_span!("outbox.dispatch", event = %record.event);
+                if let Some(trace_context) = &record.trace_context {
+                    telemetry::continue_trace(&span, trace_context);
+                }
+
+                if app.gateway.shard().is_sharded() {
+                    let message = BusMessage::Dispatch {
+                        routing,
+                        id: Some(id),
+                        event: record.event,
+                        payload: record.payload,
+                    };
+                    // Called explicitly, as the method shares its name with `Database::instrument`
+                    tracing::Instrument::instrument(
+                        EventBus::send(app.db.instrument(&mut *tx), &message, app.clock.now()),
+                        span,
+                    )
+                    .await?;
+                } else {
+                    span.in_scope(|| {
+                        app.gateway
+                            .dispatch_serialized(routing, id, record.event, record.payload);
+                    });
+                }
+            }
+
+            sqlx::query!(
+                "UPDATE outbox SET delivered_at = $2 WHERE id = ANY($1)",
+                &ids,
+                app.clock.timestamp()
+            )
+            .execute(app.db.instrument(&mut *tx))
+            .await?;
+
+            dispatched += ids.len();
+
+            if ids.len() < usize::try_from(BATCH_SIZE).expect("Batch size should fit into usize") {
+                tx.commit().await?;
+                return Ok(dispatched);
+            }
+        }
+    }
+
+    /// Remove delivered events from the outbox that are older than the retention period.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn prune(&self) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM outbox WHERE delivered_at < $1",
+            self.app().clock.timestamp() - RETENTION
+        )
+        .execute(self.app().db.executor())
+        .await?;
+
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 
-use super::{requests::UpdatePrefs, snowflake::Snowflake, state::App, user::User};
+use super::{errors::AppError, requests::UpdatePrefs, snowflake::Snowflake, state::App, user::User};
 
 bitflags! {
     /// Boolean flags for user preferences
@@ -9,6 +9,12 @@ bitflags! {
     pub struct PrefFlags: u64 {
         const RENDER_ATTACHMENTS = 1;
         const AUTOPLAY_GIF = 1 << 1;
+        /// Do not receive welcome messages from guilds the user joins
+        const MUTE_WELCOME_MESSAGES = 1 << 2;
+        /// Do not receive push notifications on any device
+        const MUTE_PUSH_NOTIFICATIONS = 1 << 3;
+        /// Leave heavy parts, such as embeds and blurhashes, out of gateway events sent to the user
+        const DATA_SAVER = 1 << 4;
     }
 }
 
@@ -76,6 +82,8 @@ pub struct Prefs {
     pub text_size: u8,
     /// The date format for chat messages.
     pub locale: String,
+    /// Incremented every time the preferences are updated.
+    version: i32,
 }
 
 impl Prefs {
@@ -87,6 +95,7 @@ impl Prefs {
             layout: Layout::Normal,
             text_size: 12,
             locale: String::from("en_US"),
+            version: 0,
         }
     }
 
@@ -95,8 +104,22 @@ impl Prefs {
         self.user_id
     }
 
+    /// Incremented every time the preferences are updated.
+    pub const fn version(&self) -> i32 {
+        self.version
+    }
+
     /// Apply a set of updates to the preferences.
-    pub fn update(&mut self, update: UpdatePrefs) {
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Conflict`] - If the update expects a different version of the preferences.
+    pub fn update(&mut self, update: UpdatePrefs) -> Result<(), AppError> {
+        if update.version.is_some_and(|version| version != self.version) {
+            return Err(AppError::Conflict(
+                "The preferences were updated since they were fetched".into(),
+            ));
+        }
         if let Some(flags) = update.flags {
             self.flags = flags;
         }
@@ -112,6 +135,7 @@ impl Prefs {
         if let Some(locale) = update.locale {
             self.locale = locale;
         }
+        Ok(())
     }
 
     /// Fetch the preferences for a user.
@@ -132,12 +156,12 @@ impl Prefs {
         let user_id_i64: i64 = user_id.into();
 
         let result = sqlx::query!(
-            "SELECT user_id, flags, message_grouping_timeout, layout, text_size, locale
+            "SELECT user_id, flags, message_grouping_timeout, layout, text_size, locale, version
             FROM prefs
             WHERE user_id = $1",
             user_id_i64
         )
-        .fetch_optional(app.db.pool())
+        .fetch_optional(app.db.executor())
         .await?;
 
         let Some(result) = result else {
@@ -152,10 +176,12 @@ impl Prefs {
             layout: Layout::from(result.layout as u8),
             text_size: result.text_size as u8,
             locale: result.locale,
+            version: result.version,
         })
     }
 
-    /// Commit the preferences to the database.
+    /// Commit the preferences to the database and increment their version.
+    /// The update only succeeds if the preferences were not updated since they were fetched.
     ///
     /// ## Locks
     ///
@@ -163,26 +189,33 @@ impl Prefs {
     ///
     /// ## Errors
     ///
-    /// * [`sqlx::Error`] - If the database query fails.
-    pub async fn commit(&self, app: App) -> Result<(), sqlx::Error> {
+    /// * [`AppError::Conflict`] - If the preferences were updated concurrently.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn commit(&mut self, app: App) -> Result<(), AppError> {
         let user_id: i64 = self.user_id.into();
         let flags: i64 = self.flags.bits().try_into().expect("Cannot fit flag into i64");
 
-        sqlx::query!(
-            "INSERT INTO prefs (user_id, flags, message_grouping_timeout, layout, text_size, locale)
-            VALUES ($1, $2, $3, $4, $5, $6)
+        let version = sqlx::query_scalar!(
+            "INSERT INTO prefs (user_id, flags, message_grouping_timeout, layout, text_size, locale, version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7 + 1)
             ON CONFLICT (user_id)
-            DO UPDATE SET flags = $2, message_grouping_timeout = $3, layout = $4, text_size = $5, locale = $6",
+            DO UPDATE SET flags = $2, message_grouping_timeout = $3, layout = $4, text_size = $5, locale = $6,
+                version = prefs.version + 1
+            WHERE prefs.version = $7
+            RETURNING version",
             user_id,
             flags,
             self.message_grouping_timeout as i32,
             self.layout as i32,
             i16::from(self.text_size),
             self.locale,
+            self.version,
         )
-        .execute(app.db.pool())
-        .await?;
+        .fetch_optional(app.db.executor())
+        .await?
+        .ok_or_else(|| AppError::Conflict("The preferences were updated concurrently".into()))?;
 
+        self.version = version;
         Ok(())
     }
 }
@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    errors::{BuildError, ErrorCode},
+    guild::Guild,
+    snowflake::Snowflake,
+};
+
+/// The most guilds a user may hide their presence from individually.
+pub const MAX_HIDDEN_GUILDS: usize = 200;
+
+/// Who can see a user's presence and activity.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum PresenceVisibility {
+    /// Members of all guilds shared with the user, except those the user hides their presence from.
+    #[default]
+    Everyone = 0,
+    /// Nobody, the user appears offline to everyone but themselves.
+    Nobody = 1,
+}
+
+impl From<i16> for PresenceVisibility {
+    fn from(visibility: i16) -> Self {
+        match visibility {
+            0 => Self::Everyone,
+            _ => Self::Nobody,
+        }
+    }
+}
+
+/// Controls who can see a user's presence and activity.
+/// Users who cannot see the presence of another user see them as offline.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct PresencePrivacy {
+    /// Who can see the user's presence.
+    visibility: PresenceVisibility,
+    /// Guilds the user hides their presence from, regardless of `visibility`.
+    hidden_guilds: HashSet<Snowflake<Guild>>,
+}
+
+impl PresencePrivacy {
+    /// Create new presence privacy settings.
+    ///
+    /// ## Arguments
+    ///
+    /// * `visibility` - Who can see the user's presence.
+    /// * `hidden_guilds` - Guilds the user hides their presence from.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If more than [`MAX_HIDDEN_GUILDS`] guilds are hidden.
+    pub fn new(
+        visibility: PresenceVisibility,
+        hidden_guilds: impl IntoIterator<Item = Snowflake<Guild>>,
+    ) -> Result<Self, BuildError> {
+        let privacy = Self {
+            visibility,
+            hidden_guilds: hidden_guilds.into_iter().collect(),
+        };
+        privacy.validate()?;
+        Ok(privacy)
+    }
+
+    /// Who can see the user's presence.
+    pub const fn visibility(&self) -> PresenceVisibility {
+        self.visibility
+    }
+
+    /// Guilds the user hides their presence from.
+    pub const fn hidden_guilds(&self) -> &HashSet<Snowflake<Guild>> {
+        &self.hidden_guilds
+    }
+
+    /// Whether members of the given guild can see the user's presence.
+    pub fn is_visible_in(&self, guild: Snowflake<Guild>) -> bool {
+        self.visibility == PresenceVisibility::Everyone && !self.hidden_guilds.contains(&guild)
+    }
+
+    /// Ensure the settings are within the allowed bounds.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If more than [`MAX_HIDDEN_GUILDS`] guilds are hidden.
+    pub fn validate(&self) -> Result<(), BuildError> {
+        if self.hidden_guilds.len() > MAX_HIDDEN_GUILDS {
+            return Err(BuildError::InvalidField {
+                field: "hidden_guilds",
+                code: ErrorCode::TooMany,
+                message: format!("Presence can be hidden from at most {MAX_HIDDEN_GUILDS} guilds."),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PresencePrivacy, PresenceVisibility, MAX_HIDDEN_GUILDS};
+    use crate::models::snowflake::Snowflake;
+
+    #[test]
+    fn test_is_visible_in() {
+        let privacy = PresencePrivacy::new(PresenceVisibility::Everyone, [Snowflake::new(1)])
+            .expect("Privacy settings should be valid");
+        assert!(!privacy.is_visible_in(Snowflake::new(1)));
+        assert!(privacy.is_visible_in(Snowflake::new(2)));
+
+        let privacy = PresencePrivacy::new(PresenceVisibility::Nobody, []).expect("Privacy settings should be valid");
+        assert!(!privacy.is_visible_in(Snowflake::new(2)));
+
+        assert!(PresencePrivacy::default().is_visible_in(Snowflake::new(1)));
+    }
+
+    #[test]
+    fn test_validate() {
+        let too_many = (0..=MAX_HIDDEN_GUILDS as i64).map(Snowflake::new);
+        assert!(PresencePrivacy::new(PresenceVisibility::Everyone, too_many).is_err());
+    }
+
+    #[test]
+    fn test_serde() {
+        let privacy: PresencePrivacy = serde_json::from_str(r#"{"visibility": "NOBODY", "hidden_guilds": ["1"]}"#)
+            .expect("Privacy settings should deserialize");
+        assert_eq!(privacy.visibility(), PresenceVisibility::Nobody);
+        assert!(privacy.hidden_guilds().contains(&Snowflake::new(1)));
+
+        let privacy: PresencePrivacy = serde_json::from_str("{}").expect("Fields should be optional");
+        assert_eq!(privacy, PresencePrivacy::default());
+    }
+}
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+
+use super::{snowflake::Snowflake, user::User};
+
+/// Who can see a user's profile.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum ProfileVisibility {
+    /// All authenticated users.
+    #[default]
+    Everyone = 0,
+    /// Only users sharing at least one guild with the user.
+    MutualGuilds = 1,
+    /// Nobody but the user themselves.
+    Nobody = 2,
+}
+
+impl From<i16> for ProfileVisibility {
+    fn from(visibility: i16) -> Self {
+        match visibility {
+            0 => Self::Everyone,
+            1 => Self::MutualGuilds,
+            _ => Self::Nobody,
+        }
+    }
+}
+
+/// Controls who can see a user's profile.
+/// Users who cannot see the profile of another user are told the user does not exist.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ProfilePrivacy {
+    /// Who can see the user's profile.
+    visibility: ProfileVisibility,
+}
+
+impl ProfilePrivacy {
+    /// Create new profile privacy settings.
+    pub const fn new(visibility: ProfileVisibility) -> Self {
+        Self { visibility }
+    }
+
+    /// Who can see the user's profile.
+    pub const fn visibility(&self) -> ProfileVisibility {
+        self.visibility
+    }
+
+    /// Whether another user can see the profile.
+    ///
+    /// ## Arguments
+    ///
+    /// * `mutual_guild_count` - The amount of guilds the user shares with the viewer.
+    pub const fn is_visible_to(&self, mutual_guild_count: i64) -> bool {
+        match self.visibility {
+            ProfileVisibility::Everyone => true,
+            ProfileVisibility::MutualGuilds => mutual_guild_count > 0,
+            ProfileVisibility::Nobody => false,
+        }
+    }
+}
+
+/// Represents a profile record stored in the database.
+pub struct ProfileRecord {
+    pub id: Snowflake<User>,
+    pub username: String,
+    pub display_name: Option<String>,
+    pub avatar_hash: Option<String>,
+    pub profile_visibility: i16,
+    pub mutual_guild_count: i64,
+}
+
+/// The public profile of a user, as seen by another user.
+///
+/// Unlike [`User`], this only ever contains fields that are safe to show to users
+/// who do not share a guild with the user, and is never extended with private fields.
+#[derive(Serialize, Debug, Clone)]
+pub struct Profile {
+    /// The ID of the user.
+    id: Snowflake<User>,
+    /// The username of the user.
+    username: String,
+    /// The display name of the user, if they set one.
+    display_name: Option<String>,
+    /// The avatar hash of the user, if they set an avatar.
+    avatar_hash: Option<String>,
+    /// The amount of guilds the user shares with the viewer.
+    mutual_guild_count: i64,
+}
+
+impl Profile {
+    /// Build a profile directly from a database record.
+    ///
+    /// ## Returns
+    ///
+    /// The profile and the privacy settings of its user.
+    pub fn from_record(record: ProfileRecord) -> (Self, ProfilePrivacy) {
+        let privacy = ProfilePrivacy::new(ProfileVisibility::from(record.profile_visibility));
+        let profile = Self {
+            id: record.id,
+            username: record.username,
+            display_name: record.display_name,
+            avatar_hash: record.avatar_hash,
+            mutual_guild_count: record.mutual_guild_count,
+        };
+        (profile, privacy)
+    }
+
+    /// The ID of the user.
+    pub const fn id(&self) -> Snowflake<User> {
+        self.id
+    }
+
+    /// The amount of guilds the user shares with the viewer.
+    pub const fn mutual_guild_count(&self) -> i64 {
+        self.mutual_guild_count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProfilePrivacy, ProfileVisibility};
+
+    #[test]
+    fn test_is_visible_to() {
+        let privacy = ProfilePrivacy::new(ProfileVisibility::Everyone);
+        assert!(privacy.is_visible_to(0));
+
+        let privacy = ProfilePrivacy::new(ProfileVisibility::MutualGuilds);
+        assert!(!privacy.is_visible_to(0));
+        assert!(privacy.is_visible_to(2));
+
+        let privacy = ProfilePrivacy::new(ProfileVisibility::Nobody);
+        assert!(!privacy.is_visible_to(2));
+    }
+}
@@ -0,0 +1,155 @@
+use std::{fmt, sync::Mutex, time::Duration};
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{header, Client, StatusCode};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{PushError, PushNotification, PushPlatform, PushProvider};
+use crate::models::state::Config;
+
+/// How long a provider token is reused for, in seconds.
+/// APNs rejects tokens older than an hour, and tokens that are refreshed more often than every 20 minutes.
+const TOKEN_REFRESH_INTERVAL: u64 = 50 * 60;
+
+/// The body of an APNs error response.
+#[derive(Deserialize)]
+struct ErrorResponse {
+    reason: String,
+}
+
+/// Delivers notifications to Apple devices through the Apple Push Notification service,
+/// authenticated with a token signing key.
+pub struct ApnsProvider {
+    client: Client,
+    /// The base URL of the production or sandbox environment.
+    url: &'static str,
+    key: EncodingKey,
+    key_id: String,
+    team_id: String,
+    /// The bundle ID of the app notifications are delivered to.
+    topic: String,
+    /// The current provider token and when it was issued.
+    token: Mutex<Option<(String, u64)>>,
+}
+
+impl fmt::Debug for ApnsProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApnsProvider")
+            .field("url", &self.url)
+            .field("key_id", &self.key_id)
+            .field("team_id", &self.team_id)
+            .field("topic", &self.topic)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ApnsProvider {
+    /// Create a new APNs provider, if a signing key is configured.
+    ///
+    /// ## Errors
+    ///
+    /// A description of the problem if the configuration is incomplete or the key is invalid.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, String> {
+        let Some(key) = config.apns_key() else {
+            return Ok(None);
+        };
+        let (Some(key_id), Some(team_id), Some(topic)) =
+            (config.apns_key_id(), config.apns_team_id(), config.apns_topic())
+        else {
+            return Err("APNS_KEY_ID, APNS_TEAM_ID and APNS_TOPIC must be set if an APNs key is set".into());
+        };
+        let key = EncodingKey::from_ec_pem(key.expose_secret().as_bytes())
+            .map_err(|e| format!("The APNs key must be a PKCS#8 PEM private key: {e}"))?;
+
+        // APNs only supports HTTP/2, which is negotiated when connecting
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("chat-push/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build APNs HTTP client");
+
+        Ok(Some(Self {
+            client,
+            url: if config.apns_sandbox() {
+                "https://api.sandbox.push.apple.com"
+            } else {
+                "https://api.push.apple.com"
+            },
+            key,
+            key_id: key_id.to_string(),
+            team_id: team_id.to_string(),
+            topic: topic.to_string(),
+            token: Mutex::new(None),
+        }))
+    }
+
+    /// Get the current provider token, issuing a new one if it is due for a refresh.
+    fn provider_token(&self) -> Result<String, PushError> {
+        let now = jsonwebtoken::get_current_timestamp();
+        let mut token = self.token.lock().expect("Lock should not be poisoned");
+
+        if let Some((token, issued_at)) = token.as_ref() {
+            if now < issued_at + TOKEN_REFRESH_INTERVAL {
+                return Ok(token.clone());
+            }
+        }
+
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(self.key_id.clone());
+        let issued = jsonwebtoken::encode(&header, &json!({ "iss": self.team_id, "iat": now }), &self.key)
+            .map_err(|e| PushError::Failed(e.to_string()))?;
+
+        *token = Some((issued.clone(), now));
+        Ok(issued)
+    }
+}
+
+#[async_trait::async_trait]
+impl PushProvider for ApnsProvider {
+    fn platform(&self) -> PushPlatform {
+        PushPlatform::Apns
+    }
+
+    async fn send(&self, token: &str, notification: &PushNotification) -> Result<(), PushError> {
+        let body = json!({
+            "aps": {
+                "alert": { "title": notification.title, "body": notification.body },
+                "sound": "default",
+                "thread-id": notification.channel_id,
+            },
+            "guild_id": notification.guild_id,
+            "channel_id": notification.channel_id,
+            "message_id": notification.message_id,
+        });
+
+        let response = self
+            .client
+            .post(format!("{}/3/device/{token}", self.url))
+            .bearer_auth(self.provider_token()?)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("apns-topic", &self.topic)
+            .header("apns-push-type", "alert")
+            .header("apns-priority", "10")
+            .body(body.to_string())
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let reason = serde_json::from_slice::<ErrorResponse>(&response.bytes().await?)
+            .map(|error| error.reason)
+            .unwrap_or_default();
+
+        match (status, reason.as_str()) {
+            (StatusCode::GONE, _) | (_, "BadDeviceToken" | "Unregistered" | "DeviceTokenNotForTopic") => {
+                Err(PushError::Unregistered)
+            }
+            _ => Err(PushError::Failed(format!("APNs responded with {status}: {reason}"))),
+        }
+    }
+}
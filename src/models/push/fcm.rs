@@ -0,0 +1,171 @@
+use std::{fmt, time::Duration};
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{header, Client, StatusCode};
+use secrecy::ExposeSecret;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use super::{PushError, PushNotification, PushPlatform, PushProvider};
+use crate::models::state::Config;
+
+/// The OAuth scope needed to send messages through FCM.
+const SCOPE: &str = "https://www.googleapis.com/auth/firebase.messaging";
+/// How long the assertions exchanged for access tokens are valid for, in seconds.
+const ASSERTION_LIFETIME: u64 = 60 * 60;
+/// How long before it expires an access token is replaced, in seconds.
+const TOKEN_EXPIRY_MARGIN: u64 = 60;
+
+/// The fields of a Google service account key file needed to authenticate with FCM.
+#[derive(Deserialize)]
+struct ServiceAccount {
+    project_id: String,
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+/// The body of an OAuth token response.
+#[derive(Deserialize)]
+struct AccessToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Delivers notifications to Android devices through the Firebase Cloud Messaging HTTP v1 API,
+/// authenticated as a service account.
+pub struct FcmProvider {
+    client: Client,
+    project_id: String,
+    client_email: String,
+    token_uri: String,
+    key: EncodingKey,
+    /// The current access token and when it expires.
+    token: Mutex<Option<(String, u64)>>,
+}
+
+impl fmt::Debug for FcmProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FcmProvider")
+            .field("project_id", &self.project_id)
+            .field("client_email", &self.client_email)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FcmProvider {
+    /// Create a new FCM provider, if a service account is configured.
+    ///
+    /// ## Errors
+    ///
+    /// A description of the problem if the service account key is invalid.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, String> {
+        let Some(service_account) = config.fcm_service_account() else {
+            return Ok(None);
+        };
+        let account: ServiceAccount = serde_json::from_str(service_account.expose_secret())
+            .map_err(|e| format!("The service account must be a Google service account key file: {e}"))?;
+        let key = EncodingKey::from_rsa_pem(account.private_key.as_bytes())
+            .map_err(|e| format!("The private key of the service account is invalid: {e}"))?;
+
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("chat-push/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build FCM HTTP client");
+
+        Ok(Some(Self {
+            client,
+            project_id: account.project_id,
+            client_email: account.client_email,
+            token_uri: account.token_uri,
+            key,
+            token: Mutex::new(None),
+        }))
+    }
+
+    /// Get the current access token, exchanging a new assertion for one if it is about to expire.
+    async fn access_token(&self) -> Result<String, PushError> {
+        let mut token = self.token.lock().await;
+        let now = jsonwebtoken::get_current_timestamp();
+
+        if let Some((token, expires_at)) = token.as_ref() {
+            if now + TOKEN_EXPIRY_MARGIN < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let claims = json!({
+            "iss": self.client_email,
+            "scope": SCOPE,
+            "aud": self.token_uri,
+            "iat": now,
+            "exp": now + ASSERTION_LIFETIME,
+        });
+        let assertion = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &self.key)
+            .map_err(|e| PushError::Failed(e.to_string()))?;
+
+        let response = self
+            .client
+            .post(&self.token_uri)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", &assertion),
+            ])
+            .send()
+            .await?
+            .error_for_status()?;
+        let issued: AccessToken =
+            serde_json::from_slice(&response.bytes().await?).map_err(|e| PushError::Failed(e.to_string()))?;
+
+        *token = Some((issued.access_token.clone(), now + issued.expires_in));
+        Ok(issued.access_token)
+    }
+}
+
+#[async_trait::async_trait]
+impl PushProvider for FcmProvider {
+    fn platform(&self) -> PushPlatform {
+        PushPlatform::Fcm
+    }
+
+    async fn send(&self, token: &str, notification: &PushNotification) -> Result<(), PushError> {
+        // The values of data messages must be strings
+        let mut data = json!({
+            "channel_id": notification.channel_id.to_string(),
+            "message_id": notification.message_id.to_string(),
+        });
+        if let Some(guild_id) = notification.guild_id {
+            data["guild_id"] = guild_id.to_string().into();
+        }
+
+        let body = json!({
+            "message": {
+                "token": token,
+                "notification": { "title": notification.title, "body": notification.body },
+                "data": data,
+                "android": { "priority": "high" },
+            }
+        });
+
+        let response = self
+            .client
+            .post(format!(
+                "https://fcm.googleapis.com/v1/projects/{}/messages:send",
+                self.project_id
+            ))
+            .bearer_auth(self.access_token().await?)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            // FCM responds with UNREGISTERED once the app was uninstalled or the token expired
+            StatusCode::NOT_FOUND => Err(PushError::Unregistered),
+            status => Err(PushError::Failed(format!("FCM responded with {status}"))),
+        }
+    }
+}
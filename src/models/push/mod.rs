@@ -0,0 +1,585 @@
+//! Push notifications for users who are not connected to the gateway, delivered through Web Push, APNs or FCM.
+
+mod apns;
+mod fcm;
+mod webpush;
+
+use std::{
+    fmt::Debug,
+    sync::{Arc, Weak},
+};
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+pub use self::{apns::ApnsProvider, fcm::FcmProvider, webpush::WebPushProvider};
+
+use super::{
+    channel::{Channel, ChannelLike},
+    errors::{AppError, BuildError, ErrorCode},
+    guild::Guild,
+    member::UserLike,
+    message::Message,
+    prefs::PrefFlags,
+    requests::CreatePushDevice,
+    snowflake::Snowflake,
+    state::{ApplicationState, Config},
+    user::User,
+};
+
+/// The maximum amount of devices a user may register for push notifications.
+pub const MAX_DEVICES_PER_USER: usize = 10;
+/// The maximum length of a device token.
+const MAX_TOKEN_LEN: usize = 4096;
+/// The maximum length of the body of a notification, in characters.
+const MAX_BODY_LEN: usize = 200;
+/// The maximum amount of notifications sent at once.
+const BATCH_SIZE: i64 = 50;
+/// The maximum amount of notifications sent concurrently.
+const CONCURRENCY: usize = 8;
+/// How long a claimed notification is reserved for a delivery attempt, in seconds.
+/// Notifications interrupted by a crash are retried after this period.
+const LEASE_DURATION: i64 = 60;
+/// The maximum amount of delivery attempts before a notification is dropped.
+const MAX_ATTEMPTS: i32 = 4;
+/// The delay before the first retry in seconds. Each further retry waits 4 times as long.
+const RETRY_BASE_DELAY: i64 = 30;
+
+/// A service push notifications are delivered through.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum PushPlatform {
+    /// A platform that is not known to this version of the server.
+    #[serde(skip_deserializing)]
+    Unknown = -1,
+    /// The Web Push protocol, supported by browsers.
+    WebPush = 0,
+    /// The Apple Push Notification service.
+    Apns = 1,
+    /// Firebase Cloud Messaging.
+    Fcm = 2,
+}
+
+impl From<i16> for PushPlatform {
+    fn from(platform: i16) -> Self {
+        match platform {
+            0 => Self::WebPush,
+            1 => Self::Apns,
+            2 => Self::Fcm,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Represents a push device record stored in the database.
+pub struct PushDeviceRecord {
+    pub id: Snowflake<PushDevice>,
+    pub user_id: Snowflake<User>,
+    pub platform: i16,
+    pub token: String,
+    pub created_at: i64,
+}
+
+/// A device a user receives push notifications on.
+#[derive(Serialize, Debug, Clone)]
+pub struct PushDevice {
+    /// The ID of the device.
+    id: Snowflake<Self>,
+    /// The user the device belongs to.
+    #[serde(skip)]
+    user_id: Snowflake<User>,
+    /// The service notifications are delivered through.
+    platform: PushPlatform,
+    /// The token the service identifies the device by. Not returned to clients, as Web Push subscriptions contain secrets.
+    #[serde(skip)]
+    token: String,
+    /// UNIX timestamp of when the device was registered.
+    created_at: i64,
+}
+
+impl PushDevice {
+    /// Build a device from a database record.
+    pub fn from_record(record: PushDeviceRecord) -> Self {
+        Self {
+            id: record.id,
+            user_id: record.user_id,
+            platform: PushPlatform::from(record.platform),
+            token: record.token,
+            created_at: record.created_at,
+        }
+    }
+
+    /// Create a new device from a registration payload. Assigns a new snowflake to the device.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration.
+    /// * `user` - The user registering the device.
+    /// * `payload` - The payload to create the device from.
+    /// * `now` - UNIX timestamp of the registration.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If the token is not valid for the platform.
+    pub fn from_payload(
+        config: &Config,
+        user: impl Into<Snowflake<User>>,
+        payload: &CreatePushDevice,
+        now: i64,
+    ) -> Result<Self, BuildError> {
+        let invalid = |message: &str| BuildError::InvalidField {
+            field: "token",
+            code: ErrorCode::ValidationFailed,
+            message: message.into(),
+        };
+
+        let token = payload.token.trim();
+        if token.is_empty() || token.len() > MAX_TOKEN_LEN {
+            return Err(BuildError::InvalidField {
+                field: "token",
+                code: ErrorCode::InvalidLength,
+                message: format!("Token must be between 1 and {MAX_TOKEN_LEN} characters long."),
+            });
+        }
+
+        let token = match payload.platform {
+            PushPlatform::WebPush => webpush::Subscription::parse(token).map_err(|e| invalid(&e))?.to_token(),
+            // APNs device tokens are sent to the server as hex strings
+            PushPlatform::Apns if token.chars().all(|c| c.is_ascii_hexdigit()) => token.to_ascii_lowercase(),
+            PushPlatform::Apns => return Err(invalid("APNs device tokens must be hex strings.")),
+            PushPlatform::Fcm if token.chars().all(|c| c.is_ascii_graphic()) => token.to_string(),
+            PushPlatform::Fcm => return Err(invalid("FCM registration tokens must not contain whitespace.")),
+            PushPlatform::Unknown => return Err(invalid("Unknown push platform.")),
+        };
+
+        Ok(Self {
+            id: Snowflake::gen_new(config),
+            user_id: user.into(),
+            platform: payload.platform,
+            token,
+            created_at: now,
+        })
+    }
+
+    /// The ID of the device.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The user the device belongs to.
+    pub const fn user_id(&self) -> Snowflake<User> {
+        self.user_id
+    }
+
+    /// The service notifications are delivered through.
+    pub const fn platform(&self) -> PushPlatform {
+        self.platform
+    }
+
+    /// The token the service identifies the device by.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// UNIX timestamp of when the device was registered.
+    pub const fn created_at(&self) -> i64 {
+        self.created_at
+    }
+}
+
+/// A notification shown to a user on their devices.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PushNotification {
+    /// The title of the notification.
+    pub title: String,
+    /// The body of the notification.
+    pub body: String,
+    /// The guild the notification is about, if any.
+    pub guild_id: Option<Snowflake<Guild>>,
+    /// The channel the notification is about.
+    pub channel_id: Snowflake<Channel>,
+    /// The message the notification is about.
+    pub message_id: Snowflake<Message>,
+}
+
+impl PushNotification {
+    /// Create a notification telling a user that they were mentioned in a message.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The message the user was mentioned in.
+    /// * `channel` - The channel the message was sent in.
+    pub fn mention(message: &Message, channel: &Channel) -> Self {
+        let author = match message.author() {
+            Some(UserLike::Member(member)) => member
+                .nickname()
+                .as_ref()
+                .or_else(|| member.user().display_name())
+                .unwrap_or_else(|| member.user().username()),
+            Some(UserLike::User(user)) => user.display_name().unwrap_or_else(|| user.username()),
+            None => "Someone",
+        };
+
+        let content = message.content().map_or("", String::as_str);
+        let mut body: String = content.chars().take(MAX_BODY_LEN).collect();
+        if body.len() < content.len() {
+            body.push('…');
+        }
+
+        Self {
+            title: format!("{author} in #{}", channel.name()),
+            body,
+            guild_id: channel.guild_id(),
+            channel_id: message.channel_id(),
+            message_id: message.id(),
+        }
+    }
+}
+
+/// Why a push notification could not be delivered.
+#[derive(Debug, thiserror::Error)]
+pub enum PushError {
+    /// The device is no longer registered with the service, it should be removed.
+    #[error("The device is no longer registered")]
+    Unregistered,
+    /// The delivery failed and may be retried.
+    #[error("{0}")]
+    Failed(String),
+}
+
+impl From<reqwest::Error> for PushError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Failed(e.to_string())
+    }
+}
+
+/// Delivers notifications to the devices of users through a push service.
+#[async_trait::async_trait]
+pub trait PushProvider: Debug + Send + Sync {
+    /// The platform this provider delivers notifications to.
+    fn platform(&self) -> PushPlatform;
+
+    /// Deliver a notification to a device.
+    ///
+    /// ## Arguments
+    ///
+    /// * `token` - The token of the device, as validated by [`PushDevice::from_payload`].
+    /// * `notification` - The notification to deliver.
+    async fn send(&self, token: &str, notification: &PushNotification) -> Result<(), PushError>;
+}
+
+/// A notification claimed for a delivery attempt.
+struct ClaimedDelivery {
+    id: i64,
+    payload: String,
+    attempts: i32,
+    device_id: i64,
+    platform: i16,
+    token: String,
+}
+
+/// Delivers push notifications to users who are not connected to the gateway, through the configured providers.
+///
+/// Notifications are queued in the database first, and sent by a background task.
+/// Failed deliveries are retried with exponential backoff, up to [`MAX_ATTEMPTS`] times.
+/// Devices the push service no longer knows about are removed.
+#[derive(Debug, Clone)]
+pub struct PushDispatcher {
+    providers: Vec<Arc<dyn PushProvider>>,
+    notify: Arc<Notify>,
+    app: Weak<ApplicationState>,
+}
+
+impl PushDispatcher {
+    /// Create a new dispatcher with a provider for every platform that is configured.
+    ///
+    /// ## Panics
+    ///
+    /// If the configuration of a platform is incomplete or its keys are invalid.
+    pub fn new(config: &Config) -> Self {
+        let mut providers: Vec<Arc<dyn PushProvider>> = Vec::new();
+
+        match WebPushProvider::from_config(config) {
+            Ok(Some(provider)) => providers.push(Arc::new(provider)),
+            Ok(None) => {}
+            Err(e) => panic!("Invalid Web Push configuration: {e}"),
+        }
+        match ApnsProvider::from_config(config) {
+            Ok(Some(provider)) => providers.push(Arc::new(provider)),
+            Ok(None) => {}
+            Err(e) => panic!("Invalid APNs configuration: {e}"),
+        }
+        match FcmProvider::from_config(config) {
+            Ok(Some(provider)) => providers.push(Arc::new(provider)),
+            Ok(None) => {}
+            Err(e) => panic!("Invalid FCM configuration: {e}"),
+        }
+
+        Self {
+            providers,
+            notify: Arc::new(Notify::new()),
+            app: Weak::new(),
+        }
+    }
+
+    pub fn bind_to(&mut self, app: Weak<ApplicationState>) {
+        self.app = app;
+    }
+
+    pub fn app(&self) -> Arc<ApplicationState> {
+        self.app.upgrade().expect("Application state has been dropped.")
+    }
+
+    /// The platforms notifications can be delivered to.
+    pub fn platforms(&self) -> Vec<PushPlatform> {
+        self.providers.iter().map(|provider| provider.platform()).collect()
+    }
+
+    /// Whether notifications can be delivered to the given platform.
+    pub fn supports(&self, platform: PushPlatform) -> bool {
+        self.providers.iter().any(|provider| provider.platform() == platform)
+    }
+
+    /// Notify the users mentioned in a message, except for its author.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The message, after its mentions were resolved.
+    /// * `channel` - The channel the message was sent in.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::JSON`] - If the notification could not be serialized.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn notify_mentions(&self, message: &Message, channel: &Channel) -> Result<(), AppError> {
+        let author = message.author().map(UserLike::id);
+        let recipients: Vec<Snowflake<User>> = message
+            .mentions()
+            .iter()
+            .copied()
+            .filter(|user| Some(*user) != author)
+            .collect();
+
+        self.enqueue(&recipients, &PushNotification::mention(message, channel))
+            .await
+    }
+
+    /// Queue a notification for delivery to the devices of the given users.
+    ///
+    /// Users connected to the gateway receive the corresponding gateway event instead, and users
+    /// who set the `MUTE_PUSH_NOTIFICATIONS` preference flag are not notified at all.
+    ///
+    /// ## Arguments
+    ///
+    /// * `users` - The users to notify.
+    /// * `notification` - The notification to deliver.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::JSON`] - If the notification could not be serialized.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn enqueue(&self, users: &[Snowflake<User>], notification: &PushNotification) -> Result<(), AppError> {
+        if users.is_empty() || self.providers.is_empty() {
+            return Ok(());
+        }
+
+        let app = self.app();
+        let users: Vec<i64> = users.iter().map(|user| (*user).into()).collect();
+        let platforms: Vec<i16> = self.platforms().into_iter().map(|platform| platform as i16).collect();
+
+        let device_ids: Vec<i64> = sqlx::query!(
+            "SELECT d.id FROM push_devices AS d
+            LEFT JOIN prefs AS p ON p.user_id = d.user_id
+            WHERE d.user_id = ANY($1) AND d.platform = ANY($2)
+            AND COALESCE(p.flags & $3, 0) = 0
+            AND NOT EXISTS (SELECT 1 FROM gateway_sessions AS s WHERE s.user_id = d.user_id)",
+            &users,
+            &platforms,
+            PrefFlags::MUTE_PUSH_NOTIFICATIONS.bits() as i64,
+        )
+        .fetch_all(app.db.executor())
+        .await?
+        .into_iter()
+        .map(|r| r.id)
+        .collect();
+
+        if device_ids.is_empty() {
+            return Ok(());
+        }
+
+        let ids: Vec<i64> = device_ids
+            .iter()
+            .map(|_| Snowflake::<PushNotification>::gen_new(&app.config).into())
+            .collect();
+
+        sqlx::query!(
+            "INSERT INTO push_deliveries (id, device_id, payload, next_attempt_at, created_at)
+            SELECT d.id, d.device_id, $3, $4, $4
+            FROM UNNEST($1::BIGINT[], $2::BIGINT[]) AS d(id, device_id)",
+            &ids,
+            &device_ids,
+            serde_json::to_string(notification)?,
+            app.clock.timestamp(),
+        )
+        .execute(app.db.executor())
+        .await?;
+
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// Wait until new notifications are queued.
+    pub async fn notified(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Attempt to deliver all notifications that are due.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of delivery attempts made.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn deliver_pending(&self) -> Result<usize, sqlx::Error> {
+        let app = self.app();
+        let mut attempted = 0;
+
+        loop {
+            let now = app.clock.timestamp();
+
+            // Claim due notifications, so they are not sent twice if another attempt is still running
+            let claimed = sqlx::query_as!(
+                ClaimedDelivery,
+                "WITH due AS (
+                    SELECT id FROM push_deliveries
+                    WHERE next_attempt_at <= $1
+                    ORDER BY next_attempt_at LIMIT $2
+                    FOR UPDATE SKIP LOCKED
+                )
+                UPDATE push_deliveries AS q
+                SET next_attempt_at = $3, attempts = q.attempts + 1
+                FROM due, push_devices AS d
+                WHERE q.id = due.id AND d.id = q.device_id
+                RETURNING q.id, q.payload, q.attempts, q.device_id, d.platform, d.token",
+                now,
+                BATCH_SIZE,
+                now + LEASE_DURATION,
+            )
+            .fetch_all(app.db.executor())
+            .await?;
+
+            let count = claimed.len();
+
+            futures::stream::iter(claimed)
+                .for_each_concurrent(CONCURRENCY, |delivery| async move {
+                    let id = delivery.id;
+                    if let Err(e) = self.attempt(delivery).await {
+                        tracing::error!(delivery = id, error = %e, "Failed to record push notification delivery");
+                    }
+                })
+                .await;
+
+            attempted += count;
+
+            if count < usize::try_from(BATCH_SIZE).expect("Batch size should fit into usize") {
+                return Ok(attempted);
+            }
+        }
+    }
+
+    /// Attempt a single delivery and record its outcome.
+    async fn attempt(&self, delivery: ClaimedDelivery) -> Result<(), sqlx::Error> {
+        let app = self.app();
+        let platform = PushPlatform::from(delivery.platform);
+
+        let result = match (
+            self.providers.iter().find(|provider| provider.platform() == platform),
+            serde_json::from_str::<PushNotification>(&delivery.payload),
+        ) {
+            (Some(provider), Ok(notification)) => provider.send(&delivery.token, &notification).await,
+            // The platform was disabled since the notification was queued
+            (None, _) => Err(PushError::Failed(format!("{platform:?} is not enabled"))),
+            (_, Err(e)) => Err(PushError::Failed(e.to_string())),
+        };
+
+        match result {
+            Ok(()) => {
+                sqlx::query!("DELETE FROM push_deliveries WHERE id = $1", delivery.id)
+                    .execute(app.db.executor())
+                    .await?;
+            }
+            Err(PushError::Unregistered) => {
+                tracing::debug!(device = delivery.device_id, "Removing unregistered push device");
+                sqlx::query!("DELETE FROM push_devices WHERE id = $1", delivery.device_id)
+                    .execute(app.db.executor())
+                    .await?;
+            }
+            Err(PushError::Failed(error)) if delivery.attempts < MAX_ATTEMPTS => {
+                tracing::debug!(delivery = delivery.id, error, "Push notification delivery failed");
+                let delay = RETRY_BASE_DELAY * 4_i64.pow(delivery.attempts.max(1).unsigned_abs() - 1);
+
+                sqlx::query!(
+                    "UPDATE push_deliveries SET next_attempt_at = $2 WHERE id = $1",
+                    delivery.id,
+                    app.clock.timestamp() + delay,
+                )
+                .execute(app.db.executor())
+                .await?;
+            }
+            Err(PushError::Failed(error)) => {
+                tracing::warn!(
+                    delivery = delivery.id,
+                    error,
+                    "Dropping push notification after repeated failures"
+                );
+                sqlx::query!("DELETE FROM push_deliveries WHERE id = $1", delivery.id)
+                    .execute(app.db.executor())
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PushDevice, PushPlatform};
+    use crate::models::{requests::CreatePushDevice, state::Config};
+
+    fn register(platform: PushPlatform, token: &str) -> Result<String, String> {
+        let config = Config::builder()
+            .database_url("postgres://localhost".to_string())
+            .minio_url("http://localhost".to_string())
+            .minio_access_key("access".to_string())
+            .minio_secret_key("secret".to_string())
+            .listen_addr(([127, 0, 0, 1], 8080))
+            .machine_id(0)
+            .process_id(0)
+            .app_secret("hunter2".to_string())
+            .build()
+            .expect("Config should be valid");
+        let payload = CreatePushDevice {
+            platform,
+            token: token.to_string(),
+        };
+        PushDevice::from_payload(&config, 1, &payload, 0)
+            .map(|device| device.token().to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_validate_token() {
+        assert_eq!(register(PushPlatform::Apns, " ABCdef0123 "), Ok("abcdef0123".into()));
+        assert!(register(PushPlatform::Apns, "not-hex").is_err());
+        assert!(register(PushPlatform::Apns, "").is_err());
+        assert!(register(PushPlatform::Fcm, "fcm:token-1").is_ok());
+        assert!(register(PushPlatform::Fcm, "two words").is_err());
+        assert!(register(PushPlatform::Fcm, &"a".repeat(4097)).is_err());
+        assert!(register(PushPlatform::WebPush, "{}").is_err());
+        assert!(register(PushPlatform::Unknown, "token").is_err());
+    }
+}
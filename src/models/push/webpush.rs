@@ -0,0 +1,386 @@
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use reqwest::{header, Client, StatusCode, Url};
+use ring::{
+    aead, agreement,
+    error::Unspecified,
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+
+use super::{PushError, PushNotification, PushPlatform, PushProvider};
+use crate::models::{
+    errors::RESTError,
+    media_proxy::{ensure_public_url, public_client, PublicResolver},
+    state::Config,
+};
+
+/// The record size advertised in the header of encrypted payloads. Payloads always fit into a single record.
+const RECORD_SIZE: u32 = 4096;
+/// How long push services keep a notification for a device that is offline, in seconds.
+const TTL: u64 = 24 * 60 * 60;
+/// How long the VAPID tokens sent to push services are valid for, in seconds. Push services reject tokens valid for more than a day.
+const VAPID_TOKEN_LIFETIME: u64 = 12 * 60 * 60;
+
+/// The keys of a Web Push subscription.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SubscriptionKeys {
+    /// The public key of the browser, as an uncompressed P-256 point.
+    p256dh: String,
+    /// The authentication secret of the subscription.
+    auth: String,
+}
+
+/// A Web Push subscription, as returned by `PushSubscription.toJSON()` in browsers.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(super) struct Subscription {
+    /// The URL of the push service to send notifications to.
+    endpoint: String,
+    keys: SubscriptionKeys,
+}
+
+impl Subscription {
+    /// Parse and validate a serialized subscription.
+    ///
+    /// ## Errors
+    ///
+    /// A description of the problem if the subscription is malformed.
+    pub(super) fn parse(token: &str) -> Result<Self, String> {
+        let subscription: Self = serde_json::from_str(token)
+            .map_err(|_| "Web Push tokens must be serialized PushSubscription objects.".to_string())?;
+
+        if !Url::parse(&subscription.endpoint).is_ok_and(|url| url.scheme() == "https") {
+            return Err("The endpoint of the subscription must be an HTTPS URL.".into());
+        }
+        subscription.public_key()?;
+        subscription.auth_secret()?;
+        Ok(subscription)
+    }
+
+    /// Serialize the subscription into the token stored for the device.
+    pub(super) fn to_token(&self) -> String {
+        serde_json::to_string(self).expect("Subscriptions should serialize")
+    }
+
+    /// The decoded public key of the browser.
+    fn public_key(&self) -> Result<Vec<u8>, String> {
+        URL_SAFE_NO_PAD
+            .decode(self.keys.p256dh.trim_end_matches('='))
+            .ok()
+            .filter(|key| key.len() == 65 && key[0] == 0x04)
+            .ok_or_else(|| "The p256dh key of the subscription must be an uncompressed P-256 point.".into())
+    }
+
+    /// The decoded authentication secret of the subscription.
+    fn auth_secret(&self) -> Result<Vec<u8>, String> {
+        URL_SAFE_NO_PAD
+            .decode(self.keys.auth.trim_end_matches('='))
+            .ok()
+            .filter(|secret| secret.len() == 16)
+            .ok_or_else(|| "The auth secret of the subscription must be 16 bytes long.".into())
+    }
+}
+
+/// Delivers notifications to browsers through the Web Push protocol, authenticated with VAPID.
+///
+/// Payloads are encrypted for the browser, so push services cannot read them.
+#[derive(Debug)]
+pub struct WebPushProvider {
+    client: Client,
+    /// The contact URL of the operator sent to push services, such as `mailto:admin@example.com`.
+    subject: String,
+    /// The public VAPID key, base64url encoded.
+    public_key: String,
+    key_pair: EcdsaKeyPair,
+    rng: SystemRandom,
+}
+
+impl WebPushProvider {
+    /// Create a new Web Push provider, if VAPID keys are configured.
+    ///
+    /// Note: The provider does not follow redirects to avoid being pointed at internal hosts.
+    ///
+    /// ## Errors
+    ///
+    /// A description of the problem if the configuration is incomplete or the keys are invalid.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, String> {
+        let (Some(public_key), Some(private_key)) = (config.vapid_public_key(), config.vapid_private_key()) else {
+            return Ok(None);
+        };
+        let Some(subject) = config.vapid_subject() else {
+            return Err("VAPID_SUBJECT must be set if VAPID keys are set".into());
+        };
+        Self::new(public_key, private_key, subject).map(Some)
+    }
+
+    /// Create a new Web Push provider.
+    ///
+    /// ## Arguments
+    ///
+    /// * `public_key` - The public VAPID key, as a base64url encoded uncompressed P-256 point.
+    /// * `private_key` - The private VAPID key, as a base64url encoded P-256 scalar.
+    /// * `subject` - The contact URL of the operator, such as `mailto:admin@example.com`.
+    ///
+    /// ## Errors
+    ///
+    /// A description of the problem if the keys are invalid.
+    pub fn new(public_key: &str, private_key: &Secret<String>, subject: &str) -> Result<Self, String> {
+        let public = URL_SAFE_NO_PAD
+            .decode(public_key.trim().trim_end_matches('='))
+            .map_err(|_| "VAPID_PUBLIC_KEY must be base64url encoded")?;
+        let private = URL_SAFE_NO_PAD
+            .decode(private_key.expose_secret().trim().trim_end_matches('='))
+            .map_err(|_| "VAPID_PRIVATE_KEY must be base64url encoded")?;
+
+        let rng = SystemRandom::new();
+        let key_pair =
+            EcdsaKeyPair::from_private_key_and_public_key(&ECDSA_P256_SHA256_FIXED_SIGNING, &private, &public, &rng)
+                .map_err(|e| format!("VAPID keys must form a P-256 key pair: {e}"))?;
+
+        // Endpoints are chosen by clients, so only publicly routable addresses are connected to
+        let client = public_client(PublicResolver::new())
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("chat-push/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build Web Push HTTP client");
+
+        Ok(Self {
+            client,
+            subject: subject.to_string(),
+            public_key: URL_SAFE_NO_PAD.encode(public),
+            key_pair,
+            rng,
+        })
+    }
+
+    /// The public VAPID key, which browsers need to subscribe to notifications from this server.
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
+
+    /// Create a VAPID token authenticating this server to the push service of an endpoint.
+    fn vapid_token(&self, endpoint: &Url) -> Result<String, Unspecified> {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = json!({
+            "aud": endpoint.origin().ascii_serialization(),
+            "exp": jsonwebtoken::get_current_timestamp() + VAPID_TOKEN_LIFETIME,
+            "sub": self.subject,
+        });
+        let message = format!("{header}.{}", URL_SAFE_NO_PAD.encode(claims.to_string()));
+        let signature = self.key_pair.sign(&self.rng, message.as_bytes())?;
+
+        Ok(format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature.as_ref())))
+    }
+
+    /// Encrypt a payload for a browser with the `aes128gcm` content encoding, as described in RFC 8291.
+    ///
+    /// ## Arguments
+    ///
+    /// * `public_key` - The public key of the browser.
+    /// * `auth_secret` - The authentication secret of the subscription.
+    /// * `plaintext` - The payload to encrypt.
+    fn encrypt(&self, public_key: &[u8], auth_secret: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Unspecified> {
+        let private = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &self.rng)?;
+        let server_key = private.compute_public_key()?;
+
+        let ikm = agreement::agree_ephemeral(
+            private,
+            &agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, public_key),
+            |shared| {
+                let info = [b"WebPush: info\0", public_key, server_key.as_ref()].concat();
+                hkdf(auth_secret, shared, &info)
+            },
+        )?;
+
+        let salt: [u8; 16] = rand::random();
+        let content_key = hkdf(&salt, &ikm, b"Content-Encoding: aes128gcm\0");
+        let nonce = hkdf(&salt, &ikm, b"Content-Encoding: nonce\0");
+
+        let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_128_GCM, &content_key[..16])?);
+        // A single record, terminated by the delimiter of the last record and no padding
+        let mut record = [plaintext, &[2]].concat();
+        key.seal_in_place_append_tag(
+            aead::Nonce::try_assume_unique_for_key(&nonce[..12])?,
+            aead::Aad::empty(),
+            &mut record,
+        )?;
+
+        let key_id = server_key.as_ref();
+        Ok([
+            &salt[..],
+            &RECORD_SIZE.to_be_bytes(),
+            &[key_id.len() as u8],
+            key_id,
+            &record,
+        ]
+        .concat())
+    }
+}
+
+#[async_trait::async_trait]
+impl PushProvider for WebPushProvider {
+    fn platform(&self) -> PushPlatform {
+        PushPlatform::WebPush
+    }
+
+    async fn send(&self, token: &str, notification: &PushNotification) -> Result<(), PushError> {
+        let subscription = Subscription::parse(token).map_err(|_| PushError::Unregistered)?;
+        let endpoint = Url::parse(&subscription.endpoint).map_err(|_| PushError::Unregistered)?;
+
+        // The endpoint is chosen by the client. The addresses of host names are checked by the resolver
+        // of the client on every attempt, as DNS records may change
+        match ensure_public_url(&endpoint) {
+            Ok(()) => {}
+            Err(RESTError::Forbidden(_)) => return Err(PushError::Unregistered),
+            Err(e) => return Err(PushError::Failed(e.to_string())),
+        }
+
+        let unspecified = |_| PushError::Failed("Failed to encrypt notification".into());
+        let body = self
+            .encrypt(
+                &subscription.public_key().map_err(PushError::Failed)?,
+                &subscription.auth_secret().map_err(PushError::Failed)?,
+                serde_json::to_string(notification)
+                    .map_err(|e| PushError::Failed(e.to_string()))?
+                    .as_bytes(),
+            )
+            .map_err(unspecified)?;
+        let vapid = self.vapid_token(&endpoint).map_err(unspecified)?;
+
+        let response = self
+            .client
+            .post(endpoint)
+            .header(header::CONTENT_ENCODING, "aes128gcm")
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .header(header::AUTHORIZATION, format!("vapid t={vapid}, k={}", self.public_key))
+            .header("TTL", TTL)
+            .header("Urgency", "high")
+            .body(body)
+            .send()
+            .await?;
+
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            // The subscription expired or was revoked by the user
+            StatusCode::NOT_FOUND | StatusCode::GONE => Err(PushError::Unregistered),
+            status => Err(PushError::Failed(format!("Push service responded with {status}"))),
+        }
+    }
+}
+
+/// Derive a key with HKDF-SHA-256. Web Push never needs more than a single block of output.
+fn hkdf(salt: &[u8], ikm: &[u8], info: &[u8]) -> [u8; 32] {
+    let prk = hmac_sha256(salt, ikm);
+    hmac_sha256(&prk, &[info, &[1]].concat())
+}
+
+/// Compute the HMAC-SHA-256 of some data.
+fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use ring::{aead, agreement, rand::SystemRandom};
+    use secrecy::Secret;
+
+    use super::{hkdf, Subscription, WebPushProvider, RECORD_SIZE};
+
+    const PUBLIC_KEY: &str = "BKcAOEdDDyxq-2t3NU9BbpfnN5sjIR2iwUv75nuDxj-FwDIniT9tiDNtNBYfIww-8au4Rt-WbBj87bWPfJXfiCY";
+    const PRIVATE_KEY: &str = "RX11lZESxE6NGhoGkzgb5FTQ81yBf4qVBSGwgonMmcI";
+
+    #[test]
+    fn test_parse_subscription() {
+        let valid = format!(
+            r#"{{"endpoint": "https://push.example.com/abc", "expirationTime": null,
+            "keys": {{"p256dh": "{PUBLIC_KEY}", "auth": "AAAAAAAAAAAAAAAAAAAAAA"}}}}"#
+        );
+        let subscription = Subscription::parse(&valid).expect("Subscription should be valid");
+        assert!(!subscription.to_token().contains("expirationTime"));
+
+        for invalid in [
+            valid.replace("https:", "http:"),
+            valid.replace(PUBLIC_KEY, "AAAA"),
+            valid.replace("AAAAAAAAAAAAAAAAAAAAAA", "AAAA"),
+            "not json".to_string(),
+        ] {
+            assert!(Subscription::parse(&invalid).is_err(), "{invalid} should be invalid");
+        }
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        let private_key = Secret::new(PRIVATE_KEY.to_string());
+        assert!(WebPushProvider::new(PUBLIC_KEY, &private_key, "mailto:admin@example.com").is_ok());
+        assert!(WebPushProvider::new(PUBLIC_KEY, &Secret::new("AAAA".into()), "mailto:admin@example.com").is_err());
+        assert!(WebPushProvider::new("!", &private_key, "mailto:admin@example.com").is_err());
+    }
+
+    #[test]
+    fn test_encrypt() {
+        let provider = WebPushProvider::new(
+            PUBLIC_KEY,
+            &Secret::new(PRIVATE_KEY.to_string()),
+            "mailto:admin@example.com",
+        )
+        .expect("Keys should be valid");
+
+        // Act as the browser, decrypting the payload as described in RFC 8291
+        let rng = SystemRandom::new();
+        let browser_key =
+            agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng).expect("Key should generate");
+        let browser_public = browser_key.compute_public_key().expect("Key should be valid");
+        let auth_secret = [7; 16];
+
+        let body = provider
+            .encrypt(browser_public.as_ref(), &auth_secret, b"hello")
+            .expect("Payload should encrypt");
+
+        let (salt, rest) = body.split_at(16);
+        let (record_size, rest) = rest.split_at(4);
+        assert_eq!(record_size, RECORD_SIZE.to_be_bytes());
+        let (key_id, record) = rest[1..].split_at(usize::from(rest[0]));
+
+        let ikm = agreement::agree_ephemeral(
+            browser_key,
+            &agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, key_id),
+            |shared| {
+                let info = [b"WebPush: info\0", browser_public.as_ref(), key_id].concat();
+                hkdf(&auth_secret, shared, &info)
+            },
+        )
+        .expect("Key agreement should succeed");
+        let content_key = hkdf(salt, &ikm, b"Content-Encoding: aes128gcm\0");
+        let nonce = hkdf(salt, &ikm, b"Content-Encoding: nonce\0");
+
+        let key = aead::LessSafeKey::new(
+            aead::UnboundKey::new(&aead::AES_128_GCM, &content_key[..16]).expect("Key should be valid"),
+        );
+        let mut record = record.to_vec();
+        let plaintext = key
+            .open_in_place(
+                aead::Nonce::try_assume_unique_for_key(&nonce[..12]).expect("Nonce should be valid"),
+                aead::Aad::empty(),
+                &mut record,
+            )
+            .expect("Payload should decrypt");
+        assert_eq!(plaintext, b"hello\x02");
+
+        let token = provider
+            .vapid_token(&"https://push.example.com/abc".parse().expect("URL should be valid"))
+            .expect("Token should be signed");
+        let claims = URL_SAFE_NO_PAD
+            .decode(token.split('.').nth(1).expect("Token should have claims"))
+            .expect("Claims should be base64url encoded");
+        assert!(String::from_utf8_lossy(&claims).contains(r#""aud":"https://push.example.com""#));
+    }
+}
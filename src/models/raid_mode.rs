@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use axum::{
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use thiserror::Error;
+
+use super::{errors::ErrorCode, message::Message, snowflake::Snowflake};
+
+/// The longest a guild owner may enable raid mode for at once, one week.
+pub const MAX_RAID_MODE_DURATION: i64 = 7 * 24 * 60 * 60;
+
+/// Sent when a member sends messages faster than the slow mode of a guild in raid mode allows.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("This guild is in slow mode, you can send another message in {retry_after} seconds.")]
+pub struct SlowModeError {
+    /// The amount of seconds until the member may send another message.
+    pub retry_after: u64,
+}
+
+impl SlowModeError {
+    /// Check whether a member may send a message, given the last message they sent in the guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `last_message` - The ID of the last message the member sent in the guild, if any.
+    /// * `interval` - The minimum time between two messages of a member.
+    /// * `now` - The time the member is sending the message at.
+    ///
+    /// ## Errors
+    ///
+    /// * [`SlowModeError`] - If the member sent their last message less than `interval` ago.
+    pub fn check(last_message: Option<Snowflake<Message>>, interval: Duration, now: DateTime<Utc>) -> Result<(), Self> {
+        let Some(last_message) = last_message else {
+            return Ok(());
+        };
+
+        let next_allowed_at = last_message.timestamp() + interval.as_millis() as i64;
+        let wait = next_allowed_at - now.timestamp_millis();
+        if wait > 0 {
+            return Err(Self {
+                retry_after: (wait as u64).div_ceil(1000),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl IntoResponse for SlowModeError {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "error": self.to_string(),
+            "code": ErrorCode::SlowMode,
+            "retry_after": self.retry_after,
+        });
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, HeaderValue::from(self.retry_after))],
+            Json(body),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use chrono::TimeDelta;
+
+    use super::SlowModeError;
+    use crate::models::{message::Message, snowflake::Snowflake};
+
+    #[test]
+    fn test_check() {
+        let last_message: Snowflake<Message> = Snowflake::new(502_225_724_397_195_265);
+        let sent_at = last_message.created_at();
+        let interval = Duration::from_secs(30);
+
+        assert_eq!(
+            SlowModeError::check(Some(last_message), interval, sent_at + TimeDelta::milliseconds(10_500)),
+            Err(SlowModeError { retry_after: 20 })
+        );
+        assert_eq!(
+            SlowModeError::check(Some(last_message), interval, sent_at + TimeDelta::seconds(30)),
+            Ok(())
+        );
+        assert_eq!(SlowModeError::check(None, interval, sent_at), Ok(()));
+    }
+}
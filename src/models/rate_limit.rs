@@ -0,0 +1,317 @@
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use dashmap::DashMap;
+use serde_json::json;
+
+use super::{errors::ErrorCode, guild::Guild, snowflake::Snowflake, user::User};
+
+/// The amount of tracked windows after which expired windows are pruned.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+pub const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+pub const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+pub const X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+pub const X_RATELIMIT_BUCKET: HeaderName = HeaderName::from_static("x-ratelimit-bucket");
+
+/// A group of routes sharing a rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitBucket {
+    Channels,
+    Messages,
+    Guilds,
+    Users,
+    Prefs,
+    Proxy,
+    AutoMod,
+    Webhooks,
+    Feeds,
+    Follows,
+    Reports,
+    Strikes,
+    /// Welcome messages sent by a guild to new members, counted per guild.
+    WelcomeMessages,
+    /// Requests for guild members sent over the gateway.
+    GuildMemberRequests,
+    /// Searches for users by username, limited separately to make enumerating users impractical.
+    UserSearch,
+    /// Translations of messages, limited separately as they are sent to an external service.
+    Translations,
+}
+
+impl RateLimitBucket {
+    /// The identifier of the bucket, sent to clients in the `X-RateLimit-Bucket` header.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Channels => "channels",
+            Self::Messages => "messages",
+            Self::Guilds => "guilds",
+            Self::Users => "users",
+            Self::Prefs => "prefs",
+            Self::Proxy => "proxy",
+            Self::AutoMod => "automod",
+            Self::Webhooks => "webhooks",
+            Self::Feeds => "feeds",
+            Self::Follows => "follows",
+            Self::Reports => "reports",
+            Self::Strikes => "strikes",
+            Self::WelcomeMessages => "welcome_messages",
+            Self::GuildMemberRequests => "guild_member_requests",
+            Self::UserSearch => "user_search",
+            Self::Translations => "translations",
+        }
+    }
+
+    /// The amount of requests allowed per window.
+    pub const fn limit(self) -> u32 {
+        match self {
+            Self::Messages | Self::WelcomeMessages | Self::GuildMemberRequests | Self::UserSearch => 10,
+            Self::Translations => 30,
+            Self::Users
+            | Self::AutoMod
+            | Self::Webhooks
+            | Self::Feeds
+            | Self::Follows
+            | Self::Reports
+            | Self::Strikes => 20,
+            Self::Channels | Self::Guilds | Self::Prefs | Self::Proxy => 50,
+        }
+    }
+
+    /// The length of a window, after which the amount of remaining requests is reset.
+    pub const fn period(self) -> Duration {
+        match self {
+            Self::WelcomeMessages | Self::GuildMemberRequests | Self::UserSearch | Self::Translations => {
+                Duration::from_mins(1)
+            }
+            _ => Duration::from_secs(10),
+        }
+    }
+}
+
+/// Who a request is counted towards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitKey {
+    /// An authenticated user, shared across all of their connections.
+    User(Snowflake<User>),
+    /// An unauthenticated client.
+    Ip(IpAddr),
+    /// A client whose address is unknown.
+    Unknown,
+    /// A guild, for limits on what the guild does rather than on requests.
+    Guild(Snowflake<Guild>),
+}
+
+/// The requests counted in the current window of a bucket.
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    /// When the window ends.
+    resets_at: Instant,
+    /// The amount of requests made in the window.
+    count: u32,
+}
+
+/// The state of a bucket after counting a request.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitStatus {
+    bucket: RateLimitBucket,
+    remaining: u32,
+    reset_after: Duration,
+    exceeded: bool,
+}
+
+impl RateLimitStatus {
+    /// The bucket the request was counted towards.
+    pub const fn bucket(&self) -> RateLimitBucket {
+        self.bucket
+    }
+
+    /// The amount of requests left in the current window.
+    pub const fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// The time until the current window resets.
+    pub const fn reset_after(&self) -> Duration {
+        self.reset_after
+    }
+
+    /// Whether the request exceeded the limit and must be rejected.
+    pub const fn is_exceeded(&self) -> bool {
+        self.exceeded
+    }
+
+    /// The UNIX timestamp of when the current window resets, in seconds.
+    pub fn reset_at(&self) -> f64 {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        (now + self.reset_after).as_secs_f64()
+    }
+
+    /// The `X-RateLimit-*` headers describing the status.
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_RATELIMIT_LIMIT, HeaderValue::from(self.bucket.limit()));
+        headers.insert(X_RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        if let Ok(reset) = HeaderValue::from_str(&format!("{:.3}", self.reset_at())) {
+            headers.insert(X_RATELIMIT_RESET, reset);
+        }
+        headers.insert(X_RATELIMIT_BUCKET, HeaderValue::from_static(self.bucket.name()));
+        headers
+    }
+}
+
+/// Returned with `429 Too Many Requests` when a client exceeds the limit of a bucket.
+impl IntoResponse for RateLimitStatus {
+    fn into_response(self) -> Response {
+        let retry_after = self.reset_after.as_secs_f64();
+        let mut headers = self.headers();
+        headers.insert(
+            axum::http::header::RETRY_AFTER,
+            HeaderValue::from(retry_after.ceil() as u64),
+        );
+
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            headers,
+            Json(json!({
+                "error": "Too Many Requests",
+                "code": ErrorCode::RateLimited,
+                "bucket": self.bucket.name(),
+                "retry_after": (retry_after * 1000.0).round() / 1000.0,
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Limits the amount of requests each client may make to a bucket in a fixed window of time.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    /// Whether requests are limited at all, shared with all clones of the limiter.
+    enabled: Arc<AtomicBool>,
+    /// The current window of each client in each bucket.
+    windows: DashMap<(RateLimitBucket, RateLimitKey), Window>,
+}
+
+impl RateLimiter {
+    /// Create a new rate limiter.
+    ///
+    /// ## Arguments
+    ///
+    /// * `enabled` - Whether requests are limited. If `false`, every request is allowed.
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: Arc::new(AtomicBool::new(enabled)),
+            windows: DashMap::new(),
+        }
+    }
+
+    /// Whether requests are limited at all.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Start or stop limiting requests. Windows counted while enabled are kept.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Count a request towards a bucket.
+    ///
+    /// ## Arguments
+    ///
+    /// * `bucket` - The bucket of the requested route.
+    /// * `key` - The client making the request.
+    ///
+    /// ## Returns
+    ///
+    /// The state of the bucket, including whether the request must be rejected.
+    pub fn hit(&self, bucket: RateLimitBucket, key: RateLimitKey) -> RateLimitStatus {
+        self.hit_at(bucket, key, Instant::now())
+    }
+
+    fn hit_at(&self, bucket: RateLimitBucket, key: RateLimitKey, now: Instant) -> RateLimitStatus {
+        if self.windows.len() > PRUNE_THRESHOLD {
+            self.windows.retain(|_, window| window.resets_at > now);
+        }
+
+        let window = {
+            let mut window = self.windows.entry((bucket, key)).or_insert(Window {
+                resets_at: now + bucket.period(),
+                count: 0,
+            });
+            if window.resets_at <= now {
+                *window = Window {
+                    resets_at: now + bucket.period(),
+                    count: 0,
+                };
+            }
+            if window.count < bucket.limit() {
+                window.count += 1;
+                *window
+            } else {
+                return RateLimitStatus {
+                    bucket,
+                    remaining: 0,
+                    reset_after: window.resets_at - now,
+                    exceeded: true,
+                };
+            }
+        };
+
+        RateLimitStatus {
+            bucket,
+            remaining: bucket.limit() - window.count,
+            reset_after: window.resets_at - now,
+            exceeded: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{RateLimitBucket, RateLimitKey, RateLimiter};
+
+    #[test]
+    fn test_hit() {
+        let limiter = RateLimiter::new(true);
+        let bucket = RateLimitBucket::Messages;
+        let now = Instant::now();
+
+        for i in 1..=bucket.limit() {
+            let status = limiter.hit_at(bucket, RateLimitKey::Unknown, now);
+            assert!(!status.is_exceeded());
+            assert_eq!(status.remaining(), bucket.limit() - i);
+        }
+
+        let status = limiter.hit_at(bucket, RateLimitKey::Unknown, now + Duration::from_secs(1));
+        assert!(status.is_exceeded());
+        assert_eq!(status.reset_after() + Duration::from_secs(1), bucket.period());
+
+        // Other buckets and clients are counted separately
+        assert!(!limiter
+            .hit_at(RateLimitBucket::Guilds, RateLimitKey::Unknown, now)
+            .is_exceeded());
+        assert!(!limiter
+            .hit_at(bucket, RateLimitKey::Ip([127, 0, 0, 1].into()), now)
+            .is_exceeded());
+
+        // The limit is reset once the window ends
+        let status = limiter.hit_at(bucket, RateLimitKey::Unknown, now + bucket.period());
+        assert!(!status.is_exceeded());
+        assert_eq!(status.remaining(), bucket.limit() - 1);
+    }
+}
@@ -0,0 +1,315 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    channel::Channel,
+    errors::{BuildError, ErrorCode},
+    guild::Guild,
+    member::UserLike,
+    message::Message,
+    requests::CreateReport,
+    snowflake::Snowflake,
+    state::Config,
+    user::User,
+};
+
+/// The maximum length of the details of a report, in characters.
+pub const MAX_DETAILS_LENGTH: usize = 1000;
+
+/// Why a message was reported.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum ReportReason {
+    /// A reason that is not known to this version of the server.
+    #[serde(skip_deserializing)]
+    Unknown = -1,
+    /// Unsolicited advertising or repetitive messages.
+    Spam = 0,
+    /// Abuse targeted at a user.
+    Harassment = 1,
+    /// Attacks on people based on who they are.
+    HateSpeech = 2,
+    /// Sexual or graphic content.
+    Explicit = 3,
+    /// Threats or glorification of violence.
+    Violence = 4,
+    /// Any other reason, explained in the details of the report.
+    Other = 5,
+}
+
+impl From<i16> for ReportReason {
+    fn from(reason: i16) -> Self {
+        match reason {
+            0 => Self::Spam,
+            1 => Self::Harassment,
+            2 => Self::HateSpeech,
+            3 => Self::Explicit,
+            4 => Self::Violence,
+            5 => Self::Other,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Where a report is in its review.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum ReportState {
+    /// The report was not reviewed yet.
+    Open = 0,
+    /// The report was reviewed, and action was taken.
+    Actioned = 1,
+    /// The report was reviewed, and no action was needed.
+    Dismissed = 2,
+}
+
+impl ReportState {
+    /// Whether a report in this state may be moved to the given state.
+    /// Open reports can be actioned or dismissed, reviewed reports are final.
+    pub const fn can_transition_to(self, state: Self) -> bool {
+        matches!((self, state), (Self::Open, Self::Actioned | Self::Dismissed))
+    }
+}
+
+impl From<i16> for ReportState {
+    fn from(state: i16) -> Self {
+        match state {
+            1 => Self::Actioned,
+            2 => Self::Dismissed,
+            _ => Self::Open,
+        }
+    }
+}
+
+/// Represents a report record stored in the database.
+pub struct ReportRecord {
+    pub id: Snowflake<Report>,
+    pub guild_id: Snowflake<Guild>,
+    pub channel_id: Snowflake<Channel>,
+    pub message_id: Snowflake<Message>,
+    pub author_id: Option<i64>,
+    pub content: Option<String>,
+    pub reporter_id: Option<i64>,
+    pub reason: i16,
+    pub details: Option<String>,
+    pub state: i16,
+    pub resolved_by: Option<i64>,
+}
+
+/// A report of a message, reviewed by the owner of the guild it was sent in.
+#[derive(Serialize, Debug, Clone)]
+pub struct Report {
+    /// The ID of the report. This also encodes when the report was made.
+    id: Snowflake<Self>,
+    /// The guild the reported message was sent in.
+    guild_id: Snowflake<Guild>,
+    /// The channel the reported message was sent in.
+    channel_id: Snowflake<Channel>,
+    /// The reported message. It may have been deleted since.
+    message_id: Snowflake<Message>,
+    /// The author of the reported message, if they still exist.
+    author_id: Option<Snowflake<User>>,
+    /// The content of the reported message at the time of the report.
+    content: Option<String>,
+    /// The user who made the report, if they still exist.
+    reporter_id: Option<Snowflake<User>>,
+    /// Why the message was reported.
+    reason: ReportReason,
+    /// Additional context given by the reporter.
+    details: Option<String>,
+    /// Where the report is in its review.
+    state: ReportState,
+    /// The user who reviewed the report, if it was reviewed.
+    resolved_by: Option<Snowflake<User>>,
+}
+
+impl Report {
+    /// Create a new open report of a message. Assigns a new snowflake to the report.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration.
+    /// * `guild` - The guild the message was sent in.
+    /// * `message` - The reported message.
+    /// * `reporter` - The user making the report.
+    /// * `payload` - The reason and details of the report.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If the reason is unknown or the details are too long.
+    pub fn new(
+        config: &Config,
+        guild: impl Into<Snowflake<Guild>>,
+        message: &Message,
+        reporter: impl Into<Snowflake<User>>,
+        payload: CreateReport,
+    ) -> Result<Self, BuildError> {
+        let report = Self {
+            id: Snowflake::gen_new(config),
+            guild_id: guild.into(),
+            channel_id: message.channel_id(),
+            message_id: message.id(),
+            author_id: message.author().map(UserLike::id),
+            content: message.content().cloned(),
+            reporter_id: Some(reporter.into()),
+            reason: payload.reason,
+            details: payload.details.filter(|details| !details.trim().is_empty()),
+            state: ReportState::Open,
+            resolved_by: None,
+        };
+        report.validate()?;
+        Ok(report)
+    }
+
+    /// Build a report directly from a database record.
+    pub fn from_record(record: ReportRecord) -> Self {
+        Self {
+            id: record.id,
+            guild_id: record.guild_id,
+            channel_id: record.channel_id,
+            message_id: record.message_id,
+            author_id: record.author_id.map(Snowflake::new),
+            content: record.content,
+            reporter_id: record.reporter_id.map(Snowflake::new),
+            reason: ReportReason::from(record.reason),
+            details: record.details,
+            state: ReportState::from(record.state),
+            resolved_by: record.resolved_by.map(Snowflake::new),
+        }
+    }
+
+    /// The ID of the report.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The guild the reported message was sent in.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The channel the reported message was sent in.
+    pub const fn channel_id(&self) -> Snowflake<Channel> {
+        self.channel_id
+    }
+
+    /// The reported message.
+    pub const fn message_id(&self) -> Snowflake<Message> {
+        self.message_id
+    }
+
+    /// The author of the reported message, if they still exist.
+    pub const fn author_id(&self) -> Option<Snowflake<User>> {
+        self.author_id
+    }
+
+    /// The content of the reported message at the time of the report.
+    pub fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    /// The user who made the report, if they still exist.
+    pub const fn reporter_id(&self) -> Option<Snowflake<User>> {
+        self.reporter_id
+    }
+
+    /// Why the message was reported.
+    pub const fn reason(&self) -> ReportReason {
+        self.reason
+    }
+
+    /// Additional context given by the reporter.
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
+    /// Where the report is in its review.
+    pub const fn state(&self) -> ReportState {
+        self.state
+    }
+
+    /// The user who reviewed the report, if it was reviewed.
+    pub const fn resolved_by(&self) -> Option<Snowflake<User>> {
+        self.resolved_by
+    }
+
+    /// Move the report to a new state.
+    ///
+    /// ## Arguments
+    ///
+    /// * `state` - The new state of the report.
+    /// * `moderator` - The user reviewing the report.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If the report cannot be moved to the given state.
+    pub fn transition(&mut self, state: ReportState, moderator: impl Into<Snowflake<User>>) -> Result<(), BuildError> {
+        if !self.state.can_transition_to(state) {
+            return Err(BuildError::InvalidField {
+                field: "state",
+                code: ErrorCode::InvalidTransition,
+                message: "Only open reports can be actioned or dismissed.".into(),
+            });
+        }
+        self.state = state;
+        self.resolved_by = Some(moderator.into());
+        Ok(())
+    }
+
+    /// Ensure the report is valid.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If the reason is unknown or the details are too long.
+    fn validate(&self) -> Result<(), BuildError> {
+        if self.reason == ReportReason::Unknown {
+            return Err(BuildError::InvalidField {
+                field: "reason",
+                code: ErrorCode::UnknownReason,
+                message: "Unknown report reason.".into(),
+            });
+        }
+        if self
+            .details
+            .as_ref()
+            .is_some_and(|details| details.chars().count() > MAX_DETAILS_LENGTH)
+        {
+            return Err(BuildError::InvalidField {
+                field: "details",
+                code: ErrorCode::TooLong,
+                message: format!("Report details must be at most {MAX_DETAILS_LENGTH} characters."),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl From<&Report> for Snowflake<Report> {
+    fn from(report: &Report) -> Self {
+        report.id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReportReason, ReportState};
+
+    #[test]
+    fn test_state_transitions() {
+        assert!(ReportState::Open.can_transition_to(ReportState::Actioned));
+        assert!(ReportState::Open.can_transition_to(ReportState::Dismissed));
+        assert!(!ReportState::Open.can_transition_to(ReportState::Open));
+        assert!(!ReportState::Actioned.can_transition_to(ReportState::Dismissed));
+        assert!(!ReportState::Dismissed.can_transition_to(ReportState::Actioned));
+        assert!(!ReportState::Dismissed.can_transition_to(ReportState::Open));
+    }
+
+    #[test]
+    fn test_reason_serde() {
+        let reason: ReportReason = serde_json::from_str(r#""HATE_SPEECH""#).expect("Reason should deserialize");
+        assert_eq!(reason, ReportReason::HateSpeech);
+        assert_eq!(ReportReason::from(reason as i16), reason);
+        assert!(serde_json::from_str::<ReportReason>(r#""UNKNOWN""#).is_err());
+    }
+}
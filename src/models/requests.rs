@@ -2,15 +2,25 @@ use secrecy::Secret;
 use serde::Deserialize;
 
 use super::{
+    automod::{AutoModAction, AutoModTrigger},
     channel::Channel,
     data_uri::DataUri,
     errors::AppError,
     guild::Guild,
+    guild_folder::GuildFolder,
+    join_requirements::JoinRequirements,
+    log_filter::LogLevels,
     member::Member,
+    message::Message,
+    onboarding::OnboardingResponse,
     prefs::{Layout, PrefFlags},
+    push::PushPlatform,
+    report::{ReportReason, ReportState},
     snowflake::Snowflake,
     state::ApplicationState,
     user::User,
+    webhook::WebhookEvent,
+    welcome_message::WelcomeMessage,
 };
 
 /// A request to create a new user
@@ -18,6 +28,9 @@ use super::{
 pub struct CreateUser {
     pub username: String,
     pub password: Secret<String>,
+    /// An identifier of the device the user registers from, used to detect ban evasion
+    #[serde(default)]
+    pub device_id: Option<String>,
 }
 
 /// The JSON part of a multipart form request to create a message
@@ -59,11 +72,19 @@ impl CreateGuild {
     }
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct UpdateGuild {
     pub name: Option<String>,
     pub owner_id: Option<Snowflake<User>>,
     pub avatar: Option<DataUri>,
+    pub join_requirements: Option<JoinRequirements>,
+    pub welcome_message: Option<WelcomeMessage>,
+    /// The locale dates and times are written in, such as `en_US`.
+    pub locale: Option<String>,
+    /// The IANA timezone times are written in, such as `Europe/Berlin`.
+    pub timezone: Option<String>,
+    /// The version of the guild the update is based on. If set, the update fails if the guild was updated since.
+    pub version: Option<i32>,
 }
 
 impl UpdateGuild {
@@ -128,6 +149,13 @@ impl UpdateUser {
     }
 }
 
+/// A request to update how the user arranges their guilds. Fields that are not present are left unchanged.
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateGuildSettings {
+    /// The guilds of the user in sidebar order, grouped into folders
+    pub folders: Option<Vec<GuildFolder>>,
+}
+
 /// Update payload for user preferences
 #[derive(Debug, Clone, Deserialize)]
 pub struct UpdatePrefs {
@@ -136,4 +164,200 @@ pub struct UpdatePrefs {
     pub layout: Option<Layout>,
     pub text_size: Option<u8>,
     pub locale: Option<String>,
+    /// The version of the preferences the update is based on. If set, the update fails if they were updated since.
+    pub version: Option<i32>,
+}
+
+/// A request to create a new automod rule
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateAutoModRule {
+    pub name: String,
+    pub trigger_type: AutoModTrigger,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    pub action: AutoModAction,
+    pub timeout_duration: Option<i64>,
+    pub issue_strike: Option<bool>,
+    pub enabled: Option<bool>,
+}
+
+/// Update payload for automod rules
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateAutoModRule {
+    pub name: Option<String>,
+    pub patterns: Option<Vec<String>>,
+    pub action: Option<AutoModAction>,
+    pub timeout_duration: Option<i64>,
+    pub issue_strike: Option<bool>,
+    pub enabled: Option<bool>,
+}
+
+/// A request to create a new webhook
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateWebhook {
+    pub name: String,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+    pub enabled: Option<bool>,
+}
+
+/// Update payload for webhooks
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateWebhook {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub events: Option<Vec<WebhookEvent>>,
+    pub enabled: Option<bool>,
+    /// Whether to generate a new signing secret
+    pub reset_secret: Option<bool>,
+}
+
+/// A request to register a device for push notifications
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreatePushDevice {
+    pub platform: PushPlatform,
+    /// The device token for APNs and FCM, or the serialized `PushSubscription` for Web Push
+    pub token: String,
+}
+
+/// A request to subscribe a channel to a feed
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateFeed {
+    pub url: String,
+    /// How often the feed is polled, in seconds
+    pub poll_interval: Option<u32>,
+    pub enabled: Option<bool>,
+}
+
+/// A request to follow an announcement channel
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateChannelFollow {
+    /// The channel to crosspost messages to
+    pub target_channel_id: Snowflake<Channel>,
+}
+
+/// A request to create a guest link for a guild
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateGuestLink {
+    /// The channels guests can access
+    pub channel_ids: Vec<Snowflake<Channel>>,
+    /// How long guest membership lasts after joining, in hours
+    pub duration: u32,
+}
+
+/// Update payload for channel feeds
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateFeed {
+    pub url: Option<String>,
+    /// How often the feed is polled, in seconds
+    pub poll_interval: Option<u32>,
+    pub enabled: Option<bool>,
+}
+
+/// A request to report a message
+#[derive(Deserialize, Debug, Clone)]
+pub struct CreateReport {
+    pub reason: ReportReason,
+    /// Additional context for the guild owner reviewing the report
+    pub details: Option<String>,
+}
+
+/// A request to review a report
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateReport {
+    pub state: ReportState,
+}
+
+/// A request to set or clear a member's timeout
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateMemberTimeout {
+    /// UNIX timestamp of when the timeout expires, or `None` to clear it
+    pub until: Option<i64>,
+}
+
+/// A request to issue a strike to a member
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct CreateStrike {
+    pub reason: Option<String>,
+    /// How long the strike stays active in seconds, defaults to the strike duration of the guild's policy
+    pub duration: Option<i64>,
+}
+
+/// A request to complete the onboarding of a guild
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct CompleteOnboarding {
+    /// The options chosen for each question, unanswered questions may be left out
+    #[serde(default)]
+    pub responses: Vec<OnboardingResponse>,
+}
+
+/// A request to set or clear the message retention period of a channel
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateChannelRetention {
+    /// The amount of days after which messages are deleted, or `None` to keep messages forever
+    pub days: Option<i32>,
+}
+
+/// A request to update the settings of a channel. Fields that are not present are left unchanged.
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateChannel {
+    /// Whether only the guild owner may send messages in the channel
+    pub announcement: Option<bool>,
+    /// The version of the channel the update is based on. If set, the update fails if the channel was updated since.
+    pub version: Option<i32>,
+}
+
+/// A request to set the sticky message of a channel
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateStickyMessage {
+    pub message_id: Snowflake<Message>,
+}
+
+/// A request to change the log verbosity of the server for a while
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateLogLevel {
+    #[serde(flatten)]
+    pub levels: LogLevels,
+    /// How long the verbosity is kept for, in seconds. Defaults to `LOG_LEVEL_REVERT_AFTER`.
+    pub duration: Option<u64>,
+}
+
+/// A request to override a setting at runtime
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateSetting {
+    /// The new value of the setting.
+    pub value: serde_json::Value,
+}
+
+/// A single operation of a bulk request to a guild
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "op", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum BulkOperation {
+    /// Create a new channel in the guild
+    CreateChannel { data: CreateChannel },
+    /// Update the settings of a channel of the guild
+    UpdateChannel {
+        channel_id: Snowflake<Channel>,
+        data: UpdateChannel,
+    },
+}
+
+/// A request to perform multiple operations on a guild at once
+#[derive(Deserialize, Debug, Clone)]
+pub struct BulkGuildOperations {
+    pub operations: Vec<BulkOperation>,
+}
+
+/// A request to enable or disable raid mode of a guild
+#[derive(Deserialize, Debug, Clone)]
+pub struct UpdateRaidMode {
+    pub enabled: bool,
+    /// How long raid mode stays enabled in seconds, defaults to the configured raid mode duration
+    pub duration: Option<i64>,
+}
+
+/// A request to invite users to a guild by their usernames
+#[derive(Deserialize, Debug, Clone)]
+pub struct ImportMembers {
+    pub usernames: Vec<String>,
 }
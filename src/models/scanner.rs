@@ -0,0 +1,125 @@
+use std::{io, time::Duration};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use super::state::Config;
+
+/// The largest chunk of a file sent to clamd at once.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// How long a single scan may take before it is considered failed.
+const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The verdict of scanning a file for malware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    /// No malware was found.
+    Clean,
+    /// Malware was found, with the name of the signature that matched.
+    Infected(String),
+}
+
+/// Scans attachments for malware using a clamd daemon, over its `INSTREAM` protocol.
+///
+/// If no clamd address is configured, scanning is disabled and attachments are considered clean on upload.
+#[derive(Debug, Clone)]
+pub struct AttachmentScanner {
+    addr: Option<String>,
+}
+
+impl AttachmentScanner {
+    /// Create a new scanner from the given config.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            addr: config.clamav_addr().map(String::from),
+        }
+    }
+
+    /// Whether attachments are scanned before they are served.
+    pub const fn is_enabled(&self) -> bool {
+        self.addr.is_some()
+    }
+
+    /// Scan the contents of a file.
+    ///
+    /// ## Arguments
+    ///
+    /// * `content` - The contents of the file.
+    ///
+    /// ## Errors
+    ///
+    /// * [`io::Error`] - If scanning is disabled, clamd could not be reached, it timed out or reported an error.
+    pub async fn scan(&self, content: &[u8]) -> io::Result<ScanVerdict> {
+        let Some(addr) = &self.addr else {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Attachment scanning is disabled",
+            ));
+        };
+
+        tokio::time::timeout(SCAN_TIMEOUT, Self::scan_with(addr, content))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "clamd did not respond in time"))?
+    }
+
+    async fn scan_with(addr: &str, content: &[u8]) -> io::Result<ScanVerdict> {
+        let mut stream = TcpStream::connect(addr).await?;
+
+        stream.write_all(b"zINSTREAM\0").await?;
+        for chunk in content.chunks(CHUNK_SIZE) {
+            let len = u32::try_from(chunk.len()).expect("chunk size should fit into a u32");
+            stream.write_all(&len.to_be_bytes()).await?;
+            stream.write_all(chunk).await?;
+        }
+        // A zero-length chunk ends the stream
+        stream.write_all(&0_u32.to_be_bytes()).await?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).await?;
+        parse_reply(&String::from_utf8_lossy(&reply))
+    }
+}
+
+/// Parse the reply of clamd to an `INSTREAM` command.
+///
+/// ## Errors
+///
+/// * [`io::Error`] - If clamd reported an error or the reply is malformed.
+fn parse_reply(reply: &str) -> io::Result<ScanVerdict> {
+    let reply = reply.trim_end_matches(['\0', '\n']);
+    let Some(result) = reply.strip_prefix("stream: ") else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Unexpected clamd reply: {reply}"),
+        ));
+    };
+
+    if result == "OK" {
+        Ok(ScanVerdict::Clean)
+    } else if let Some(signature) = result.strip_suffix(" FOUND") {
+        Ok(ScanVerdict::Infected(signature.to_string()))
+    } else {
+        Err(io::Error::other(format!("clamd failed to scan the file: {result}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_reply, ScanVerdict};
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(
+            parse_reply("stream: OK\0").expect("reply should be valid"),
+            ScanVerdict::Clean
+        );
+        assert_eq!(
+            parse_reply("stream: Win.Test.EICAR_HDB-1 FOUND\0").expect("reply should be valid"),
+            ScanVerdict::Infected("Win.Test.EICAR_HDB-1".into())
+        );
+        assert!(parse_reply("INSTREAM size limit exceeded. ERROR\0").is_err());
+        assert!(parse_reply("stream: Can't allocate memory ERROR\0").is_err());
+    }
+}
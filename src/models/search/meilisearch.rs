@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use reqwest::{header, Client, Method, Response};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::OnceCell;
+
+use crate::models::{
+    channel::Channel, errors::AppError, message::Message, snowflake::Snowflake, state::ApplicationState,
+};
+
+use super::{SearchBackend, SearchDocument};
+
+/// The Meilisearch index messages are stored in.
+const INDEX: &str = "messages";
+
+/// A search result returned by Meilisearch.
+#[derive(Deserialize)]
+struct Hit {
+    id: Snowflake<Message>,
+}
+
+/// The body of a Meilisearch search response.
+#[derive(Deserialize)]
+struct SearchResponse {
+    hits: Vec<Hit>,
+}
+
+/// Stores the search index in an external Meilisearch instance,
+/// so search can be scaled independently of the database.
+///
+/// Meilisearch stores numbers as floating point values, which cannot represent snowflakes exactly.
+/// Documents are therefore identified by their ID as a string, and ordered by their creation time.
+#[derive(Debug)]
+pub struct MeilisearchBackend {
+    client: Client,
+    url: String,
+    api_key: Option<Secret<String>>,
+    /// Set once the index settings were applied.
+    configured: OnceCell<()>,
+}
+
+impl MeilisearchBackend {
+    /// Create a new Meilisearch backend. Nothing is sent to Meilisearch until the first message is indexed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - The base URL of the Meilisearch instance.
+    /// * `api_key` - The API key to authenticate with, if any.
+    pub fn new(url: &str, api_key: Option<Secret<String>>) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("chat-search/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build search HTTP client");
+
+        Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            configured: OnceCell::new(),
+        }
+    }
+
+    /// Send an authenticated request with a JSON body to the Meilisearch API.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Http`] - If the request fails or Meilisearch returns an error status.
+    async fn send(&self, method: Method, path: &str, body: &Value) -> Result<Response, AppError> {
+        let mut request = self
+            .client
+            .request(method, format!("{}{path}", self.url))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key.expose_secret());
+        }
+
+        Ok(request.send().await?.error_for_status()?)
+    }
+
+    /// Make the fields used by searches filterable and sortable. Only done once per process.
+    /// Meilisearch applies settings asynchronously, and creates the index if it does not exist.
+    async fn ensure_configured(&self) -> Result<(), AppError> {
+        self.configured
+            .get_or_try_init(|| async {
+                let settings = json!({
+                    "filterableAttributes": ["channel_id", "created_at"],
+                    "sortableAttributes": ["created_at"],
+                });
+                self.send(Method::PATCH, &format!("/indexes/{INDEX}/settings"), &settings)
+                    .await
+                    .map(drop)
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl SearchBackend for MeilisearchBackend {
+    fn name(&self) -> &'static str {
+        "meilisearch"
+    }
+
+    async fn index(&self, _app: &ApplicationState, documents: &[SearchDocument]) -> Result<(), AppError> {
+        self.ensure_configured().await?;
+
+        let documents: Value = documents
+            .iter()
+            .map(|document| {
+                json!({
+                    "id": document.id.to_string(),
+                    "channel_id": document.channel_id.to_string(),
+                    "created_at": document.id.timestamp(),
+                    "content": document.content,
+                })
+            })
+            .collect();
+
+        self.send(
+            Method::POST,
+            &format!("/indexes/{INDEX}/documents?primaryKey=id"),
+            &documents,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, _app: &ApplicationState, messages: &[Snowflake<Message>]) -> Result<(), AppError> {
+        let ids: Value = messages.iter().map(ToString::to_string).collect();
+
+        self.send(Method::POST, &format!("/indexes/{INDEX}/documents/delete-batch"), &ids)
+            .await?;
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        _app: &ApplicationState,
+        channel: Snowflake<Channel>,
+        query: &str,
+        limit: u32,
+        before: Option<Snowflake<Message>>,
+    ) -> Result<Vec<Snowflake<Message>>, AppError> {
+        self.ensure_configured().await?;
+
+        let mut filter = vec![format!("channel_id = \"{channel}\"")];
+        if let Some(before) = before {
+            // Messages sent in the same millisecond as `before` are skipped, as they cannot be told apart
+            filter.push(format!("created_at < {}", before.timestamp()));
+        }
+
+        let body = json!({
+            "q": query,
+            "filter": filter,
+            "sort": ["created_at:desc"],
+            "limit": limit,
+            "attributesToRetrieve": ["id"],
+        });
+        let response = self
+            .send(Method::POST, &format!("/indexes/{INDEX}/search"), &body)
+            .await?;
+        let response: SearchResponse = serde_json::from_str(&response.text().await?)?;
+
+        Ok(response.hits.into_iter().map(|hit| hit.id).collect())
+    }
+}
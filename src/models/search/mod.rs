@@ -0,0 +1,389 @@
+mod meilisearch;
+mod postgres;
+
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    sync::{Arc, Weak},
+};
+
+use sqlx::{Executor, Postgres};
+use tokio::sync::Notify;
+
+pub use self::meilisearch::MeilisearchBackend;
+pub use self::postgres::PostgresBackend;
+
+use super::{
+    channel::Channel,
+    errors::{AppError, BuildError, ErrorCode},
+    message::Message,
+    snowflake::Snowflake,
+    state::{ApplicationState, Config},
+};
+
+/// The longest search query accepted, in characters.
+pub const MAX_QUERY_LENGTH: usize = 200;
+/// The maximum amount of queued messages indexed at once.
+const QUEUE_BATCH_SIZE: i64 = 100;
+/// The maximum amount of historical messages indexed at once by the backfill.
+const BACKFILL_BATCH_SIZE: i64 = 500;
+
+/// A message as stored in a search backend.
+#[derive(Debug, Clone)]
+pub struct SearchDocument {
+    pub id: Snowflake<Message>,
+    pub channel_id: Snowflake<Channel>,
+    pub content: Option<String>,
+}
+
+/// Stores message contents and searches them.
+///
+/// Backends only need to return the IDs of matching messages,
+/// the messages themselves are always loaded from the database.
+#[async_trait::async_trait]
+pub trait SearchBackend: Debug + Send + Sync {
+    /// The name of the backend. If the configured backend changes, all messages are indexed again.
+    fn name(&self) -> &'static str;
+
+    /// Add messages to the index, replacing them if they were indexed before.
+    ///
+    /// ## Arguments
+    ///
+    /// * `app` - The application state.
+    /// * `documents` - The messages to index.
+    async fn index(&self, app: &ApplicationState, documents: &[SearchDocument]) -> Result<(), AppError>;
+
+    /// Remove messages from the index. Messages that are not indexed are ignored.
+    ///
+    /// ## Arguments
+    ///
+    /// * `app` - The application state.
+    /// * `messages` - The IDs of the messages to remove.
+    async fn remove(&self, app: &ApplicationState, messages: &[Snowflake<Message>]) -> Result<(), AppError>;
+
+    /// Search the messages of a channel, newest messages first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `app` - The application state.
+    /// * `channel` - The channel to search in.
+    /// * `query` - The search query.
+    /// * `limit` - The maximum number of messages to return.
+    /// * `before` - Only return messages before this ID.
+    async fn search(
+        &self,
+        app: &ApplicationState,
+        channel: Snowflake<Channel>,
+        query: &str,
+        limit: u32,
+        before: Option<Snowflake<Message>>,
+    ) -> Result<Vec<Snowflake<Message>>, AppError>;
+}
+
+/// How far the backfill of historical messages has progressed.
+#[derive(Debug, Clone, Copy)]
+pub struct BackfillProgress {
+    /// The last message that was indexed. All messages with a lower ID are indexed.
+    pub cursor: Snowflake<Message>,
+    /// The amount of messages indexed by the backfill so far.
+    pub indexed: i64,
+    /// Whether all historical messages are indexed.
+    pub done: bool,
+}
+
+/// A full-text search index of message contents, stored in a [`SearchBackend`].
+///
+/// Changes to messages are queued in the same transaction as the change itself,
+/// and sent to the backend by a background task once the transaction is committed.
+/// Messages that existed before the index are indexed by a backfill, in batches.
+#[derive(Debug, Clone)]
+pub struct SearchIndex {
+    backend: Arc<dyn SearchBackend>,
+    notify: Arc<Notify>,
+    app: Weak<ApplicationState>,
+}
+
+impl SearchIndex {
+    /// Create a new search index, stored in Meilisearch if it is configured, and in Postgres otherwise.
+    pub fn new(config: &Config) -> Self {
+        let backend: Arc<dyn SearchBackend> = match config.meilisearch_url() {
+            Some(url) => Arc::new(MeilisearchBackend::new(url, config.meilisearch_api_key().cloned())),
+            None => Arc::new(PostgresBackend),
+        };
+
+        Self {
+            backend,
+            notify: Arc::new(Notify::new()),
+            app: Weak::new(),
+        }
+    }
+
+    pub fn bind_to(&mut self, app: Weak<ApplicationState>) {
+        self.app = app;
+    }
+
+    pub fn app(&self) -> Arc<ApplicationState> {
+        self.app.upgrade().expect("Application state has been dropped.")
+    }
+
+    /// Queue messages to be indexed as part of a transaction.
+    /// Call [`SearchIndex::notify`] after the transaction is committed to index them without delay.
+    ///
+    /// ## Arguments
+    ///
+    /// * `conn` - The transaction to queue the messages in.
+    /// * `messages` - The messages that were created, edited or deleted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn enqueue(
+        conn: impl Executor<'_, Database = Postgres>,
+        messages: &[Snowflake<Message>],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO search_index_queue (message_id) SELECT UNNEST($1::BIGINT[])",
+            messages as &[Snowflake<Message>]
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
+    }
+
+    /// Wake up the indexer after messages were queued.
+    pub fn notify(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Wait until [`SearchIndex::notify`] is called.
+    pub async fn notified(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Index all queued messages. Messages that no longer exist are removed from the index.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of queued changes processed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Http`] - If the request to an external backend fails.
+    pub async fn process_queue(&self) -> Result<usize, AppError> {
+        let app = self.app();
+        let mut processed = 0;
+
+        loop {
+            let mut tx = app.db.pool().begin().await?;
+
+            // Other processes may index the queue concurrently, skip the changes they claimed.
+            // The changes are only removed from the queue if the backend accepted them.
+            let ids = sqlx::query_scalar!(
+                "DELETE FROM search_index_queue
+                WHERE id IN (SELECT id FROM search_index_queue ORDER BY id LIMIT $1 FOR UPDATE SKIP LOCKED)
+                RETURNING message_id",
+                QUEUE_BATCH_SIZE
+            )
+            .fetch_all(app.db.instrument(&mut *tx))
+            .await?;
+
+            let count = ids.len();
+            let documents = Self::fetch_documents(&app, &ids).await?;
+
+            let existing: HashSet<Snowflake<Message>> = documents.iter().map(|document| document.id).collect();
+            let removed: Vec<Snowflake<Message>> = ids
+                .into_iter()
+                .map(Snowflake::new)
+                .filter(|id| !existing.contains(id))
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .collect();
+
+            if !documents.is_empty() {
+                self.backend.index(&app, &documents).await?;
+            }
+            if !removed.is_empty() {
+                self.backend.remove(&app, &removed).await?;
+            }
+            tx.commit().await?;
+
+            processed += count;
+
+            if count < usize::try_from(QUEUE_BATCH_SIZE).expect("Batch size should fit into usize") {
+                return Ok(processed);
+            }
+        }
+    }
+
+    /// Start the backfill over, so that all messages are indexed again by [`SearchIndex::backfill`].
+    /// Messages stay searchable while they are indexed again.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn restart_backfill(&self) -> Result<(), sqlx::Error> {
+        let app = self.app();
+        sqlx::query!(
+            "UPDATE search_backfill SET cursor = 0, indexed = 0, completed_at = NULL, backend = $1",
+            self.backend.name()
+        )
+        .execute(app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Index the next batch of historical messages, if the backfill is not done yet.
+    /// If the backend changed since the backfill ran, it starts over.
+    ///
+    /// ## Returns
+    ///
+    /// The progress of the backfill after the batch, or `None` if the backfill was already done.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Http`] - If the request to an external backend fails.
+    pub async fn backfill(&self) -> Result<Option<BackfillProgress>, AppError> {
+        let app = self.app();
+        let mut tx = app.db.pool().begin().await?;
+
+        // Locking the progress ensures that processes sharing the database do not index the same batch
+        let state = sqlx::query!("SELECT cursor, indexed, completed_at, backend FROM search_backfill FOR UPDATE")
+            .fetch_one(app.db.instrument(&mut *tx))
+            .await?;
+
+        let (cursor, indexed) = if state.backend == self.backend.name() {
+            if state.completed_at.is_some() {
+                return Ok(None);
+            }
+            (state.cursor, state.indexed)
+        } else {
+            tracing::info!(
+                from = state.backend,
+                to = self.backend.name(),
+                "Search backend changed, indexing all messages again"
+            );
+            (0, 0)
+        };
+
+        let documents = sqlx::query!(
+            "SELECT id, channel_id, content FROM messages WHERE id > $1 ORDER BY id ASC LIMIT $2",
+            cursor,
+            BACKFILL_BATCH_SIZE
+        )
+        .fetch_all(app.db.instrument(&mut *tx))
+        .await?
+        .into_iter()
+        .map(|r| SearchDocument {
+            id: Snowflake::new(r.id),
+            channel_id: Snowflake::new(r.channel_id),
+            content: r.content,
+        })
+        .collect::<Vec<_>>();
+
+        if !documents.is_empty() {
+            self.backend.index(&app, &documents).await?;
+        }
+
+        let progress = BackfillProgress {
+            cursor: documents
+                .last()
+                .map_or_else(|| Snowflake::new(cursor), |document| document.id),
+            indexed: indexed + i64::try_from(documents.len()).expect("Batch size should fit into i64"),
+            done: documents.len() < usize::try_from(BACKFILL_BATCH_SIZE).expect("Batch size should fit into usize"),
+        };
+
+        sqlx::query!(
+            "UPDATE search_backfill SET cursor = $1, indexed = $2, completed_at = $3, backend = $4",
+            progress.cursor as Snowflake<Message>,
+            progress.indexed,
+            progress.done.then(|| app.clock.timestamp()),
+            self.backend.name(),
+        )
+        .execute(app.db.instrument(&mut *tx))
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(progress))
+    }
+
+    /// Search the messages of a channel, newest messages first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel to search in.
+    /// * `query` - The search query.
+    /// * `limit` - The maximum number of messages to return. Defaults to 25, capped at 100.
+    /// * `before` - Only return messages before this ID.
+    ///
+    /// ## Returns
+    ///
+    /// The IDs of the matching messages.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Http`] - If the request to an external backend fails.
+    pub async fn search(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        query: &str,
+        limit: Option<u32>,
+        before: Option<Snowflake<Message>>,
+    ) -> Result<Vec<Snowflake<Message>>, AppError> {
+        let limit = limit.unwrap_or(25).min(100);
+
+        self.backend
+            .search(&self.app(), channel.into(), query, limit, before)
+            .await
+    }
+
+    /// Fetch the current state of the given messages. Messages that do not exist are skipped.
+    async fn fetch_documents(app: &ApplicationState, ids: &[i64]) -> Result<Vec<SearchDocument>, sqlx::Error> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let records = sqlx::query!("SELECT id, channel_id, content FROM messages WHERE id = ANY($1)", ids)
+            .fetch_all(app.db.executor())
+            .await?;
+
+        Ok(records
+            .into_iter()
+            .map(|r| SearchDocument {
+                id: Snowflake::new(r.id),
+                channel_id: Snowflake::new(r.channel_id),
+                content: r.content,
+            })
+            .collect())
+    }
+}
+
+/// Ensure a search query can be run.
+///
+/// ## Errors
+///
+/// * [`BuildError::InvalidField`] - If the query is blank or longer than [`MAX_QUERY_LENGTH`].
+pub fn validate_query(query: &str) -> Result<(), BuildError> {
+    if query.trim().is_empty() || query.chars().count() > MAX_QUERY_LENGTH {
+        return Err(BuildError::InvalidField {
+            field: "query",
+            code: ErrorCode::InvalidLength,
+            message: format!("Search query must be between 1 and {MAX_QUERY_LENGTH} characters."),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_query, MAX_QUERY_LENGTH};
+
+    #[test]
+    fn test_validate_query() {
+        assert!(validate_query("  ").is_err());
+        assert!(validate_query(&"a".repeat(MAX_QUERY_LENGTH + 1)).is_err());
+        assert!(validate_query("\"exact phrase\" -excluded").is_ok());
+    }
+}
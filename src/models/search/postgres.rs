@@ -0,0 +1,75 @@
+use crate::models::{
+    channel::Channel, errors::AppError, message::Message, snowflake::Snowflake, state::ApplicationState,
+};
+
+use super::{SearchBackend, SearchDocument};
+
+/// Stores the search index in the application database, using Postgres full-text search.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PostgresBackend;
+
+#[async_trait::async_trait]
+impl SearchBackend for PostgresBackend {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    async fn index(&self, app: &ApplicationState, documents: &[SearchDocument]) -> Result<(), AppError> {
+        let ids: Vec<i64> = documents.iter().map(|document| document.id.into()).collect();
+        let channel_ids: Vec<i64> = documents.iter().map(|document| document.channel_id.into()).collect();
+        let contents: Vec<String> = documents
+            .iter()
+            .map(|document| document.content.clone().unwrap_or_default())
+            .collect();
+
+        sqlx::query!(
+            "INSERT INTO message_search (message_id, channel_id, document)
+            SELECT d.id, d.channel_id, to_tsvector('simple', d.content)
+            FROM UNNEST($1::BIGINT[], $2::BIGINT[], $3::TEXT[]) AS d(id, channel_id, content)
+            -- The message may have been deleted since it was fetched
+            WHERE EXISTS (SELECT 1 FROM messages WHERE id = d.id)
+            ON CONFLICT (message_id) DO UPDATE SET channel_id = EXCLUDED.channel_id, document = EXCLUDED.document",
+            &ids,
+            &channel_ids,
+            &contents,
+        )
+        .execute(app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, app: &ApplicationState, messages: &[Snowflake<Message>]) -> Result<(), AppError> {
+        // Entries are usually removed along with their message already
+        sqlx::query!(
+            "DELETE FROM message_search WHERE message_id = ANY($1)",
+            messages as &[Snowflake<Message>]
+        )
+        .execute(app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    async fn search(
+        &self,
+        app: &ApplicationState,
+        channel: Snowflake<Channel>,
+        query: &str,
+        limit: u32,
+        before: Option<Snowflake<Message>>,
+    ) -> Result<Vec<Snowflake<Message>>, AppError> {
+        let ids = sqlx::query_scalar!(
+            "SELECT message_id FROM message_search
+            WHERE channel_id = $1 AND document @@ websearch_to_tsquery('simple', $2)
+            AND ($3::BIGINT IS NULL OR message_id < $3)
+            ORDER BY message_id DESC LIMIT $4",
+            channel as Snowflake<Channel>,
+            query,
+            before.map(i64::from),
+            i64::from(limit),
+        )
+        .fetch_all(app.db.executor())
+        .await?;
+
+        Ok(ids.into_iter().map(Snowflake::new).collect())
+    }
+}
@@ -0,0 +1,244 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    errors::{BuildError, ErrorCode},
+    shard::ShardInfo,
+    snowflake::Snowflake,
+    state::Config,
+    user::User,
+};
+
+/// The maximum length of each client property, in characters.
+pub const MAX_PROPERTY_LENGTH: usize = 64;
+
+/// The kind of device a client runs on, as reported by the client.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum DeviceType {
+    /// A desktop application.
+    Desktop = 1,
+    /// A browser.
+    Web = 2,
+    /// A mobile application.
+    Mobile = 3,
+    /// An automated client.
+    Bot = 4,
+    /// The client did not report a device type, or reported one that is not known.
+    #[default]
+    #[serde(other)]
+    Unknown = 0,
+}
+
+impl DeviceType {
+    /// The name of the device type, used to label metrics.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::Desktop => "desktop",
+            Self::Web => "web",
+            Self::Mobile => "mobile",
+            Self::Bot => "bot",
+        }
+    }
+}
+
+impl From<i16> for DeviceType {
+    fn from(device: i16) -> Self {
+        match device {
+            1 => Self::Desktop,
+            2 => Self::Web,
+            3 => Self::Mobile,
+            4 => Self::Bot,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Information about the client of a gateway session, sent by the client in `IDENTIFY`.
+/// All properties are optional and are not verified by the server.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ClientProperties {
+    /// The name of the client.
+    name: Option<String>,
+    /// The version of the client.
+    version: Option<String>,
+    /// The operating system the client runs on.
+    os: Option<String>,
+    /// The kind of device the client runs on.
+    device: DeviceType,
+}
+
+impl ClientProperties {
+    /// Validate the properties, discarding the ones that are empty.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If a property is longer than [`MAX_PROPERTY_LENGTH`] characters.
+    pub fn validate(self) -> Result<Self, BuildError> {
+        Ok(Self {
+            name: validate_property("name", self.name)?,
+            version: validate_property("version", self.version)?,
+            os: validate_property("os", self.os)?,
+            device: self.device,
+        })
+    }
+
+    /// The name of the client, if it reported one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The version of the client, if it reported one.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// The operating system the client runs on, if it reported one.
+    pub fn os(&self) -> Option<&str> {
+        self.os.as_deref()
+    }
+
+    /// The kind of device the client runs on.
+    pub const fn device(&self) -> DeviceType {
+        self.device
+    }
+}
+
+/// Trim a client property, discarding it if it is empty.
+fn validate_property(field: &'static str, value: Option<String>) -> Result<Option<String>, BuildError> {
+    let Some(value) = value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+    else {
+        return Ok(None);
+    };
+    if value.chars().count() > MAX_PROPERTY_LENGTH {
+        return Err(BuildError::InvalidField {
+            field,
+            code: ErrorCode::TooLong,
+            message: format!("Client property must be at most {MAX_PROPERTY_LENGTH} characters."),
+        });
+    }
+    Ok(Some(value))
+}
+
+/// Represents a gateway session record stored in the database.
+pub struct GatewaySessionRecord {
+    pub id: Snowflake<GatewaySession>,
+    pub user_id: Snowflake<User>,
+    pub shard_id: i32,
+    pub shard_count: i32,
+    pub client_name: Option<String>,
+    pub client_version: Option<String>,
+    pub client_os: Option<String>,
+    pub client_device: i16,
+}
+
+/// A connection of a user to the gateway.
+///
+/// A user has at most one session per shard, a new session replaces the previous one.
+#[derive(Serialize, Debug, Clone)]
+pub struct GatewaySession {
+    /// The ID of the session. This also encodes when the session was started.
+    id: Snowflake<Self>,
+    /// The user the session belongs to.
+    #[serde(skip)]
+    user_id: Snowflake<User>,
+    /// The shard the session is connected to.
+    shard: ShardInfo,
+    /// Information about the client, as reported by the client.
+    properties: ClientProperties,
+}
+
+impl GatewaySession {
+    /// Create a new session. Assigns a new snowflake to the session.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration.
+    /// * `user` - The user the session belongs to.
+    /// * `shard` - The shard the session is connected to.
+    /// * `properties` - Information about the client, already validated.
+    pub fn new(
+        config: &Config,
+        user: impl Into<Snowflake<User>>,
+        shard: ShardInfo,
+        properties: ClientProperties,
+    ) -> Self {
+        Self {
+            id: Snowflake::gen_new(config),
+            user_id: user.into(),
+            shard,
+            properties,
+        }
+    }
+
+    /// Build a session directly from a database record.
+    pub fn from_record(record: GatewaySessionRecord) -> Self {
+        let shard = u32::try_from(record.shard_id)
+            .ok()
+            .zip(u32::try_from(record.shard_count).ok())
+            .and_then(|(id, count)| ShardInfo::new(id, count).ok())
+            .unwrap_or_default();
+        Self {
+            id: record.id,
+            user_id: record.user_id,
+            shard,
+            properties: ClientProperties {
+                name: record.client_name,
+                version: record.client_version,
+                os: record.client_os,
+                device: DeviceType::from(record.client_device),
+            },
+        }
+    }
+
+    /// The ID of the session.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The user the session belongs to.
+    pub const fn user_id(&self) -> Snowflake<User> {
+        self.user_id
+    }
+
+    /// The shard the session is connected to.
+    pub const fn shard(&self) -> ShardInfo {
+        self.shard
+    }
+
+    /// Information about the client, as reported by the client.
+    pub const fn properties(&self) -> &ClientProperties {
+        &self.properties
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientProperties, DeviceType, MAX_PROPERTY_LENGTH};
+
+    #[test]
+    fn test_deserialize() {
+        let properties: ClientProperties =
+            serde_json::from_str(r#"{"name": " chat-web ", "version": "", "device": "WEB"}"#)
+                .expect("Properties should deserialize");
+        let properties = properties.validate().expect("Properties should be valid");
+        assert_eq!(properties.name(), Some("chat-web"));
+        assert_eq!(properties.version(), None);
+        assert_eq!(properties.os(), None);
+        assert_eq!(properties.device(), DeviceType::Web);
+
+        // Clients may report device types this server does not know yet
+        let properties: ClientProperties =
+            serde_json::from_str(r#"{"device": "FRIDGE"}"#).expect("Properties should deserialize");
+        assert_eq!(properties.device(), DeviceType::Unknown);
+
+        let properties: ClientProperties =
+            serde_json::from_str(&format!(r#"{{"os": "{}"}}"#, "a".repeat(MAX_PROPERTY_LENGTH + 1)))
+                .expect("Properties should deserialize");
+        assert!(properties.validate().is_err());
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use super::{
+    limits::{Limit, Limits},
+    state::Config,
+};
+
+/// A configuration value that operators may override at runtime, without a redeploy.
+/// Values are named after the environment variables they override, in lowercase.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingKey {
+    MaxGuildsPerUser,
+    MaxChannelsPerGuild,
+    MaxMembersPerGuild,
+    MaxAttachmentSize,
+    MediaProxyEnabled,
+    MediaRehostEnabled,
+    RateLimitsEnabled,
+    UploadRateLimit,
+}
+
+impl SettingKey {
+    /// The name of the setting, as stored in the `settings` table.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::MaxGuildsPerUser => "max_guilds_per_user",
+            Self::MaxChannelsPerGuild => "max_channels_per_guild",
+            Self::MaxMembersPerGuild => "max_members_per_guild",
+            Self::MaxAttachmentSize => "max_attachment_size",
+            Self::MediaProxyEnabled => "media_proxy_enabled",
+            Self::MediaRehostEnabled => "media_rehost_enabled",
+            Self::RateLimitsEnabled => "rate_limits_enabled",
+            Self::UploadRateLimit => "upload_rate_limit",
+        }
+    }
+
+    /// Look up a setting by its name, as stored in the `settings` table.
+    pub fn from_name(name: &str) -> Option<Self> {
+        serde_json::from_value(Value::String(name.to_string())).ok()
+    }
+
+    /// The limit the setting overrides, if it is one of the [`Limits`].
+    const fn limit(self) -> Option<Limit> {
+        match self {
+            Self::MaxGuildsPerUser => Some(Limit::MaxGuildsPerUser),
+            Self::MaxChannelsPerGuild => Some(Limit::MaxChannelsPerGuild),
+            Self::MaxMembersPerGuild => Some(Limit::MaxMembersPerGuild),
+            Self::MaxAttachmentSize => Some(Limit::MaxAttachmentSize),
+            _ => None,
+        }
+    }
+}
+
+/// A value that cannot be used for a setting.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("Invalid value for setting '{}': {reason}", key.name())]
+pub struct InvalidSetting {
+    /// The setting the value was given for.
+    pub key: SettingKey,
+    /// Why the value cannot be used.
+    pub reason: String,
+}
+
+/// The effective values of all settings that may be overridden at runtime.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SettingValues {
+    #[serde(flatten)]
+    limits: Limits,
+    media_proxy_enabled: bool,
+    media_rehost_enabled: bool,
+    rate_limits_enabled: bool,
+    upload_rate_limit: u64,
+}
+
+impl SettingValues {
+    /// The values configured through environment variables, before any overrides.
+    pub const fn from_config(config: &Config) -> Self {
+        Self {
+            limits: *config.limits(),
+            media_proxy_enabled: config.media_proxy_enabled(),
+            media_rehost_enabled: config.media_rehost_enabled(),
+            rate_limits_enabled: config.rate_limits_enabled(),
+            upload_rate_limit: config.upload_rate_limit(),
+        }
+    }
+
+    /// Override a single value.
+    ///
+    /// ## Arguments
+    ///
+    /// * `key` - The setting to override.
+    /// * `value` - The new value, a positive integer for limits, a boolean for toggles,
+    ///   and a non-negative integer for the upload rate limit.
+    ///
+    /// ## Errors
+    ///
+    /// * [`InvalidSetting`] - If the value has the wrong type or is out of range. Nothing is changed.
+    pub fn set(&mut self, key: SettingKey, value: &Value) -> Result<(), InvalidSetting> {
+        if let Some(limit) = key.limit() {
+            let max: usize = parse(key, value)?;
+            if max == 0 {
+                return Err(InvalidSetting {
+                    key,
+                    reason: "limits must be at least 1".into(),
+                });
+            }
+            self.limits.set(limit, max);
+            return Ok(());
+        }
+
+        match key {
+            SettingKey::MediaProxyEnabled => self.media_proxy_enabled = parse(key, value)?,
+            SettingKey::MediaRehostEnabled => self.media_rehost_enabled = parse(key, value)?,
+            SettingKey::RateLimitsEnabled => self.rate_limits_enabled = parse(key, value)?,
+            SettingKey::UploadRateLimit => self.upload_rate_limit = parse(key, value)?,
+            _ => unreachable!("Limits are handled above"),
+        }
+        Ok(())
+    }
+
+    /// The limits enforced when creating resources.
+    pub const fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Whether the media proxy is enabled.
+    pub const fn media_proxy_enabled(&self) -> bool {
+        self.media_proxy_enabled
+    }
+
+    /// Whether direct links to external media in new messages are re-hosted as attachments.
+    pub const fn media_rehost_enabled(&self) -> bool {
+        self.media_rehost_enabled
+    }
+
+    /// Whether requests to the REST API are rate limited.
+    pub const fn rate_limits_enabled(&self) -> bool {
+        self.rate_limits_enabled
+    }
+
+    /// The maximum upload rate per user in bytes per second, `0` if uploads are not throttled.
+    pub const fn upload_rate_limit(&self) -> u64 {
+        self.upload_rate_limit
+    }
+}
+
+/// Parse the value of a setting into the type it is stored as.
+fn parse<T: DeserializeOwned>(key: SettingKey, value: &Value) -> Result<T, InvalidSetting> {
+    T::deserialize(value).map_err(|e| InvalidSetting {
+        key,
+        reason: e.to_string(),
+    })
+}
+
+/// The effective values of all settings, and the overrides they differ from the configuration by.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SettingsState {
+    #[serde(flatten)]
+    pub values: SettingValues,
+    /// The values stored in the `settings` table, by the name of the setting they override.
+    pub overrides: BTreeMap<SettingKey, Value>,
+}
+
+/// A cached view of the configuration values overridden in the `settings` table.
+/// The environment variables remain the source of every value that is not overridden.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    state: Arc<RwLock<SettingsState>>,
+}
+
+impl Settings {
+    /// Create a new view without any overrides, until the first [`Settings::update`].
+    pub fn new(config: &Config) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(SettingsState {
+                values: SettingValues::from_config(config),
+                overrides: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// The effective values of all settings, and the overrides they differ from the configuration by.
+    pub fn state(&self) -> SettingsState {
+        self.state.read().expect("Settings lock poisoned").clone()
+    }
+
+    /// The effective values of all settings.
+    pub fn values(&self) -> SettingValues {
+        self.state.read().expect("Settings lock poisoned").values
+    }
+
+    /// The limits enforced when creating resources.
+    pub fn limits(&self) -> Limits {
+        self.values().limits()
+    }
+
+    /// Whether the media proxy is enabled.
+    pub fn media_proxy_enabled(&self) -> bool {
+        self.values().media_proxy_enabled()
+    }
+
+    /// Whether direct links to external media in new messages are re-hosted as attachments.
+    pub fn media_rehost_enabled(&self) -> bool {
+        self.values().media_rehost_enabled()
+    }
+
+    /// Replace the cached overrides with the ones currently stored.
+    /// Overrides of unknown settings, or with invalid values, are logged and skipped.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The configuration the overrides are applied to.
+    /// * `stored` - The stored overrides, by the name of the setting they override.
+    ///
+    /// ## Returns
+    ///
+    /// The new effective values of all settings.
+    pub fn update(&self, config: &Config, stored: Vec<(String, Value)>) -> SettingValues {
+        let mut values = SettingValues::from_config(config);
+        let mut overrides = BTreeMap::new();
+
+        for (name, value) in stored {
+            let Some(key) = SettingKey::from_name(&name) else {
+                tracing::warn!(setting = %name, "Ignoring override of unknown setting");
+                continue;
+            };
+            if let Err(e) = values.set(key, &value) {
+                tracing::warn!(error = %e, "Ignoring invalid setting override");
+                continue;
+            }
+            overrides.insert(key, value);
+        }
+
+        let mut state = self.state.write().expect("Settings lock poisoned");
+        if state.values != values {
+            tracing::info!(?overrides, "Setting overrides changed");
+        }
+        *state = SettingsState { values, overrides };
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{SettingKey, Settings};
+    use crate::models::{limits::Limit, state::Config};
+
+    #[test]
+    fn test_update() {
+        let config = Config::builder()
+            .database_url("postgres://localhost".to_string())
+            .minio_url("http://localhost".to_string())
+            .minio_access_key("access".to_string())
+            .minio_secret_key("secret".to_string())
+            .listen_addr(([127, 0, 0, 1], 8080))
+            .machine_id(0)
+            .process_id(0)
+            .app_secret("hunter2".to_string())
+            .build()
+            .expect("Config should be valid");
+        let settings = Settings::new(&config);
+        assert_eq!(settings.limits(), *config.limits());
+
+        let values = settings.update(
+            &config,
+            vec![
+                ("max_guilds_per_user".into(), json!(5)),
+                ("media_proxy_enabled".into(), json!(true)),
+                // Skipped, as they cannot be applied
+                ("max_channels_per_guild".into(), json!(0)),
+                ("rate_limits_enabled".into(), json!("yes")),
+                ("unknown".into(), json!(1)),
+            ],
+        );
+        assert_eq!(values.limits().max(Limit::MaxGuildsPerUser), 5);
+        assert_eq!(
+            values.limits().max(Limit::MaxChannelsPerGuild),
+            config.limits().max(Limit::MaxChannelsPerGuild)
+        );
+        assert!(settings.media_proxy_enabled());
+        assert_eq!(values.rate_limits_enabled(), config.rate_limits_enabled());
+        assert_eq!(
+            settings.state().overrides.keys().copied().collect::<Vec<_>>(),
+            vec![SettingKey::MaxGuildsPerUser, SettingKey::MediaProxyEnabled]
+        );
+
+        // Removed overrides fall back to the configuration
+        settings.update(&config, Vec::new());
+        assert_eq!(settings.limits(), *config.limits());
+        assert!(settings.state().overrides.is_empty());
+    }
+}
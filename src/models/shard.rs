@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+use super::{errors::BuildError, guild::Guild, snowflake::Snowflake};
+
+/// Calculate which shard a guild belongs to.
+///
+/// Guilds are spread across shards by the timestamp part of their ID.
+///
+/// ## Arguments
+///
+/// * `guild` - The ID of the guild.
+/// * `count` - The total amount of shards. Must not be zero.
+pub fn shard_for(guild: Snowflake<Guild>, count: u32) -> u32 {
+    let shard = (i64::from(guild) >> 22).rem_euclid(i64::from(count));
+    u32::try_from(shard).expect("Shard ID should be less than the shard count")
+}
+
+/// A gateway shard, serialized as `[shard_id, shard_count]`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "[u32; 2]", into = "[u32; 2]")]
+pub struct ShardInfo {
+    id: u32,
+    count: u32,
+}
+
+impl ShardInfo {
+    /// Create a new shard.
+    ///
+    /// ## Arguments
+    ///
+    /// * `id` - The ID of the shard, starting at 0.
+    /// * `count` - The total amount of shards.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the shard count is zero or the ID is not less than the shard count.
+    pub fn new(id: u32, count: u32) -> Result<Self, BuildError> {
+        if id >= count {
+            return Err(BuildError::ValidationError(
+                "Shard ID must be less than the shard count".into(),
+            ));
+        }
+        Ok(Self { id, count })
+    }
+
+    /// The ID of the shard, starting at 0.
+    pub const fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The total amount of shards.
+    pub const fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Whether guilds are split across more than one shard.
+    pub const fn is_sharded(&self) -> bool {
+        self.count > 1
+    }
+
+    /// Whether this is the first shard, which also receives events that do not belong to any guild.
+    pub const fn is_primary(&self) -> bool {
+        self.id == 0
+    }
+
+    /// Whether the given guild belongs to this shard.
+    pub fn owns(&self, guild: Snowflake<Guild>) -> bool {
+        shard_for(guild, self.count) == self.id
+    }
+}
+
+impl Default for ShardInfo {
+    fn default() -> Self {
+        Self { id: 0, count: 1 }
+    }
+}
+
+impl TryFrom<[u32; 2]> for ShardInfo {
+    type Error = BuildError;
+
+    fn try_from([id, count]: [u32; 2]) -> Result<Self, Self::Error> {
+        Self::new(id, count)
+    }
+}
+
+impl From<ShardInfo> for [u32; 2] {
+    fn from(shard: ShardInfo) -> Self {
+        [shard.id, shard.count]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shard_for, ShardInfo};
+    use crate::models::snowflake::Snowflake;
+
+    #[test]
+    fn test_shard_for() {
+        let guild = Snowflake::new(502_225_724_397_195_265);
+        let shard = shard_for(guild, 4);
+        assert!(shard < 4);
+        assert_eq!(shard_for(guild, 1), 0);
+
+        // Every guild belongs to exactly one shard
+        let owners: Vec<u32> = (0..4)
+            .filter(|id| ShardInfo::new(*id, 4).expect("Shard should be valid").owns(guild))
+            .collect();
+        assert_eq!(owners, vec![shard]);
+        assert!(ShardInfo::default().owns(guild));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let shard: ShardInfo = serde_json::from_str("[1, 4]").expect("Shard should deserialize");
+        assert_eq!((shard.id(), shard.count()), (1, 4));
+        assert_eq!(serde_json::to_string(&shard).expect("Shard should serialize"), "[1,4]");
+
+        assert!(serde_json::from_str::<ShardInfo>("[4, 4]").is_err());
+        assert!(serde_json::from_str::<ShardInfo>("[0, 0]").is_err());
+        assert!(serde_json::from_str::<ShardInfo>("[0]").is_err());
+    }
+}
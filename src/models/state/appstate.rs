@@ -1,8 +1,16 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
 
+use argon2::Params;
 use aws_config::BehaviorVersion;
 use aws_sdk_s3::{
-    config::{Credentials as S3Creds, Region},
+    config::{timeout::TimeoutConfig, Credentials as S3Creds, Region},
     Client, Config as S3Config,
 };
 
@@ -12,7 +20,40 @@ use secrecy::{ExposeSecret, Secret};
 
 use super::ops::Ops;
 use crate::gateway::handler::Gateway;
-use crate::models::{bucket::Buckets, db::Database, errors::BuildError};
+use crate::models::{
+    auth::{SameSite, SigningKey, SigningKeys},
+    automod::AutoMod,
+    bucket::{BucketConfig, BucketConfigs, Buckets},
+    clock::{Clock, SystemClock},
+    crosspost::Crossposter,
+    db::Database,
+    doctor::{self, Mode, Report},
+    errors::{AppError, BuildError, StartupError},
+    event_bus::EventBus,
+    feed::FeedPoller,
+    firehose::{Firehose, PiiFilter},
+    instance::InstanceLease,
+    ip_filter::IpFilter,
+    jobs::JobRunner,
+    limits::Limits,
+    load_shedder::LoadShedder,
+    log_filter::LogFilter,
+    media_proxy::MediaProxy,
+    outbox::Outbox,
+    push::PushDispatcher,
+    rate_limit::RateLimiter,
+    scanner::AttachmentScanner,
+    search::SearchIndex,
+    settings::Settings,
+    shard::ShardInfo,
+    snowflake::{self, DEFAULT_EPOCH},
+    translation::Translator,
+    upload_throttle::UploadThrottle,
+    webhook::WebhookDispatcher,
+};
+use crate::services::{
+    GuildService, MemberService, MessageService, OnboardingService, PresenceService, ReportService, StrikeService,
+};
 
 pub type App = Arc<ApplicationState>;
 pub type S3Client = Client;
@@ -23,18 +64,34 @@ pub struct ApplicationState {
     pub db: Database,
     pub gateway: Gateway,
     pub config: Config,
+    /// The configuration values overridden at runtime, refreshed periodically from the database.
+    pub settings: Settings,
     pub s3: Buckets,
+    pub media_proxy: MediaProxy,
+    pub automod: AutoMod,
+    pub jobs: JobRunner,
+    pub upload_throttle: UploadThrottle,
+    pub rate_limiter: RateLimiter,
+    pub load_shedder: LoadShedder,
+    pub outbox: Outbox,
+    pub bus: EventBus,
+    pub webhooks: WebhookDispatcher,
+    pub push: PushDispatcher,
+    pub feeds: FeedPoller,
+    pub crossposts: Crossposter,
+    pub search: SearchIndex,
+    pub scanner: AttachmentScanner,
+    pub translator: Translator,
+    pub firehose: Firehose,
+    pub instance: InstanceLease,
+    pub log_filter: LogFilter,
+    /// The source of the current time, replaced in tests to control it.
+    pub clock: Arc<dyn Clock>,
 }
 
 impl ApplicationState {
-    /// Create a new application state.
-    ///
-    /// ## Errors
-    ///
-    /// * [`sqlx::Error`] - If the database initialization fails.
-    pub async fn new_shared() -> Result<Arc<Self>, sqlx::Error> {
-        let config = Config::from_env();
-
+    /// Create a new application state from the given config and clock. Nothing is connected yet.
+    fn new(config: Config, clock: Arc<dyn Clock>) -> Self {
         let s3creds = S3Creds::new(
             config.minio_access_key().expose_secret(),
             config.minio_secret_key().expose_secret(),
@@ -48,50 +105,282 @@ impl ApplicationState {
             .endpoint_url(config.minio_url())
             .credentials_provider(s3creds)
             .force_path_style(true) // MinIO does not support virtual hosts
+            // Fail quickly if MinIO is down so the circuit breaker can open
+            .timeout_config(TimeoutConfig::builder().connect_timeout(Duration::from_secs(3)).build())
             .behavior_version(BehaviorVersion::v2024_03_28())
             .build();
 
-        let buckets = Buckets::new(Client::from_conf(s3conf));
+        let buckets = Buckets::new(Client::from_conf(s3conf), config.buckets().clone());
+
+        let upload_throttle = UploadThrottle::new(config.upload_rate_limit());
+        let rate_limiter = RateLimiter::new(config.rate_limits_enabled());
+        let load_shedder = LoadShedder::new(&config);
+        let search = SearchIndex::new(&config);
+        let firehose = Firehose::new(&config);
+        let scanner = AttachmentScanner::new(&config);
+        let translator = Translator::new(&config);
+        let push = PushDispatcher::new(&config);
+        let settings = Settings::new(&config);
 
-        let mut state = Self {
+        // Snowflakes are decoded in many places that have no access to the configuration
+        snowflake::set_epoch(config.snowflake_epoch());
+
+        Self {
             db: Database::new(),
+            gateway: Gateway::new(config.gateway_shard()),
             config,
-            gateway: Gateway::new(),
+            settings,
             s3: buckets,
-        };
+            media_proxy: MediaProxy::new(),
+            automod: AutoMod::new(),
+            jobs: JobRunner::new(),
+            upload_throttle,
+            rate_limiter,
+            load_shedder,
+            outbox: Outbox::new(),
+            bus: EventBus::new(),
+            webhooks: WebhookDispatcher::new(),
+            push,
+            feeds: FeedPoller::new(),
+            crossposts: Crossposter::new(),
+            search,
+            scanner,
+            translator,
+            firehose,
+            instance: InstanceLease::new(),
+            log_filter: LogFilter::new(),
+            clock,
+        }
+    }
+
+    /// Create a new application state.
+    ///
+    /// ## Errors
+    ///
+    /// * [`StartupError::Database`] - If the database initialization fails after all connection attempts.
+    /// * [`StartupError::ChecksFailed`] - If any of the startup checks fail.
+    pub async fn new_shared() -> Result<Arc<Self>, StartupError> {
+        let mut state = Self::new(Config::from_env(), Arc::new(SystemClock));
 
         state.init().await?;
 
-        Ok(Arc::new_cyclic(|w| {
-            state.db.bind_to(w.clone());
-            state.gateway.bind_to(w.clone());
-            state.s3.bind_to(w.clone());
-            state
-        }))
+        Ok(state.into_shared())
+    }
+
+    /// Create a new application state for tests and benchmarks from the given config.
+    /// Migrations are applied, but the startup checks are skipped, as tests do not have object storage.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The configuration of the application.
+    /// * `clock` - The source of the current time, such as a [`ManualClock`](crate::models::clock::ManualClock).
+    ///
+    /// ## Errors
+    ///
+    /// * [`StartupError::Database`] - If the database connection or migrations fail.
+    #[cfg(any(test, feature = "bench"))]
+    pub async fn new_test(config: Config, clock: Arc<dyn Clock>) -> Result<Arc<Self>, StartupError> {
+        let mut state = Self::new(config, clock);
+
+        state.db.connect(&state.config).await?;
+        state.db.migrate().await?;
+
+        Ok(state.into_shared())
+    }
+
+    /// Create a new application state for maintenance commands.
+    /// Migrations are applied and the machine and process IDs are claimed,
+    /// but the startup checks are skipped and no background jobs are started.
+    ///
+    /// ## Errors
+    ///
+    /// * [`StartupError::Database`] - If the database connection or migrations fail.
+    /// * [`StartupError::InstanceInUse`] - If a running server uses the same machine and process IDs.
+    /// * [`StartupError::EpochMismatch`] - If existing snowflakes were generated with a different epoch.
+    pub async fn new_maintenance() -> Result<Arc<Self>, StartupError> {
+        let mut state = Self::new(Config::from_env(), Arc::new(SystemClock));
+
+        state.db.connect(&state.config).await?;
+        state.db.migrate().await?;
+
+        // Maintenance commands may create snowflakes while the server is running
+        if state
+            .instance
+            .claim(&state.db, &state.config, state.clock.now())
+            .await?
+            .is_some()
+        {
+            state.db.close().await;
+            return Err(StartupError::InstanceInUse {
+                machine_id: state.config.machine_id(),
+                process_id: state.config.process_id(),
+            });
+        }
+
+        let stored = snowflake::store_epoch(&state.db, state.config.snowflake_epoch()).await?;
+        if stored != state.config.snowflake_epoch() {
+            state.close().await;
+            return Err(StartupError::EpochMismatch {
+                configured: state.config.snowflake_epoch(),
+                stored,
+            });
+        }
+
+        Ok(state.bind())
     }
 
-    /// Initializes the application
+    /// Apply all pending migrations without starting the server.
     ///
     /// ## Errors
     ///
-    /// * [`sqlx::Error`] - If the database connection fails.
-    async fn init(&mut self) -> Result<(), sqlx::Error> {
-        self.db.connect(self.config.database_url().expose_secret()).await
+    /// * [`StartupError::Database`] - If the database connection or migrations fail.
+    pub async fn migrate() -> Result<(), StartupError> {
+        let mut state = Self::new(Config::from_env(), Arc::new(SystemClock));
+
+        state.db.connect(&state.config).await?;
+        state.db.migrate().await?;
+
+        state.db.close().await;
+        Ok(())
+    }
+
+    /// Bind all components to the shared state and start the background jobs.
+    fn into_shared(self) -> Arc<Self> {
+        let app = self.bind();
+        app.jobs.start(&app.config);
+        app
+    }
+
+    /// Bind all components to the shared state, without starting the background jobs.
+    fn bind(mut self) -> Arc<Self> {
+        Arc::new_cyclic(|w| {
+            self.db.bind_to(w.clone());
+            self.gateway.bind_to(w.clone());
+            self.s3.bind_to(w.clone());
+            self.media_proxy.bind_to(w.clone());
+            self.jobs.bind_to(w.clone());
+            self.outbox.bind_to(w.clone());
+            self.bus.bind_to(w.clone());
+            self.webhooks.bind_to(w.clone());
+            self.push.bind_to(w.clone());
+            self.feeds.bind_to(w.clone());
+            self.crossposts.bind_to(w.clone());
+            self.search.bind_to(w.clone());
+            self
+        })
+    }
+
+    /// Run all startup checks without applying migrations or starting the server.
+    ///
+    /// ## Returns
+    ///
+    /// The [`Report`] of all checks.
+    ///
+    /// ## Errors
+    ///
+    /// * [`StartupError::Database`] - If the database connection fails after all connection attempts.
+    pub async fn check() -> Result<Report, StartupError> {
+        let mut state = Self::new(Config::from_env(), Arc::new(SystemClock));
+        state.db.connect(&state.config).await?;
+
+        let report = doctor::run(&state, Mode::Check).await;
+        report.log();
+
+        state.db.close().await;
+        Ok(report)
+    }
+
+    /// Initializes the application and runs the startup checks.
+    ///
+    /// ## Errors
+    ///
+    /// * [`StartupError::Database`] - If the database connection or migrations fail.
+    /// * [`StartupError::ChecksFailed`] - If any of the startup checks fail.
+    async fn init(&mut self) -> Result<(), StartupError> {
+        self.db.connect(&self.config).await?;
+        self.db.migrate().await?;
+
+        // Requests must not be served with values that were overridden before the restart
+        if let Err(e) = self.refresh_settings().await {
+            tracing::warn!(error = %e, "Failed to load setting overrides, using the configured values");
+        }
+
+        let report = doctor::run(self, Mode::Startup).await;
+        report.log();
+
+        match report.failures() {
+            0 => Ok(()),
+            failures => Err(StartupError::ChecksFailed(failures)),
+        }
+    }
+
+    /// Reload the setting overrides from the database, and apply them to the components that cache them.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails. The previous overrides are kept.
+    pub async fn refresh_settings(&self) -> Result<(), AppError> {
+        let stored = self.ops().fetch_settings().await?;
+        let values = self.settings.update(&self.config, stored);
+
+        self.rate_limiter.set_enabled(values.rate_limits_enabled());
+        self.upload_throttle.set_rate(values.upload_rate_limit());
+        Ok(())
     }
 
     /// Closes the application and cleans up resources.
     pub async fn close(&self) {
+        self.jobs.close();
         self.gateway.close();
+        if let Err(e) = self.instance.release(&self.db).await {
+            tracing::warn!(error = %e, "Failed to release instance IDs");
+        }
         self.db.close().await;
     }
 
     #[inline]
-    pub const fn ops(&self) -> Ops {
+    pub const fn ops(&self) -> Ops<'_> {
         Ops::new(self)
     }
+
+    #[inline]
+    pub const fn guilds(&self) -> GuildService<'_> {
+        GuildService::new(self)
+    }
+
+    #[inline]
+    pub const fn members(&self) -> MemberService<'_> {
+        MemberService::new(self)
+    }
+
+    #[inline]
+    pub const fn messages(&self) -> MessageService<'_> {
+        MessageService::new(self)
+    }
+
+    #[inline]
+    pub const fn onboarding(&self) -> OnboardingService<'_> {
+        OnboardingService::new(self)
+    }
+
+    #[inline]
+    pub const fn presences(&self) -> PresenceService<'_> {
+        PresenceService::new(self)
+    }
+
+    #[inline]
+    pub const fn reports(&self) -> ReportService<'_> {
+        ReportService::new(self)
+    }
+
+    #[inline]
+    pub const fn strikes(&self) -> StrikeService<'_> {
+        StrikeService::new(self)
+    }
 }
 
 /// Application configuration
+#[allow(clippy::struct_excessive_bools)] // Each flag toggles an independent feature
 #[derive(Debug, Clone, Builder)]
 #[builder(setter(into), build_fn(error = "BuildError"))]
 pub struct Config {
@@ -102,7 +391,166 @@ pub struct Config {
     listen_addr: SocketAddr,
     machine_id: i32,
     process_id: i32,
-    app_secret: Secret<String>,
+    #[builder(default = "DEFAULT_EPOCH")]
+    snowflake_epoch: i64,
+    #[builder(setter(custom))]
+    signing_key: SigningKey,
+    #[builder(default)]
+    previous_signing_keys: Vec<SigningKey>,
+    #[builder(default)]
+    media_proxy_enabled: bool,
+    #[builder(default = "10 * 1024 * 1024")]
+    media_proxy_max_size: usize,
+    #[builder(default)]
+    media_rehost_enabled: bool,
+    #[builder(default = "10")]
+    database_connect_attempts: u32,
+    #[builder(default)]
+    database_min_connections: u32,
+    #[builder(default = "10")]
+    database_max_connections: u32,
+    #[builder(default = "Duration::from_secs(5)")]
+    database_acquire_timeout: Duration,
+    #[builder(default = "Duration::from_secs(15)")]
+    database_health_check_interval: Duration,
+    #[builder(default = "Duration::from_millis(500)")]
+    database_slow_query_threshold: Duration,
+    #[builder(default = "Duration::from_secs(30)")]
+    request_timeout: Duration,
+    #[builder(default = "2 * 1024 * 1024")]
+    max_body_size: usize,
+    #[builder(default = "Duration::from_mins(2)")]
+    upload_timeout: Duration,
+    #[builder(default = "8 * 1024 * 1024")]
+    max_upload_size: usize,
+    #[builder(default = "1024")]
+    max_concurrent_requests: usize,
+    #[builder(default = "1024 * 1024")]
+    upload_rate_limit: u64,
+    #[builder(default = "true")]
+    rate_limits_enabled: bool,
+    #[builder(default = "0")]
+    load_shed_max_in_flight: usize,
+    #[builder(default = "0")]
+    load_shed_max_queue_depth: usize,
+    #[builder(default = "Duration::from_secs(5)")]
+    load_shed_retry_after: Duration,
+    #[builder(default = "Duration::from_secs(20)")]
+    gateway_ping_interval: Duration,
+    #[builder(default = "Duration::from_mins(1)")]
+    gateway_idle_timeout: Duration,
+    #[builder(default = "16 * 1024")]
+    gateway_max_payload_size: usize,
+    #[builder(default = "3")]
+    gateway_max_invalid_payloads: u32,
+    #[builder(default = "Duration::from_secs(45)")]
+    gateway_heartbeat_interval: Duration,
+    #[builder(default = "Duration::from_secs(25)")]
+    gateway_poll_timeout: Duration,
+    #[builder(default = "Duration::from_mins(1)")]
+    gateway_resume_timeout: Duration,
+    #[builder(default = "1000")]
+    gateway_replay_buffer_size: usize,
+    #[builder(default)]
+    media_listen_addr: Option<SocketAddr>,
+    #[builder(default)]
+    metrics_enabled: bool,
+    #[builder(default)]
+    s3_create_buckets: bool,
+    #[builder(default)]
+    buckets: BucketConfigs,
+    #[builder(default)]
+    argon2_params: Params,
+    #[builder(default = "default_reserved_usernames()")]
+    reserved_usernames: Vec<String>,
+    #[builder(default)]
+    gateway_allowed_origins: Vec<String>,
+    #[builder(default)]
+    auth_cookies_enabled: bool,
+    #[builder(default)]
+    auth_cookie_same_site: SameSite,
+    #[builder(default = "true")]
+    auth_cookie_secure: bool,
+    #[builder(default)]
+    gateway_shard: ShardInfo,
+    #[builder(default)]
+    limits: Limits,
+    #[builder(default = "250")]
+    large_guild_threshold: usize,
+    #[builder(default = "Duration::from_hours(7 * 24)")]
+    guild_deletion_grace_period: Duration,
+    #[builder(default = "10")]
+    raid_join_threshold: u32,
+    #[builder(default = "Duration::from_mins(1)")]
+    raid_join_window: Duration,
+    #[builder(default = "Duration::from_hours(1)")]
+    raid_mode_duration: Duration,
+    #[builder(default = "86400")]
+    raid_min_account_age: i32,
+    #[builder(default = "Duration::from_secs(30)")]
+    raid_slow_mode: Duration,
+    #[builder(default)]
+    clamav_addr: Option<String>,
+    #[builder(default)]
+    serve_unscanned_attachments: bool,
+    #[builder(default)]
+    meilisearch_url: Option<String>,
+    #[builder(default)]
+    meilisearch_api_key: Option<Secret<String>>,
+    #[builder(default)]
+    libretranslate_url: Option<String>,
+    #[builder(default)]
+    libretranslate_api_key: Option<Secret<String>>,
+    #[builder(default)]
+    internal_api_addr: Option<SocketAddr>,
+    #[builder(default)]
+    internal_api_secret: Option<Secret<String>>,
+    #[builder(default)]
+    internal_api_tls_cert: Option<PathBuf>,
+    #[builder(default)]
+    internal_api_tls_key: Option<PathBuf>,
+    #[builder(default)]
+    internal_api_client_ca: Option<PathBuf>,
+    #[builder(default)]
+    admin_api_secret: Option<Secret<String>>,
+    #[builder(default)]
+    admin_ip_filter: IpFilter,
+    #[builder(default)]
+    webhook_ip_filter: IpFilter,
+    #[builder(default = "Duration::from_mins(15)")]
+    log_level_revert_after: Duration,
+    #[builder(default)]
+    firehose_url: Option<String>,
+    #[builder(default)]
+    firehose_secret: Option<Secret<String>>,
+    #[builder(default)]
+    firehose_pii_filter: PiiFilter,
+    #[builder(default = "500")]
+    firehose_batch_size: usize,
+    #[builder(default = "Duration::from_secs(5)")]
+    firehose_flush_interval: Duration,
+    #[builder(default = "10_000")]
+    firehose_buffer_size: usize,
+    #[builder(default)]
+    fingerprint_salt: Option<Secret<String>>,
+    #[builder(default)]
+    vapid_public_key: Option<String>,
+    #[builder(default)]
+    vapid_private_key: Option<Secret<String>>,
+    #[builder(default)]
+    vapid_subject: Option<String>,
+    #[builder(default)]
+    apns_key: Option<Secret<String>>,
+    #[builder(default)]
+    apns_key_id: Option<String>,
+    #[builder(default)]
+    apns_team_id: Option<String>,
+    #[builder(default)]
+    apns_topic: Option<String>,
+    #[builder(default)]
+    apns_sandbox: bool,
+    #[builder(default)]
+    fcm_service_account: Option<Secret<String>>,
 }
 
 impl Config {
@@ -141,14 +589,430 @@ impl Config {
         self.process_id
     }
 
+    /// The epoch snowflakes are generated with, as a UNIX timestamp in milliseconds.
+    pub const fn snowflake_epoch(&self) -> i64 {
+        self.snowflake_epoch
+    }
+
     /// The addres for the backend server to listen on.
     pub const fn listen_addr(&self) -> SocketAddr {
         self.listen_addr
     }
 
-    /// APP secret used to create JWT tokens.
+    /// APP secret used to create JWT tokens. This is the secret of the active signing key.
     pub const fn app_secret(&self) -> &Secret<String> {
-        &self.app_secret
+        self.signing_key.secret()
+    }
+
+    /// The keys JWT tokens are signed and verified with.
+    /// Tokens are signed with the active key, and tokens signed with a previous key are still accepted.
+    pub fn signing_keys(&self) -> SigningKeys<'_> {
+        SigningKeys::new(&self.signing_key, &self.previous_signing_keys)
+    }
+
+    /// Whether the media proxy is enabled.
+    pub const fn media_proxy_enabled(&self) -> bool {
+        self.media_proxy_enabled
+    }
+
+    /// The maximum size of a single object fetched by the media proxy in bytes.
+    pub const fn media_proxy_max_size(&self) -> usize {
+        self.media_proxy_max_size
+    }
+
+    /// Whether direct links to external media in new messages are re-hosted as attachments.
+    pub const fn media_rehost_enabled(&self) -> bool {
+        self.media_rehost_enabled
+    }
+
+    /// How many times to try connecting to the database on startup before giving up.
+    pub const fn database_connect_attempts(&self) -> u32 {
+        self.database_connect_attempts
+    }
+
+    /// The minimum amount of connections kept open in the database pool.
+    pub const fn database_min_connections(&self) -> u32 {
+        self.database_min_connections
+    }
+
+    /// The maximum amount of connections the database pool may open.
+    pub const fn database_max_connections(&self) -> u32 {
+        self.database_max_connections
+    }
+
+    /// How long to wait for a database connection before failing a query.
+    pub const fn database_acquire_timeout(&self) -> Duration {
+        self.database_acquire_timeout
+    }
+
+    /// How often the database connection is checked for availability.
+    pub const fn database_health_check_interval(&self) -> Duration {
+        self.database_health_check_interval
+    }
+
+    /// Queries taking longer than this are logged as slow queries.
+    pub const fn database_slow_query_threshold(&self) -> Duration {
+        self.database_slow_query_threshold
+    }
+
+    /// The maximum time to handle a request.
+    pub const fn request_timeout(&self) -> Duration {
+        self.request_timeout
+    }
+
+    /// The maximum size of a request body in bytes.
+    pub const fn max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+
+    /// The maximum time to handle a request that uploads files.
+    pub const fn upload_timeout(&self) -> Duration {
+        self.upload_timeout
+    }
+
+    /// The maximum size of a request body that uploads files in bytes.
+    pub const fn max_upload_size(&self) -> usize {
+        self.max_upload_size
+    }
+
+    /// The maximum amount of requests handled concurrently. Further requests wait until one finishes.
+    pub const fn max_concurrent_requests(&self) -> usize {
+        self.max_concurrent_requests
+    }
+
+    /// The maximum rate at which a single user may upload files in bytes per second. `0` means unlimited.
+    pub const fn upload_rate_limit(&self) -> u64 {
+        self.upload_rate_limit
+    }
+
+    /// Whether requests to the REST API are rate limited per route group.
+    pub const fn rate_limits_enabled(&self) -> bool {
+        self.rate_limits_enabled
+    }
+
+    /// The amount of in-flight requests above which low priority requests are rejected. `0` means unlimited.
+    pub const fn load_shed_max_in_flight(&self) -> usize {
+        self.load_shed_max_in_flight
+    }
+
+    /// The amount of queued events above which low priority requests are rejected. `0` means unlimited.
+    pub const fn load_shed_max_queue_depth(&self) -> usize {
+        self.load_shed_max_queue_depth
+    }
+
+    /// How long clients are told to wait before retrying a rejected low priority request.
+    pub const fn load_shed_retry_after(&self) -> Duration {
+        self.load_shed_retry_after
+    }
+
+    /// How often the gateway sends websocket pings to connected clients.
+    pub const fn gateway_ping_interval(&self) -> Duration {
+        self.gateway_ping_interval
+    }
+
+    /// How long a gateway connection may stay silent before it is considered dead.
+    pub const fn gateway_idle_timeout(&self) -> Duration {
+        self.gateway_idle_timeout
+    }
+
+    /// The maximum size of a payload sent by a gateway client in bytes. Larger payloads close the connection.
+    pub const fn gateway_max_payload_size(&self) -> usize {
+        self.gateway_max_payload_size
+    }
+
+    /// The amount of payloads a gateway client may send that cannot be parsed before its connection is closed.
+    pub const fn gateway_max_invalid_payloads(&self) -> u32 {
+        self.gateway_max_invalid_payloads
+    }
+
+    /// How often gateway clients must send a `HEARTBEAT`, announced to them in `HELLO`.
+    pub const fn gateway_heartbeat_interval(&self) -> Duration {
+        self.gateway_heartbeat_interval
+    }
+
+    /// How long a long-polling gateway client waits for events before an empty response is returned.
+    pub const fn gateway_poll_timeout(&self) -> Duration {
+        self.gateway_poll_timeout
+    }
+
+    /// How long a gateway session is kept after its connection dropped, so the client can `RESUME` it.
+    /// Zero if sessions end as soon as their connection drops.
+    pub const fn gateway_resume_timeout(&self) -> Duration {
+        self.gateway_resume_timeout
+    }
+
+    /// The maximum amount of events a gateway session keeps until the client acknowledges them,
+    /// so they can be sent again after it resumes.
+    pub const fn gateway_replay_buffer_size(&self) -> usize {
+        self.gateway_replay_buffer_size
+    }
+
+    /// The origins browsers may connect to the gateway from. If empty, any origin is allowed.
+    /// Clients that do not send an `Origin` header, such as non-browser clients, are always allowed.
+    /// The gateway only accepts session cookies from these origins.
+    pub fn gateway_allowed_origins(&self) -> &[String] {
+        &self.gateway_allowed_origins
+    }
+
+    /// Whether browser clients may keep their session token in a cookie instead of script-accessible storage.
+    pub const fn auth_cookies_enabled(&self) -> bool {
+        self.auth_cookies_enabled
+    }
+
+    /// The `SameSite` attribute of the session cookies.
+    pub const fn auth_cookie_same_site(&self) -> SameSite {
+        self.auth_cookie_same_site
+    }
+
+    /// Whether the session cookies are only sent over HTTPS.
+    pub const fn auth_cookie_secure(&self) -> bool {
+        self.auth_cookie_secure
+    }
+
+    /// The gateway shard served by this process. Guilds of other shards are served by other processes.
+    pub const fn gateway_shard(&self) -> ShardInfo {
+        self.gateway_shard
+    }
+
+    /// A separate address to serve media on, for example to put a CDN in front of it.
+    /// If `None`, media is served on [`Config::listen_addr`].
+    pub const fn media_listen_addr(&self) -> Option<SocketAddr> {
+        self.media_listen_addr
+    }
+
+    /// The Argon2 parameters used to hash passwords.
+    /// Passwords hashed with weaker parameters are rehashed on the next login.
+    pub const fn argon2_params(&self) -> &Params {
+        &self.argon2_params
+    }
+
+    /// Usernames that cannot be registered, in addition to the ones in the `reserved_usernames` table.
+    pub fn reserved_usernames(&self) -> &[String] {
+        &self.reserved_usernames
+    }
+
+    /// The limits enforced when creating guilds, channels, members and attachments.
+    pub const fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// The amount of members above which a guild is large.
+    /// Members of large guilds are not sent in `GUILD_CREATE`, clients request them on demand instead.
+    pub const fn large_guild_threshold(&self) -> usize {
+        self.large_guild_threshold
+    }
+
+    /// How long a deleted guild can be restored by its owner before it is purged.
+    pub const fn guild_deletion_grace_period(&self) -> Duration {
+        self.guild_deletion_grace_period
+    }
+
+    /// The amount of members joining a guild within [`Self::raid_join_window`] that enables raid mode.
+    /// If `0`, raid mode is never enabled automatically.
+    pub const fn raid_join_threshold(&self) -> u32 {
+        self.raid_join_threshold
+    }
+
+    /// The window in which joins are counted towards [`Self::raid_join_threshold`].
+    pub const fn raid_join_window(&self) -> Duration {
+        self.raid_join_window
+    }
+
+    /// How long raid mode stays enabled when it is enabled automatically, or without a duration.
+    pub const fn raid_mode_duration(&self) -> Duration {
+        self.raid_mode_duration
+    }
+
+    /// The minimum account age in seconds required to join a guild in raid mode.
+    pub const fn raid_min_account_age(&self) -> i32 {
+        self.raid_min_account_age
+    }
+
+    /// The minimum time between two messages of a member in a guild in raid mode.
+    pub const fn raid_slow_mode(&self) -> Duration {
+        self.raid_slow_mode
+    }
+
+    /// Whether missing S3 buckets are created on startup.
+    pub const fn s3_create_buckets(&self) -> bool {
+        self.s3_create_buckets
+    }
+
+    /// The bucket name and lifecycle policy of every class of content stored in S3.
+    pub const fn buckets(&self) -> &BucketConfigs {
+        &self.buckets
+    }
+
+    /// Whether Prometheus metrics are served at `/api/v1/metrics`.
+    pub const fn metrics_enabled(&self) -> bool {
+        self.metrics_enabled
+    }
+
+    /// The address of a clamd daemon to scan attachments with, such as `clamav:3310`.
+    /// If `None`, attachments are not scanned.
+    pub fn clamav_addr(&self) -> Option<&str> {
+        self.clamav_addr.as_deref()
+    }
+
+    /// Whether attachments that are still being scanned, or could not be scanned, are served.
+    /// Infected attachments are never served.
+    pub const fn serve_unscanned_attachments(&self) -> bool {
+        self.serve_unscanned_attachments
+    }
+
+    /// The URL of a Meilisearch instance to store the message search index in.
+    /// If `None`, the index is stored in the database.
+    pub fn meilisearch_url(&self) -> Option<&str> {
+        self.meilisearch_url.as_deref()
+    }
+
+    /// The API key used to authenticate with Meilisearch, if it requires one.
+    pub const fn meilisearch_api_key(&self) -> Option<&Secret<String>> {
+        self.meilisearch_api_key.as_ref()
+    }
+
+    /// The URL of a `LibreTranslate` instance used to translate messages.
+    /// If `None`, message translations are disabled.
+    pub fn libretranslate_url(&self) -> Option<&str> {
+        self.libretranslate_url.as_deref()
+    }
+
+    /// The API key used to authenticate with `LibreTranslate`, if it requires one.
+    pub const fn libretranslate_api_key(&self) -> Option<&Secret<String>> {
+        self.libretranslate_api_key.as_ref()
+    }
+
+    /// The address to serve the internal gRPC API on. If `None`, the internal API is disabled.
+    /// This address should only be reachable by trusted services.
+    pub const fn internal_api_addr(&self) -> Option<SocketAddr> {
+        self.internal_api_addr
+    }
+
+    /// The secret callers of the internal API must present as a bearer token.
+    /// If `None`, callers are only authenticated by their client certificate.
+    pub const fn internal_api_secret(&self) -> Option<&Secret<String>> {
+        self.internal_api_secret.as_ref()
+    }
+
+    /// The PEM certificate the internal API is served with. If `None`, the internal API is served without TLS.
+    pub fn internal_api_tls_cert(&self) -> Option<&Path> {
+        self.internal_api_tls_cert.as_deref()
+    }
+
+    /// The PEM private key of [`Config::internal_api_tls_cert`].
+    pub fn internal_api_tls_key(&self) -> Option<&Path> {
+        self.internal_api_tls_key.as_deref()
+    }
+
+    /// The PEM certificate authority that must have signed the client certificates of internal API callers.
+    /// If `None`, callers do not need a client certificate.
+    pub fn internal_api_client_ca(&self) -> Option<&Path> {
+        self.internal_api_client_ca.as_deref()
+    }
+
+    /// The secret operators must present as a bearer token to use the admin endpoints of the REST API.
+    /// If `None`, the admin endpoints are disabled.
+    pub const fn admin_api_secret(&self) -> Option<&Secret<String>> {
+        self.admin_api_secret.as_ref()
+    }
+
+    /// The client addresses that may use the admin endpoints of the REST API.
+    pub const fn admin_ip_filter(&self) -> &IpFilter {
+        &self.admin_ip_filter
+    }
+
+    /// The client addresses that may use the webhook endpoints of guilds.
+    pub const fn webhook_ip_filter(&self) -> &IpFilter {
+        &self.webhook_ip_filter
+    }
+
+    /// How long a log verbosity changed at runtime is kept, unless another duration is requested.
+    pub const fn log_level_revert_after(&self) -> Duration {
+        self.log_level_revert_after
+    }
+
+    /// The URL the firehose sends batches of dispatched gateway events to. If `None`, the firehose is disabled.
+    pub fn firehose_url(&self) -> Option<&str> {
+        self.firehose_url.as_deref()
+    }
+
+    /// The secret sent as a bearer token with every firehose batch, if any.
+    pub const fn firehose_secret(&self) -> Option<&Secret<String>> {
+        self.firehose_secret.as_ref()
+    }
+
+    /// How personal information is removed from events before they are sent to the firehose.
+    pub const fn firehose_pii_filter(&self) -> PiiFilter {
+        self.firehose_pii_filter
+    }
+
+    /// The maximum amount of events sent to the firehose in a single batch.
+    pub const fn firehose_batch_size(&self) -> usize {
+        self.firehose_batch_size
+    }
+
+    /// The longest time an event waits for its batch to fill up before it is sent to the firehose.
+    pub const fn firehose_flush_interval(&self) -> Duration {
+        self.firehose_flush_interval
+    }
+
+    /// The maximum amount of events waiting to be sent to the firehose. Further events are dropped.
+    pub const fn firehose_buffer_size(&self) -> usize {
+        self.firehose_buffer_size
+    }
+
+    /// The salt device identifiers and addresses of users are hashed with to detect ban evasion.
+    /// Fingerprints are only recorded if this is set.
+    pub const fn fingerprint_salt(&self) -> Option<&Secret<String>> {
+        self.fingerprint_salt.as_ref()
+    }
+
+    /// The public VAPID key push notifications are sent to browsers with, base64url encoded.
+    /// If `None`, push notifications are not sent to browsers.
+    pub fn vapid_public_key(&self) -> Option<&str> {
+        self.vapid_public_key.as_deref()
+    }
+
+    /// The private VAPID key belonging to [`Config::vapid_public_key`], base64url encoded.
+    pub const fn vapid_private_key(&self) -> Option<&Secret<String>> {
+        self.vapid_private_key.as_ref()
+    }
+
+    /// The contact URL of the operator sent to Web Push services, such as `mailto:admin@example.com`.
+    pub fn vapid_subject(&self) -> Option<&str> {
+        self.vapid_subject.as_deref()
+    }
+
+    /// The PEM signing key push notifications are sent to Apple devices with.
+    /// If `None`, push notifications are not sent to Apple devices.
+    pub const fn apns_key(&self) -> Option<&Secret<String>> {
+        self.apns_key.as_ref()
+    }
+
+    /// The ID of [`Config::apns_key`], as shown in the Apple developer account.
+    pub fn apns_key_id(&self) -> Option<&str> {
+        self.apns_key_id.as_deref()
+    }
+
+    /// The ID of the Apple developer team the app belongs to.
+    pub fn apns_team_id(&self) -> Option<&str> {
+        self.apns_team_id.as_deref()
+    }
+
+    /// The bundle ID of the app push notifications are sent to.
+    pub fn apns_topic(&self) -> Option<&str> {
+        self.apns_topic.as_deref()
+    }
+
+    /// Whether push notifications are sent through the APNs sandbox, for development builds of the app.
+    pub const fn apns_sandbox(&self) -> bool {
+        self.apns_sandbox
+    }
+
+    /// The Google service account key, in JSON, push notifications are sent through FCM with.
+    /// If `None`, push notifications are not sent through FCM.
+    pub const fn fcm_service_account(&self) -> Option<&Secret<String>> {
+        self.fcm_service_account.as_ref()
     }
 
     /// Creates a new config from environment variables
@@ -157,9 +1021,10 @@ impl Config {
     ///
     /// Panics if any of the required environment variables are not set
     /// or if they are not in a valid format.
+    #[allow(clippy::too_many_lines)] // One builder call per setting
     pub fn from_env() -> Self {
         dotenv().ok();
-        Self::builder()
+        let config = Self::builder()
             .database_url(std::env::var("DATABASE_URL").expect("DATABASE_URL environment variable must be set"))
             .minio_url(std::env::var("MINIO_URL").expect("MINIO_URL environment variable must be set"))
             .minio_access_key(
@@ -180,14 +1045,232 @@ impl Config {
                     .parse::<i32>()
                     .expect("PROCESS_ID must be a valid integer"),
             )
+            .snowflake_epoch(std::env::var("SNOWFLAKE_EPOCH").map_or(DEFAULT_EPOCH, |epoch| {
+                snowflake::parse_epoch(&epoch).expect(
+                    "SNOWFLAKE_EPOCH must be a UNIX timestamp in milliseconds, or one of 'default', 'discord' and 'twitter'",
+                )
+            }))
             .listen_addr(
                 std::env::var("LISTEN_ADDR")
                     .expect("LISTEN_ADDR environment variable must be set")
                     .parse::<SocketAddr>()
                     .expect("LISTEN_ADDR must be a valid socket address"),
             )
-            .app_secret(std::env::var("APP_SECRET").expect("APP_SECRET environment variable must be set"))
+            .signing_key(SigningKey::new(
+                std::env::var("APP_SECRET_ID").unwrap_or_else(|_| DEFAULT_SIGNING_KEY_ID.into()),
+                std::env::var("APP_SECRET").expect("APP_SECRET environment variable must be set"),
+            ))
+            .previous_signing_keys(env_list("APP_PREVIOUS_SECRETS").map_or_else(Vec::new, |keys| {
+                keys.iter()
+                    .map(|key| {
+                        key.parse::<SigningKey>()
+                            .expect("APP_PREVIOUS_SECRETS must be a comma-separated list of 'id:secret' keys")
+                    })
+                    .collect()
+            }))
+            .media_proxy_enabled(env_or("MEDIA_PROXY_ENABLED", false))
+            .media_proxy_max_size(env_or::<usize>("MEDIA_PROXY_MAX_SIZE", 10 * 1024 * 1024))
+            .media_rehost_enabled(env_or("MEDIA_REHOST_ENABLED", false))
+            .database_connect_attempts(env_or::<u32>("DATABASE_CONNECT_ATTEMPTS", 10).max(1))
+            .database_min_connections(env_or::<u32>("DATABASE_MIN_CONNECTIONS", 0))
+            .database_max_connections(env_or::<u32>("DATABASE_MAX_CONNECTIONS", 10).max(1))
+            .database_acquire_timeout(Duration::from_secs(env_or("DATABASE_ACQUIRE_TIMEOUT", 5)))
+            .database_health_check_interval(Duration::from_secs(env_or("DATABASE_HEALTH_CHECK_INTERVAL", 15)))
+            .database_slow_query_threshold(Duration::from_millis(env_or("DATABASE_SLOW_QUERY_THRESHOLD", 500)))
+            .request_timeout(Duration::from_secs(env_or("REQUEST_TIMEOUT", 30)))
+            .max_body_size(env_or::<usize>("MAX_BODY_SIZE", 2 * 1024 * 1024))
+            .upload_timeout(Duration::from_secs(env_or("UPLOAD_TIMEOUT", 120)))
+            .max_upload_size(env_or::<usize>("MAX_UPLOAD_SIZE", 8 * 1024 * 1024))
+            .max_concurrent_requests(env_or::<usize>("MAX_CONCURRENT_REQUESTS", 1024).max(1))
+            .upload_rate_limit(env_or::<u64>("UPLOAD_RATE_LIMIT", 1024 * 1024))
+            .rate_limits_enabled(env_or("RATE_LIMITS_ENABLED", true))
+            .load_shed_max_in_flight(env_or::<usize>("LOAD_SHED_MAX_IN_FLIGHT", 0))
+            .load_shed_max_queue_depth(env_or::<usize>("LOAD_SHED_MAX_QUEUE_DEPTH", 0))
+            .load_shed_retry_after(Duration::from_secs(env_or::<u64>("LOAD_SHED_RETRY_AFTER", 5).max(1)))
+            .gateway_ping_interval(Duration::from_secs(env_or::<u64>("GATEWAY_PING_INTERVAL", 20).max(1)))
+            .gateway_idle_timeout(Duration::from_secs(env_or::<u64>("GATEWAY_IDLE_TIMEOUT", 60).max(1)))
+            .gateway_max_payload_size(env_or::<usize>("GATEWAY_MAX_PAYLOAD_SIZE", 16 * 1024).max(1024))
+            .gateway_max_invalid_payloads(env_or::<u32>("GATEWAY_MAX_INVALID_PAYLOADS", 3).max(1))
+            .gateway_heartbeat_interval(Duration::from_secs(
+                env_or::<u64>("GATEWAY_HEARTBEAT_INTERVAL", 45).max(1),
+            ))
+            .gateway_poll_timeout(Duration::from_secs(env_or::<u64>("GATEWAY_POLL_TIMEOUT", 25).max(1)))
+            .gateway_resume_timeout(Duration::from_secs(env_or::<u64>("GATEWAY_RESUME_TIMEOUT", 60)))
+            .gateway_replay_buffer_size(env_or::<usize>("GATEWAY_REPLAY_BUFFER_SIZE", 1000).max(1))
+            .media_listen_addr(std::env::var("MEDIA_LISTEN_ADDR").ok().map(|addr| {
+                addr.parse::<SocketAddr>()
+                    .expect("MEDIA_LISTEN_ADDR must be a valid socket address")
+            }))
+            .metrics_enabled(env_or("METRICS_ENABLED", false))
+            .s3_create_buckets(env_or("S3_CREATE_BUCKETS", false))
+            .buckets(BucketConfigs::from_fn(|class| {
+                let key = class.as_str().to_ascii_uppercase();
+                let expire_after_days = env_or(
+                    &format!("S3_{key}_EXPIRE_AFTER_DAYS"),
+                    class.default_expire_after_days().unwrap_or(0),
+                );
+                BucketConfig::new(
+                    env_or(&format!("S3_{key}_BUCKET"), class.default_bucket_name().to_string()),
+                    (expire_after_days > 0).then_some(expire_after_days),
+                )
+            }))
+            .reserved_usernames(env_list("RESERVED_USERNAMES").unwrap_or_else(default_reserved_usernames))
+            .gateway_allowed_origins(
+                env_list("GATEWAY_ALLOWED_ORIGINS")
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|origin| origin.trim_end_matches('/').to_ascii_lowercase())
+                    .collect::<Vec<_>>(),
+            )
+            .auth_cookies_enabled(env_or("AUTH_COOKIES_ENABLED", false))
+            .auth_cookie_same_site(env_or("AUTH_COOKIE_SAME_SITE", SameSite::Lax))
+            .auth_cookie_secure(env_or("AUTH_COOKIE_SECURE", true))
+            .limits(Limits::new(
+                env_or("MAX_GUILDS_PER_USER", 100),
+                env_or("MAX_CHANNELS_PER_GUILD", 500),
+                env_or("MAX_MEMBERS_PER_GUILD", 10_000),
+                env_or("MAX_ATTACHMENT_SIZE", 8 * 1024 * 1024),
+            ))
+            .large_guild_threshold(env_or::<usize>("LARGE_GUILD_THRESHOLD", 250))
+            .guild_deletion_grace_period(Duration::from_secs(env_or("GUILD_DELETION_GRACE_PERIOD", 604_800)))
+            .raid_join_threshold(env_or("RAID_JOIN_THRESHOLD", 10_u32))
+            .raid_join_window(Duration::from_secs(env_or("RAID_JOIN_WINDOW", 60)))
+            .raid_mode_duration(Duration::from_secs(env_or("RAID_MODE_DURATION", 3600)))
+            .raid_min_account_age(env_or("RAID_MIN_ACCOUNT_AGE", 86400))
+            .raid_slow_mode(Duration::from_secs(env_or("RAID_SLOW_MODE", 30)))
+            .gateway_shard(
+                ShardInfo::new(env_or("GATEWAY_SHARD_ID", 0), env_or("GATEWAY_SHARD_COUNT", 1))
+                    .expect("GATEWAY_SHARD_ID must be less than GATEWAY_SHARD_COUNT"),
+            )
+            .clamav_addr(std::env::var("CLAMAV_ADDR").ok())
+            .serve_unscanned_attachments(env_or("SERVE_UNSCANNED_ATTACHMENTS", false))
+            .meilisearch_url(std::env::var("MEILISEARCH_URL").ok())
+            .meilisearch_api_key(std::env::var("MEILISEARCH_API_KEY").ok().map(Secret::new))
+            .libretranslate_url(std::env::var("LIBRETRANSLATE_URL").ok())
+            .libretranslate_api_key(std::env::var("LIBRETRANSLATE_API_KEY").ok().map(Secret::new))
+            .internal_api_addr(std::env::var("INTERNAL_API_ADDR").ok().map(|addr| {
+                addr.parse::<SocketAddr>()
+                    .expect("INTERNAL_API_ADDR must be a valid socket address")
+            }))
+            .internal_api_secret(std::env::var("INTERNAL_API_SECRET").ok().map(Secret::new))
+            .internal_api_tls_cert(std::env::var("INTERNAL_API_TLS_CERT").ok().map(PathBuf::from))
+            .internal_api_tls_key(std::env::var("INTERNAL_API_TLS_KEY").ok().map(PathBuf::from))
+            .internal_api_client_ca(std::env::var("INTERNAL_API_CLIENT_CA").ok().map(PathBuf::from))
+            .admin_api_secret(std::env::var("ADMIN_API_SECRET").ok().map(Secret::new))
+            .admin_ip_filter(
+                IpFilter::new(
+                    &env_list("ADMIN_ALLOWED_IPS").unwrap_or_default(),
+                    &env_list("ADMIN_DENIED_IPS").unwrap_or_default(),
+                )
+                .expect("ADMIN_ALLOWED_IPS and ADMIN_DENIED_IPS must be lists of networks in CIDR notation"),
+            )
+            .webhook_ip_filter(
+                IpFilter::new(
+                    &env_list("WEBHOOK_ALLOWED_IPS").unwrap_or_default(),
+                    &env_list("WEBHOOK_DENIED_IPS").unwrap_or_default(),
+                )
+                .expect("WEBHOOK_ALLOWED_IPS and WEBHOOK_DENIED_IPS must be lists of networks in CIDR notation"),
+            )
+            .log_level_revert_after(Duration::from_secs(env_or::<u64>("LOG_LEVEL_REVERT_AFTER", 900).max(1)))
+            .firehose_url(std::env::var("FIREHOSE_URL").ok())
+            .firehose_secret(std::env::var("FIREHOSE_SECRET").ok().map(Secret::new))
+            .firehose_pii_filter(env_or("FIREHOSE_PII_FILTER", PiiFilter::Redact))
+            .firehose_batch_size(env_or::<usize>("FIREHOSE_BATCH_SIZE", 500).max(1))
+            .firehose_flush_interval(Duration::from_secs(env_or::<u64>("FIREHOSE_FLUSH_INTERVAL", 5).max(1)))
+            .firehose_buffer_size(env_or::<usize>("FIREHOSE_BUFFER_SIZE", 10_000))
+            .fingerprint_salt(std::env::var("FINGERPRINT_SALT").ok().map(Secret::new))
+            .vapid_public_key(std::env::var("VAPID_PUBLIC_KEY").ok())
+            .vapid_private_key(std::env::var("VAPID_PRIVATE_KEY").ok().map(Secret::new))
+            .vapid_subject(std::env::var("VAPID_SUBJECT").ok())
+            .apns_key(
+                std::env::var("APNS_KEY_FILE").ok().map(|path| {
+                    Secret::new(std::fs::read_to_string(path).expect("APNS_KEY_FILE must be a readable file"))
+                }),
+            )
+            .apns_key_id(std::env::var("APNS_KEY_ID").ok())
+            .apns_team_id(std::env::var("APNS_TEAM_ID").ok())
+            .apns_topic(std::env::var("APNS_TOPIC").ok())
+            .apns_sandbox(env_or("APNS_SANDBOX", false))
+            .fcm_service_account(std::env::var("FCM_SERVICE_ACCOUNT_FILE").ok().map(|path| {
+                Secret::new(std::fs::read_to_string(path).expect("FCM_SERVICE_ACCOUNT_FILE must be a readable file"))
+            }))
+            .argon2_params(
+                Params::new(
+                    env_or("ARGON2_MEMORY_COST", Params::DEFAULT_M_COST),
+                    env_or("ARGON2_TIME_COST", Params::DEFAULT_T_COST),
+                    env_or("ARGON2_PARALLELISM", Params::DEFAULT_P_COST),
+                    None,
+                )
+                .expect("ARGON2_MEMORY_COST, ARGON2_TIME_COST and ARGON2_PARALLELISM must be valid Argon2 parameters"),
+            )
             .build()
-            .expect("Failed to create application configuration.")
+            .expect("Failed to create application configuration.");
+
+        let mut key_ids = HashSet::new();
+        assert!(
+            config.signing_keys().iter().all(|key| key_ids.insert(key.id())),
+            "APP_SECRET_ID and the key IDs of APP_PREVIOUS_SECRETS must be unique"
+        );
+        config
+    }
+}
+
+impl ConfigBuilder {
+    /// The key JWT tokens are signed with.
+    pub fn signing_key(&mut self, key: SigningKey) -> &mut Self {
+        self.signing_key = Some(key);
+        self
+    }
+
+    /// The secret JWT tokens are signed with, under the default key ID.
+    pub fn app_secret(&mut self, secret: impl Into<String>) -> &mut Self {
+        self.signing_key(SigningKey::new(DEFAULT_SIGNING_KEY_ID, secret))
     }
 }
+
+/// The key ID of the active signing key if `APP_SECRET_ID` is not set.
+const DEFAULT_SIGNING_KEY_ID: &str = "default";
+
+/// Parse a comma-separated list from an environment variable, ignoring empty entries.
+/// Returns `None` if the variable is not set.
+fn env_list(key: &str) -> Option<Vec<String>> {
+    std::env::var(key).ok().map(|v| {
+        v.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect()
+    })
+}
+
+/// The usernames reserved by default, as they could be used to impersonate the system or its operators.
+fn default_reserved_usernames() -> Vec<String> {
+    [
+        "admin",
+        "administrator",
+        "system",
+        "api",
+        "root",
+        "support",
+        "moderator",
+        "staff",
+        "official",
+        "everyone",
+        "here",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+/// Parse an optional environment variable, falling back to `default` if it is not set.
+///
+/// ## Panics
+///
+/// Panics if the variable is set but is not in a valid format.
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    std::env::var(key).map_or(default, |v| {
+        v.parse::<T>()
+            .unwrap_or_else(|_| panic!("{key} environment variable is not in a valid format"))
+    })
+}
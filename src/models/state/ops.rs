@@ -1,16 +1,47 @@
-use chrono::Utc;
+use std::collections::HashMap;
+
+use sqlx::PgConnection;
 
 use crate::models::{
-    attachment::{Attachment, AttachmentLike, FullAttachment},
+    analytics::{self, DailyStatsRecord, GuildAnalytics, SECONDS_PER_DAY},
+    announcement::{Announcement, AnnouncementRecord},
+    attachment::{format_s3_key, Attachment, AttachmentLike, FullAttachment, PartialAttachment, ScanStatus},
+    audit_log::{AuditLogAction, AuditLogEntry, AuditLogRecord},
+    automod::{AutoModAction, AutoModRule, AutoModRuleRecord},
     avatar::{Avatar, AvatarLike},
-    channel::{Channel, ChannelLike, ChannelRecord, TextChannel},
-    errors::{AppError, BuildError, RESTError},
+    bulk::{BulkError, BulkResult},
+    channel::{Channel, ChannelLike, ChannelRecord, SavedMessagesChannel, TextChannel},
+    content::{ChannelMention, Mention, MentionTargets},
+    crosspost::{ChannelFollow, ChannelFollowRecord, Crossposter},
+    emoji::{EmojiUsage, EmojiUsageRecord},
+    errors::{AppError, BuildError, ErrorCode},
+    feed::{Feed, FeedRecord},
+    fingerprint::{EvasionFlag, EvasionFlagRecord},
+    gateway_event::{ChannelBulkUpdatePayload, DeletePayload, GatewayEvent, MessageBulkRemovePayload},
+    guest_link::{GuestAccess, GuestLink, GuestLinkRecord},
     guild::{Guild, GuildRecord},
+    guild_folder::{GuildFolderRecord, GuildSettings},
+    instance::INSTANCE_TIMEOUT,
+    invite::{ExtendedGuildInviteRecord, GuildInvite},
     member::{ExtendedMemberRecord, Member, MemberRecord, UserLike},
-    message::{ExtendedMessageRecord, Message},
+    message::{ExtendedMessageRecord, Message, MessageFields, PartialMessage, PartialMessageRecord},
+    message_change::{MessageChange, MessageChangeRecord, MESSAGE_CHANGE_RETENTION},
+    onboarding::{MemberOnboarding, MemberOnboardingRecord, Onboarding, OnboardingRecord},
+    outbox::Outbox,
+    prefs::PrefFlags,
+    presence_privacy::{PresencePrivacy, PresenceVisibility},
+    profile::{Profile, ProfilePrivacy, ProfileRecord},
+    push::{PushDevice, PushDeviceRecord},
+    report::{Report, ReportRecord, ReportState},
     requests::{CreateGuild, CreateUser, UpdateGuild, UpdateUser},
+    search::SearchIndex,
+    session::{GatewaySession, GatewaySessionRecord},
+    settings::SettingKey,
     snowflake::Snowflake,
-    user::{Presence, User, UserRecord},
+    strike::{GuildBan, GuildBanRecord, Strike, StrikePolicy, StrikePolicyRecord, StrikeRecord},
+    translation::Translation,
+    user::{Presence, User, UserFlags, UserRecord},
+    webhook::{Webhook, WebhookDelivery, WebhookDeliveryRecord, WebhookRecord},
 };
 
 use super::ApplicationState;
@@ -38,10 +69,11 @@ impl<'a> Ops<'a> {
     pub async fn fetch_channel(&self, id: impl Into<Snowflake<Channel>>) -> Option<Channel> {
         let record = sqlx::query_as!(
             ChannelRecord,
-            "SELECT * FROM channels WHERE id = $1",
+            "SELECT * FROM channels
+            WHERE id = $1 AND NOT EXISTS(SELECT 1 FROM guilds WHERE guilds.id = channels.guild_id AND guilds.deleted_at IS NOT NULL)",
             id.into() as Snowflake<Channel>
         )
-        .fetch_optional(self.app.db.pool())
+        .fetch_optional(self.app.db.executor())
         .await
         .ok()??;
 
@@ -56,32 +88,123 @@ impl<'a> Ops<'a> {
     pub async fn create_channel(&self, channel: &Channel) -> Result<Channel, sqlx::Error> {
         sqlx::query_as!(
             ChannelRecord,
-            "INSERT INTO channels (id, guild_id, name, channel_type)
-            VALUES ($1, $2, $3, $4) RETURNING *",
+            "INSERT INTO channels (id, guild_id, owner_id, name, channel_type)
+            VALUES ($1, $2, $3, $4, $5) RETURNING *",
             channel.id() as Snowflake<Channel>,
-            channel.guild_id() as Snowflake<Guild>,
+            channel.guild_id() as Option<Snowflake<Guild>>,
+            channel.owner_id() as Option<Snowflake<User>>,
             channel.name(),
             channel.channel_type(),
         )
-        .fetch_one(self.app.db.pool())
+        .fetch_one(self.app.db.executor())
         .await
         .map(Channel::from_record)
     }
 
-    /// Commit this channel to the database.
+    /// Commit this channel to the database and increment its version.
+    /// The update only succeeds if the channel was not updated since it was fetched.
     ///
     /// ## Errors
     ///
-    /// * [`sqlx::Error`] - If the database query fails.
-    pub async fn update_channel(&self, channel: &Channel) -> Result<(), sqlx::Error> {
-        sqlx::query!(
-            "UPDATE channels SET name = $2 WHERE id = $1",
+    /// * [`AppError::Conflict`] - If the channel was updated concurrently.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update_channel(&self, channel: &mut Channel) -> Result<(), AppError> {
+        let mut conn = self.app.db.pool().acquire().await?;
+        self.update_channel_in(&mut conn, channel).await
+    }
+
+    /// Commit this channel to the database as part of a transaction, see [`Ops::update_channel`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Conflict`] - If the channel was updated concurrently.
+    /// * [`AppError::Database`] - If the database query fails.
+    async fn update_channel_in(&self, conn: &mut PgConnection, channel: &mut Channel) -> Result<(), AppError> {
+        let version = sqlx::query_scalar!(
+            "UPDATE channels SET name = $2, retention_days = $3, announcement = $4, archived = $5, sticky_message_id = $7,
+                version = version + 1
+            WHERE id = $1 AND version = $6
+            RETURNING version",
             channel.id() as Snowflake<Channel>,
-            channel.name()
+            channel.name(),
+            channel.retention_days(),
+            channel.is_announcement(),
+            channel.is_archived(),
+            channel.version(),
+            channel.sticky_message_id() as Option<Snowflake<Message>>,
+        )
+        .fetch_optional(self.app.db.instrument(&mut *conn))
+        .await?
+        .ok_or_else(|| AppError::Conflict("The channel was updated concurrently".into()))?;
+
+        *channel.version_mut() = version;
+        Ok(())
+    }
+
+    /// Commit the results of a bulk request to a guild in a single transaction.
+    /// If any of them fails, none of them are committed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the bulk request was made to.
+    /// * `results` - The prepared results of the operations, in order. Versions of updated channels are incremented.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::ChannelBulkUpdate`] - To all guild members, through the outbox
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Conflict`] - If a channel was updated concurrently, along with the index of its operation.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn commit_bulk(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        results: &mut [BulkResult],
+    ) -> Result<(), BulkError> {
+        let mut tx = self.app.db.pool().begin().await?;
+
+        for (index, result) in results.iter_mut().enumerate() {
+            match result {
+                BulkResult::CreateChannel { channel } => {
+                    sqlx::query!(
+                        "INSERT INTO channels (id, guild_id, owner_id, name, channel_type)
+                        VALUES ($1, $2, $3, $4, $5)",
+                        channel.id() as Snowflake<Channel>,
+                        channel.guild_id() as Option<Snowflake<Guild>>,
+                        channel.owner_id() as Option<Snowflake<User>>,
+                        channel.name(),
+                        channel.channel_type(),
+                    )
+                    .execute(self.app.db.instrument(&mut *tx))
+                    .await?;
+                }
+                BulkResult::UpdateChannel { channel } => {
+                    self.update_channel_in(&mut tx, channel)
+                        .await
+                        .map_err(|e| BulkError::at(index, e))?;
+                }
+            }
+        }
+
+        let (created, updated) = results
+            .iter()
+            .partition::<Vec<_>, _>(|result| matches!(result, BulkResult::CreateChannel { .. }));
+        let event = GatewayEvent::ChannelBulkUpdate(ChannelBulkUpdatePayload {
+            guild_id: guild.into(),
+            created: created.into_iter().map(|result| result.channel().clone()).collect(),
+            updated: updated.into_iter().map(|result| result.channel().clone()).collect(),
+        });
+        Outbox::enqueue(
+            self.app.db.instrument(&mut *tx),
+            &self.app.config,
+            &event,
+            self.app.clock.now(),
         )
-        .execute(self.app.db.pool())
         .await?;
 
+        tx.commit().await?;
+        self.app.outbox.notify();
         Ok(())
     }
 
@@ -100,13 +223,144 @@ impl<'a> Ops<'a> {
 
         self.app.s3.remove_all_for_channel(channel_id).await?;
 
+        let mut tx = self.app.db.pool().begin().await?;
+
+        // External search backends are not affected by the cascade, so the messages are removed from them explicitly
+        sqlx::query!(
+            "INSERT INTO search_index_queue (message_id) SELECT id FROM messages WHERE channel_id = $1",
+            channel_id as Snowflake<Channel>
+        )
+        .execute(self.app.db.instrument(&mut *tx))
+        .await?;
+
         sqlx::query!("DELETE FROM channels WHERE id = $1", channel_id as Snowflake<Channel>)
-            .execute(self.app.db.pool())
+            .execute(self.app.db.instrument(&mut *tx))
             .await?;
 
+        tx.commit().await?;
+        self.app.search.notify();
         Ok(())
     }
 
+    /// Fetch all channels that have a message retention period set.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_channels_with_retention(&self) -> Result<Vec<Channel>, sqlx::Error> {
+        let records = sqlx::query_as!(ChannelRecord, "SELECT * FROM channels WHERE retention_days IS NOT NULL")
+            .fetch_all(self.app.db.executor())
+            .await?;
+
+        Ok(records.into_iter().map(Channel::from_record).collect())
+    }
+
+    /// Delete the oldest messages of a channel that were sent before the given message ID, including their attachments.
+    /// The sticky message of the channel is never deleted.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel to delete messages from.
+    /// * `before` - Only delete messages with an ID lower than this.
+    /// * `limit` - The maximum amount of messages to delete.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of messages that matched. If this equals `limit`, more messages may be left to delete.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::MessageBulkRemove`] - To all members who can view the channel, through the outbox
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request to delete the attachments fails.
+    /// * [`AppError::StorageUnavailable`] - If the messages have attachments and S3 is unavailable.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn delete_messages_before(
+        &self,
+        channel: &Channel,
+        before: Snowflake<Message>,
+        limit: i64,
+    ) -> Result<usize, AppError> {
+        // The sticky message of the channel is kept, so it stays visible
+        let ids = sqlx::query_scalar!(
+            "SELECT id FROM messages
+            WHERE channel_id = $1 AND id < $2
+                AND id IS DISTINCT FROM (SELECT sticky_message_id FROM channels WHERE id = $1)
+            ORDER BY id ASC LIMIT $3",
+            channel.id() as Snowflake<Channel>,
+            before as Snowflake<Message>,
+            limit
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        if ids.is_empty() {
+            return Ok(0);
+        }
+
+        let keys: Vec<String> = sqlx::query!(
+            "SELECT id, message_id, filename, key_token FROM attachments WHERE message_id = ANY($1)",
+            &ids
+        )
+        .fetch_all(self.app.db.executor())
+        .await?
+        .into_iter()
+        .map(|r| format_s3_key(channel.id(), r.message_id, r.id, r.key_token.as_deref(), &r.filename))
+        .collect();
+
+        // Attachments are removed first, so they are never left behind without a message referencing them
+        if !keys.is_empty() {
+            self.app.s3.attachments().delete_objects(keys).await?;
+        }
+
+        let mut tx = self.app.db.pool().begin().await?;
+
+        let deleted = sqlx::query_scalar!("DELETE FROM messages WHERE id = ANY($1) RETURNING id", &ids)
+            .fetch_all(self.app.db.instrument(&mut *tx))
+            .await?;
+
+        if !deleted.is_empty() {
+            sqlx::query!(
+                "UPDATE channels
+                SET message_count = GREATEST(message_count - $2, 0),
+                    last_message_id = CASE
+                        WHEN last_message_id = ANY($3) THEN (SELECT MAX(id) FROM messages WHERE channel_id = $1)
+                        ELSE last_message_id
+                    END
+                WHERE id = $1",
+                channel.id() as Snowflake<Channel>,
+                deleted.len() as i64,
+                &deleted
+            )
+            .execute(self.app.db.instrument(&mut *tx))
+            .await?;
+
+            let deleted: Vec<Snowflake<Message>> = deleted.into_iter().map(Snowflake::new).collect();
+            SearchIndex::enqueue(self.app.db.instrument(&mut *tx), &deleted).await?;
+
+            let event = GatewayEvent::MessageBulkRemove(MessageBulkRemovePayload {
+                ids: deleted,
+                channel_id: channel.id(),
+                guild_id: channel.guild_id(),
+            });
+            Outbox::enqueue_to(
+                self.app.db.instrument(&mut *tx),
+                &self.app.config,
+                channel.owner_id(),
+                &event,
+                self.app.clock.now(),
+            )
+            .await?;
+        }
+
+        tx.commit().await?;
+        self.app.outbox.notify();
+        self.app.search.notify();
+        Ok(ids.len())
+    }
+
     /// Fetch messages from this channel.
     ///
     /// ## Arguments
@@ -134,7 +388,7 @@ impl<'a> Ops<'a> {
             // SAFETY: sqlx doesn't understand LEFT JOIN properly, so we have to use unchecked here.
             sqlx::query_as_unchecked!(
                 ExtendedMessageRecord,
-                "SELECT messages.*, users.username, users.display_name, users.avatar_hash, attachments.id AS attachment_id, attachments.filename AS attachment_filename, attachments.content_type AS attachment_content_type
+                "SELECT messages.*, users.username, users.display_name, users.avatar_hash, attachments.id AS attachment_id, attachments.filename AS attachment_filename, attachments.content_type AS attachment_content_type, attachments.width AS attachment_width, attachments.height AS attachment_height, attachments.duration AS attachment_duration, attachments.blurhash AS attachment_blurhash, attachments.scan_status AS attachment_scan_status, attachments.key_token AS attachment_key_token
                 FROM messages
                 LEFT JOIN users ON messages.user_id = users.id
                 LEFT JOIN attachments ON messages.id = attachments.message_id
@@ -143,29 +397,120 @@ impl<'a> Ops<'a> {
                 channel.into() as Snowflake<Channel>,
                 i64::from(limit)
             )
-            .fetch_all(self.app.db.pool())
+            .fetch_all(self.app.db.executor())
             .await?
         } else {
             sqlx::query_as_unchecked!(
                 ExtendedMessageRecord,
-                "SELECT messages.*, users.username, users.display_name, users.avatar_hash, attachments.id AS attachment_id, attachments.filename AS attachment_filename, attachments.content_type AS attachment_content_type
+                "SELECT messages.*, users.username, users.display_name, users.avatar_hash, attachments.id AS attachment_id, attachments.filename AS attachment_filename, attachments.content_type AS attachment_content_type, attachments.width AS attachment_width, attachments.height AS attachment_height, attachments.duration AS attachment_duration, attachments.blurhash AS attachment_blurhash, attachments.scan_status AS attachment_scan_status, attachments.key_token AS attachment_key_token
                 FROM messages
                 LEFT JOIN users ON messages.user_id = users.id
                 LEFT JOIN attachments ON messages.id = attachments.message_id
-                WHERE messages.channel_id = $1 AND messages.id > $2 AND messages.id < $3
+                WHERE messages.channel_id = $1 AND messages.id < $2 AND messages.id > $3
                 ORDER BY messages.id DESC LIMIT $4",
                 channel.into() as Snowflake<Channel>,
                 before.map_or(i64::MAX, Into::into),
                 after.map_or(i64::MIN, Into::into),
                 i64::from(limit)
             )
-            .fetch_all(self.app.db.pool())
+            .fetch_all(self.app.db.executor())
             .await?
         };
         Ok(Message::from_records(&records)?)
     }
 
-    /// Fetches a guild from the database by ID.
+    /// Fetch messages from this channel with only some of their optional fields.
+    /// Users are only joined if the author is selected, and attachments are only queried if they are selected.
+    ///
+    /// ## Arguments
+    ///
+    /// * `fields` - The optional fields to fetch.
+    /// * `limit` - The maximum number of messages to fetch. Defaults to 50, capped at 100.
+    /// * `before` - Fetch messages before this ID.
+    /// * `after` - Fetch messages after this ID.
+    ///
+    /// ## Returns
+    ///
+    /// [`Vec<PartialMessage>`] - The messages fetched, newest first.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Build`] - If a message could not be built.
+    pub async fn fetch_partial_messages_from(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        fields: MessageFields,
+        limit: Option<u32>,
+        before: Option<Snowflake<Message>>,
+        after: Option<Snowflake<Message>>,
+    ) -> Result<Vec<PartialMessage>, AppError> {
+        let limit = i64::from(limit.unwrap_or(50).min(100));
+        let channel: Snowflake<Channel> = channel.into();
+        let before = before.map_or(i64::MAX, Into::into);
+        let after = after.map_or(i64::MIN, Into::into);
+
+        let records = if fields.author {
+            // SAFETY: sqlx doesn't understand LEFT JOIN properly, so we have to use unchecked here.
+            sqlx::query_as_unchecked!(
+                PartialMessageRecord,
+                "SELECT messages.id, messages.channel_id, messages.content, messages.mentions, messages.channel_mentions,
+                messages.mention_channels, messages.crosspost,
+                CASE WHEN $5 THEN messages.embeds END AS embeds,
+                messages.user_id, users.username, users.display_name, users.avatar_hash
+                FROM messages
+                LEFT JOIN users ON messages.user_id = users.id
+                WHERE messages.channel_id = $1 AND messages.id < $2 AND messages.id > $3
+                ORDER BY messages.id DESC LIMIT $4",
+                channel,
+                before,
+                after,
+                limit,
+                fields.embeds
+            )
+            .fetch_all(self.app.db.executor())
+            .await?
+        } else {
+            sqlx::query_as_unchecked!(
+                PartialMessageRecord,
+                "SELECT id, channel_id, content, mentions, channel_mentions, mention_channels, crosspost,
+                CASE WHEN $5 THEN embeds END AS embeds,
+                user_id, NULL::TEXT AS username, NULL::TEXT AS display_name, NULL::TEXT AS avatar_hash
+                FROM messages
+                WHERE channel_id = $1 AND id < $2 AND id > $3
+                ORDER BY id DESC LIMIT $4",
+                channel,
+                before,
+                after,
+                limit,
+                fields.embeds
+            )
+            .fetch_all(self.app.db.executor())
+            .await?
+        };
+
+        let mut attachments: HashMap<Snowflake<Message>, Vec<Attachment>> = HashMap::new();
+        if fields.attachments && !records.is_empty() {
+            let ids: Vec<Snowflake<Message>> = records.iter().map(|r| r.id.into()).collect();
+            for attachment in PartialAttachment::fetch_all_for(self.app, &ids).await? {
+                attachments
+                    .entry(attachment.message_id())
+                    .or_default()
+                    .push(Attachment::Partial(attachment));
+            }
+        }
+
+        records
+            .into_iter()
+            .map(|record| {
+                let message_attachments = attachments.remove(&record.id.into()).unwrap_or_default();
+                PartialMessage::from_record(record, fields, message_attachments)
+            })
+            .collect::<Result<_, _>>()
+            .map_err(Into::into)
+    }
+
+    /// Fetches a guild from the database by ID. Deleted guilds are not returned.
     ///
     /// ## Arguments
     ///
@@ -173,10 +518,11 @@ impl<'a> Ops<'a> {
     pub async fn fetch_guild(&self, guild: impl Into<Snowflake<Guild>>) -> Option<Guild> {
         let record = sqlx::query_as!(
             GuildRecord,
-            "SELECT id, name, owner_id, avatar_hash FROM guilds WHERE id = $1",
+            "SELECT id, name, owner_id, avatar_hash, min_account_age, invite_only, welcome_message, raid_mode_until, locale, timezone, version
+            FROM guilds WHERE id = $1 AND deleted_at IS NULL",
             guild.into() as Snowflake<Guild>,
         )
-        .fetch_optional(self.app.db.pool())
+        .fetch_optional(self.app.db.executor())
         .await
         .ok()??;
 
@@ -210,17 +556,96 @@ impl<'a> Ops<'a> {
             WHERE members.guild_id = $1",
             guild.into() as Snowflake<Guild>
         )
-        .fetch_all(self.app.db.pool())
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        records
+            .into_iter()
+            .map(|record| Member::from_extended_record(record, self.app.clock.now()))
+            .collect::<Result<_, _>>()
+            .map_err(Into::into)
+    }
+
+    /// Fetch a page of the members of a guild, ordered by their user ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild.
+    /// * `query` - Only fetch members whose username, display name or nickname starts with this, case-insensitively.
+    /// * `after` - Only fetch members whose user ID is greater than this.
+    /// * `limit` - The maximum amount of members to fetch.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Build`] - If a member could not be built.
+    pub async fn fetch_members_page(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        query: Option<&str>,
+        after: Snowflake<User>,
+        limit: i64,
+    ) -> Result<Vec<Member>, AppError> {
+        let records = sqlx::query_as!(
+            ExtendedMemberRecord,
+            "SELECT members.*, users.username, users.display_name, users.avatar_hash, users.last_presence 
+            FROM members
+            INNER JOIN users ON users.id = members.user_id
+            WHERE members.guild_id = $1 AND members.user_id > $2
+            AND ($3::TEXT IS NULL
+                OR starts_with(LOWER(users.username), LOWER($3))
+                OR starts_with(LOWER(users.display_name), LOWER($3))
+                OR starts_with(LOWER(members.nickname), LOWER($3)))
+            ORDER BY members.user_id
+            LIMIT $4",
+            guild.into() as Snowflake<Guild>,
+            after as Snowflake<User>,
+            query,
+            limit,
+        )
+        .fetch_all(self.app.db.executor())
         .await?;
 
         records
             .into_iter()
-            .map(Member::from_extended_record)
+            .map(|record| Member::from_extended_record(record, self.app.clock.now()))
             .collect::<Result<_, _>>()
             .map_err(Into::into)
     }
 
-    /// Fetch all channels that are in the guild.
+    /// Fetch the amount of members of a guild whose username, display name or nickname starts with a query.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild.
+    /// * `query` - The query to match, case-insensitively. If `None`, all members are counted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_matching_member_count(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        query: Option<&str>,
+    ) -> Result<usize, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            "SELECT COUNT(*) AS \"count!\" FROM members
+            INNER JOIN users ON users.id = members.user_id
+            WHERE members.guild_id = $1
+            AND ($2::TEXT IS NULL
+                OR starts_with(LOWER(users.username), LOWER($2))
+                OR starts_with(LOWER(users.display_name), LOWER($2))
+                OR starts_with(LOWER(members.nickname), LOWER($2)))",
+            guild.into() as Snowflake<Guild>,
+            query,
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+
+        Ok(usize::try_from(count).unwrap_or_default())
+    }
+
+    /// Fetch all channels that are in the guild, except for archived channels.
     ///
     /// ## Errors
     ///
@@ -228,16 +653,42 @@ impl<'a> Ops<'a> {
     pub async fn fetch_channels_for(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<Channel>, sqlx::Error> {
         let records = sqlx::query_as!(
             ChannelRecord,
-            "SELECT * FROM channels WHERE guild_id = $1",
+            "SELECT * FROM channels WHERE guild_id = $1 AND NOT archived",
+            guild.into() as Snowflake<Guild>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(Channel::from_record).collect())
+    }
+
+    /// Fetch all archived channels of the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_archived_channels(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+    ) -> Result<Vec<Channel>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            ChannelRecord,
+            "SELECT * FROM channels WHERE guild_id = $1 AND archived ORDER BY id",
             guild.into() as Snowflake<Guild>
         )
-        .fetch_all(self.app.db.pool())
+        .fetch_all(self.app.db.executor())
         .await?;
 
         Ok(records.into_iter().map(Channel::from_record).collect())
     }
 
-    /// Adds a member to the guild. If the member already exists, does nothing.
+    /// Adds a member to the guild. Any pending invite of the user to the guild is removed.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to add the member to.
+    /// * `user` - The user to add.
+    /// * `guest` - The access of the member if they join as a guest, `None` for full members.
     ///
     /// ## Errors
     ///
@@ -246,45 +697,156 @@ impl<'a> Ops<'a> {
         &self,
         guild: impl Into<Snowflake<Guild>>,
         user: impl Into<Snowflake<User>>,
+        guest: Option<&GuestAccess>,
+    ) -> Result<Member, sqlx::Error> {
+        let user_id = user.into();
+
+        let user = self.fetch_user(user_id).await.ok_or(sqlx::Error::RowNotFound)?;
+        let guest_channel_ids: Option<Vec<i64>> =
+            guest.map(|guest| guest.channel_ids().iter().copied().map(i64::from).collect());
+
+        let record = sqlx::query_as!(
+            MemberRecord,
+            "INSERT INTO members (user_id, guild_id, joined_at, guest_until, guest_channel_ids)
+            VALUES ($1, $2, $3, $4, $5) RETURNING *",
+            user_id as Snowflake<User>,
+            guild.into() as Snowflake<Guild>,
+            self.app.clock.timestamp(),
+            guest.map(GuestAccess::expires_at),
+            guest_channel_ids.as_deref(),
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+
+        self.record_membership_change(record.guild_id, true).await?;
+        self.delete_guild_invite(record.guild_id, user_id).await?;
+
+        Ok(Member::from_record(user, record, self.app.clock.now()))
+    }
+
+    /// Change the guest access of an existing member, renewing it or making the member a full member.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild of the member.
+    /// * `user` - The user the member represents.
+    /// * `guest` - The new access of the member, `None` to make them a full member.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails, or the member does not exist.
+    pub async fn update_guest_access(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        guest: Option<&GuestAccess>,
     ) -> Result<Member, sqlx::Error> {
         let user_id = user.into();
 
         let user = self.fetch_user(user_id).await.ok_or(sqlx::Error::RowNotFound)?;
+        let guest_channel_ids: Option<Vec<i64>> =
+            guest.map(|guest| guest.channel_ids().iter().copied().map(i64::from).collect());
 
         let record = sqlx::query_as!(
             MemberRecord,
-            "INSERT INTO members (user_id, guild_id, joined_at)
-            VALUES ($1, $2, $3) RETURNING *",
+            "UPDATE members SET guest_until = $3, guest_channel_ids = $4
+            WHERE user_id = $1 AND guild_id = $2 RETURNING *",
             user_id as Snowflake<User>,
             guild.into() as Snowflake<Guild>,
-            Utc::now().timestamp(),
+            guest.map(GuestAccess::expires_at),
+            guest_channel_ids.as_deref(),
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+
+        Ok(Member::from_record(user, record, self.app.clock.now()))
+    }
+
+    /// Remove all guests whose membership expired. Guests of deleted guilds are removed once the guild is restored.
+    ///
+    /// ## Returns
+    ///
+    /// The users that were removed, and the guilds they were removed from.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_expired_guests(&self) -> Result<Vec<(Snowflake<User>, Snowflake<Guild>)>, sqlx::Error> {
+        let removed = sqlx::query!(
+            "DELETE FROM members
+            WHERE guest_until <= $1 AND guild_id NOT IN (SELECT id FROM guilds WHERE deleted_at IS NOT NULL)
+            RETURNING user_id, guild_id",
+            self.app.clock.timestamp(),
         )
-        .fetch_one(self.app.db.pool())
+        .fetch_all(self.app.db.executor())
         .await?;
-        Ok(Member::from_record(user, record))
+
+        let mut expired = Vec::with_capacity(removed.len());
+        for record in removed {
+            self.record_membership_change(record.guild_id, false).await?;
+            expired.push((record.user_id.into(), record.guild_id.into()));
+        }
+        Ok(expired)
     }
 
     /// Removes a member from a guild.
     ///
     /// ## Errors
     ///
-    /// * [`RESTError::App`] - If the database query fails.
-    /// * [`RESTError::Forbidden`] - If the member is the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Forbidden`] - If the member is the owner of the guild.
     ///
     /// Note: If the member is the owner of the guild, this will fail.
-    pub async fn delete_member(&self, guild: &Guild, user: impl Into<Snowflake<User>>) -> Result<(), RESTError> {
+    pub async fn delete_member(&self, guild: &Guild, user: impl Into<Snowflake<User>>) -> Result<(), AppError> {
         let user_id = user.into();
         if guild.owner_id() == user_id {
-            return Err(RESTError::Forbidden("Cannot remove owner from guild".into()));
+            return Err(AppError::Forbidden("Cannot remove owner from guild".into()));
         }
 
-        sqlx::query!(
+        let result = sqlx::query!(
             "DELETE FROM members WHERE user_id = $1 AND guild_id = $2",
             user_id as Snowflake<User>,
             guild.id() as Snowflake<Guild>,
         )
-        .execute(self.app.db.pool())
+        .execute(self.app.db.executor())
+        .await?;
+
+        if result.rows_affected() > 0 {
+            self.record_membership_change(guild.id(), false).await?;
+        }
+        Ok(())
+    }
+
+    /// Count a member joining or leaving a guild towards today's guild statistics.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the member joined or left.
+    /// * `joined` - Whether the member joined, otherwise they left.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    async fn record_membership_change(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        joined: bool,
+    ) -> Result<(), sqlx::Error> {
+        let (joins, leaves) = if joined { (1, 0) } else { (0, 1) };
+
+        sqlx::query!(
+            "INSERT INTO guild_daily_stats (guild_id, day, joins, leaves)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (guild_id, day) DO UPDATE
+            SET joins = guild_daily_stats.joins + EXCLUDED.joins, leaves = guild_daily_stats.leaves + EXCLUDED.leaves",
+            guild.into() as Snowflake<Guild>,
+            analytics::day_start(self.app.clock.now()),
+            joins,
+            leaves
+        )
+        .execute(self.app.db.executor())
         .await?;
+
         Ok(())
     }
 
@@ -313,41 +875,115 @@ impl<'a> Ops<'a> {
             "SELECT members.*, users.username, users.display_name, users.avatar_hash, users.last_presence 
             FROM members
             INNER JOIN users ON users.id = members.user_id
+            INNER JOIN guilds ON guilds.id = members.guild_id AND guilds.deleted_at IS NULL
             WHERE members.user_id = $1 AND members.guild_id = $2",
             user.into() as Snowflake<User>,
             guild.into() as Snowflake<Guild>,
         )
-        .fetch_optional(self.app.db.pool())
+        .fetch_optional(self.app.db.executor())
         .await?;
 
-        record.map(Member::from_extended_record).transpose().map_err(Into::into)
+        record
+            .map(|record| Member::from_extended_record(record, self.app.clock.now()))
+            .transpose()
+            .map_err(Into::into)
     }
 
     /// Commit the member to the database.
     ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::MemberUpdate`] - Once the member is committed
+    ///
     /// ## Errors
     ///
-    /// * [`sqlx::Error`] - If the database query fails.
-    pub async fn update_member(&self, member: &Member) -> Result<(), sqlx::Error> {
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update_member(&self, member: &Member) -> Result<(), AppError> {
+        let mut tx = self.app.db.pool().begin().await?;
+
         sqlx::query!(
-            "INSERT INTO members (user_id, guild_id, nickname, joined_at)
-            VALUES ($1, $2, $3, $4)
+            "INSERT INTO members (user_id, guild_id, nickname, joined_at, timeout_until)
+            VALUES ($1, $2, $3, $4, $5)
             ON CONFLICT (user_id, guild_id) DO UPDATE
-            SET nickname = $3, joined_at = $4",
+            SET nickname = $3, joined_at = $4, timeout_until = $5",
             member.user().id() as Snowflake<User>,
             member.guild_id() as Snowflake<Guild>,
             member.nickname().as_ref(),
-            member.joined_at()
+            member.joined_at(),
+            member.timeout_until(),
         )
-        .execute(self.app.db.pool())
+        .execute(self.app.db.instrument(&mut *tx))
         .await?;
 
         //self.app.ops().update_user(member.user()).await?;
 
+        Outbox::enqueue(
+            self.app.db.instrument(&mut *tx),
+            &self.app.config,
+            &GatewayEvent::MemberUpdate(member.clone()),
+            self.app.clock.now(),
+        )
+        .await?;
+        tx.commit().await?;
+        self.app.outbox.notify();
+
         Ok(())
     }
 
-    /// Create a new guild
+    /// Clear all member timeouts that have expired.
+    ///
+    /// ## Returns
+    ///
+    /// The members whose timeouts were cleared.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::MemberUpdate`] - For every member whose timeout was cleared, once committed
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Build`] - If one of the members could not be built.
+    pub async fn clear_expired_timeouts(&self) -> Result<Vec<Member>, AppError> {
+        let mut tx = self.app.db.pool().begin().await?;
+
+        let records = sqlx::query_as!(
+            ExtendedMemberRecord,
+            "WITH expired AS (
+                UPDATE members SET timeout_until = NULL
+                WHERE timeout_until <= $1 AND guild_id NOT IN (SELECT id FROM guilds WHERE deleted_at IS NOT NULL)
+                RETURNING *
+            )
+            SELECT expired.*, users.username, users.display_name, users.avatar_hash, users.last_presence
+            FROM expired
+            INNER JOIN users ON users.id = expired.user_id",
+            self.app.clock.timestamp(),
+        )
+        .fetch_all(self.app.db.instrument(&mut *tx))
+        .await?;
+
+        let members = records
+            .into_iter()
+            .map(|record| Member::from_extended_record(record, self.app.clock.now()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for member in &members {
+            Outbox::enqueue(
+                self.app.db.instrument(&mut *tx),
+                &self.app.config,
+                &GatewayEvent::MemberUpdate(member.clone()),
+                self.app.clock.now(),
+            )
+            .await?;
+        }
+
+        tx.commit().await?;
+        self.app.outbox.notify();
+
+        Ok(members)
+    }
+
+    /// Create a new guild
     ///
     /// ## Errors
     ///
@@ -373,10 +1009,10 @@ impl<'a> Ops<'a> {
             guild.name(),
             guild.owner_id() as Snowflake<User>,
         )
-        .execute(self.app.db.pool())
+        .execute(self.app.db.executor())
         .await?;
 
-        let member = self.create_member(&guild, guild.owner_id()).await?;
+        let member = self.create_member(&guild, guild.owner_id(), None).await?;
 
         let general = TextChannel::new(guild.id().cast(), &guild, "general".to_string()).into();
         self.app.ops().create_channel(&general).await?;
@@ -384,9 +1020,11 @@ impl<'a> Ops<'a> {
     }
 
     /// Commits the current state of this guild object to the database.
+    /// The update only succeeds if the guild was not updated since `old_guild` was fetched.
     ///
     /// ## Errors
     ///
+    /// * [`AppError::Conflict`] - If the guild was updated concurrently, or the payload expects a different version.
     /// * [`sqlx::Error`] - If the database query fails.
     pub async fn update_guild(&self, payload: UpdateGuild, old_guild: &Guild) -> Result<Guild, AppError> {
         let mut guild = old_guild.clone();
@@ -409,19 +1047,28 @@ impl<'a> Ops<'a> {
         let record = sqlx::query_as!(
             GuildRecord,
             "UPDATE guilds
-            SET name = $2, owner_id = $3, avatar_hash = $4
-            WHERE id = $1 RETURNING *",
+            SET name = $2, owner_id = $3, avatar_hash = $4, min_account_age = $5, invite_only = $6, welcome_message = $7,
+                locale = $9, timezone = $10, version = version + 1
+            WHERE id = $1 AND version = $8
+            RETURNING id, name, owner_id, avatar_hash, min_account_age, invite_only, welcome_message, raid_mode_until, locale, timezone, version",
             guild.id() as Snowflake<Guild>,
             guild.name(),
             guild.owner_id() as Snowflake<User>,
             guild.avatar().map(AvatarLike::avatar_hash),
+            guild.join_requirements().min_account_age(),
+            guild.join_requirements().invite_only(),
+            guild.welcome_message().content(),
+            old_guild.version(),
+            guild.locale().as_str(),
+            guild.timezone().name(),
         )
-        .fetch_one(self.app.db.pool())
-        .await?;
+        .fetch_optional(self.app.db.executor())
+        .await?
+        .ok_or_else(|| AppError::Conflict("The guild was updated concurrently".into()))?;
         Ok(Guild::from_record(record))
     }
 
-    /// Deletes the guild.
+    /// Deletes the guild permanently, along with all of its attachments.
     ///
     /// ## Errors
     ///
@@ -432,12 +1079,114 @@ impl<'a> Ops<'a> {
 
         self.app.s3.remove_all_for_guild(guild_id).await?;
 
+        let mut tx = self.app.db.pool().begin().await?;
+
+        // External search backends are not affected by the cascade, so the messages are removed from them explicitly
+        sqlx::query!(
+            "INSERT INTO search_index_queue (message_id)
+            SELECT messages.id FROM messages JOIN channels ON messages.channel_id = channels.id
+            WHERE channels.guild_id = $1",
+            guild_id as Snowflake<Guild>
+        )
+        .execute(self.app.db.instrument(&mut *tx))
+        .await?;
+
         sqlx::query!("DELETE FROM guilds WHERE id = $1", guild_id as Snowflake<Guild>)
-            .execute(self.app.db.pool())
+            .execute(self.app.db.instrument(&mut *tx))
             .await?;
+
+        tx.commit().await?;
+        self.app.search.notify();
         Ok(())
     }
 
+    /// Mark the guild as deleted. It stays hidden until it is restored or purged by [`Ops::delete_guild`].
+    ///
+    /// ## Returns
+    ///
+    /// Whether the guild was marked as deleted, `false` if it does not exist or was already deleted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn soft_delete_guild(&self, guild: impl Into<Snowflake<Guild>>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE guilds SET deleted_at = $2 WHERE id = $1 AND deleted_at IS NULL",
+            guild.into() as Snowflake<Guild>,
+            self.app.clock.timestamp(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Fetch a deleted guild that can still be restored.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild to fetch.
+    /// * `deleted_after` - UNIX timestamp, guilds deleted before it can no longer be restored.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_deleted_guild(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        deleted_after: i64,
+    ) -> Result<Option<Guild>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            GuildRecord,
+            "SELECT id, name, owner_id, avatar_hash, min_account_age, invite_only, welcome_message, raid_mode_until, locale, timezone, version
+            FROM guilds WHERE id = $1 AND deleted_at > $2",
+            guild.into() as Snowflake<Guild>,
+            deleted_after,
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(Guild::from_record))
+    }
+
+    /// Restore a deleted guild, unless it was deleted before `deleted_after`.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the guild was restored.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn restore_guild(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        deleted_after: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE guilds SET deleted_at = NULL WHERE id = $1 AND deleted_at > $2",
+            guild.into() as Snowflake<Guild>,
+            deleted_after,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Fetch the IDs of all guilds deleted at or before the given time.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_guilds_deleted_before(&self, deleted_before: i64) -> Result<Vec<Snowflake<Guild>>, sqlx::Error> {
+        let records = sqlx::query!("SELECT id FROM guilds WHERE deleted_at <= $1", deleted_before,)
+            .fetch_all(self.app.db.executor())
+            .await?;
+
+        Ok(records.into_iter().map(|r| r.id.into()).collect())
+    }
+
     /// Retrieve a message and fetch its author from the database in one query.
     /// Attachment contents will not be retrieved from S3.
     ///
@@ -457,46 +1206,202 @@ impl<'a> Ops<'a> {
         // sqlx cannot handle LEFT JOIN properly, so we have to use unchecked here.
         let records = sqlx::query_as_unchecked!(
             ExtendedMessageRecord,
-            "SELECT messages.*, users.username, users.display_name, users.avatar_hash, attachments.id AS attachment_id, attachments.filename AS attachment_filename, attachments.content_type AS attachment_content_type
+            "SELECT messages.*, users.username, users.display_name, users.avatar_hash, attachments.id AS attachment_id, attachments.filename AS attachment_filename, attachments.content_type AS attachment_content_type, attachments.width AS attachment_width, attachments.height AS attachment_height, attachments.duration AS attachment_duration, attachments.blurhash AS attachment_blurhash, attachments.scan_status AS attachment_scan_status, attachments.key_token AS attachment_key_token
             FROM messages
             LEFT JOIN users ON messages.user_id = users.id
             LEFT JOIN attachments ON messages.id = attachments.message_id
             WHERE messages.id = $1",
             message.into() as Snowflake<Message>
         )
-        .fetch_all(self.app.db.pool())
+        .fetch_all(self.app.db.executor())
         .await?;
 
         Ok(Message::from_records(&records)?.pop())
     }
 
+    /// Fetch messages by their IDs, newest messages first. Messages that do not exist are skipped.
+    ///
+    /// ## Arguments
+    ///
+    /// * `messages` - The IDs of the messages to fetch.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Build`] - If a message is malformed.
+    pub async fn fetch_messages(&self, messages: &[Snowflake<Message>]) -> Result<Vec<Message>, AppError> {
+        // sqlx cannot handle LEFT JOIN properly, so we have to use unchecked here.
+        let records = sqlx::query_as_unchecked!(
+            ExtendedMessageRecord,
+            "SELECT messages.*, users.username, users.display_name, users.avatar_hash, attachments.id AS attachment_id, attachments.filename AS attachment_filename, attachments.content_type AS attachment_content_type, attachments.width AS attachment_width, attachments.height AS attachment_height, attachments.duration AS attachment_duration, attachments.blurhash AS attachment_blurhash, attachments.scan_status AS attachment_scan_status, attachments.key_token AS attachment_key_token
+            FROM messages
+            LEFT JOIN users ON messages.user_id = users.id
+            LEFT JOIN attachments ON messages.id = attachments.message_id
+            WHERE messages.id = ANY($1)
+            ORDER BY messages.id DESC",
+            messages as &[Snowflake<Message>]
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(Message::from_records(&records)?)
+    }
+
+    /// Fetch the changes to the messages of a channel after the given ID, oldest first.
+    /// Changes are returned with the current state of their message.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to fetch changes from.
+    /// * `since` - Only return changes after this ID.
+    /// * `limit` - The maximum amount of changes to return.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Build`] - If a message is malformed.
+    pub async fn fetch_message_changes(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        since: Snowflake<MessageChange>,
+        limit: u32,
+    ) -> Result<Vec<MessageChange>, AppError> {
+        let records = sqlx::query_as!(
+            MessageChangeRecord,
+            "SELECT id, message_id, kind FROM message_changes
+            WHERE channel_id = $1 AND id > $2
+            ORDER BY id ASC LIMIT $3",
+            channel.into() as Snowflake<Channel>,
+            since as Snowflake<MessageChange>,
+            i64::from(limit)
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        let ids: Vec<Snowflake<Message>> = records.iter().map(|r| Snowflake::new(r.message_id)).collect();
+        let messages: HashMap<Snowflake<Message>, Message> = self
+            .fetch_messages(&ids)
+            .await?
+            .into_iter()
+            .map(|message| (message.id(), message))
+            .collect();
+
+        Ok(records
+            .iter()
+            .map(|r| MessageChange::from_record(r, messages.get(&Snowflake::new(r.message_id)).cloned()))
+            .collect())
+    }
+
+    /// Delete changes to messages that are older than the retention period.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of deleted changes.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_old_message_changes(&self) -> Result<u64, sqlx::Error> {
+        let oldest =
+            Snowflake::<MessageChange>::from_timestamp((self.app.clock.timestamp() - MESSAGE_CHANGE_RETENTION) * 1000);
+        let result = sqlx::query!(
+            "DELETE FROM message_changes WHERE id < $1",
+            oldest as Snowflake<MessageChange>
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
     /// Commit this message to the database. Uploads all attachments to S3.
+    /// If the message is new, the last message ID and message count of its channel are updated.
+    /// The message is queued to be indexed for search. New messages in announcement channels are queued to be
+    /// crossposted to the channels following them.
     /// It is highly recommended to call [`Message::strip_attachment_contents`] after calling
     /// this method to remove the attachment contents from memory.
     ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::MessageCreate`] - If the message is new, once it is committed
+    ///
     /// ## Errors
     ///
     /// * [`AppError::S3`] - If the S3 request to upload one of the attachments fails.
     /// * [`AppError::Database`] - If the database request fails.
     pub async fn update_message(&self, message: &Message) -> Result<(), AppError> {
-        sqlx::query!(
-            "INSERT INTO messages (id, user_id, channel_id, content)
-            VALUES ($1, $2, $3, $4)
-            ON CONFLICT (id) DO UPDATE
-            SET user_id = $2, channel_id = $3, content = $4",
+        let embeds = if message.embeds().is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(message.embeds())?)
+        };
+        let mention_channels = if message.mention_channels().is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(message.mention_channels())?)
+        };
+        let crosspost = message.crosspost().map(serde_json::to_string).transpose()?;
+        let mut tx = self.app.db.pool().begin().await?;
+
+        // The channel metadata is only updated if the message was newly created
+        let created = sqlx::query!(
+            "WITH upsert AS (
+                INSERT INTO messages (id, user_id, channel_id, content, mentions, channel_mentions, embeds, mention_channels, crosspost)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                ON CONFLICT (id) DO UPDATE
+                SET user_id = $2, channel_id = $3, content = $4, mentions = $5, channel_mentions = $6, embeds = $7,
+                mention_channels = $8, crosspost = $9
+                RETURNING (xmax = 0) AS created
+            )
+            UPDATE channels
+            SET last_message_id = GREATEST(last_message_id, $1), message_count = message_count + 1
+            WHERE id = $3 AND (SELECT created FROM upsert)
+            RETURNING owner_id, announcement",
             message.id() as Snowflake<Message>,
             message.author().map(UserLike::id) as Option<Snowflake<User>>,
             message.channel_id() as Snowflake<Channel>,
             message.content(),
+            message.mentions() as &[Snowflake<User>],
+            message.channel_mentions() as &[Snowflake<Channel>],
+            embeds,
+            mention_channels,
+            crosspost,
         )
-        .execute(self.app.db.pool())
+        .fetch_optional(self.app.db.instrument(&mut *tx))
         .await?;
 
         for attachment in message.attachments() {
             if let Attachment::Full(f) = attachment {
-                self.create_attachment(f).await?;
+                self.create_attachment(&mut tx, f).await?;
+            }
+        }
+
+        let mut crossposted = false;
+        if let Some(channel) = created {
+            let event = GatewayEvent::MessageCreate(message.clone().strip_attachment_contents());
+            Outbox::enqueue_to(
+                self.app.db.instrument(&mut *tx),
+                &self.app.config,
+                channel.owner_id.map(Snowflake::new),
+                &event,
+                self.app.clock.now(),
+            )
+            .await?;
+
+            if channel.announcement {
+                crossposted =
+                    Crossposter::enqueue(self.app.db.instrument(&mut *tx), message, self.app.clock.timestamp()).await?;
             }
         }
+
+        SearchIndex::enqueue(self.app.db.instrument(&mut *tx), &[message.id()]).await?;
+
+        tx.commit().await?;
+        self.app.outbox.notify();
+        self.app.search.notify();
+        if crossposted {
+            self.app.crossposts.notify();
+        }
         Ok(())
     }
 
@@ -517,130 +1422,810 @@ impl<'a> Ops<'a> {
             WHERE id = $1",
             user.into() as Snowflake<User>
         )
-        .fetch_optional(self.app.db.pool())
+        .fetch_optional(self.app.db.executor())
         .await
         .ok()??;
 
         Some(User::from_record(row))
     }
 
-    /// Fetch the presence of a user.
+    /// Fetch users by their IDs. Users that do not exist are skipped.
     ///
     /// ## Arguments
     ///
-    /// * `user` - The ID of the user to retrieve the presence of.
+    /// * `users` - The IDs of the users to fetch.
     ///
-    /// ## Returns
+    /// ## Errors
     ///
-    /// The presence of the user if found, otherwise `None`.
-    pub async fn fetch_presence(&self, user: impl Into<Snowflake<User>>) -> Option<Presence> {
-        let row = sqlx::query!(
-            "SELECT last_presence
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_users(&self, users: &[Snowflake<User>]) -> Result<Vec<User>, sqlx::Error> {
+        let rows = sqlx::query_as!(
+            UserRecord,
+            "SELECT id, username, display_name, avatar_hash, last_presence
             FROM users
-            WHERE id = $1",
-            user.into() as Snowflake<User>
+            WHERE id = ANY($1)",
+            users as &[Snowflake<User>]
         )
-        .fetch_optional(self.app.db.pool())
-        .await
-        .ok()??;
+        .fetch_all(self.app.db.executor())
+        .await?;
 
-        Some(Presence::from(row.last_presence))
+        Ok(rows.into_iter().map(User::from_record).collect())
     }
 
-    /// Retrieve a user from the database by their username.
+    /// Fetch the flags the server operator set on a user.
     ///
     /// ## Arguments
     ///
-    /// * `username` - The username of the user to retrieve.
+    /// * `user` - The ID of the user to retrieve the flags of.
     ///
     /// ## Returns
     ///
-    /// The user if found, otherwise `None`.
-    pub async fn fetch_user_by_username(&self, username: &str) -> Option<User> {
-        let row = sqlx::query_as!(
-            UserRecord,
-            "SELECT id, username, display_name, avatar_hash, last_presence
-            FROM users
-            WHERE username = $1
-            LIMIT 1",
-            username
-        )
-        .fetch_optional(self.app.db.pool())
-        .await
-        .ok()??;
-
-        Some(User::from_record(row))
-    }
-
-    /// Fetch all guilds that this user is a member of.
+    /// The flags of the user, or no flags if the user does not exist.
     ///
     /// ## Errors
     ///
     /// * [`sqlx::Error`] - If the database query fails.
-    pub async fn fetch_guilds_for(&self, user: impl Into<Snowflake<User>>) -> Result<Vec<Guild>, sqlx::Error> {
-        let records = sqlx::query_as!(
-            GuildRecord,
-            "SELECT guilds.id, guilds.name, guilds.owner_id, guilds.avatar_hash
-            FROM guilds
-            INNER JOIN members ON members.guild_id = guilds.id
-            WHERE members.user_id = $1",
-            user.into() as Snowflake<User>
-        )
-        .fetch_all(self.app.db.pool())
-        .await?;
+    pub async fn fetch_user_flags(&self, user: impl Into<Snowflake<User>>) -> Result<UserFlags, sqlx::Error> {
+        let flags = sqlx::query_scalar!("SELECT flags FROM users WHERE id = $1", user.into() as Snowflake<User>)
+            .fetch_optional(self.app.db.executor())
+            .await?;
 
-        Ok(records.into_iter().map(Guild::from_record).collect())
+        Ok(flags.map(UserFlags::from_bits_retain).unwrap_or_default())
     }
 
-    /// Fetch all guild IDs that this user is a member of.
-    /// This is a more efficient version of [`Ops::fetch_guilds_for`] if you only need the IDs.
+    /// Replace the flags the server operator set on a user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to set the flags of.
+    /// * `flags` - The new flags of the user.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the user exists.
     ///
     /// ## Errors
     ///
     /// * [`sqlx::Error`] - If the database query fails.
-    pub async fn fetch_guild_ids_for(
+    pub async fn update_user_flags(
         &self,
         user: impl Into<Snowflake<User>>,
-    ) -> Result<Vec<Snowflake<Guild>>, sqlx::Error> {
-        let records = sqlx::query!(
-            "SELECT guild_id
-            FROM members
-            WHERE user_id = $1",
-            user.into() as Snowflake<User>
+        flags: UserFlags,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE users SET flags = $2 WHERE id = $1",
+            user.into() as Snowflake<User>,
+            flags.bits()
         )
-        .fetch_all(self.app.db.pool())
+        .execute(self.app.db.executor())
         .await?;
 
-        Ok(records.into_iter().map(|r| r.guild_id.into()).collect())
+        Ok(result.rows_affected() > 0)
     }
 
-    /// Create a new user in the database.
+    /// Permanently delete a user, along with their avatar, their saved messages and the guilds they own.
+    /// Messages the user sent to other guilds are kept without an author.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to delete.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::GuildRemove`] - To all members of the guilds owned by the user
+    /// * [`GatewayEvent::MemberRemove`] - To all members of the other guilds the user was a member of
     ///
     /// ## Errors
     ///
-    /// * [`sqlx::Error`] - If the database query fails.
-    pub async fn create_user(&self, payload: CreateUser) -> Result<User, sqlx::Error> {
-        let gen_id = Snowflake::<User>::gen_new(&self.app.config);
+    /// * [`AppError::S3`] - If the avatar or the attachments of the user could not be deleted.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn delete_user(&self, user: &User) -> Result<(), AppError> {
+        let owned_guilds: Vec<Snowflake<Guild>> = sqlx::query_scalar!(
+            "SELECT id FROM guilds WHERE owner_id = $1",
+            user.id() as Snowflake<User>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?
+        .into_iter()
+        .map(Snowflake::from)
+        .collect();
+        // Objects in S3 are not affected by the cascade, so they are removed before the user.
+        // Channels without attachments are skipped, orphans are left to the prune-attachments command.
+        let channels_with_attachments = sqlx::query_scalar!(
+            "SELECT DISTINCT attachments.channel_id FROM attachments JOIN channels ON attachments.channel_id = channels.id
+            WHERE channels.guild_id = ANY($1) OR channels.owner_id = $2",
+            &owned_guilds as &[Snowflake<Guild>],
+            user.id() as Snowflake<User>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+        for channel in channels_with_attachments {
+            self.app.s3.remove_all_for_channel(channel).await?;
+        }
+        if let Some(avatar) = user.avatar() {
+            avatar.delete(&self.app.s3).await?;
+        }
 
-        sqlx::query_as!(
-            UserRecord,
-            "INSERT INTO users (id, username)
-            VALUES ($1, $2) RETURNING *",
-            gen_id as Snowflake<User>,
-            payload.username,
+        let mut tx = self.app.db.pool().begin().await?;
+
+        // External search backends are not affected by the cascade either
+        sqlx::query!(
+            "INSERT INTO search_index_queue (message_id)
+            SELECT messages.id FROM messages JOIN channels ON messages.channel_id = channels.id
+            WHERE channels.guild_id = ANY($1) OR channels.owner_id = $2",
+            &owned_guilds as &[Snowflake<Guild>],
+            user.id() as Snowflake<User>
         )
-        .fetch_one(self.app.db.pool())
-        .await
-        .map(User::from_record)
+        .execute(self.app.db.instrument(&mut *tx))
+        .await?;
+
+        let now = self.app.clock.now();
+        for guild in &owned_guilds {
+            if let Some(guild) = self.fetch_guild(*guild).await {
+                Outbox::enqueue(
+                    self.app.db.instrument(&mut *tx),
+                    &self.app.config,
+                    &GatewayEvent::GuildRemove(guild),
+                    now,
+                )
+                .await?;
+            }
+        }
+        let member_of = sqlx::query_scalar!(
+            "SELECT guild_id FROM members JOIN guilds ON members.guild_id = guilds.id
+            WHERE members.user_id = $1 AND guilds.owner_id != $1 AND guilds.deleted_at IS NULL",
+            user.id() as Snowflake<User>
+        )
+        .fetch_all(self.app.db.instrument(&mut *tx))
+        .await?;
+        for guild in member_of {
+            let event = GatewayEvent::MemberRemove(DeletePayload::new(user.id(), Some(guild.into())));
+            Outbox::enqueue(self.app.db.instrument(&mut *tx), &self.app.config, &event, now).await?;
+        }
+
+        // Guilds, channels, memberships and credentials are deleted along with the user
+        sqlx::query!("DELETE FROM users WHERE id = $1", user.id() as Snowflake<User>)
+            .execute(self.app.db.instrument(&mut *tx))
+            .await?;
+
+        tx.commit().await?;
+        self.app.outbox.notify();
+        self.app.search.notify();
+        Ok(())
     }
 
-    /// Commit this user to the database.
+    /// Fetch the presence of a user.
     ///
-    /// ## Errors
+    /// ## Arguments
     ///
-    /// * [`AppError::Database`] - If the database query fails.
-    /// * [`AppError::NotFound`] - If the user does not exist.
-    /// * [`AppError::Build`] - If the avatar is partial.
+    /// * `user` - The ID of the user to retrieve the presence of.
+    ///
+    /// ## Returns
+    ///
+    /// The presence of the user if found, otherwise `None`.
+    pub async fn fetch_presence(&self, user: impl Into<Snowflake<User>>) -> Option<Presence> {
+        let row = sqlx::query!(
+            "SELECT last_presence
+            FROM users
+            WHERE id = $1",
+            user.into() as Snowflake<User>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await
+        .ok()??;
+
+        Some(Presence::from(row.last_presence))
+    }
+
+    /// Fetch the presence privacy settings of a user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to retrieve the settings of.
+    ///
+    /// ## Returns
+    ///
+    /// The settings of the user if found, otherwise `None`.
+    pub async fn fetch_presence_privacy(&self, user: impl Into<Snowflake<User>>) -> Option<PresencePrivacy> {
+        let row = sqlx::query!(
+            "SELECT presence_visibility, presence_hidden_guilds
+            FROM users
+            WHERE id = $1",
+            user.into() as Snowflake<User>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await
+        .ok()??;
+
+        PresencePrivacy::new(
+            PresenceVisibility::from(row.presence_visibility),
+            row.presence_hidden_guilds.into_iter().map(Snowflake::new),
+        )
+        .ok()
+    }
+
+    /// Fetch the preference flags of a user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to retrieve the flags of.
+    ///
+    /// ## Returns
+    ///
+    /// The flags of the user, or the default flags if they never changed their preferences.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_pref_flags(&self, user: impl Into<Snowflake<User>>) -> Result<PrefFlags, sqlx::Error> {
+        let flags = sqlx::query_scalar!(
+            "SELECT flags FROM prefs WHERE user_id = $1",
+            user.into() as Snowflake<User>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(flags
+            .and_then(|flags| u64::try_from(flags).ok())
+            .and_then(PrefFlags::from_bits)
+            .unwrap_or_default())
+    }
+
+    /// Commit the presence privacy settings of a user to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_presence_privacy(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        privacy: &PresencePrivacy,
+    ) -> Result<(), sqlx::Error> {
+        let hidden_guilds: Vec<i64> = privacy.hidden_guilds().iter().copied().map(i64::from).collect();
+
+        sqlx::query!(
+            "UPDATE users SET presence_visibility = $2, presence_hidden_guilds = $3 WHERE id = $1",
+            user.into() as Snowflake<User>,
+            privacy.visibility() as i16,
+            &hidden_guilds,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch how a user arranges their guilds.
+    ///
+    /// ## Returns
+    ///
+    /// The guild settings of the user, which are empty if they never arranged their guilds.
+    /// Guilds the user left since are not removed, see [`GuildSettings::retain_guilds`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_guild_settings(&self, user: impl Into<Snowflake<User>>) -> Result<GuildSettings, sqlx::Error> {
+        let records = sqlx::query_as!(
+            GuildFolderRecord,
+            "SELECT folder_id, name, color, guild_ids FROM guild_folders WHERE user_id = $1 ORDER BY position",
+            user.into() as Snowflake<User>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(GuildSettings::from_records(records))
+    }
+
+    /// Replace how a user arranges their guilds.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_guild_settings(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        settings: &GuildSettings,
+    ) -> Result<(), sqlx::Error> {
+        let user_id: Snowflake<User> = user.into();
+        let mut tx = self.app.db.pool().begin().await?;
+
+        sqlx::query!(
+            "DELETE FROM guild_folders WHERE user_id = $1",
+            user_id as Snowflake<User>
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        for (position, folder) in settings.folders().iter().enumerate() {
+            let guild_ids: Vec<i64> = folder.guild_ids.iter().copied().map(i64::from).collect();
+
+            sqlx::query!(
+                "INSERT INTO guild_folders (user_id, position, folder_id, name, color, guild_ids)
+                VALUES ($1, $2, $3, $4, $5, $6)",
+                user_id as Snowflake<User>,
+                i16::try_from(position).expect("Guild settings should be validated"),
+                folder.id.map(i64::from),
+                folder.name,
+                folder
+                    .color
+                    .map(|color| i32::try_from(color).expect("Guild settings should be validated")),
+                &guild_ids,
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Fetch the public profile of a user, as seen by another user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user whose profile to fetch.
+    /// * `viewer` - The ID of the user viewing the profile, used to count mutual guilds.
+    ///
+    /// ## Returns
+    ///
+    /// The profile and the profile privacy settings of the user if found, otherwise `None`.
+    /// The caller is responsible for checking whether the viewer may see the profile.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_profile(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        viewer: impl Into<Snowflake<User>>,
+    ) -> Result<Option<(Profile, ProfilePrivacy)>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            ProfileRecord,
+            "SELECT users.id, users.username, users.display_name, users.avatar_hash, users.profile_visibility,
+            (SELECT COUNT(*) FROM members AS theirs
+                INNER JOIN members AS ours ON ours.guild_id = theirs.guild_id AND ours.user_id = $2
+                INNER JOIN guilds ON guilds.id = theirs.guild_id AND guilds.deleted_at IS NULL
+                WHERE theirs.user_id = users.id) AS \"mutual_guild_count!\"
+            FROM users
+            WHERE users.id = $1",
+            user.into() as Snowflake<User>,
+            viewer.into() as Snowflake<User>,
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(Profile::from_record))
+    }
+
+    /// Fetch the profile privacy settings of a user.
+    ///
+    /// ## Returns
+    ///
+    /// The settings of the user if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_profile_privacy(
+        &self,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Option<ProfilePrivacy>, sqlx::Error> {
+        let visibility = sqlx::query_scalar!(
+            "SELECT profile_visibility FROM users WHERE id = $1",
+            user.into() as Snowflake<User>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(visibility.map(|visibility| ProfilePrivacy::new(visibility.into())))
+    }
+
+    /// Commit the profile privacy settings of a user to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_profile_privacy(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        privacy: ProfilePrivacy,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE users SET profile_visibility = $2 WHERE id = $1",
+            user.into() as Snowflake<User>,
+            privacy.visibility() as i16,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Retrieve a user from the database by their username.
+    ///
+    /// ## Arguments
+    ///
+    /// * `username` - The username of the user to retrieve.
+    ///
+    /// ## Returns
+    ///
+    /// The user if found, otherwise `None`.
+    /// Ensure that a username is not reserved, either by the configuration or in the `reserved_usernames` table.
+    ///
+    /// ## Arguments
+    ///
+    /// * `username` - The username to check.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Build`] - If the username is reserved.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn ensure_username_not_reserved(&self, username: &str) -> Result<(), AppError> {
+        let normalized = User::normalize_username(username);
+
+        let reserved_by_config = self
+            .app
+            .config
+            .reserved_usernames()
+            .iter()
+            .any(|reserved| User::normalize_username(reserved) == normalized);
+
+        let reserved = reserved_by_config
+            || sqlx::query_scalar!(
+                "SELECT EXISTS(
+                    SELECT 1 FROM reserved_usernames WHERE translate(lower(username), '._', '') = $1
+                ) AS \"exists!\"",
+                normalized
+            )
+            .fetch_one(self.app.db.executor())
+            .await?;
+
+        if reserved {
+            return Err(BuildError::InvalidField {
+                field: "username",
+                code: ErrorCode::UsernameReserved,
+                message: format!("Username '{username}' is reserved"),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    pub async fn fetch_user_by_username(&self, username: &str) -> Option<User> {
+        let row = sqlx::query_as!(
+            UserRecord,
+            "SELECT id, username, display_name, avatar_hash, last_presence
+            FROM users
+            WHERE username = $1
+            LIMIT 1",
+            username
+        )
+        .fetch_optional(self.app.db.executor())
+        .await
+        .ok()??;
+
+        Some(User::from_record(row))
+    }
+
+    /// Search for users by their username, case-insensitively.
+    ///
+    /// ## Arguments
+    ///
+    /// * `query` - The username to search for. Must only contain characters valid in usernames.
+    /// * `fuzzy` - If true, usernames similar to the query are matched and the closest matches are returned first.
+    ///   Otherwise, usernames starting with the query are matched, in alphabetical order.
+    /// * `limit` - The maximum amount of users to return.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn search_users(&self, query: &str, fuzzy: bool, limit: i64) -> Result<Vec<User>, sqlx::Error> {
+        let query = query.to_lowercase();
+
+        let records = if fuzzy {
+            // `%` matches usernames whose trigram similarity is above `pg_trgm.similarity_threshold`
+            sqlx::query_as!(
+                UserRecord,
+                "SELECT id, username, display_name, avatar_hash, last_presence
+                FROM users
+                WHERE LOWER(username) % $1
+                ORDER BY similarity(LOWER(username), $1) DESC, username ASC
+                LIMIT $2",
+                query,
+                limit
+            )
+            .fetch_all(self.app.db.executor())
+            .await?
+        } else {
+            // Underscores are the only LIKE wildcard that is valid in usernames
+            sqlx::query_as!(
+                UserRecord,
+                "SELECT id, username, display_name, avatar_hash, last_presence
+                FROM users
+                WHERE LOWER(username) LIKE $1
+                ORDER BY username ASC
+                LIMIT $2",
+                format!("{}%", query.replace('_', "\\_")),
+                limit
+            )
+            .fetch_all(self.app.db.executor())
+            .await?
+        };
+
+        Ok(records.into_iter().map(User::from_record).collect())
+    }
+
+    /// Fetch all guilds that this user is a member of. Deleted guilds are skipped.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_guilds_for(&self, user: impl Into<Snowflake<User>>) -> Result<Vec<Guild>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            GuildRecord,
+            "SELECT guilds.id, guilds.name, guilds.owner_id, guilds.avatar_hash, guilds.min_account_age, guilds.invite_only,
+            guilds.welcome_message, guilds.raid_mode_until, guilds.locale, guilds.timezone, guilds.version
+            FROM guilds
+            INNER JOIN members ON members.guild_id = guilds.id
+            WHERE members.user_id = $1 AND guilds.deleted_at IS NULL",
+            user.into() as Snowflake<User>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(Guild::from_record).collect())
+    }
+
+    /// Fetch all guild IDs that this user is a member of. Deleted guilds are skipped.
+    /// This is a more efficient version of [`Ops::fetch_guilds_for`] if you only need the IDs.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_guild_ids_for(
+        &self,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Vec<Snowflake<Guild>>, sqlx::Error> {
+        let records = sqlx::query!(
+            "SELECT members.guild_id
+            FROM members
+            INNER JOIN guilds ON guilds.id = members.guild_id
+            WHERE members.user_id = $1 AND guilds.deleted_at IS NULL",
+            user.into() as Snowflake<User>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(|r| r.guild_id.into()).collect())
+    }
+
+    /// Fetch the amount of guilds this user is a member of. Deleted guilds are skipped.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_guild_count_for(&self, user: impl Into<Snowflake<User>>) -> Result<usize, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            "SELECT COUNT(*) AS \"count!\" FROM members
+            INNER JOIN guilds ON guilds.id = members.guild_id
+            WHERE members.user_id = $1 AND guilds.deleted_at IS NULL",
+            user.into() as Snowflake<User>
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+
+        Ok(usize::try_from(count).unwrap_or_default())
+    }
+
+    /// Fetch the amount of members in a guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_member_count(&self, guild: impl Into<Snowflake<Guild>>) -> Result<usize, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            "SELECT COUNT(*) AS \"count!\" FROM members WHERE guild_id = $1",
+            guild.into() as Snowflake<Guild>
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+
+        Ok(usize::try_from(count).unwrap_or_default())
+    }
+
+    /// Fetch the amount of members who joined a guild after the given time.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild.
+    /// * `since` - UNIX timestamp, only members who joined after it are counted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_recent_join_count(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        since: i64,
+    ) -> Result<usize, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            "SELECT COUNT(*) AS \"count!\" FROM members WHERE guild_id = $1 AND joined_at > $2",
+            guild.into() as Snowflake<Guild>,
+            since,
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+
+        Ok(usize::try_from(count).unwrap_or_default())
+    }
+
+    /// Set the time raid mode of a guild ends at. Setting it to the current time disables raid mode.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild.
+    /// * `until` - UNIX timestamp of when raid mode ends.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_raid_mode(&self, guild: impl Into<Snowflake<Guild>>, until: i64) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE guilds SET raid_mode_until = $2 WHERE id = $1",
+            guild.into() as Snowflake<Guild>,
+            until,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Enable raid mode of a guild until the given time, unless it is already enabled.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild.
+    /// * `now` - The current UNIX timestamp. Raid mode that ended before it is considered disabled.
+    /// * `until` - UNIX timestamp of when raid mode ends.
+    ///
+    /// ## Returns
+    ///
+    /// Whether raid mode was enabled by this call.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn enable_raid_mode_if_inactive(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        now: i64,
+        until: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE guilds SET raid_mode_until = $3
+            WHERE id = $1 AND (raid_mode_until IS NULL OR raid_mode_until <= $2)",
+            guild.into() as Snowflake<Guild>,
+            now,
+            until,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Fetch the ID of the last message a user sent in any channel of a guild, if it was sent after `after`.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the author.
+    /// * `guild` - The ID of the guild.
+    /// * `after` - Only messages with a higher ID are considered.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_last_message_id_in_guild(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        guild: impl Into<Snowflake<Guild>>,
+        after: Snowflake<Message>,
+    ) -> Result<Option<Snowflake<Message>>, sqlx::Error> {
+        let id = sqlx::query_scalar!(
+            "SELECT MAX(messages.id) FROM messages
+            INNER JOIN channels ON channels.id = messages.channel_id
+            WHERE messages.id > $3 AND messages.user_id = $1 AND channels.guild_id = $2",
+            user.into() as Snowflake<User>,
+            guild.into() as Snowflake<Guild>,
+            after as Snowflake<Message>,
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+
+        Ok(id.map(Snowflake::new))
+    }
+
+    /// Fetch the amount of channels in a guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_channel_count(&self, guild: impl Into<Snowflake<Guild>>) -> Result<usize, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            "SELECT COUNT(*) AS \"count!\" FROM channels WHERE guild_id = $1",
+            guild.into() as Snowflake<Guild>
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+
+        Ok(usize::try_from(count).unwrap_or_default())
+    }
+
+    /// Create a new user in the database, along with their saved messages channel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_user(&self, payload: CreateUser) -> Result<User, sqlx::Error> {
+        let gen_id = Snowflake::<User>::gen_new(&self.app.config);
+
+        let user = sqlx::query_as!(
+            UserRecord,
+            "INSERT INTO users (id, username)
+            VALUES ($1, $2) RETURNING id, username, display_name, avatar_hash, last_presence",
+            gen_id as Snowflake<User>,
+            payload.username,
+        )
+        .fetch_one(self.app.db.executor())
+        .await
+        .map(User::from_record)?;
+
+        let saved_messages = SavedMessagesChannel::new(Snowflake::gen_new(&self.app.config), &user).into();
+        self.create_channel(&saved_messages).await?;
+        Ok(user)
+    }
+
+    /// Fetch the saved messages channel of a user.
+    /// Users registered before saved messages channels existed get one on first access.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_saved_messages(&self, user: impl Into<Snowflake<User>>) -> Result<Channel, sqlx::Error> {
+        let user_id: Snowflake<User> = user.into();
+        let channel: Channel = SavedMessagesChannel::new(Snowflake::gen_new(&self.app.config), user_id).into();
+
+        sqlx::query!(
+            "INSERT INTO channels (id, owner_id, name, channel_type)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (owner_id) WHERE channel_type = 'SAVED_MESSAGES' DO NOTHING",
+            channel.id() as Snowflake<Channel>,
+            user_id as Snowflake<User>,
+            channel.name(),
+            channel.channel_type(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        sqlx::query_as!(
+            ChannelRecord,
+            "SELECT * FROM channels WHERE owner_id = $1 AND channel_type = 'SAVED_MESSAGES'",
+            user_id as Snowflake<User>
+        )
+        .fetch_one(self.app.db.executor())
+        .await
+        .map(Channel::from_record)
+    }
+
+    /// Commit this user to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::NotFound`] - If the user does not exist.
+    /// * [`AppError::Build`] - If the avatar is partial.
     ///
     /// ## Returns
     ///
@@ -648,65 +2233,2129 @@ impl<'a> Ops<'a> {
     pub async fn update_user(&self, user: impl Into<Snowflake<User>>, payload: UpdateUser) -> Result<User, AppError> {
         let user_id = user.into();
 
-        let old_user = self
-            .fetch_user(user_id)
-            .await
-            .ok_or(AppError::NotFound("User not found".into()))?;
+        let old_user = self
+            .fetch_user(user_id)
+            .await
+            .ok_or(AppError::NotFound("User not found".into()))?;
+
+        let mut user = old_user.clone();
+        user.update(payload)?;
+
+        if old_user == user {
+            return Ok(user);
+        }
+
+        if old_user.username() != user.username() {
+            self.ensure_username_not_reserved(user.username()).await?;
+        }
+
+        if old_user.avatar() != user.avatar() {
+            old_user.avatar().map(|a| async { a.delete(&self.app.s3).await });
+            match user.avatar() {
+                Some(Avatar::Full(f)) => f.upload(&self.app.s3).await?,
+                _ => {
+                    Err(BuildError::ValidationError("Cannot upload partial avatar".into()))?;
+                }
+            }
+        }
+
+        let record = sqlx::query_as!(
+            UserRecord,
+            "UPDATE users SET username = $2, display_name = $3, last_presence = $4, avatar_hash = $5
+            WHERE id = $1 RETURNING id, username, display_name, avatar_hash, last_presence",
+            user_id as Snowflake<User>,
+            user.username(),
+            user.display_name(),
+            *user.last_presence() as i16,
+            user.avatar().map(AvatarLike::avatar_hash),
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+        Ok(User::from_record(record))
+    }
+
+    /// Commit the attachment to the database as part of a transaction. Uploads the contents to S3 implicitly.
+    ///
+    /// ## Arguments
+    ///
+    /// * `conn` - The transaction to commit the attachment in.
+    /// * `attachment` - The attachment to commit.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request fails.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn create_attachment(
+        &self,
+        conn: &mut PgConnection,
+        attachment: &FullAttachment,
+    ) -> Result<(), AppError> {
+        attachment.upload(&self.app.s3).await?;
+
+        let metadata = attachment.metadata();
+        sqlx::query!(
+            "INSERT INTO attachments (id, filename, message_id, channel_id, content_type, width, height, duration, blurhash, scan_status, key_token)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) 
+            ON CONFLICT (id, message_id) 
+            DO UPDATE SET filename = $2, content_type = $5, width = $6, height = $7, duration = $8, blurhash = $9, scan_status = $10, key_token = $11",
+            i32::from(attachment.id()),
+            attachment.filename(),
+            attachment.message_id() as Snowflake<Message>,
+            attachment.channel_id() as Snowflake<Channel>,
+            attachment.mime().to_string(),
+            metadata.width().map(|w| w as i32),
+            metadata.height().map(|h| h as i32),
+            metadata.duration(),
+            metadata.blurhash(),
+            attachment.scan_status() as i16,
+            attachment.key_token(),
+        )
+        .execute(self.app.db.instrument(conn))
+        .await?;
+
+        Ok(())
+    }
+
+    /// Store the rendering metadata of all attachments of a message.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The message whose attachments were updated. Only attachments with metadata are stored.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::MessageUpdate`] - Once the metadata is committed
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update_attachment_metadata(&self, message: &Message) -> Result<(), AppError> {
+        let mut tx = self.app.db.pool().begin().await?;
+
+        for attachment in message.attachments() {
+            let metadata = attachment.metadata();
+            if metadata.is_empty() {
+                continue;
+            }
+
+            sqlx::query!(
+                "UPDATE attachments SET width = $3, height = $4, duration = $5, blurhash = $6
+                WHERE id = $1 AND message_id = $2",
+                i32::from(attachment.id()),
+                message.id() as Snowflake<Message>,
+                metadata.width().map(|w| w as i32),
+                metadata.height().map(|h| h as i32),
+                metadata.duration(),
+                metadata.blurhash(),
+            )
+            .execute(self.app.db.instrument(&mut *tx))
+            .await?;
+        }
+
+        self.enqueue_message_update(&mut tx, message).await?;
+
+        tx.commit().await?;
+        self.app.outbox.notify();
+        Ok(())
+    }
+
+    /// Store the malware scan status of all attachments of a message.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The message whose attachments were scanned.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::MessageUpdate`] - Once the scan status is committed
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update_attachment_scan_status(&self, message: &Message) -> Result<(), AppError> {
+        let mut tx = self.app.db.pool().begin().await?;
+
+        for attachment in message.attachments() {
+            sqlx::query!(
+                "UPDATE attachments SET scan_status = $3
+                WHERE id = $1 AND message_id = $2",
+                i32::from(attachment.id()),
+                message.id() as Snowflake<Message>,
+                attachment.scan_status() as i16,
+            )
+            .execute(self.app.db.instrument(&mut *tx))
+            .await?;
+        }
+
+        self.enqueue_message_update(&mut tx, message).await?;
+
+        tx.commit().await?;
+        self.app.outbox.notify();
+        Ok(())
+    }
+
+    /// Store the content of a message along with attachments re-hosted from links in it.
+    /// Uploads the attachments to S3. The message is queued to be indexed for search again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The message, with the links in its content replaced by references to the attachments.
+    /// * `attachments` - The attachments re-hosted from the links.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the message still existed and was updated.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::MessageUpdate`] - Once the content and attachments are committed
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the S3 request to upload one of the attachments fails.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update_rehosted_media(
+        &self,
+        message: &Message,
+        attachments: &[FullAttachment],
+    ) -> Result<bool, AppError> {
+        let mut tx = self.app.db.pool().begin().await?;
+
+        let updated = sqlx::query!(
+            "UPDATE messages SET content = $2 WHERE id = $1",
+            message.id() as Snowflake<Message>,
+            message.content(),
+        )
+        .execute(self.app.db.instrument(&mut *tx))
+        .await?
+        .rows_affected()
+            > 0;
+
+        // The message may have been deleted while the media was downloaded
+        if !updated {
+            return Ok(false);
+        }
+
+        for attachment in attachments {
+            self.create_attachment(&mut tx, attachment).await?;
+        }
+
+        self.enqueue_message_update(&mut tx, message).await?;
+        SearchIndex::enqueue(self.app.db.instrument(&mut *tx), &[message.id()]).await?;
+
+        tx.commit().await?;
+        self.app.outbox.notify();
+        self.app.search.notify();
+        Ok(true)
+    }
+
+    /// Queue a [`GatewayEvent::MessageUpdate`] for a message whose attachments changed as part of a transaction.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    async fn enqueue_message_update(&self, conn: &mut PgConnection, message: &Message) -> Result<(), AppError> {
+        let owner_id = sqlx::query_scalar!(
+            "SELECT owner_id FROM channels WHERE id = $1",
+            message.channel_id() as Snowflake<Channel>
+        )
+        .fetch_optional(self.app.db.instrument(&mut *conn))
+        .await?
+        .flatten();
+
+        let mut message = message.clone().strip_attachment_contents();
+        message.clear_nonce();
+        Outbox::enqueue_to(
+            self.app.db.instrument(&mut *conn),
+            &self.app.config,
+            owner_id.map(Snowflake::new),
+            &GatewayEvent::MessageUpdate(message),
+            self.app.clock.now(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch the malware scan status of an attachment.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The ID of the message the attachment belongs to.
+    /// * `id` - The ID of the attachment within the message.
+    ///
+    /// ## Returns
+    ///
+    /// The scan status, or `None` if the attachment does not exist.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_attachment_scan_status(
+        &self,
+        message: impl Into<Snowflake<Message>>,
+        id: u8,
+    ) -> Result<Option<ScanStatus>, AppError> {
+        let status = sqlx::query_scalar!(
+            "SELECT scan_status FROM attachments WHERE message_id = $1 AND id = $2",
+            message.into() as Snowflake<Message>,
+            i32::from(id),
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(status.map(ScanStatus::from))
+    }
+
+    /// Fetch the IDs of messages that were sent before the given ID and still have attachments pending a scan.
+    ///
+    /// ## Arguments
+    ///
+    /// * `before` - Only messages sent before this ID are returned.
+    /// * `limit` - The maximum amount of messages to return.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_messages_pending_scan(
+        &self,
+        before: Snowflake<Message>,
+        limit: i64,
+    ) -> Result<Vec<Snowflake<Message>>, AppError> {
+        let ids = sqlx::query_scalar!(
+            "SELECT DISTINCT message_id FROM attachments
+            WHERE scan_status = $1 AND message_id < $2
+            ORDER BY message_id
+            LIMIT $3",
+            ScanStatus::Pending as i16,
+            before as Snowflake<Message>,
+            limit,
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(ids.into_iter().map(Snowflake::new).collect())
+    }
+
+    /// Set the key token of an attachment that has none yet.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the key token was set. This is `false` if the attachment was deleted,
+    /// or already received a key token from another instance.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn set_attachment_key_token(
+        &self,
+        attachment: &impl AttachmentLike,
+        key_token: &str,
+    ) -> Result<bool, AppError> {
+        let result = sqlx::query!(
+            "UPDATE attachments SET key_token = $3 WHERE message_id = $1 AND id = $2 AND key_token IS NULL",
+            attachment.message_id() as Snowflake<Message>,
+            i32::from(attachment.id()),
+            key_token,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Fetch all automod rules of a guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Build`] - If one of the rules is malformed.
+    pub async fn fetch_automod_rules(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<AutoModRule>, AppError> {
+        let records = sqlx::query_as!(
+            AutoModRuleRecord,
+            "SELECT * FROM automod_rules WHERE guild_id = $1 ORDER BY id",
+            guild.into() as Snowflake<Guild>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        records
+            .into_iter()
+            .map(AutoModRule::from_record)
+            .collect::<Result<_, _>>()
+            .map_err(Into::into)
+    }
+
+    /// Fetch an automod rule from the database by ID.
+    ///
+    /// ## Returns
+    ///
+    /// The rule if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Build`] - If the rule is malformed.
+    pub async fn fetch_automod_rule(
+        &self,
+        rule: impl Into<Snowflake<AutoModRule>>,
+    ) -> Result<Option<AutoModRule>, AppError> {
+        let record = sqlx::query_as!(
+            AutoModRuleRecord,
+            "SELECT * FROM automod_rules WHERE id = $1",
+            rule.into() as Snowflake<AutoModRule>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        record.map(AutoModRule::from_record).transpose().map_err(Into::into)
+    }
+
+    /// Commit the automod rule to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_automod_rule(&self, rule: &AutoModRule) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO automod_rules (id, guild_id, name, trigger_type, patterns, action, timeout_duration, enabled, issue_strike)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (id) DO UPDATE
+            SET name = $3, trigger_type = $4, patterns = $5, action = $6, timeout_duration = $7, enabled = $8, issue_strike = $9",
+            rule.id() as Snowflake<AutoModRule>,
+            rule.guild_id() as Snowflake<Guild>,
+            rule.name(),
+            rule.trigger_type() as i16,
+            rule.patterns(),
+            rule.action() as i16,
+            rule.timeout_duration(),
+            rule.enabled(),
+            rule.issue_strike(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Delete an automod rule from the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_automod_rule(&self, rule: impl Into<Snowflake<AutoModRule>>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM automod_rules WHERE id = $1",
+            rule.into() as Snowflake<AutoModRule>
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch the members and channels of a guild that the given mentions may refer to.
+    /// Channels of other guilds the author is a member of may be linked to by ID.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the mentions were made in.
+    /// * `author` - The author of the mentions, if any.
+    /// * `mentions` - The mentions to resolve.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_mention_targets(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        author: Option<Snowflake<User>>,
+        mentions: &[Mention<'_>],
+    ) -> Result<MentionTargets, AppError> {
+        let guild_id = guild.into();
+        let mut targets = MentionTargets::new();
+
+        let mut usernames = Vec::new();
+        let mut user_ids = Vec::new();
+        let mut channel_names = Vec::new();
+        let mut channel_ids = Vec::new();
+        for mention in mentions {
+            match mention {
+                Mention::User(id) => user_ids.push(*id),
+                Mention::Username(name) => usernames.push(name.to_lowercase()),
+                Mention::Channel(id) => channel_ids.push(*id),
+                Mention::ChannelName(name) => channel_names.push(name.to_lowercase()),
+            }
+        }
+
+        if !usernames.is_empty() || !user_ids.is_empty() {
+            let users = sqlx::query!(
+                "SELECT users.id, users.username
+                FROM members
+                JOIN users ON users.id = members.user_id
+                WHERE members.guild_id = $1 AND (lower(users.username) = ANY($2) OR users.id = ANY($3))",
+                guild_id as Snowflake<Guild>,
+                &usernames,
+                &user_ids as &[Snowflake<User>],
+            )
+            .fetch_all(self.app.db.executor())
+            .await?;
+
+            for user in users {
+                targets.add_user(user.id.into(), &user.username);
+            }
+        }
+
+        if !channel_names.is_empty() || !channel_ids.is_empty() {
+            // Channels of other guilds are only visible to the author if they are a member
+            let channels = sqlx::query!(
+                r#"SELECT id, guild_id AS "guild_id!", name
+                FROM channels
+                WHERE (guild_id = $1 AND (lower(name) = ANY($2) OR id = ANY($3)))
+                OR (id = ANY($3) AND guild_id IN (
+                    SELECT members.guild_id FROM members
+                    JOIN guilds ON guilds.id = members.guild_id AND guilds.deleted_at IS NULL
+                    WHERE members.user_id = $4
+                ))
+                ORDER BY id ASC"#,
+                guild_id as Snowflake<Guild>,
+                &channel_names,
+                &channel_ids as &[Snowflake<Channel>],
+                author as Option<Snowflake<User>>,
+            )
+            .fetch_all(self.app.db.executor())
+            .await?;
+
+            for channel in channels {
+                let mention = ChannelMention {
+                    id: channel.id.into(),
+                    guild_id: channel.guild_id.into(),
+                    name: channel.name,
+                };
+                if mention.guild_id == guild_id {
+                    targets.add_channel(mention);
+                } else {
+                    targets.add_linked_channel(mention);
+                }
+            }
+        }
+
+        Ok(targets)
+    }
+
+    /// Evaluate the automod rules of the message's guild against a message that is about to be sent.
+    /// Creates an audit log entry for every rule that triggered.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the message is sent in.
+    /// * `message` - The message to evaluate.
+    ///
+    /// ## Returns
+    ///
+    /// The triggered rule with the most severe action, or `None` if no rule triggered.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Build`] - If one of the rules is malformed.
+    pub async fn run_automod(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        message: &Message,
+    ) -> Result<Option<AutoModRule>, AppError> {
+        let guild_id = guild.into();
+        let Some(author) = message.author().map(UserLike::id) else {
+            return Ok(None);
+        };
+
+        let rules = self.fetch_automod_rules(guild_id).await?;
+        let triggered = self
+            .app
+            .automod
+            .evaluate(&rules, guild_id, author, message.content().map(String::as_str));
+
+        for rule in &triggered {
+            let action_type = match rule.action() {
+                AutoModAction::Flag => AuditLogAction::AutoModFlagMessage,
+                AutoModAction::Delete => AuditLogAction::AutoModDeleteMessage,
+                AutoModAction::Block => AuditLogAction::AutoModBlockMessage,
+                AutoModAction::Timeout => AuditLogAction::AutoModTimeoutMember,
+            };
+            let entry = AuditLogEntry::new(
+                &self.app.config,
+                guild_id,
+                None,
+                action_type,
+                Some(author.cast()),
+                Some(format!("Triggered automod rule '{}'", rule.name())),
+            );
+            self.create_audit_log_entry(&entry).await?;
+        }
+
+        Ok(triggered
+            .into_iter()
+            .max_by_key(|rule| (rule.action(), rule.timeout_duration()))
+            .cloned())
+    }
+
+    /// Commit a new audit log entry to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_audit_log_entry(&self, entry: &AuditLogEntry) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO audit_log (id, guild_id, user_id, action_type, target_id, reason)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+            entry.id() as Snowflake<AuditLogEntry>,
+            entry.guild_id() as Snowflake<Guild>,
+            entry.user_id() as Option<Snowflake<User>>,
+            entry.action_type() as i16,
+            entry.target_id() as Option<Snowflake<()>>,
+            entry.reason(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch the audit log of a guild, newest entries first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the audit log of.
+    /// * `limit` - The maximum amount of entries to fetch. Defaults to 50, capped at 100.
+    /// * `before` - Only fetch entries before this ID.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_audit_log(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        limit: Option<u32>,
+        before: Option<Snowflake<AuditLogEntry>>,
+    ) -> Result<Vec<AuditLogEntry>, sqlx::Error> {
+        let limit = limit.unwrap_or(50).min(100);
+
+        let records = sqlx::query_as!(
+            AuditLogRecord,
+            "SELECT * FROM audit_log
+            WHERE guild_id = $1 AND ($2::BIGINT IS NULL OR id < $2)
+            ORDER BY id DESC LIMIT $3",
+            guild.into() as Snowflake<Guild>,
+            before as Option<Snowflake<AuditLogEntry>>,
+            i64::from(limit)
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(AuditLogEntry::from_record).collect())
+    }
+
+    /// Store a new report, unless the reporter already reported the message.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the report was stored, `false` if the reporter already reported the message.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_report(&self, report: &Report) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "INSERT INTO reports (id, guild_id, channel_id, message_id, author_id, content, reporter_id, reason, details, state)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT (message_id, reporter_id) DO NOTHING",
+            report.id() as Snowflake<Report>,
+            report.guild_id() as Snowflake<Guild>,
+            report.channel_id() as Snowflake<Channel>,
+            report.message_id() as Snowflake<Message>,
+            report.author_id() as Option<Snowflake<User>>,
+            report.content(),
+            report.reporter_id() as Option<Snowflake<User>>,
+            report.reason() as i16,
+            report.details(),
+            report.state() as i16,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Fetch a report by its ID.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_report(&self, report: impl Into<Snowflake<Report>>) -> Result<Option<Report>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            ReportRecord,
+            "SELECT * FROM reports WHERE id = $1",
+            report.into() as Snowflake<Report>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(Report::from_record))
+    }
+
+    /// Fetch the reports of a guild, newest reports first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the reports of.
+    /// * `state` - Only fetch reports in this state, if set.
+    /// * `limit` - The maximum amount of reports to fetch. Defaults to 50, capped at 100.
+    /// * `before` - Only fetch reports before this ID.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_reports(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        state: Option<ReportState>,
+        limit: Option<u32>,
+        before: Option<Snowflake<Report>>,
+    ) -> Result<Vec<Report>, sqlx::Error> {
+        let limit = limit.unwrap_or(50).min(100);
+
+        let records = sqlx::query_as!(
+            ReportRecord,
+            "SELECT * FROM reports
+            WHERE guild_id = $1 AND ($2::SMALLINT IS NULL OR state = $2) AND ($3::BIGINT IS NULL OR id < $3)
+            ORDER BY id DESC LIMIT $4",
+            guild.into() as Snowflake<Guild>,
+            state.map(|state| state as i16),
+            before as Option<Snowflake<Report>>,
+            i64::from(limit)
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(Report::from_record).collect())
+    }
+
+    /// Commit the review of a report to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_report(&self, report: &Report) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE reports SET state = $2, resolved_by = $3 WHERE id = $1",
+            report.id() as Snowflake<Report>,
+            report.state() as i16,
+            report.resolved_by() as Option<Snowflake<User>>,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Commit a new strike to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_strike(&self, strike: &Strike) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO strikes (id, guild_id, user_id, issuer_id, reason, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+            strike.id() as Snowflake<Strike>,
+            strike.guild_id() as Snowflake<Guild>,
+            strike.user_id() as Snowflake<User>,
+            strike.issuer_id() as Option<Snowflake<User>>,
+            strike.reason(),
+            strike.expires_at(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch a strike by its ID, even if it expired.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_strike(&self, strike: impl Into<Snowflake<Strike>>) -> Result<Option<Strike>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            StrikeRecord,
+            "SELECT * FROM strikes WHERE id = $1",
+            strike.into() as Snowflake<Strike>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(Strike::from_record))
+    }
+
+    /// Fetch the strikes of a user in a guild that did not expire yet, oldest first.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_active_strikes(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Vec<Strike>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            StrikeRecord,
+            "SELECT * FROM strikes
+            WHERE guild_id = $1 AND user_id = $2 AND (expires_at IS NULL OR expires_at > $3)
+            ORDER BY id ASC",
+            guild.into() as Snowflake<Guild>,
+            user.into() as Snowflake<User>,
+            self.app.clock.timestamp(),
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(Strike::from_record).collect())
+    }
+
+    /// Delete a strike from the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_strike(&self, strike: impl Into<Snowflake<Strike>>) -> Result<(), sqlx::Error> {
+        sqlx::query!("DELETE FROM strikes WHERE id = $1", strike.into() as Snowflake<Strike>)
+            .execute(self.app.db.executor())
+            .await?;
+        Ok(())
+    }
+
+    /// Delete all strikes that have expired.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of deleted strikes.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_expired_strikes(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM strikes WHERE expires_at <= $1", self.app.clock.timestamp())
+            .execute(self.app.db.executor())
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Fetch the strike policy of a guild. Guilds that never set a policy have the default policy.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_strike_policy(&self, guild: impl Into<Snowflake<Guild>>) -> Result<StrikePolicy, sqlx::Error> {
+        let record = sqlx::query_as!(
+            StrikePolicyRecord,
+            "SELECT timeout_threshold, timeout_duration, ban_threshold, strike_duration, flag_evasion
+            FROM strike_policies WHERE guild_id = $1",
+            guild.into() as Snowflake<Guild>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(StrikePolicy::from_record).unwrap_or_default())
+    }
+
+    /// Commit the strike policy of a guild to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_strike_policy(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        policy: &StrikePolicy,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO strike_policies
+            (guild_id, timeout_threshold, timeout_duration, ban_threshold, strike_duration, flag_evasion)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (guild_id) DO UPDATE
+            SET timeout_threshold = $2, timeout_duration = $3, ban_threshold = $4, strike_duration = $5,
+                flag_evasion = $6",
+            guild.into() as Snowflake<Guild>,
+            policy.timeout_threshold(),
+            policy.timeout_duration(),
+            policy.ban_threshold(),
+            policy.strike_duration(),
+            policy.flag_evasion(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch the onboarding of a guild. Guilds that never set up onboarding have it disabled, without questions.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_onboarding(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Onboarding, sqlx::Error> {
+        let record = sqlx::query_as!(
+            OnboardingRecord,
+            "SELECT enabled, default_channel_ids, questions FROM guild_onboarding WHERE guild_id = $1",
+            guild.into() as Snowflake<Guild>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(Onboarding::from_record).unwrap_or_default())
+    }
+
+    /// Commit the onboarding of a guild to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_onboarding(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        onboarding: &Onboarding,
+    ) -> Result<(), sqlx::Error> {
+        let default_channel_ids: Vec<i64> = onboarding
+            .default_channel_ids()
+            .iter()
+            .copied()
+            .map(i64::from)
+            .collect();
+        let questions = serde_json::to_string(onboarding.questions()).unwrap_or_else(|_| "[]".into());
+
+        sqlx::query!(
+            "INSERT INTO guild_onboarding (guild_id, enabled, default_channel_ids, questions)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (guild_id) DO UPDATE
+            SET enabled = $2, default_channel_ids = $3, questions = $4",
+            guild.into() as Snowflake<Guild>,
+            onboarding.enabled(),
+            &default_channel_ids,
+            questions,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch how a user completed the onboarding of a guild.
+    ///
+    /// ## Returns
+    ///
+    /// The onboarding of the member, or `None` if they did not complete it.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_member_onboarding(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Option<MemberOnboarding>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            MemberOnboardingRecord,
+            "SELECT guild_id, responses, channel_ids, completed_at FROM member_onboarding
+            WHERE guild_id = $1 AND user_id = $2",
+            guild.into() as Snowflake<Guild>,
+            user.into() as Snowflake<User>,
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(MemberOnboarding::from_record))
+    }
+
+    /// Commit how a user completed the onboarding of a guild, replacing any earlier answers.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_member_onboarding(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        onboarding: &MemberOnboarding,
+    ) -> Result<(), sqlx::Error> {
+        let channel_ids: Vec<i64> = onboarding.channel_ids().iter().copied().map(i64::from).collect();
+        let responses = serde_json::to_string(onboarding.responses()).unwrap_or_else(|_| "[]".into());
+
+        sqlx::query!(
+            "INSERT INTO member_onboarding (guild_id, user_id, responses, channel_ids, completed_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (guild_id, user_id) DO UPDATE
+            SET responses = $3, channel_ids = $4, completed_at = $5",
+            onboarding.guild_id() as Snowflake<Guild>,
+            user.into() as Snowflake<User>,
+            responses,
+            &channel_ids,
+            onboarding.completed_at(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Commit a guild ban to the database. Banning a user twice keeps the original reason.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_guild_ban(&self, ban: &GuildBan) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO guild_bans (guild_id, user_id, reason)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (guild_id, user_id) DO NOTHING",
+            ban.guild_id as Snowflake<Guild>,
+            ban.user_id as Snowflake<User>,
+            ban.reason,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch all bans of a guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_guild_bans(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<GuildBan>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            GuildBanRecord,
+            "SELECT * FROM guild_bans WHERE guild_id = $1 ORDER BY user_id",
+            guild.into() as Snowflake<Guild>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(GuildBan::from_record).collect())
+    }
+
+    /// Check if a user is banned from a guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn is_banned(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<bool, sqlx::Error> {
+        let record = sqlx::query!(
+            "SELECT EXISTS(SELECT 1 FROM guild_bans WHERE guild_id = $1 AND user_id = $2) AS \"banned!\"",
+            guild.into() as Snowflake<Guild>,
+            user.into() as Snowflake<User>,
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+        Ok(record.banned)
+    }
+
+    /// Lift the ban of a user from a guild.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the user was banned.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_guild_ban(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM guild_bans WHERE guild_id = $1 AND user_id = $2",
+            guild.into() as Snowflake<Guild>,
+            user.into() as Snowflake<User>,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record the fingerprints of a user. Fingerprints the user already has are kept.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user the fingerprints belong to.
+    /// * `hashes` - The salted hashes of the user's identifiers, see [`fingerprints`](crate::models::fingerprint::fingerprints).
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_fingerprints(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        hashes: &[String],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO user_fingerprints (user_id, hash)
+            SELECT $1, hash FROM UNNEST($2::TEXT[]) AS hash
+            ON CONFLICT (user_id, hash) DO NOTHING",
+            user.into() as Snowflake<User>,
+            hashes,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Find a user banned from a guild who shares a fingerprint with the given user.
+    ///
+    /// ## Returns
+    ///
+    /// The ID of the banned user, or `None` if the user shares no fingerprint with a banned user.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_evasion_match(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Option<Snowflake<User>>, sqlx::Error> {
+        let record = sqlx::query!(
+            "SELECT guild_bans.user_id FROM guild_bans
+            JOIN user_fingerprints banned ON banned.user_id = guild_bans.user_id
+            JOIN user_fingerprints joined ON joined.hash = banned.hash
+            WHERE guild_bans.guild_id = $1 AND joined.user_id = $2 AND guild_bans.user_id <> $2
+            ORDER BY guild_bans.user_id
+            LIMIT 1",
+            guild.into() as Snowflake<Guild>,
+            user.into() as Snowflake<User>,
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(|record| record.user_id.into()))
+    }
+
+    /// Commit an evasion flag to the database. Flagging a member twice keeps the original flag.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_evasion_flag(&self, flag: &EvasionFlag) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO evasion_flags (guild_id, user_id, banned_user_id, flagged_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (guild_id, user_id) DO NOTHING",
+            flag.guild_id as Snowflake<Guild>,
+            flag.user_id as Snowflake<User>,
+            flag.banned_user_id as Snowflake<User>,
+            flag.flagged_at,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch all evasion flags of a guild, oldest first.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_evasion_flags(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+    ) -> Result<Vec<EvasionFlag>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            EvasionFlagRecord,
+            "SELECT * FROM evasion_flags WHERE guild_id = $1 ORDER BY flagged_at, user_id",
+            guild.into() as Snowflake<Guild>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(EvasionFlag::from_record).collect())
+    }
+
+    /// Remove the evasion flag of a member of a guild.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the member was flagged.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_evasion_flag(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM evasion_flags WHERE guild_id = $1 AND user_id = $2",
+            guild.into() as Snowflake<Guild>,
+            user.into() as Snowflake<User>,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Add to the usage counts of emojis in a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the emojis were used in.
+    /// * `usage` - The amount of times each emoji was used.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn record_emoji_usage(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        usage: &HashMap<String, i64>,
+    ) -> Result<(), sqlx::Error> {
+        let (emojis, counts): (Vec<String>, Vec<i64>) = usage.iter().map(|(e, c)| (e.clone(), *c)).unzip();
+
+        sqlx::query!(
+            "INSERT INTO emoji_usage (guild_id, emoji, count, last_used_at)
+            SELECT $1, emoji, count, $4 FROM UNNEST($2::TEXT[], $3::BIGINT[]) AS u(emoji, count)
+            ON CONFLICT (guild_id, emoji) DO UPDATE
+            SET count = emoji_usage.count + EXCLUDED.count, last_used_at = EXCLUDED.last_used_at",
+            guild.into() as Snowflake<Guild>,
+            &emojis,
+            &counts,
+            self.app.clock.timestamp()
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Fetch the most used emojis of a guild, most used first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the emoji usage of.
+    /// * `limit` - The maximum amount of emojis to fetch. Defaults to 50, capped at 100.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_emoji_usage(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        limit: Option<u32>,
+    ) -> Result<Vec<EmojiUsage>, sqlx::Error> {
+        let limit = limit.unwrap_or(50).min(100);
+
+        let records = sqlx::query_as!(
+            EmojiUsageRecord,
+            "SELECT emoji, count, last_used_at FROM emoji_usage
+            WHERE guild_id = $1
+            ORDER BY count DESC, emoji ASC LIMIT $2",
+            guild.into() as Snowflake<Guild>,
+            i64::from(limit)
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(EmojiUsage::from_record).collect())
+    }
+
+    /// Recompute the message statistics of all guilds for a single day from the messages sent on it.
+    /// Messages are selected by the time encoded in their ID, which is covered by the primary key.
+    ///
+    /// ## Arguments
+    ///
+    /// * `day` - The UNIX timestamp of the start of the day, in UTC.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of guilds whose statistics were updated.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn aggregate_guild_stats(&self, day: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            "INSERT INTO guild_daily_stats (guild_id, day, messages, active_members)
+            SELECT channels.guild_id, $3, COUNT(*), COUNT(DISTINCT messages.user_id)
+            FROM messages
+            INNER JOIN channels ON channels.id = messages.channel_id
+            WHERE messages.id >= $1 AND messages.id < $2 AND channels.guild_id IS NOT NULL
+            GROUP BY channels.guild_id
+            ON CONFLICT (guild_id, day) DO UPDATE
+            SET messages = EXCLUDED.messages, active_members = EXCLUDED.active_members",
+            Snowflake::<Message>::from_timestamp(day * 1000) as Snowflake<Message>,
+            Snowflake::<Message>::from_timestamp((day + SECONDS_PER_DAY) * 1000) as Snowflake<Message>,
+            day
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Fetch the activity of a guild over the last days, including today.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to fetch the analytics of.
+    /// * `days` - The amount of days to fetch. Defaults to 30, capped at 90.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_guild_analytics(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        days: Option<u32>,
+    ) -> Result<GuildAnalytics, sqlx::Error> {
+        let days = days
+            .unwrap_or(analytics::DEFAULT_ANALYTICS_DAYS)
+            .clamp(1, analytics::MAX_ANALYTICS_DAYS);
+        let first_day = analytics::day_start(self.app.clock.now()) - i64::from(days - 1) * SECONDS_PER_DAY;
+
+        let records = sqlx::query_as!(
+            DailyStatsRecord,
+            "SELECT day, messages, active_members, joins, leaves FROM guild_daily_stats
+            WHERE guild_id = $1 AND day >= $2",
+            guild.into() as Snowflake<Guild>,
+            first_day
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(GuildAnalytics::from_records(records, first_day, days))
+    }
+
+    /// Fetch all webhooks of a guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_webhooks(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<Webhook>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            WebhookRecord,
+            "SELECT * FROM webhooks WHERE guild_id = $1 ORDER BY id",
+            guild.into() as Snowflake<Guild>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(Webhook::from_record).collect())
+    }
+
+    /// Fetch a webhook from the database by ID.
+    ///
+    /// ## Returns
+    ///
+    /// The webhook if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_webhook(&self, webhook: impl Into<Snowflake<Webhook>>) -> Result<Option<Webhook>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            WebhookRecord,
+            "SELECT * FROM webhooks WHERE id = $1",
+            webhook.into() as Snowflake<Webhook>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(Webhook::from_record))
+    }
+
+    /// Commit the webhook to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_webhook(&self, webhook: &Webhook) -> Result<(), sqlx::Error> {
+        let events: Vec<i16> = webhook.events().iter().map(|event| *event as i16).collect();
+
+        sqlx::query!(
+            "INSERT INTO webhooks (id, guild_id, name, url, secret, events, enabled)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (id) DO UPDATE
+            SET name = $3, url = $4, secret = $5, events = $6, enabled = $7",
+            webhook.id() as Snowflake<Webhook>,
+            webhook.guild_id() as Snowflake<Guild>,
+            webhook.name(),
+            webhook.url(),
+            webhook.secret(),
+            &events,
+            webhook.enabled(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Delete a webhook and its delivery log from the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_webhook(&self, webhook: impl Into<Snowflake<Webhook>>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM webhooks WHERE id = $1",
+            webhook.into() as Snowflake<Webhook>
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch the delivery log of a webhook, newest deliveries first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `webhook` - The webhook to fetch the deliveries of.
+    /// * `limit` - The maximum amount of deliveries to fetch. Defaults to 50, capped at 100.
+    /// * `before` - Only fetch deliveries before this ID.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_webhook_deliveries(
+        &self,
+        webhook: impl Into<Snowflake<Webhook>>,
+        limit: Option<u32>,
+        before: Option<Snowflake<WebhookDelivery>>,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let limit = limit.unwrap_or(50).min(100);
+
+        let records = sqlx::query_as!(
+            WebhookDeliveryRecord,
+            "SELECT id, webhook_id, event, attempts, next_attempt_at, response_status, error, delivered_at, created_at
+            FROM webhook_deliveries
+            WHERE webhook_id = $1 AND ($2::BIGINT IS NULL OR id < $2)
+            ORDER BY id DESC LIMIT $3",
+            webhook.into() as Snowflake<Webhook>,
+            before as Option<Snowflake<WebhookDelivery>>,
+            i64::from(limit)
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(WebhookDelivery::from_record).collect())
+    }
+
+    /// Fetch all feeds a channel is subscribed to.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_feeds(&self, channel: impl Into<Snowflake<Channel>>) -> Result<Vec<Feed>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            FeedRecord,
+            "SELECT * FROM channel_feeds WHERE channel_id = $1 ORDER BY id",
+            channel.into() as Snowflake<Channel>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(Feed::from_record).collect())
+    }
+
+    /// Fetch a channel feed from the database by ID.
+    ///
+    /// ## Returns
+    ///
+    /// The feed if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_feed(&self, feed: impl Into<Snowflake<Feed>>) -> Result<Option<Feed>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            FeedRecord,
+            "SELECT * FROM channel_feeds WHERE id = $1",
+            feed.into() as Snowflake<Feed>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(Feed::from_record))
+    }
+
+    /// Commit the channel feed to the database, including its poll state.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_feed(&self, feed: &Feed) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO channel_feeds (id, channel_id, url, title, poll_interval, enabled, failures, last_error, last_polled_at, next_poll_at, seen_entries)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (id) DO UPDATE
+            SET url = $3, title = $4, poll_interval = $5, enabled = $6, failures = $7, last_error = $8,
+            last_polled_at = $9, next_poll_at = $10, seen_entries = $11",
+            feed.id() as Snowflake<Feed>,
+            feed.channel_id() as Snowflake<Channel>,
+            feed.url(),
+            feed.title(),
+            feed.poll_interval() as i32,
+            feed.enabled(),
+            feed.failures(),
+            feed.last_error(),
+            feed.last_polled_at(),
+            feed.next_poll_at(),
+            feed.seen_entries(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Delete a channel feed from the database. Entries it already posted are kept.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_feed(&self, feed: impl Into<Snowflake<Feed>>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM channel_feeds WHERE id = $1",
+            feed.into() as Snowflake<Feed>
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch a channel follow from the database by ID.
+    ///
+    /// ## Returns
+    ///
+    /// The follow if found, otherwise `None`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_channel_follow(
+        &self,
+        follow: impl Into<Snowflake<ChannelFollow>>,
+    ) -> Result<Option<ChannelFollow>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            ChannelFollowRecord,
+            "SELECT * FROM channel_follows WHERE id = $1",
+            follow.into() as Snowflake<ChannelFollow>
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.as_ref().map(ChannelFollow::from_record))
+    }
+
+    /// Fetch the channels following an announcement channel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_channel_followers(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+    ) -> Result<Vec<ChannelFollow>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            ChannelFollowRecord,
+            "SELECT * FROM channel_follows WHERE channel_id = $1 ORDER BY id",
+            channel.into() as Snowflake<Channel>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.iter().map(ChannelFollow::from_record).collect())
+    }
+
+    /// Fetch the announcement channels a channel follows.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_channel_follows(
+        &self,
+        target: impl Into<Snowflake<Channel>>,
+    ) -> Result<Vec<ChannelFollow>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            ChannelFollowRecord,
+            "SELECT * FROM channel_follows WHERE target_channel_id = $1 ORDER BY id",
+            target.into() as Snowflake<Channel>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.iter().map(ChannelFollow::from_record).collect())
+    }
+
+    /// Commit a new channel follow to the database.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the follow was created, `false` if the target channel already follows the channel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_channel_follow(&self, follow: &ChannelFollow) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "INSERT INTO channel_follows (id, channel_id, target_channel_id, user_id)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (channel_id, target_channel_id) DO NOTHING",
+            follow.id() as Snowflake<ChannelFollow>,
+            follow.channel_id() as Snowflake<Channel>,
+            follow.target_channel_id() as Snowflake<Channel>,
+            follow.user_id() as Option<Snowflake<User>>,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete a channel follow from the database. Messages that are still queued are not crossposted,
+    /// messages that were already crossposted are kept.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_channel_follow(&self, follow: impl Into<Snowflake<ChannelFollow>>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM channel_follows WHERE id = $1",
+            follow.into() as Snowflake<ChannelFollow>
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Commit a new guild invite to the database.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the invite was created, `false` if the user already had a pending invite to the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_guild_invite(&self, invite: &GuildInvite) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "INSERT INTO guild_invites (guild_id, user_id, inviter_id, created_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (guild_id, user_id) DO NOTHING",
+            invite.guild().id() as Snowflake<Guild>,
+            invite.user_id() as Snowflake<User>,
+            invite.inviter_id() as Option<Snowflake<User>>,
+            invite.created_at(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Fetch all pending guild invites of a user. Deleted guilds are skipped.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_invites_for(&self, user: impl Into<Snowflake<User>>) -> Result<Vec<GuildInvite>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            ExtendedGuildInviteRecord,
+            "SELECT guild_invites.*, guilds.name, guilds.owner_id, guilds.avatar_hash, guilds.min_account_age, guilds.invite_only,
+            guilds.welcome_message, guilds.raid_mode_until, guilds.locale, guilds.timezone, guilds.version
+            FROM guild_invites
+            INNER JOIN guilds ON guilds.id = guild_invites.guild_id
+            WHERE guild_invites.user_id = $1 AND guilds.deleted_at IS NULL
+            ORDER BY guild_invites.created_at DESC",
+            user.into() as Snowflake<User>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(GuildInvite::from_extended_record).collect())
+    }
+
+    /// Check whether a user has a pending invite to a guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn has_guild_invite(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<bool, sqlx::Error> {
+        let record = sqlx::query!(
+            "SELECT EXISTS(SELECT 1 FROM guild_invites WHERE guild_id = $1 AND user_id = $2) AS \"invited!\"",
+            guild.into() as Snowflake<Guild>,
+            user.into() as Snowflake<User>,
+        )
+        .fetch_one(self.app.db.executor())
+        .await?;
+        Ok(record.invited)
+    }
+
+    /// Remove a pending guild invite. If the invite does not exist, does nothing.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_guild_invite(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM guild_invites WHERE guild_id = $1 AND user_id = $2",
+            guild.into() as Snowflake<Guild>,
+            user.into() as Snowflake<User>,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Commit a new guest link to the database.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_guest_link(&self, link: &GuestLink) -> Result<(), sqlx::Error> {
+        let channel_ids: Vec<i64> = link.channel_ids().iter().copied().map(i64::from).collect();
 
-        let mut user = old_user.clone();
-        user.update(payload)?;
+        sqlx::query!(
+            "INSERT INTO guest_links (code, guild_id, creator_id, channel_ids, duration, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+            link.code(),
+            link.guild_id() as Snowflake<Guild>,
+            link.creator_id() as Option<Snowflake<User>>,
+            &channel_ids,
+            i32::try_from(link.duration()).expect("Guest link duration should fit into i32"),
+            link.created_at(),
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
 
-        if old_user == user {
-            return Ok(user);
-        }
+    /// Fetch a guest link by its code. Links of deleted guilds are not returned.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_guest_link(&self, code: &str) -> Result<Option<GuestLink>, sqlx::Error> {
+        let record = sqlx::query_as!(
+            GuestLinkRecord,
+            "SELECT guest_links.* FROM guest_links
+            INNER JOIN guilds ON guilds.id = guest_links.guild_id AND guilds.deleted_at IS NULL
+            WHERE guest_links.code = $1",
+            code
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
 
-        if old_user.avatar() != user.avatar() {
-            old_user.avatar().map(|a| async { a.delete(&self.app.s3).await });
-            match user.avatar() {
-                Some(Avatar::Full(f)) => f.upload(&self.app.s3).await?,
-                _ => {
-                    Err(BuildError::ValidationError("Cannot upload partial avatar".into()))?;
-                }
+        Ok(record.map(GuestLink::from_record))
+    }
+
+    /// Fetch all guest links of a guild, oldest first.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_guest_links(&self, guild: impl Into<Snowflake<Guild>>) -> Result<Vec<GuestLink>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            GuestLinkRecord,
+            "SELECT * FROM guest_links WHERE guild_id = $1 ORDER BY created_at, code",
+            guild.into() as Snowflake<Guild>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(GuestLink::from_record).collect())
+    }
+
+    /// Delete a guest link of a guild. Members who joined through it stay until their membership expires.
+    ///
+    /// ## Returns
+    ///
+    /// `true` if the link was deleted, `false` if the guild has no link with the given code.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_guest_link(&self, guild: impl Into<Snowflake<Guild>>, code: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM guest_links WHERE guild_id = $1 AND code = $2",
+            guild.into() as Snowflake<Guild>,
+            code
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Commit an announcement to the database, so it is included in `READY` until it expires.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Build`] - If the announcement does not expire.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn create_announcement(&self, announcement: &Announcement) -> Result<(), AppError> {
+        let Some(expires_at) = announcement.expires_at() else {
+            return Err(BuildError::InvalidField {
+                field: "duration",
+                code: ErrorCode::Required,
+                message: "Persisted announcements must expire.".into(),
             }
-        }
+            .into());
+        };
+
+        sqlx::query!(
+            "INSERT INTO announcements (id, content, expires_at) VALUES ($1, $2, $3)",
+            announcement.id() as Snowflake<Announcement>,
+            announcement.content(),
+            expires_at,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch all announcements that did not expire yet, oldest first.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_active_announcements(&self) -> Result<Vec<Announcement>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            AnnouncementRecord,
+            "SELECT * FROM announcements WHERE expires_at > $1 ORDER BY id ASC",
+            self.app.clock.timestamp(),
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(Announcement::from_record).collect())
+    }
+
+    /// Delete all announcements that have expired.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of deleted announcements.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_expired_announcements(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM announcements WHERE expires_at <= $1",
+            self.app.clock.timestamp()
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Record a new gateway session served by this instance, replacing the previous session of the user on the same shard.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_gateway_session(&self, session: &GatewaySession) -> Result<(), sqlx::Error> {
+        let properties = session.properties();
+        sqlx::query!(
+            "INSERT INTO gateway_sessions
+            (user_id, shard_id, shard_count, id, instance_id, client_name, client_version, client_os, client_device)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (user_id, shard_id) DO UPDATE
+            SET shard_count = $3, id = $4, instance_id = $5, client_name = $6,
+            client_version = $7, client_os = $8, client_device = $9",
+            session.user_id() as Snowflake<User>,
+            session.shard().id() as i32,
+            session.shard().count() as i32,
+            session.id() as Snowflake<GatewaySession>,
+            self.app.instance.id(),
+            properties.name(),
+            properties.version(),
+            properties.os(),
+            properties.device() as i16,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Remove a gateway session that ended. Does nothing if it was already replaced by a newer session.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_gateway_session(&self, session: &GatewaySession) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM gateway_sessions WHERE user_id = $1 AND id = $2",
+            session.user_id() as Snowflake<User>,
+            session.id() as Snowflake<GatewaySession>,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch the gateway sessions of a user, oldest first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user to fetch the sessions of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_gateway_sessions(
+        &self,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Vec<GatewaySession>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            GatewaySessionRecord,
+            "SELECT id, user_id, shard_id, shard_count, client_name, client_version, client_os, client_device
+            FROM gateway_sessions WHERE user_id = $1 ORDER BY id ASC",
+            user.into() as Snowflake<User>,
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(GatewaySession::from_record).collect())
+    }
+
+    /// Delete the gateway sessions of instances that stopped without ending their sessions, such as after a crash.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of deleted sessions.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_orphaned_gateway_sessions(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM gateway_sessions WHERE NOT EXISTS (
+                SELECT 1 FROM instances
+                WHERE instances.instance_id = gateway_sessions.instance_id AND instances.last_heartbeat >= $1
+            )",
+            self.app.clock.timestamp() - INSTANCE_TIMEOUT,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Fetch the cached translation of a message, if it is up to date.
+    ///
+    /// ## Arguments
+    ///
+    /// * `message` - The ID of the translated message.
+    /// * `language` - The language the message was translated into.
+    /// * `source_hash` - The hash of the current content of the message.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_translation(
+        &self,
+        message: impl Into<Snowflake<Message>>,
+        language: &str,
+        source_hash: &[u8],
+    ) -> Result<Option<Translation>, sqlx::Error> {
+        let message_id = message.into();
+        let record = sqlx::query!(
+            "SELECT content, source_language FROM message_translations
+            WHERE message_id = $1 AND language = $2 AND source_hash = $3",
+            message_id as Snowflake<Message>,
+            language,
+            source_hash,
+        )
+        .fetch_optional(self.app.db.executor())
+        .await?;
+
+        Ok(record.map(|record| Translation {
+            message_id,
+            language: language.to_string(),
+            content: record.content,
+            source_language: record.source_language,
+        }))
+    }
+
+    /// Cache the translation of a message, replacing a previous translation into the same language.
+    ///
+    /// ## Arguments
+    ///
+    /// * `translation` - The translation to cache.
+    /// * `source_hash` - The hash of the content that was translated.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_translation(&self, translation: &Translation, source_hash: &[u8]) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "INSERT INTO message_translations (message_id, language, source_hash, content, source_language)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (message_id, language) DO UPDATE
+            SET source_hash = $3, content = $4, source_language = $5",
+            translation.message_id as Snowflake<Message>,
+            translation.language,
+            source_hash,
+            translation.content,
+            translation.source_language,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+        Ok(())
+    }
+
+    /// Fetch all devices a user registered for push notifications.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_push_devices(&self, user: impl Into<Snowflake<User>>) -> Result<Vec<PushDevice>, sqlx::Error> {
+        let records = sqlx::query_as!(
+            PushDeviceRecord,
+            "SELECT * FROM push_devices WHERE user_id = $1 ORDER BY id",
+            user.into() as Snowflake<User>
+        )
+        .fetch_all(self.app.db.executor())
+        .await?;
+
+        Ok(records.into_iter().map(PushDevice::from_record).collect())
+    }
+
+    /// Register a device for push notifications.
+    ///
+    /// A token can only be registered by one user at a time. If another user registered the same token,
+    /// for example because they logged out on a shared device, their registration is replaced.
+    ///
+    /// ## Returns
+    ///
+    /// The registered device, which is the existing device if the user already registered the token.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn create_push_device(&self, device: &PushDevice) -> Result<PushDevice, sqlx::Error> {
+        let mut tx = self.app.db.pool().begin().await?;
+
+        // Deleting the previous registration also drops the notifications still queued for its user
+        sqlx::query!(
+            "DELETE FROM push_devices WHERE platform = $1 AND token = $2 AND user_id <> $3",
+            device.platform() as i16,
+            device.token(),
+            device.user_id() as Snowflake<User>,
+        )
+        .execute(&mut *tx)
+        .await?;
 
         let record = sqlx::query_as!(
-            UserRecord,
-            "UPDATE users SET username = $2, display_name = $3, last_presence = $4, avatar_hash = $5
-            WHERE id = $1 RETURNING *",
-            user_id as Snowflake<User>,
-            user.username(),
-            user.display_name(),
-            *user.last_presence() as i16,
-            user.avatar().map(AvatarLike::avatar_hash),
+            PushDeviceRecord,
+            "INSERT INTO push_devices (id, user_id, platform, token, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (platform, token) DO UPDATE SET platform = EXCLUDED.platform
+            RETURNING *",
+            device.id() as Snowflake<PushDevice>,
+            device.user_id() as Snowflake<User>,
+            device.platform() as i16,
+            device.token(),
+            device.created_at(),
         )
-        .fetch_one(self.app.db.pool())
+        .fetch_one(&mut *tx)
         .await?;
-        Ok(User::from_record(record))
+
+        tx.commit().await?;
+        Ok(PushDevice::from_record(record))
     }
 
-    /// Commit the attachment to the database. Uploads the contents to S3 implicitly.
+    /// Remove a device a user registered for push notifications.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the user had registered the device.
     ///
     /// ## Errors
     ///
-    /// * [`AppError::S3`] - If the S3 request fails.
-    pub async fn create_attachment(&self, attachment: &FullAttachment) -> Result<(), AppError> {
-        attachment.upload(&self.app.s3).await?;
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_push_device(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        device: impl Into<Snowflake<PushDevice>>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "DELETE FROM push_devices WHERE user_id = $1 AND id = $2",
+            user.into() as Snowflake<User>,
+            device.into() as Snowflake<PushDevice>,
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
 
+    /// Fetch all setting overrides stored by operators.
+    ///
+    /// ## Returns
+    ///
+    /// The name and value of every override, including those of settings this version does not know.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn fetch_settings(&self) -> Result<Vec<(String, serde_json::Value)>, sqlx::Error> {
+        let records = sqlx::query!("SELECT key, value FROM settings ORDER BY key")
+            .fetch_all(self.app.db.executor())
+            .await?;
+
+        Ok(records.into_iter().map(|r| (r.key, r.value)).collect())
+    }
+
+    /// Store the override of a setting, replacing a previous one.
+    /// The override is not applied until the settings are refreshed.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn update_setting(&self, key: SettingKey, value: &serde_json::Value) -> Result<(), sqlx::Error> {
         sqlx::query!(
-            "INSERT INTO attachments (id, filename, message_id, channel_id, content_type)
-            VALUES ($1, $2, $3, $4, $5) 
-            ON CONFLICT (id, message_id) 
-            DO UPDATE SET filename = $2, content_type = $5",
-            i32::from(attachment.id()),
-            attachment.filename(),
-            attachment.message_id() as Snowflake<Message>,
-            attachment.channel_id() as Snowflake<Channel>,
-            attachment.mime().to_string(),
+            "INSERT INTO settings (key, value, updated_at) VALUES ($1, $2, $3)
+            ON CONFLICT (key) DO UPDATE SET value = $2, updated_at = $3",
+            key.name(),
+            value,
+            self.app.clock.timestamp(),
         )
-        .execute(self.app.db.pool())
+        .execute(self.app.db.executor())
         .await?;
 
         Ok(())
     }
+
+    /// Remove the override of a setting, so the configured value is used again.
+    /// The override stays in effect until the settings are refreshed.
+    ///
+    /// ## Returns
+    ///
+    /// Whether the setting was overridden.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn delete_setting(&self, key: SettingKey) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("DELETE FROM settings WHERE key = $1", key.name())
+            .execute(self.app.db.executor())
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
 }
@@ -0,0 +1,370 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    automod::MAX_TIMEOUT_DURATION,
+    errors::{BuildError, ErrorCode},
+    guild::Guild,
+    requests::CreateStrike,
+    snowflake::Snowflake,
+    state::Config,
+    user::User,
+};
+
+/// The maximum length of the reason of a strike, in characters.
+pub const MAX_REASON_LENGTH: usize = 1000;
+/// The highest amount of strikes a policy may act upon.
+pub const MAX_STRIKE_THRESHOLD: i32 = 100;
+/// The longest time a strike may stay active, one year.
+pub const MAX_STRIKE_DURATION: i64 = 365 * 24 * 60 * 60;
+
+/// Represents a strike record stored in the database.
+pub struct StrikeRecord {
+    pub id: Snowflake<Strike>,
+    pub guild_id: Snowflake<Guild>,
+    pub user_id: Snowflake<User>,
+    pub issuer_id: Option<i64>,
+    pub reason: Option<String>,
+    pub expires_at: Option<i64>,
+}
+
+/// A strike issued to a member of a guild, by a moderator or by automod.
+///
+/// Active strikes count towards the thresholds of the guild's [`StrikePolicy`].
+#[derive(Serialize, Debug, Clone)]
+pub struct Strike {
+    /// The ID of the strike. This also encodes when the strike was issued.
+    id: Snowflake<Self>,
+    /// The guild the strike was issued in.
+    guild_id: Snowflake<Guild>,
+    /// The user the strike was issued to.
+    user_id: Snowflake<User>,
+    /// The user who issued the strike, if any. Strikes issued by automod have no issuer.
+    issuer_id: Option<Snowflake<User>>,
+    /// Why the strike was issued.
+    reason: Option<String>,
+    /// UNIX timestamp of when the strike expires, if it does.
+    expires_at: Option<i64>,
+}
+
+impl Strike {
+    /// Create a new strike. Assigns a new snowflake to the strike.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration.
+    /// * `guild` - The guild the strike is issued in.
+    /// * `user` - The user the strike is issued to.
+    /// * `issuer` - The user issuing the strike, or `None` if it is issued by automod.
+    /// * `payload` - The reason and duration of the strike.
+    /// * `now` - The time the strike is issued at.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If the reason is too long, or the duration is out of range.
+    pub fn new(
+        config: &Config,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        issuer: Option<Snowflake<User>>,
+        payload: CreateStrike,
+        now: DateTime<Utc>,
+    ) -> Result<Self, BuildError> {
+        if payload
+            .reason
+            .as_ref()
+            .is_some_and(|reason| reason.chars().count() > MAX_REASON_LENGTH)
+        {
+            return Err(BuildError::InvalidField {
+                field: "reason",
+                code: ErrorCode::TooLong,
+                message: format!("Strike reason must be at most {MAX_REASON_LENGTH} characters."),
+            });
+        }
+        validate_duration("duration", payload.duration)?;
+
+        Ok(Self {
+            id: Snowflake::gen_new(config),
+            guild_id: guild.into(),
+            user_id: user.into(),
+            issuer_id: issuer,
+            reason: payload.reason.filter(|reason| !reason.trim().is_empty()),
+            expires_at: payload.duration.map(|duration| now.timestamp() + duration),
+        })
+    }
+
+    /// Build a strike directly from a database record.
+    pub fn from_record(record: StrikeRecord) -> Self {
+        Self {
+            id: record.id,
+            guild_id: record.guild_id,
+            user_id: record.user_id,
+            issuer_id: record.issuer_id.map(Snowflake::new),
+            reason: record.reason,
+            expires_at: record.expires_at,
+        }
+    }
+
+    /// The ID of the strike.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The guild the strike was issued in.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The user the strike was issued to.
+    pub const fn user_id(&self) -> Snowflake<User> {
+        self.user_id
+    }
+
+    /// The user who issued the strike, if any.
+    pub const fn issuer_id(&self) -> Option<Snowflake<User>> {
+        self.issuer_id
+    }
+
+    /// Why the strike was issued.
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    /// UNIX timestamp of when the strike expires, if it does.
+    pub const fn expires_at(&self) -> Option<i64> {
+        self.expires_at
+    }
+}
+
+impl From<&Strike> for Snowflake<Strike> {
+    fn from(strike: &Strike) -> Self {
+        strike.id()
+    }
+}
+
+/// Represents a strike policy record stored in the database.
+#[derive(Clone, Copy)]
+pub struct StrikePolicyRecord {
+    pub timeout_threshold: Option<i32>,
+    pub timeout_duration: Option<i64>,
+    pub ban_threshold: Option<i32>,
+    pub strike_duration: Option<i64>,
+    pub flag_evasion: bool,
+}
+
+/// How a guild acts upon the strikes of its members.
+///
+/// By default, strikes never expire and no action is taken automatically.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct StrikePolicy {
+    /// The amount of active strikes at which a member is timed out, if any.
+    timeout_threshold: Option<i32>,
+    /// The duration of the timeout in seconds.
+    timeout_duration: Option<i64>,
+    /// The amount of active strikes at which a member is banned, if any.
+    ban_threshold: Option<i32>,
+    /// How long strikes stay active in seconds, unless a duration is given when issuing them.
+    strike_duration: Option<i64>,
+    /// Whether new members sharing a device fingerprint with a banned user are flagged for review.
+    flag_evasion: bool,
+}
+
+/// An action taken automatically once a member reaches a threshold of a [`StrikePolicy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrikeAction {
+    /// The member is timed out for the given duration, in seconds.
+    Timeout(i64),
+    /// The member is removed from the guild, and may not join it again.
+    Ban,
+}
+
+impl StrikePolicy {
+    /// Build a strike policy directly from a database record.
+    pub const fn from_record(record: StrikePolicyRecord) -> Self {
+        Self {
+            timeout_threshold: record.timeout_threshold,
+            timeout_duration: record.timeout_duration,
+            ban_threshold: record.ban_threshold,
+            strike_duration: record.strike_duration,
+            flag_evasion: record.flag_evasion,
+        }
+    }
+
+    /// The amount of active strikes at which a member is timed out, if any.
+    pub const fn timeout_threshold(&self) -> Option<i32> {
+        self.timeout_threshold
+    }
+
+    /// The duration of the timeout in seconds.
+    pub const fn timeout_duration(&self) -> Option<i64> {
+        self.timeout_duration
+    }
+
+    /// The amount of active strikes at which a member is banned, if any.
+    pub const fn ban_threshold(&self) -> Option<i32> {
+        self.ban_threshold
+    }
+
+    /// How long strikes stay active in seconds, unless a duration is given when issuing them.
+    pub const fn strike_duration(&self) -> Option<i64> {
+        self.strike_duration
+    }
+
+    /// Whether new members sharing a device fingerprint with a banned user are flagged for review.
+    pub const fn flag_evasion(&self) -> bool {
+        self.flag_evasion
+    }
+
+    /// Ensure the policy is within the allowed bounds.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If a threshold or duration is out of range,
+    ///   or a timeout threshold is set without a timeout duration.
+    pub fn validate(&self) -> Result<(), BuildError> {
+        for (field, threshold) in [
+            ("timeout_threshold", self.timeout_threshold),
+            ("ban_threshold", self.ban_threshold),
+        ] {
+            if threshold.is_some_and(|threshold| !(1..=MAX_STRIKE_THRESHOLD).contains(&threshold)) {
+                return Err(BuildError::InvalidField {
+                    field,
+                    code: ErrorCode::OutOfRange,
+                    message: format!("Strike thresholds must be between 1 and {MAX_STRIKE_THRESHOLD}."),
+                });
+            }
+        }
+
+        if self.timeout_threshold.is_some() != self.timeout_duration.is_some() {
+            return Err(BuildError::InvalidField {
+                field: "timeout_duration",
+                code: ErrorCode::Required,
+                message: "Timeout threshold and timeout duration must be set together.".into(),
+            });
+        }
+        if self
+            .timeout_duration
+            .is_some_and(|duration| !(1..=MAX_TIMEOUT_DURATION).contains(&duration))
+        {
+            return Err(BuildError::InvalidField {
+                field: "timeout_duration",
+                code: ErrorCode::OutOfRange,
+                message: format!("Timeout duration must be between 1 and {MAX_TIMEOUT_DURATION} seconds."),
+            });
+        }
+
+        validate_duration("strike_duration", self.strike_duration)
+    }
+
+    /// The action to take against a member with the given amount of active strikes, if any.
+    ///
+    /// Members are banned once they reach the ban threshold, and timed out once they reach the timeout threshold.
+    /// Every strike beyond a threshold triggers its action again.
+    pub fn action_for(&self, active_strikes: usize) -> Option<StrikeAction> {
+        let reached = |threshold: Option<i32>| {
+            threshold.is_some_and(|threshold| usize::try_from(threshold).is_ok_and(|t| active_strikes >= t))
+        };
+
+        if reached(self.ban_threshold) {
+            return Some(StrikeAction::Ban);
+        }
+        match self.timeout_duration {
+            Some(duration) if reached(self.timeout_threshold) => Some(StrikeAction::Timeout(duration)),
+            _ => None,
+        }
+    }
+}
+
+/// Ensure the duration of a strike is within the allowed bounds.
+fn validate_duration(field: &'static str, duration: Option<i64>) -> Result<(), BuildError> {
+    if duration.is_some_and(|duration| !(1..=MAX_STRIKE_DURATION).contains(&duration)) {
+        return Err(BuildError::InvalidField {
+            field,
+            code: ErrorCode::OutOfRange,
+            message: format!("Strike duration must be between 1 and {MAX_STRIKE_DURATION} seconds."),
+        });
+    }
+    Ok(())
+}
+
+/// The standing of a member in a guild, as seen by the member.
+#[derive(Serialize, Debug, Clone)]
+pub struct Standing {
+    /// The guild the standing is in.
+    pub guild_id: Snowflake<Guild>,
+    /// The active strikes of the member, oldest first.
+    pub strikes: Vec<Strike>,
+    /// How the guild acts upon strikes.
+    pub policy: StrikePolicy,
+}
+
+/// Represents a guild ban record stored in the database.
+pub struct GuildBanRecord {
+    pub guild_id: Snowflake<Guild>,
+    pub user_id: Snowflake<User>,
+    pub reason: Option<String>,
+}
+
+/// A user who was banned from a guild, and may not join it again until the ban is lifted.
+#[derive(Serialize, Debug, Clone)]
+pub struct GuildBan {
+    /// The guild the user is banned from.
+    pub guild_id: Snowflake<Guild>,
+    /// The banned user.
+    pub user_id: Snowflake<User>,
+    /// Why the user was banned.
+    pub reason: Option<String>,
+}
+
+impl GuildBan {
+    /// Build a ban directly from a database record.
+    pub fn from_record(record: GuildBanRecord) -> Self {
+        Self {
+            guild_id: record.guild_id,
+            user_id: record.user_id,
+            reason: record.reason,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StrikeAction, StrikePolicy};
+
+    fn policy(timeout_threshold: Option<i32>, ban_threshold: Option<i32>) -> StrikePolicy {
+        StrikePolicy {
+            timeout_threshold,
+            timeout_duration: timeout_threshold.map(|_| 60),
+            ban_threshold,
+            strike_duration: None,
+            flag_evasion: false,
+        }
+    }
+
+    #[test]
+    fn test_action_for() {
+        let policy = policy(Some(2), Some(3));
+        assert_eq!(policy.action_for(1), None);
+        assert_eq!(policy.action_for(2), Some(StrikeAction::Timeout(60)));
+        assert_eq!(policy.action_for(3), Some(StrikeAction::Ban));
+        assert_eq!(policy.action_for(4), Some(StrikeAction::Ban));
+
+        assert_eq!(StrikePolicy::default().action_for(100), None);
+        assert_eq!(self::policy(None, Some(1)).action_for(1), Some(StrikeAction::Ban));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(StrikePolicy::default().validate().is_ok());
+        assert!(policy(Some(2), Some(3)).validate().is_ok());
+        assert!(policy(Some(0), None).validate().is_err());
+        assert!(policy(None, Some(101)).validate().is_err());
+
+        let missing_duration = StrikePolicy {
+            timeout_duration: None,
+            ..policy(Some(2), None)
+        };
+        assert!(missing_duration.validate().is_err());
+    }
+}
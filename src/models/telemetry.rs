@@ -0,0 +1,167 @@
+use std::{collections::HashMap, time::Duration};
+
+use axum::{
+    extract::MatchedPath,
+    http::{HeaderMap, Request, Response},
+};
+use opentelemetry::{
+    global,
+    propagation::Extractor,
+    trace::{TraceError, TracerProvider as _},
+    KeyValue,
+};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Tracer, TracerProvider},
+    Resource,
+};
+use tracing::{Span, Subscriber};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// The service name spans are reported under if `OTEL_SERVICE_NAME` is not set.
+const DEFAULT_SERVICE_NAME: &str = "chat-backend";
+/// The key of the W3C trace context in a carrier.
+const TRACEPARENT: &str = "traceparent";
+
+/// Create the provider that exports spans to an OpenTelemetry collector over OTLP/gRPC.
+///
+/// The exporter is configured through the standard `OTEL_*` environment variables,
+/// and is disabled unless `OTEL_EXPORTER_OTLP_ENDPOINT` or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT` is set.
+/// Must be called from within a Tokio runtime, as spans are exported in batches by a background task.
+///
+/// ## Returns
+///
+/// The provider, or `None` if tracing is disabled. It must be shut down before exiting to flush pending spans.
+///
+/// ## Errors
+///
+/// * [`TraceError`] - If the exporter could not be created.
+pub fn tracer_provider() -> Result<Option<TracerProvider>, TraceError> {
+    dotenvy::dotenv().ok();
+    if std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_none()
+        && std::env::var_os("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT").is_none()
+    {
+        return Ok(None);
+    }
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder().with_tonic().build()?;
+    // The default resource reads OTEL_SERVICE_NAME, but falls back to an unhelpful "unknown_service"
+    let resource = if std::env::var_os("OTEL_SERVICE_NAME").is_some() {
+        Resource::default()
+    } else {
+        Resource::new_with_defaults([KeyValue::new("service.name", DEFAULT_SERVICE_NAME)])
+    };
+
+    // Trace context is read from and written to carriers in the W3C format
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    Ok(Some(
+        TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(resource)
+            .build(),
+    ))
+}
+
+/// The layer that forwards spans to the given provider.
+pub fn layer<S>(provider: &TracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(DEFAULT_SERVICE_NAME))
+}
+
+/// Create the span of an HTTP request. If the request carries a W3C trace context, such as one
+/// started by a client or a reverse proxy, the span continues its trace.
+pub fn request_span<B>(request: &Request<B>) -> Span {
+    // The route does not contain IDs, so requests to the same endpoint are grouped together
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str);
+
+    let span = tracing::info_span!(
+        "request",
+        otel.name = format!("{} {route}", request.method()),
+        otel.kind = "server",
+        http.request.method = %request.method(),
+        http.route = route,
+        http.response.status_code = tracing::field::Empty,
+    );
+    span.set_parent(global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    }));
+    span
+}
+
+/// Record the status of the response to an HTTP request in its span.
+pub fn record_response<B>(response: &Response<B>, _latency: Duration, span: &Span) {
+    span.record("http.response.status_code", response.status().as_u16());
+}
+
+/// The W3C trace context of the current span, so its trace can be continued by a background task.
+///
+/// ## Returns
+///
+/// The `traceparent` of the current span, or `None` if tracing is disabled.
+pub fn current_trace_context() -> Option<String> {
+    let mut carrier = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&Span::current().context(), &mut carrier));
+    carrier.remove(TRACEPARENT)
+}
+
+/// Continue the trace of a W3C trace context returned by [`current_trace_context`] in the given span.
+pub fn continue_trace(span: &Span, traceparent: &str) {
+    let carrier = HashMap::from([(TRACEPARENT.to_string(), traceparent.to_string())]);
+    span.set_parent(global::get_text_map_propagator(|propagator| {
+        propagator.extract(&carrier)
+    }));
+}
+
+/// Reads the trace context of a request from its headers.
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(axum::http::HeaderName::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::{global, trace::TraceContextExt};
+    use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::TracerProvider};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::{continue_trace, current_trace_context, layer};
+
+    #[test]
+    fn test_continue_trace() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert_eq!(current_trace_context(), None);
+
+            let request = tracing::info_span!("request");
+            let trace_context = request
+                .in_scope(current_trace_context)
+                .expect("The span should have a trace context");
+
+            let job = tracing::info_span!(parent: None, "job");
+            continue_trace(&job, &trace_context);
+            assert_eq!(
+                job.context().span().span_context().trace_id(),
+                request.context().span().span_context().trace_id()
+            );
+        });
+    }
+}
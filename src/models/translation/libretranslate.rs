@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use reqwest::{header, Client};
+use secrecy::{ExposeSecret, Secret};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::models::errors::{AppError, BuildError, ErrorCode};
+
+use super::{TranslatedText, TranslationBackend};
+
+/// The language `LibreTranslate` detected the text to be in.
+#[derive(Deserialize)]
+struct DetectedLanguage {
+    language: String,
+}
+
+/// The body of a `LibreTranslate` translation response.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TranslateResponse {
+    translated_text: String,
+    detected_language: Option<DetectedLanguage>,
+}
+
+/// Translates text with a `LibreTranslate` instance, which can be self-hosted.
+#[derive(Debug)]
+pub struct LibreTranslateBackend {
+    client: Client,
+    url: String,
+    api_key: Option<Secret<String>>,
+}
+
+impl LibreTranslateBackend {
+    /// Create a new `LibreTranslate` backend.
+    ///
+    /// ## Arguments
+    ///
+    /// * `url` - The base URL of the `LibreTranslate` instance.
+    /// * `api_key` - The API key to authenticate with, if the instance requires one.
+    pub fn new(url: &str, api_key: Option<Secret<String>>) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(15))
+            .user_agent(concat!("chat-translate/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build translation HTTP client");
+
+        Self {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TranslationBackend for LibreTranslateBackend {
+    fn name(&self) -> &'static str {
+        "libretranslate"
+    }
+
+    async fn translate(&self, text: &str, target: &str) -> Result<TranslatedText, AppError> {
+        let body = json!({
+            "q": text,
+            "source": "auto",
+            "target": target,
+            "format": "text",
+            "api_key": self.api_key.as_ref().map(ExposeSecret::expose_secret),
+        });
+
+        let response = self
+            .client
+            .post(format!("{}/translate", self.url))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await?;
+
+        // LibreTranslate rejects languages it does not support with 400 Bad Request
+        if response.status() == reqwest::StatusCode::BAD_REQUEST {
+            return Err(BuildError::InvalidField {
+                field: "to",
+                code: ErrorCode::ValidationFailed,
+                message: format!("Messages cannot be translated into '{target}'."),
+            }
+            .into());
+        }
+
+        let response: TranslateResponse = serde_json::from_slice(&response.error_for_status()?.bytes().await?)?;
+
+        Ok(TranslatedText {
+            content: response.translated_text,
+            source_language: response.detected_language.map(|detected| detected.language),
+        })
+    }
+}
@@ -0,0 +1,125 @@
+mod libretranslate;
+
+use std::{fmt::Debug, sync::Arc};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+pub use self::libretranslate::LibreTranslateBackend;
+
+use super::{
+    errors::{AppError, BuildError, ErrorCode},
+    message::Message,
+    snowflake::Snowflake,
+    state::Config,
+};
+
+/// Text translated by a [`TranslationBackend`].
+#[derive(Debug, Clone)]
+pub struct TranslatedText {
+    /// The translated text.
+    pub content: String,
+    /// The language the text was detected to be in, if the backend reports it.
+    pub source_language: Option<String>,
+}
+
+/// Translates text between languages, such as with an external translation service.
+#[async_trait::async_trait]
+pub trait TranslationBackend: Debug + Send + Sync {
+    /// The name of the backend.
+    fn name(&self) -> &'static str;
+
+    /// Translate text into another language. The language of the text is detected by the backend.
+    ///
+    /// ## Arguments
+    ///
+    /// * `text` - The text to translate.
+    /// * `target` - The language code of the language to translate into, such as `en`.
+    async fn translate(&self, text: &str, target: &str) -> Result<TranslatedText, AppError>;
+}
+
+/// The content of a message, translated into another language.
+#[derive(Serialize, Debug, Clone)]
+pub struct Translation {
+    /// The message that was translated.
+    pub message_id: Snowflake<Message>,
+    /// The language code of the language the content was translated into.
+    pub language: String,
+    /// The translated content.
+    pub content: String,
+    /// The language code of the language the message was detected to be in, if known.
+    pub source_language: Option<String>,
+}
+
+/// Translates messages with the configured [`TranslationBackend`], if any.
+///
+/// Translations are cached in the database per message and language,
+/// together with a hash of the content they were translated from.
+#[derive(Debug, Clone)]
+pub struct Translator {
+    backend: Option<Arc<dyn TranslationBackend>>,
+}
+
+impl Translator {
+    /// Create a new translator, backed by `LibreTranslate` if it is configured. Otherwise, translations are disabled.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            backend: config.libretranslate_url().map(|url| {
+                Arc::new(LibreTranslateBackend::new(
+                    url,
+                    config.libretranslate_api_key().cloned(),
+                )) as Arc<dyn TranslationBackend>
+            }),
+        }
+    }
+
+    /// The configured backend, or `None` if translations are disabled.
+    pub fn backend(&self) -> Option<&dyn TranslationBackend> {
+        self.backend.as_deref()
+    }
+}
+
+/// Hash the content of a message, to tell whether a cached translation is still up to date.
+pub fn source_hash(content: &str) -> Vec<u8> {
+    Sha256::digest(content.as_bytes()).to_vec()
+}
+
+/// Ensure a language code looks like an ISO 639 code, optionally followed by a script or region, such as `zh-Hant`.
+///
+/// ## Errors
+///
+/// * [`BuildError::InvalidField`] - If the language code is malformed.
+pub fn validate_language(language: &str) -> Result<(), BuildError> {
+    let (code, subtag) = language
+        .split_once('-')
+        .map_or((language, None), |(code, subtag)| (code, Some(subtag)));
+
+    let is_valid = (2..=3).contains(&code.len())
+        && code.chars().all(|c| c.is_ascii_lowercase())
+        && subtag
+            .is_none_or(|subtag| (2..=4).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphabetic()));
+
+    if !is_valid {
+        return Err(BuildError::InvalidField {
+            field: "to",
+            code: ErrorCode::ValidationFailed,
+            message: "Language must be a language code, such as 'en' or 'zh-Hant'.".into(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_language;
+
+    #[test]
+    fn test_validate_language() {
+        for language in ["en", "pt", "zh-Hant", "pt-BR", "fil"] {
+            assert!(validate_language(language).is_ok(), "{language} should be valid");
+        }
+        for language in ["", "e", "EN", "english", "zh-", "zh-Hant-TW", "en_US", "../en"] {
+            assert!(validate_language(language).is_err(), "{language} should be invalid");
+        }
+    }
+}
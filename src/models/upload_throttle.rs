@@ -0,0 +1,100 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+
+use super::{snowflake::Snowflake, user::User};
+
+/// How much time worth of bandwidth a user may use in a single burst.
+const BURST: Duration = Duration::from_secs(1);
+/// The amount of tracked users after which idle users are pruned.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Limits the rate at which each user may upload data, shared across all of a user's requests.
+#[derive(Debug, Clone, Default)]
+pub struct UploadThrottle {
+    /// The maximum upload rate per user in bytes per second, or `0` if uploads are not throttled.
+    /// Shared with all clones of the throttle.
+    rate: Arc<AtomicU64>,
+    /// The point in time until which each user's upload bandwidth is used up.
+    reserved_until: DashMap<Snowflake<User>, Instant>,
+}
+
+impl UploadThrottle {
+    /// Create a new upload throttle.
+    ///
+    /// ## Arguments
+    ///
+    /// * `rate` - The maximum upload rate per user in bytes per second. `0` disables throttling.
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: Arc::new(AtomicU64::new(rate)),
+            reserved_until: DashMap::new(),
+        }
+    }
+
+    /// Change the maximum upload rate per user in bytes per second. `0` disables throttling.
+    /// Uploads that are already in progress are throttled at the new rate from their next chunk.
+    pub fn set_rate(&self, rate: u64) {
+        self.rate.store(rate, Ordering::Relaxed);
+    }
+
+    /// Wrap a stream of uploaded data, delaying chunks so the user does not exceed the upload rate.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user uploading the data.
+    /// * `stream` - The stream of uploaded data, such as a multipart field.
+    pub fn throttle<'a, S, E>(
+        &'a self,
+        user: impl Into<Snowflake<User>>,
+        stream: S,
+    ) -> impl Stream<Item = Result<Bytes, E>> + 'a
+    where
+        S: Stream<Item = Result<Bytes, E>> + 'a,
+        E: 'a,
+    {
+        let user = user.into();
+        stream.then(move |chunk| async move {
+            if let Ok(bytes) = &chunk {
+                self.acquire(user, bytes.len()).await;
+            }
+            chunk
+        })
+    }
+
+    /// Reserve bandwidth for the given amount of bytes, waiting until the user may upload them.
+    async fn acquire(&self, user: Snowflake<User>, bytes: usize) {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+
+        if self.reserved_until.len() > PRUNE_THRESHOLD {
+            self.reserved_until.retain(|_, until| *until > now);
+        }
+
+        let cost = Duration::from_secs_f64(bytes as f64 / rate as f64);
+
+        let reserved_until = {
+            let mut until = self.reserved_until.entry(user).or_insert(now);
+            *until = (*until).max(now) + cost;
+            *until
+        };
+
+        // Users may get ahead of their rate by up to one burst
+        let allowed_until = now + BURST;
+        if reserved_until > allowed_until {
+            tokio::time::sleep(reserved_until.duration_since(allowed_until)).await;
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::{hash::Hash, sync::LazyLock};
 
+use bitflags::bitflags;
 use chrono::prelude::*;
 use chrono::DateTime;
 use derive_builder::Builder;
@@ -10,7 +11,7 @@ use crate::gateway::handler::Gateway;
 
 use super::{
     avatar::{Avatar, FullAvatar, PartialAvatar, UserAvatar},
-    errors::BuildError,
+    errors::{BuildError, ErrorCode},
     requests::{CreateUser, UpdateUser},
     snowflake::Snowflake,
     state::Config,
@@ -21,12 +22,28 @@ static USERNAME_REGEX: LazyLock<Regex> = LazyLock::new(|| {
         .expect("Failed to compile username regex")
 });
 
+/// The shortest query accepted when searching users by username, shorter queries would match too many users.
+pub const MIN_SEARCH_QUERY_LENGTH: usize = 3;
+/// The longest query accepted when searching users by username, the same as the longest username.
+pub const MAX_SEARCH_QUERY_LENGTH: usize = 32;
+
+bitflags! {
+    /// Flags set on a user by the server operator. Users cannot change these themselves.
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct UserFlags: i64 {
+        /// Not subject to per-user limits, such as `MAX_GUILDS_PER_USER`.
+        /// Meant for the accounts of administrators and bots.
+        const EXEMPT_FROM_LIMITS = 1;
+    }
+}
+
 /// Represents the presence of a user.
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 #[repr(i16)]
 pub enum Presence {
     /// The user is currently active.
+    #[default]
     Online = 0,
     /// The user is idle or away from the keyboard.
     Away = 1,
@@ -47,9 +64,70 @@ impl From<i16> for Presence {
     }
 }
 
-impl Default for Presence {
-    fn default() -> Self {
-        Self::Online
+/// The kind of activity a user is engaged in.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ActivityType {
+    /// The user is playing a game.
+    Playing,
+    /// The user is streaming.
+    Streaming,
+    /// The user is listening to something, for example music.
+    Listening,
+    /// The user is watching something.
+    Watching,
+    /// The user is competing in something.
+    Competing,
+}
+
+/// A structured "currently playing" status of a user, set by integrations over the gateway.
+///
+/// Activities are only kept in memory for the duration of the user's gateway session.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Activity {
+    /// The name of the activity, e.g. the name of the game.
+    name: String,
+    /// The kind of activity.
+    #[serde(rename = "type")]
+    kind: ActivityType,
+    /// UNIX timestamp of when the activity started, if known.
+    started_at: Option<i64>,
+}
+
+impl Activity {
+    /// The name of the activity, e.g. the name of the game.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The kind of activity.
+    pub const fn kind(&self) -> ActivityType {
+        self.kind
+    }
+
+    /// UNIX timestamp of when the activity started, if known.
+    pub const fn started_at(&self) -> Option<i64> {
+        self.started_at
+    }
+
+    /// Validate the activity.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the name is empty or longer than 128 characters,
+    ///   or if the start time is later than `now`.
+    pub fn validate(&self, now: DateTime<Utc>) -> Result<(), BuildError> {
+        if self.name.trim().is_empty() || self.name.chars().count() > 128 {
+            return Err(BuildError::ValidationError(
+                "Activity name must be between 1 and 128 characters long".into(),
+            ));
+        }
+        if self.started_at.is_some_and(|t| t > now.timestamp()) {
+            return Err(BuildError::ValidationError(
+                "Activity cannot start in the future".into(),
+            ));
+        }
+        Ok(())
     }
 }
 
@@ -64,6 +142,7 @@ pub struct UserRecord {
 
 #[derive(Serialize, Debug, Clone, PartialEq, Eq, Builder)]
 #[builder(setter(into), build_fn(error = "BuildError"))]
+#[allow(clippy::use_self)] // Self would refer to the builder in the generated code
 pub struct User {
     /// The snowflake belonging to this user.
     id: Snowflake<User>,
@@ -88,6 +167,10 @@ pub struct User {
     #[serde(rename = "presence")]
     #[builder(setter(skip), default)]
     displayed_presence: Option<Presence>,
+    /// Is 'null' in all cases except when the user is sent in a `GUILD_CREATE` event and has an activity.
+    #[serde(rename = "activity")]
+    #[builder(setter(skip), default)]
+    displayed_activity: Option<Activity>,
 }
 
 impl User {
@@ -117,7 +200,7 @@ impl User {
     }
 
     /// The user's display name. This is the same as the username unless the user has changed it.
-    pub fn display_name_mut(&mut self) -> Option<&mut String> {
+    pub const fn display_name_mut(&mut self) -> Option<&mut String> {
         self.display_name.as_mut()
     }
 
@@ -161,6 +244,7 @@ impl User {
             avatar: None,
             last_presence: Presence::default(),
             displayed_presence: None,
+            displayed_activity: None,
         })
     }
 
@@ -177,6 +261,7 @@ impl User {
             display_name: record.display_name,
             last_presence: Presence::from(record.last_presence),
             displayed_presence: None,
+            displayed_activity: None,
         }
     }
 
@@ -213,12 +298,33 @@ impl User {
         Ok(has_avatar_changed)
     }
 
-    /// Transform this object to also include the user's presence.
+    /// Retrieve the user's activity. Users who appear offline never have an activity.
+    pub fn activity(&self, gateway: &Gateway) -> Option<Activity> {
+        if *self.presence(gateway) == Presence::Offline {
+            None
+        } else {
+            gateway.activity_of(self.id())
+        }
+    }
+
+    /// Transform this object to also include the user's presence and activity.
     #[must_use]
     pub fn include_presence(self, gateway: &Gateway) -> Self {
-        let presence = self.presence(gateway);
+        let presence = *self.presence(gateway);
+        let activity = self.activity(gateway);
+        Self {
+            displayed_presence: Some(presence),
+            displayed_activity: activity,
+            ..self
+        }
+    }
+
+    /// Transform this object to include the user's presence as it appears to users they hide it from.
+    #[must_use]
+    pub fn include_hidden_presence(self) -> Self {
         Self {
-            displayed_presence: Some(*presence),
+            displayed_presence: Some(Presence::Offline),
+            displayed_activity: None,
             ..self
         }
     }
@@ -236,6 +342,17 @@ impl User {
         Ok(())
     }
 
+    /// Normalize a username for comparison against reserved usernames.
+    /// Reserved usernames are matched case-insensitively and regardless of separators,
+    /// so `Ad_min` matches the reserved username `admin`.
+    pub fn normalize_username(username: &str) -> String {
+        username
+            .chars()
+            .filter(|c| !matches!(c, '.' | '_'))
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+
     fn validate_username(username: &str) -> Result<&str, BuildError> {
         if !USERNAME_REGEX.is_match(username) {
             return Err(BuildError::ValidationError(format!(
@@ -269,3 +386,44 @@ impl From<&mut User> for Snowflake<User> {
         user.id()
     }
 }
+
+/// Ensure a username search query can be run.
+///
+/// ## Errors
+///
+/// * [`BuildError::InvalidField`] - If the query is too short or too long, or contains characters
+///   that are not allowed in usernames.
+pub fn validate_search_query(query: &str) -> Result<(), BuildError> {
+    if !(MIN_SEARCH_QUERY_LENGTH..=MAX_SEARCH_QUERY_LENGTH).contains(&query.len()) {
+        return Err(BuildError::InvalidField {
+            field: "username",
+            code: ErrorCode::InvalidLength,
+            message: format!(
+                "Username query must be between {MIN_SEARCH_QUERY_LENGTH} and {MAX_SEARCH_QUERY_LENGTH} characters."
+            ),
+        });
+    }
+    if !query.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_') {
+        return Err(BuildError::InvalidField {
+            field: "username",
+            code: ErrorCode::ValidationFailed,
+            message: "Username query may only contain letters, digits, periods and underscores.".into(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_search_query;
+
+    #[test]
+    fn test_validate_search_query() {
+        assert!(validate_search_query("ali").is_ok());
+        assert!(validate_search_query("Alice_W.").is_ok());
+        assert!(validate_search_query("al").is_err());
+        assert!(validate_search_query(&"a".repeat(33)).is_err());
+        assert!(validate_search_query("ali%").is_err());
+        assert!(validate_search_query("ali ce").is_err());
+    }
+}
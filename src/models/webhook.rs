@@ -0,0 +1,681 @@
+use std::{
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use futures::StreamExt;
+use reqwest::{header, Client, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+
+use super::{
+    errors::{AppError, BuildError, RESTError},
+    guild::Guild,
+    media_proxy::{ensure_public_host, ensure_public_url, public_client, sign, PublicResolver},
+    requests::{CreateWebhook, UpdateWebhook},
+    snowflake::Snowflake,
+    state::{ApplicationState, Config},
+};
+
+/// The maximum amount of webhooks a guild may have.
+pub const MAX_WEBHOOKS_PER_GUILD: usize = 10;
+/// The maximum length of a webhook URL.
+const MAX_URL_LEN: usize = 2048;
+/// The maximum amount of deliveries attempted at once.
+const BATCH_SIZE: i64 = 50;
+/// The maximum amount of deliveries sent concurrently.
+const CONCURRENCY: usize = 8;
+/// How long a claimed delivery is reserved for a delivery attempt, in seconds.
+/// Deliveries interrupted by a crash are retried after this period.
+const LEASE_DURATION: i64 = 60;
+/// The maximum amount of delivery attempts before a delivery is marked as failed.
+const MAX_ATTEMPTS: i32 = 5;
+/// The delay before the first retry in seconds. Each further retry waits 4 times as long.
+const RETRY_BASE_DELAY: i64 = 30;
+/// How long finished deliveries are kept in the delivery log, in seconds. (7 days)
+const RETENTION: i64 = 7 * 24 * 60 * 60;
+/// The maximum length of a recorded delivery error.
+const MAX_ERROR_LEN: usize = 500;
+
+/// An event in a guild that webhooks can subscribe to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[repr(i16)]
+pub enum WebhookEvent {
+    /// An event that is not known to this version of the server.
+    #[serde(skip_deserializing)]
+    Unknown = -1,
+    /// A user joined the guild.
+    MemberJoin = 0,
+    /// A user left the guild.
+    MemberLeave = 1,
+}
+
+impl WebhookEvent {
+    /// The name of the event, as sent to webhooks.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Unknown => "UNKNOWN",
+            Self::MemberJoin => "MEMBER_JOIN",
+            Self::MemberLeave => "MEMBER_LEAVE",
+        }
+    }
+}
+
+impl From<i16> for WebhookEvent {
+    fn from(event: i16) -> Self {
+        match event {
+            0 => Self::MemberJoin,
+            1 => Self::MemberLeave,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Represents a webhook record stored in the database.
+pub struct WebhookRecord {
+    pub id: Snowflake<Webhook>,
+    pub guild_id: Snowflake<Guild>,
+    pub name: String,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<i16>,
+    pub enabled: bool,
+}
+
+/// An outgoing webhook, notifying an external service about events in a guild.
+#[derive(Serialize, Debug, Clone)]
+pub struct Webhook {
+    /// The ID of the webhook.
+    id: Snowflake<Self>,
+    /// The guild this webhook belongs to.
+    guild_id: Snowflake<Guild>,
+    /// The name of the webhook.
+    name: String,
+    /// The URL events are delivered to.
+    url: String,
+    /// The secret used to sign deliveries.
+    secret: String,
+    /// The events this webhook is subscribed to.
+    events: Vec<WebhookEvent>,
+    /// Whether the webhook is currently enabled.
+    enabled: bool,
+}
+
+impl Webhook {
+    /// The ID of the webhook.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The guild this webhook belongs to.
+    pub const fn guild_id(&self) -> Snowflake<Guild> {
+        self.guild_id
+    }
+
+    /// The name of the webhook.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The URL events are delivered to.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The secret used to sign deliveries.
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// The events this webhook is subscribed to.
+    pub fn events(&self) -> &[WebhookEvent] {
+        &self.events
+    }
+
+    /// Whether the webhook is currently enabled.
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Build a webhook from a database record.
+    pub fn from_record(record: WebhookRecord) -> Self {
+        Self {
+            id: record.id,
+            guild_id: record.guild_id,
+            name: record.name,
+            url: record.url,
+            secret: record.secret,
+            events: record.events.into_iter().map(WebhookEvent::from).collect(),
+            enabled: record.enabled,
+        }
+    }
+
+    /// Create a new webhook from a creation payload. Assigns a new snowflake and a random secret to the webhook.
+    ///
+    /// ## Arguments
+    ///
+    /// * `config` - The application configuration.
+    /// * `guild` - The guild the webhook belongs to.
+    /// * `payload` - The payload to create the webhook from.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the payload is invalid.
+    pub fn from_payload(
+        config: &Config,
+        guild: impl Into<Snowflake<Guild>>,
+        payload: CreateWebhook,
+    ) -> Result<Self, BuildError> {
+        let mut webhook = Self {
+            id: Snowflake::gen_new(config),
+            guild_id: guild.into(),
+            name: payload.name,
+            url: payload.url,
+            secret: hex::encode(rand::random::<[u8; 32]>()),
+            events: payload.events,
+            enabled: payload.enabled.unwrap_or(true),
+        };
+        webhook.validate()?;
+        Ok(webhook)
+    }
+
+    /// Update the webhook with the given payload.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::ValidationError`] - If the resulting webhook is invalid.
+    pub fn update(&mut self, payload: UpdateWebhook) -> Result<(), BuildError> {
+        if let Some(name) = payload.name {
+            self.name = name;
+        }
+        if let Some(url) = payload.url {
+            self.url = url;
+        }
+        if let Some(events) = payload.events {
+            self.events = events;
+        }
+        if let Some(enabled) = payload.enabled {
+            self.enabled = enabled;
+        }
+        if payload.reset_secret.unwrap_or(false) {
+            self.secret = hex::encode(rand::random::<[u8; 32]>());
+        }
+        self.validate()
+    }
+
+    /// Ensure that the webhook URL points to a publicly routable host.
+    ///
+    /// ## Errors
+    ///
+    /// * [`RESTError::BadRequest`] - If the URL is malformed or its host cannot be resolved.
+    /// * [`RESTError::Forbidden`] - If the URL points to a non-public address.
+    pub async fn ensure_public_url(&self) -> Result<(), RESTError> {
+        let url = Url::parse(&self.url).map_err(|_| RESTError::MalformedField("url".into()))?;
+        ensure_public_host(&url).await
+    }
+
+    /// Validate the webhook.
+    fn validate(&mut self) -> Result<(), BuildError> {
+        self.name = self.name.trim().to_string();
+
+        if self.name.is_empty() || self.name.len() > 100 {
+            return Err(BuildError::ValidationError(
+                "Webhook name must be between 1 and 100 characters long".into(),
+            ));
+        }
+
+        if self.url.len() > MAX_URL_LEN {
+            return Err(BuildError::ValidationError(format!(
+                "Webhook URL must be at most {MAX_URL_LEN} characters long"
+            )));
+        }
+
+        match Url::parse(&self.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => {
+                return Err(BuildError::ValidationError(
+                    "Webhook URL must be a valid HTTP(S) URL".into(),
+                ))
+            }
+        }
+
+        self.events.sort_unstable_by_key(|event| *event as i16);
+        self.events.dedup();
+
+        if self.events.is_empty() || self.events.contains(&WebhookEvent::Unknown) {
+            return Err(BuildError::ValidationError(
+                "Webhook must subscribe to at least one known event".into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// The state of a webhook delivery.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum DeliveryState {
+    /// The delivery has not succeeded yet, but will be attempted again.
+    Pending,
+    /// The delivery was accepted by the receiving service.
+    Delivered,
+    /// All delivery attempts failed, the delivery is not retried.
+    Failed,
+}
+
+/// Represents a webhook delivery record stored in the database.
+pub struct WebhookDeliveryRecord {
+    pub id: Snowflake<WebhookDelivery>,
+    pub webhook_id: Snowflake<Webhook>,
+    pub event: i16,
+    pub attempts: i32,
+    pub next_attempt_at: Option<i64>,
+    pub response_status: Option<i16>,
+    pub error: Option<String>,
+    pub delivered_at: Option<i64>,
+    pub created_at: i64,
+}
+
+/// An entry in a webhook's delivery log.
+#[derive(Serialize, Debug, Clone)]
+pub struct WebhookDelivery {
+    /// The ID of the delivery. This is also sent to the receiving service.
+    id: Snowflake<Self>,
+    /// The webhook the event is delivered to.
+    webhook_id: Snowflake<Webhook>,
+    /// The event being delivered.
+    event: WebhookEvent,
+    /// The state of the delivery.
+    state: DeliveryState,
+    /// The amount of delivery attempts made so far.
+    attempts: i32,
+    /// The HTTP status returned by the receiving service on the last attempt, if any.
+    response_status: Option<i16>,
+    /// The reason the last attempt failed, if it did.
+    error: Option<String>,
+    /// UNIX timestamp of when the event happened.
+    created_at: i64,
+    /// UNIX timestamp of when the event was delivered, if it was.
+    delivered_at: Option<i64>,
+    /// UNIX timestamp of the next delivery attempt, if the delivery is pending.
+    next_attempt_at: Option<i64>,
+}
+
+impl WebhookDelivery {
+    /// Build a delivery log entry from a database record.
+    pub fn from_record(record: WebhookDeliveryRecord) -> Self {
+        let state = if record.delivered_at.is_some() {
+            DeliveryState::Delivered
+        } else if record.next_attempt_at.is_some() {
+            DeliveryState::Pending
+        } else {
+            DeliveryState::Failed
+        };
+
+        Self {
+            id: record.id,
+            webhook_id: record.webhook_id,
+            event: WebhookEvent::from(record.event),
+            state,
+            attempts: record.attempts,
+            response_status: record.response_status,
+            error: record.error,
+            created_at: record.created_at,
+            delivered_at: record.delivered_at,
+            next_attempt_at: record.next_attempt_at,
+        }
+    }
+
+    /// The ID of the delivery.
+    pub const fn id(&self) -> Snowflake<Self> {
+        self.id
+    }
+
+    /// The state of the delivery.
+    pub const fn state(&self) -> DeliveryState {
+        self.state
+    }
+}
+
+/// A delivery claimed for a delivery attempt.
+struct ClaimedDelivery {
+    id: i64,
+    event: i16,
+    payload: String,
+    attempts: i32,
+    guild_id: i64,
+    url: String,
+    secret: String,
+}
+
+/// Delivers guild events to webhooks in the background.
+///
+/// Events are stored in the delivery log first, and sent by a background task.
+/// Failed deliveries are retried with exponential backoff, up to [`MAX_ATTEMPTS`] times.
+#[derive(Debug, Clone)]
+pub struct WebhookDispatcher {
+    client: Client,
+    notify: Arc<Notify>,
+    app: Weak<ApplicationState>,
+}
+
+impl WebhookDispatcher {
+    /// Create a new webhook dispatcher.
+    ///
+    /// Note: The dispatcher only connects to publicly routable addresses, and does not follow redirects
+    /// to avoid being pointed at internal hosts.
+    pub fn new() -> Self {
+        Self::with_resolver(PublicResolver::new())
+    }
+
+    /// Create a new webhook dispatcher connecting to the addresses returned by the given resolver.
+    pub fn with_resolver(resolver: PublicResolver) -> Self {
+        let client = public_client(resolver)
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("chat-webhooks/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("Failed to build webhook HTTP client");
+
+        Self {
+            client,
+            notify: Arc::new(Notify::new()),
+            app: Weak::new(),
+        }
+    }
+
+    pub fn bind_to(&mut self, app: Weak<ApplicationState>) {
+        self.app = app;
+    }
+
+    pub fn app(&self) -> Arc<ApplicationState> {
+        self.app.upgrade().expect("Application state has been dropped.")
+    }
+
+    /// Queue an event for delivery to all enabled webhooks of a guild subscribed to it.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the event happened in.
+    /// * `event` - The type of event.
+    /// * `data` - The event data, sent as the `data` field of the delivery.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::JSON`] - If the event data could not be serialized.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn enqueue(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        event: WebhookEvent,
+        data: &impl Serialize,
+    ) -> Result<(), AppError> {
+        let app = self.app();
+
+        let webhook_ids: Vec<i64> = sqlx::query!(
+            "SELECT id FROM webhooks WHERE guild_id = $1 AND enabled AND $2 = ANY(events)",
+            guild.into() as Snowflake<Guild>,
+            event as i16,
+        )
+        .fetch_all(app.db.executor())
+        .await?
+        .into_iter()
+        .map(|r| r.id)
+        .collect();
+
+        if webhook_ids.is_empty() {
+            return Ok(());
+        }
+
+        let ids: Vec<i64> = webhook_ids
+            .iter()
+            .map(|_| Snowflake::<WebhookDelivery>::gen_new(&app.config).into())
+            .collect();
+        let now = app.clock.timestamp();
+
+        sqlx::query!(
+            "INSERT INTO webhook_deliveries (id, webhook_id, event, payload, next_attempt_at, created_at)
+            SELECT d.id, d.webhook_id, $3, $4, $5, $5
+            FROM UNNEST($1::BIGINT[], $2::BIGINT[]) AS d(id, webhook_id)",
+            &ids,
+            &webhook_ids,
+            event as i16,
+            serde_json::to_string(data)?,
+            now,
+        )
+        .execute(app.db.executor())
+        .await?;
+
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// Wait until new events are queued.
+    pub async fn notified(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Attempt all deliveries that are due.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of delivery attempts made.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn deliver_pending(&self) -> Result<usize, sqlx::Error> {
+        let app = self.app();
+        let mut attempted = 0;
+
+        loop {
+            let now = app.clock.timestamp();
+
+            // Claim due deliveries, so they are not attempted twice if another attempt is still running
+            let claimed = sqlx::query_as!(
+                ClaimedDelivery,
+                "WITH due AS (
+                    SELECT id FROM webhook_deliveries
+                    WHERE next_attempt_at <= $1
+                    ORDER BY next_attempt_at LIMIT $2
+                    FOR UPDATE SKIP LOCKED
+                )
+                UPDATE webhook_deliveries AS d
+                SET next_attempt_at = $3, attempts = d.attempts + 1
+                FROM due, webhooks AS w
+                WHERE d.id = due.id AND w.id = d.webhook_id
+                RETURNING d.id, d.event, d.payload, d.attempts, w.guild_id, w.url, w.secret",
+                now,
+                BATCH_SIZE,
+                now + LEASE_DURATION,
+            )
+            .fetch_all(app.db.executor())
+            .await?;
+
+            let count = claimed.len();
+
+            futures::stream::iter(claimed)
+                .for_each_concurrent(CONCURRENCY, |delivery| async move {
+                    let id = delivery.id;
+                    if let Err(e) = self.attempt(delivery).await {
+                        tracing::error!(delivery = id, error = %e, "Failed to record webhook delivery");
+                    }
+                })
+                .await;
+
+            attempted += count;
+
+            if count < usize::try_from(BATCH_SIZE).expect("Batch size should fit into usize") {
+                return Ok(attempted);
+            }
+        }
+    }
+
+    /// Attempt a single delivery and record its outcome.
+    async fn attempt(&self, delivery: ClaimedDelivery) -> Result<(), sqlx::Error> {
+        let (status, result) = self.send(&delivery).await;
+        let now = self.app().clock.timestamp();
+
+        match result {
+            Ok(()) => {
+                sqlx::query!(
+                    "UPDATE webhook_deliveries
+                    SET delivered_at = $2, next_attempt_at = NULL, response_status = $3, error = NULL
+                    WHERE id = $1",
+                    delivery.id,
+                    now,
+                    status,
+                )
+                .execute(self.app().db.executor())
+                .await?;
+            }
+            Err(mut error) => {
+                tracing::debug!(delivery = delivery.id, error, "Webhook delivery failed");
+                error.truncate(error.floor_char_boundary(MAX_ERROR_LEN));
+
+                let next_attempt_at = (delivery.attempts < MAX_ATTEMPTS)
+                    .then(|| now + RETRY_BASE_DELAY * 4_i64.pow(delivery.attempts.max(1).unsigned_abs() - 1));
+
+                sqlx::query!(
+                    "UPDATE webhook_deliveries
+                    SET next_attempt_at = $2, response_status = $3, error = $4
+                    WHERE id = $1",
+                    delivery.id,
+                    next_attempt_at,
+                    status,
+                    error,
+                )
+                .execute(self.app().db.executor())
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a delivery to its webhook.
+    ///
+    /// ## Returns
+    ///
+    /// The HTTP status returned by the receiving service, if any,
+    /// and whether the delivery succeeded, with the reason if it did not.
+    async fn send(&self, delivery: &ClaimedDelivery) -> (Option<i16>, Result<(), String>) {
+        self.post(delivery, self.app().clock.timestamp()).await
+    }
+
+    /// Sign a delivery with the given timestamp and post it to its webhook.
+    ///
+    /// ## Returns
+    ///
+    /// The HTTP status returned by the receiving service, if any,
+    /// and whether the delivery succeeded, with the reason if it did not.
+    async fn post(&self, delivery: &ClaimedDelivery, timestamp: i64) -> (Option<i16>, Result<(), String>) {
+        let Ok(url) = Url::parse(&delivery.url) else {
+            return (None, Err("Malformed webhook URL".into()));
+        };
+
+        // The addresses of host names are checked by the resolver of the client on every attempt,
+        // as DNS records may have changed since the webhook was created
+        if let Err(e) = ensure_public_url(&url) {
+            return (None, Err(e.to_string()));
+        }
+
+        let event = WebhookEvent::from(delivery.event);
+        let data: Value = serde_json::from_str(&delivery.payload).unwrap_or(Value::Null);
+
+        let body = json!({
+            "id": Snowflake::<WebhookDelivery>::new(delivery.id),
+            "event": event,
+            "guild_id": Snowflake::<Guild>::new(delivery.guild_id),
+            "timestamp": timestamp,
+            "data": data,
+        })
+        .to_string();
+
+        let signature = sign(&delivery.secret, &format!("{timestamp}.{body}"));
+
+        let response = self
+            .client
+            .post(url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("X-Chat-Event", event.name())
+            .header("X-Chat-Delivery", delivery.id.to_string())
+            .header("X-Chat-Timestamp", timestamp.to_string())
+            .header("X-Chat-Signature", format!("sha256={signature}"))
+            .body(body)
+            .send()
+            .await;
+
+        match response {
+            Ok(resp) => {
+                let status = resp.status();
+                let code = i16::try_from(status.as_u16()).ok();
+                if status.is_success() {
+                    (code, Ok(()))
+                } else {
+                    (code, Err(format!("Webhook responded with {status}")))
+                }
+            }
+            Err(e) => (None, Err(e.to_string())),
+        }
+    }
+
+    /// Remove finished deliveries from the delivery log that are older than the retention period.
+    ///
+    /// ## Errors
+    ///
+    /// * [`sqlx::Error`] - If the database query fails.
+    pub async fn prune(&self) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM webhook_deliveries WHERE next_attempt_at IS NULL AND created_at < $1",
+            self.app().clock.timestamp() - RETENTION
+        )
+        .execute(self.app().db.executor())
+        .await?;
+
+        Ok(())
+    }
+}
+
+impl Default for WebhookDispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::{ClaimedDelivery, WebhookDispatcher, WebhookEvent};
+    use crate::models::media_proxy::testing::RebindingHost;
+
+    #[tokio::test]
+    async fn test_delivery_connects_to_checked_address() {
+        let host = RebindingHost::serve(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n").await;
+        let dispatcher = WebhookDispatcher::with_resolver(host.resolver);
+        let delivery = ClaimedDelivery {
+            id: 1,
+            event: WebhookEvent::MemberJoin as i16,
+            payload: "{}".into(),
+            attempts: 0,
+            guild_id: 1,
+            url: format!("http://hooks.example:{}/chat", host.port),
+            secret: "secret".into(),
+        };
+
+        // Deliveries are posted to the address that was checked, and only to it
+        let (status, result) = dispatcher.post(&delivery, 0).await;
+        assert_eq!(status, Some(204));
+        assert!(result.is_ok());
+        assert_eq!(host.checked.load(Ordering::SeqCst), 1);
+
+        // Once the host resolves to the internal address, no connection is made at all
+        let (status, result) = dispatcher.post(&delivery, 0).await;
+        assert_eq!(status, None);
+        assert!(result.is_err());
+        assert_eq!(host.internal.load(Ordering::SeqCst), 0);
+        assert_eq!(host.lookups.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,139 @@
+// The `{user}`, `{guild}` and `{joined_at}` placeholders of welcome messages are not format arguments
+#![allow(clippy::literal_string_with_formatting_args)]
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    errors::{BuildError, ErrorCode},
+    guild::Guild,
+    i18n::format_timestamp,
+    user::User,
+};
+
+/// The longest welcome message a guild may set, in characters.
+pub const MAX_WELCOME_MESSAGE_LENGTH: usize = 2000;
+
+/// A message sent to users when they join a guild.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct WelcomeMessage {
+    /// The template of the message, or `None` if no message is sent.
+    content: Option<String>,
+}
+
+impl WelcomeMessage {
+    /// Create a new welcome message.
+    ///
+    /// ## Arguments
+    ///
+    /// * `content` - The template of the message, or `None` if no message is sent.
+    ///   `{user}` is replaced with the name of the new member, `{guild}` with the name of the guild,
+    ///   and `{joined_at}` with the time the member joined, in the guild's locale and timezone.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If the template is blank or longer than [`MAX_WELCOME_MESSAGE_LENGTH`].
+    pub fn new(content: Option<String>) -> Result<Self, BuildError> {
+        let message = Self { content };
+        message.validate()?;
+        Ok(message)
+    }
+
+    /// The template of the message, or `None` if no message is sent.
+    pub fn content(&self) -> Option<&str> {
+        self.content.as_deref()
+    }
+
+    /// Ensure the template is within the allowed bounds.
+    ///
+    /// ## Errors
+    ///
+    /// * [`BuildError::InvalidField`] - If the template is blank or longer than [`MAX_WELCOME_MESSAGE_LENGTH`].
+    pub fn validate(&self) -> Result<(), BuildError> {
+        if self
+            .content
+            .as_ref()
+            .is_some_and(|content| content.trim().is_empty() || content.chars().count() > MAX_WELCOME_MESSAGE_LENGTH)
+        {
+            return Err(BuildError::InvalidField {
+                field: "welcome_message.content",
+                code: ErrorCode::InvalidLength,
+                message: format!("Welcome message must be between 1 and {MAX_WELCOME_MESSAGE_LENGTH} characters."),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fill in the template for a user joining a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user who joined the guild.
+    /// * `guild` - The guild the user joined.
+    /// * `joined_at` - UNIX timestamp of when the user joined the guild.
+    ///
+    /// ## Returns
+    ///
+    /// The message to send, or `None` if no message is sent.
+    pub fn render(&self, user: &User, guild: &Guild, joined_at: i64) -> Option<String> {
+        let name = user.display_name().unwrap_or_else(|| user.username());
+        self.content.as_ref().map(|content| {
+            let mut message = content.replace("{user}", name).replace("{guild}", guild.name());
+            if message.contains("{joined_at}") {
+                let joined_at = format_timestamp(joined_at, guild.locale(), guild.timezone());
+                message = message.replace("{joined_at}", &joined_at);
+            }
+            message
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WelcomeMessage, MAX_WELCOME_MESSAGE_LENGTH};
+    use crate::models::{guild::Guild, requests::UpdateGuild, snowflake::Snowflake, user::User};
+
+    #[test]
+    fn test_render() {
+        let user = User::builder()
+            .id(Snowflake::new(1))
+            .username("among_us")
+            .build()
+            .expect("User should be valid");
+        let mut guild = Guild::new(Snowflake::new(2), "Crewmates".into(), Snowflake::new(3));
+        // 2024-07-01 18:30:00 UTC
+        let joined_at = 1_719_858_600;
+
+        let message = WelcomeMessage::new(Some("Welcome to {guild}, {user}!".into())).expect("Message should be valid");
+        assert_eq!(
+            message.render(&user, &guild, joined_at).as_deref(),
+            Some("Welcome to Crewmates, among_us!")
+        );
+        assert_eq!(WelcomeMessage::default().render(&user, &guild, joined_at), None);
+
+        let message = WelcomeMessage::new(Some("You joined on {joined_at}.".into())).expect("Message should be valid");
+        assert_eq!(
+            message.render(&user, &guild, joined_at).as_deref(),
+            Some("You joined on 07/01/2024 6:30 PM UTC.")
+        );
+        guild
+            .update(UpdateGuild {
+                locale: Some("de_DE".into()),
+                timezone: Some("Europe/Berlin".into()),
+                ..Default::default()
+            })
+            .expect("Update should be valid");
+        assert_eq!(
+            message.render(&user, &guild, joined_at).as_deref(),
+            Some("You joined on 01.07.2024 20:30 CEST.")
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(WelcomeMessage::new(Some("   ".into())).is_err());
+        assert!(WelcomeMessage::new(Some("a".repeat(MAX_WELCOME_MESSAGE_LENGTH + 1))).is_err());
+        assert!(WelcomeMessage::new(Some("a".repeat(MAX_WELCOME_MESSAGE_LENGTH))).is_ok());
+        assert!(WelcomeMessage::new(None).is_ok());
+    }
+}
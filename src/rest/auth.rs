@@ -1,5 +1,7 @@
+use std::sync::OnceLock;
+
 use argon2::password_hash::SaltString;
-use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use secrecy::{ExposeSecret, Secret};
 
 use crate::models::{
@@ -8,10 +10,16 @@ use crate::models::{
 };
 use crate::models::{snowflake::Snowflake, state::App, user::User};
 
+/// A hash of a random password, created with the current hashing parameters.
+static DUMMY_HASH: OnceLock<Secret<String>> = OnceLock::new();
+
 // TODO: Add auth attempts logging and authenticated endpoint to query it.
 
 /// Verify a set of credentials against the database in constant time.
 ///
+/// If the stored hash was created with weaker parameters than the current policy,
+/// the password is transparently rehashed with the current parameters.
+///
 /// # Arguments
 ///
 /// * `credentials` - The credentials to verify.
@@ -26,28 +34,70 @@ use crate::models::{snowflake::Snowflake, state::App, user::User};
 /// * [`AuthError::InvalidCredentials`] - If the credentials are invalid.
 /// * [`AuthError::PasswordHash`] - If the password could not be hashed.
 pub async fn validate_credentials(app: App, credentials: Credentials) -> Result<Snowflake<User>, AuthError> {
-    let mut user_id: Option<Snowflake<User>> = None;
-    // We set up a dummy hash here so verify_password_hash is always run.
-    // This is to prevent timing attacks.
-    let mut expected_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
-
-    if let Some(stored_credentials) =
-        StoredCredentials::fetch_by_username(app, credentials.username().to_string()).await
-    {
-        user_id = Some(stored_credentials.user_id());
-        expected_hash = stored_credentials.hash().clone();
+    let stored_credentials =
+        StoredCredentials::fetch_by_username(app.clone(), credentials.username().to_string()).await;
+    let params = app.config.argon2_params().clone();
+
+    // If the user does not exist, a dummy hash is verified instead to prevent timing attacks.
+    let expected_hash = stored_credentials.as_ref().map(|c| c.hash().clone());
+
+    let new_hash = tokio::task::spawn_blocking(move || {
+        let expected_hash = expected_hash.unwrap_or_else(|| dummy_hash(&params).clone());
+        verify_password_hash(&expected_hash, credentials.password())?;
+
+        if needs_rehash(&expected_hash, &params) {
+            generate_hash(&params, credentials.password()).map(Some)
+        } else {
+            Ok(None)
+        }
+    })
+    .await
+    .expect("Failed to join hash verification task")?;
+
+    let mut stored_credentials = stored_credentials.ok_or(AuthError::InvalidCredentials)?;
+
+    if let Some(new_hash) = new_hash {
+        if let Err(e) = stored_credentials.rehash(app, Secret::new(new_hash)).await {
+            tracing::warn!(error = %e, "Failed to store rehashed password");
+        }
     }
 
-    tokio::task::spawn_blocking(move || verify_password_hash(&expected_hash, credentials.password()))
-        .await
-        .expect("Failed to join hash verification task")?;
+    Ok(stored_credentials.user_id())
+}
+
+/// The hasher used for new passwords.
+fn hasher(params: &Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+}
+
+/// A hash to verify against if a user does not exist, so that it takes as long as verifying an existing user.
+fn dummy_hash(params: &Params) -> &'static Secret<String> {
+    DUMMY_HASH.get_or_init(|| {
+        let password = Secret::new(hex::encode(rand::random::<[u8; 32]>()));
+        Secret::new(generate_hash(params, &password).expect("Failed to generate dummy hash"))
+    })
+}
+
+/// Check if a hash was created with a different algorithm or weaker parameters than the given ones.
+///
+/// # Arguments
+///
+/// * `hash` - The hash to check, in PHC string format.
+/// * `params` - The current hashing parameters.
+fn needs_rehash(hash: &Secret<String>, params: &Params) -> bool {
+    let Ok(hash) = PasswordHash::new(hash.expose_secret()) else {
+        return false;
+    };
+
+    let Ok(stored) = Params::try_from(&hash) else {
+        return true;
+    };
 
-    user_id.ok_or(AuthError::InvalidCredentials)
+    hash.algorithm != Algorithm::Argon2id.ident()
+        || hash.version != Some(Version::V0x13.into())
+        || stored.m_cost() < params.m_cost()
+        || stored.t_cost() < params.t_cost()
+        || stored.p_cost() < params.p_cost()
 }
 
 /// Verify a password candidate against a known hash.
@@ -70,9 +120,11 @@ fn verify_password_hash(expected_hash: &Secret<String>, password_candidate: &Sec
 }
 
 /// Generate a hash for a new password.
+/// The parameters are embedded in the resulting PHC string, so they can be changed without breaking existing hashes.
 ///
 /// # Arguments
 ///
+/// * `params` - The hashing parameters to use.
 /// * `password` - The password to hash.
 ///
 /// # Returns
@@ -82,8 +134,8 @@ fn verify_password_hash(expected_hash: &Secret<String>, password_candidate: &Sec
 /// ## Errors
 ///
 /// * [`AuthError::PasswordHash`] - If the password could not be hashed.
-pub fn generate_hash(password: &Secret<String>) -> Result<String, AuthError> {
-    let hasher = Argon2::default();
+pub fn generate_hash(params: &Params, password: &Secret<String>) -> Result<String, AuthError> {
+    let hasher = hasher(params);
     let salt = SaltString::generate(&mut rand::thread_rng());
     Ok(hasher
         .hash_password(password.expose_secret().as_bytes(), &salt)?
@@ -0,0 +1,199 @@
+use std::{net::SocketAddr, time::Duration};
+
+use axum::{
+    body::to_bytes,
+    extract::{ConnectInfo, Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tower::{
+    layer::util::{Identity, Stack},
+    ServiceBuilder,
+};
+use tower_http::{limit::RequestBodyLimitLayer, timeout::TimeoutLayer};
+
+use crate::models::{
+    auth::{self, Token, TokenSource, CSRF_HEADER},
+    errors::{AppError, AuthError, ErrResponse, ErrorCode},
+    ip_filter::IpFilterScope,
+    rate_limit::{RateLimitBucket, RateLimitKey},
+    state::App,
+};
+
+/// The largest body of an error response that is rewritten by [`json_errors`], larger bodies are discarded.
+const MAX_ERROR_BODY_SIZE: usize = 4096;
+
+pub type RequestLimitsLayer = ServiceBuilder<Stack<TimeoutLayer, Stack<RequestBodyLimitLayer, Identity>>>;
+
+/// Limits applied to every request of a group of routes.
+///
+/// ## Arguments
+///
+/// * `timeout` - The maximum time to handle a request, after which `408 Request Timeout` is returned
+/// * `max_body_size` - The maximum size of a request body in bytes, larger bodies are rejected with `413 Payload Too Large`
+pub fn request_limits(timeout: Duration, max_body_size: usize) -> RequestLimitsLayer {
+    ServiceBuilder::new()
+        .layer(RequestBodyLimitLayer::new(max_body_size))
+        .layer(TimeoutLayer::new(timeout))
+}
+
+/// Reject requests early while the database is known to be unreachable,
+/// instead of letting every query wait for the pool to time out.
+///
+/// ## Errors
+///
+/// * [`AppError::DatabaseUnavailable`] - If the last database health check failed
+pub async fn require_database(State(app): State<App>, request: Request, next: Next) -> Result<Response, AppError> {
+    if !app.db.is_healthy() {
+        return Err(AppError::DatabaseUnavailable);
+    }
+    Ok(next.run(request).await)
+}
+
+/// Count the request as in-flight until its response is produced, see [`shed_load`].
+pub async fn track_load(State(app): State<App>, request: Request, next: Next) -> Response {
+    let _guard = app.load_shedder.track();
+    next.run(request).await
+}
+
+/// Reject low priority requests with `503 Service Unavailable` while the server is overloaded,
+/// so they do not slow down sending messages and dispatching events.
+///
+/// The server is overloaded while more requests are in flight, or more events wait to be dispatched,
+/// than the configured thresholds.
+pub async fn shed_load(State(app): State<App>, request: Request, next: Next) -> Response {
+    let queue_depth = app.bus.queue_depth() + app.firehose.queue_depth();
+
+    if let Err(e) = app.load_shedder.check(queue_depth) {
+        return e.into_response();
+    }
+    next.run(request).await
+}
+
+/// Reject requests that are authenticated by the session cookie and may change state,
+/// unless they repeat the CSRF token of the session in the `X-CSRF-Token` header.
+///
+/// Browsers attach cookies to requests other sites make as well, but those sites cannot read the CSRF token.
+/// Requests authenticated by the `Authorization` header are not affected.
+///
+/// ## Errors
+///
+/// * [`AuthError::InvalidCsrfToken`] - If the CSRF token is missing or does not belong to the session
+pub async fn csrf_protection(State(app): State<App>, request: Request, next: Next) -> Result<Response, AppError> {
+    let is_safe = matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+
+    if let Some((token, TokenSource::Cookie)) = auth::find_token(&app.config, request.headers()) {
+        let csrf_token = request
+            .headers()
+            .get(CSRF_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        if !is_safe && !auth::verify_csrf_token(app.config.signing_keys(), &token, csrf_token) {
+            return Err(AuthError::InvalidCsrfToken.into());
+        }
+    }
+    Ok(next.run(request).await)
+}
+
+/// Count the request towards the rate limit of its route group, and reject it with
+/// `429 Too Many Requests` if the limit is exceeded. Every response includes the `X-RateLimit-*` headers.
+///
+/// Authenticated requests are counted per user, all other requests per client address.
+pub async fn rate_limit(State((app, bucket)): State<(App, RateLimitBucket)>, request: Request, next: Next) -> Response {
+    if !app.rate_limiter.is_enabled() {
+        return next.run(request).await;
+    }
+
+    let status = app.rate_limiter.hit(bucket, rate_limit_key(&app, &request));
+
+    if status.is_exceeded() {
+        return status.into_response();
+    }
+
+    let mut response = next.run(request).await;
+    response.headers_mut().extend(status.headers());
+    response
+}
+
+/// Reject requests from client addresses the IP filter of a group of routes does not permit.
+///
+/// Requests whose client address is unknown are rejected as well while the filter is enabled.
+/// Rejected requests are only logged, as they are not authenticated and could otherwise flood guild audit logs.
+///
+/// ## Errors
+///
+/// * [`AppError::Forbidden`] - If the client address is not permitted
+pub async fn ip_filter(
+    State((app, scope)): State<(App, IpFilterScope)>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let filter = scope.filter(&app.config);
+    if !filter.is_enabled() {
+        return Ok(next.run(request).await);
+    }
+
+    let ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+
+    if ip.is_some_and(|ip| filter.permits(ip)) {
+        return Ok(next.run(request).await);
+    }
+
+    let client = ip.map_or_else(|| String::from("an unknown address"), |ip| ip.to_string());
+    tracing::warn!(scope = scope.name(), path = %request.uri().path(), "Rejected request from {client} by IP filter");
+
+    Err(AppError::Forbidden(
+        "Requests from this address are not allowed.".into(),
+    ))
+}
+
+/// Find out who a request is counted towards. Tokens are only decoded here, they are validated by the route.
+fn rate_limit_key(app: &App, request: &Request) -> RateLimitKey {
+    let user = auth::find_token(&app.config, request.headers())
+        .and_then(|(token, _)| Token::decode(app.config.signing_keys(), &token, app.clock.now()).ok());
+
+    if let Some(token) = user {
+        return RateLimitKey::User(token.data().user_id());
+    }
+
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or(RateLimitKey::Unknown, |ConnectInfo(addr)| RateLimitKey::Ip(addr.ip()))
+}
+
+/// Give error responses that were not created by a route the same JSON body as errors returned by routes.
+///
+/// This covers rejected path parameters, malformed bodies and timed out requests,
+/// so every error response includes a machine-readable `code`.
+pub async fn json_errors(response: Response) -> Response {
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+
+    if !(status.is_client_error() || status.is_server_error()) || is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let message = to_bytes(body, MAX_ERROR_BODY_SIZE)
+        .await
+        .ok()
+        .and_then(|body| String::from_utf8(body.to_vec()).ok())
+        .filter(|message| !message.trim().is_empty())
+        .unwrap_or_else(|| status.canonical_reason().unwrap_or("Unknown error").to_string());
+
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    let mut response = ErrResponse::new(status, ErrorCode::from_status(status), message).into_response();
+    response.headers_mut().extend(parts.headers);
+    response
+}
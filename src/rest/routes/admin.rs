@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{FromRequestParts, Path, State},
+    http::request::Parts,
+    routing::{get, put},
+    Json, Router,
+};
+use axum_extra::headers::{authorization::Bearer, Authorization, HeaderMapExt};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+
+use crate::models::{
+    errors::{AuthError, RESTError},
+    log_filter::LogFilterState,
+    requests::{UpdateLogLevel, UpdateSetting},
+    settings::{SettingKey, SettingsState},
+    state::App,
+};
+
+/// The longest time a log verbosity may be kept for, in seconds.
+const MAX_LOG_LEVEL_DURATION: u64 = 24 * 60 * 60;
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route(
+            "/admin/log-level",
+            get(fetch_log_level).put(update_log_level).delete(reset_log_level),
+        )
+        .route("/admin/settings", get(fetch_settings))
+        .route("/admin/settings/:key", put(update_setting).delete(reset_setting))
+}
+
+/// Proof that a request was made by an operator of the server, who presented `ADMIN_API_SECRET` as a bearer token.
+struct Admin;
+
+#[async_trait::async_trait]
+impl FromRequestParts<App> for Admin {
+    type Rejection = RESTError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &App) -> Result<Self, Self::Rejection> {
+        let Some(secret) = state.config.admin_api_secret() else {
+            return Err(RESTError::NotFound("Admin endpoints are not enabled".into()));
+        };
+        let Some(Authorization(bearer)) = parts.headers.typed_get::<Authorization<Bearer>>() else {
+            return Err(AuthError::MissingCredentials.into());
+        };
+
+        // Comparing the MACs of the token and the secret takes the same time regardless of where they differ
+        let mut expected =
+            Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes()).expect("HMAC can take a key of any size");
+        expected.update(b"chat-admin-api");
+        let mut presented =
+            Hmac::<Sha256>::new_from_slice(bearer.token().as_bytes()).expect("HMAC can take a key of any size");
+        presented.update(b"chat-admin-api");
+
+        if expected.verify_slice(&presented.finalize().into_bytes()).is_err() {
+            return Err(AuthError::InvalidToken.into());
+        }
+        Ok(Self)
+    }
+}
+
+/// Fetch the current log verbosity of the server.
+///
+/// ## Arguments
+///
+/// * `admin` - Proof that the request was made by an operator
+///
+/// ## Returns
+///
+/// * [`LogFilterState`] - A JSON response containing the current verbosity, and when it is reverted
+///
+/// ## Endpoint
+///
+/// GET `/admin/log-level`
+async fn fetch_log_level(State(app): State<App>, _admin: Admin) -> Json<LogFilterState> {
+    Json(app.log_filter.state())
+}
+
+/// Change the log verbosity of the server. The verbosity is reverted to the default after a while.
+///
+/// ## Arguments
+///
+/// * `admin` - Proof that the request was made by an operator
+/// * `payload` - The [`UpdateLogLevel`] payload
+///
+/// ## Returns
+///
+/// * [`LogFilterState`] - A JSON response containing the new verbosity, and when it is reverted
+///
+/// ## Endpoint
+///
+/// PUT `/admin/log-level`
+async fn update_log_level(
+    State(app): State<App>,
+    _admin: Admin,
+    Json(payload): Json<UpdateLogLevel>,
+) -> Result<Json<LogFilterState>, RESTError> {
+    let duration = match payload.duration {
+        Some(duration @ 1..=MAX_LOG_LEVEL_DURATION) => Duration::from_secs(duration),
+        Some(_) => {
+            return Err(RESTError::BadRequest(format!(
+                "Duration must be between 1 and {MAX_LOG_LEVEL_DURATION} seconds"
+            )))
+        }
+        None => app.config.log_level_revert_after(),
+    };
+
+    Ok(Json(app.log_filter.set(payload.levels, duration, app.clock.now())))
+}
+
+/// Revert the log verbosity of the server to the default.
+///
+/// ## Arguments
+///
+/// * `admin` - Proof that the request was made by an operator
+///
+/// ## Returns
+///
+/// * [`LogFilterState`] - A JSON response containing the default verbosity
+///
+/// ## Endpoint
+///
+/// DELETE `/admin/log-level`
+async fn reset_log_level(State(app): State<App>, _admin: Admin) -> Json<LogFilterState> {
+    Json(app.log_filter.reset())
+}
+
+/// Fetch the effective values of the settings that may be overridden at runtime, and their overrides.
+///
+/// ## Arguments
+///
+/// * `admin` - Proof that the request was made by an operator
+///
+/// ## Returns
+///
+/// * [`SettingsState`] - A JSON response containing the effective values and the overrides
+///
+/// ## Endpoint
+///
+/// GET `/admin/settings`
+async fn fetch_settings(State(app): State<App>, _admin: Admin) -> Json<SettingsState> {
+    Json(app.settings.state())
+}
+
+/// Override a setting without a redeploy. The override is applied immediately on this instance,
+/// and picked up by other instances the next time they refresh their settings.
+///
+/// ## Arguments
+///
+/// * `admin` - Proof that the request was made by an operator
+/// * `key` - The name of the setting to override
+/// * `payload` - The [`UpdateSetting`] payload
+///
+/// ## Returns
+///
+/// * [`SettingsState`] - A JSON response containing the new effective values and overrides
+///
+/// ## Endpoint
+///
+/// PUT `/admin/settings/{key}`
+async fn update_setting(
+    Path(key): Path<String>,
+    State(app): State<App>,
+    _admin: Admin,
+    Json(payload): Json<UpdateSetting>,
+) -> Result<Json<SettingsState>, RESTError> {
+    let key = SettingKey::from_name(&key).ok_or(RESTError::NotFound("Unknown setting".into()))?;
+
+    // Invalid values are rejected before they are stored, as every instance would skip them
+    app.settings
+        .values()
+        .set(key, &payload.value)
+        .map_err(|e| RESTError::BadRequest(e.to_string()))?;
+
+    tracing::warn!(setting = key.name(), value = %payload.value, "Setting overridden");
+    app.ops().update_setting(key, &payload.value).await?;
+    app.refresh_settings().await?;
+
+    Ok(Json(app.settings.state()))
+}
+
+/// Remove the override of a setting, so the value configured through the environment is used again.
+///
+/// ## Arguments
+///
+/// * `admin` - Proof that the request was made by an operator
+/// * `key` - The name of the setting to reset
+///
+/// ## Returns
+///
+/// * [`SettingsState`] - A JSON response containing the new effective values and overrides
+///
+/// ## Endpoint
+///
+/// DELETE `/admin/settings/{key}`
+async fn reset_setting(
+    Path(key): Path<String>,
+    State(app): State<App>,
+    _admin: Admin,
+) -> Result<Json<SettingsState>, RESTError> {
+    let key = SettingKey::from_name(&key).ok_or(RESTError::NotFound("Unknown setting".into()))?;
+
+    if app.ops().delete_setting(key).await? {
+        tracing::warn!(setting = key.name(), "Setting override removed");
+        app.refresh_settings().await?;
+    }
+
+    Ok(Json(app.settings.state()))
+}
@@ -0,0 +1,226 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, patch},
+    Json, Router,
+};
+use serde::Deserialize;
+
+use crate::models::{
+    audit_log::{AuditLogAction, AuditLogEntry},
+    auth::Token,
+    automod::{AutoModRule, MAX_RULES_PER_GUILD},
+    errors::RESTError,
+    guild::Guild,
+    requests::{CreateAutoModRule, UpdateAutoModRule},
+    snowflake::Snowflake,
+    state::App,
+};
+
+#[derive(Deserialize, Debug, Clone)]
+struct FetchAuditLogQuery {
+    limit: Option<u32>,
+    before: Option<Snowflake<AuditLogEntry>>,
+}
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route(
+            "/guilds/:guild_id/automod/rules",
+            get(fetch_automod_rules).post(create_automod_rule),
+        )
+        .route(
+            "/guilds/:guild_id/automod/rules/:rule_id",
+            patch(update_automod_rule).delete(delete_automod_rule),
+        )
+        .route("/guilds/:guild_id/audit-logs", get(fetch_audit_log))
+}
+
+/// Fetch an automod rule and ensure that it belongs to the given guild.
+async fn fetch_guild_rule(
+    app: &App,
+    guild_id: Snowflake<Guild>,
+    rule_id: Snowflake<AutoModRule>,
+) -> Result<AutoModRule, RESTError> {
+    app.ops()
+        .fetch_automod_rule(rule_id)
+        .await?
+        .filter(|rule| rule.guild_id() == guild_id)
+        .ok_or(RESTError::NotFound("Automod rule not found".into()))
+}
+
+/// Fetch all automod rules of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the rules of
+///
+/// ## Returns
+///
+/// * [`Vec<AutoModRule>`] - A JSON response containing a list of [`AutoModRule`] objects
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/automod/rules`
+async fn fetch_automod_rules(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<AutoModRule>>, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(app.ops().fetch_automod_rules(guild_id).await?))
+}
+
+/// Create a new automod rule in a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to create the rule in
+/// * `payload` - The [`CreateAutoModRule`] payload
+///
+/// ## Returns
+///
+/// * [`AutoModRule`] - A JSON response containing the created [`AutoModRule`] object
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/automod/rules`
+async fn create_automod_rule(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateAutoModRule>,
+) -> Result<(StatusCode, Json<AutoModRule>), RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    if app.ops().fetch_automod_rules(guild_id).await?.len() >= MAX_RULES_PER_GUILD {
+        return Err(RESTError::BadRequest(format!(
+            "A guild may not have more than {MAX_RULES_PER_GUILD} automod rules"
+        )));
+    }
+
+    let rule = AutoModRule::from_payload(&app.config, guild_id, payload)?;
+    app.ops().update_automod_rule(&rule).await?;
+
+    let entry = AuditLogEntry::new(
+        &app.config,
+        guild_id,
+        Some(token.data().user_id()),
+        AuditLogAction::AutoModRuleCreate,
+        Some(rule.id().cast()),
+        None,
+    );
+    app.ops().create_audit_log_entry(&entry).await?;
+
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+/// Update an automod rule in a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the rule belongs to
+/// * `rule_id` - The ID of the rule to update
+/// * `payload` - The [`UpdateAutoModRule`] payload
+///
+/// ## Returns
+///
+/// * [`AutoModRule`] - A JSON response containing the updated [`AutoModRule`] object
+///
+/// ## Endpoint
+///
+/// PATCH `/guilds/{guild_id}/automod/rules/{rule_id}`
+async fn update_automod_rule(
+    Path((guild_id, rule_id)): Path<(Snowflake<Guild>, Snowflake<AutoModRule>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateAutoModRule>,
+) -> Result<Json<AutoModRule>, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    let mut rule = fetch_guild_rule(&app, guild_id, rule_id).await?;
+    rule.update(payload)?;
+    app.ops().update_automod_rule(&rule).await?;
+
+    let entry = AuditLogEntry::new(
+        &app.config,
+        guild_id,
+        Some(token.data().user_id()),
+        AuditLogAction::AutoModRuleUpdate,
+        Some(rule.id().cast()),
+        None,
+    );
+    app.ops().create_audit_log_entry(&entry).await?;
+
+    Ok(Json(rule))
+}
+
+/// Delete an automod rule from a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the rule belongs to
+/// * `rule_id` - The ID of the rule to delete
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/automod/rules/{rule_id}`
+async fn delete_automod_rule(
+    Path((guild_id, rule_id)): Path<(Snowflake<Guild>, Snowflake<AutoModRule>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    let rule = fetch_guild_rule(&app, guild_id, rule_id).await?;
+    app.ops().delete_automod_rule(rule.id()).await?;
+
+    let entry = AuditLogEntry::new(
+        &app.config,
+        guild_id,
+        Some(token.data().user_id()),
+        AuditLogAction::AutoModRuleDelete,
+        Some(rule.id().cast()),
+        Some(format!("Deleted automod rule '{}'", rule.name())),
+    );
+    app.ops().create_audit_log_entry(&entry).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch a guild's audit log, newest entries first.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the audit log of
+/// * `query` - The query parameters
+///
+/// ## Returns
+///
+/// * [`Vec<AuditLogEntry>`] - A JSON response containing a list of [`AuditLogEntry`] objects
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/audit-logs`
+async fn fetch_audit_log(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Query(query): Query<FetchAuditLogQuery>,
+) -> Result<Json<Vec<AuditLogEntry>>, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    let entries = app.ops().fetch_audit_log(guild_id, query.limit, query.before).await?;
+
+    Ok(Json(entries))
+}
@@ -1,21 +1,23 @@
 use axum::{
     extract::{DefaultBodyLimit, Multipart, Path, Query, State},
     http::StatusCode,
-    routing::{delete, get, post},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
-use tower_http::limit::RequestBodyLimitLayer;
 
 use crate::models::{
+    audit_log::{AuditLogAction, AuditLogEntry},
     auth::Token,
-    channel::{Channel, ChannelLike},
+    channel::{Channel, ChannelLike, MAX_RETENTION_DAYS},
     errors::RESTError,
-    gateway_event::GatewayEvent,
-    member::UserLike,
-    message::Message,
+    message::{Message, MessageFields, PartialMessage},
+    message_change::{MessageChange, MessageChanges},
+    requests::{UpdateChannel, UpdateChannelRetention, UpdateStickyMessage},
     snowflake::Snowflake,
     state::App,
+    translation::Translation,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -23,6 +25,28 @@ struct FetchMessagesQuery {
     limit: Option<u32>,
     before: Option<Snowflake<Message>>,
     after: Option<Snowflake<Message>>,
+    /// Comma-separated optional fields to include, all of them if not given.
+    fields: Option<String>,
+    /// Comma-separated optional fields to leave out.
+    exclude: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct SearchMessagesQuery {
+    query: String,
+    limit: Option<u32>,
+    before: Option<Snowflake<Message>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct FetchMessageChangesQuery {
+    since: Snowflake<MessageChange>,
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct TranslateMessageQuery {
+    to: String,
 }
 
 /* let message_create_lim: SharedIDLimiter = Arc::new(RateLimiter::keyed(
@@ -32,11 +56,42 @@ struct FetchMessagesQuery {
 pub fn get_router() -> Router<App> {
     Router::new()
         .route("/channels/:channel_id", get(fetch_channel))
+        .route("/channels/:channel_id", patch(update_channel))
         .route("/channels/:channel_id", delete(delete_channel))
-        .route("/channels/:channel_id/messages", post(create_message))
+        .route("/channels/:channel_id/retention", put(update_channel_retention))
+        .route(
+            "/channels/:channel_id/archive",
+            put(archive_channel).delete(unarchive_channel),
+        )
+        .route(
+            "/channels/:channel_id/sticky",
+            get(fetch_sticky_message)
+                .put(update_sticky_message)
+                .delete(delete_sticky_message),
+        )
         .route("/channels/:channel_id/messages", get(fetch_messages))
+        .route("/channels/:channel_id/messages/changes", get(fetch_message_changes))
+}
+
+/// Get the message search route, which is low priority and rejected while the server is overloaded.
+pub fn get_search_router() -> Router<App> {
+    Router::new().route("/channels/:channel_id/messages/search", get(search_messages))
+}
+
+/// Get the routes that accept file uploads.
+/// These are subject to the upload limits instead of the default request limits.
+pub fn get_upload_router() -> Router<App> {
+    Router::new()
+        .route("/channels/:channel_id/messages", post(create_message))
         .layer(DefaultBodyLimit::disable())
-        .layer(RequestBodyLimitLayer::new(8 * 1024 * 1024 /* 8mb */))
+}
+
+/// Get the message translation route, which has its own rate limit bucket.
+pub fn get_translate_router() -> Router<App> {
+    Router::new().route(
+        "/channels/:channel_id/messages/:message_id/translate",
+        get(translate_message),
+    )
 }
 
 /// Fetch a channel's data.
@@ -58,15 +113,102 @@ async fn fetch_channel(
     State(app): State<App>,
     token: Token,
 ) -> Result<Json<Channel>, RESTError> {
-    let channel = app.ops().fetch_channel(channel_id).await.ok_or(RESTError::NotFound(
-        "Channel does not exist or is not available.".to_string(),
-    ))?;
+    let (channel, _) = app.messages().fetch_channel(channel_id, token.data().user_id()).await?;
+
+    Ok(Json(channel))
+}
 
-    // Check if the user is in the channel's guild
-    app.ops()
-        .fetch_member(token.data().user_id(), channel.guild_id())
-        .await?
-        .ok_or(RESTError::Forbidden("Not permitted to view resource.".to_string()))?;
+/// Update the settings of a channel, such as whether it is an announcement channel.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel to update
+/// * `payload` - The [`UpdateChannel`] payload, containing the settings to change
+///
+/// ## Returns
+///
+/// * [`Channel`] - A JSON response containing the updated [`Channel`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::ChannelUpdate`] - To all members who can view the channel
+///
+/// ## Endpoint
+///
+/// PATCH `/channels/{channel_id}`
+async fn update_channel(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateChannel>,
+) -> Result<Json<Channel>, RESTError> {
+    let channel = app
+        .guilds()
+        .update_channel(channel_id, token.data().user_id(), payload)
+        .await?;
+
+    Ok(Json(channel))
+}
+
+/// Archive a channel, making it read-only and hiding it from the channel list of its guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel to archive
+///
+/// ## Returns
+///
+/// * [`Channel`] - A JSON response containing the archived [`Channel`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::ChannelUpdate`] - To all members who can view the channel
+///
+/// ## Endpoint
+///
+/// PUT `/channels/{channel_id}/archive`
+async fn archive_channel(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Channel>, RESTError> {
+    let channel = app
+        .guilds()
+        .set_channel_archived(channel_id, token.data().user_id(), true)
+        .await?;
+
+    Ok(Json(channel))
+}
+
+/// Unarchive a channel, so messages can be sent in it again.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel to unarchive
+///
+/// ## Returns
+///
+/// * [`Channel`] - A JSON response containing the unarchived [`Channel`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::ChannelUpdate`] - To all members who can view the channel
+///
+/// ## Endpoint
+///
+/// DELETE `/channels/{channel_id}/archive`
+async fn unarchive_channel(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Channel>, RESTError> {
+    let channel = app
+        .guilds()
+        .set_channel_archived(channel_id, token.data().user_id(), false)
+        .await?;
 
     Ok(Json(channel))
 }
@@ -94,29 +236,154 @@ async fn delete_channel(
     State(app): State<App>,
     token: Token,
 ) -> Result<StatusCode, RESTError> {
-    let channel = app.ops().fetch_channel(channel_id).await.ok_or(RESTError::NotFound(
-        "Channel does not exist or is not available.".into(),
-    ))?;
-
-    // Check guild owner_id
-    let guild = app
-        .ops()
-        .fetch_guild(channel.guild_id())
-        .await
-        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
-
-    if guild.owner_id() != token.data().user_id() {
-        return Err(RESTError::NotFound("Not permitted to delete channel.".into()));
+    app.guilds().delete_channel(channel_id, token.data().user_id()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the sticky message of a channel.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel to fetch the sticky message of
+///
+/// ## Returns
+///
+/// * [`Message`] - A JSON response containing the sticky [`Message`] object
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/sticky`
+async fn fetch_sticky_message(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Message>, RESTError> {
+    let message = app.messages().fetch_sticky(channel_id, token.data().user_id()).await?;
+
+    Ok(Json(message))
+}
+
+/// Set the sticky message of a channel, keeping it visible at the top of the channel.
+/// Replaces the previous sticky message of the channel, if any.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel to update
+/// * `payload` - The [`UpdateStickyMessage`] payload, containing the ID of the message
+///
+/// ## Returns
+///
+/// * [`Channel`] - A JSON response containing the updated [`Channel`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::ChannelStickyUpdate`] - To all members who can view the channel
+///
+/// ## Endpoint
+///
+/// PUT `/channels/{channel_id}/sticky`
+async fn update_sticky_message(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateStickyMessage>,
+) -> Result<Json<Channel>, RESTError> {
+    let channel = app
+        .guilds()
+        .set_sticky_message(channel_id, token.data().user_id(), Some(payload.message_id))
+        .await?;
+
+    Ok(Json(channel))
+}
+
+/// Clear the sticky message of a channel.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel to update
+///
+/// ## Returns
+///
+/// * [`Channel`] - A JSON response containing the updated [`Channel`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::ChannelStickyUpdate`] - To all members who can view the channel
+///
+/// ## Endpoint
+///
+/// DELETE `/channels/{channel_id}/sticky`
+async fn delete_sticky_message(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Channel>, RESTError> {
+    let channel = app
+        .guilds()
+        .set_sticky_message(channel_id, token.data().user_id(), None)
+        .await?;
+
+    Ok(Json(channel))
+}
+
+/// Set or clear the message retention period of a channel.
+/// Messages older than the retention period are deleted periodically in the background.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel to update
+/// * `payload` - The [`UpdateChannelRetention`] payload, containing the retention period in days
+///
+/// ## Returns
+///
+/// * [`Channel`] - A JSON response containing the updated [`Channel`] object
+///
+/// ## Endpoint
+///
+/// PUT `/channels/{channel_id}/retention`
+async fn update_channel_retention(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateChannelRetention>,
+) -> Result<Json<Channel>, RESTError> {
+    let (mut channel, guild) = app
+        .guilds()
+        .fetch_owned_channel(channel_id, token.data().user_id())
+        .await?;
+
+    if payload
+        .days
+        .is_some_and(|days| !(1..=MAX_RETENTION_DAYS).contains(&days))
+    {
+        return Err(RESTError::BadRequest(format!(
+            "Retention period must be between 1 and {MAX_RETENTION_DAYS} days."
+        )));
     }
 
-    app.ops().delete_channel(&channel).await?;
+    *channel.retention_days_mut() = payload.days;
+    app.ops().update_channel(&mut channel).await?;
 
-    app.gateway.dispatch(GatewayEvent::ChannelRemove(channel));
+    let entry = AuditLogEntry::new(
+        &app.config,
+        guild.id(),
+        Some(token.data().user_id()),
+        AuditLogAction::ChannelRetentionUpdate,
+        Some(channel_id.cast()),
+        None,
+    );
+    app.ops().create_audit_log_entry(&entry).await?;
 
-    Ok(StatusCode::NO_CONTENT)
+    Ok(Json(channel))
 }
 
 /// Send a new message and return the message data.
+/// Emojis in the message are counted towards the guild's emoji usage statistics in the background.
 ///
 /// ## Arguments
 ///
@@ -130,6 +397,14 @@ async fn delete_channel(
 /// ## Dispatches
 ///
 /// * [`GatewayEvent::MessageCreate`] - To all members who can view the channel
+/// * [`GatewayEvent::MemberUpdate`] - If the author was timed out by automod
+/// * [`GatewayEvent::MessageUpdate`] - Once the metadata of image, video and audio attachments is extracted,
+///   and once linked media is re-hosted as attachments if enabled
+///
+/// ## Automod
+///
+/// The message is evaluated against the guild's automod rules before it is committed.
+/// Blocked messages are rejected with 403 Forbidden, deleted messages are returned but never committed.
 ///
 /// ## Endpoint
 ///
@@ -140,28 +415,28 @@ async fn create_message(
     token: Token,
     payload: Multipart,
 ) -> Result<(StatusCode, Json<Message>), RESTError> {
-    let channel = app.ops().fetch_channel(channel_id).await.ok_or(RESTError::NotFound(
-        "Channel does not exist or is not available.".into(),
-    ))?;
-
-    let member = app
-        .ops()
-        .fetch_member(token.data().user_id(), channel.guild_id())
-        .await?
-        .ok_or(RESTError::Forbidden("Not permitted to access resource.".into()))?;
-
-    let message = Message::from_formdata(&app.config, UserLike::Member(member), channel_id, payload).await?;
+    let (channel, author) = app
+        .messages()
+        .fetch_sendable_channel(channel_id, token.data().user_id())
+        .await?;
 
-    app.ops().update_message(&message).await?;
+    let message = Message::from_formdata(
+        &app.config,
+        &app.settings.limits(),
+        &app.upload_throttle,
+        author,
+        channel_id,
+        payload,
+    )
+    .await?;
 
-    let message = message.strip_attachment_contents();
-    let reply = Json(message.clone());
+    let message = app.messages().create(&channel, message).await?;
 
-    app.gateway.dispatch(GatewayEvent::MessageCreate(message));
-    Ok((StatusCode::CREATED, reply))
+    Ok((StatusCode::CREATED, Json(message.strip_attachment_contents())))
 }
 
 /// Fetch a channel's messages.
+/// The optional fields of the messages can be selected with the `fields` and `exclude` query parameters.
 ///
 /// ## Arguments
 ///
@@ -171,7 +446,8 @@ async fn create_message(
 ///
 /// ## Returns
 ///
-/// * [`Vec<Message>`] - A JSON response containing a list of [`Message`] objects
+/// * [`Vec<Message>`] - A JSON response containing a list of [`Message`] objects,
+///   or of [`PartialMessage`] objects if only some fields are selected
 ///
 /// ## Endpoint
 ///
@@ -181,21 +457,128 @@ async fn fetch_messages(
     State(app): State<App>,
     token: Token,
     Query(query): Query<FetchMessagesQuery>,
-) -> Result<(StatusCode, Json<Vec<Message>>), RESTError> {
-    let channel = app.ops().fetch_channel(channel_id).await.ok_or(RESTError::NotFound(
-        "Channel does not exist or is not available.".into(),
-    ))?;
+) -> Result<Response, RESTError> {
+    let fields = MessageFields::from_query(query.fields.as_deref(), query.exclude.as_deref())?;
+
+    if fields == MessageFields::all() {
+        let messages = app
+            .messages()
+            .fetch_history(
+                channel_id,
+                token.data().user_id(),
+                query.limit,
+                query.before,
+                query.after,
+            )
+            .await?;
+        return Ok(Json(messages).into_response());
+    }
+
+    let messages: Vec<PartialMessage> = app
+        .messages()
+        .fetch_partial_history(
+            channel_id,
+            token.data().user_id(),
+            fields,
+            query.limit,
+            query.before,
+            query.after,
+        )
+        .await?;
+
+    Ok(Json(messages).into_response())
+}
+
+/// Fetch the messages of a channel that were sent, updated or deleted since a cursor, oldest changes first.
+///
+/// ## Arguments
+///
+/// * `token` - The authorization token
+/// * `channel_id` - The ID of the channel to fetch changes from
+/// * `query` - The cursor and the maximum amount of changes
+///
+/// ## Returns
+///
+/// * [`MessageChanges`] - A JSON response containing the changes and the cursor of the next page
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/messages/changes`
+async fn fetch_message_changes(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+    Query(query): Query<FetchMessageChangesQuery>,
+) -> Result<Json<MessageChanges>, RESTError> {
+    let changes = app
+        .messages()
+        .fetch_changes(channel_id, token.data().user_id(), query.since, query.limit)
+        .await?;
 
-    // Check if the user is in the channel's guild
-    app.ops()
-        .fetch_member(token.data().user_id(), channel.guild_id())
-        .await?
-        .ok_or(RESTError::Forbidden("Not permitted to view resource.".into()))?;
+    Ok(Json(changes))
+}
 
+/// Translate the content of a message into another language.
+///
+/// ## Arguments
+///
+/// * `token` - The authorization token
+/// * `channel_id` - The ID of the channel the message is in
+/// * `message_id` - The ID of the message to translate
+/// * `query` - The language to translate into
+///
+/// ## Returns
+///
+/// * [`Translation`] - A JSON response containing the translated content
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/messages/{message_id}/translate`
+async fn translate_message(
+    Path((channel_id, message_id)): Path<(Snowflake<Channel>, Snowflake<Message>)>,
+    State(app): State<App>,
+    token: Token,
+    Query(query): Query<TranslateMessageQuery>,
+) -> Result<Json<Translation>, RESTError> {
+    let translation = app
+        .messages()
+        .translate(channel_id, message_id, token.data().user_id(), &query.to)
+        .await?;
+
+    Ok(Json(translation))
+}
+
+/// Search a channel's messages, newest messages first.
+///
+/// ## Arguments
+///
+/// * `token` - The authorization token
+/// * `channel_id` - The ID of the channel to search in
+/// * `query` - The search query and pagination
+///
+/// ## Returns
+///
+/// * [`Vec<Message>`] - A JSON response containing a list of matching [`Message`] objects
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/messages/search`
+async fn search_messages(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+    Query(query): Query<SearchMessagesQuery>,
+) -> Result<Json<Vec<Message>>, RESTError> {
     let messages = app
-        .ops()
-        .fetch_messages_from(channel_id, query.limit, query.before, query.after)
+        .messages()
+        .search(
+            channel_id,
+            token.data().user_id(),
+            &query.query,
+            query.limit,
+            query.before,
+        )
         .await?;
 
-    Ok((StatusCode::OK, Json(messages)))
+    Ok(Json(messages))
 }
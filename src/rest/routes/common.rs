@@ -1,21 +1,117 @@
 use std::time::Duration;
 
-use axum::Router;
+use axum::{middleware, Router};
 use http::{header, Method};
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::models::state::App;
+use crate::models::{
+    auth::CSRF_HEADER,
+    ip_filter::IpFilterScope,
+    rate_limit::{RateLimitBucket, X_RATELIMIT_BUCKET, X_RATELIMIT_LIMIT, X_RATELIMIT_REMAINING, X_RATELIMIT_RESET},
+    state::App,
+};
+use crate::rest::middleware::{csrf_protection, ip_filter, rate_limit, shed_load, track_load};
 
-use super::channels::get_router as get_channel_router;
-use super::guilds::get_router as get_guild_router;
+use super::admin::get_router as get_admin_router;
+use super::automod::get_router as get_automod_router;
+use super::channels::{
+    get_router as get_channel_router, get_search_router as get_message_search_router, get_translate_router,
+    get_upload_router as get_channel_upload_router,
+};
+use super::feeds::get_router as get_feed_router;
+use super::follows::get_router as get_follow_router;
+use super::guest_links::get_router as get_guest_link_router;
+use super::guilds::{get_analytics_router, get_router as get_guild_router};
+use super::limits::get_router as get_limits_router;
+use super::media::get_router as get_media_routes;
+use super::meta::get_router as get_meta_router;
+use super::onboarding::get_router as get_onboarding_router;
 use super::prefs::get_router as get_prefs_router;
-use super::users::get_router as get_user_router;
+use super::proxy::get_router as get_proxy_router;
+use super::push::get_router as get_push_router;
+use super::reports::get_router as get_report_router;
+use super::strikes::get_router as get_strike_router;
+use super::users::{get_router as get_user_router, get_search_router as get_user_search_router};
+use super::webhooks::get_router as get_webhook_router;
 
-/// Get all routes for the REST API. Includes CORS.
-pub fn get_router() -> Router<App> {
+/// Get all routes for the REST API, except for upload routes.
+/// Includes CORS, CSRF protection, rate limits and load shedding.
+pub fn get_router(app: &App) -> Router<App> {
+    rate_limited(get_channel_router(), app, RateLimitBucket::Channels)
+        .merge(low_priority(
+            rate_limited(get_message_search_router(), app, RateLimitBucket::Channels),
+            app,
+        ))
+        .merge(rate_limited(get_guild_router(), app, RateLimitBucket::Guilds))
+        .merge(low_priority(
+            rate_limited(get_analytics_router(), app, RateLimitBucket::Guilds),
+            app,
+        ))
+        .merge(rate_limited(get_onboarding_router(), app, RateLimitBucket::Guilds))
+        .merge(rate_limited(get_guest_link_router(), app, RateLimitBucket::Guilds))
+        .merge(rate_limited(get_user_router(), app, RateLimitBucket::Users))
+        .merge(rate_limited(get_push_router(), app, RateLimitBucket::Users))
+        .merge(low_priority(
+            rate_limited(get_user_search_router(), app, RateLimitBucket::UserSearch),
+            app,
+        ))
+        .merge(rate_limited(get_translate_router(), app, RateLimitBucket::Translations))
+        .merge(rate_limited(get_prefs_router(), app, RateLimitBucket::Prefs))
+        .merge(rate_limited(get_proxy_router(), app, RateLimitBucket::Proxy))
+        .merge(rate_limited(get_automod_router(), app, RateLimitBucket::AutoMod))
+        .merge(rate_limited(
+            ip_filtered(get_webhook_router(), app, IpFilterScope::Webhooks),
+            app,
+            RateLimitBucket::Webhooks,
+        ))
+        .merge(rate_limited(get_feed_router(), app, RateLimitBucket::Feeds))
+        .merge(rate_limited(get_follow_router(), app, RateLimitBucket::Follows))
+        .merge(rate_limited(get_report_router(), app, RateLimitBucket::Reports))
+        .merge(rate_limited(get_strike_router(), app, RateLimitBucket::Strikes))
+        .merge(get_limits_router())
+        .merge(get_meta_router())
+        .merge(ip_filtered(get_admin_router(), app, IpFilterScope::Admin))
+        .layer(middleware::from_fn_with_state(app.clone(), csrf_protection))
+        .layer(middleware::from_fn_with_state(app.clone(), track_load))
+        .layer(cors())
+}
+
+/// Get all routes of the REST API that accept file uploads. Includes CORS, CSRF protection and rate limits.
+/// These routes are never shed, but count towards the load of the server.
+pub fn get_upload_router(app: &App) -> Router<App> {
+    rate_limited(get_channel_upload_router(), app, RateLimitBucket::Messages)
+        .layer(middleware::from_fn_with_state(app.clone(), csrf_protection))
+        .layer(middleware::from_fn_with_state(app.clone(), track_load))
+        .layer(cors())
+}
+
+/// Get all routes serving stored media, such as attachments and avatars. Includes CORS.
+pub fn get_media_router() -> Router<App> {
+    get_media_routes().layer(cors())
+}
+
+/// Count requests to a group of routes towards a shared rate limit bucket.
+fn rate_limited(router: Router<App>, app: &App, bucket: RateLimitBucket) -> Router<App> {
+    router.route_layer(middleware::from_fn_with_state((app.clone(), bucket), rate_limit))
+}
+
+/// Reject requests to a group of sensitive routes from client addresses the IP filter of the scope does not permit.
+/// Requests rejected by the rate limit are not checked.
+fn ip_filtered(router: Router<App>, app: &App, scope: IpFilterScope) -> Router<App> {
+    router.route_layer(middleware::from_fn_with_state((app.clone(), scope), ip_filter))
+}
+
+/// Reject requests to a group of low priority routes while the server is overloaded.
+/// Shed requests do not count towards the rate limit.
+fn low_priority(router: Router<App>, app: &App) -> Router<App> {
+    router.route_layer(middleware::from_fn_with_state(app.clone(), shed_load))
+}
+
+/// The CORS policy of the REST API.
+fn cors() -> CorsLayer {
     // https://javascript.info/fetch-crossorigin
     // https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS
-    let cors = CorsLayer::new()
+    CorsLayer::new()
         // TODO: Change this to the actual origin
         .allow_origin(Any)
         .allow_methods([
@@ -31,12 +127,15 @@ pub fn get_router() -> Router<App> {
             header::ORIGIN,
             header::AUTHORIZATION,
             header::CACHE_CONTROL,
+            CSRF_HEADER,
         ])
-        .max_age(Duration::from_secs(3600));
-
-    get_channel_router()
-        .merge(get_guild_router())
-        .merge(get_user_router())
-        .merge(get_prefs_router())
-        .layer(cors)
+        // Allow clients to throttle themselves before hitting the rate limit
+        .expose_headers([
+            X_RATELIMIT_LIMIT,
+            X_RATELIMIT_REMAINING,
+            X_RATELIMIT_RESET,
+            X_RATELIMIT_BUCKET,
+            header::RETRY_AFTER,
+        ])
+        .max_age(Duration::from_hours(1))
 }
@@ -0,0 +1,203 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, patch},
+    Json, Router,
+};
+
+use crate::models::{
+    audit_log::{AuditLogAction, AuditLogEntry},
+    auth::Token,
+    channel::{Channel, ChannelLike},
+    errors::RESTError,
+    feed::{Feed, MAX_FEEDS_PER_CHANNEL},
+    guild::Guild,
+    requests::{CreateFeed, UpdateFeed},
+    snowflake::Snowflake,
+    state::App,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/channels/:channel_id/feeds", get(fetch_feeds).post(create_feed))
+        .route(
+            "/channels/:channel_id/feeds/:feed_id",
+            patch(update_feed).delete(delete_feed),
+        )
+}
+
+/// Fetch a feed and ensure that it belongs to the given channel.
+async fn fetch_channel_feed(
+    app: &App,
+    channel_id: Snowflake<Channel>,
+    feed_id: Snowflake<Feed>,
+) -> Result<Feed, RESTError> {
+    app.ops()
+        .fetch_feed(feed_id)
+        .await?
+        .filter(|feed| feed.channel_id() == channel_id)
+        .ok_or(RESTError::NotFound("Feed not found".into()))
+}
+
+/// Record a change to a feed in the audit log of the channel's guild.
+async fn log_feed_action(
+    app: &App,
+    guild: &Guild,
+    token: &Token,
+    action: AuditLogAction,
+    feed: &Feed,
+    reason: Option<String>,
+) -> Result<(), RESTError> {
+    let entry = AuditLogEntry::new(
+        &app.config,
+        guild.id(),
+        Some(token.data().user_id()),
+        action,
+        Some(feed.id().cast()),
+        reason,
+    );
+    app.ops().create_audit_log_entry(&entry).await?;
+    Ok(())
+}
+
+/// Fetch all feeds a channel is subscribed to.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel to fetch the feeds of
+///
+/// ## Returns
+///
+/// * [`Vec<Feed>`] - A JSON response containing a list of [`Feed`] objects
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/feeds`
+async fn fetch_feeds(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<Feed>>, RESTError> {
+    app.guilds()
+        .fetch_owned_channel(channel_id, token.data().user_id())
+        .await?;
+
+    Ok(Json(app.ops().fetch_feeds(channel_id).await?))
+}
+
+/// Subscribe a channel to a feed. New entries of the feed are posted to the channel in the background.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel to post entries to
+/// * `payload` - The [`CreateFeed`] payload
+///
+/// ## Returns
+///
+/// * [`Feed`] - A JSON response containing the created [`Feed`] object
+///
+/// ## Endpoint
+///
+/// POST `/channels/{channel_id}/feeds`
+async fn create_feed(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateFeed>,
+) -> Result<(StatusCode, Json<Feed>), RESTError> {
+    let (channel, guild) = app
+        .guilds()
+        .fetch_owned_channel(channel_id, token.data().user_id())
+        .await?;
+
+    if app.ops().fetch_feeds(channel_id).await?.len() >= MAX_FEEDS_PER_CHANNEL {
+        return Err(RESTError::BadRequest(format!(
+            "A channel may not have more than {MAX_FEEDS_PER_CHANNEL} feeds"
+        )));
+    }
+
+    let feed = Feed::from_payload(&app.config, channel.id(), payload, app.clock.timestamp())?;
+    feed.ensure_public_url().await?;
+    app.ops().update_feed(&feed).await?;
+
+    log_feed_action(&app, &guild, &token, AuditLogAction::FeedCreate, &feed, None).await?;
+
+    Ok((StatusCode::CREATED, Json(feed)))
+}
+
+/// Update a channel feed.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel the feed belongs to
+/// * `feed_id` - The ID of the feed to update
+/// * `payload` - The [`UpdateFeed`] payload
+///
+/// ## Returns
+///
+/// * [`Feed`] - A JSON response containing the updated [`Feed`] object
+///
+/// ## Endpoint
+///
+/// PATCH `/channels/{channel_id}/feeds/{feed_id}`
+async fn update_feed(
+    Path((channel_id, feed_id)): Path<(Snowflake<Channel>, Snowflake<Feed>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateFeed>,
+) -> Result<Json<Feed>, RESTError> {
+    let (_, guild) = app
+        .guilds()
+        .fetch_owned_channel(channel_id, token.data().user_id())
+        .await?;
+
+    let mut feed = fetch_channel_feed(&app, channel_id, feed_id).await?;
+    let url_changed = payload.url.is_some();
+    feed.update(payload, app.clock.timestamp())?;
+
+    if url_changed {
+        feed.ensure_public_url().await?;
+    }
+    app.ops().update_feed(&feed).await?;
+
+    log_feed_action(&app, &guild, &token, AuditLogAction::FeedUpdate, &feed, None).await?;
+
+    Ok(Json(feed))
+}
+
+/// Unsubscribe a channel from a feed. Entries already posted are kept.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel the feed belongs to
+/// * `feed_id` - The ID of the feed to delete
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/channels/{channel_id}/feeds/{feed_id}`
+async fn delete_feed(
+    Path((channel_id, feed_id)): Path<(Snowflake<Channel>, Snowflake<Feed>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let (_, guild) = app
+        .guilds()
+        .fetch_owned_channel(channel_id, token.data().user_id())
+        .await?;
+
+    let feed = fetch_channel_feed(&app, channel_id, feed_id).await?;
+    app.ops().delete_feed(feed.id()).await?;
+
+    let reason = Some(format!("Deleted feed '{}'", feed.title().unwrap_or_else(|| feed.url())));
+    log_feed_action(&app, &guild, &token, AuditLogAction::FeedDelete, &feed, reason).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,234 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+
+use crate::models::{
+    audit_log::{AuditLogAction, AuditLogEntry},
+    auth::Token,
+    channel::{Channel, ChannelLike},
+    crosspost::{ChannelFollow, MAX_FOLLOWS_PER_CHANNEL},
+    errors::{AppError, RESTError},
+    guild::Guild,
+    requests::CreateChannelFollow,
+    snowflake::Snowflake,
+    state::App,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route(
+            "/channels/:channel_id/followers",
+            get(fetch_followers).post(follow_channel),
+        )
+        .route("/channels/:channel_id/followers/:follow_id", delete(remove_follower))
+        .route("/channels/:channel_id/follows", get(fetch_follows))
+        .route("/channels/:channel_id/follows/:follow_id", delete(unfollow_channel))
+}
+
+/// Fetch a follow and ensure that it matches the given channel.
+async fn fetch_matching_follow(
+    app: &App,
+    follow_id: Snowflake<ChannelFollow>,
+    matches: impl FnOnce(&ChannelFollow) -> bool,
+) -> Result<ChannelFollow, RESTError> {
+    app.ops()
+        .fetch_channel_follow(follow_id)
+        .await?
+        .filter(matches)
+        .ok_or(RESTError::NotFound("Follow not found".into()))
+}
+
+/// Record a change to a follow in the audit log of a guild.
+async fn log_follow_action(
+    app: &App,
+    guild: &Guild,
+    token: &Token,
+    action: AuditLogAction,
+    follow: &ChannelFollow,
+) -> Result<(), RESTError> {
+    let entry = AuditLogEntry::new(
+        &app.config,
+        guild.id(),
+        Some(token.data().user_id()),
+        action,
+        Some(follow.id().cast()),
+        None,
+    );
+    app.ops().create_audit_log_entry(&entry).await?;
+    Ok(())
+}
+
+/// Fetch the channels following an announcement channel.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the announcement channel
+///
+/// ## Returns
+///
+/// * [`Vec<ChannelFollow>`] - A JSON response containing a list of [`ChannelFollow`] objects
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/followers`
+async fn fetch_followers(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<ChannelFollow>>, RESTError> {
+    app.guilds()
+        .fetch_owned_channel(channel_id, token.data().user_id())
+        .await?;
+
+    Ok(Json(app.ops().fetch_channel_followers(channel_id).await?))
+}
+
+/// Follow an announcement channel. Messages sent in it from now on are crossposted to the target channel.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the announcement channel to follow
+/// * `payload` - The [`CreateChannelFollow`] payload
+///
+/// ## Returns
+///
+/// * [`ChannelFollow`] - A JSON response containing the created [`ChannelFollow`] object
+///
+/// ## Endpoint
+///
+/// POST `/channels/{channel_id}/followers`
+async fn follow_channel(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateChannelFollow>,
+) -> Result<(StatusCode, Json<ChannelFollow>), RESTError> {
+    let user_id = token.data().user_id();
+    let (channel, _) = app.messages().fetch_channel(channel_id, user_id).await?;
+
+    if !channel.is_announcement() {
+        return Err(RESTError::BadRequest(
+            "Only announcement channels can be followed".into(),
+        ));
+    }
+    if payload.target_channel_id == channel.id() {
+        return Err(RESTError::BadRequest("A channel cannot follow itself".into()));
+    }
+
+    let (target, guild) = app
+        .guilds()
+        .fetch_owned_channel(payload.target_channel_id, user_id)
+        .await?;
+
+    if app.ops().fetch_channel_follows(target.id()).await?.len() >= MAX_FOLLOWS_PER_CHANNEL {
+        return Err(RESTError::BadRequest(format!(
+            "A channel may not follow more than {MAX_FOLLOWS_PER_CHANNEL} channels"
+        )));
+    }
+
+    let follow = ChannelFollow::new(&app.config, channel.id(), target.id(), user_id);
+    if !app.ops().create_channel_follow(&follow).await? {
+        return Err(AppError::Conflict("The channel already follows this channel.".into()).into());
+    }
+
+    log_follow_action(&app, &guild, &token, AuditLogAction::ChannelFollowCreate, &follow).await?;
+
+    Ok((StatusCode::CREATED, Json(follow)))
+}
+
+/// Remove a channel from the followers of an announcement channel.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the announcement channel
+/// * `follow_id` - The ID of the follow to remove
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/channels/{channel_id}/followers/{follow_id}`
+async fn remove_follower(
+    Path((channel_id, follow_id)): Path<(Snowflake<Channel>, Snowflake<ChannelFollow>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let (_, guild) = app
+        .guilds()
+        .fetch_owned_channel(channel_id, token.data().user_id())
+        .await?;
+
+    let follow = fetch_matching_follow(&app, follow_id, |follow| follow.channel_id() == channel_id).await?;
+    app.ops().delete_channel_follow(follow.id()).await?;
+
+    log_follow_action(&app, &guild, &token, AuditLogAction::ChannelFollowDelete, &follow).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the announcement channels a channel follows.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the following channel
+///
+/// ## Returns
+///
+/// * [`Vec<ChannelFollow>`] - A JSON response containing a list of [`ChannelFollow`] objects
+///
+/// ## Endpoint
+///
+/// GET `/channels/{channel_id}/follows`
+async fn fetch_follows(
+    Path(channel_id): Path<Snowflake<Channel>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<ChannelFollow>>, RESTError> {
+    app.guilds()
+        .fetch_owned_channel(channel_id, token.data().user_id())
+        .await?;
+
+    Ok(Json(app.ops().fetch_channel_follows(channel_id).await?))
+}
+
+/// Stop following an announcement channel. Messages that were already crossposted are kept.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the following channel
+/// * `follow_id` - The ID of the follow to delete
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/channels/{channel_id}/follows/{follow_id}`
+async fn unfollow_channel(
+    Path((channel_id, follow_id)): Path<(Snowflake<Channel>, Snowflake<ChannelFollow>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let (_, guild) = app
+        .guilds()
+        .fetch_owned_channel(channel_id, token.data().user_id())
+        .await?;
+
+    let follow = fetch_matching_follow(&app, follow_id, |follow| follow.target_channel_id() == channel_id).await?;
+    app.ops().delete_channel_follow(follow.id()).await?;
+
+    log_follow_action(&app, &guild, &token, AuditLogAction::ChannelFollowDelete, &follow).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,171 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, post},
+    Json, Router,
+};
+
+use crate::models::{
+    auth::Token,
+    channel::ChannelLike,
+    errors::RESTError,
+    guest_link::{GuestLink, MAX_GUEST_LINKS_PER_GUILD},
+    guild::Guild,
+    member::Member,
+    requests::CreateGuestLink,
+    snowflake::Snowflake,
+    state::App,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route(
+            "/guilds/:guild_id/guest-links",
+            get(fetch_guest_links).post(create_guest_link),
+        )
+        .route("/guilds/:guild_id/guest-links/:code", delete(delete_guest_link))
+        .route("/guest-links/:code", post(join_as_guest))
+}
+
+/// Fetch the guest links of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the guest links of
+///
+/// ## Returns
+///
+/// * [`Vec<GuestLink>`] - A JSON response containing a list of [`GuestLink`] objects
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/guest-links`
+async fn fetch_guest_links(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<GuestLink>>, RESTError> {
+    let guild = app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(app.ops().fetch_guest_links(&guild).await?))
+}
+
+/// Create a guest link, letting users join the guild as guests of the given channels.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to create the guest link for
+/// * `payload` - The [`CreateGuestLink`] payload
+///
+/// ## Returns
+///
+/// * [`GuestLink`] - A JSON response containing the created [`GuestLink`] object
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/guest-links`
+async fn create_guest_link(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateGuestLink>,
+) -> Result<(StatusCode, Json<GuestLink>), RESTError> {
+    let user_id = token.data().user_id();
+    let guild = app.guilds().fetch_as_owner(guild_id, user_id).await?;
+    let link = GuestLink::from_payload(&guild, user_id, payload, app.clock.timestamp())?;
+
+    // Archived channels are read-only, so they are not offered to guests
+    let channels = app
+        .ops()
+        .fetch_channels_for(&guild)
+        .await?
+        .into_iter()
+        .filter(|channel| !channel.is_archived())
+        .map(|channel| channel.id())
+        .collect::<HashSet<_>>();
+    if link.channel_ids().iter().any(|channel| !channels.contains(channel)) {
+        return Err(RESTError::BadRequest(
+            "Guest links can only grant access to unarchived channels of the guild".into(),
+        ));
+    }
+
+    if app.ops().fetch_guest_links(&guild).await?.len() >= MAX_GUEST_LINKS_PER_GUILD {
+        return Err(RESTError::BadRequest(format!(
+            "A guild may not have more than {MAX_GUEST_LINKS_PER_GUILD} guest links"
+        )));
+    }
+
+    app.ops().create_guest_link(&link).await?;
+
+    Ok((StatusCode::CREATED, Json(link)))
+}
+
+/// Delete a guest link. Guests who already joined through it keep their access until it expires.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the guest link belongs to
+/// * `code` - The code of the guest link to delete
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/guest-links/{code}`
+async fn delete_guest_link(
+    Path((guild_id, code)): Path<(Snowflake<Guild>, String)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    let guild = app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    if !app.ops().delete_guest_link(&guild, &code).await? {
+        return Err(RESTError::NotFound("Guest link not found".into()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Join the guild of a guest link as a guest, or renew the guest membership of the token-holder.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `code` - The code of the guest link to join through
+///
+/// ## Returns
+///
+/// * [`Member`] - A JSON response containing the created or renewed [`Member`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildCreate`] - For the user who joined the guild, with only the channels of the link
+/// * [`GatewayEvent::MemberCreate`] - For all members already in the guild, if the user was not a guest already
+/// * [`GatewayEvent::MemberUpdate`] - For all members in the guild, if the guest membership was renewed
+/// * [`WebhookEvent::MemberJoin`] - To all webhooks of the guild subscribed to it, if the user was not a guest already
+///
+/// ## Endpoint
+///
+/// POST `/guest-links/{code}`
+async fn join_as_guest(
+    Path(code): Path<String>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<(StatusCode, Json<Member>), RESTError> {
+    let link = app
+        .ops()
+        .fetch_guest_link(&code)
+        .await?
+        .ok_or(RESTError::NotFound("Guest link not found".into()))?;
+
+    let member = app.members().join_as_guest(&link, token.data().user_id()).await?;
+
+    Ok((StatusCode::CREATED, Json(member)))
+}
@@ -1,41 +1,79 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
-    routing::{delete, get, patch, post},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
+use serde::Deserialize;
+use serde_json::{json, Value};
 use tower_http::limit::RequestBodyLimitLayer;
 
 use crate::models::{
+    analytics::GuildAnalytics,
+    audit_log::{AuditLogAction, AuditLogEntry},
     auth::Token,
+    automod::MAX_TIMEOUT_DURATION,
+    bulk::MAX_BULK_OPERATIONS,
     channel::Channel,
+    emoji::EmojiUsage,
     errors::RESTError,
-    gateway_event::{DeletePayload, GatewayEvent},
+    gateway_event::GatewayEvent,
     guild::Guild,
+    jobs,
     member::Member,
-    requests::{CreateChannel, CreateGuild},
+    requests::{BulkGuildOperations, CreateChannel, CreateGuild, ImportMembers, UpdateMemberTimeout, UpdateRaidMode},
     snowflake::Snowflake,
     state::App,
     user::User,
 };
 use crate::models::{gateway_event::GuildCreatePayload, requests::UpdateGuild};
 
+/// The maximum amount of usernames that can be imported at once.
+const MAX_IMPORT_USERNAMES: usize = 1000;
+
+#[derive(Deserialize)]
+struct FetchEmojiStatsQuery {
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct FetchAnalyticsQuery {
+    days: Option<u32>,
+}
+
 pub fn get_router() -> Router<App> {
     Router::new()
         .route("/guilds", post(create_guild))
         .route("/guilds/:guild_id", get(fetch_guild))
         .route("/guilds/:guild_id/channels", post(create_channel))
+        .route("/guilds/:guild_id/bulk", post(bulk_guild_operations))
+        .route("/guilds/:guild_id/channels/archived", get(fetch_archived_channels))
         .route("/guilds/:guild_id/members", post(create_member))
         .route("/guilds/:guild_id/members/@me", get(fetch_member_self))
+        .route("/guilds/:guild_id/members/export", get(export_members))
+        .route("/guilds/:guild_id/members/import", post(import_members))
         .route("/guilds/:guild_id/members/:member_id", get(fetch_member))
+        .route("/guilds/:guild_id/stats/emojis", get(fetch_emoji_stats))
+        .route(
+            "/guilds/:guild_id/members/:member_id/timeout",
+            put(update_member_timeout),
+        )
         .route("/guilds/:guild_id/members/@me", delete(leave_guild))
         .route("/guilds/:guild_id", delete(delete_guild))
+        .route("/guilds/:guild_id/restore", post(restore_guild))
+        .route("/guilds/:guild_id/raid-mode", put(update_raid_mode))
         .route(
             "/guilds/:guild_id",
             patch(update_guild).layer(RequestBodyLimitLayer::new(2 * 1024 * 1024 /* 2mb */)),
         )
 }
 
+/// Get the guild analytics route, which is low priority and rejected while the server is overloaded.
+pub fn get_analytics_router() -> Router<App> {
+    Router::new().route("/guilds/:guild_id/analytics", get(fetch_analytics))
+}
+
 /// Create a new guild and return the guild data.
 ///
 /// ## Arguments
@@ -59,9 +97,11 @@ async fn create_guild(
     State(app): State<App>,
     Json(payload): Json<CreateGuild>,
 ) -> Result<(StatusCode, Json<Guild>), RESTError> {
+    app.members().check_guild_limit(token.data().user_id()).await?;
+
     let (guild, general, owner) = payload.perform_request(&app, token.data().user_id()).await?;
 
-    app.gateway.add_member(token.data().user_id(), &guild);
+    app.gateway.add_member(token.data().user_id(), &guild, None);
 
     app.gateway.dispatch(GatewayEvent::GuildCreate(GuildCreatePayload::new(
         guild.clone(),
@@ -72,6 +112,30 @@ async fn create_guild(
     Ok((StatusCode::CREATED, Json(guild)))
 }
 
+/// Fetch the archived channels of a guild, which are not included in the guild's channel list.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the archived channels of
+///
+/// ## Returns
+///
+/// * [`Vec<Channel>`] - A JSON response containing a list of archived [`Channel`] objects
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/channels/archived`
+async fn fetch_archived_channels(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<Channel>>, RESTError> {
+    app.guilds().fetch_as_member(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(app.ops().fetch_archived_channels(guild_id).await?))
+}
+
 /// Create a new channel in a guild and return the channel data.
 ///
 /// ## Arguments
@@ -97,23 +161,54 @@ async fn create_channel(
     token: Token,
     Json(payload): Json<CreateChannel>,
 ) -> Result<(StatusCode, Json<Channel>), RESTError> {
-    let guild = app
-        .ops()
-        .fetch_guild(guild_id)
-        .await
-        .ok_or(RESTError::NotFound("Guild not found".into()))?;
-
-    if guild.owner_id() != token.data().user_id() {
-        return Err(RESTError::Forbidden("You are not the owner of this guild.".into()));
-    }
+    let guild = app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+    let channel = app.guilds().create_channel(&guild, payload).await?;
 
-    let channel = Channel::from_payload(&app.config, payload, guild_id);
-
-    app.ops().create_channel(&channel).await?;
+    Ok((StatusCode::CREATED, Json(channel)))
+}
 
-    app.gateway.dispatch(GatewayEvent::ChannelCreate(channel.clone()));
+/// Perform multiple operations on a guild at once, such as creating many channels while setting up a guild.
+/// The operations are committed together, if any of them fails, none of them are.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to perform the operations on
+/// * `payload` - The [`BulkGuildOperations`] payload, containing the operations to perform in order
+///
+/// ## Returns
+///
+/// * [`Vec<BulkResult>`](crate::models::bulk::BulkResult) - A JSON response containing the result of every operation, in order
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::ChannelBulkUpdate`] - To all guild members, once for all operations
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/bulk`
+async fn bulk_guild_operations(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<BulkGuildOperations>,
+) -> Result<Response, RESTError> {
+    let guild = app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    if payload.operations.is_empty() || payload.operations.len() > MAX_BULK_OPERATIONS {
+        return Err(RESTError::BadRequest(format!(
+            "Must perform between 1 and {MAX_BULK_OPERATIONS} operations"
+        )));
+    }
 
-    Ok((StatusCode::CREATED, Json(channel)))
+    match app
+        .guilds()
+        .bulk(&guild, token.data().user_id(), payload.operations)
+        .await
+    {
+        Ok(results) => Ok(Json(results).into_response()),
+        Err(e) => Ok(e.into_response().await),
+    }
 }
 
 /// Fetch a guild's data.
@@ -135,18 +230,7 @@ async fn fetch_guild(
     State(app): State<App>,
     token: Token,
 ) -> Result<Json<Guild>, RESTError> {
-    app.ops()
-        .fetch_member(token.data().user_id(), guild_id)
-        .await?
-        .ok_or(RESTError::Forbidden("Not permitted to view resource.".into()))?;
-
-    let guild = app
-        .ops()
-        .fetch_guild(guild_id)
-        .await
-        .ok_or(RESTError::InternalServerError(
-            "Failed to fetch guild from database".into(),
-        ))?;
+    let guild = app.guilds().fetch_as_member(guild_id, token.data().user_id()).await?;
 
     Ok(Json(guild))
 }
@@ -172,26 +256,22 @@ async fn update_guild(
     token: Token,
     Json(payload): Json<UpdateGuild>,
 ) -> Result<Json<Guild>, RESTError> {
-    let guild = app
-        .ops()
-        .fetch_guild(guild_id)
-        .await
-        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
-
-    if guild.owner_id() != token.data().user_id() {
-        return Err(RESTError::Forbidden("Not permitted to update resource.".into()));
-    }
+    let guild = app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
     let guild = payload.perform_request(&app, &guild).await?;
     Ok(Json(guild))
 }
 
-/// Delete a guild and all associated objects
+/// Delete a guild. It is purged along with all associated objects once the grace period has passed.
 ///
 /// ## Arguments
 ///
 /// * `guild_id` - The ID of the guild to delete
 /// * `token` - The user's session token, already validated
 ///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildRemove`] - To all guild members
+///
 /// ## Endpoint
 ///
 /// DELETE `/guilds/{guild_id}`
@@ -200,21 +280,70 @@ async fn delete_guild(
     State(app): State<App>,
     token: Token,
 ) -> Result<StatusCode, RESTError> {
-    let guild = app
-        .ops()
-        .fetch_guild(guild_id)
-        .await
-        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
+    app.guilds().delete(guild_id, token.data().user_id()).await?;
 
-    if guild.owner_id() != token.data().user_id() {
-        return Err(RESTError::Forbidden("Not permitted to delete guild.".into()));
-    }
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    app.ops().delete_guild(&guild).await?;
+/// Restore a deleted guild before its grace period has passed
+///
+/// ## Arguments
+///
+/// * `guild_id` - The ID of the guild to restore
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Guild`] - A JSON response containing the restored [`Guild`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildCreate`] - To all guild members
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/restore`
+async fn restore_guild(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Guild>, RESTError> {
+    let guild = app.guilds().restore(guild_id, token.data().user_id()).await?;
 
-    app.gateway.dispatch(GatewayEvent::GuildRemove(guild));
+    Ok(Json(guild))
+}
 
-    Ok(StatusCode::NO_CONTENT)
+/// Enable or disable raid mode of a guild
+///
+/// ## Arguments
+///
+/// * `guild_id` - The ID of the guild
+/// * `token` - The user's session token, already validated
+/// * `payload` - The [`UpdateRaidMode`] payload, containing whether to enable raid mode and for how long
+///
+/// ## Returns
+///
+/// * [`Guild`] - A JSON response containing the updated [`Guild`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::RaidModeUpdate`] - To all guild members
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/raid-mode`
+async fn update_raid_mode(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateRaidMode>,
+) -> Result<Json<Guild>, RESTError> {
+    let guild = app
+        .guilds()
+        .update_raid_mode(guild_id, token.data().user_id(), payload)
+        .await?;
+
+    Ok(Json(guild))
 }
 
 /// Fetch a member's data.
@@ -237,11 +366,8 @@ async fn fetch_member(
     State(app): State<App>,
     token: Token,
 ) -> Result<Json<Member>, RESTError> {
-    // Check if the user is in the channel's guild
-    app.ops()
-        .fetch_member(token.data().user_id(), guild_id)
-        .await?
-        .ok_or(RESTError::Forbidden("Not permitted to view resource.".into()))?;
+    // Check if the user is in the guild
+    app.members().fetch_required(token.data().user_id(), guild_id).await?;
 
     let member = app
         .ops()
@@ -252,6 +378,197 @@ async fn fetch_member(
     Ok(Json(member))
 }
 
+/// Set or clear the timeout of a guild member. Timed out members may not send messages in the guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the member is in
+/// * `member_id` - The ID of the member to time out
+/// * `payload` - The [`UpdateMemberTimeout`] payload, containing the expiry of the timeout
+///
+/// ## Returns
+///
+/// * [`Member`] - A JSON response containing the updated [`Member`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::MemberUpdate`] - To all guild members
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/members/{member_id}/timeout`
+async fn update_member_timeout(
+    Path((guild_id, member_id)): Path<(Snowflake<Guild>, Snowflake<User>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateMemberTimeout>,
+) -> Result<Json<Member>, RESTError> {
+    let guild = app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    if member_id == guild.owner_id() {
+        return Err(RESTError::Forbidden("Cannot time out the owner of the guild.".into()));
+    }
+
+    let now = app.clock.now();
+    if let Some(until) = payload.until {
+        if until <= now.timestamp() || until > now.timestamp() + MAX_TIMEOUT_DURATION {
+            return Err(RESTError::BadRequest(
+                "Timeout must expire in the future and at most 28 days from now.".into(),
+            ));
+        }
+    }
+
+    let mut member = app
+        .ops()
+        .fetch_member(member_id, guild_id)
+        .await?
+        .ok_or(RESTError::NotFound("Member does not exist or is not available.".into()))?;
+
+    member.set_timeout_until(payload.until, now);
+    app.ops().update_member(&member).await?;
+
+    let entry = AuditLogEntry::new(
+        &app.config,
+        guild_id,
+        Some(token.data().user_id()),
+        AuditLogAction::MemberTimeoutUpdate,
+        Some(member_id.cast()),
+        None,
+    );
+    app.ops().create_audit_log_entry(&entry).await?;
+
+    Ok(Json(member))
+}
+
+/// Export the member list of a guild, including nicknames and join dates.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to export the members of
+///
+/// ## Returns
+///
+/// * [`Vec<Member>`] - A JSON response containing a list of all [`Member`] objects in the guild
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/members/export`
+async fn export_members(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<Member>>, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(app.ops().fetch_members_for(guild_id).await?))
+}
+
+/// Invite users to a guild by their usernames. The users are not added to the guild directly,
+/// instead they receive an invite they may accept by joining the guild.
+///
+/// The import is processed in the background, progress is reported to the token-holder over the gateway.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to invite the users to
+/// * `payload` - The [`ImportMembers`] payload, containing the usernames to invite
+///
+/// ## Returns
+///
+/// * `{"job_id": job_id}` - A JSON response containing the ID of the import job
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::InviteCreate`] - To every invited user
+/// * [`GatewayEvent::MemberImportProgress`] - To the token-holder, as the import progresses
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/members/import`
+async fn import_members(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<ImportMembers>,
+) -> Result<(StatusCode, Json<Value>), RESTError> {
+    let guild = app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    let mut usernames = payload.usernames;
+    usernames.sort_unstable();
+    usernames.dedup();
+
+    if usernames.is_empty() || usernames.len() > MAX_IMPORT_USERNAMES {
+        return Err(RESTError::BadRequest(format!(
+            "Must import between 1 and {MAX_IMPORT_USERNAMES} usernames"
+        )));
+    }
+
+    let job_id = Snowflake::gen_new(&app.config);
+
+    app.jobs.spawn(
+        "import_members",
+        jobs::import_members(app.clone(), job_id, guild, token.data().user_id(), usernames),
+    );
+
+    Ok((StatusCode::ACCEPTED, Json(json!({ "job_id": job_id }))))
+}
+
+/// Fetch the most used emojis of a guild, most used first.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the emoji usage of
+/// * `query` - The query parameters
+///
+/// ## Returns
+///
+/// * [`Vec<EmojiUsage>`] - A JSON response containing a list of [`EmojiUsage`] objects
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/stats/emojis`
+async fn fetch_emoji_stats(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Query(query): Query<FetchEmojiStatsQuery>,
+) -> Result<Json<Vec<EmojiUsage>>, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(app.ops().fetch_emoji_usage(guild_id, query.limit).await?))
+}
+
+/// Fetch the activity of a guild over the last days.
+/// The requester must be the owner of the guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the analytics of
+/// * `query` - The query parameters
+///
+/// ## Returns
+///
+/// * [`GuildAnalytics`] - A JSON response containing the [`GuildAnalytics`] of the guild
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/analytics`
+async fn fetch_analytics(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Query(query): Query<FetchAnalyticsQuery>,
+) -> Result<Json<GuildAnalytics>, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(app.ops().fetch_guild_analytics(guild_id, query.days).await?))
+}
+
 /// Fetch the current user's member data.
 ///
 /// ## Arguments
@@ -295,6 +612,7 @@ async fn fetch_member_self(
 ///
 /// * [`GatewayEvent::GuildCreate`] - For the user who joined the guild
 /// * [`GatewayEvent::MemberCreate`] - For all members already in the guild
+/// * [`WebhookEvent::MemberJoin`] - To all webhooks of the guild subscribed to it
 ///
 /// ## Endpoint
 ///
@@ -304,33 +622,7 @@ async fn create_member(
     State(app): State<App>,
     token: Token,
 ) -> Result<(StatusCode, Json<Member>), RESTError> {
-    let guild = app
-        .ops()
-        .fetch_guild(guild_id)
-        .await
-        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
-
-    app.ops().create_member(&guild, token.data().user_id()).await?;
-
-    let member =
-        app.ops()
-            .fetch_member(token.data().user_id(), guild_id)
-            .await?
-            .ok_or(RESTError::InternalServerError(
-                "A member should have been created.".into(),
-            ))?;
-
-    // Create payload seperately as it needs read access to gateway
-    let gc_payload = GatewayEvent::GuildCreate(GuildCreatePayload::from_guild(&app, guild).await?);
-
-    // Send GUILD_CREATE to the user who joined
-    app.gateway.send_to(&member, gc_payload);
-
-    // Add the member to the gateway's cache
-    app.gateway.add_member(&member, guild_id);
-
-    // Dispatch the member create event to all guild members
-    app.gateway.dispatch(GatewayEvent::MemberCreate(member.clone()));
+    let member = app.members().join(guild_id, token.data().user_id()).await?;
 
     Ok((StatusCode::CREATED, Json(member)))
 }
@@ -350,6 +642,7 @@ async fn create_member(
 ///
 /// * [`GatewayEvent::GuildRemove`] - For the user who left the guild
 /// * [`GatewayEvent::MemberRemove`] - For all members still in the guild
+/// * [`WebhookEvent::MemberLeave`] - To all webhooks of the guild subscribed to it
 ///
 /// ## Endpoint
 ///
@@ -359,35 +652,7 @@ async fn leave_guild(
     State(app): State<App>,
     token: Token,
 ) -> Result<StatusCode, RESTError> {
-    let guild = app
-        .ops()
-        .fetch_guild(guild_id)
-        .await
-        .ok_or(RESTError::NotFound("Guild does not exist or is not available.".into()))?;
-    let member = app
-        .ops()
-        .fetch_member(token.data().user_id(), guild_id)
-        .await?
-        .ok_or(RESTError::NotFound("Member does not exist or is not available.".into()))?;
-
-    if member.user().id() == guild.owner_id() {
-        return Err(RESTError::Forbidden("Owner cannot leave owned guild.".into()));
-    }
-
-    app.ops().delete_member(&guild, token.data().user_id()).await?;
-
-    // Remove the member from the gateway's sessions
-    app.gateway.remove_member(token.data().user_id(), guild_id);
-
-    // Send GUILD_REMOVE to the user who left
-    app.gateway
-        .send_to(member.user().id(), GatewayEvent::GuildRemove(guild));
-
-    // Dispatch the member remove event
-    app.gateway.dispatch(GatewayEvent::MemberRemove(DeletePayload::new(
-        member.user().id(),
-        Some(member.guild_id()),
-    )));
+    app.members().leave(guild_id, token.data().user_id()).await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
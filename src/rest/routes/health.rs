@@ -0,0 +1,60 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use serde_json::{json, Value};
+
+use crate::models::{errors::RESTError, metrics, state::App};
+
+/// Get the health check routes. These routes are not affected by fail-fast behavior during outages.
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/health", get(health))
+        .route("/metrics", get(fetch_metrics))
+}
+
+/// Report the health of the backend and its dependencies.
+///
+/// ## Returns
+///
+/// * `{"database": stats}` - A JSON response containing the database pool statistics,
+///   with status `503` if the database is unreachable
+///
+/// ## Endpoint
+///
+/// GET `/health`
+async fn health(State(app): State<App>) -> (StatusCode, Json<Value>) {
+    let stats = app.db.stats();
+
+    let code = if stats.healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (code, Json(json!({ "database": stats })))
+}
+
+/// Export metrics in the Prometheus text format. Only available if enabled in the configuration.
+///
+/// ## Returns
+///
+/// * The metrics in the Prometheus text exposition format
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If metrics are not enabled
+///
+/// ## Endpoint
+///
+/// GET `/metrics`
+async fn fetch_metrics(State(app): State<App>) -> Result<impl IntoResponse, RESTError> {
+    if !app.config.metrics_enabled() {
+        return Err(RESTError::NotFound("Metrics are not enabled".into()));
+    }
+
+    Ok(([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], metrics::render()))
+}
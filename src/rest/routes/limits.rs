@@ -0,0 +1,20 @@
+use axum::{extract::State, routing::get, Json, Router};
+
+use crate::models::{limits::Limits, state::App};
+
+pub fn get_router() -> Router<App> {
+    Router::new().route("/limits", get(fetch_limits))
+}
+
+/// Fetch the limits enforced when creating resources, so clients can check them before making requests.
+///
+/// ## Returns
+///
+/// * [`Limits`] - A JSON response containing the limits
+///
+/// ## Endpoint
+///
+/// GET `/limits`
+async fn fetch_limits(State(app): State<App>) -> Json<Limits> {
+    Json(app.settings.limits())
+}
@@ -0,0 +1,233 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+
+use crate::models::{
+    attachment::{format_s3_key, ScanStatus},
+    avatar::{AvatarKind, GuildAvatar, UserAvatar},
+    bucket::{Bucket, ObjectResponse},
+    channel::Channel,
+    errors::RESTError,
+    guild::Guild,
+    message::Message,
+    snowflake::Snowflake,
+    state::App,
+    user::User,
+};
+
+/// The path parameters of an attachment URL: channel, message, attachment ID, key token and filename.
+type AttachmentPath = (Snowflake<Channel>, Snowflake<Message>, u8, String, String);
+
+/// Stored media never changes under the same key, so it may be cached indefinitely.
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route(
+            "/attachments/:channel_id/:message_id/:attachment_id/:key_token/:filename",
+            get(fetch_attachment),
+        )
+        .route(
+            "/attachments/:channel_id/:message_id/:attachment_id/:filename",
+            get(fetch_legacy_attachment),
+        )
+        .route("/users/:user_id/:avatar", get(fetch_user_avatar))
+        .route("/guilds/:guild_id/:icon", get(fetch_guild_icon))
+}
+
+/// Fetch the contents of a message attachment.
+///
+/// ## Arguments
+///
+/// * `channel_id` - The ID of the channel the message was sent in
+/// * `message_id` - The ID of the message the attachment belongs to
+/// * `attachment_id` - The ID of the attachment within the message
+/// * `key_token` - The random component of the attachment's key
+/// * `filename` - The name of the attachment file
+/// * `headers` - The request headers, used for range and conditional requests
+///
+/// ## Returns
+///
+/// * The attachment contents, with caching headers
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the attachment does not exist, the key token is wrong, the attachment was removed
+///   as infected, or has not been scanned clean while scanning is enabled
+///
+/// ## Endpoint
+///
+/// GET `/media/attachments/{channel_id}/{message_id}/{attachment_id}/{key_token}/{filename}`
+async fn fetch_attachment(
+    Path((channel_id, message_id, attachment_id, key_token, filename)): Path<AttachmentPath>,
+    State(app): State<App>,
+    headers: HeaderMap,
+) -> Result<Response, RESTError> {
+    ensure_servable(&app, message_id, attachment_id).await?;
+
+    let key = format_s3_key(channel_id, message_id, attachment_id, Some(&key_token), &filename);
+    serve_object(app.s3.attachments(), key, &headers).await
+}
+
+/// Fetch the contents of a message attachment that was not moved to a key with a key token yet.
+/// Once it is moved, it is only served under its new URL.
+///
+/// ## Endpoint
+///
+/// GET `/media/attachments/{channel_id}/{message_id}/{attachment_id}/{filename}`
+async fn fetch_legacy_attachment(
+    Path((channel_id, message_id, attachment_id, filename)): Path<(Snowflake<Channel>, Snowflake<Message>, u8, String)>,
+    State(app): State<App>,
+    headers: HeaderMap,
+) -> Result<Response, RESTError> {
+    ensure_servable(&app, message_id, attachment_id).await?;
+
+    let key = format_s3_key(channel_id, message_id, attachment_id, None, &filename);
+    serve_object(app.s3.attachments(), key, &headers).await
+}
+
+/// Ensure the contents of an attachment may be served, if malware scanning is enabled.
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the attachment does not exist, was removed as infected,
+///   or has not been scanned clean
+async fn ensure_servable(app: &App, message_id: Snowflake<Message>, attachment_id: u8) -> Result<(), RESTError> {
+    if !app.scanner.is_enabled() {
+        return Ok(());
+    }
+
+    let status = app
+        .ops()
+        .fetch_attachment_scan_status(message_id, attachment_id)
+        .await?
+        .ok_or_else(|| RESTError::NotFound("Media not found".into()))?;
+
+    if !status.is_servable(app.config.serve_unscanned_attachments()) {
+        return Err(RESTError::NotFound(match status {
+            ScanStatus::Infected => "Attachment was removed as it contains malware".into(),
+            _ => "Attachment has not been scanned for malware yet".into(),
+        }));
+    }
+    Ok(())
+}
+
+/// Fetch a user's avatar.
+///
+/// ## Arguments
+///
+/// * `user_id` - The ID of the user
+/// * `avatar` - The avatar hash, followed by the file extension
+/// * `headers` - The request headers, used for range and conditional requests
+///
+/// ## Returns
+///
+/// * The avatar contents, with caching headers
+///
+/// ## Endpoint
+///
+/// GET `/media/users/{user_id}/{avatar_hash}.{avatar_ext}`
+async fn fetch_user_avatar(
+    Path((user_id, avatar)): Path<(Snowflake<User>, String)>,
+    State(app): State<App>,
+    headers: HeaderMap,
+) -> Result<Response, RESTError> {
+    let bucket = app.s3.get(UserAvatar.content_class());
+    serve_object(bucket, format!("{user_id}/{avatar}"), &headers).await
+}
+
+/// Fetch a guild's icon.
+///
+/// ## Arguments
+///
+/// * `guild_id` - The ID of the guild
+/// * `icon` - The icon hash, followed by the file extension
+/// * `headers` - The request headers, used for range and conditional requests
+///
+/// ## Returns
+///
+/// * The icon contents, with caching headers
+///
+/// ## Endpoint
+///
+/// GET `/media/guilds/{guild_id}/{avatar_hash}.{avatar_ext}`
+async fn fetch_guild_icon(
+    Path((guild_id, icon)): Path<(Snowflake<Guild>, String)>,
+    State(app): State<App>,
+    headers: HeaderMap,
+) -> Result<Response, RESTError> {
+    let bucket = app.s3.get(GuildAvatar.content_class());
+    serve_object(bucket, format!("{guild_id}/{icon}"), &headers).await
+}
+
+/// Stream an object from S3 to the client, honoring `Range` and `If-None-Match` request headers.
+///
+/// ## Arguments
+///
+/// * `bucket` - The bucket the object is stored in
+/// * `key` - The key of the object
+/// * `headers` - The request headers
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the object does not exist
+/// * [`RESTError::App`] - If S3 is unavailable or the request to it fails
+async fn serve_object(bucket: Bucket<'_>, key: String, headers: &HeaderMap) -> Result<Response, RESTError> {
+    let header_str = |name| {
+        headers
+            .get(name)
+            .and_then(|v: &HeaderValue| v.to_str().ok())
+            .map(String::from)
+    };
+
+    let object = match bucket
+        .stream_object(key, header_str(header::RANGE), header_str(header::IF_NONE_MATCH))
+        .await?
+    {
+        ObjectResponse::Found(object) => object,
+        ObjectResponse::NotModified => {
+            let mut response = StatusCode::NOT_MODIFIED.into_response();
+            response
+                .headers_mut()
+                .insert(header::CACHE_CONTROL, HeaderValue::from_static(CACHE_CONTROL));
+            if let Some(e_tag) = headers.get(header::IF_NONE_MATCH) {
+                response.headers_mut().insert(header::ETAG, e_tag.clone());
+            }
+            return Ok(response);
+        }
+        ObjectResponse::NotFound => return Err(RESTError::NotFound("Media not found".into())),
+        ObjectResponse::RangeNotSatisfiable => return Ok(StatusCode::RANGE_NOT_SATISFIABLE.into_response()),
+    };
+
+    let mut response = Response::builder()
+        .header(header::CACHE_CONTROL, CACHE_CONTROL)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        // Uploaded files must never be able to run scripts on our origin
+        .header(header::CONTENT_SECURITY_POLICY, "default-src 'none'; sandbox")
+        .header(
+            header::CONTENT_TYPE,
+            object.content_type().unwrap_or("application/octet-stream"),
+        );
+
+    if let Some(e_tag) = object.e_tag() {
+        response = response.header(header::ETAG, e_tag);
+    }
+    if let Some(length) = object.content_length() {
+        response = response.header(header::CONTENT_LENGTH, length);
+    }
+    if let Some(range) = object.content_range() {
+        response = response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(header::CONTENT_RANGE, range);
+    }
+
+    response
+        .body(Body::from_stream(object.into_stream()))
+        .map_err(|e| RESTError::InternalServerError(format!("Failed to build media response: {e}")))
+}
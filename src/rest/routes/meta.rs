@@ -0,0 +1,25 @@
+use axum::{extract::State, routing::get, Json, Router};
+use serde_json::{json, Value};
+
+use crate::models::state::App;
+
+pub fn get_router() -> Router<App> {
+    Router::new().route("/meta", get(fetch_meta))
+}
+
+/// Fetch information about the server that clients need to interpret its responses.
+///
+/// ## Returns
+///
+/// * `{"version": string, "snowflake_epoch": int}` - A JSON response containing the version of the server
+///   and the epoch of its snowflakes
+///
+/// ## Endpoint
+///
+/// GET `/meta`
+async fn fetch_meta(State(app): State<App>) -> Json<Value> {
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "snowflake_epoch": app.config.snowflake_epoch(),
+    }))
+}
@@ -1,7 +1,22 @@
+pub mod admin;
+pub mod automod;
 pub mod channels;
 pub mod common;
+pub mod feeds;
+pub mod follows;
+pub mod guest_links;
 pub mod guilds;
+pub mod health;
+pub mod limits;
+pub mod media;
+pub mod meta;
+pub mod onboarding;
 pub mod prefs;
+pub mod proxy;
+pub mod push;
+pub mod reports;
+pub mod strikes;
 pub mod users;
+pub mod webhooks;
 
-pub use common::get_router;
+pub use common::{get_media_router, get_router, get_upload_router};
@@ -0,0 +1,134 @@
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Json, Router,
+};
+
+use crate::models::{
+    auth::Token,
+    errors::RESTError,
+    guild::Guild,
+    onboarding::{MemberOnboarding, Onboarding},
+    requests::CompleteOnboarding,
+    snowflake::Snowflake,
+    state::App,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route(
+            "/guilds/:guild_id/onboarding",
+            get(fetch_onboarding).put(update_onboarding),
+        )
+        .route(
+            "/guilds/:guild_id/members/@me/onboarding",
+            get(fetch_member_onboarding).put(complete_onboarding),
+        )
+}
+
+/// Fetch the onboarding of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the onboarding of
+///
+/// ## Returns
+///
+/// * [`Onboarding`] - A JSON response containing the guild's [`Onboarding`]
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/onboarding`
+async fn fetch_onboarding(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Onboarding>, RESTError> {
+    let onboarding = app.onboarding().fetch(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(onboarding))
+}
+
+/// Replace the onboarding of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to update the onboarding of
+/// * `payload` - The new [`Onboarding`]
+///
+/// ## Returns
+///
+/// * [`Onboarding`] - A JSON response containing the updated [`Onboarding`]
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/onboarding`
+async fn update_onboarding(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<Onboarding>,
+) -> Result<Json<Onboarding>, RESTError> {
+    let onboarding = app
+        .onboarding()
+        .update(guild_id, token.data().user_id(), payload)
+        .await?;
+
+    Ok(Json(onboarding))
+}
+
+/// Fetch how the current user completed the onboarding of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the onboarding of
+///
+/// ## Returns
+///
+/// * [`MemberOnboarding`] - A JSON response containing the user's [`MemberOnboarding`],
+///   or `null` if they did not complete the onboarding
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/members/@me/onboarding`
+async fn fetch_member_onboarding(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Option<MemberOnboarding>>, RESTError> {
+    let onboarding = app.onboarding().fetch_member(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(onboarding))
+}
+
+/// Complete the onboarding of a guild, replacing any earlier answers.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to complete the onboarding of
+/// * `payload` - The [`CompleteOnboarding`] payload
+///
+/// ## Returns
+///
+/// * [`MemberOnboarding`] - A JSON response containing the user's [`MemberOnboarding`]
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/members/@me/onboarding`
+async fn complete_onboarding(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CompleteOnboarding>,
+) -> Result<Json<MemberOnboarding>, RESTError> {
+    let onboarding = app
+        .onboarding()
+        .complete(guild_id, token.data().user_id(), payload.responses)
+        .await?;
+
+    Ok(Json(onboarding))
+}
@@ -5,7 +5,10 @@ use axum::{
     Json, Router,
 };
 
-use crate::models::{auth::Token, prefs::Prefs};
+use crate::models::{
+    auth::Token,
+    prefs::{PrefFlags, Prefs},
+};
 use crate::models::{errors::RESTError, requests::UpdatePrefs, state::App};
 
 pub fn get_router() -> Router<App> {
@@ -26,8 +29,15 @@ async fn update_prefs(
     token: Token,
     Json(payload): Json<UpdatePrefs>,
 ) -> Result<StatusCode, RESTError> {
-    let mut prefs = Prefs::fetch(app.clone(), token.data().user_id()).await?;
-    prefs.update(payload);
-    prefs.commit(app).await?;
+    let user_id = token.data().user_id();
+    let mut prefs = Prefs::fetch(app.clone(), user_id).await?;
+    let data_saver = prefs.flags.contains(PrefFlags::DATA_SAVER);
+    prefs.update(payload)?;
+    prefs.commit(app.clone()).await?;
+
+    // Connected sessions start or stop reducing events right away
+    if prefs.flags.contains(PrefFlags::DATA_SAVER) != data_saver {
+        app.gateway.set_data_saver(user_id, !data_saver);
+    }
     Ok(StatusCode::NO_CONTENT)
 }
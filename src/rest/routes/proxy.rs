@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::models::{auth::Token, errors::RESTError, state::App};
+
+#[derive(Deserialize, Debug, Clone)]
+struct ProxyUrlQuery {
+    url: String,
+}
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/proxy", get(create_proxy_url))
+        .route("/proxy/:signature/:url", get(fetch_proxied_media))
+}
+
+/// Create a signed media proxy URL for an external image.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `query` - The query parameters, containing the external URL
+///
+/// ## Returns
+///
+/// * `{"url": url}` - A JSON response containing the proxied URL
+///
+/// ## Endpoint
+///
+/// GET `/proxy`
+async fn create_proxy_url(
+    State(app): State<App>,
+    _token: Token,
+    Query(query): Query<ProxyUrlQuery>,
+) -> Result<Json<Value>, RESTError> {
+    if !app.settings.media_proxy_enabled() {
+        return Err(RESTError::NotFound("Media proxy is not enabled".into()));
+    }
+
+    let url = Url::parse(&query.url).map_err(|_| RESTError::MalformedField("url".into()))?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(RESTError::BadRequest("Only HTTP(S) URLs can be proxied".into()));
+    }
+
+    Ok(Json(json!({ "url": app.media_proxy.proxied_url(&url) })))
+}
+
+/// Fetch an external image through the media proxy.
+///
+/// This endpoint is not authenticated, as it is meant to be used directly in image tags.
+/// Instead, the URL must be signed by the server.
+///
+/// ## Arguments
+///
+/// * `signature` - The signature of the external URL
+/// * `url` - The hex-encoded external URL
+///
+/// ## Returns
+///
+/// * The image contents, with caching and security headers
+///
+/// ## Endpoint
+///
+/// GET `/proxy/{signature}/{url}`
+async fn fetch_proxied_media(
+    Path((signature, url)): Path<(String, String)>,
+    State(app): State<App>,
+) -> Result<impl IntoResponse, RESTError> {
+    if !app.settings.media_proxy_enabled() {
+        return Err(RESTError::NotFound("Media proxy is not enabled".into()));
+    }
+
+    let url = app.media_proxy.verify(&signature, &url)?;
+    let media = app.media_proxy.fetch(&url).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, media.content_type().to_string()),
+            (header::CACHE_CONTROL, "public, max-age=86400, immutable".to_string()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            // Proxied media is untrusted, it must never run scripts on the origin of the API
+            (
+                header::CONTENT_SECURITY_POLICY,
+                "sandbox; default-src 'none'".to_string(),
+            ),
+        ],
+        media.content().clone(),
+    ))
+}
@@ -0,0 +1,135 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use serde_json::{json, Value};
+
+use crate::models::{
+    auth::Token,
+    errors::RESTError,
+    push::{PushDevice, MAX_DEVICES_PER_USER},
+    requests::CreatePushDevice,
+    snowflake::Snowflake,
+    state::App,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/push", get(fetch_push_config))
+        .route(
+            "/users/@me/push-devices",
+            get(fetch_push_devices).post(create_push_device),
+        )
+        .route("/users/@me/push-devices/:device_id", delete(delete_push_device))
+}
+
+/// Fetch the platforms push notifications can be delivered to, and the keys clients need to subscribe.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Value`] - A JSON response containing the supported platforms and the public VAPID key, if Web Push is enabled
+///
+/// ## Endpoint
+///
+/// GET `/push`
+async fn fetch_push_config(State(app): State<App>, _token: Token) -> Json<Value> {
+    Json(json!({
+        "platforms": app.push.platforms(),
+        "vapid_public_key": app.config.vapid_public_key(),
+    }))
+}
+
+/// Fetch all devices the current user registered for push notifications.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Vec<PushDevice>`] - A JSON response containing a list of [`PushDevice`] objects
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/push-devices`
+async fn fetch_push_devices(State(app): State<App>, token: Token) -> Result<Json<Vec<PushDevice>>, RESTError> {
+    Ok(Json(app.ops().fetch_push_devices(token.data().user_id()).await?))
+}
+
+/// Register a device of the current user for push notifications.
+/// Registering a device again returns the existing device.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `payload` - The [`CreatePushDevice`] payload
+///
+/// ## Returns
+///
+/// * [`PushDevice`] - A JSON response containing the registered [`PushDevice`] object
+///
+/// ## Endpoint
+///
+/// POST `/users/@me/push-devices`
+async fn create_push_device(
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreatePushDevice>,
+) -> Result<(StatusCode, Json<PushDevice>), RESTError> {
+    let user_id = token.data().user_id();
+
+    if !app.push.supports(payload.platform) {
+        return Err(RESTError::BadRequest(
+            "Push notifications are not enabled for this platform".into(),
+        ));
+    }
+
+    let device = PushDevice::from_payload(&app.config, user_id, &payload, app.clock.timestamp())?;
+    let devices = app.ops().fetch_push_devices(user_id).await?;
+
+    let registered = devices
+        .iter()
+        .any(|d| d.platform() == device.platform() && d.token() == device.token());
+
+    if !registered && devices.len() >= MAX_DEVICES_PER_USER {
+        return Err(RESTError::BadRequest(format!(
+            "A user may not register more than {MAX_DEVICES_PER_USER} devices for push notifications"
+        )));
+    }
+
+    let device = app.ops().create_push_device(&device).await?;
+
+    Ok((StatusCode::CREATED, Json(device)))
+}
+
+/// Remove a device of the current user, it stops receiving push notifications.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `device_id` - The ID of the device to remove
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/users/@me/push-devices/{device_id}`
+async fn delete_push_device(
+    Path(device_id): Path<Snowflake<PushDevice>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    if !app.ops().delete_push_device(token.data().user_id(), device_id).await? {
+        return Err(RESTError::NotFound("Push device not found".into()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
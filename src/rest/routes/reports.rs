@@ -0,0 +1,129 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, patch, post},
+    Json, Router,
+};
+use serde::Deserialize;
+
+use crate::models::{
+    auth::Token,
+    channel::Channel,
+    errors::RESTError,
+    guild::Guild,
+    message::Message,
+    report::{Report, ReportState},
+    requests::{CreateReport, UpdateReport},
+    snowflake::Snowflake,
+    state::App,
+};
+
+#[derive(Deserialize, Debug, Clone)]
+struct FetchReportsQuery {
+    state: Option<ReportState>,
+    limit: Option<u32>,
+    before: Option<Snowflake<Report>>,
+}
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/channels/:channel_id/messages/:message_id/report", post(create_report))
+        .route("/guilds/:guild_id/reports", get(fetch_reports))
+        .route("/guilds/:guild_id/reports/:report_id", patch(update_report))
+}
+
+/// Report a message to the owner of the guild it was sent in.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `channel_id` - The ID of the channel the message was sent in
+/// * `message_id` - The ID of the message to report
+/// * `payload` - The [`CreateReport`] payload
+///
+/// ## Returns
+///
+/// * [`Report`] - A JSON response containing the created [`Report`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::ReportCreate`] - For the owner of the guild
+///
+/// ## Endpoint
+///
+/// POST `/channels/{channel_id}/messages/{message_id}/report`
+///
+/// [`GatewayEvent::ReportCreate`]: crate::models::gateway_event::GatewayEvent::ReportCreate
+async fn create_report(
+    Path((channel_id, message_id)): Path<(Snowflake<Channel>, Snowflake<Message>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateReport>,
+) -> Result<(StatusCode, Json<Report>), RESTError> {
+    let report = app
+        .reports()
+        .create(channel_id, message_id, token.data().user_id(), payload)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(report)))
+}
+
+/// Fetch the reports made in a guild, newest first.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the reports of
+/// * `query` - The state to filter by, and the pagination of the reports
+///
+/// ## Returns
+///
+/// * [`Vec<Report>`] - A JSON response containing a list of [`Report`] objects
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/reports`
+async fn fetch_reports(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    Query(query): Query<FetchReportsQuery>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<Report>>, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(
+        app.ops()
+            .fetch_reports(guild_id, query.state, query.limit, query.before)
+            .await?,
+    ))
+}
+
+/// Review an open report in a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the report belongs to
+/// * `report_id` - The ID of the report to review
+/// * `payload` - The [`UpdateReport`] payload
+///
+/// ## Returns
+///
+/// * [`Report`] - A JSON response containing the reviewed [`Report`] object
+///
+/// ## Endpoint
+///
+/// PATCH `/guilds/{guild_id}/reports/{report_id}`
+async fn update_report(
+    Path((guild_id, report_id)): Path<(Snowflake<Guild>, Snowflake<Report>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateReport>,
+) -> Result<Json<Report>, RESTError> {
+    let report = app
+        .reports()
+        .review(guild_id, report_id, token.data().user_id(), payload.state)
+        .await?;
+
+    Ok(Json(report))
+}
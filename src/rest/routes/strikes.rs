@@ -0,0 +1,315 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+
+use crate::models::{
+    auth::Token,
+    errors::RESTError,
+    fingerprint::EvasionFlag,
+    guild::Guild,
+    requests::CreateStrike,
+    snowflake::Snowflake,
+    state::App,
+    strike::{GuildBan, Standing, Strike, StrikePolicy},
+    user::User,
+};
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route(
+            "/guilds/:guild_id/strike-policy",
+            get(fetch_strike_policy).put(update_strike_policy),
+        )
+        .route("/guilds/:guild_id/members/@me/standing", get(fetch_standing))
+        .route(
+            "/guilds/:guild_id/members/:member_id/strikes",
+            get(fetch_strikes).post(create_strike),
+        )
+        .route(
+            "/guilds/:guild_id/members/:member_id/strikes/:strike_id",
+            delete(delete_strike),
+        )
+        .route("/guilds/:guild_id/bans", get(fetch_bans))
+        .route("/guilds/:guild_id/bans/:user_id", delete(delete_ban))
+        .route("/guilds/:guild_id/evasion-flags", get(fetch_evasion_flags))
+        .route("/guilds/:guild_id/evasion-flags/:user_id", delete(delete_evasion_flag))
+}
+
+/// Fetch the strike policy of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the strike policy of
+///
+/// ## Returns
+///
+/// * [`StrikePolicy`] - A JSON response containing the guild's [`StrikePolicy`]
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/strike-policy`
+async fn fetch_strike_policy(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<StrikePolicy>, RESTError> {
+    let policy = app.strikes().fetch_policy(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(policy))
+}
+
+/// Replace the strike policy of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to update the strike policy of
+/// * `payload` - The new [`StrikePolicy`]
+///
+/// ## Returns
+///
+/// * [`StrikePolicy`] - A JSON response containing the updated [`StrikePolicy`]
+///
+/// ## Endpoint
+///
+/// PUT `/guilds/{guild_id}/strike-policy`
+async fn update_strike_policy(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<StrikePolicy>,
+) -> Result<Json<StrikePolicy>, RESTError> {
+    let policy = app
+        .strikes()
+        .update_policy(guild_id, token.data().user_id(), payload)
+        .await?;
+
+    Ok(Json(policy))
+}
+
+/// Fetch the standing of the current user in a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the standing in
+///
+/// ## Returns
+///
+/// * [`Standing`] - A JSON response containing the user's active strikes and the guild's strike policy
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/members/@me/standing`
+async fn fetch_standing(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Standing>, RESTError> {
+    let standing = app.strikes().fetch_standing(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(standing))
+}
+
+/// Fetch the active strikes of a guild member.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the member is in
+/// * `member_id` - The ID of the member to fetch the strikes of
+///
+/// ## Returns
+///
+/// * [`Vec<Strike>`] - A JSON response containing a list of [`Strike`] objects, oldest first
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/members/{member_id}/strikes`
+async fn fetch_strikes(
+    Path((guild_id, member_id)): Path<(Snowflake<Guild>, Snowflake<User>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<Strike>>, RESTError> {
+    let strikes = app
+        .strikes()
+        .fetch_active(guild_id, member_id, token.data().user_id())
+        .await?;
+
+    Ok(Json(strikes))
+}
+
+/// Issue a strike to a guild member. The member may be timed out or banned, depending on the guild's strike policy.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the member is in
+/// * `member_id` - The ID of the member to issue the strike to
+/// * `payload` - The [`CreateStrike`] payload
+///
+/// ## Returns
+///
+/// * [`Strike`] - A JSON response containing the created [`Strike`] object
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::StrikeCreate`] - For the member who was issued the strike
+/// * [`GatewayEvent::MemberUpdate`] - To all guild members, if the member was timed out
+/// * [`GatewayEvent::MemberRemove`] - To all guild members, if the member was banned
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/members/{member_id}/strikes`
+///
+/// [`GatewayEvent::StrikeCreate`]: crate::models::gateway_event::GatewayEvent::StrikeCreate
+/// [`GatewayEvent::MemberUpdate`]: crate::models::gateway_event::GatewayEvent::MemberUpdate
+/// [`GatewayEvent::MemberRemove`]: crate::models::gateway_event::GatewayEvent::MemberRemove
+async fn create_strike(
+    Path((guild_id, member_id)): Path<(Snowflake<Guild>, Snowflake<User>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateStrike>,
+) -> Result<(StatusCode, Json<Strike>), RESTError> {
+    let strike = app
+        .strikes()
+        .issue(guild_id, member_id, token.data().user_id(), payload)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(strike)))
+}
+
+/// Remove a strike from a guild member before it expires.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the strike was issued in
+/// * `member_id` - The ID of the member the strike was issued to
+/// * `strike_id` - The ID of the strike to remove
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/members/{member_id}/strikes/{strike_id}`
+async fn delete_strike(
+    Path((guild_id, member_id, strike_id)): Path<(Snowflake<Guild>, Snowflake<User>, Snowflake<Strike>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    app.strikes()
+        .remove(guild_id, member_id, strike_id, token.data().user_id())
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the bans of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the bans of
+///
+/// ## Returns
+///
+/// * [`Vec<GuildBan>`] - A JSON response containing a list of [`GuildBan`] objects
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/bans`
+async fn fetch_bans(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<GuildBan>>, RESTError> {
+    let bans = app.strikes().fetch_bans(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(bans))
+}
+
+/// Lift the ban of a user from a guild, so they may join it again.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the user is banned from
+/// * `user_id` - The ID of the banned user
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/bans/{user_id}`
+async fn delete_ban(
+    Path((guild_id, user_id)): Path<(Snowflake<Guild>, Snowflake<User>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    app.strikes().unban(guild_id, user_id, token.data().user_id()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the members of a guild who were flagged for sharing a fingerprint with a banned user.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the flags of
+///
+/// ## Returns
+///
+/// * [`Vec<EvasionFlag>`] - A JSON response containing the guild's open [`EvasionFlag`]s, oldest first
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/evasion-flags`
+async fn fetch_evasion_flags(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<EvasionFlag>>, RESTError> {
+    let flags = app
+        .strikes()
+        .fetch_evasion_flags(guild_id, token.data().user_id())
+        .await?;
+
+    Ok(Json(flags))
+}
+
+/// Dismiss the evasion flag of a member after reviewing it.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the member is flagged in
+/// * `user_id` - The ID of the flagged member
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/evasion-flags/{user_id}`
+async fn delete_evasion_flag(
+    Path((guild_id, user_id)): Path<(Snowflake<Guild>, Snowflake<User>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    app.strikes()
+        .dismiss_evasion_flag(guild_id, user_id, token.data().user_id())
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,44 +1,94 @@
+use std::net::SocketAddr;
+
 use axum::{
-    extract::State,
-    http::StatusCode,
-    routing::{get, patch, post},
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, StatusCode},
+    response::{AppendHeaders, IntoResponse, Response},
+    routing::{get, patch, post, put},
     Json, Router,
 };
 use secrecy::ExposeSecret;
+use serde::Deserialize;
 use serde_json::json;
 use tower_http::limit::RequestBodyLimitLayer;
 
 use crate::models::{
-    auth::{Credentials, StoredCredentials, Token},
-    gateway_event::{GatewayEvent, PresenceUpdatePayload},
+    auth::{self, Credentials, StoredCredentials, Token},
+    channel::Channel,
+    errors::{BuildError, ErrorCode},
+    fingerprint::{self, MAX_DEVICE_ID_LENGTH},
     guild::Guild,
+    guild_folder::GuildSettings,
+    invite::GuildInvite,
+    presence_privacy::PresencePrivacy,
+    profile::{Profile, ProfilePrivacy},
     requests::CreateUser,
+    session::GatewaySession,
+    snowflake::Snowflake,
     state::App,
-    user::{Presence, User},
+    user::{validate_search_query, Presence, User},
+};
+use crate::models::{
+    errors::RESTError,
+    requests::{UpdateGuildSettings, UpdateUser},
 };
-use crate::models::{errors::RESTError, requests::UpdateUser};
 use crate::rest::auth::{generate_hash, validate_credentials};
-use serde_json::Value;
+
+#[derive(Deserialize)]
+struct AuthUserQuery {
+    #[serde(default)]
+    cookie: bool,
+}
+
+#[derive(Deserialize)]
+struct SearchUsersQuery {
+    username: String,
+    #[serde(default)]
+    fuzzy: bool,
+    limit: Option<u32>,
+}
 
 pub fn get_router() -> Router<App> {
     Router::new()
         .route("/users", post(create_user))
-        .route("/users/auth", post(auth_user))
+        .route("/users/auth", post(auth_user).delete(end_cookie_session))
         .route("/users/@me", get(fetch_self))
         .route("/users/@me/guilds", get(fetch_self_guilds))
+        .route("/users/@me/invites", get(fetch_self_invites))
+        .route("/users/@me/sessions", get(fetch_self_sessions))
+        .route("/users/@me/saved-messages", get(fetch_saved_messages))
         .route("/users/@me/presence", patch(update_presence))
-        .route("/usernames/:username", get(query_username))
+        .route("/users/@me/presence/privacy", get(fetch_presence_privacy))
+        .route("/users/@me/presence/privacy", put(update_presence_privacy))
+        .route(
+            "/users/@me/profile/privacy",
+            get(fetch_profile_privacy).put(update_profile_privacy),
+        )
+        .route(
+            "/users/@me/guild-settings",
+            get(fetch_guild_settings).patch(update_guild_settings),
+        )
+        .route("/users/:user_id/profile", get(fetch_profile))
         .route(
             "/users/@me",
             patch(update_self).layer(RequestBodyLimitLayer::new(2 * 1024 * 1024 /* 2mb */)),
         )
 }
 
+/// Get the user search route, which has its own rate limit bucket.
+pub fn get_search_router() -> Router<App> {
+    Router::new().route("/users/search", get(search_users))
+}
+
 /// Create a new user and return the user data.
 ///
+/// If fingerprints are enabled, salted hashes of the device identifier and address the user registers from
+/// are recorded to detect ban evasion.
+///
 /// ## Arguments
 ///
-/// * `payload` - The `CreateUser` payload, containing the username and password
+/// * `payload` - The `CreateUser` payload, containing the username, password and optionally a device identifier
+/// * `connect_info` - The address the request was sent from
 ///
 /// ## Returns
 ///
@@ -47,8 +97,25 @@ pub fn get_router() -> Router<App> {
 /// ## Endpoint
 ///
 /// POST `/users`
-async fn create_user(State(app): State<App>, Json(payload): Json<CreateUser>) -> Result<Json<User>, RESTError> {
+async fn create_user(
+    State(app): State<App>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Json(payload): Json<CreateUser>,
+) -> Result<Json<User>, RESTError> {
     let password = payload.password.clone();
+    let device_id = payload.device_id.clone();
+
+    if device_id
+        .as_ref()
+        .is_some_and(|id| id.chars().count() > MAX_DEVICE_ID_LENGTH)
+    {
+        return Err(BuildError::InvalidField {
+            field: "device_id",
+            code: ErrorCode::TooLong,
+            message: format!("Device ID must be at most {MAX_DEVICE_ID_LENGTH} characters long."),
+        }
+        .into());
+    }
 
     let user = User::from_payload(&app.config, &payload)?;
 
@@ -59,11 +126,23 @@ async fn create_user(State(app): State<App>, Json(payload): Json<CreateUser>) ->
         )));
     }
 
-    let credentials = StoredCredentials::new(user.id(), generate_hash(&password)?);
+    app.ops().ensure_username_not_reserved(user.username()).await?;
+
+    let hash = generate_hash(app.config.argon2_params(), &password)?;
 
     // User needs to be created before credentials to avoid foreign key constraint
-    app.ops().create_user(payload).await?;
-    credentials.commit(app).await?;
+    let user = app.ops().create_user(payload).await?;
+    StoredCredentials::new(user.id(), hash, app.clock.now())
+        .commit(app.clone())
+        .await?;
+
+    if let Some(salt) = app.config.fingerprint_salt() {
+        let ip = connect_info.map(|ConnectInfo(addr)| addr.ip());
+        let hashes = fingerprint::fingerprints(salt, device_id.as_deref(), ip);
+        if !hashes.is_empty() {
+            app.ops().create_fingerprints(&user, &hashes).await?;
+        }
+    }
 
     Ok(Json(user))
 }
@@ -72,23 +151,63 @@ async fn create_user(State(app): State<App>, Json(payload): Json<CreateUser>) ->
 ///
 /// ## Arguments
 ///
+/// * `query` - If `cookie` is set, the token is stored in a session cookie instead of being returned
 /// * `credentials` - The user's credentials
 ///
 /// ## Returns
 ///
 /// * `{"user_id": user_id, "token": token}` - A JSON response containing the session token and `user_id`
+/// * `{"user_id": user_id, "csrf_token": csrf_token}` - If `cookie` is set, the CSRF token of the session instead
 ///
 /// ## Endpoint
 ///
 /// POST `/users/auth`
-async fn auth_user(State(app): State<App>, Json(credentials): Json<Credentials>) -> Result<Json<Value>, RESTError> {
+async fn auth_user(
+    State(app): State<App>,
+    Query(query): Query<AuthUserQuery>,
+    Json(credentials): Json<Credentials>,
+) -> Result<Response, RESTError> {
+    if query.cookie && !app.config.auth_cookies_enabled() {
+        return Err(RESTError::BadRequest("Cookie sessions are not enabled".into()));
+    }
+
     let user_id = validate_credentials(app.clone(), credentials).await?;
-    let token = Token::new_for(app.config.app_secret(), user_id)?;
+    let token = Token::new_for(app.config.signing_keys(), user_id, app.clock.now())?;
 
-    Ok(Json(json!({
-        "user_id": user_id,
-        "token": token.expose_secret(),
-    })))
+    if !query.cookie {
+        return Ok(Json(json!({
+            "user_id": user_id,
+            "token": token.expose_secret(),
+        }))
+        .into_response());
+    }
+
+    let [session, csrf] = auth::session_cookies(&app.config, Some(&token));
+
+    Ok((
+        AppendHeaders([(header::SET_COOKIE, session), (header::SET_COOKIE, csrf)]),
+        Json(json!({
+            "user_id": user_id,
+            "csrf_token": token.csrf_token(app.config.signing_keys()),
+        })),
+    )
+        .into_response())
+}
+
+/// End the cookie session of a browser client by removing its session cookies.
+///
+/// The session is not required to be valid, so that expired sessions can be cleared as well.
+///
+/// ## Endpoint
+///
+/// DELETE `/users/auth`
+async fn end_cookie_session(State(app): State<App>) -> impl IntoResponse {
+    let [session, csrf] = auth::session_cookies(&app.config, None);
+
+    (
+        StatusCode::NO_CONTENT,
+        AppendHeaders([(header::SET_COOKIE, session), (header::SET_COOKIE, csrf)]),
+    )
 }
 
 /// Get the current user's data.
@@ -133,6 +252,65 @@ async fn fetch_self_guilds(State(app): State<App>, token: Token) -> Result<Json<
     Ok(Json(guilds))
 }
 
+/// Fetch the token-holder's saved messages channel.
+/// Messages are sent to and fetched from it through the regular channel endpoints, and are only visible to the user.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Channel`] - A JSON response containing the user's saved messages [`Channel`]
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/saved-messages`
+async fn fetch_saved_messages(State(app): State<App>, token: Token) -> Result<Json<Channel>, RESTError> {
+    let channel = app.ops().fetch_saved_messages(token.data().user_id()).await?;
+
+    Ok(Json(channel))
+}
+
+/// Fetch the token-holder's pending guild invites.
+/// Invites are accepted by joining the guild they belong to.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Vec<GuildInvite>`] - A JSON response containing the pending [`GuildInvite`] objects
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/invites`
+async fn fetch_self_invites(State(app): State<App>, token: Token) -> Result<Json<Vec<GuildInvite>>, RESTError> {
+    let invites = app.ops().fetch_invites_for(token.data().user_id()).await?;
+
+    Ok(Json(invites))
+}
+
+/// Fetch the token-holder's gateway sessions, including the client properties each client sent.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`Vec<GatewaySession>`] - A JSON response containing the [`GatewaySession`] objects, oldest first
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/sessions`
+async fn fetch_self_sessions(State(app): State<App>, token: Token) -> Result<Json<Vec<GatewaySession>>, RESTError> {
+    let sessions = app.ops().fetch_gateway_sessions(token.data().user_id()).await?;
+
+    Ok(Json(sessions))
+}
+
 /// Update the token-holder's presence.
 ///
 /// ## Arguments
@@ -161,27 +339,213 @@ pub async fn update_presence(
     token: Token,
     Json(new_presence): Json<Presence>,
 ) -> Result<Json<Presence>, RESTError> {
-    let user_id_i64: i64 = token.data().user_id().into();
-
-    sqlx::query!(
-        "UPDATE users SET last_presence = $1 WHERE id = $2",
-        new_presence as i16,
-        user_id_i64
-    )
-    .execute(app.db.pool())
-    .await?;
-
-    if app.gateway.is_connected(token.data().user_id()) {
-        app.gateway
-            .dispatch(GatewayEvent::PresenceUpdate(PresenceUpdatePayload {
-                presence: new_presence,
-                user_id: token.data().user_id(),
-            }));
-    }
+    app.presences().update(token.data().user_id(), new_presence).await?;
 
     Ok(Json(new_presence))
 }
 
+/// Fetch who can see the token-holder's presence.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`PresencePrivacy`] - A JSON response containing the user's [`PresencePrivacy`] settings
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the user is not found
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/presence/privacy`
+async fn fetch_presence_privacy(State(app): State<App>, token: Token) -> Result<Json<PresencePrivacy>, RESTError> {
+    let privacy = app
+        .ops()
+        .fetch_presence_privacy(token.data().user_id())
+        .await
+        .ok_or_else(|| RESTError::NotFound("User not found".into()))?;
+
+    Ok(Json(privacy))
+}
+
+/// Replace who can see the token-holder's presence.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `privacy` - The new settings
+///
+/// ## Returns
+///
+/// * [`PresencePrivacy`] - A JSON response containing the updated [`PresencePrivacy`] settings
+///
+/// ## Errors
+///
+/// * [`RESTError::App`] - If the settings are invalid or the database query fails
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::PresenceUpdate`] - For all members in guilds shared with the user whose view of the presence changed
+///
+/// ## Endpoint
+///
+/// PUT `/users/@me/presence/privacy`
+async fn update_presence_privacy(
+    State(app): State<App>,
+    token: Token,
+    Json(privacy): Json<PresencePrivacy>,
+) -> Result<Json<PresencePrivacy>, RESTError> {
+    app.presences()
+        .update_privacy(token.data().user_id(), privacy.clone())
+        .await?;
+
+    Ok(Json(privacy))
+}
+
+/// Fetch how the token-holder arranges their guilds.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`GuildSettings`] - A JSON response containing the user's [`GuildSettings`]
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/guild-settings`
+async fn fetch_guild_settings(State(app): State<App>, token: Token) -> Result<Json<GuildSettings>, RESTError> {
+    Ok(Json(app.guilds().fetch_settings(token.data().user_id()).await?))
+}
+
+/// Update how the token-holder arranges their guilds, such as their order and folders.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `payload` - The [`UpdateGuildSettings`] payload, containing the settings to change
+///
+/// ## Returns
+///
+/// * [`GuildSettings`] - A JSON response containing the updated [`GuildSettings`]
+///
+/// ## Errors
+///
+/// * [`RESTError::App`] - If the settings are invalid or the database query fails
+///
+/// ## Dispatches
+///
+/// * [`GatewayEvent::GuildSettingsUpdate`] - To the user, so that all of their sessions follow the new layout
+///
+/// ## Endpoint
+///
+/// PATCH `/users/@me/guild-settings`
+async fn update_guild_settings(
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateGuildSettings>,
+) -> Result<Json<GuildSettings>, RESTError> {
+    let settings = app.guilds().update_settings(token.data().user_id(), payload).await?;
+
+    Ok(Json(settings))
+}
+
+/// Fetch who can see the token-holder's profile.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+///
+/// ## Returns
+///
+/// * [`ProfilePrivacy`] - A JSON response containing the user's [`ProfilePrivacy`] settings
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the user is not found
+///
+/// ## Endpoint
+///
+/// GET `/users/@me/profile/privacy`
+async fn fetch_profile_privacy(State(app): State<App>, token: Token) -> Result<Json<ProfilePrivacy>, RESTError> {
+    let privacy = app
+        .ops()
+        .fetch_profile_privacy(token.data().user_id())
+        .await?
+        .ok_or_else(|| RESTError::NotFound("User not found".into()))?;
+
+    Ok(Json(privacy))
+}
+
+/// Replace who can see the token-holder's profile.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `privacy` - The new settings
+///
+/// ## Returns
+///
+/// * [`ProfilePrivacy`] - A JSON response containing the updated [`ProfilePrivacy`] settings
+///
+/// ## Errors
+///
+/// * [`RESTError::App`] - If the database query fails
+///
+/// ## Endpoint
+///
+/// PUT `/users/@me/profile/privacy`
+async fn update_profile_privacy(
+    State(app): State<App>,
+    token: Token,
+    Json(privacy): Json<ProfilePrivacy>,
+) -> Result<Json<ProfilePrivacy>, RESTError> {
+    app.ops()
+        .update_profile_privacy(token.data().user_id(), privacy)
+        .await?;
+
+    Ok(Json(privacy))
+}
+
+/// Fetch the public profile of a user, such as for a popover.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `user_id` - The ID of the user whose profile to fetch
+///
+/// ## Returns
+///
+/// * [`Profile`] - A JSON response containing the user's [`Profile`], including the amount of mutual guilds
+///
+/// ## Errors
+///
+/// * [`RESTError::NotFound`] - If the user does not exist, or hides their profile from the token-holder
+///
+/// ## Endpoint
+///
+/// GET `/users/{user_id}/profile`
+async fn fetch_profile(
+    State(app): State<App>,
+    token: Token,
+    Path(user_id): Path<Snowflake<User>>,
+) -> Result<Json<Profile>, RESTError> {
+    let viewer = token.data().user_id();
+    let profile = app.ops().fetch_profile(user_id, viewer).await?;
+
+    // Hidden profiles are indistinguishable from users that do not exist
+    match profile {
+        Some((profile, privacy)) if user_id == viewer || privacy.is_visible_to(profile.mutual_guild_count()) => {
+            Ok(Json(profile))
+        }
+        _ => Err(RESTError::NotFound("User not found".into())),
+    }
+}
+
 /// Update the token-holder's user data.
 ///
 /// ## Arguments
@@ -211,25 +575,37 @@ pub async fn update_self(
     Ok(Json(user))
 }
 
-/// Check for the existence of a user with the given username.
+/// Search for users by their username. Only authenticated users may search,
+/// and searches are limited per user to make enumerating users impractical.
 ///
 /// ## Arguments
 ///
-/// * `username` - The username to check for
+/// * `token` - The authorization token
+/// * `query` - The username to search for, whether to match it fuzzily, and the maximum amount of results
+///
+/// ## Returns
+///
+/// * [`Vec<User>`] - A JSON response containing the matching [`User`] objects, without presences
 ///
 /// ## Errors
 ///
-/// * [`RESTError::NotFound`] - If the user is not found
-/// * [`RESTError::App`] - If the database query fails
+/// * [`RESTError::App`] - If the query is invalid or the database query fails
 ///
 /// ## Endpoint
 ///
-/// GET `/users/{username}`
-pub async fn query_username(State(app): State<App>, username: String) -> Result<StatusCode, RESTError> {
-    sqlx::query!("SELECT id FROM users WHERE username = $1", username)
-        .fetch_optional(app.db.pool())
-        .await?
-        .ok_or(RESTError::NotFound("User not found".into()))?;
+/// GET `/users/search`
+async fn search_users(
+    State(app): State<App>,
+    _: Token,
+    Query(query): Query<SearchUsersQuery>,
+) -> Result<Json<Vec<User>>, RESTError> {
+    validate_search_query(&query.username)?;
+    let limit = query.limit.unwrap_or(10).clamp(1, 25);
+
+    let users = app
+        .ops()
+        .search_users(&query.username, query.fuzzy, i64::from(limit))
+        .await?;
 
-    Ok(StatusCode::OK)
+    Ok(Json(users))
 }
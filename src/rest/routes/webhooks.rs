@@ -0,0 +1,237 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, patch},
+    Json, Router,
+};
+use serde::Deserialize;
+
+use crate::models::{
+    audit_log::{AuditLogAction, AuditLogEntry},
+    auth::Token,
+    errors::RESTError,
+    guild::Guild,
+    requests::{CreateWebhook, UpdateWebhook},
+    snowflake::Snowflake,
+    state::App,
+    webhook::{Webhook, WebhookDelivery, MAX_WEBHOOKS_PER_GUILD},
+};
+
+#[derive(Deserialize, Debug, Clone)]
+struct FetchDeliveriesQuery {
+    limit: Option<u32>,
+    before: Option<Snowflake<WebhookDelivery>>,
+}
+
+pub fn get_router() -> Router<App> {
+    Router::new()
+        .route("/guilds/:guild_id/webhooks", get(fetch_webhooks).post(create_webhook))
+        .route(
+            "/guilds/:guild_id/webhooks/:webhook_id",
+            patch(update_webhook).delete(delete_webhook),
+        )
+        .route(
+            "/guilds/:guild_id/webhooks/:webhook_id/deliveries",
+            get(fetch_webhook_deliveries),
+        )
+}
+
+/// Fetch a webhook and ensure that it belongs to the given guild.
+async fn fetch_guild_webhook(
+    app: &App,
+    guild_id: Snowflake<Guild>,
+    webhook_id: Snowflake<Webhook>,
+) -> Result<Webhook, RESTError> {
+    app.ops()
+        .fetch_webhook(webhook_id)
+        .await?
+        .filter(|webhook| webhook.guild_id() == guild_id)
+        .ok_or(RESTError::NotFound("Webhook not found".into()))
+}
+
+/// Fetch all webhooks of a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to fetch the webhooks of
+///
+/// ## Returns
+///
+/// * [`Vec<Webhook>`] - A JSON response containing a list of [`Webhook`] objects
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/webhooks`
+async fn fetch_webhooks(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<Json<Vec<Webhook>>, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    Ok(Json(app.ops().fetch_webhooks(guild_id).await?))
+}
+
+/// Create a new webhook in a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild to create the webhook in
+/// * `payload` - The [`CreateWebhook`] payload
+///
+/// ## Returns
+///
+/// * [`Webhook`] - A JSON response containing the created [`Webhook`] object, including its signing secret
+///
+/// ## Endpoint
+///
+/// POST `/guilds/{guild_id}/webhooks`
+async fn create_webhook(
+    Path(guild_id): Path<Snowflake<Guild>>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<CreateWebhook>,
+) -> Result<(StatusCode, Json<Webhook>), RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    if app.ops().fetch_webhooks(guild_id).await?.len() >= MAX_WEBHOOKS_PER_GUILD {
+        return Err(RESTError::BadRequest(format!(
+            "A guild may not have more than {MAX_WEBHOOKS_PER_GUILD} webhooks"
+        )));
+    }
+
+    let webhook = Webhook::from_payload(&app.config, guild_id, payload)?;
+    webhook.ensure_public_url().await?;
+    app.ops().update_webhook(&webhook).await?;
+
+    let entry = AuditLogEntry::new(
+        &app.config,
+        guild_id,
+        Some(token.data().user_id()),
+        AuditLogAction::WebhookCreate,
+        Some(webhook.id().cast()),
+        None,
+    );
+    app.ops().create_audit_log_entry(&entry).await?;
+
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+/// Update a webhook in a guild.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the webhook belongs to
+/// * `webhook_id` - The ID of the webhook to update
+/// * `payload` - The [`UpdateWebhook`] payload
+///
+/// ## Returns
+///
+/// * [`Webhook`] - A JSON response containing the updated [`Webhook`] object
+///
+/// ## Endpoint
+///
+/// PATCH `/guilds/{guild_id}/webhooks/{webhook_id}`
+async fn update_webhook(
+    Path((guild_id, webhook_id)): Path<(Snowflake<Guild>, Snowflake<Webhook>)>,
+    State(app): State<App>,
+    token: Token,
+    Json(payload): Json<UpdateWebhook>,
+) -> Result<Json<Webhook>, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    let mut webhook = fetch_guild_webhook(&app, guild_id, webhook_id).await?;
+    let url_changed = payload.url.is_some();
+    webhook.update(payload)?;
+
+    if url_changed {
+        webhook.ensure_public_url().await?;
+    }
+    app.ops().update_webhook(&webhook).await?;
+
+    let entry = AuditLogEntry::new(
+        &app.config,
+        guild_id,
+        Some(token.data().user_id()),
+        AuditLogAction::WebhookUpdate,
+        Some(webhook.id().cast()),
+        None,
+    );
+    app.ops().create_audit_log_entry(&entry).await?;
+
+    Ok(Json(webhook))
+}
+
+/// Delete a webhook from a guild, along with its delivery log.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the webhook belongs to
+/// * `webhook_id` - The ID of the webhook to delete
+///
+/// ## Returns
+///
+/// * [`StatusCode`] - 204 No Content if successful
+///
+/// ## Endpoint
+///
+/// DELETE `/guilds/{guild_id}/webhooks/{webhook_id}`
+async fn delete_webhook(
+    Path((guild_id, webhook_id)): Path<(Snowflake<Guild>, Snowflake<Webhook>)>,
+    State(app): State<App>,
+    token: Token,
+) -> Result<StatusCode, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    let webhook = fetch_guild_webhook(&app, guild_id, webhook_id).await?;
+    app.ops().delete_webhook(webhook.id()).await?;
+
+    let entry = AuditLogEntry::new(
+        &app.config,
+        guild_id,
+        Some(token.data().user_id()),
+        AuditLogAction::WebhookDelete,
+        Some(webhook.id().cast()),
+        Some(format!("Deleted webhook '{}'", webhook.name())),
+    );
+    app.ops().create_audit_log_entry(&entry).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Fetch the delivery log of a webhook, newest deliveries first.
+///
+/// ## Arguments
+///
+/// * `token` - The user's session token, already validated
+/// * `guild_id` - The ID of the guild the webhook belongs to
+/// * `webhook_id` - The ID of the webhook to fetch the deliveries of
+/// * `query` - The query parameters
+///
+/// ## Returns
+///
+/// * [`Vec<WebhookDelivery>`] - A JSON response containing a list of [`WebhookDelivery`] objects
+///
+/// ## Endpoint
+///
+/// GET `/guilds/{guild_id}/webhooks/{webhook_id}/deliveries`
+async fn fetch_webhook_deliveries(
+    Path((guild_id, webhook_id)): Path<(Snowflake<Guild>, Snowflake<Webhook>)>,
+    State(app): State<App>,
+    token: Token,
+    Query(query): Query<FetchDeliveriesQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, RESTError> {
+    app.guilds().fetch_as_owner(guild_id, token.data().user_id()).await?;
+
+    let webhook = fetch_guild_webhook(&app, guild_id, webhook_id).await?;
+    let deliveries = app
+        .ops()
+        .fetch_webhook_deliveries(webhook.id(), query.limit, query.before)
+        .await?;
+
+    Ok(Json(deliveries))
+}
@@ -0,0 +1,769 @@
+use std::collections::HashSet;
+
+use crate::models::{
+    audit_log::{AuditLogAction, AuditLogEntry},
+    bulk::{BulkError, BulkResult},
+    channel::{Channel, ChannelLike},
+    errors::{AppError, BuildError, ErrorCode},
+    gateway_event::{ChannelStickyUpdatePayload, GatewayEvent, GuildCreatePayload, RaidModeUpdatePayload},
+    guild::Guild,
+    guild_folder::GuildSettings,
+    limits::Limit,
+    message::Message,
+    raid_mode::MAX_RAID_MODE_DURATION,
+    requests::{BulkOperation, CreateChannel, UpdateChannel, UpdateGuildSettings, UpdateRaidMode},
+    snowflake::Snowflake,
+    state::ApplicationState,
+    user::User,
+};
+
+/// Guild operations that require permission checks or dispatch events.
+pub struct GuildService<'a> {
+    app: &'a ApplicationState,
+}
+
+impl<'a> GuildService<'a> {
+    /// Create a new guild service.
+    pub const fn new(app: &'a ApplicationState) -> Self {
+        Self { app }
+    }
+
+    /// Fetch a guild the user is a member of.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild to fetch.
+    /// * `user` - The ID of the user requesting the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Forbidden`] - If the user is not a member of the guild.
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    pub async fn fetch_as_member(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Guild, AppError> {
+        let guild_id = guild.into();
+        self.app.members().fetch_required(user, guild_id).await?;
+
+        self.app
+            .ops()
+            .fetch_guild(guild_id)
+            .await
+            .ok_or_else(|| AppError::NotFound("Guild does not exist or is not available.".into()))
+    }
+
+    /// Fetch a guild owned by the user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild to fetch.
+    /// * `user` - The ID of the user requesting the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    /// * [`AppError::Forbidden`] - If the user is not the owner of the guild.
+    pub async fn fetch_as_owner(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Guild, AppError> {
+        let guild = self
+            .app
+            .ops()
+            .fetch_guild(guild)
+            .await
+            .ok_or_else(|| AppError::NotFound("Guild does not exist or is not available.".into()))?;
+
+        if guild.owner_id() != user.into() {
+            return Err(AppError::Forbidden("You are not the owner of this guild.".into()));
+        }
+        Ok(guild)
+    }
+
+    /// Create a new channel in a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to create the channel in.
+    /// * `payload` - The channel to create.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::ChannelCreate`] - To all guild members
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::LimitExceeded`] - If the guild already has the maximum amount of channels.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn create_channel(&self, guild: &Guild, payload: CreateChannel) -> Result<Channel, AppError> {
+        let channel_count = self.app.ops().fetch_channel_count(guild.id()).await?;
+        self.app
+            .settings
+            .limits()
+            .check(Limit::MaxChannelsPerGuild, channel_count + 1)?;
+
+        let channel = Channel::from_payload(&self.app.config, payload, guild.id());
+
+        self.app.ops().create_channel(&channel).await?;
+
+        self.app.gateway.dispatch(GatewayEvent::ChannelCreate(channel.clone()));
+        Ok(channel)
+    }
+
+    /// Delete a channel of a guild owned by the user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to delete.
+    /// * `user` - The ID of the user deleting the channel.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::ChannelRemove`] - To all members who can view the channel
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist.
+    /// * [`AppError::Forbidden`] - If the user is not the owner of the channel's guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn delete_channel(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<(), AppError> {
+        let (channel, _) = self.fetch_owned_channel(channel, user).await?;
+
+        self.app.ops().delete_channel(&channel).await?;
+
+        self.app.gateway.dispatch(GatewayEvent::ChannelRemove(channel));
+        Ok(())
+    }
+
+    /// Update the settings of a channel of a guild owned by the user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to update.
+    /// * `user` - The ID of the user updating the channel.
+    /// * `payload` - The settings to change.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::ChannelUpdate`] - To all members who can view the channel
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist.
+    /// * [`AppError::Forbidden`] - If the user is not the owner of the channel's guild.
+    /// * [`AppError::Conflict`] - If the channel was updated since the version the payload is based on.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update_channel(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+        payload: UpdateChannel,
+    ) -> Result<Channel, AppError> {
+        let user_id: Snowflake<User> = user.into();
+        let (mut channel, guild) = self.fetch_owned_channel(channel, user_id).await?;
+
+        Self::apply_channel_update(&mut channel, &payload)?;
+        self.app.ops().update_channel(&mut channel).await?;
+
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            Some(user_id),
+            AuditLogAction::ChannelUpdate,
+            Some(channel.id().cast()),
+            None,
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        self.app.gateway.dispatch(GatewayEvent::ChannelUpdate(channel.clone()));
+        Ok(channel)
+    }
+
+    /// Apply the changes of an update request to a channel, without committing them.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Conflict`] - If the channel was updated since the version the payload is based on.
+    fn apply_channel_update(channel: &mut Channel, payload: &UpdateChannel) -> Result<(), AppError> {
+        if payload.version.is_some_and(|version| version != channel.version()) {
+            return Err(AppError::Conflict(
+                "The channel was updated since it was fetched".into(),
+            ));
+        }
+        if let Channel::GuildText(text) = channel {
+            if let Some(announcement) = payload.announcement {
+                *text.announcement_mut() = announcement;
+            }
+        }
+        Ok(())
+    }
+
+    /// Perform multiple operations on a guild owned by the user at once.
+    ///
+    /// All operations are validated before any of them is committed, and they are committed in a single transaction,
+    /// so either all of them succeed or none of them do.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild to perform the operations on.
+    /// * `user` - The ID of the user performing the operations.
+    /// * `operations` - The operations to perform, in order.
+    ///
+    /// ## Returns
+    ///
+    /// The result of every operation, in order.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::ChannelBulkUpdate`] - To all guild members, once for all operations
+    ///
+    /// ## Errors
+    ///
+    /// Errors caused by a specific operation include its index.
+    ///
+    /// * [`AppError::NotFound`] - If a channel to update does not exist in the guild.
+    /// * [`AppError::Build`] - If a channel is updated more than once.
+    /// * [`AppError::LimitExceeded`] - If the guild would have more than the maximum amount of channels.
+    /// * [`AppError::Conflict`] - If a channel was updated since the version its operation is based on.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn bulk(
+        &self,
+        guild: &Guild,
+        user: impl Into<Snowflake<User>>,
+        operations: Vec<BulkOperation>,
+    ) -> Result<Vec<BulkResult>, BulkError> {
+        let user_id: Snowflake<User> = user.into();
+        let mut channel_count = self.app.ops().fetch_channel_count(guild.id()).await?;
+        let mut updated = HashSet::new();
+        let mut results = Vec::with_capacity(operations.len());
+
+        for (index, operation) in operations.into_iter().enumerate() {
+            let result = match operation {
+                BulkOperation::CreateChannel { data } => {
+                    channel_count += 1;
+                    self.app
+                        .settings
+                        .limits()
+                        .check(Limit::MaxChannelsPerGuild, channel_count)
+                        .map_err(|e| BulkError::at(index, e))?;
+
+                    BulkResult::CreateChannel {
+                        channel: Channel::from_payload(&self.app.config, data, guild.id()),
+                    }
+                }
+                BulkOperation::UpdateChannel { channel_id, data } => {
+                    if !updated.insert(channel_id) {
+                        return Err(BulkError::at(
+                            index,
+                            BuildError::ValidationError("A channel may only be updated once per request".into()),
+                        ));
+                    }
+                    let mut channel = self
+                        .app
+                        .ops()
+                        .fetch_channel(channel_id)
+                        .await
+                        .filter(|channel| channel.guild_id() == Some(guild.id()))
+                        .ok_or_else(|| {
+                            BulkError::at(
+                                index,
+                                AppError::NotFound("Channel does not exist in this guild.".into()),
+                            )
+                        })?;
+
+                    Self::apply_channel_update(&mut channel, &data).map_err(|e| BulkError::at(index, e))?;
+                    BulkResult::UpdateChannel { channel }
+                }
+            };
+            results.push(result);
+        }
+
+        self.app.ops().commit_bulk(guild, &mut results).await?;
+
+        for result in &results {
+            if let BulkResult::UpdateChannel { channel } = result {
+                let entry = AuditLogEntry::new(
+                    &self.app.config,
+                    guild.id(),
+                    Some(user_id),
+                    AuditLogAction::ChannelUpdate,
+                    Some(channel.id().cast()),
+                    None,
+                );
+                self.app.ops().create_audit_log_entry(&entry).await?;
+            }
+        }
+        Ok(results)
+    }
+
+    /// Archive or unarchive a channel of a guild owned by the user.
+    ///
+    /// Archived channels are read-only and hidden from the channel list of their guild,
+    /// but their message history can still be read. Feeds of archived channels are paused.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to archive or unarchive.
+    /// * `user` - The ID of the user archiving the channel.
+    /// * `archived` - Whether the channel should be archived.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::ChannelUpdate`] - To all members who can view the channel, if the channel changed
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist.
+    /// * [`AppError::Forbidden`] - If the user is not the owner of the channel's guild.
+    /// * [`AppError::Conflict`] - If the channel was updated concurrently.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn set_channel_archived(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+        archived: bool,
+    ) -> Result<Channel, AppError> {
+        let user_id: Snowflake<User> = user.into();
+        let (mut channel, guild) = self.fetch_owned_channel(channel, user_id).await?;
+
+        if channel.is_archived() == archived {
+            return Ok(channel);
+        }
+        if let Channel::GuildText(text) = &mut channel {
+            *text.archived_mut() = archived;
+        }
+        self.app.ops().update_channel(&mut channel).await?;
+
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            Some(user_id),
+            if archived {
+                AuditLogAction::ChannelArchive
+            } else {
+                AuditLogAction::ChannelUnarchive
+            },
+            Some(channel.id().cast()),
+            None,
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        self.app.gateway.dispatch(GatewayEvent::ChannelUpdate(channel.clone()));
+        Ok(channel)
+    }
+
+    /// Set or clear the sticky message of a channel of a guild owned by the user.
+    /// Channels have at most one sticky message, setting a new one replaces the previous one.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to update.
+    /// * `user` - The ID of the user updating the channel.
+    /// * `message` - The ID of the message to keep visible at the top of the channel, or `None` to clear it.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::ChannelStickyUpdate`] - To all members who can view the channel, if the sticky message changed
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist, or the message does not exist in the channel.
+    /// * [`AppError::Forbidden`] - If the user is not the owner of the channel's guild.
+    /// * [`AppError::Conflict`] - If the channel was updated concurrently.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn set_sticky_message(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+        message: Option<Snowflake<Message>>,
+    ) -> Result<Channel, AppError> {
+        let user_id: Snowflake<User> = user.into();
+        let (mut channel, guild) = self.fetch_owned_channel(channel, user_id).await?;
+
+        if channel.sticky_message_id() == message {
+            return Ok(channel);
+        }
+        let message = match message {
+            Some(message_id) => Some(
+                self.app
+                    .ops()
+                    .fetch_message(message_id)
+                    .await?
+                    .filter(|message| message.channel_id() == channel.id())
+                    .ok_or_else(|| AppError::NotFound("Message does not exist in this channel.".into()))?,
+            ),
+            None => None,
+        };
+
+        if let Channel::GuildText(text) = &mut channel {
+            *text.sticky_message_id_mut() = message.as_ref().map(Message::id);
+        }
+        self.app.ops().update_channel(&mut channel).await?;
+
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            Some(user_id),
+            AuditLogAction::ChannelStickyUpdate,
+            Some(channel.id().cast()),
+            None,
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        self.app
+            .gateway
+            .dispatch(GatewayEvent::ChannelStickyUpdate(ChannelStickyUpdatePayload {
+                channel_id: channel.id(),
+                guild_id: guild.id(),
+                message,
+            }));
+        Ok(channel)
+    }
+
+    /// Fetch how the user arranges their guilds. Guilds the user is no longer a member of are left out.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to fetch the settings of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_settings(&self, user: impl Into<Snowflake<User>>) -> Result<GuildSettings, AppError> {
+        let user_id: Snowflake<User> = user.into();
+        let mut settings = self.app.ops().fetch_guild_settings(user_id).await?;
+        let guilds: HashSet<Snowflake<Guild>> =
+            self.app.ops().fetch_guild_ids_for(user_id).await?.into_iter().collect();
+
+        settings.retain_guilds(&guilds);
+        Ok(settings)
+    }
+
+    /// Update how the user arranges their guilds. Guilds the user is not a member of are left out.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to update the settings of.
+    /// * `payload` - The settings to change.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::GuildSettingsUpdate`] - To the user
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Build`] - If the settings are invalid.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update_settings(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        payload: UpdateGuildSettings,
+    ) -> Result<GuildSettings, AppError> {
+        let user_id: Snowflake<User> = user.into();
+
+        let Some(folders) = payload.folders else {
+            return self.fetch_settings(user_id).await;
+        };
+        let mut settings = GuildSettings::new(folders)?;
+        let guilds: HashSet<Snowflake<Guild>> =
+            self.app.ops().fetch_guild_ids_for(user_id).await?.into_iter().collect();
+        settings.retain_guilds(&guilds);
+
+        self.app.ops().update_guild_settings(user_id, &settings).await?;
+        self.app
+            .gateway
+            .send_to(user_id, GatewayEvent::GuildSettingsUpdate(settings.clone()));
+        Ok(settings)
+    }
+
+    /// Fetch a channel of a guild owned by the user, along with the guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to fetch.
+    /// * `user` - The ID of the user requesting the channel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel or its guild does not exist.
+    /// * [`AppError::Forbidden`] - If the user is not the owner of the channel's guild, or the channel does not belong to a guild.
+    pub async fn fetch_owned_channel(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<(Channel, Guild), AppError> {
+        let channel = self
+            .app
+            .ops()
+            .fetch_channel(channel)
+            .await
+            .ok_or_else(|| AppError::NotFound("Channel does not exist or is not available.".into()))?;
+
+        let Some(guild_id) = channel.guild_id() else {
+            return Err(AppError::Forbidden("This channel does not belong to a guild.".into()));
+        };
+
+        let guild = self.fetch_as_owner(guild_id, user).await?;
+        Ok((channel, guild))
+    }
+
+    /// Delete a guild owned by the user.
+    ///
+    /// The guild is hidden immediately, but only purged along with all associated objects
+    /// once the configured grace period has passed. Until then, the owner may restore it.
+    /// If the grace period is zero, the guild is purged immediately.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild to delete.
+    /// * `user` - The ID of the user deleting the guild.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::GuildRemove`] - To all guild members
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    /// * [`AppError::Forbidden`] - If the user is not the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn delete(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<(), AppError> {
+        let guild = self.fetch_as_owner(guild, user).await?;
+
+        if self.app.config.guild_deletion_grace_period().is_zero() {
+            self.app.ops().delete_guild(&guild).await?;
+        } else if !self.app.ops().soft_delete_guild(&guild).await? {
+            return Err(AppError::NotFound("Guild does not exist or is not available.".into()));
+        }
+
+        self.app.gateway.dispatch(GatewayEvent::GuildRemove(guild));
+        Ok(())
+    }
+
+    /// Restore a deleted guild owned by the user, if its grace period has not passed yet.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild to restore.
+    /// * `user` - The ID of the user restoring the guild.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::GuildCreate`] - To all guild members
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild was not deleted, or can no longer be restored.
+    /// * [`AppError::Forbidden`] - If the user is not the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn restore(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Guild, AppError> {
+        let deleted_after = self.restorable_since();
+        let not_found = || AppError::NotFound("Guild does not exist or can no longer be restored.".into());
+
+        let guild = self
+            .app
+            .ops()
+            .fetch_deleted_guild(guild, deleted_after)
+            .await?
+            .ok_or_else(not_found)?;
+
+        if guild.owner_id() != user.into() {
+            return Err(AppError::Forbidden("You are not the owner of this guild.".into()));
+        }
+
+        if !self.app.ops().restore_guild(&guild, deleted_after).await? {
+            return Err(not_found());
+        }
+
+        let payload = GuildCreatePayload::from_guild(self.app, guild.clone()).await?;
+
+        // Members who connected while the guild was deleted are not subscribed to it
+        for member in self.app.ops().fetch_members_for(&guild).await? {
+            self.app.gateway.add_member(&member, &guild, member.guest());
+        }
+        self.app.gateway.dispatch(GatewayEvent::GuildCreate(payload));
+        Ok(guild)
+    }
+
+    /// Permanently delete all guilds whose grace period has passed.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of guilds purged.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::S3`] - If the attachments of a guild could not be deleted.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn purge_deleted(&self) -> Result<usize, AppError> {
+        let guilds = self
+            .app
+            .ops()
+            .fetch_guilds_deleted_before(self.restorable_since())
+            .await?;
+
+        for guild in &guilds {
+            self.app.ops().delete_guild(*guild).await?;
+        }
+        Ok(guilds.len())
+    }
+
+    /// Enable or disable raid mode of a guild owned by the user.
+    ///
+    /// While raid mode is enabled, users need a pending invite and an account at least as old as the configured
+    /// minimum account age to join, and members other than the owner are limited by slow mode.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild.
+    /// * `user` - The ID of the user changing raid mode.
+    /// * `payload` - Whether to enable raid mode, and for how long.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::RaidModeUpdate`] - To all guild members
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Build`] - If the duration is not positive or longer than [`MAX_RAID_MODE_DURATION`].
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    /// * [`AppError::Forbidden`] - If the user is not the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update_raid_mode(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        payload: UpdateRaidMode,
+    ) -> Result<Guild, AppError> {
+        let user_id: Snowflake<User> = user.into();
+        let guild = self.fetch_as_owner(guild, user_id).await?;
+
+        if payload
+            .duration
+            .is_some_and(|duration| !(1..=MAX_RAID_MODE_DURATION).contains(&duration))
+        {
+            return Err(BuildError::InvalidField {
+                field: "duration",
+                code: ErrorCode::OutOfRange,
+                message: format!("Duration must be between 1 and {MAX_RAID_MODE_DURATION} seconds."),
+            }
+            .into());
+        }
+
+        // Disabling raid mode ends it now, so joins before this point no longer count towards a raid
+        let now = self.app.clock.timestamp();
+        let until = if payload.enabled {
+            now + payload.duration.unwrap_or_else(|| self.raid_mode_duration())
+        } else {
+            now
+        };
+        self.app.ops().update_raid_mode(guild.id(), until).await?;
+        self.record_raid_mode_update(guild.id(), Some(user_id), until, None)
+            .await?;
+
+        self.app
+            .ops()
+            .fetch_guild(guild.id())
+            .await
+            .ok_or_else(|| AppError::NotFound("Guild does not exist or is not available.".into()))
+    }
+
+    /// Enable raid mode of a guild if more members joined it recently than the configured threshold allows.
+    /// Called whenever a new member joins. Only joins since raid mode last ended are counted.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild a member joined.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::RaidModeUpdate`] - To all guild members, if raid mode was enabled
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn detect_raid(&self, guild: &Guild) -> Result<(), AppError> {
+        let threshold = self.app.config.raid_join_threshold();
+        let now = self.app.clock.now();
+        if threshold == 0 || guild.is_raid_mode_active(now) {
+            return Ok(());
+        }
+
+        let window = self.app.config.raid_join_window().as_secs();
+        let now = now.timestamp();
+        let since = (now - i64::try_from(window).unwrap_or(i64::MAX)).max(guild.raid_mode_until().unwrap_or(i64::MIN));
+        let joins = self.app.ops().fetch_recent_join_count(guild.id(), since).await?;
+        if joins < threshold as usize {
+            return Ok(());
+        }
+
+        let until = now + self.raid_mode_duration();
+        // Several processes may detect the same raid, only the first one to enable raid mode reports it
+        if !self
+            .app
+            .ops()
+            .enable_raid_mode_if_inactive(guild.id(), now, until)
+            .await?
+        {
+            return Ok(());
+        }
+
+        tracing::warn!(guild_id = %guild.id(), joins, "Enabled raid mode after a spike of joins");
+        let reason = format!("{joins} members joined within {window} seconds");
+        self.record_raid_mode_update(guild.id(), None, until, Some(reason))
+            .await
+    }
+
+    /// Record a change of raid mode in the audit log of the guild, and notify its members.
+    async fn record_raid_mode_update(
+        &self,
+        guild: Snowflake<Guild>,
+        user: Option<Snowflake<User>>,
+        until: i64,
+        reason: Option<String>,
+    ) -> Result<(), AppError> {
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild,
+            user,
+            AuditLogAction::RaidModeUpdate,
+            None,
+            reason,
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        self.app
+            .gateway
+            .dispatch(GatewayEvent::RaidModeUpdate(RaidModeUpdatePayload {
+                guild_id: guild,
+                raid_mode_until: until,
+            }));
+        Ok(())
+    }
+
+    /// How long raid mode stays enabled if no duration is given, in seconds.
+    fn raid_mode_duration(&self) -> i64 {
+        i64::try_from(self.app.config.raid_mode_duration().as_secs()).unwrap_or(MAX_RAID_MODE_DURATION)
+    }
+
+    /// UNIX timestamp, guilds deleted at or before it can no longer be restored.
+    fn restorable_since(&self) -> i64 {
+        let grace_period = i64::try_from(self.app.config.guild_deletion_grace_period().as_secs()).unwrap_or(i64::MAX);
+        self.app.clock.timestamp().saturating_sub(grace_period)
+    }
+}
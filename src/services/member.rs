@@ -0,0 +1,484 @@
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::models::{
+    errors::AppError,
+    gateway_event::{
+        DeletePayload, GatewayEvent, GuildCreatePayload, GuildMembersChunkPayload, RequestGuildMembersPayload,
+        WelcomeMessagePayload,
+    },
+    guest_link::{GuestAccess, GuestLink},
+    guild::Guild,
+    limits::Limit,
+    member::Member,
+    prefs::PrefFlags,
+    rate_limit::{RateLimitBucket, RateLimitKey},
+    snowflake::Snowflake,
+    state::ApplicationState,
+    user::{User, UserFlags},
+    webhook::WebhookEvent,
+};
+
+/// The maximum amount of members sent in a single `GUILD_MEMBERS_CHUNK` event.
+const MEMBER_CHUNK_SIZE: usize = 1000;
+/// How long to wait between two `GUILD_MEMBERS_CHUNK` events, so large guilds do not starve other queries.
+const MEMBER_CHUNK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Guild membership operations that require permission checks or dispatch events.
+pub struct MemberService<'a> {
+    app: &'a ApplicationState,
+}
+
+impl<'a> MemberService<'a> {
+    /// Create a new member service.
+    pub const fn new(app: &'a ApplicationState) -> Self {
+        Self { app }
+    }
+
+    /// Fetch the member of a user in a guild, failing if the user is not a member.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user.
+    /// * `guild` - The ID of the guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Forbidden`] - If the user is not a member of the guild, or their guest membership expired.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_required(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        guild: impl Into<Snowflake<Guild>>,
+    ) -> Result<Member, AppError> {
+        // Expired guests are removed in the background, until then they are treated as if they were gone
+        let now = self.app.clock.timestamp();
+        self.app
+            .ops()
+            .fetch_member(user, guild)
+            .await?
+            .filter(|member| member.guest().is_none_or(|guest| !guest.is_expired(now)))
+            .ok_or_else(|| AppError::Forbidden("Not permitted to access resource.".into()))
+    }
+
+    /// Send the members of a guild to a user in chunks, at a bounded rate.
+    /// If the user is not a member of the guild, a single empty chunk is sent.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user who requested the members.
+    /// * `request` - The guild and the members to send.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::GuildMembersChunk`] - To the user, at least once
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    /// * [`AppError::Build`] - If a member could not be built.
+    pub async fn send_chunks(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        request: RequestGuildMembersPayload,
+    ) -> Result<(), AppError> {
+        let user_id = user.into();
+        let guild_id = request.guild_id;
+        let query = request.query.as_deref().filter(|q| !q.is_empty());
+
+        let total = if self.app.ops().fetch_member(user_id, guild_id).await?.is_some() {
+            let matching = self.app.ops().fetch_matching_member_count(guild_id, query).await?;
+            match request.limit {
+                Some(limit) if limit > 0 => matching.min(limit),
+                _ => matching,
+            }
+        } else {
+            0
+        };
+
+        let chunk_count = total.div_ceil(MEMBER_CHUNK_SIZE).max(1);
+        let mut remaining = total;
+        let mut after = Snowflake::new(0);
+
+        for chunk_index in 0..chunk_count {
+            if chunk_index > 0 {
+                tokio::time::sleep(MEMBER_CHUNK_INTERVAL).await;
+            }
+
+            let members = if remaining == 0 {
+                Vec::new()
+            } else {
+                let limit = remaining.min(MEMBER_CHUNK_SIZE);
+                self.app
+                    .ops()
+                    .fetch_members_page(guild_id, query, after, limit.try_into().unwrap_or(i64::MAX))
+                    .await?
+            };
+
+            remaining = remaining.saturating_sub(members.len());
+            if let Some(last) = members.last() {
+                after = last.user().id();
+            }
+
+            self.app.gateway.send_to(
+                user_id,
+                GatewayEvent::GuildMembersChunk(GuildMembersChunkPayload {
+                    guild_id,
+                    members: members
+                        .into_iter()
+                        .map(|m| m.include_presence(&self.app.gateway))
+                        .collect(),
+                    chunk_index,
+                    chunk_count,
+                    nonce: request.nonce.clone(),
+                }),
+            );
+        }
+        Ok(())
+    }
+
+    /// Ensure a user can become a member of one more guild, by joining or creating it.
+    /// Users with [`UserFlags::EXEMPT_FROM_LIMITS`] may be a member of any amount of guilds.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::LimitExceeded`] - If the user is already a member of the maximum amount of guilds.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn check_guild_limit(&self, user: impl Into<Snowflake<User>>) -> Result<(), AppError> {
+        let user_id = user.into();
+
+        if self
+            .app
+            .ops()
+            .fetch_user_flags(user_id)
+            .await?
+            .contains(UserFlags::EXEMPT_FROM_LIMITS)
+        {
+            return Ok(());
+        }
+
+        let guild_count = self.app.ops().fetch_guild_count_for(user_id).await?;
+        self.app
+            .settings
+            .limits()
+            .check(Limit::MaxGuildsPerUser, guild_count + 1)?;
+        Ok(())
+    }
+
+    /// Add a user to a guild. Users who are not members yet must meet the guild's join requirements,
+    /// which are stricter while the guild is in raid mode. A spike of new members enables raid mode.
+    /// Guests joining this way become full members.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild to join.
+    /// * `user` - The ID of the user joining the guild.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::GuildCreate`] - For the user who joined the guild
+    /// * [`GatewayEvent::WelcomeMessage`] - For the user who joined the guild, if the guild has a welcome message
+    /// * [`GatewayEvent::MemberCreate`] - For all members already in the guild
+    /// * [`GatewayEvent::MemberUpdate`] - For all guild members instead, if the user was a guest
+    /// * [`WebhookEvent::MemberJoin`] - To all webhooks of the guild subscribed to it
+    /// * [`GatewayEvent::RaidModeUpdate`] - To all guild members, if the join enabled raid mode
+    /// * [`GatewayEvent::EvasionFlagCreate`] - For the owner of the guild, if the user was flagged for ban evasion
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    /// * [`AppError::Forbidden`] - If the user is banned from the guild.
+    /// * [`AppError::JoinRequirement`] - If the user does not meet one of the guild's join requirements.
+    /// * [`AppError::LimitExceeded`] - If the user is in too many guilds, or the guild has too many members.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn join(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Member, AppError> {
+        self.add(guild.into(), user.into(), None).await
+    }
+
+    /// Add a user to a guild as a guest through a guest link. Guests can only access the channels of the link,
+    /// and are removed once their membership expires. Joining through a link again renews the membership.
+    ///
+    /// The link counts as an invite, except while the guild is in raid mode. Other join requirements still apply.
+    ///
+    /// ## Arguments
+    ///
+    /// * `link` - The guest link the user joins through.
+    /// * `user` - The ID of the user joining the guild.
+    ///
+    /// ## Dispatches
+    ///
+    /// See [`MemberService::join`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Conflict`] - If the user is already a full member of the guild.
+    /// * See [`MemberService::join`] for the other errors.
+    pub async fn join_as_guest(&self, link: &GuestLink, user: impl Into<Snowflake<User>>) -> Result<Member, AppError> {
+        let guest = link.grant(self.app.clock.timestamp());
+        self.add(link.guild_id(), user.into(), Some(guest)).await
+    }
+
+    /// Add a user to a guild, either as a full member or as a guest.
+    async fn add(
+        &self,
+        guild: Snowflake<Guild>,
+        user_id: Snowflake<User>,
+        guest: Option<GuestAccess>,
+    ) -> Result<Member, AppError> {
+        let guild = self
+            .app
+            .ops()
+            .fetch_guild(guild)
+            .await
+            .ok_or_else(|| AppError::NotFound("Guild does not exist or is not available.".into()))?;
+        let guild_id = guild.id();
+
+        let existing = self.app.ops().fetch_member(user_id, guild_id).await?;
+        let is_new = existing.is_none();
+        let was_guest = existing.as_ref().is_some_and(Member::is_guest);
+
+        if guest.is_some() && !is_new && !was_guest {
+            return Err(AppError::Conflict("You are already a member of this guild.".into()));
+        }
+
+        // Guests becoming full members are held to the same requirements as new members
+        if is_new || (was_guest && guest.is_none()) {
+            if self.app.ops().is_banned(guild_id, user_id).await? {
+                return Err(AppError::Forbidden("You are banned from this guild.".into()));
+            }
+
+            let now = self.app.clock.now();
+            let raid_mode = guild.is_raid_mode_active(now);
+            let requirements = if raid_mode {
+                guild
+                    .join_requirements()
+                    .during_raid(self.app.config.raid_min_account_age())
+            } else {
+                *guild.join_requirements()
+            };
+            let invited = requirements.invite_only()
+                && ((guest.is_some() && !raid_mode) || self.app.ops().has_guild_invite(guild_id, user_id).await?);
+            requirements.check(user_id, invited, now)?;
+        }
+
+        if is_new {
+            self.check_guild_limit(user_id).await?;
+            let member_count = self.app.ops().fetch_member_count(guild_id).await?;
+            self.app
+                .settings
+                .limits()
+                .check(Limit::MaxMembersPerGuild, member_count + 1)?;
+        }
+
+        let member = if was_guest {
+            self.app
+                .ops()
+                .update_guest_access(guild_id, user_id, guest.as_ref())
+                .await?
+        } else {
+            self.app.ops().create_member(&guild, user_id, guest.as_ref()).await?
+        };
+
+        // Only members joining for the first time are welcomed
+        let welcome_message = if is_new {
+            guild
+                .welcome_message()
+                .render(member.user(), &guild, member.joined_at())
+        } else {
+            None
+        };
+
+        // Create payload seperately as it needs read access to gateway
+        let mut gc_payload = GuildCreatePayload::from_guild(self.app, guild.clone()).await?;
+        if let Some(guest) = member.guest() {
+            gc_payload = gc_payload.for_guest(guest);
+        }
+
+        // Send GUILD_CREATE to the user who joined
+        self.app.gateway.send_to(&member, GatewayEvent::GuildCreate(gc_payload));
+
+        if let Some(content) = welcome_message {
+            self.send_welcome_message(guild_id, &member, content).await?;
+        }
+
+        // Add the member to the gateway's cache
+        self.app.gateway.add_member(&member, guild_id, member.guest());
+
+        // Guests renewing their membership or becoming full members are already known to the guild
+        if was_guest {
+            self.app.gateway.dispatch(GatewayEvent::MemberUpdate(member.clone()));
+            return Ok(member);
+        }
+
+        // Dispatch the member create event to all guild members
+        self.app.gateway.dispatch(GatewayEvent::MemberCreate(member.clone()));
+
+        self.app
+            .webhooks
+            .enqueue(guild_id, WebhookEvent::MemberJoin, &member)
+            .await?;
+
+        if is_new {
+            self.app.guilds().detect_raid(&guild).await?;
+            self.app.strikes().flag_evasion(&guild, user_id).await?;
+        }
+
+        Ok(member)
+    }
+
+    /// Send the welcome message of a guild to a new member.
+    ///
+    /// Nothing is sent if the member muted welcome messages,
+    /// or the guild already sent too many welcome messages recently, for example during a raid.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild the member joined.
+    /// * `member` - The new member.
+    /// * `content` - The welcome message of the guild, filled in for the member.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::WelcomeMessage`] - For the new member
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    async fn send_welcome_message(
+        &self,
+        guild: Snowflake<Guild>,
+        member: &Member,
+        content: String,
+    ) -> Result<(), AppError> {
+        let flags = self.app.ops().fetch_pref_flags(member).await?;
+        if flags.contains(PrefFlags::MUTE_WELCOME_MESSAGES) {
+            return Ok(());
+        }
+
+        let rate_limiter = &self.app.rate_limiter;
+        if rate_limiter.is_enabled()
+            && rate_limiter
+                .hit(RateLimitBucket::WelcomeMessages, RateLimitKey::Guild(guild))
+                .is_exceeded()
+        {
+            tracing::debug!(guild_id = %guild, "Skipping welcome message, too many members joined recently");
+            return Ok(());
+        }
+
+        self.app.gateway.send_to(
+            member,
+            GatewayEvent::WelcomeMessage(WelcomeMessagePayload {
+                guild_id: guild,
+                content,
+            }),
+        );
+        Ok(())
+    }
+
+    /// Remove a user from a guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild to leave.
+    /// * `user` - The ID of the user leaving the guild.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::GuildRemove`] - For the user who left the guild
+    /// * [`GatewayEvent::MemberRemove`] - For all members still in the guild
+    /// * [`WebhookEvent::MemberLeave`] - To all webhooks of the guild subscribed to it
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist, or the user is not a member of it.
+    /// * [`AppError::Forbidden`] - If the user is the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn leave(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<(), AppError> {
+        let user_id = user.into();
+        let guild = self
+            .app
+            .ops()
+            .fetch_guild(guild)
+            .await
+            .ok_or_else(|| AppError::NotFound("Guild does not exist or is not available.".into()))?;
+
+        self.app
+            .ops()
+            .fetch_member(user_id, guild.id())
+            .await?
+            .ok_or_else(|| AppError::NotFound("Member does not exist or is not available.".into()))?;
+
+        if user_id == guild.owner_id() {
+            return Err(AppError::Forbidden("Owner cannot leave owned guild.".into()));
+        }
+
+        self.app.ops().delete_member(&guild, user_id).await?;
+        self.announce_removal(guild, user_id).await
+    }
+
+    /// Remove all guests whose membership expired from their guilds.
+    ///
+    /// ## Returns
+    ///
+    /// The amount of guests removed.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::GuildRemove`] - For every guest removed from a guild
+    /// * [`GatewayEvent::MemberRemove`] - For all members still in the guild
+    /// * [`WebhookEvent::MemberLeave`] - To all webhooks of the guild subscribed to it
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn expire_guests(&self) -> Result<usize, AppError> {
+        let expired = self.app.ops().delete_expired_guests().await?;
+
+        for (user_id, guild_id) in &expired {
+            if let Some(guild) = self.app.ops().fetch_guild(*guild_id).await {
+                self.announce_removal(guild, *user_id).await?;
+            }
+        }
+        Ok(expired.len())
+    }
+
+    /// Notify the user, the other members and the guild's webhooks of a user that was removed from a guild.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the webhook deliveries could not be queued.
+    async fn announce_removal(&self, guild: Guild, user_id: Snowflake<User>) -> Result<(), AppError> {
+        let guild_id = guild.id();
+
+        // Remove the member from the gateway's sessions
+        self.app.gateway.remove_member(user_id, guild_id);
+
+        // Send GUILD_REMOVE to the user who left
+        self.app.gateway.send_to(user_id, GatewayEvent::GuildRemove(guild));
+
+        // Dispatch the member remove event
+        self.app
+            .gateway
+            .dispatch(GatewayEvent::MemberRemove(DeletePayload::new(user_id, Some(guild_id))));
+
+        self.app
+            .webhooks
+            .enqueue(guild_id, WebhookEvent::MemberLeave, &json!({ "user_id": user_id }))
+            .await?;
+
+        Ok(())
+    }
+}
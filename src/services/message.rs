@@ -0,0 +1,508 @@
+use crate::models::{
+    attachment::{AttachmentLike, ScanStatus},
+    automod::{AutoModAction, AutoModRule},
+    channel::{Channel, ChannelLike},
+    content::{self, MentionTargets},
+    errors::{AppError, BuildError, ErrorCode},
+    jobs,
+    media_metadata::MediaMetadata,
+    member::UserLike,
+    message::{Message, MessageFields, PartialMessage},
+    message_change::{
+        MessageChange, MessageChanges, DEFAULT_CHANGES_LIMIT, MAX_CHANGES_LIMIT, MESSAGE_CHANGE_RETENTION,
+    },
+    raid_mode::SlowModeError,
+    search,
+    snowflake::Snowflake,
+    state::ApplicationState,
+    translation::{self, Translation},
+    user::User,
+};
+
+/// Message operations that require permission checks or dispatch events.
+pub struct MessageService<'a> {
+    app: &'a ApplicationState,
+}
+
+impl<'a> MessageService<'a> {
+    /// Create a new message service.
+    pub const fn new(app: &'a ApplicationState) -> Self {
+        Self { app }
+    }
+
+    /// Fetch a channel the user can view, along with the user as the author of messages in it.
+    /// In guild channels, the user is represented by their member in the channel's guild.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to fetch.
+    /// * `user` - The ID of the user requesting the channel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist, or is the saved messages channel of another user.
+    /// * [`AppError::Forbidden`] - If the user is not a member of the channel's guild,
+    ///   or is a guest and their guest link does not grant access to the channel.
+    pub async fn fetch_channel(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<(Channel, UserLike), AppError> {
+        let user_id: Snowflake<User> = user.into();
+        let channel = self
+            .app
+            .ops()
+            .fetch_channel(channel)
+            .await
+            .ok_or_else(|| AppError::NotFound("Channel does not exist or is not available.".into()))?;
+
+        let Some(guild_id) = channel.guild_id() else {
+            // Saved messages channels are only visible to their owner
+            if channel.owner_id() != Some(user_id) {
+                return Err(AppError::NotFound("Channel does not exist or is not available.".into()));
+            }
+            let user = self
+                .app
+                .ops()
+                .fetch_user(user_id)
+                .await
+                .ok_or_else(|| AppError::NotFound("User does not exist.".into()))?;
+            return Ok((channel, UserLike::User(user)));
+        };
+
+        let member = self.app.members().fetch_required(user_id, guild_id).await?;
+        if !member.can_access(channel.id()) {
+            return Err(AppError::Forbidden(
+                "Guests can only access the channels they were invited to.".into(),
+            ));
+        }
+        Ok((channel, UserLike::Member(member)))
+    }
+
+    /// Fetch a channel the user can send messages in, along with the user as the author of messages in it.
+    /// Nobody may send messages in archived channels, only the guild owner may send messages in announcement channels,
+    /// and other members are limited by slow mode while the guild is in raid mode.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to fetch.
+    /// * `user` - The ID of the user sending messages.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist, or is the saved messages channel of another user.
+    /// * [`AppError::Forbidden`] - If the channel is archived, the user is not a member of the channel's guild, is timed out in it,
+    ///   or the channel is an announcement channel and the user is not the guild owner.
+    /// * [`AppError::SlowMode`] - If the guild is in raid mode and the user sent a message too recently.
+    pub async fn fetch_sendable_channel(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<(Channel, UserLike), AppError> {
+        let (channel, author) = self.fetch_channel(channel, user).await?;
+
+        if channel.is_archived() {
+            return Err(AppError::Forbidden("This channel is archived.".into()));
+        }
+
+        if let UserLike::Member(member) = &author {
+            let now = self.app.clock.now();
+            if member.is_timed_out(now) {
+                return Err(AppError::Forbidden("You are timed out in this guild.".into()));
+            }
+
+            let guild = self
+                .app
+                .ops()
+                .fetch_guild(member.guild_id())
+                .await
+                .ok_or_else(|| AppError::NotFound("Guild does not exist or is not available.".into()))?;
+            let is_owner = guild.owner_id() == member.user().id();
+
+            if channel.is_announcement() && !is_owner {
+                return Err(AppError::Forbidden(
+                    "Only the guild owner can send messages in announcement channels.".into(),
+                ));
+            }
+
+            if guild.is_raid_mode_active(now) && !is_owner {
+                let interval = self.app.config.raid_slow_mode();
+                let after = Snowflake::from_timestamp(now.timestamp_millis() - interval.as_millis() as i64);
+                let last_message = self
+                    .app
+                    .ops()
+                    .fetch_last_message_id_in_guild(member.user().id(), guild.id(), after)
+                    .await?;
+                SlowModeError::check(last_message, interval, now)?;
+            }
+        }
+        Ok((channel, author))
+    }
+
+    /// Process the content of a new message and evaluate it against the guild's automod rules, then commit it.
+    /// Emojis in the message are counted towards the guild's emoji usage statistics in the background.
+    ///
+    /// HTML is stripped from the content, emoji shortcodes are replaced with unicode emojis, and mentions
+    /// of the guild's members and channels are rewritten into `<@id>` and `<#id>`. Channels of other guilds the author
+    /// is a member of may be linked to by ID. The mentioned users and channels are stored with the message.
+    /// Messages in channels outside of guilds, such as saved messages, are not checked by automod and mention nobody.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The channel the message was sent in.
+    /// * `message` - The message to send.
+    ///
+    /// ## Returns
+    ///
+    /// The sent message. If automod deletes the message, it is returned but never committed.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::MessageCreate`] - To all members who can view the channel
+    /// * [`GatewayEvent::MemberUpdate`] - If the author was timed out by automod
+    /// * [`GatewayEvent::StrikeCreate`] - If the author was issued a strike by automod, see [`StrikeService::issue`]
+    /// * [`GatewayEvent::MessageUpdate`] - Once the attachments are scanned for malware,
+    ///   once the metadata of image, video and audio attachments is extracted,
+    ///   and once linked media is re-hosted as attachments if enabled
+    ///
+    /// Mentioned users who are not connected to the gateway are sent a push notification instead.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Build`] - If the message has no content left after processing, and no attachments.
+    /// * [`AppError::Forbidden`] - If the message was blocked by automod.
+    /// * [`AppError::Database`] - If the database query fails.
+    ///
+    /// [`GatewayEvent::MessageCreate`]: crate::models::gateway_event::GatewayEvent::MessageCreate
+    /// [`GatewayEvent::MemberUpdate`]: crate::models::gateway_event::GatewayEvent::MemberUpdate
+    /// [`GatewayEvent::StrikeCreate`]: crate::models::gateway_event::GatewayEvent::StrikeCreate
+    /// [`StrikeService::issue`]: crate::services::StrikeService::issue
+    /// [`GatewayEvent::MessageUpdate`]: crate::models::gateway_event::GatewayEvent::MessageUpdate
+    pub async fn create(&self, channel: &Channel, mut message: Message) -> Result<Message, AppError> {
+        if let Some(content) = message.content() {
+            let sanitized = content::normalize_emojis(&content::sanitize(content));
+            let mentions = content::find_mentions(&sanitized);
+            let targets = match channel.guild_id() {
+                Some(guild_id) => {
+                    let author = message.author().map(UserLike::id);
+                    self.app
+                        .ops()
+                        .fetch_mention_targets(guild_id, author, &mentions)
+                        .await?
+                }
+                None => MentionTargets::new(),
+            };
+            let processed = content::canonicalize(&sanitized, &targets);
+            message.set_processed_content(processed)?;
+        }
+
+        let triggered = match channel.guild_id() {
+            Some(guild_id) => self.app.ops().run_automod(guild_id, &message).await?,
+            None => None,
+        };
+
+        if let (Some(rule), Some(UserLike::Member(member))) = (&triggered, message.author()) {
+            if rule.action() == AutoModAction::Timeout {
+                let mut member = member.clone();
+                let now = self.app.clock.now();
+                member.set_timeout_until(rule.timeout_duration().map(|d| now.timestamp() + d), now);
+                self.app.ops().update_member(&member).await?;
+            }
+            if rule.issue_strike() {
+                self.app
+                    .strikes()
+                    .issue_for_rule(member.guild_id(), member.user().id(), rule)
+                    .await?;
+            }
+        }
+
+        match triggered.as_ref().map(AutoModRule::action) {
+            Some(AutoModAction::Timeout | AutoModAction::Block) => {
+                return Err(AppError::Forbidden("Message was blocked by automod.".into()));
+            }
+            // Pretend that the message was sent, but do not commit or dispatch it
+            Some(AutoModAction::Delete) => return Ok(message),
+            Some(AutoModAction::Flag) | None => {}
+        }
+
+        self.app.ops().update_message(&message).await?;
+
+        if let (Some(guild_id), Some(content)) = (channel.guild_id(), message.content()) {
+            let content = content.clone();
+            self.app.jobs.spawn_with("record_emoji_usage", move |app| {
+                jobs::record_emoji_usage(app, guild_id, content)
+            });
+        }
+
+        if !message.mentions().is_empty() {
+            let (message, channel) = (message.clone(), channel.clone());
+            self.app.jobs.spawn_with("notify_mentions", move |app| async move {
+                app.push.notify_mentions(&message, &channel).await
+            });
+        }
+
+        let links = match message.content() {
+            Some(content) if self.app.settings.media_rehost_enabled() => content::find_media_links(content),
+            _ => Vec::new(),
+        };
+
+        // Re-hosting the media scans all attachments and extracts their metadata once it is done
+        if !links.is_empty() {
+            let message = message.clone();
+            self.app
+                .jobs
+                .spawn_with("rehost_media", move |app| jobs::rehost_media(app, message, links));
+        } else if message
+            .attachments()
+            .iter()
+            .any(|a| a.scan_status() == ScanStatus::Pending)
+        {
+            let message = message.clone();
+            self.app
+                .jobs
+                .spawn_with("scan_attachments", move |app| jobs::scan_attachments(app, message));
+        } else if message
+            .attachments()
+            .iter()
+            .any(|a| MediaMetadata::is_supported(&a.mime()))
+        {
+            let message = message.clone();
+            self.app.jobs.spawn_with("extract_attachment_metadata", move |app| {
+                jobs::extract_attachment_metadata(app, message)
+            });
+        }
+
+        Ok(message)
+    }
+
+    /// Fetch the messages of a channel the user can view.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to fetch messages from.
+    /// * `user` - The ID of the user requesting the messages.
+    /// * `limit` - The maximum number of messages to fetch. Defaults to 50, capped at 100.
+    /// * `before` - Fetch messages before this ID.
+    /// * `after` - Fetch messages after this ID.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist, or is the saved messages channel of another user.
+    /// * [`AppError::Forbidden`] - If the user is not a member of the channel's guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_history(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+        limit: Option<u32>,
+        before: Option<Snowflake<Message>>,
+        after: Option<Snowflake<Message>>,
+    ) -> Result<Vec<Message>, AppError> {
+        let (channel, _) = self.fetch_channel(channel, user).await?;
+
+        self.app
+            .ops()
+            .fetch_messages_from(channel.id(), limit, before, after)
+            .await
+    }
+
+    /// Fetch the messages of a channel the user can view, with only some of their optional fields.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to fetch messages from.
+    /// * `user` - The ID of the user requesting the messages.
+    /// * `fields` - The optional fields to fetch.
+    /// * `limit` - The maximum number of messages to fetch. Defaults to 50, capped at 100.
+    /// * `before` - Fetch messages before this ID.
+    /// * `after` - Fetch messages after this ID.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist, or is the saved messages channel of another user.
+    /// * [`AppError::Forbidden`] - If the user is not a member of the channel's guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_partial_history(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+        fields: MessageFields,
+        limit: Option<u32>,
+        before: Option<Snowflake<Message>>,
+        after: Option<Snowflake<Message>>,
+    ) -> Result<Vec<PartialMessage>, AppError> {
+        let (channel, _) = self.fetch_channel(channel, user).await?;
+
+        self.app
+            .ops()
+            .fetch_partial_messages_from(channel.id(), fields, limit, before, after)
+            .await
+    }
+
+    /// Fetch the changes to the messages of a channel the user can view since the given ID,
+    /// so clients can catch up on a channel without fetching its history again.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to fetch changes from.
+    /// * `user` - The ID of the user requesting the changes.
+    /// * `since` - Only return changes after this ID. This is the cursor of the previous page, or any message ID.
+    /// * `limit` - The maximum number of changes to fetch. Defaults to 100, capped at 1000.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist, or is the saved messages channel of another user.
+    /// * [`AppError::Forbidden`] - If the user is not a member of the channel's guild.
+    /// * [`AppError::Build`] - If `since` is older than the changes are kept for.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_changes(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+        since: Snowflake<MessageChange>,
+        limit: Option<u32>,
+    ) -> Result<MessageChanges, AppError> {
+        let (channel, _) = self.fetch_channel(channel, user).await?;
+
+        if since.timestamp() < (self.app.clock.timestamp() - MESSAGE_CHANGE_RETENTION) * 1000 {
+            return Err(BuildError::InvalidField {
+                field: "since",
+                code: ErrorCode::OutOfRange,
+                message: "Changes are only kept for 7 days, fetch the messages of the channel instead.".into(),
+            }
+            .into());
+        }
+
+        let limit = limit.unwrap_or(DEFAULT_CHANGES_LIMIT).clamp(1, MAX_CHANGES_LIMIT);
+        let changes = self.app.ops().fetch_message_changes(channel.id(), since, limit).await?;
+
+        Ok(MessageChanges::new(changes, since))
+    }
+
+    /// Fetch the sticky message of a channel the user can view.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to fetch the sticky message of.
+    /// * `user` - The ID of the user requesting the message.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist or has no sticky message.
+    /// * [`AppError::Forbidden`] - If the user is not a member of the channel's guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_sticky(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Message, AppError> {
+        let (channel, _) = self.fetch_channel(channel, user).await?;
+        let not_found = || AppError::NotFound("The channel has no sticky message.".into());
+
+        let message_id = channel.sticky_message_id().ok_or_else(not_found)?;
+        self.app.ops().fetch_message(message_id).await?.ok_or_else(not_found)
+    }
+
+    /// Search the messages of a channel the user can view, newest messages first.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel to search in.
+    /// * `user` - The ID of the user searching.
+    /// * `query` - The search query.
+    /// * `limit` - The maximum number of messages to return. Defaults to 25, capped at 100.
+    /// * `before` - Only return messages before this ID.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel does not exist, or is the saved messages channel of another user.
+    /// * [`AppError::Forbidden`] - If the user is not a member of the channel's guild.
+    /// * [`AppError::Build`] - If the query is blank or too long.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn search(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        user: impl Into<Snowflake<User>>,
+        query: &str,
+        limit: Option<u32>,
+        before: Option<Snowflake<Message>>,
+    ) -> Result<Vec<Message>, AppError> {
+        search::validate_query(query)?;
+        let (channel, _) = self.fetch_channel(channel, user).await?;
+
+        let ids = self.app.search.search(channel.id(), query, limit, before).await?;
+        self.app.ops().fetch_messages(&ids).await
+    }
+
+    /// Translate the content of a message in a channel the user can view.
+    /// Translations are cached, so each message is only translated once per language.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel the message is in.
+    /// * `message` - The ID of the message to translate.
+    /// * `user` - The ID of the user requesting the translation.
+    /// * `language` - The language code of the language to translate into.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If translations are disabled, or the channel or message does not exist.
+    /// * [`AppError::Forbidden`] - If the user is not a member of the channel's guild.
+    /// * [`AppError::Build`] - If the language is invalid or unsupported, or the message has no content.
+    /// * [`AppError::Http`] - If the translation service could not be reached.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn translate(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        message: impl Into<Snowflake<Message>>,
+        user: impl Into<Snowflake<User>>,
+        language: &str,
+    ) -> Result<Translation, AppError> {
+        let Some(backend) = self.app.translator.backend() else {
+            return Err(AppError::NotFound(
+                "Translations are not enabled on this server.".into(),
+            ));
+        };
+        translation::validate_language(language)?;
+        let (channel, _) = self.fetch_channel(channel, user).await?;
+
+        let message = self
+            .app
+            .ops()
+            .fetch_message(message)
+            .await?
+            .filter(|message| message.channel_id() == channel.id())
+            .ok_or_else(|| AppError::NotFound("Message does not exist or is not available.".into()))?;
+
+        let Some(content) = message.content().filter(|content| !content.trim().is_empty()) else {
+            return Err(BuildError::ValidationError("Message has no content to translate.".into()).into());
+        };
+
+        let source_hash = translation::source_hash(content);
+        if let Some(cached) = self
+            .app
+            .ops()
+            .fetch_translation(message.id(), language, &source_hash)
+            .await?
+        {
+            return Ok(cached);
+        }
+
+        tracing::debug!(
+            backend = backend.name(),
+            language,
+            "Translating message {}",
+            message.id()
+        );
+        let translated = backend.translate(content, language).await?;
+        let translation = Translation {
+            message_id: message.id(),
+            language: language.to_string(),
+            content: translated.content,
+            source_language: translated.source_language,
+        };
+        self.app.ops().update_translation(&translation, &source_hash).await?;
+
+        Ok(translation)
+    }
+}
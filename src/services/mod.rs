@@ -0,0 +1,20 @@
+//! Domain services shared by the REST API and the gateway.
+//!
+//! Services own the permission checks and the order in which changes are committed and dispatched,
+//! so that handlers only need to translate between their transport and the service calls.
+
+pub mod guild;
+pub mod member;
+pub mod message;
+pub mod onboarding;
+pub mod presence;
+pub mod report;
+pub mod strike;
+
+pub use guild::GuildService;
+pub use member::MemberService;
+pub use message::MessageService;
+pub use onboarding::OnboardingService;
+pub use presence::PresenceService;
+pub use report::ReportService;
+pub use strike::StrikeService;
@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+
+use crate::models::{
+    audit_log::{AuditLogAction, AuditLogEntry},
+    channel::{Channel, ChannelLike},
+    errors::{AppError, BuildError, ErrorCode},
+    guild::Guild,
+    onboarding::{MemberOnboarding, Onboarding, OnboardingResponse},
+    snowflake::Snowflake,
+    state::ApplicationState,
+    user::User,
+};
+
+/// Guild onboarding operations, set up by guild owners and completed by new members.
+pub struct OnboardingService<'a> {
+    app: &'a ApplicationState,
+}
+
+impl<'a> OnboardingService<'a> {
+    /// Create a new onboarding service.
+    pub const fn new(app: &'a ApplicationState) -> Self {
+        Self { app }
+    }
+
+    /// Fetch the onboarding of a guild the user is a member of.
+    /// Channels that were deleted or archived since the onboarding was set up are left out.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Forbidden`] - If the user is not a member of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Onboarding, AppError> {
+        let guild_id = guild.into();
+        self.app.members().fetch_required(user, guild_id).await?;
+
+        let mut onboarding = self.app.ops().fetch_onboarding(guild_id).await?;
+        onboarding.retain_channels(&self.channel_ids(guild_id).await?);
+        Ok(onboarding)
+    }
+
+    /// Replace the onboarding of a guild owned by the moderator.
+    /// Members who already completed the onboarding keep their answers and channels.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    /// * [`AppError::Forbidden`] - If the moderator is not the owner of the guild.
+    /// * [`AppError::Build`] - If the onboarding is invalid, or references a channel that is not in the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        moderator: impl Into<Snowflake<User>>,
+        mut onboarding: Onboarding,
+    ) -> Result<Onboarding, AppError> {
+        let moderator_id = moderator.into();
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator_id).await?;
+
+        onboarding.validate()?;
+        let channels = self.channel_ids(guild.id()).await?;
+        if let Some(id) = onboarding.channel_ids().find(|id| !channels.contains(id)) {
+            return Err(BuildError::InvalidField {
+                field: "channel_ids",
+                code: ErrorCode::ValidationFailed,
+                message: format!("Channel {id} is not a channel of this guild."),
+            }
+            .into());
+        }
+        self.app.ops().update_onboarding(guild.id(), &onboarding).await?;
+
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            Some(moderator_id),
+            AuditLogAction::OnboardingUpdate,
+            None,
+            None,
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        Ok(onboarding)
+    }
+
+    /// Fetch how the user completed the onboarding of a guild they are a member of.
+    ///
+    /// ## Returns
+    ///
+    /// The onboarding of the member, or `None` if they did not complete it.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Forbidden`] - If the user is not a member of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_member(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Option<MemberOnboarding>, AppError> {
+        let guild_id = guild.into();
+        let user_id = user.into();
+        self.app.members().fetch_required(user_id, guild_id).await?;
+
+        Ok(self.app.ops().fetch_member_onboarding(guild_id, user_id).await?)
+    }
+
+    /// Complete the onboarding of a guild the user is a member of, subscribing them to the default channels
+    /// and the channels of the options they chose. Completing it again replaces the earlier answers.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Forbidden`] - If the user is not a member of the guild, or the guild has no onboarding enabled.
+    /// * [`AppError::Build`] - If the responses do not answer the questions of the onboarding.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn complete(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        responses: Vec<OnboardingResponse>,
+    ) -> Result<MemberOnboarding, AppError> {
+        let guild_id = guild.into();
+        let user_id = user.into();
+        self.app.members().fetch_required(user_id, guild_id).await?;
+
+        let onboarding = self.app.ops().fetch_onboarding(guild_id).await?;
+        if !onboarding.enabled() {
+            return Err(AppError::Forbidden("Onboarding is not enabled in this guild.".into()));
+        }
+
+        let channels = self.channel_ids(guild_id).await?;
+        let mut channel_ids = onboarding.resolve(&responses)?;
+        channel_ids.retain(|id| channels.contains(id));
+
+        let member_onboarding = MemberOnboarding::new(guild_id, responses, channel_ids, self.app.clock.timestamp());
+        self.app
+            .ops()
+            .update_member_onboarding(user_id, &member_onboarding)
+            .await?;
+
+        Ok(member_onboarding)
+    }
+
+    /// The IDs of the channels of a guild that are not archived.
+    async fn channel_ids(&self, guild: Snowflake<Guild>) -> Result<HashSet<Snowflake<Channel>>, AppError> {
+        let channels = self.app.ops().fetch_channels_for(guild).await?;
+        Ok(channels.iter().map(ChannelLike::id).collect())
+    }
+}
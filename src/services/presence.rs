@@ -0,0 +1,177 @@
+use crate::models::{
+    errors::AppError,
+    gateway_event::{GatewayEvent, PresenceUpdatePayload},
+    presence_privacy::PresencePrivacy,
+    snowflake::Snowflake,
+    state::ApplicationState,
+    user::{Activity, Presence, User},
+};
+
+/// Presence and activity operations, shared by the REST API and the gateway.
+pub struct PresenceService<'a> {
+    app: &'a ApplicationState,
+}
+
+impl<'a> PresenceService<'a> {
+    /// Create a new presence service.
+    pub const fn new(app: &'a ApplicationState) -> Self {
+        Self { app }
+    }
+
+    /// Update the presence of a user. The presence is persisted across sessions.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to update the presence of.
+    /// * `presence` - The new presence of the user.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::PresenceUpdate`] - To all users sharing a guild with the user, if they are connected
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update(&self, user: impl Into<Snowflake<User>>, presence: Presence) -> Result<(), AppError> {
+        let user_id = user.into();
+
+        sqlx::query!(
+            "UPDATE users SET last_presence = $1 WHERE id = $2",
+            presence as i16,
+            user_id as Snowflake<User>
+        )
+        .execute(self.app.db.executor())
+        .await?;
+
+        if self.app.gateway.is_connected(user_id) {
+            // The gateway needs to know the user appears offline before the presence is dispatched
+            self.app.gateway.set_presence(user_id, presence);
+
+            // Users appearing offline should not leak their activity
+            let activity = if presence == Presence::Offline {
+                None
+            } else {
+                self.app.gateway.activity_of(user_id)
+            };
+            self.dispatch(user_id, presence, activity);
+        }
+        Ok(())
+    }
+
+    /// Update the activity of a connected user. Activities are only kept for the duration of the session.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to update the activity of.
+    /// * `activity` - The new activity of the user, or `None` to clear it.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::PresenceUpdate`] - To all users sharing a guild with the user, unless they appear offline
+    pub async fn update_activity(&self, user: impl Into<Snowflake<User>>, activity: Option<Activity>) {
+        let user_id = user.into();
+        self.app.gateway.set_activity(user_id, activity.clone());
+
+        // Users appearing offline should not leak their activity
+        match self.app.ops().fetch_presence(user_id).await {
+            None | Some(Presence::Offline) => {}
+            Some(presence) => self.dispatch(user_id, presence, activity),
+        }
+    }
+
+    /// Update who can see the presence of a user. The settings are persisted across sessions.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user to update the settings of.
+    /// * `privacy` - The new settings.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::PresenceUpdate`] - To all users who could see the user, marking them offline,
+    ///   then to all users who can now see the user, unless they appear offline
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Build`] - If the settings are invalid.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update_privacy(
+        &self,
+        user: impl Into<Snowflake<User>>,
+        privacy: PresencePrivacy,
+    ) -> Result<(), AppError> {
+        let user_id = user.into();
+        privacy.validate()?;
+        self.app.ops().update_presence_privacy(user_id, &privacy).await?;
+
+        if !self.app.gateway.is_connected(user_id) {
+            return Ok(());
+        }
+
+        match self.app.ops().fetch_presence(user_id).await {
+            None | Some(Presence::Offline) => self.app.gateway.set_presence_privacy(user_id, privacy),
+            Some(presence) => {
+                // Users who can no longer see the presence should be left with the user appearing offline
+                self.dispatch(user_id, Presence::Offline, None);
+                self.app.gateway.set_presence_privacy(user_id, privacy);
+                self.dispatch(user_id, presence, self.app.gateway.activity_of(user_id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Announce that a user has connected, using the presence they last set.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The user who connected.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::PresenceUpdate`] - To all users sharing a guild with the user, unless they appear offline
+    pub fn announce_online(&self, user: &User) {
+        if *user.last_presence() != Presence::Offline {
+            self.dispatch(user.id(), *user.last_presence(), None);
+        }
+    }
+
+    /// Announce that a user has disconnected.
+    ///
+    /// ## Arguments
+    ///
+    /// * `user` - The ID of the user who disconnected.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::PresenceUpdate`] - To all users sharing a guild with the user, unless they already appeared offline
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the user does not exist.
+    pub async fn announce_offline(&self, user: impl Into<Snowflake<User>>) -> Result<(), AppError> {
+        let user_id = user.into();
+
+        // Refetch presence in case it changed
+        let presence = self
+            .app
+            .ops()
+            .fetch_presence(user_id)
+            .await
+            .ok_or_else(|| AppError::NotFound("User does not exist.".into()))?;
+
+        if presence != Presence::Offline {
+            self.dispatch(user_id, Presence::Offline, None);
+        }
+        Ok(())
+    }
+
+    fn dispatch(&self, user_id: Snowflake<User>, presence: Presence, activity: Option<Activity>) {
+        self.app
+            .gateway
+            .dispatch(GatewayEvent::PresenceUpdate(PresenceUpdatePayload {
+                user_id,
+                presence,
+                activity,
+            }));
+    }
+}
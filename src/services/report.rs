@@ -0,0 +1,136 @@
+use crate::models::{
+    audit_log::{AuditLogAction, AuditLogEntry},
+    channel::{Channel, ChannelLike},
+    errors::AppError,
+    gateway_event::GatewayEvent,
+    guild::Guild,
+    message::Message,
+    report::{Report, ReportState},
+    requests::CreateReport,
+    snowflake::Snowflake,
+    state::ApplicationState,
+    user::User,
+};
+
+/// Message report operations, reviewed by guild owners.
+pub struct ReportService<'a> {
+    app: &'a ApplicationState,
+}
+
+impl<'a> ReportService<'a> {
+    /// Create a new report service.
+    pub const fn new(app: &'a ApplicationState) -> Self {
+        Self { app }
+    }
+
+    /// Report a message in a channel the user can view.
+    ///
+    /// ## Arguments
+    ///
+    /// * `channel` - The ID of the channel the message was sent in.
+    /// * `message` - The ID of the message to report.
+    /// * `reporter` - The ID of the user making the report.
+    /// * `payload` - The reason and details of the report.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::ReportCreate`] - For the owner of the guild the message was sent in
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the channel or the message does not exist.
+    /// * [`AppError::Forbidden`] - If the user cannot view the channel, or the channel does not belong to a guild.
+    /// * [`AppError::Conflict`] - If the user already reported the message.
+    /// * [`AppError::Build`] - If the report is invalid.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn create(
+        &self,
+        channel: impl Into<Snowflake<Channel>>,
+        message: impl Into<Snowflake<Message>>,
+        reporter: impl Into<Snowflake<User>>,
+        payload: CreateReport,
+    ) -> Result<Report, AppError> {
+        let reporter_id = reporter.into();
+        let (channel, _) = self.app.messages().fetch_channel(channel, reporter_id).await?;
+
+        // Nobody but the reporter can see messages outside of guilds
+        let guild = match channel.guild_id() {
+            Some(guild_id) => self.app.ops().fetch_guild(guild_id).await,
+            None => None,
+        }
+        .ok_or_else(|| AppError::Forbidden("Only messages in guilds can be reported.".into()))?;
+
+        let message = self
+            .app
+            .ops()
+            .fetch_message(message)
+            .await?
+            .filter(|message| message.channel_id() == channel.id())
+            .ok_or_else(|| AppError::NotFound("Message does not exist or is not available.".into()))?;
+
+        let report = Report::new(&self.app.config, &guild, &message, reporter_id, payload)?;
+
+        if !self.app.ops().create_report(&report).await? {
+            return Err(AppError::Conflict("You already reported this message.".into()));
+        }
+
+        self.app
+            .gateway
+            .send_to(guild.owner_id(), GatewayEvent::ReportCreate(report.clone()));
+        Ok(report)
+    }
+
+    /// Review a report of a message in a guild owned by the user.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild the report belongs to.
+    /// * `report` - The ID of the report to review.
+    /// * `moderator` - The ID of the user reviewing the report.
+    /// * `state` - The outcome of the review.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild or the report does not exist.
+    /// * [`AppError::Forbidden`] - If the user is not the owner of the guild.
+    /// * [`AppError::Build`] - If the report was already reviewed.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn review(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        report: impl Into<Snowflake<Report>>,
+        moderator: impl Into<Snowflake<User>>,
+        state: ReportState,
+    ) -> Result<Report, AppError> {
+        let moderator_id = moderator.into();
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator_id).await?;
+
+        let mut report = self
+            .app
+            .ops()
+            .fetch_report(report)
+            .await?
+            .filter(|report| report.guild_id() == guild.id())
+            .ok_or_else(|| AppError::NotFound("Report does not exist or is not available.".into()))?;
+
+        report.transition(state, moderator_id)?;
+        self.app.ops().update_report(&report).await?;
+
+        let action = if state == ReportState::Actioned {
+            AuditLogAction::ReportAction
+        } else {
+            AuditLogAction::ReportDismiss
+        };
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            Some(moderator_id),
+            action,
+            Some(report.id().cast()),
+            None,
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        Ok(report)
+    }
+}
@@ -0,0 +1,497 @@
+use crate::models::{
+    audit_log::{AuditLogAction, AuditLogEntry},
+    automod::AutoModRule,
+    errors::AppError,
+    fingerprint::EvasionFlag,
+    gateway_event::GatewayEvent,
+    guild::Guild,
+    requests::CreateStrike,
+    snowflake::Snowflake,
+    state::ApplicationState,
+    strike::{GuildBan, Standing, Strike, StrikeAction, StrikePolicy},
+    user::User,
+};
+
+/// Strike and ban operations, issued by guild owners and automod.
+pub struct StrikeService<'a> {
+    app: &'a ApplicationState,
+}
+
+impl<'a> StrikeService<'a> {
+    /// Create a new strike service.
+    pub const fn new(app: &'a ApplicationState) -> Self {
+        Self { app }
+    }
+
+    /// Issue a strike to a member of a guild owned by the moderator.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild the member is in.
+    /// * `user` - The ID of the member to issue the strike to.
+    /// * `moderator` - The ID of the user issuing the strike.
+    /// * `payload` - The reason and duration of the strike.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::StrikeCreate`] - For the member who was issued the strike
+    /// * [`GatewayEvent::MemberUpdate`] - If the member was timed out by the guild's strike policy
+    /// * [`GatewayEvent::GuildRemove`] - For the member, if they were banned by the guild's strike policy
+    /// * [`GatewayEvent::MemberRemove`] - For all other members, if the member was banned
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist, or the user is not a member of it.
+    /// * [`AppError::Forbidden`] - If the moderator is not the owner of the guild, or the member is the owner.
+    /// * [`AppError::Build`] - If the strike is invalid.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn issue(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        moderator: impl Into<Snowflake<User>>,
+        payload: CreateStrike,
+    ) -> Result<Strike, AppError> {
+        let moderator_id = moderator.into();
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator_id).await?;
+
+        self.issue_in(&guild, user.into(), Some(moderator_id), payload).await
+    }
+
+    /// Issue a strike to the author of a message that triggered an automod rule.
+    /// The owner of the guild is never issued strikes by automod.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild the message was sent in.
+    /// * `user` - The ID of the author of the message.
+    /// * `rule` - The triggered rule.
+    ///
+    /// ## Returns
+    ///
+    /// The issued strike, or `None` if the author is the owner of the guild.
+    ///
+    /// ## Dispatches
+    ///
+    /// See [`StrikeService::issue`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist, or the user is not a member of it.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn issue_for_rule(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        rule: &AutoModRule,
+    ) -> Result<Option<Strike>, AppError> {
+        let user_id = user.into();
+        let guild = self
+            .app
+            .ops()
+            .fetch_guild(guild)
+            .await
+            .ok_or_else(|| AppError::NotFound("Guild does not exist or is not available.".into()))?;
+
+        if user_id == guild.owner_id() {
+            return Ok(None);
+        }
+
+        let payload = CreateStrike {
+            reason: Some(format!("Triggered automod rule '{}'", rule.name())),
+            duration: None,
+        };
+        self.issue_in(&guild, user_id, None, payload).await.map(Some)
+    }
+
+    /// Commit a strike, then take the action of the guild's strike policy the member reached, if any.
+    async fn issue_in(
+        &self,
+        guild: &Guild,
+        user_id: Snowflake<User>,
+        issuer: Option<Snowflake<User>>,
+        mut payload: CreateStrike,
+    ) -> Result<Strike, AppError> {
+        if user_id == guild.owner_id() {
+            return Err(AppError::Forbidden(
+                "Cannot issue a strike to the owner of the guild.".into(),
+            ));
+        }
+
+        let mut member = self
+            .app
+            .ops()
+            .fetch_member(user_id, guild.id())
+            .await?
+            .ok_or_else(|| AppError::NotFound("Member does not exist or is not available.".into()))?;
+
+        let policy = self.app.ops().fetch_strike_policy(guild.id()).await?;
+        payload.duration = payload.duration.or_else(|| policy.strike_duration());
+
+        let now = self.app.clock.now();
+        let strike = Strike::new(&self.app.config, guild.id(), user_id, issuer, payload, now)?;
+        self.app.ops().create_strike(&strike).await?;
+
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            issuer,
+            AuditLogAction::StrikeCreate,
+            Some(user_id.cast()),
+            strike.reason().map(String::from),
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        self.app
+            .gateway
+            .send_to(user_id, GatewayEvent::StrikeCreate(strike.clone()));
+
+        let active = self.app.ops().fetch_active_strikes(guild.id(), user_id).await?.len();
+        let reason = format!("Reached {active} active strikes");
+
+        match policy.action_for(active) {
+            Some(StrikeAction::Timeout(duration)) => {
+                // Never shorten a longer timeout the member already has
+                let until = now.timestamp() + duration;
+                if member.timeout_until().is_none_or(|current| current < until) {
+                    member.set_timeout_until(Some(until), now);
+                    self.app.ops().update_member(&member).await?;
+                }
+
+                let entry = AuditLogEntry::new(
+                    &self.app.config,
+                    guild.id(),
+                    None,
+                    AuditLogAction::MemberTimeoutUpdate,
+                    Some(user_id.cast()),
+                    Some(reason),
+                );
+                self.app.ops().create_audit_log_entry(&entry).await?;
+            }
+            Some(StrikeAction::Ban) => {
+                let ban = GuildBan {
+                    guild_id: guild.id(),
+                    user_id,
+                    reason: Some(reason.clone()),
+                };
+                self.app.ops().create_guild_ban(&ban).await?;
+                self.app.members().leave(guild.id(), user_id).await?;
+
+                let entry = AuditLogEntry::new(
+                    &self.app.config,
+                    guild.id(),
+                    None,
+                    AuditLogAction::MemberBan,
+                    Some(user_id.cast()),
+                    Some(reason),
+                );
+                self.app.ops().create_audit_log_entry(&entry).await?;
+            }
+            None => {}
+        }
+
+        Ok(strike)
+    }
+
+    /// Fetch the active strikes of a member of a guild owned by the moderator, oldest first.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    /// * [`AppError::Forbidden`] - If the moderator is not the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_active(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        moderator: impl Into<Snowflake<User>>,
+    ) -> Result<Vec<Strike>, AppError> {
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator).await?;
+
+        Ok(self.app.ops().fetch_active_strikes(guild.id(), user).await?)
+    }
+
+    /// Remove a strike before it expires. Removing strikes does not lift timeouts or bans they caused.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The ID of the guild the strike was issued in.
+    /// * `user` - The ID of the user the strike was issued to.
+    /// * `strike` - The ID of the strike to remove.
+    /// * `moderator` - The ID of the user removing the strike.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild or the strike does not exist.
+    /// * [`AppError::Forbidden`] - If the moderator is not the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn remove(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        strike: impl Into<Snowflake<Strike>>,
+        moderator: impl Into<Snowflake<User>>,
+    ) -> Result<(), AppError> {
+        let user_id = user.into();
+        let moderator_id = moderator.into();
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator_id).await?;
+
+        let strike = self
+            .app
+            .ops()
+            .fetch_strike(strike)
+            .await?
+            .filter(|strike| strike.guild_id() == guild.id() && strike.user_id() == user_id)
+            .ok_or_else(|| AppError::NotFound("Strike does not exist or is not available.".into()))?;
+        self.app.ops().delete_strike(&strike).await?;
+
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            Some(moderator_id),
+            AuditLogAction::StrikeDelete,
+            Some(user_id.cast()),
+            strike.reason().map(String::from),
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        Ok(())
+    }
+
+    /// Fetch the standing of a user in a guild they are a member of.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Forbidden`] - If the user is not a member of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_standing(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Standing, AppError> {
+        let guild_id = guild.into();
+        let user_id = user.into();
+        self.app.members().fetch_required(user_id, guild_id).await?;
+
+        Ok(Standing {
+            guild_id,
+            strikes: self.app.ops().fetch_active_strikes(guild_id, user_id).await?,
+            policy: self.app.ops().fetch_strike_policy(guild_id).await?,
+        })
+    }
+
+    /// Fetch the strike policy of a guild owned by the moderator.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    /// * [`AppError::Forbidden`] - If the moderator is not the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_policy(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        moderator: impl Into<Snowflake<User>>,
+    ) -> Result<StrikePolicy, AppError> {
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator).await?;
+
+        Ok(self.app.ops().fetch_strike_policy(guild.id()).await?)
+    }
+
+    /// Replace the strike policy of a guild owned by the moderator.
+    /// The new thresholds are applied the next time a strike is issued.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    /// * [`AppError::Forbidden`] - If the moderator is not the owner of the guild.
+    /// * [`AppError::Build`] - If the policy is invalid.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn update_policy(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        moderator: impl Into<Snowflake<User>>,
+        policy: StrikePolicy,
+    ) -> Result<StrikePolicy, AppError> {
+        let moderator_id = moderator.into();
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator_id).await?;
+
+        policy.validate()?;
+        self.app.ops().update_strike_policy(guild.id(), &policy).await?;
+
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            Some(moderator_id),
+            AuditLogAction::StrikePolicyUpdate,
+            None,
+            None,
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        Ok(policy)
+    }
+
+    /// Fetch the bans of a guild owned by the moderator.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    /// * [`AppError::Forbidden`] - If the moderator is not the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_bans(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        moderator: impl Into<Snowflake<User>>,
+    ) -> Result<Vec<GuildBan>, AppError> {
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator).await?;
+
+        Ok(self.app.ops().fetch_guild_bans(guild.id()).await?)
+    }
+
+    /// Lift the ban of a user from a guild owned by the moderator, so they may join it again.
+    /// The strikes of the user are kept.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist, or the user is not banned from it.
+    /// * [`AppError::Forbidden`] - If the moderator is not the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn unban(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        moderator: impl Into<Snowflake<User>>,
+    ) -> Result<(), AppError> {
+        let moderator_id = moderator.into();
+        let user_id = user.into();
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator_id).await?;
+
+        if !self.app.ops().delete_guild_ban(guild.id(), user_id).await? {
+            return Err(AppError::NotFound("Ban does not exist or is not available.".into()));
+        }
+
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            Some(moderator_id),
+            AuditLogAction::MemberUnban,
+            Some(user_id.cast()),
+            None,
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        Ok(())
+    }
+
+    /// Flag a new member of a guild for review if they share a fingerprint with a user banned from it,
+    /// and the guild's strike policy flags ban evasion.
+    ///
+    /// ## Arguments
+    ///
+    /// * `guild` - The guild the user joined.
+    /// * `user` - The ID of the user who joined.
+    ///
+    /// ## Returns
+    ///
+    /// The flag, or `None` if the member was not flagged.
+    ///
+    /// ## Dispatches
+    ///
+    /// * [`GatewayEvent::EvasionFlagCreate`] - For the owner of the guild, if the member was flagged
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn flag_evasion(
+        &self,
+        guild: &Guild,
+        user: impl Into<Snowflake<User>>,
+    ) -> Result<Option<EvasionFlag>, AppError> {
+        let user_id = user.into();
+        if self.app.config.fingerprint_salt().is_none() {
+            return Ok(None);
+        }
+        if !self.app.ops().fetch_strike_policy(guild.id()).await?.flag_evasion() {
+            return Ok(None);
+        }
+        let Some(banned_user_id) = self.app.ops().fetch_evasion_match(guild.id(), user_id).await? else {
+            return Ok(None);
+        };
+
+        let flag = EvasionFlag {
+            guild_id: guild.id(),
+            user_id,
+            banned_user_id,
+            flagged_at: self.app.clock.now().timestamp(),
+        };
+        self.app.ops().create_evasion_flag(&flag).await?;
+
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            None,
+            AuditLogAction::MemberEvasionFlag,
+            Some(user_id.cast()),
+            Some(format!("Shares a fingerprint with banned user {banned_user_id}")),
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        self.app
+            .gateway
+            .send_to(guild.owner_id(), GatewayEvent::EvasionFlagCreate(flag.clone()));
+
+        Ok(Some(flag))
+    }
+
+    /// Fetch the members of a guild owned by the moderator who were flagged for ban evasion, oldest first.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist.
+    /// * [`AppError::Forbidden`] - If the moderator is not the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn fetch_evasion_flags(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        moderator: impl Into<Snowflake<User>>,
+    ) -> Result<Vec<EvasionFlag>, AppError> {
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator).await?;
+
+        Ok(self.app.ops().fetch_evasion_flags(guild.id()).await?)
+    }
+
+    /// Dismiss the evasion flag of a member of a guild owned by the moderator, after reviewing it.
+    /// Members who are evading a ban can be banned by issuing strikes instead.
+    ///
+    /// ## Errors
+    ///
+    /// * [`AppError::NotFound`] - If the guild does not exist, or the user is not flagged in it.
+    /// * [`AppError::Forbidden`] - If the moderator is not the owner of the guild.
+    /// * [`AppError::Database`] - If the database query fails.
+    pub async fn dismiss_evasion_flag(
+        &self,
+        guild: impl Into<Snowflake<Guild>>,
+        user: impl Into<Snowflake<User>>,
+        moderator: impl Into<Snowflake<User>>,
+    ) -> Result<(), AppError> {
+        let moderator_id = moderator.into();
+        let user_id = user.into();
+        let guild = self.app.guilds().fetch_as_owner(guild, moderator_id).await?;
+
+        if !self.app.ops().delete_evasion_flag(guild.id(), user_id).await? {
+            return Err(AppError::NotFound("Flag does not exist or is not available.".into()));
+        }
+
+        let entry = AuditLogEntry::new(
+            &self.app.config,
+            guild.id(),
+            Some(moderator_id),
+            AuditLogAction::MemberEvasionFlagDismiss,
+            Some(user_id.cast()),
+            None,
+        );
+        self.app.ops().create_audit_log_entry(&entry).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+//! Tests of the admin API, the internal API and the analytics firehose.
+
+use std::time::Duration;
+
+use reqwest::Method;
+use secrecy::Secret;
+use serde_json::{json, Value};
+
+use crate::gateway::testkit::TestServer;
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_internal_api() {
+    use crate::internal::{
+        proto::{
+            internal_server::Internal, CreateMessageRequest, FetchPresencesRequest, FetchUserRequest, Presence,
+            PresenceEntry,
+        },
+        InternalService,
+    };
+
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let service = InternalService::new(server.app().clone());
+    let id = |user: &str| user.parse::<i64>().expect("IDs should be numeric");
+
+    let user = service
+        .fetch_user(tonic::Request::new(FetchUserRequest {
+            user_id: None,
+            username: Some("alice".into()),
+        }))
+        .await
+        .expect("User should be found")
+        .into_inner();
+    assert_eq!(user.id, id(&alice.id));
+
+    // Injected messages are dispatched like any other message
+    let (mut client, _) = server.identify(&alice).await;
+    let response = service
+        .create_message(tonic::Request::new(CreateMessageRequest {
+            channel_id: id(&guild),
+            author_id: id(&alice.id),
+            content: "Hello from a bridge".into(),
+            nonce: None,
+        }))
+        .await
+        .expect("Message should be created")
+        .into_inner();
+    let event = client.expect_event("MESSAGE_CREATE").await;
+    assert_eq!(event["data"]["id"], response.message_id.to_string());
+    assert_eq!(event["data"]["content"], "Hello from a bridge");
+
+    // The author still needs to be able to send messages in the channel
+    let status = service
+        .create_message(tonic::Request::new(CreateMessageRequest {
+            channel_id: id(&guild),
+            author_id: id(&bob.id),
+            content: "Let me in".into(),
+            nonce: None,
+        }))
+        .await
+        .expect_err("Non-members should not be able to send messages");
+    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+    let presences = service
+        .fetch_presences(tonic::Request::new(FetchPresencesRequest {
+            user_ids: vec![id(&alice.id), id(&bob.id)],
+        }))
+        .await
+        .expect("Presences should be fetched")
+        .into_inner()
+        .presences;
+    let presence_of = |user: &str| {
+        presences
+            .iter()
+            .find(|entry| entry.user_id == id(user))
+            .map(PresenceEntry::presence)
+    };
+    assert_eq!(presence_of(&alice.id), Some(Presence::Online));
+    assert_eq!(presence_of(&bob.id), Some(Presence::Offline));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_firehose() {
+    use axum::{extract::State, routing::post, Json, Router};
+    use tokio::sync::mpsc;
+
+    // A stand-in for the analytics pipeline, forwarding every received batch to the test
+    let (batches_tx, mut batches) = mpsc::unbounded_channel::<Value>();
+    let receiver = Router::new()
+        .route(
+            "/events",
+            post(
+                |State(tx): State<mpsc::UnboundedSender<Value>>, Json(batch): Json<Value>| async move {
+                    tx.send(batch).ok();
+                },
+            ),
+        )
+        .with_state(batches_tx);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind firehose receiver");
+    let url = format!(
+        "http://{}/events",
+        listener.local_addr().expect("Receiver should have an address")
+    );
+    tokio::spawn(async move { axum::serve(listener, receiver).await.ok() });
+
+    let server = TestServer::start_with(|config| {
+        config
+            .firehose_url(Some(url))
+            .firehose_flush_interval(Duration::from_millis(200));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server.send_message(&alice, &guild, "Top secret plans").await;
+
+    // Events are sent in the background, possibly across multiple batches
+    let message = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let batch = batches.recv().await.expect("Receiver should stay open");
+            let events = batch["events"].as_array().expect("Batch should contain events").clone();
+            if let Some(event) = events.into_iter().find(|event| event["event"] == "MESSAGE_CREATE") {
+                return event;
+            }
+        }
+    })
+    .await
+    .expect("MESSAGE_CREATE should reach the firehose");
+
+    assert_eq!(message["guild_id"], guild);
+    assert_eq!(message["data"]["content"], Value::Null);
+    assert_eq!(message["data"]["author"]["username"], Value::Null);
+    assert!(message["timestamp"].is_i64());
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_system_announcements() {
+    use crate::internal::{
+        proto::{internal_server::Internal, CreateAnnouncementRequest},
+        InternalService,
+    };
+
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    server.create_guild(&alice, "Alice's guild").await;
+    let service = InternalService::new(server.app().clone());
+
+    // Announcements reach every connected user, even those that do not share a guild with anyone
+    let (mut alice_client, _) = server.identify(&alice).await;
+    let (mut bob_client, _) = server.identify(&bob).await;
+    let response = service
+        .create_announcement(tonic::Request::new(CreateAnnouncementRequest {
+            content: "Maintenance in 10 minutes".into(),
+            duration: None,
+            persist: false,
+        }))
+        .await
+        .expect("Announcement should be dispatched")
+        .into_inner();
+    for client in [&mut alice_client, &mut bob_client] {
+        let event = client.expect_event("SYSTEM_ANNOUNCEMENT").await;
+        assert_eq!(event["data"]["id"], response.announcement_id.to_string());
+        assert_eq!(event["data"]["content"], "Maintenance in 10 minutes");
+    }
+
+    // Persisted announcements must expire
+    let status = service
+        .create_announcement(tonic::Request::new(CreateAnnouncementRequest {
+            content: "Forever".into(),
+            duration: None,
+            persist: true,
+        }))
+        .await
+        .expect_err("Persisted announcements without a duration should be rejected");
+    assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+    // Only persisted announcements are included in READY
+    service
+        .create_announcement(tonic::Request::new(CreateAnnouncementRequest {
+            content: "Maintenance tonight".into(),
+            duration: Some(3600),
+            persist: true,
+        }))
+        .await
+        .expect("Announcement should be persisted");
+    drop(bob_client);
+    let (_, ready) = server.identify(&bob).await;
+    let announcements = ready["data"]["announcements"]
+        .as_array()
+        .expect("READY should include announcements");
+    assert_eq!(announcements.len(), 1);
+    assert_eq!(announcements[0]["content"], "Maintenance tonight");
+    assert!(announcements[0]["expires_at"].is_i64());
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_admin_log_level() {
+    let server = TestServer::start().await;
+    let (status, _) = server.try_request(Method::GET, "/admin/log-level", None, None).await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    server.close().await;
+
+    let server = TestServer::start_with(|config| {
+        config.admin_api_secret(Some(Secret::new(String::from("hunter2"))));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+
+    // Session tokens of users are not accepted
+    for token in [None, Some(alice.token.as_str()), Some("hunter3")] {
+        let (status, _) = server.try_request(Method::GET, "/admin/log-level", token, None).await;
+        assert_eq!(status, reqwest::StatusCode::UNAUTHORIZED);
+    }
+
+    let initial = server
+        .request(Method::GET, "/admin/log-level", Some("hunter2"), None)
+        .await;
+    assert_eq!(initial["revert_at"], Value::Null);
+    assert_eq!(initial["targets"], json!({}));
+
+    let (status, _) = server
+        .try_request(
+            Method::PUT,
+            "/admin/log-level",
+            Some("hunter2"),
+            Some(json!({ "level": "warn", "duration": 0 })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    let updated = server
+        .request(
+            Method::PUT,
+            "/admin/log-level",
+            Some("hunter2"),
+            Some(json!({ "level": "warn", "targets": { "gateway": "trace" }, "duration": 1 })),
+        )
+        .await;
+    assert_eq!(updated["level"], "warn");
+    assert_eq!(updated["targets"], json!({ "gateway": "trace" }));
+    assert!(updated["revert_at"].is_i64());
+
+    // The verbosity is reverted once the duration passed
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let reverted = server
+        .request(Method::GET, "/admin/log-level", Some("hunter2"), None)
+        .await;
+    assert_eq!(reverted, initial);
+
+    server
+        .request(
+            Method::PUT,
+            "/admin/log-level",
+            Some("hunter2"),
+            Some(json!({ "level": "error" })),
+        )
+        .await;
+    let reset = server
+        .request(Method::DELETE, "/admin/log-level", Some("hunter2"), None)
+        .await;
+    assert_eq!(reset, initial);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_setting_overrides() {
+    let server = TestServer::start_with(|config| {
+        config.admin_api_secret(Some(Secret::new(String::from("hunter2"))));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    server.create_guild(&alice, "Alice's guild").await;
+
+    let settings = server
+        .request(Method::GET, "/admin/settings", Some("hunter2"), None)
+        .await;
+    assert_eq!(settings["max_guilds_per_user"], 100);
+    assert_eq!(settings["overrides"], json!({}));
+
+    // Overrides take effect without a restart
+    let settings = server
+        .request(
+            Method::PUT,
+            "/admin/settings/max_guilds_per_user",
+            Some("hunter2"),
+            Some(json!({ "value": 1 })),
+        )
+        .await;
+    assert_eq!(settings["max_guilds_per_user"], 1);
+    assert_eq!(settings["overrides"], json!({ "max_guilds_per_user": 1 }));
+    assert_eq!(
+        server.request(Method::GET, "/limits", None, None).await["max_guilds_per_user"],
+        1
+    );
+    let (status, error) = server
+        .try_request(
+            Method::POST,
+            "/guilds",
+            Some(&alice.token),
+            Some(json!({ "name": "Another" })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "LIMIT_EXCEEDED");
+
+    for (key, value, expected) in [
+        ("max_guilds_per_user", json!(0), reqwest::StatusCode::BAD_REQUEST),
+        ("media_proxy_enabled", json!("yes"), reqwest::StatusCode::BAD_REQUEST),
+        ("app_secret", json!("hunter3"), reqwest::StatusCode::NOT_FOUND),
+    ] {
+        let (status, _) = server
+            .try_request(
+                Method::PUT,
+                &format!("/admin/settings/{key}"),
+                Some("hunter2"),
+                Some(json!({ "value": value })),
+            )
+            .await;
+        assert_eq!(status, expected, "Overriding {key} with {value}");
+    }
+
+    // Overrides stored by other instances are picked up on the next refresh
+    sqlx::query("INSERT INTO settings (key, value, updated_at) VALUES ('media_proxy_enabled', 'true', 0)")
+        .execute(server.app().db.pool())
+        .await
+        .expect("Failed to store override");
+    server.app().refresh_settings().await.expect("Settings should refresh");
+    assert!(server.app().settings.media_proxy_enabled());
+
+    let settings = server
+        .request(
+            Method::DELETE,
+            "/admin/settings/max_guilds_per_user",
+            Some("hunter2"),
+            None,
+        )
+        .await;
+    assert_eq!(settings["max_guilds_per_user"], 100);
+    assert_eq!(settings["overrides"], json!({ "media_proxy_enabled": true }));
+
+    server.close().await;
+}
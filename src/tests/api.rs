@@ -0,0 +1,344 @@
+//! Tests of behaviour shared by all REST routes, such as errors, rate limits and IP filters.
+
+use std::sync::Arc;
+
+use chat_client::RestClient;
+use chrono::TimeDelta;
+use reqwest::Method;
+use secrecy::Secret;
+use serde_json::{json, Value};
+
+use crate::{
+    gateway::testkit::TestServer,
+    models::{
+        clock::{Clock, ManualClock},
+        ip_filter::IpFilter,
+        snowflake::{self, Snowflake, DEFAULT_EPOCH, DISCORD_EPOCH},
+        user::User,
+    },
+};
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_error_codes() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+
+    let (status, error) = server.try_request(Method::GET, "/users/@me", None, None).await;
+    assert_eq!(status, reqwest::StatusCode::UNAUTHORIZED);
+    assert_eq!(error["code"], "MISSING_CREDENTIALS");
+
+    let (status, error) = server
+        .try_request(Method::DELETE, &format!("/guilds/{guild}"), Some(&bob.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    assert_eq!(error["code"], "FORBIDDEN");
+
+    let (status, error) = server
+        .try_request(
+            Method::PUT,
+            &format!("/guilds/{guild}/strike-policy"),
+            Some(&alice.token),
+            Some(json!({ "ban_threshold": 0 })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "OUT_OF_RANGE");
+    assert_eq!(error["field"], "ban_threshold");
+
+    // Requests rejected before reaching a route have the same format
+    let (status, error) = server
+        .try_request(Method::GET, "/guilds/not-a-snowflake", Some(&alice.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "BAD_REQUEST");
+    assert!(error["error"].is_string());
+
+    let (status, error) = server
+        .try_request(Method::POST, "/guilds", Some(&alice.token), Some(json!({ "name": 42 })))
+        .await;
+    assert_eq!(status, reqwest::StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["code"], "BAD_REQUEST");
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_manual_clock() {
+    let clock = Arc::new(ManualClock::default());
+    let server = TestServer::start_with_clock(clock.clone(), |_| {}).await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "general" })),
+        )
+        .await;
+    let channel = channel["id"].as_str().expect("Channel should have an ID");
+
+    let until = clock.timestamp() + 600;
+    server
+        .request(
+            Method::PUT,
+            &format!("/guilds/{guild}/members/{}/timeout", bob.id),
+            Some(&alice.token),
+            Some(json!({ "until": until })),
+        )
+        .await;
+    let (status, _) = server.try_send_message(&bob, channel, "Hello").await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    // The timeout expires once the clock passes it, without waiting for real time to pass
+    clock.advance(TimeDelta::seconds(601));
+    server.send_message(&bob, channel, "Hello").await;
+    let member = server
+        .request(
+            Method::GET,
+            &format!("/guilds/{guild}/members/@me"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    assert!(member["timeout_until"].is_null());
+
+    // Session tokens expire based on the same clock
+    clock.advance(TimeDelta::days(2));
+    let (status, _) = server
+        .try_request(Method::GET, "/users/@me", Some(&bob.token), None)
+        .await;
+    assert!(status.is_client_error());
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_optimistic_concurrency() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let guild_path = format!("/guilds/{guild}");
+
+    let fetched = server.request(Method::GET, &guild_path, Some(&alice.token), None).await;
+    assert_eq!(fetched["version"], 0);
+    let updated = server
+        .request(
+            Method::PATCH,
+            &guild_path,
+            Some(&alice.token),
+            Some(json!({ "name": "Renamed", "version": 0 })),
+        )
+        .await;
+    assert_eq!(updated["version"], 1);
+
+    // An update based on an outdated version is rejected, and does not change the guild
+    let (status, error) = server
+        .try_request(
+            Method::PATCH,
+            &guild_path,
+            Some(&alice.token),
+            Some(json!({ "name": "Stale", "version": 0 })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::CONFLICT);
+    assert_eq!(error["code"], "CONFLICT");
+    let fetched = server.request(Method::GET, &guild_path, Some(&alice.token), None).await;
+    assert_eq!(fetched["name"], "Renamed");
+
+    // Updates without a version always apply
+    let updated = server
+        .request(
+            Method::PATCH,
+            &guild_path,
+            Some(&alice.token),
+            Some(json!({ "name": "Latest" })),
+        )
+        .await;
+    assert_eq!(updated["version"], 2);
+
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "news" })),
+        )
+        .await;
+    let channel_path = format!(
+        "/channels/{}",
+        channel["id"].as_str().expect("Channel should have an ID")
+    );
+    let patch = json!({ "announcement": true, "version": 0 });
+    let updated = server
+        .request(Method::PATCH, &channel_path, Some(&alice.token), Some(patch.clone()))
+        .await;
+    assert_eq!(updated["version"], 1);
+    let (status, _) = server
+        .try_request(Method::PATCH, &channel_path, Some(&alice.token), Some(patch))
+        .await;
+    assert_eq!(status, reqwest::StatusCode::CONFLICT);
+
+    let prefs = server.request(Method::GET, "/prefs", Some(&alice.token), None).await;
+    assert_eq!(prefs["version"], 0);
+    let patch = json!({ "text_size": 14, "version": 0 });
+    server
+        .request(Method::PATCH, "/prefs", Some(&alice.token), Some(patch.clone()))
+        .await;
+    let (status, _) = server
+        .try_request(Method::PATCH, "/prefs", Some(&alice.token), Some(patch))
+        .await;
+    assert_eq!(status, reqwest::StatusCode::CONFLICT);
+    let prefs = server.request(Method::GET, "/prefs", Some(&alice.token), None).await;
+    assert_eq!(prefs["version"], 1);
+    assert_eq!(prefs["text_size"], 14);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_snowflake_epoch() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+
+    let meta = RestClient::new(server.base_url())
+        .fetch_meta()
+        .await
+        .expect("Meta should be fetched");
+    assert_eq!(meta.snowflake_epoch, DEFAULT_EPOCH);
+
+    // IDs decode to the time they were created at
+    let id: Snowflake<User> = alice.id.parse().expect("User ID should be a snowflake");
+    let age = server.app().clock.now() - id.created_at();
+    assert!(age >= TimeDelta::zero() && age < TimeDelta::minutes(1));
+
+    // Once recorded, the epoch cannot be changed
+    let db = &server.app().db;
+    assert_eq!(
+        snowflake::store_epoch(db, DEFAULT_EPOCH).await.ok(),
+        Some(DEFAULT_EPOCH)
+    );
+    assert_eq!(
+        snowflake::store_epoch(db, DISCORD_EPOCH).await.ok(),
+        Some(DEFAULT_EPOCH)
+    );
+    assert_eq!(snowflake::fetch_stored_epoch(db).await.ok(), Some(Some(DEFAULT_EPOCH)));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_load_shedding() {
+    let server = TestServer::start_with(|config| {
+        config.load_shed_max_in_flight(1_usize);
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Busy").await;
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "general" })),
+        )
+        .await;
+    let channel = channel["id"].as_str().expect("Channel should have an ID");
+    let search = format!("/channels/{channel}/messages/search?query=hello");
+
+    // A single request in flight does not overload the server
+    let (status, _) = server.try_request(Method::GET, &search, Some(&alice.token), None).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+
+    // Pretend another request is in flight
+    let guard = server.app().load_shedder.track();
+
+    for path in [
+        search.as_str(),
+        "/users/search?username=alice",
+        &format!("/guilds/{guild}/analytics"),
+    ] {
+        let response = server
+            .build_request(Method::GET, path)
+            .bearer_auth(&alice.token)
+            .send()
+            .await
+            .expect("Failed to send request");
+        assert_eq!(response.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[reqwest::header::RETRY_AFTER], "5");
+        let error: Value = response.json().await.expect("Response should be JSON");
+        assert_eq!(error["code"], "SERVICE_UNAVAILABLE");
+        assert_eq!(error["retry_after"], 5);
+    }
+
+    // Sending messages and other requests are never shed
+    server.send_message(&alice, channel, "hello").await;
+    server
+        .request(Method::GET, &format!("/guilds/{guild}"), Some(&alice.token), None)
+        .await;
+
+    drop(guard);
+    let (status, _) = server.try_request(Method::GET, &search, Some(&alice.token), None).await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_ip_filters() {
+    // Test clients connect from the loopback address
+    let server = TestServer::start_with(|config| {
+        config.admin_api_secret(Some(Secret::new(String::from("hunter2"))));
+        config.admin_ip_filter(IpFilter::new(&["10.0.0.0/8"], &[]).expect("Networks should be valid"));
+        config.webhook_ip_filter(IpFilter::new(&[], &["127.0.0.0/8", "::1"]).expect("Networks should be valid"));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+
+    let (status, _) = server
+        .try_request(Method::GET, "/admin/log-level", Some("hunter2"), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    let (status, _) = server
+        .try_request(
+            Method::GET,
+            &format!("/guilds/{guild}/webhooks"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    // Rejected requests are not authenticated, so they are not recorded in the audit log of the guild.
+    // Other routes are not filtered.
+    let entries = server
+        .request(
+            Method::GET,
+            &format!("/guilds/{guild}/audit-logs"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(entries, json!([]));
+
+    server.close().await;
+}
@@ -0,0 +1,365 @@
+//! Tests of announcement, archived and followed channels and sticky messages.
+
+use reqwest::Method;
+use serde_json::{json, Value};
+
+use crate::gateway::testkit::TestServer;
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_announcement_channels() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "announcements" })),
+        )
+        .await;
+    assert_eq!(channel["announcement"], false);
+    let channel = channel["id"].as_str().expect("Channel should have an ID").to_string();
+
+    // Only the guild owner may change the mode
+    let (status, _) = server
+        .try_request(
+            Method::PATCH,
+            &format!("/channels/{channel}"),
+            Some(&bob.token),
+            Some(json!({ "announcement": true })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    let (mut bob_client, _) = server.identify(&bob).await;
+    let updated = server
+        .request(
+            Method::PATCH,
+            &format!("/channels/{channel}"),
+            Some(&alice.token),
+            Some(json!({ "announcement": true })),
+        )
+        .await;
+    assert_eq!(updated["announcement"], true);
+    let channel_update = bob_client.expect_event("CHANNEL_UPDATE").await;
+    assert_eq!(channel_update["data"]["id"], channel);
+    assert_eq!(channel_update["data"]["announcement"], true);
+
+    let (status, error) = server.try_send_message(&bob, &channel, "Hello!").await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    assert_eq!(error["code"], "FORBIDDEN");
+    server.send_message(&alice, &channel, "Welcome!").await;
+
+    let fetched = server
+        .request(Method::GET, &format!("/channels/{channel}"), Some(&bob.token), None)
+        .await;
+    assert_eq!(fetched["announcement"], true);
+
+    server
+        .request(
+            Method::PATCH,
+            &format!("/channels/{channel}"),
+            Some(&alice.token),
+            Some(json!({ "announcement": false })),
+        )
+        .await;
+    server.send_message(&bob, &channel, "Hello!").await;
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_archived_channels() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "old-projects" })),
+        )
+        .await;
+    assert_eq!(channel["archived"], false);
+    let channel = channel["id"].as_str().expect("Channel should have an ID").to_string();
+    let archive = format!("/channels/{channel}/archive");
+    let archived = format!("/guilds/{guild}/channels/archived");
+    let message = server.send_message(&bob, &channel, "Project wrapped up!").await;
+
+    // Only the guild owner may archive channels
+    let (status, _) = server.try_request(Method::PUT, &archive, Some(&bob.token), None).await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    let (mut bob_client, _) = server.identify(&bob).await;
+    bob_client.expect_event("GUILD_CREATE").await;
+    let updated = server.request(Method::PUT, &archive, Some(&alice.token), None).await;
+    assert_eq!(updated["archived"], true);
+    let channel_update = bob_client.expect_event("CHANNEL_UPDATE").await;
+    assert_eq!(channel_update["data"]["id"], channel);
+    assert_eq!(channel_update["data"]["archived"], true);
+
+    // Archived channels are read-only, but their history stays available
+    let (status, error) = server.try_send_message(&alice, &channel, "Hello?").await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    assert_eq!(error["code"], "FORBIDDEN");
+    let history = server
+        .request(
+            Method::GET,
+            &format!("/channels/{channel}/messages"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    assert_eq!(history[0]["id"], message["id"]);
+
+    // Archived channels are only listed on request
+    let (mut client, _) = server.identify(&bob).await;
+    let guild_create = client.expect_event("GUILD_CREATE").await;
+    let channels = guild_create["data"]["channels"]
+        .as_array()
+        .expect("Channels should be an array");
+    assert!(channels.iter().all(|c| c["id"] != channel.as_str()));
+    let listed = server.request(Method::GET, &archived, Some(&bob.token), None).await;
+    assert_eq!(listed.as_array().map(Vec::len), Some(1));
+    assert_eq!(listed[0]["id"], channel);
+    let carol = server.create_user("carol").await;
+    let (status, _) = server
+        .try_request(Method::GET, &archived, Some(&carol.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    let updated = server.request(Method::DELETE, &archive, Some(&alice.token), None).await;
+    assert_eq!(updated["archived"], false);
+    server.send_message(&alice, &channel, "We're back!").await;
+    let listed = server.request(Method::GET, &archived, Some(&bob.token), None).await;
+    assert_eq!(listed, json!([]));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_sticky_messages() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "general" })),
+        )
+        .await;
+    let channel = channel["id"].as_str().expect("Channel should have an ID");
+    let sticky_path = format!("/channels/{channel}/sticky");
+    let rules = server.send_message(&alice, channel, "Be nice").await;
+    let faq = server.send_message(&alice, channel, "Read the FAQ").await;
+    let (mut bob_client, _) = server.identify(&bob).await;
+
+    let (status, _) = server
+        .try_request(Method::GET, &sticky_path, Some(&bob.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    let updated = server
+        .request(
+            Method::PUT,
+            &sticky_path,
+            Some(&alice.token),
+            Some(json!({ "message_id": rules["id"] })),
+        )
+        .await;
+    assert_eq!(updated["sticky_message_id"], rules["id"]);
+    let event = bob_client.expect_event("CHANNEL_STICKY_UPDATE").await;
+    assert_eq!(event["data"]["channel_id"], channel);
+    assert_eq!(event["data"]["message"]["content"], "Be nice");
+    let sticky = server.request(Method::GET, &sticky_path, Some(&bob.token), None).await;
+    assert_eq!(sticky["id"], rules["id"]);
+
+    // A channel has at most one sticky message, setting another one replaces it
+    let updated = server
+        .request(
+            Method::PUT,
+            &sticky_path,
+            Some(&alice.token),
+            Some(json!({ "message_id": faq["id"] })),
+        )
+        .await;
+    assert_eq!(updated["sticky_message_id"], faq["id"]);
+    bob_client.expect_event("CHANNEL_STICKY_UPDATE").await;
+
+    // Only the guild owner may change the sticky message, and only to messages of the channel
+    let (status, _) = server
+        .try_request(
+            Method::PUT,
+            &sticky_path,
+            Some(&bob.token),
+            Some(json!({ "message_id": rules["id"] })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let (status, _) = server
+        .try_request(
+            Method::PUT,
+            &sticky_path,
+            Some(&alice.token),
+            Some(json!({ "message_id": "1" })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    let cleared = server
+        .request(Method::DELETE, &sticky_path, Some(&alice.token), None)
+        .await;
+    assert_eq!(cleared["sticky_message_id"], Value::Null);
+    let event = bob_client.expect_event("CHANNEL_STICKY_UPDATE").await;
+    assert_eq!(event["data"]["message"], Value::Null);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_channel_follows() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let news_guild = server.create_guild(&alice, "Alice's guild").await;
+    let bob_guild = server.create_guild(&bob, "Bob's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{news_guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let news = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{news_guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "news" })),
+        )
+        .await["id"]
+        .as_str()
+        .expect("Channel should have an ID")
+        .to_string();
+    let target = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{bob_guild}/channels"),
+            Some(&bob.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "updates" })),
+        )
+        .await["id"]
+        .as_str()
+        .expect("Channel should have an ID")
+        .to_string();
+    let follow_body = json!({ "target_channel_id": target });
+
+    // Only announcement channels can be followed
+    let path = format!("/channels/{news}/followers");
+    let (status, _) = server
+        .try_request(Method::POST, &path, Some(&bob.token), Some(follow_body.clone()))
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    let body = json!({ "announcement": true });
+    server
+        .request(
+            Method::PATCH,
+            &format!("/channels/{news}"),
+            Some(&alice.token),
+            Some(body),
+        )
+        .await;
+
+    // Only the owner of the target channel's guild may follow into it
+    let (status, _) = server
+        .try_request(Method::POST, &path, Some(&alice.token), Some(follow_body.clone()))
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let follow = server
+        .request(Method::POST, &path, Some(&bob.token), Some(follow_body.clone()))
+        .await;
+    assert_eq!(follow["channel_id"], news);
+    assert_eq!(follow["target_channel_id"], target);
+    let (status, _) = server
+        .try_request(Method::POST, &path, Some(&bob.token), Some(follow_body))
+        .await;
+    assert_eq!(status, reqwest::StatusCode::CONFLICT);
+
+    let followers = server.request(Method::GET, &path, Some(&alice.token), None).await;
+    assert_eq!(followers[0]["id"], follow["id"]);
+
+    // Messages sent in the announcement channel are crossposted, with where they were sent
+    let (mut bob_client, _) = server.identify(&bob).await;
+    let original = server.send_message(&alice, &news, "Big news!").await;
+    let crosspost = loop {
+        let event = bob_client.expect_event("MESSAGE_CREATE").await;
+        if event["data"]["channel_id"] == target {
+            break event["data"].clone();
+        }
+    };
+    assert_eq!(crosspost["content"], "Big news!");
+    assert_eq!(crosspost["author"], Value::Null);
+    assert_eq!(crosspost["crosspost"]["message_id"], original["id"]);
+    assert_eq!(crosspost["crosspost"]["guild_name"], "Alice's guild");
+    assert_eq!(crosspost["crosspost"]["channel_name"], "news");
+
+    // Unfollowing stops crossposting
+    let follow_id = follow["id"].as_str().expect("Follow should have an ID");
+    let (status, _) = server
+        .try_request(
+            Method::DELETE,
+            &format!("/channels/{target}/follows/{follow_id}"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+    server.send_message(&alice, &news, "More news!").await;
+    let crossposts = server
+        .request(
+            Method::GET,
+            &format!("/channels/{target}/messages"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    assert_eq!(crossposts.as_array().map(Vec::len), Some(1));
+
+    server.close().await;
+}
@@ -0,0 +1,188 @@
+//! Tests of channel feeds.
+
+use std::sync::Arc;
+
+use chrono::TimeDelta;
+use reqwest::Method;
+use serde_json::json;
+
+use crate::{
+    gateway::testkit::{TestServer, TestUser, QUIET_PERIOD},
+    models::{
+        clock::{Clock, ManualClock},
+        feed::{Feed, ParsedFeed},
+        requests::CreateFeed,
+        snowflake::Snowflake,
+    },
+};
+
+/// Create a text channel in the guild, and subscribe it to a feed.
+/// The test server has no public feed to poll, so the feed is created directly and must be fed with [`FeedPoller::ingest`].
+///
+/// [`FeedPoller::ingest`]: crate::models::feed::FeedPoller::ingest
+pub(crate) async fn create_feed(server: &TestServer, owner: &TestUser, guild: &str, now: i64) -> (String, Feed) {
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&owner.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "news" })),
+        )
+        .await;
+    let channel = channel["id"].as_str().expect("Channel should have an ID").to_string();
+    let channel_id: Snowflake<_> = channel.parse::<i64>().expect("IDs should be numeric").into();
+
+    let payload = CreateFeed {
+        url: "http://127.0.0.1:9/feed.xml".into(),
+        poll_interval: Some(600),
+        enabled: None,
+    };
+    let feed = Feed::from_payload(&server.app().config, channel_id, payload, now).expect("Feed should be valid");
+    server
+        .app()
+        .ops()
+        .update_feed(&feed)
+        .await
+        .expect("Feed should be saved");
+    (channel, feed)
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_channel_feed_routes() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let (channel, feed) = create_feed(&server, &alice, &guild, server.app().clock.timestamp()).await;
+    let feeds_path = format!("/channels/{channel}/feeds");
+
+    // Feeds must not point at internal hosts
+    let (status, _) = server
+        .try_request(
+            Method::POST,
+            &feeds_path,
+            Some(&alice.token),
+            Some(json!({ "url": "http://127.0.0.1/feed.xml" })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let (status, _) = server
+        .try_request(
+            Method::POST,
+            &feeds_path,
+            Some(&alice.token),
+            Some(json!({ "url": "https://example.com/feed.xml", "poll_interval": 10 })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    let feeds = server.request(Method::GET, &feeds_path, Some(&alice.token), None).await;
+    assert_eq!(feeds[0]["id"], feed.id().to_string());
+    assert_eq!(feeds[0]["poll_interval"], 600);
+
+    let feed_path = format!("{feeds_path}/{}", feed.id());
+    let updated = server
+        .request(
+            Method::PATCH,
+            &feed_path,
+            Some(&alice.token),
+            Some(json!({ "enabled": false })),
+        )
+        .await;
+    assert_eq!(updated["enabled"], false);
+
+    let (status, _) = server
+        .try_request(Method::DELETE, &feed_path, Some(&alice.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+    let feeds = server.request(Method::GET, &feeds_path, Some(&alice.token), None).await;
+    assert_eq!(feeds, json!([]));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_channel_feed_polling() {
+    let clock = Arc::new(ManualClock::default());
+    let server = TestServer::start_with_clock(clock.clone(), |_| {}).await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let (channel, feed) = create_feed(&server, &alice, &guild, clock.timestamp()).await;
+
+    let (mut client, _) = server.identify(&alice).await;
+    let document = |ids: &[u32]| {
+        let items = ids
+            .iter()
+            .map(|id| {
+                format!("<item><guid>{id}</guid><title>Post {id}</title><link>https://example.com/{id}</link></item>")
+            })
+            .collect::<Vec<_>>()
+            .concat();
+        ParsedFeed::parse(&format!("<rss><channel><title>Example</title>{items}</channel></rss>"))
+            .expect("Feed should parse")
+    };
+
+    // Entries already in the feed when it is first polled are not posted
+    server
+        .app()
+        .feeds
+        .ingest(&feed, &document(&[1]))
+        .await
+        .expect("Feed should be ingested");
+    assert!(client
+        .collect_events(QUIET_PERIOD)
+        .await
+        .iter()
+        .all(|event| event["event"] != "MESSAGE_CREATE"));
+
+    let feed = server
+        .app()
+        .ops()
+        .fetch_feed(feed.id())
+        .await
+        .expect("Feed should be fetched")
+        .expect("Feed should exist");
+    assert_eq!(feed.title(), Some("Example"));
+    assert!(feed.last_polled_at().is_some());
+
+    server
+        .app()
+        .feeds
+        .ingest(&feed, &document(&[2, 1]))
+        .await
+        .expect("Feed should be ingested");
+    let message = client.expect_event("MESSAGE_CREATE").await;
+    assert!(message["data"]["author"].is_null());
+    assert_eq!(message["data"]["embeds"][0]["title"], "Post 2");
+    assert_eq!(message["data"]["embeds"][0]["url"], "https://example.com/2");
+    assert_eq!(message["data"]["embeds"][0]["provider"], "Example");
+
+    let messages = server
+        .request(
+            Method::GET,
+            &format!("/channels/{channel}/messages"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(messages[0]["embeds"][0]["title"], "Post 2");
+
+    // Failed polls are retried with backoff
+    clock.advance(TimeDelta::seconds(601));
+    assert_eq!(server.app().feeds.poll_due().await.expect("Feeds should be polled"), 1);
+    let feeds = server
+        .request(
+            Method::GET,
+            &format!("/channels/{channel}/feeds"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(feeds[0]["failures"], 1);
+    assert!(feeds[0]["last_error"].is_string());
+    assert_eq!(feeds[0]["next_poll_at"], clock.timestamp() + 1200);
+    assert_eq!(server.app().feeds.poll_due().await.expect("Feeds should be polled"), 0);
+
+    server.close().await;
+}
@@ -0,0 +1,687 @@
+//! Tests of guild settings, limits, onboarding and guest links.
+
+use std::sync::Arc;
+
+use chrono::TimeDelta;
+use reqwest::Method;
+use serde_json::{json, Value};
+
+use crate::{
+    gateway::testkit::{TestServer, TestUser, QUIET_PERIOD},
+    models::{
+        analytics,
+        clock::{Clock, ManualClock},
+    },
+};
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+#[allow(clippy::literal_string_with_formatting_args)] // Welcome message placeholders
+async fn test_welcome_message() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let carol = server.create_user("carol").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::PATCH,
+            &format!("/guilds/{guild}"),
+            Some(&alice.token),
+            Some(json!({ "welcome_message": { "content": "Welcome to {guild}, {user}!" } })),
+        )
+        .await;
+
+    let (mut bob_client, _) = server.identify(&bob).await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let welcome = bob_client.expect_event("WELCOME_MESSAGE").await;
+    assert_eq!(welcome["data"]["guild_id"], guild);
+    assert_eq!(welcome["data"]["content"], "Welcome to Alice's guild, bob!");
+
+    // Carol muted welcome messages in her preferences
+    server
+        .request(Method::PATCH, "/prefs", Some(&carol.token), Some(json!({ "flags": 7 })))
+        .await;
+    let (mut carol_client, _) = server.identify(&carol).await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&carol.token),
+            None,
+        )
+        .await;
+    carol_client.expect_event("GUILD_CREATE").await;
+    assert!(carol_client
+        .collect_events(QUIET_PERIOD)
+        .await
+        .iter()
+        .all(|event| event["event"] != "WELCOME_MESSAGE"));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_guild_limit_exemption() {
+    use crate::models::limits::Limits;
+
+    let server = TestServer::start_with(|config| {
+        config.limits(Limits::new(1, 500, 10_000, 8 * 1024 * 1024));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    server.create_guild(&alice, "Alice's guild").await;
+
+    let (status, error) = server
+        .try_request(
+            Method::POST,
+            "/guilds",
+            Some(&alice.token),
+            Some(json!({ "name": "Another guild" })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "LIMIT_EXCEEDED");
+    assert_eq!(error["limit"], "max_guilds_per_user");
+    assert_eq!(error["max"], 1);
+
+    sqlx::query("UPDATE users SET flags = flags | 1 WHERE id = $1")
+        .bind(alice.id.parse::<i64>().expect("IDs should be numeric"))
+        .execute(server.app().db.pool())
+        .await
+        .expect("Failed to set user flags");
+    server.create_guild(&alice, "Another guild").await;
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_guild_soft_delete() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+
+    let (mut bob_client, _) = server.identify(&bob).await;
+    server
+        .request(Method::DELETE, &format!("/guilds/{guild}"), Some(&alice.token), None)
+        .await;
+    bob_client.expect_event("GUILD_REMOVE").await;
+
+    // Deleted guilds are hidden from queries and new sessions, as if they did not exist
+    let (status, _) = server
+        .try_request(Method::GET, &format!("/guilds/{guild}"), Some(&alice.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let guilds = server
+        .request(Method::GET, "/users/@me/guilds", Some(&bob.token), None)
+        .await;
+    assert_eq!(guilds, json!([]));
+    let (mut bob_client, ready) = server.identify(&bob).await;
+    assert_eq!(ready["data"]["guilds"], json!([]));
+
+    // Only the owner may restore the guild
+    let restore = format!("/guilds/{guild}/restore");
+    let (status, _) = server.try_request(Method::POST, &restore, Some(&bob.token), None).await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let restored = server.request(Method::POST, &restore, Some(&alice.token), None).await;
+    assert_eq!(restored["id"], guild);
+    let guild_create = bob_client.expect_event("GUILD_CREATE").await;
+    assert_eq!(guild_create["data"]["guild"]["id"], guild);
+
+    // Once the grace period has passed, the guild can no longer be restored and is due for purging
+    server
+        .request(Method::DELETE, &format!("/guilds/{guild}"), Some(&alice.token), None)
+        .await;
+    sqlx::query("UPDATE guilds SET deleted_at = 0 WHERE id = $1")
+        .bind(guild.parse::<i64>().expect("IDs should be numeric"))
+        .execute(server.app().db.pool())
+        .await
+        .expect("Failed to expire the grace period");
+    let due = server
+        .app()
+        .ops()
+        .fetch_guilds_deleted_before(0)
+        .await
+        .expect("Failed to fetch deleted guilds");
+    assert_eq!(due.len(), 1);
+    let (status, _) = server
+        .try_request(Method::POST, &restore, Some(&alice.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_guild_analytics() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    server.send_message(&alice, &guild, "Hello").await;
+    server.send_message(&bob, &guild, "Hi").await;
+    server.send_message(&bob, &guild, "Bye").await;
+    server
+        .request(
+            Method::DELETE,
+            &format!("/guilds/{guild}/members/@me"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+
+    // Messages are only counted once the statistics are aggregated
+    let path = format!("/guilds/{guild}/analytics?days=7");
+    let analytics = server.request(Method::GET, &path, Some(&alice.token), None).await;
+    assert_eq!(analytics["days"].as_array().expect("Days should be an array").len(), 7);
+    assert_eq!(analytics["totals"]["messages"], 0);
+    // The owner joining on creation is counted as well
+    assert_eq!(analytics["totals"]["joins"], 2);
+    assert_eq!(analytics["totals"]["leaves"], 1);
+
+    let today = analytics::day_start(server.app().clock.now());
+    for day in [today - analytics::SECONDS_PER_DAY, today] {
+        server
+            .app()
+            .ops()
+            .aggregate_guild_stats(day)
+            .await
+            .expect("Statistics should aggregate");
+    }
+
+    let analytics = server.request(Method::GET, &path, Some(&alice.token), None).await;
+    assert_eq!(analytics["totals"]["messages"], 3);
+    let days = analytics["days"].as_array().expect("Days should be an array");
+    assert_eq!(days[6]["day"], today);
+    assert_eq!(days[0]["day"], today - 6 * analytics::SECONDS_PER_DAY);
+    let active_members: i64 = days
+        .iter()
+        .map(|day| {
+            day["active_members"]
+                .as_i64()
+                .expect("Active members should be a number")
+        })
+        .sum();
+    assert!(active_members >= 2);
+
+    let analytics = server
+        .request(
+            Method::GET,
+            &format!("/guilds/{guild}/analytics?days=1000"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(
+        analytics["days"].as_array().expect("Days should be an array").len(),
+        analytics::MAX_ANALYTICS_DAYS as usize
+    );
+
+    // Only the owner can see the analytics
+    let (status, _) = server.try_request(Method::GET, &path, Some(&bob.token), None).await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_guild_settings() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let alices_guild = server.create_guild(&alice, "Alice's guild").await;
+    let bobs_guild = server.create_guild(&bob, "Bob's guild").await;
+    let games = server.create_guild(&bob, "Games").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{alices_guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+
+    let settings = server
+        .request(Method::GET, "/users/@me/guild-settings", Some(&bob.token), None)
+        .await;
+    assert_eq!(settings, json!({ "folders": [] }));
+
+    let (mut client, _) = server.identify(&bob).await;
+    let folders = json!([
+        { "id": null, "guild_ids": [bobs_guild] },
+        { "id": 1, "name": " Friends ", "color": 0x00FF_8800, "guild_ids": [games, alices_guild] },
+    ]);
+    let updated = server
+        .request(
+            Method::PATCH,
+            "/users/@me/guild-settings",
+            Some(&bob.token),
+            Some(json!({ "folders": folders })),
+        )
+        .await;
+    assert_eq!(updated["folders"][1]["name"], "Friends");
+    assert_eq!(updated["folders"][1]["guild_ids"], json!([games, alices_guild]));
+    let settings_update = client.expect_event("GUILD_SETTINGS_UPDATE").await;
+    assert_eq!(settings_update["data"], updated);
+
+    // Settings are part of READY, so all sessions start from the same layout
+    let (_client, ready) = server.identify(&bob).await;
+    assert_eq!(ready["data"]["guild_settings"], updated);
+
+    // Each guild may only be listed once
+    let (status, error) = server
+        .try_request(
+            Method::PATCH,
+            "/users/@me/guild-settings",
+            Some(&bob.token),
+            Some(json!({ "folders": [{ "id": 1, "guild_ids": [games, games] }] })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "VALIDATION_FAILED");
+    assert_eq!(error["field"], "folders");
+
+    // Guilds the user left are no longer listed
+    server
+        .request(
+            Method::DELETE,
+            &format!("/guilds/{alices_guild}/members/@me"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let settings = server
+        .request(Method::GET, "/users/@me/guild-settings", Some(&bob.token), None)
+        .await;
+    assert_eq!(settings["folders"][1]["guild_ids"], json!([games]));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_bulk_guild_operations() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let bulk_path = format!("/guilds/{guild}/bulk");
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let general = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "general" })),
+        )
+        .await;
+    let (mut bob_client, _) = server.identify(&bob).await;
+
+    let results = server
+        .request(
+            Method::POST,
+            &bulk_path,
+            Some(&alice.token),
+            Some(json!({ "operations": [
+                { "op": "CREATE_CHANNEL", "data": { "type": "GUILD_TEXT", "name": "rules" } },
+                { "op": "CREATE_CHANNEL", "data": { "type": "GUILD_TEXT", "name": "news" } },
+                { "op": "UPDATE_CHANNEL", "channel_id": general["id"], "data": { "announcement": true } },
+            ] })),
+        )
+        .await;
+    assert_eq!(results[0]["op"], "CREATE_CHANNEL");
+    assert_eq!(results[1]["channel"]["name"], "news");
+    assert_eq!(results[2]["op"], "UPDATE_CHANNEL");
+    assert_eq!(results[2]["channel"]["announcement"], true);
+    assert_eq!(results[2]["channel"]["version"], 1);
+
+    // Members receive a single event for the whole batch
+    let events = bob_client.collect_events(QUIET_PERIOD).await;
+    let channel_events: Vec<_> = events
+        .iter()
+        .filter(|event| event["event"].as_str().is_some_and(|name| name.starts_with("CHANNEL_")))
+        .collect();
+    assert_eq!(channel_events.len(), 1);
+    assert_eq!(channel_events[0]["event"], "CHANNEL_BULK_UPDATE");
+    assert_eq!(channel_events[0]["data"]["created"][0]["name"], "rules");
+    assert_eq!(channel_events[0]["data"]["updated"][0]["id"], general["id"]);
+
+    // If an operation fails, the error names it and none of the operations are committed
+    let (status, error) = server
+        .try_request(
+            Method::POST,
+            &bulk_path,
+            Some(&alice.token),
+            Some(json!({ "operations": [
+                { "op": "CREATE_CHANNEL", "data": { "type": "GUILD_TEXT", "name": "ghost" } },
+                { "op": "UPDATE_CHANNEL", "channel_id": general["id"], "data": { "announcement": false, "version": 0 } },
+            ] })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::CONFLICT);
+    assert_eq!(error["code"], "CONFLICT");
+    assert_eq!(error["index"], 1);
+    assert!(bob_client.collect_events(QUIET_PERIOD).await.is_empty());
+
+    let (status, error) = server
+        .try_request(
+            Method::POST,
+            &bulk_path,
+            Some(&alice.token),
+            Some(json!({ "operations": [
+                { "op": "UPDATE_CHANNEL", "channel_id": "1", "data": { "announcement": true } },
+            ] })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    assert_eq!(error["index"], 0);
+
+    let (mut bob_client, _) = server.identify(&bob).await;
+    let guild_create = bob_client.expect_event("GUILD_CREATE").await;
+    let names: Vec<_> = guild_create["data"]["channels"]
+        .as_array()
+        .expect("Guild should have channels")
+        .iter()
+        .map(|channel| channel["name"].clone())
+        .collect();
+    assert!(names.contains(&json!("rules")));
+    assert!(!names.contains(&json!("ghost")));
+
+    // Batches are bounded
+    let (status, _) = server
+        .try_request(
+            Method::POST,
+            &bulk_path,
+            Some(&alice.token),
+            Some(json!({ "operations": [] })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_onboarding() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let other = server.create_guild(&alice, "Other").await;
+
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let mut channels = Vec::new();
+    for (target, name) in [
+        (&guild, "rules"),
+        (&guild, "art"),
+        (&guild, "games"),
+        (&other, "elsewhere"),
+    ] {
+        let channel = server
+            .request(
+                Method::POST,
+                &format!("/guilds/{target}/channels"),
+                Some(&alice.token),
+                Some(json!({ "type": "GUILD_TEXT", "name": name })),
+            )
+            .await;
+        channels.push(channel["id"].as_str().unwrap_or_default().to_string());
+    }
+    let [rules, art, games, elsewhere] = [&channels[0], &channels[1], &channels[2], &channels[3]];
+    let (settings, completion) = (
+        format!("/guilds/{guild}/onboarding"),
+        format!("/guilds/{guild}/members/@me/onboarding"),
+    );
+
+    // Guilds start without onboarding, so it cannot be completed
+    let onboarding = server.request(Method::GET, &settings, Some(&bob.token), None).await;
+    assert_eq!(
+        onboarding,
+        json!({ "enabled": false, "default_channel_ids": [], "questions": [] })
+    );
+    let (status, _) = put_onboarding(&server, &bob, &completion, json!({ "responses": [] })).await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    let mut payload = json!({
+        "enabled": true,
+        "default_channel_ids": [rules],
+        "questions": [{
+            "id": 1,
+            "title": "What are you into?",
+            "required": true,
+            "options": [
+                { "id": 1, "title": "Art", "channel_ids": [art] },
+                { "id": 2, "title": "Games", "channel_ids": [games, rules] },
+            ],
+        }],
+    });
+
+    // Only the owner may set up onboarding, and only with channels of the guild
+    let (status, _) = put_onboarding(&server, &bob, &settings, payload.clone()).await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    payload["default_channel_ids"] = json!([elsewhere]);
+    let (status, _) = put_onboarding(&server, &alice, &settings, payload.clone()).await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    payload["default_channel_ids"] = json!([rules]);
+    let (_, onboarding) = put_onboarding(&server, &alice, &settings, payload.clone()).await;
+    assert_eq!(
+        onboarding["questions"][0]["options"][1]["channel_ids"],
+        json!([games, rules])
+    );
+
+    // Required questions must be answered
+    let (status, _) = put_onboarding(&server, &bob, &completion, json!({ "responses": [] })).await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    // Bob is subscribed to the default channels and the channels of the options they chose, without duplicates
+    let (_, completed) = put_onboarding(
+        &server,
+        &bob,
+        &completion,
+        json!({ "responses": [{ "question_id": 1, "option_ids": [1, 2] }] }),
+    )
+    .await;
+    assert_eq!(completed["guild_id"], json!(guild));
+    assert_eq!(completed["channel_ids"], json!([rules, art, games]));
+
+    let fetched = server.request(Method::GET, &completion, Some(&bob.token), None).await;
+    assert_eq!(fetched, completed);
+
+    // Members who did not complete the onboarding have none
+    let fetched = server.request(Method::GET, &completion, Some(&alice.token), None).await;
+    assert_eq!(fetched, json!(null));
+
+    server.close().await;
+}
+
+/// Replace an onboarding as the user, returning the status and body of the response
+async fn put_onboarding(
+    server: &TestServer,
+    user: &TestUser,
+    path: &str,
+    payload: Value,
+) -> (reqwest::StatusCode, Value) {
+    server
+        .try_request(Method::PUT, path, Some(&user.token), Some(payload))
+        .await
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_guest_links() {
+    let clock = Arc::new(ManualClock::default());
+    let server = TestServer::start_with_clock(clock.clone(), |_| {}).await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let mut channels = Vec::new();
+    for name in ["lobby", "staff"] {
+        let channel = server
+            .request(
+                Method::POST,
+                &format!("/guilds/{guild}/channels"),
+                Some(&alice.token),
+                Some(json!({ "type": "GUILD_TEXT", "name": name })),
+            )
+            .await;
+        channels.push(channel["id"].as_str().expect("Channel should have an ID").to_string());
+    }
+    let (lobby, staff) = (&channels[0], &channels[1]);
+
+    // Only the owner may create guest links
+    let path = format!("/guilds/{guild}/guest-links");
+    let body = json!({ "channel_ids": [lobby], "duration": 2 });
+    let (status, _) = server
+        .try_request(Method::POST, &path, Some(&bob.token), Some(body.clone()))
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let link = server
+        .request(Method::POST, &path, Some(&alice.token), Some(body))
+        .await;
+    let code = link["code"].as_str().expect("Link should have a code");
+
+    // Guests only see and can only write in the channels of the link
+    let (mut bob_client, _) = server.identify(&bob).await;
+    let member = server
+        .request(Method::POST, &format!("/guest-links/{code}"), Some(&bob.token), None)
+        .await;
+    assert_eq!(member["guest"]["channel_ids"], json!([lobby]));
+    assert_eq!(member["guest"]["expires_at"], clock.timestamp() + 2 * 3600);
+    let guild_create = bob_client.expect_event("GUILD_CREATE").await;
+    assert_eq!(guild_create["data"]["channels"].as_array().map(Vec::len), Some(1));
+    assert_eq!(guild_create["data"]["channels"][0]["id"], *lobby);
+
+    let (status, _) = server.try_send_message(&bob, staff, "Hello").await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    server.send_message(&bob, lobby, "Hello").await;
+    server.send_message(&alice, staff, "Staff only").await;
+    server.send_message(&alice, lobby, "Welcome!").await;
+    loop {
+        let event = bob_client.expect_event("MESSAGE_CREATE").await;
+        assert_ne!(event["data"]["channel_id"], *staff);
+        if event["data"]["content"] == "Welcome!" {
+            break;
+        }
+    }
+
+    // Expired guests lose access right away, and are removed by the sweep
+    clock.advance(TimeDelta::hours(2));
+    let (status, _) = server.try_send_message(&bob, lobby, "Still here?").await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let removed = server
+        .app()
+        .members()
+        .expire_guests()
+        .await
+        .expect("Sweep should succeed");
+    assert_eq!(removed, 1);
+    let guild_remove = bob_client.expect_event("GUILD_REMOVE").await;
+    assert_eq!(guild_remove["data"]["id"], guild);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+#[allow(clippy::literal_string_with_formatting_args)] // Welcome message placeholders
+async fn test_guild_locale() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let path = format!("/guilds/{guild}");
+
+    let fetched = server.request(Method::GET, &path, Some(&alice.token), None).await;
+    assert_eq!(fetched["locale"], "en_US");
+    assert_eq!(fetched["timezone"], "UTC");
+
+    for payload in [
+        json!({ "locale": "german" }),
+        json!({ "timezone": "Mars/Olympus_Mons" }),
+    ] {
+        let (status, _) = server
+            .try_request(Method::PATCH, &path, Some(&alice.token), Some(payload))
+            .await;
+        assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    }
+
+    let updated = server
+        .request(
+            Method::PATCH,
+            &path,
+            Some(&alice.token),
+            Some(json!({
+                "locale": "de_DE",
+                "timezone": "Europe/Berlin",
+                "welcome_message": { "content": "{user} joined on {joined_at}" }
+            })),
+        )
+        .await;
+    assert_eq!(updated["locale"], "de_DE");
+    assert_eq!(updated["timezone"], "Europe/Berlin");
+
+    let (mut bob_client, _) = server.identify(&bob).await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let welcome = bob_client.expect_event("WELCOME_MESSAGE").await;
+    let content = welcome["data"]["content"].as_str().expect("Content should be a string");
+    // The date is written as DD.MM.YYYY, followed by the time and the abbreviation of the timezone
+    let (date, time) = content
+        .strip_prefix("bob joined on ")
+        .and_then(|rest| rest.split_once(' '))
+        .expect("Content should contain the date");
+    assert_eq!(date.split('.').count(), 3);
+    assert!(time.ends_with("CET") || time.ends_with("CEST"));
+
+    server.close().await;
+}
@@ -0,0 +1,57 @@
+//! Tests of re-hosting linked media.
+
+use reqwest::Method;
+use serde_json::json;
+
+use crate::{
+    gateway::testkit::TestServer,
+    models::{content, jobs, snowflake::Snowflake},
+};
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_media_rehost_rejects_private_hosts() {
+    let server = TestServer::start_with(|config| {
+        config.media_rehost_enabled(true);
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let saved = server
+        .request(Method::GET, "/users/@me/saved-messages", Some(&alice.token), None)
+        .await;
+    let channel = saved["id"].as_str().expect("Channel should have an ID");
+
+    // The server must never be tricked into fetching media from internal hosts
+    let content = "look <http://127.0.0.1:9/cat.png> and http://169.254.169.254/latest/clip.mp4";
+    let sent = server.send_message(&alice, channel, content).await;
+    let id: i64 = sent["id"]
+        .as_str()
+        .and_then(|id| id.parse().ok())
+        .expect("Message should have an ID");
+
+    let message = server
+        .app()
+        .ops()
+        .fetch_message(Snowflake::new(id))
+        .await
+        .expect("Query should succeed")
+        .expect("Message should exist");
+    let links = content::find_media_links(content);
+    assert_eq!(links.len(), 2);
+    jobs::rehost_media(server.app().clone(), message, links)
+        .await
+        .expect("Re-hosting should not fail the job");
+
+    let messages = server
+        .request(
+            Method::GET,
+            &format!("/channels/{channel}/messages"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(messages[0]["content"], content);
+    assert_eq!(messages[0]["attachments"], json!([]));
+
+    server.close().await;
+}
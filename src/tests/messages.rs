@@ -0,0 +1,416 @@
+//! Tests of sending, fetching and searching messages.
+
+use std::time::Duration;
+
+use reqwest::Method;
+use serde_json::{json, Value};
+
+use crate::{
+    gateway::testkit::{TestServer, QUIET_PERIOD},
+    models::{channel::Channel, snowflake::Snowflake},
+};
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_saved_messages_are_private() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+
+    let saved = server
+        .request(Method::GET, "/users/@me/saved-messages", Some(&alice.token), None)
+        .await;
+    assert_eq!(saved["type"], "SAVED_MESSAGES");
+    assert_eq!(saved["owner_id"], alice.id);
+    let channel = saved["id"].as_str().expect("Channel should have an ID");
+
+    let (mut alice_client, _) = server.identify(&alice).await;
+    let (mut bob_client, _) = server.identify(&bob).await;
+
+    server.send_message(&alice, channel, "Buy milk").await;
+    let message_create = alice_client.expect_event("MESSAGE_CREATE").await;
+    assert_eq!(message_create["data"]["content"], "Buy milk");
+    // Bob shares a guild with Alice, but must not see her saved messages
+    assert!(bob_client
+        .collect_events(QUIET_PERIOD)
+        .await
+        .iter()
+        .all(|event| event["event"] != "MESSAGE_CREATE"));
+
+    let history = server
+        .request(
+            Method::GET,
+            &format!("/channels/{channel}/messages"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(history[0]["content"], "Buy milk");
+
+    let (status, _) = server
+        .try_request(
+            Method::GET,
+            &format!("/channels/{channel}/messages"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    assert_eq!(status, 404);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_message_search() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "general" })),
+        )
+        .await;
+    let channel = channel["id"].as_str().expect("Channel should have an ID");
+    let pizza = server.send_message(&alice, channel, "Who wants pizza tonight?").await;
+    server.send_message(&alice, channel, "Meeting moved to Friday").await;
+
+    // Messages are indexed in the background
+    let path = format!("/channels/{channel}/messages/search?query=pizza");
+    let mut results = Value::Null;
+    for _ in 0..50 {
+        results = server.request(Method::GET, &path, Some(&alice.token), None).await;
+        if results.as_array().is_some_and(|results| !results.is_empty()) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let results = results.as_array().expect("Results should be an array");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["id"], pizza["id"]);
+
+    // Only users who can view the channel can search it
+    let (status, _) = server.try_request(Method::GET, &path, Some(&bob.token), None).await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    let (status, _) = server
+        .try_request(
+            Method::GET,
+            &format!("/channels/{channel}/messages/search?query=%20"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_message_translations() {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use axum::{extract::State, routing::post, Json, Router};
+
+    // A stand-in for LibreTranslate, counting how often it is asked to translate
+    let requests = Arc::new(AtomicUsize::new(0));
+    let translator = Router::new()
+        .route(
+            "/translate",
+            post(|State(requests): State<Arc<AtomicUsize>>, Json(body): Json<Value>| async move {
+                requests.fetch_add(1, Ordering::SeqCst);
+                Json(json!({
+                    "translatedText": format!("[{}] {}", body["target"].as_str().unwrap_or_default(), body["q"].as_str().unwrap_or_default()),
+                    "detectedLanguage": { "confidence": 90.0, "language": "en" },
+                }))
+            }),
+        )
+        .with_state(requests.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind translation service");
+    let url = format!(
+        "http://{}",
+        listener
+            .local_addr()
+            .expect("Translation service should have an address")
+    );
+    tokio::spawn(async move { axum::serve(listener, translator).await.ok() });
+
+    let server = TestServer::start_with(|config| {
+        config.libretranslate_url(Some(url));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let message = server.send_message(&alice, &guild, "Hello there").await;
+    let path = format!(
+        "/channels/{guild}/messages/{}/translate",
+        message["id"].as_str().expect("ID should be a string")
+    );
+
+    let translation = server
+        .request(Method::GET, &format!("{path}?to=es"), Some(&alice.token), None)
+        .await;
+    assert_eq!(translation["content"], "[es] Hello there");
+    assert_eq!(translation["language"], "es");
+    assert_eq!(translation["source_language"], "en");
+
+    // Translations are cached per language
+    server
+        .request(Method::GET, &format!("{path}?to=es"), Some(&alice.token), None)
+        .await;
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    server
+        .request(Method::GET, &format!("{path}?to=de"), Some(&alice.token), None)
+        .await;
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    let (status, _) = server
+        .try_request(Method::GET, &format!("{path}?to=spanish"), Some(&alice.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    // Only users who can see the message can translate it
+    let (status, _) = server
+        .try_request(Method::GET, &format!("{path}?to=es"), Some(&bob.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_fetch_message_fields() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let first = server.send_message(&alice, &guild, "first").await;
+    let second = server.send_message(&alice, &guild, "second").await;
+    let path = format!("/channels/{guild}/messages");
+
+    // Without a selection, full messages are returned
+    let messages = server.request(Method::GET, &path, Some(&alice.token), None).await;
+    assert_eq!(messages[0]["author"]["username"], "alice");
+    assert!(messages[0]["attachments"].is_array());
+    assert!(messages[0].get("author_id").is_none());
+
+    let messages = server
+        .request(
+            Method::GET,
+            &format!("{path}?exclude=author,attachments"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    let messages = messages.as_array().expect("Messages should be an array");
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0]["id"], second["id"]);
+    assert_eq!(messages[0]["content"], "second");
+    assert_eq!(messages[0]["author_id"], alice.id);
+    assert!(messages[0].get("author").is_none());
+    assert!(messages[0].get("attachments").is_none());
+    assert!(messages[0]["embeds"].is_array());
+
+    // Only the listed fields are included, and pagination still applies
+    let messages = server
+        .request(
+            Method::GET,
+            &format!(
+                "{path}?fields=author&before={}",
+                second["id"].as_str().expect("ID should be a string")
+            ),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    let messages = messages.as_array().expect("Messages should be an array");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0]["id"], first["id"]);
+    assert_eq!(messages[0]["author"]["username"], "alice");
+    assert!(messages[0].get("attachments").is_none());
+    assert!(messages[0].get("embeds").is_none());
+
+    let (status, _) = server
+        .try_request(Method::GET, &format!("{path}?fields=nonce"), Some(&alice.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_channel_links() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let hub = server.create_guild(&alice, "Hub").await;
+    let other = server.create_guild(&alice, "Other").await;
+
+    server
+        .request(Method::POST, &format!("/guilds/{hub}/members"), Some(&bob.token), None)
+        .await;
+    let lobby = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{hub}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "lobby" })),
+        )
+        .await;
+    let plans = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{other}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "plans" })),
+        )
+        .await;
+    let (lobby, plans) = (
+        lobby["id"].as_str().unwrap_or_default(),
+        plans["id"].as_str().unwrap_or_default(),
+    );
+
+    // Channels of other guilds the author is a member of can be linked by ID, but not by name
+    let message = server
+        .send_message(&alice, &hub, &format!("see <#{plans}>, #lobby and #plans"))
+        .await;
+    assert_eq!(message["content"], format!("see <#{plans}>, <#{lobby}> and #plans"));
+    assert_eq!(message["channel_mentions"], json!([plans, lobby]));
+    assert_eq!(
+        message["mention_channels"],
+        json!([
+            { "id": plans, "guild_id": other, "name": "plans" },
+            { "id": lobby, "guild_id": hub, "name": "lobby" },
+        ])
+    );
+
+    // The names are kept when the message is fetched
+    let messages = server
+        .request(
+            Method::GET,
+            &format!("/channels/{hub}/messages"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    assert_eq!(messages[0]["mention_channels"], message["mention_channels"]);
+
+    // Bob is not a member of the other guild, so its channels cannot be linked by Bob
+    let message = server.send_message(&bob, &hub, &format!("<#{plans}>")).await;
+    assert_eq!(message["content"], format!("<#{plans}>"));
+    assert_eq!(message["channel_mentions"], json!([]));
+    assert_eq!(message["mention_channels"], json!([]));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_message_changes() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Archive").await;
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "general" })),
+        )
+        .await;
+    let channel = channel["id"].as_str().expect("Channel should have an ID");
+    let changes = |since: &str, limit: u32| format!("/channels/{channel}/messages/changes?since={since}&limit={limit}");
+
+    let first = server.send_message(&alice, channel, "First").await;
+    let second = server.send_message(&alice, channel, "Second").await;
+    server.app().outbox.flush().await.expect("Outbox should flush");
+
+    // Any ID works as a starting point, such as the ID of the guild
+    let page = server
+        .request(Method::GET, &changes(&guild, 10), Some(&alice.token), None)
+        .await;
+    let types: Vec<&Value> = page["changes"]
+        .as_array()
+        .expect("Changes should be an array")
+        .iter()
+        .map(|c| &c["type"])
+        .collect();
+    assert_eq!(types, [&json!("CREATE"), &json!("CREATE")]);
+    assert_eq!(page["changes"][0]["message_id"], first["id"]);
+    assert_eq!(page["changes"][1]["message"]["content"], "Second");
+    assert_eq!(page["cursor"], page["changes"][1]["id"]);
+
+    // Pages continue after the cursor of the previous page
+    let page = server
+        .request(Method::GET, &changes(&guild, 1), Some(&alice.token), None)
+        .await;
+    assert_eq!(page["changes"][0]["message_id"], first["id"]);
+    let cursor = page["cursor"].as_str().expect("Cursor should be a string").to_string();
+    let page = server
+        .request(Method::GET, &changes(&cursor, 1), Some(&alice.token), None)
+        .await;
+    assert_eq!(page["changes"][0]["message_id"], second["id"]);
+    let cursor = page["cursor"].as_str().expect("Cursor should be a string").to_string();
+
+    // Caught up clients receive no changes and keep their cursor
+    let page = server
+        .request(Method::GET, &changes(&cursor, 10), Some(&alice.token), None)
+        .await;
+    assert_eq!(page, json!({ "changes": [], "cursor": cursor }));
+
+    // Deleted messages are reported without their contents
+    let app = server.app();
+    let channel_id: Snowflake<Channel> = channel.parse().expect("Channel ID should be valid");
+    let second_id = second["id"]
+        .as_str()
+        .expect("Message should have an ID")
+        .parse()
+        .expect("ID should be valid");
+    let stored = app.ops().fetch_channel(channel_id).await.expect("Channel should exist");
+    app.ops()
+        .delete_messages_before(&stored, second_id, 100)
+        .await
+        .expect("Messages should be deleted");
+    app.outbox.flush().await.expect("Outbox should flush");
+    let page = server
+        .request(Method::GET, &changes(&cursor, 10), Some(&alice.token), None)
+        .await;
+    assert_eq!(page["changes"][0]["type"], "DELETE");
+    assert_eq!(page["changes"][0]["message_id"], first["id"]);
+    assert_eq!(page["changes"][0]["message"], json!(null));
+
+    // Only members can fetch changes, and only for as long as they are kept
+    let (status, _) = server
+        .try_request(Method::GET, &changes(&cursor, 10), Some(&bob.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let (status, error) = server
+        .try_request(Method::GET, &changes("1", 10), Some(&alice.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "OUT_OF_RANGE");
+
+    server.close().await;
+}
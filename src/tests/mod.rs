@@ -0,0 +1,16 @@
+//! Integration tests of the REST API and other features, run against a real server
+//! with [`testkit`](crate::gateway::testkit). Tests of the gateway protocol are in the gateway module.
+//!
+//! These tests need a Postgres server and are ignored by default, run them with
+//! `DATABASE_URL=... cargo test -- --ignored`.
+
+mod admin;
+mod api;
+mod channels;
+pub(crate) mod feeds;
+mod guilds;
+mod media;
+mod messages;
+mod moderation;
+mod push;
+mod users;
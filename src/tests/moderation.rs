@@ -0,0 +1,384 @@
+//! Tests of reports, strikes, raid mode and ban evasion detection.
+
+use reqwest::Method;
+use secrecy::Secret;
+use serde_json::json;
+
+use crate::gateway::testkit::{TestServer, PASSWORD};
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_reports() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "general" })),
+        )
+        .await;
+    let channel = channel["id"].as_str().expect("Channel should have an ID");
+    let message = server.send_message(&alice, channel, "Buy my course").await;
+    let message = message["id"].as_str().expect("Message should have an ID");
+
+    let (mut alice_client, _) = server.identify(&alice).await;
+    let path = format!("/channels/{channel}/messages/{message}/report");
+    let report = json!({ "reason": "SPAM", "details": "Posted in every channel" });
+    server
+        .request(Method::POST, &path, Some(&bob.token), Some(report.clone()))
+        .await;
+
+    let report_create = alice_client.expect_event("REPORT_CREATE").await;
+    assert_eq!(report_create["data"]["reporter_id"], bob.id);
+    assert_eq!(report_create["data"]["content"], "Buy my course");
+    assert_eq!(report_create["data"]["state"], "OPEN");
+    let report_id = report_create["data"]["id"].as_str().expect("Report should have an ID");
+
+    let (status, _) = server
+        .try_request(Method::POST, &path, Some(&bob.token), Some(report))
+        .await;
+    assert_eq!(status, reqwest::StatusCode::CONFLICT);
+
+    // Only the owner can review reports
+    let (status, _) = server
+        .try_request(Method::GET, &format!("/guilds/{guild}/reports"), Some(&bob.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let reports = server
+        .request(
+            Method::GET,
+            &format!("/guilds/{guild}/reports?state=OPEN"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(reports[0]["id"], report_id);
+
+    let review_path = format!("/guilds/{guild}/reports/{report_id}");
+    let reviewed = server
+        .request(
+            Method::PATCH,
+            &review_path,
+            Some(&alice.token),
+            Some(json!({ "state": "DISMISSED" })),
+        )
+        .await;
+    assert_eq!(reviewed["state"], "DISMISSED");
+    assert_eq!(reviewed["resolved_by"], alice.id);
+
+    let (status, _) = server
+        .try_request(
+            Method::PATCH,
+            &review_path,
+            Some(&alice.token),
+            Some(json!({ "state": "ACTIONED" })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_strikes() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    server
+        .request(
+            Method::PUT,
+            &format!("/guilds/{guild}/strike-policy"),
+            Some(&alice.token),
+            Some(json!({ "timeout_threshold": 2, "timeout_duration": 600, "ban_threshold": 3 })),
+        )
+        .await;
+
+    let (mut bob_client, _) = server.identify(&bob).await;
+    let strikes = format!("/guilds/{guild}/members/{}/strikes", bob.id);
+    let strike = server
+        .request(
+            Method::POST,
+            &strikes,
+            Some(&alice.token),
+            Some(json!({ "reason": "Spamming" })),
+        )
+        .await;
+    let event = bob_client.expect_event("STRIKE_CREATE").await;
+    assert_eq!(event["data"]["id"], strike["id"]);
+    assert_eq!(event["data"]["issuer_id"], alice.id);
+
+    // Members cannot moderate, only view their own standing
+    let (status, _) = server
+        .try_request(Method::POST, &strikes, Some(&bob.token), Some(json!({})))
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let standing = server
+        .request(
+            Method::GET,
+            &format!("/guilds/{guild}/members/@me/standing"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    assert_eq!(standing["strikes"][0]["reason"], "Spamming");
+    assert_eq!(standing["policy"]["ban_threshold"], 3);
+
+    // Second strike reaches the timeout threshold
+    server
+        .request(Method::POST, &strikes, Some(&alice.token), Some(json!({})))
+        .await;
+    let update = bob_client.expect_event("MEMBER_UPDATE").await;
+    assert!(update["data"]["timeout_until"].is_i64());
+
+    // Third strike reaches the ban threshold
+    server
+        .request(Method::POST, &strikes, Some(&alice.token), Some(json!({})))
+        .await;
+    bob_client.expect_event("GUILD_REMOVE").await;
+    let (status, _) = server
+        .try_request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    let bans = server
+        .request(Method::GET, &format!("/guilds/{guild}/bans"), Some(&alice.token), None)
+        .await;
+    assert_eq!(bans[0]["user_id"], bob.id);
+    server
+        .request(
+            Method::DELETE,
+            &format!("/guilds/{guild}/bans/{}", bob.id),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_raid_mode() {
+    let server = TestServer::start_with(|config| {
+        config.raid_join_threshold(3_u32);
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let carol = server.create_user("carol").await;
+    let dave = server.create_user("dave").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let channel = server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/channels"),
+            Some(&alice.token),
+            Some(json!({ "type": "GUILD_TEXT", "name": "general" })),
+        )
+        .await["id"]
+        .as_str()
+        .expect("Channel should have an ID")
+        .to_string();
+    let (mut alice_client, _) = server.identify(&alice).await;
+
+    // The owner, bob and carol joining within the window reaches the threshold
+    for user in [&bob, &carol] {
+        server
+            .request(
+                Method::POST,
+                &format!("/guilds/{guild}/members"),
+                Some(&user.token),
+                None,
+            )
+            .await;
+    }
+    let raid_mode = alice_client.expect_event("RAID_MODE_UPDATE").await;
+    assert_eq!(raid_mode["data"]["guild_id"], guild);
+
+    let (status, error) = server
+        .try_request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&dave.token),
+            None,
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    assert_eq!(error["code"], "INVITE_REQUIRED");
+
+    // Members other than the owner are limited by slow mode
+    server.send_message(&bob, &channel, "First!").await;
+    let (status, error) = server.try_send_message(&bob, &channel, "Second!").await;
+    assert_eq!(status, reqwest::StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(error["code"], "SLOW_MODE");
+    assert!(error["retry_after"].as_u64().is_some_and(|retry_after| retry_after > 0));
+    server.send_message(&carol, &channel, "Hi!").await;
+    server.send_message(&alice, &channel, "Calm down").await;
+    server.send_message(&alice, &channel, "Please").await;
+
+    // Only the owner can change raid mode
+    let (status, _) = server
+        .try_request(
+            Method::PUT,
+            &format!("/guilds/{guild}/raid-mode"),
+            Some(&bob.token),
+            Some(json!({ "enabled": false })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+
+    let updated = server
+        .request(
+            Method::PUT,
+            &format!("/guilds/{guild}/raid-mode"),
+            Some(&alice.token),
+            Some(json!({ "enabled": false })),
+        )
+        .await;
+    alice_client.expect_event("RAID_MODE_UPDATE").await;
+
+    // Joins before raid mode was disabled no longer count towards a raid
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&dave.token),
+            None,
+        )
+        .await;
+    server.send_message(&bob, &channel, "Second!").await;
+    let guild = server
+        .request(Method::GET, &format!("/guilds/{guild}"), Some(&alice.token), None)
+        .await;
+    assert_eq!(guild["raid_mode_until"], updated["raid_mode_until"]);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_ban_evasion_flags() {
+    let server = TestServer::start_with(|config| {
+        config.fingerprint_salt(Some(Secret::new("salt".into())));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let mallory = server.create_user_on_device("mallory", "phone-1").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let members = format!("/guilds/{guild}/members");
+    let flags = format!("/guilds/{guild}/evasion-flags");
+
+    let (status, _) = server
+        .try_request(
+            Method::POST,
+            "/users",
+            None,
+            Some(json!({ "username": "eve", "password": PASSWORD, "device_id": "x".repeat(257) })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    server
+        .request(
+            Method::PUT,
+            &format!("/guilds/{guild}/strike-policy"),
+            Some(&alice.token),
+            Some(json!({ "ban_threshold": 1, "flag_evasion": true })),
+        )
+        .await;
+    server.request(Method::POST, &members, Some(&mallory.token), None).await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members/{}/strikes", mallory.id),
+            Some(&alice.token),
+            Some(json!({ "reason": "Spamming" })),
+        )
+        .await;
+
+    // A new account on the same device is flagged, one on another device is not
+    let (mut alice_client, _) = server.identify(&alice).await;
+    alice_client.expect_event("GUILD_CREATE").await;
+    let eve = server.create_user_on_device("eve", "phone-1").await;
+    let carol = server.create_user_on_device("carol", "laptop").await;
+    let (status, _) = server.try_request(Method::POST, &members, Some(&eve.token), None).await;
+    assert_eq!(status, reqwest::StatusCode::CREATED);
+    let (status, _) = server
+        .try_request(Method::POST, &members, Some(&carol.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::CREATED);
+
+    let event = alice_client.expect_event("EVASION_FLAG_CREATE").await;
+    assert_eq!(event["data"]["user_id"], eve.id);
+    assert_eq!(event["data"]["banned_user_id"], mallory.id);
+
+    let (status, _) = server.try_request(Method::GET, &flags, Some(&eve.token), None).await;
+    assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+    let open = server.request(Method::GET, &flags, Some(&alice.token), None).await;
+    assert_eq!(open.as_array().map(Vec::len), Some(1));
+    assert_eq!(open[0]["user_id"], eve.id);
+
+    let flag = format!("{flags}/{}", eve.id);
+    let (status, _) = server
+        .try_request(Method::DELETE, &flag, Some(&alice.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+    let (status, _) = server
+        .try_request(Method::DELETE, &flag, Some(&alice.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+
+    // Guilds that do not flag ban evasion are left alone
+    server
+        .request(
+            Method::PUT,
+            &format!("/guilds/{guild}/strike-policy"),
+            Some(&alice.token),
+            Some(json!({ "ban_threshold": 1 })),
+        )
+        .await;
+    let trent = server.create_user_on_device("trent", "phone-1").await;
+    let (status, _) = server
+        .try_request(Method::POST, &members, Some(&trent.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::CREATED);
+    let open = server.request(Method::GET, &flags, Some(&alice.token), None).await;
+    assert_eq!(open, json!([]));
+
+    server.close().await;
+}
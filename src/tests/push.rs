@@ -0,0 +1,137 @@
+//! Tests of push notifications.
+
+use std::time::Duration;
+
+use reqwest::Method;
+use secrecy::Secret;
+use serde_json::json;
+
+use crate::gateway::testkit::{TestServer, TestUser};
+
+/// A key pair only used to test Web Push, see the tests of [`crate::models::push`].
+const VAPID_PUBLIC_KEY: &str =
+    "BKcAOEdDDyxq-2t3NU9BbpfnN5sjIR2iwUv75nuDxj-FwDIniT9tiDNtNBYfIww-8au4Rt-WbBj87bWPfJXfiCY";
+const VAPID_PRIVATE_KEY: &str = "RX11lZESxE6NGhoGkzgb5FTQ81yBf4qVBSGwgonMmcI";
+
+/// Count the push notifications queued for the devices of a user.
+async fn queued_push_notifications(server: &TestServer, user: &TestUser) -> i64 {
+    sqlx::query_scalar(
+        "SELECT COUNT(*) FROM push_deliveries AS q
+        JOIN push_devices AS d ON d.id = q.device_id
+        WHERE d.user_id = $1",
+    )
+    .bind(user.id.parse::<i64>().expect("IDs should be numeric"))
+    .fetch_one(server.app().db.pool())
+    .await
+    .expect("Failed to count push notifications")
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_push_notifications() {
+    let server = TestServer::start_with(|config| {
+        config.vapid_public_key(Some(VAPID_PUBLIC_KEY.into()));
+        config.vapid_private_key(Some(Secret::new(VAPID_PRIVATE_KEY.into())));
+        config.vapid_subject(Some("mailto:admin@example.com".into()));
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let carol = server.create_user("carol").await;
+    let dave = server.create_user("dave").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    let devices = "/users/@me/push-devices";
+    let subscription = |name: &str| {
+        json!({
+            "platform": "WEB_PUSH",
+            "token": json!({
+                "endpoint": format!("https://push.example.invalid/send/{name}"),
+                "keys": { "p256dh": VAPID_PUBLIC_KEY, "auth": "AAAAAAAAAAAAAAAAAAAAAA" },
+            })
+            .to_string(),
+        })
+    };
+
+    let config = server.request(Method::GET, "/push", Some(&bob.token), None).await;
+    assert_eq!(config["platforms"], json!(["WEB_PUSH"]));
+    assert_eq!(config["vapid_public_key"], VAPID_PUBLIC_KEY);
+
+    // Only enabled platforms are accepted
+    let (status, _) = server
+        .try_request(
+            Method::POST,
+            devices,
+            Some(&bob.token),
+            Some(json!({ "platform": "APNS", "token": "abcdef" })),
+        )
+        .await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+
+    // Registering a device again returns the existing device
+    let device = server
+        .request(Method::POST, devices, Some(&bob.token), Some(subscription("bob")))
+        .await;
+    assert_eq!(device["platform"], "WEB_PUSH");
+    assert!(device.get("token").is_none());
+    let again = server
+        .request(Method::POST, devices, Some(&bob.token), Some(subscription("bob")))
+        .await;
+    assert_eq!(again["id"], device["id"]);
+
+    server
+        .request(Method::POST, devices, Some(&carol.token), Some(subscription("carol")))
+        .await;
+    server
+        .request(Method::POST, devices, Some(&dave.token), Some(subscription("dave")))
+        .await;
+    server
+        .request(Method::PATCH, "/prefs", Some(&dave.token), Some(json!({ "flags": 8 })))
+        .await;
+
+    for user in [&bob, &carol, &dave] {
+        server
+            .request(
+                Method::POST,
+                &format!("/guilds/{guild}/members"),
+                Some(&user.token),
+                None,
+            )
+            .await;
+    }
+
+    // Only offline users who did not mute push notifications are notified
+    let (_alice_client, _) = server.identify(&alice).await;
+    let (_carol_client, _) = server.identify(&carol).await;
+    server
+        .send_message(
+            &alice,
+            &guild,
+            &format!("<@{}> <@{}> <@{}> <@{}> lunch?", alice.id, bob.id, carol.id, dave.id),
+        )
+        .await;
+
+    for _ in 0..50 {
+        if queued_push_notifications(&server, &bob).await > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(queued_push_notifications(&server, &bob).await, 1);
+    assert_eq!(queued_push_notifications(&server, &alice).await, 0);
+    assert_eq!(queued_push_notifications(&server, &carol).await, 0);
+    assert_eq!(queued_push_notifications(&server, &dave).await, 0);
+
+    // Removing a device drops its queued notifications
+    let listed = server.request(Method::GET, devices, Some(&bob.token), None).await;
+    assert_eq!(listed, json!([device]));
+    let path = format!("{devices}/{}", device["id"].as_str().expect("Device should have an ID"));
+    let (status, _) = server
+        .try_request(Method::DELETE, &path, Some(&carol.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    let (status, _) = server.try_request(Method::DELETE, &path, Some(&bob.token), None).await;
+    assert_eq!(status, reqwest::StatusCode::NO_CONTENT);
+    assert_eq!(queued_push_notifications(&server, &bob).await, 0);
+
+    server.close().await;
+}
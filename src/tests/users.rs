@@ -0,0 +1,375 @@
+//! Tests of user search, profiles, deletion and cookie sessions.
+
+use reqwest::Method;
+use serde_json::{json, Value};
+
+use crate::{
+    gateway::testkit::{TestServer, PASSWORD},
+    models::{
+        snowflake::Snowflake,
+        user::{User, UserFlags},
+    },
+};
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_cookie_session() {
+    let server = TestServer::start_with(|config| {
+        config
+            .auth_cookies_enabled(true)
+            .auth_cookie_secure(false)
+            .gateway_allowed_origins(vec![String::from("https://chat.example.com")]);
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+
+    let response = server
+        .build_request(Method::POST, "/users/auth?cookie=true")
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(json!({ "username": "alice", "password": PASSWORD }).to_string())
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert!(response.status().is_success());
+
+    let cookies: Vec<String> = response
+        .headers()
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .map(|value| value.to_str().expect("Cookie should be ASCII").to_string())
+        .collect();
+    let session = cookies
+        .iter()
+        .find(|cookie| cookie.starts_with("chat_session="))
+        .expect("Session cookie should be set");
+    assert!(session.contains("HttpOnly"));
+    assert!(session.contains("SameSite=Lax"));
+
+    let body: Value = serde_json::from_str(&response.text().await.expect("Failed to read response body"))
+        .expect("Response should be JSON");
+    assert_eq!(body["user_id"], alice.id);
+    assert!(body.get("token").is_none());
+    let csrf_token = body["csrf_token"].as_str().expect("CSRF token should be returned");
+    let cookie = session
+        .split(';')
+        .next()
+        .expect("Cookie should have a value")
+        .to_string();
+
+    // Read-only requests only need the cookie
+    let me = server
+        .build_request(Method::GET, "/users/@me")
+        .header(reqwest::header::COOKIE, &cookie)
+        .send()
+        .await
+        .expect("Failed to send request")
+        .text()
+        .await
+        .expect("Failed to read response body");
+    let me: Value = serde_json::from_str(&me).expect("Response should be JSON");
+    assert_eq!(me["id"], alice.id);
+
+    // State-changing requests must repeat the CSRF token
+    let create_guild = |csrf_token: Option<&str>| {
+        let mut request = server
+            .build_request(Method::POST, "/guilds")
+            .header(reqwest::header::COOKIE, &cookie)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(json!({ "name": "Alice's guild" }).to_string());
+        if let Some(csrf_token) = csrf_token {
+            request = request.header("X-CSRF-Token", csrf_token);
+        }
+        request.send()
+    };
+
+    let response = create_guild(None).await.expect("Failed to send request");
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+    let error: Value = serde_json::from_str(&response.text().await.expect("Failed to read response body"))
+        .expect("Response should be JSON");
+    assert_eq!(error["code"], "INVALID_CSRF_TOKEN");
+
+    let forged = "00".repeat(32);
+    let response = create_guild(Some(&forged)).await.expect("Failed to send request");
+    assert_eq!(response.status(), reqwest::StatusCode::FORBIDDEN);
+
+    let response = create_guild(Some(csrf_token)).await.expect("Failed to send request");
+    assert!(response.status().is_success());
+
+    // The gateway accepts the cookie in place of the token
+    let mut client = server.connect_with_cookie(&cookie, "https://chat.example.com").await;
+    client.expect_event("HELLO").await;
+    client.send(&json!({ "event": "IDENTIFY", "data": {} })).await;
+    let ready = client.expect_event("READY").await;
+    assert_eq!(ready["data"]["user"]["id"], alice.id);
+
+    // Logging out clears the cookies
+    let response = server
+        .build_request(Method::DELETE, "/users/auth")
+        .header(reqwest::header::COOKIE, &cookie)
+        .header("X-CSRF-Token", csrf_token)
+        .send()
+        .await
+        .expect("Failed to send request");
+    assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    assert!(response
+        .headers()
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .all(|value| value.to_str().is_ok_and(|value| value.contains("Max-Age=0"))));
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_cookie_session_cross_site_gateway() {
+    // No origins are allowed to connect to the gateway, so any site may open a connection
+    let server = TestServer::start_with(|config| {
+        config.auth_cookies_enabled(true).auth_cookie_secure(false);
+    })
+    .await;
+    server.create_user("alice").await;
+
+    let response = server
+        .build_request(Method::POST, "/users/auth?cookie=true")
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(json!({ "username": "alice", "password": PASSWORD }).to_string())
+        .send()
+        .await
+        .expect("Failed to send request");
+    let cookie = response
+        .headers()
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|cookie| cookie.starts_with("chat_session="))
+        .and_then(|cookie| cookie.split(';').next())
+        .expect("Session cookie should be set")
+        .to_string();
+
+    // Connections opened by other sites cannot use the cookie the browser sends along
+    let mut client = server.connect_with_cookie(&cookie, "https://evil.example").await;
+    client.expect_event("HELLO").await;
+    client.send(&json!({ "event": "IDENTIFY", "data": {} })).await;
+    assert_eq!(client.expect_close().await.0, 4001);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_user_search() {
+    let server = TestServer::start_with(|config| {
+        config.rate_limits_enabled(true);
+    })
+    .await;
+    let alice = server.create_user("alice").await;
+    server.create_user("bob").await;
+    server.create_user("bobby").await;
+    server.create_user("rob_bo").await;
+
+    // Searching requires authentication
+    let (status, _) = server
+        .try_request(Method::GET, "/users/search?username=bob", None, None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::UNAUTHORIZED);
+
+    let search = |query: &str| {
+        let path = format!("/users/search?{query}");
+        let token = alice.token.clone();
+        let server = &server;
+        async move { server.try_request(Method::GET, &path, Some(&token), None).await }
+    };
+    let usernames = |users: &Value| -> Vec<String> {
+        users
+            .as_array()
+            .expect("Response should be an array")
+            .iter()
+            .map(|user| {
+                user["username"]
+                    .as_str()
+                    .expect("Username should be a string")
+                    .to_string()
+            })
+            .collect()
+    };
+
+    let (status, users) = search("username=BOB").await;
+    assert_eq!(status, reqwest::StatusCode::OK);
+    assert_eq!(usernames(&users), ["bob", "bobby"]);
+
+    // Underscores are matched literally, not as wildcards
+    let (_, users) = search("username=rob_").await;
+    assert_eq!(usernames(&users), ["rob_bo"]);
+    let (_, users) = search("username=bo_").await;
+    assert!(usernames(&users).is_empty());
+
+    let (_, users) = search("username=bobb&fuzzy=true").await;
+    assert_eq!(usernames(&users).first().map(String::as_str), Some("bobby"));
+
+    // Short queries would match too many users
+    let (status, error) = search("username=bo").await;
+    assert_eq!(status, reqwest::StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "INVALID_LENGTH");
+
+    // Searches have their own, stricter rate limit
+    let mut status = reqwest::StatusCode::OK;
+    for _ in 0..10 {
+        (status, _) = search("username=alice").await;
+    }
+    assert_eq!(status, reqwest::StatusCode::TOO_MANY_REQUESTS);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_profiles() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let carol = server.create_user("carol").await;
+    let guild = server.create_guild(&alice, "Alice's guild").await;
+    server
+        .request(
+            Method::POST,
+            &format!("/guilds/{guild}/members"),
+            Some(&bob.token),
+            None,
+        )
+        .await;
+    let profile_path = format!("/users/{}/profile", alice.id);
+
+    let profile = server.request(Method::GET, &profile_path, Some(&bob.token), None).await;
+    assert_eq!(profile["username"], "alice");
+    assert_eq!(profile["mutual_guild_count"], 1);
+    assert!(profile.get("presence").is_none());
+
+    let profile = server
+        .request(Method::GET, &profile_path, Some(&carol.token), None)
+        .await;
+    assert_eq!(profile["mutual_guild_count"], 0);
+
+    // Users without mutual guilds are told the user does not exist
+    let privacy = server
+        .request(
+            Method::PUT,
+            "/users/@me/profile/privacy",
+            Some(&alice.token),
+            Some(json!({ "visibility": "MUTUAL_GUILDS" })),
+        )
+        .await;
+    assert_eq!(privacy["visibility"], "MUTUAL_GUILDS");
+    let (status, _) = server
+        .try_request(Method::GET, &profile_path, Some(&carol.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    server.request(Method::GET, &profile_path, Some(&bob.token), None).await;
+
+    // Users can always see their own profile
+    server
+        .request(
+            Method::PUT,
+            "/users/@me/profile/privacy",
+            Some(&alice.token),
+            Some(json!({ "visibility": "NOBODY" })),
+        )
+        .await;
+    let (status, _) = server
+        .try_request(Method::GET, &profile_path, Some(&bob.token), None)
+        .await;
+    assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    server
+        .request(Method::GET, &profile_path, Some(&alice.token), None)
+        .await;
+    let privacy = server
+        .request(Method::GET, "/users/@me/profile/privacy", Some(&alice.token), None)
+        .await;
+    assert_eq!(privacy["visibility"], "NOBODY");
+
+    // Profiles require authentication
+    let (status, _) = server.try_request(Method::GET, &profile_path, None, None).await;
+    assert_eq!(status, reqwest::StatusCode::UNAUTHORIZED);
+
+    server.close().await;
+}
+
+#[tokio::test]
+#[ignore = "requires a Postgres database"]
+async fn test_delete_user() {
+    let server = TestServer::start().await;
+    let alice = server.create_user("alice").await;
+    let bob = server.create_user("bob").await;
+    let alice_guild = server.create_guild(&alice, "Alice's guild").await;
+    let bob_guild = server.create_guild(&bob, "Bob's guild").await;
+    for (user, guild) in [(&bob, &alice_guild), (&alice, &bob_guild)] {
+        server
+            .request(
+                Method::POST,
+                &format!("/guilds/{guild}/members"),
+                Some(&user.token),
+                None,
+            )
+            .await;
+    }
+    let message = server.send_message(&bob, &alice_guild, "Goodbye").await;
+    let bob_id: Snowflake<User> = bob.id.parse().expect("IDs should be numeric");
+
+    server
+        .app()
+        .ops()
+        .update_user_flags(bob_id, UserFlags::EXEMPT_FROM_LIMITS)
+        .await
+        .expect("Failed to update flags");
+    assert_eq!(
+        server
+            .app()
+            .ops()
+            .fetch_user_flags(bob_id)
+            .await
+            .expect("Failed to fetch flags"),
+        UserFlags::EXEMPT_FROM_LIMITS
+    );
+
+    let (mut alice_client, _) = server.identify(&alice).await;
+    let user = server.app().ops().fetch_user(bob_id).await.expect("Bob should exist");
+    server
+        .app()
+        .ops()
+        .delete_user(&user)
+        .await
+        .expect("Failed to delete user");
+
+    // Guilds owned by the user are deleted, and the user leaves all other guilds
+    let mut events = [
+        alice_client.expect_event("GUILD_REMOVE").await,
+        alice_client.expect_event("MEMBER_REMOVE").await,
+    ];
+    events.sort_by_key(|event| event["event"].to_string());
+    assert_eq!(events[0]["data"]["id"], bob_guild);
+    assert_eq!(events[1]["data"]["id"], bob.id);
+    let guilds = server
+        .request(Method::GET, "/users/@me/guilds", Some(&alice.token), None)
+        .await;
+    assert_eq!(guilds.as_array().map(Vec::len), Some(1));
+
+    // Messages sent to other guilds are kept without an author
+    let messages = server
+        .request(
+            Method::GET,
+            &format!("/channels/{alice_guild}/messages"),
+            Some(&alice.token),
+            None,
+        )
+        .await;
+    assert_eq!(messages[0]["id"], message["id"]);
+    assert_eq!(messages[0]["author"], Value::Null);
+
+    let (status, _) = server
+        .try_request(Method::GET, "/users/@me", Some(&bob.token), None)
+        .await;
+    assert!(status.is_client_error());
+    assert!(server.app().ops().fetch_user(bob_id).await.is_none());
+
+    server.close().await;
+}
@@ -14,7 +14,7 @@ pub struct AbortingJoinHandle<T> {
 }
 
 impl<T> AbortingJoinHandle<T> {
-    fn new(inner: JoinHandle<T>) -> Self {
+    const fn new(inner: JoinHandle<T>) -> Self {
         Self { inner }
     }
 
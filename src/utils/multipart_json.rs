@@ -14,6 +14,8 @@ use serde::de::DeserializeOwned;
 use serde_json::json;
 use thiserror::Error;
 
+use crate::models::errors::ErrorCode;
+
 /// Errors that can occur while trying to extract a `MultipartJson` from a request.
 #[derive(Debug, Error)]
 #[non_exhaustive]
@@ -46,7 +48,8 @@ impl IntoResponse for MultipartJsonError {
             Self::ParseError(e) => return e.into_response(),
         };
         let body = json!({
-            "error": self.to_string()
+            "error": self.to_string(),
+            "code": ErrorCode::BadRequest,
         });
 
         (status, Json(body)).into_response()